@@ -0,0 +1,273 @@
+use crate::config::de_duration;
+use crate::fmt::format_age;
+use crate::hooks::shell;
+use crate::model::ConflictGroup;
+use crate::scan::conflict_meta;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// The daemon's alerts about conflicts left unresolved for a long time, from the
+/// `[alerts]` table. They always go to the daemon log; `webhook` and `command` send them
+/// on as well.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Alerts {
+    /// Age of the oldest conflict copy at which a group gets a notice.
+    #[serde(deserialize_with = "de_duration")]
+    pub notice: Duration,
+    /// Age at which the notice escalates to a warning.
+    #[serde(deserialize_with = "de_duration")]
+    pub warning: Duration,
+    /// URL the alerts are POSTed to as JSON.
+    pub webhook: Option<String>,
+    /// Shell command given the alerts as JSON on stdin, e.g. one calling `notify-send`.
+    pub command: Option<String>,
+}
+
+impl Default for Alerts {
+    fn default() -> Self {
+        Self {
+            notice: Duration::from_secs(7 * DAY),
+            warning: Duration::from_secs(30 * DAY),
+            webhook: None,
+            command: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertLevel {
+    Notice,
+    Warning,
+}
+
+impl AlertLevel {
+    pub fn label(self) -> &'static str {
+        match self {
+            AlertLevel::Notice => "notice",
+            AlertLevel::Warning => "warning",
+        }
+    }
+}
+
+/// A group unresolved for long enough to alert about, or a `problem` that keeps the
+/// daemon from resolving anything (then `path` is `.`, the whole folder).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Alert {
+    pub level: AlertLevel,
+    pub path: PathBuf,
+    /// Days since its oldest conflict copy appeared.
+    pub age_days: u64,
+    /// Command line that opens the group in the TUI.
+    pub open: String,
+    /// What went wrong, such as too little free space to apply.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub problem: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    /// One line per alert, so chat webhooks that only show `text` still say something.
+    text: String,
+    root: &'a Path,
+    alerts: &'a [Alert],
+}
+
+/// When the oldest existing conflict copy of `g` appeared: the time in its name, else
+/// its modification time.
+pub fn conflict_since(g: &ConflictGroup) -> Option<SystemTime> {
+    g.candidates
+        .iter()
+        .filter(|c| c.exists && !c.is_original)
+        .filter_map(|c| {
+            conflict_meta(&c.path)
+                .and_then(|m| m.timestamp())
+                .or(c.modified)
+        })
+        .min()
+}
+
+impl Alerts {
+    /// The level a conflict this old has reached, if any.
+    pub fn level(&self, age: Duration) -> Option<AlertLevel> {
+        if age >= self.warning {
+            Some(AlertLevel::Warning)
+        } else if age >= self.notice {
+            Some(AlertLevel::Notice)
+        } else {
+            None
+        }
+    }
+
+    /// The text line an alert is logged and sent with.
+    pub fn describe(alert: &Alert) -> String {
+        if let Some(problem) = &alert.problem {
+            return format!("{problem}; open the folder with {}", alert.open);
+        }
+        format!(
+            "{}: unresolved for {}, open it with {}",
+            alert.path.display(),
+            format_age(Duration::from_secs(alert.age_days * DAY)),
+            alert.open
+        )
+    }
+
+    /// Sends `alerts` to the webhook and the command, whichever are set; both are tried
+    /// before the first failure is returned.
+    pub fn send(&self, root: &Path, alerts: &[Alert]) -> Result<()> {
+        if alerts.is_empty() {
+            return Ok(());
+        }
+        let text = alerts
+            .iter()
+            .map(|a| format!("[{}] {}", a.level.label(), Self::describe(a)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let payload = serde_json::to_vec(&Payload { text, root, alerts })?;
+        let webhook = self.webhook.as_deref().map_or(Ok(()), |url| {
+            post(url, &payload).with_context(|| format!("alert webhook {url}"))
+        });
+        let command = self
+            .command
+            .as_deref()
+            .filter(|c| !c.trim().is_empty())
+            .map_or(Ok(()), |line| run(line, root, alerts, &payload));
+        webhook.and(command)
+    }
+}
+
+fn post(url: &str, payload: &[u8]) -> Result<()> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(10)))
+        .build()
+        .into();
+    agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(payload)?;
+    Ok(())
+}
+
+/// Runs the alert command in `root` with the JSON on stdin, and `SYNCTUI_ALERT_LEVEL`
+/// (the highest level) and `SYNCTUI_ALERT_TEXT` (one line per alert) set.
+fn run(line: &str, root: &Path, alerts: &[Alert], payload: &[u8]) -> Result<()> {
+    let level = alerts
+        .iter()
+        .map(|a| a.level)
+        .max()
+        .unwrap_or(AlertLevel::Notice);
+    let text = alerts
+        .iter()
+        .map(Alerts::describe)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut child = shell(line)
+        .current_dir(root)
+        .env("SYNCTUI_ALERT_LEVEL", level.label())
+        .env("SYNCTUI_ALERT_TEXT", text)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("start alert command `{line}`"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that ignores stdin may exit before reading it all.
+        let _ = stdin.write_all(payload);
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "alert command `{line}` {}: {}",
+            output.status,
+            stderr.trim().lines().last().unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Candidate;
+
+    #[test]
+    fn levels_escalate_with_age_and_start_at_the_oldest_conflict() {
+        let alerts = Alerts::default();
+        let day = Duration::from_secs(DAY);
+        assert_eq!(alerts.level(6 * day), None);
+        assert_eq!(alerts.level(7 * day), Some(AlertLevel::Notice));
+        assert_eq!(alerts.level(30 * day), Some(AlertLevel::Warning));
+
+        let cand = |path: &str, is_original: bool| Candidate {
+            path: PathBuf::from(path),
+            exists: true,
+            is_original,
+            size: None,
+            modified: Some(SystemTime::UNIX_EPOCH),
+            label: String::new(),
+            trust: None,
+        };
+        let g = ConflictGroup {
+            base_path: PathBuf::from("/r/a.txt"),
+            candidates: vec![
+                cand("/r/a.txt", true),
+                cand("/r/a.txt.sync-conflict-20240301-000000-DEV", false),
+                cand("/r/a.txt.sync-conflict-20240201-000000-DEV", false),
+            ],
+            chosen: None,
+            references: Vec::new(),
+        };
+        let since = conflict_since(&g).unwrap();
+        let meta = conflict_meta(&g.candidates[2].path).unwrap();
+        assert_eq!(Some(since), meta.timestamp());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_command_gets_the_alerts_as_json_and_text() {
+        let td = tempfile::tempdir().unwrap();
+        let alerts = Alerts {
+            command: Some(
+                "cat > alerts.json; printf '%s' \"$SYNCTUI_ALERT_LEVEL\" > level.txt".to_string(),
+            ),
+            ..Alerts::default()
+        };
+        let alert = |level, name: &str| Alert {
+            level,
+            path: PathBuf::from(name),
+            age_days: 40,
+            open: format!("synctui-resolver . --goto {name}"),
+            problem: None,
+        };
+        let sent = [
+            alert(AlertLevel::Warning, "a.txt"),
+            alert(AlertLevel::Notice, "b.txt"),
+        ];
+        alerts.send(td.path(), &sent).unwrap();
+        let level = std::fs::read_to_string(td.path().join("level.txt")).unwrap();
+        assert_eq!(level, "warning");
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(td.path().join("alerts.json")).unwrap())
+                .unwrap();
+        assert_eq!(json["alerts"][1]["level"], "notice");
+        assert_eq!(
+            json["text"].as_str().unwrap().lines().next().unwrap(),
+            "[warning] a.txt: unresolved for 40d, open it with synctui-resolver . --goto a.txt"
+        );
+
+        let failing = Alerts {
+            command: Some("echo no display >&2; exit 1".to_string()),
+            ..Alerts::default()
+        };
+        let err = failing.send(td.path(), &sent).unwrap_err().to_string();
+        assert!(err.ends_with(": no display"), "{err}");
+    }
+}
@@ -0,0 +1,66 @@
+use crate::trust::same_device;
+use std::collections::BTreeMap;
+
+/// Friendly names for devices (`[device_aliases]` in the config), shown in place of the
+/// device IDs in conflict names and the history. They come from the config alone, so they
+/// work without the Syncthing API.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceAliases {
+    /// Device ID (full or short) and its alias, trimmed; empty aliases are left out.
+    entries: Vec<(String, String)>,
+}
+
+impl DeviceAliases {
+    pub fn new(aliases: &BTreeMap<String, String>) -> Self {
+        DeviceAliases {
+            entries: aliases
+                .iter()
+                .map(|(id, alias)| (id.trim().to_string(), alias.trim().to_string()))
+                .filter(|(id, alias)| !id.is_empty() && !alias.is_empty())
+                .collect(),
+        }
+    }
+
+    /// The alias of `device`, a full or short ID matched as in `device_trust`.
+    pub fn get(&self, device: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(id, _)| same_device(id, device))
+            .map(|(_, alias)| alias.as_str())
+    }
+
+    /// `device`'s alias, else `device` itself.
+    pub fn name(&self, device: &str) -> String {
+        self.get(device).unwrap_or(device).to_string()
+    }
+
+    /// `alias (ID)`, else the ID, for places with room for both.
+    pub fn name_and_id(&self, device: &str) -> String {
+        match self.get(device) {
+            Some(alias) => format!("{alias} ({device})"),
+            None => device.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_devices_by_full_or_short_id() {
+        let aliases = DeviceAliases::new(&BTreeMap::from([
+            ("PHONE12".to_string(), "📱 Téléphone".to_string()),
+            (
+                "laptop1-AAAAAAA-BBBBBBB-CCCCCCC-DDDDDDD-EEEEEEE-FFFFFFF-GGGGGGG".to_string(),
+                " 笔记本 ".to_string(),
+            ),
+            ("TABLET3".to_string(), "  ".to_string()),
+        ]));
+        assert_eq!(aliases.name("PHONE12"), "📱 Téléphone");
+        assert_eq!(aliases.name("LAPTOP1"), "笔记本");
+        assert_eq!(aliases.name_and_id("LAPTOP1"), "笔记本 (LAPTOP1)");
+        assert_eq!(aliases.name("TABLET3"), "TABLET3");
+        assert_eq!(aliases.name_and_id("local"), "local");
+    }
+}
@@ -0,0 +1,163 @@
+use crate::ops::{unique_suffix_millis, Actor};
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The process's audit log, once [`enable`]d.
+static LOG: OnceLock<AuditLog> = OnceLock::new();
+
+/// One line of the audit log.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// UTC, RFC 3339 with milliseconds.
+    pub ts: String,
+    /// Shared by every entry one run of the tool writes.
+    pub session: String,
+    pub user: String,
+    pub host: String,
+    pub version: String,
+    /// `move`, `delete`, `trash` or `write`.
+    pub action: String,
+    pub source: String,
+    /// Where a move went: a path, or an rclone remote.
+    pub destination: Option<String>,
+    /// SHA-256 of the contents moved, deleted or written; `None` for symlinks.
+    pub sha256: Option<String>,
+}
+
+/// An append-only JSONL file of every file the tool moves, deletes or writes.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    session: String,
+}
+
+impl AuditLog {
+    /// Opens (creating) `path` for appending, so a bad path fails before anything is
+    /// changed.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            crate::ops::ensure_dir(parent)?;
+        }
+        append(path).with_context(|| format!("open audit log {path:?}"))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            session: format!("{:x}-{:x}", unique_suffix_millis(), std::process::id()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entry(
+        &self,
+        action: &str,
+        source: &Path,
+        destination: Option<&str>,
+        sha256: Option<&str>,
+    ) -> AuditEntry {
+        let actor = Actor::current();
+        AuditEntry {
+            ts: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            session: self.session.clone(),
+            user: actor.user.clone(),
+            host: actor.host.clone(),
+            version: actor.version.clone(),
+            action: action.to_string(),
+            source: source.display().to_string(),
+            destination: destination.map(str::to_string),
+            sha256: sha256.map(str::to_string),
+        }
+    }
+
+    /// Appends `entry` as one line, in a single write so that concurrent runs sharing
+    /// the file don't interleave.
+    pub fn write(&self, entry: &AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        append(&self.path)
+            .and_then(|mut f| f.write_all(line.as_bytes()))
+            .with_context(|| {
+                format!(
+                    "{} {} done, but not recorded in the audit log {:?}",
+                    entry.action, entry.source, self.path
+                )
+            })
+    }
+}
+
+fn append(path: &Path) -> std::io::Result<std::fs::File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Sends every later file operation of this process to the audit log at `path`. Only
+/// the first call takes effect.
+pub fn enable(path: &Path) -> Result<()> {
+    let log = AuditLog::open(path)?;
+    let _ = LOG.set(log);
+    Ok(())
+}
+
+/// Whether operations are being audited; callers only hash contents for the log then.
+pub fn enabled() -> bool {
+    LOG.get().is_some()
+}
+
+/// Records a completed operation, if auditing is enabled.
+pub fn record(
+    action: &str,
+    source: &Path,
+    destination: Option<&str>,
+    sha256: Option<&str>,
+) -> Result<()> {
+    match LOG.get() {
+        Some(log) => log.write(&log.entry(action, source, destination, sha256)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn entries_are_appended_as_json_lines() {
+        let td = tempdir().unwrap();
+        let path = td.path().join("logs/audit.jsonl");
+        let log = AuditLog::open(&path).unwrap();
+        assert!(path.exists());
+        let moved = log.entry("move", Path::new("/s/a.txt"), Some("/s/b.txt"), Some("ab"));
+        log.write(&moved).unwrap();
+        log.write(&log.entry("delete", Path::new("/s/c.txt"), None, None))
+            .unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["action"], "move");
+        assert_eq!(lines[0]["source"], "/s/a.txt");
+        assert_eq!(lines[0]["destination"], "/s/b.txt");
+        assert_eq!(lines[0]["sha256"], "ab");
+        assert_eq!(lines[0]["user"], Actor::current().user.as_str());
+        assert!(lines[0]["ts"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(lines[1]["destination"], serde_json::Value::Null);
+        assert_eq!(lines[0]["session"], lines[1]["session"]);
+
+        // Reopening appends.
+        let again = AuditLog::open(&path).unwrap();
+        again
+            .write(&again.entry("trash", Path::new("/s/d.txt"), None, None))
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::fmt::format_size;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// Differing regions kept per comparison; the rest are only counted.
+pub const MAX_RANGES: usize = 100;
+/// Blocks compared between progress reports.
+const PROGRESS_EVERY: u64 = 256;
+
+/// Block size for files of `len` bytes: about 4096 blocks, from 4 KiB up to 1 MiB.
+pub fn block_size(len: u64) -> u64 {
+    (len / 4096).next_power_of_two().clamp(4 << 10, 1 << 20)
+}
+
+/// Which fixed-size blocks of two files differ, for files too large to diff line by
+/// line. Bytes past the end of the shorter file count as differing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockDiff {
+    pub block: u64,
+    /// The longer file's length.
+    pub total: u64,
+    /// Bytes compared so far; `total` once done.
+    pub compared: u64,
+    /// Bytes in differing blocks so far.
+    pub differing: u64,
+    /// Differing regions as `start..end` byte offsets, adjacent blocks merged; the first
+    /// [`MAX_RANGES`] only.
+    pub ranges: Vec<(u64, u64)>,
+    /// All differing regions, including those not kept in `ranges`.
+    pub range_count: usize,
+    /// End of the latest differing region, to merge the next block into it.
+    last_end: u64,
+}
+
+impl BlockDiff {
+    pub fn done(&self) -> bool {
+        self.compared >= self.total
+    }
+
+    /// Share of the bytes compared so far that differ, in percent.
+    pub fn percent(&self) -> f64 {
+        if self.compared == 0 {
+            0.0
+        } else {
+            self.differing as f64 * 100.0 / self.compared as f64
+        }
+    }
+
+    fn mark(&mut self, start: u64, end: u64) {
+        self.differing += end - start;
+        if self.range_count > 0 && self.last_end == start {
+            // The latest region goes on; it is only kept while there is room.
+            if self.range_count <= MAX_RANGES {
+                if let Some(last) = self.ranges.last_mut() {
+                    last.1 = end;
+                }
+            }
+        } else {
+            self.range_count += 1;
+            if self.ranges.len() < MAX_RANGES {
+                self.ranges.push((start, end));
+            }
+        }
+        self.last_end = end;
+    }
+
+    /// A summary line, then up to `max` regions as hex offsets.
+    pub fn describe(&self, max: usize) -> Vec<String> {
+        if !self.done() {
+            return vec![format!(
+                "comparing {} blocks... {}% ({:.1}% differs so far)",
+                format_size(self.block),
+                self.compared * 100 / self.total.max(1),
+                self.percent()
+            )];
+        }
+        if self.range_count == 0 {
+            return vec!["identical".to_string()];
+        }
+        let mut out = vec![format!(
+            "{:.1}% differs ({}) in {} regions, by {} blocks",
+            self.percent(),
+            format_size(self.differing),
+            self.range_count,
+            format_size(self.block)
+        )];
+        out.extend(
+            self.ranges.iter().take(max).map(|&(start, end)| {
+                format!("{start:#x}..{end:#x} ({})", format_size(end - start))
+            }),
+        );
+        if self.range_count > max {
+            out.push(format!("... {} more regions", self.range_count - max));
+        }
+        out
+    }
+}
+
+/// Compares `a` and `b` block by block, calling `progress` every so often with the result
+/// so far; it returns `false` to stop early.
+pub fn compare_blocks(
+    a: &Path,
+    b: &Path,
+    mut progress: impl FnMut(&BlockDiff) -> bool,
+) -> io::Result<BlockDiff> {
+    let (mut fa, mut fb) = (File::open(a)?, File::open(b)?);
+    let (la, lb) = (fa.metadata()?.len(), fb.metadata()?.len());
+    let common = la.min(lb);
+    let mut diff = BlockDiff {
+        block: block_size(la.max(lb)),
+        total: la.max(lb),
+        ..BlockDiff::default()
+    };
+    let mut ba = vec![0; diff.block as usize];
+    let mut bb = vec![0; diff.block as usize];
+    let mut blocks = 0;
+    while diff.compared < common {
+        let n = diff.block.min(common - diff.compared) as usize;
+        fa.read_exact(&mut ba[..n])?;
+        fb.read_exact(&mut bb[..n])?;
+        let start = diff.compared;
+        diff.compared += n as u64;
+        if ba[..n] != bb[..n] {
+            diff.mark(start, diff.compared);
+        }
+        blocks += 1;
+        if blocks % PROGRESS_EVERY == 0 && !progress(&diff) {
+            return Ok(diff);
+        }
+    }
+    if diff.total > common {
+        diff.mark(common, diff.total);
+        diff.compared = diff.total;
+    }
+    Ok(diff)
+}
+
+/// Runs [`compare_blocks`] for each `(row, a, b)` on a background thread, sending each
+/// row's progress and result. The thread stops once the receiver is dropped; a pair that
+/// can't be read is skipped.
+pub fn spawn_block_diffs(pairs: Vec<(usize, PathBuf, PathBuf)>) -> Receiver<(usize, BlockDiff)> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for (row, a, b) in pairs {
+            let result = compare_blocks(&a, &b, |d| tx.send((row, d.clone())).is_ok());
+            let Ok(diff) = result else { continue };
+            let stopped = !diff.done();
+            if stopped || tx.send((row, diff)).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn reports_the_differing_regions_and_the_tail_of_the_longer_file() {
+        let td = tempdir().unwrap();
+        let (a, b) = (td.path().join("a.img"), td.path().join("b.img"));
+        let block = block_size(64 << 10);
+        assert_eq!(block, 4 << 10);
+        let left = vec![0u8; 64 << 10];
+        let mut right = left.clone();
+        // Two adjacent blocks, then one on its own, then 4 KiB more on the right.
+        right[(block + 1) as usize] = 1;
+        right[(2 * block + 7) as usize] = 1;
+        right[(10 * block) as usize] = 1;
+        right.extend(vec![0u8; 4 << 10]);
+        fs::write(&a, &left).unwrap();
+        fs::write(&b, &right).unwrap();
+
+        let diff = compare_blocks(&a, &b, |_| true).unwrap();
+        assert!(diff.done());
+        assert_eq!(diff.total, 68 << 10);
+        assert_eq!(
+            diff.ranges,
+            [
+                (block, 3 * block),
+                (10 * block, 11 * block),
+                (64 << 10, 68 << 10)
+            ]
+        );
+        assert_eq!(diff.differing, 4 * block);
+        let lines = diff.describe(2);
+        assert_eq!(
+            lines[0],
+            "23.5% differs (16.0 KiB) in 3 regions, by 4.0 KiB blocks"
+        );
+        assert_eq!(lines[1], "0x1000..0x3000 (8.0 KiB)");
+        assert_eq!(lines[3], "... 1 more regions");
+
+        let same = compare_blocks(&a, &a, |_| true).unwrap();
+        assert_eq!(same.describe(5), ["identical"]);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::ops::{record_archive, remove_file_or_trash, ARCHIVE_DIR};
+use crate::scan::is_conflict_name;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Syncthing's file-versioning directory.
+pub const VERSIONS_DIR: &str = ".stversions";
+
+/// Why a file is a leftover to delete rather than a conflict to resolve.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LeftoverKind {
+    /// `.syncthing.*.tmp` or `~syncthing~*`, left behind by an interrupted transfer.
+    TempFile,
+    /// A conflict copy inside `.stversions`.
+    VersionedConflict,
+    /// A conflict copy inside a `.stconflict-archive`.
+    ArchivedConflict,
+}
+
+/// A file the Cleanup view offers to delete.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Leftover {
+    pub path: PathBuf,
+    pub kind: LeftoverKind,
+    pub size: u64,
+}
+
+/// Temporary file names Syncthing uses while pulling (`~syncthing~` on Windows).
+pub fn is_temp_name(file_name: &str) -> bool {
+    (file_name.starts_with(".syncthing.") && file_name.ends_with(".tmp"))
+        || file_name.starts_with("~syncthing~")
+}
+
+/// The Syncthing or archive directory a path relative to the root lies in, if any;
+/// conflicts there are leftovers, not groups.
+pub fn nested_kind(rel: &Path) -> Option<LeftoverKind> {
+    let mut dirs = rel.parent()?.components();
+    dirs.find_map(|c| match c.as_os_str().to_str() {
+        Some(VERSIONS_DIR) => Some(LeftoverKind::VersionedConflict),
+        Some(ARCHIVE_DIR) => Some(LeftoverKind::ArchivedConflict),
+        _ => None,
+    })
+}
+
+/// Temp files and nested conflicts under `root`, by kind then path. `.stversions` and
+/// archive directories are always searched; other dot-directories only with
+/// `include_hidden`.
+pub fn find_leftovers(root: &Path, include_hidden: bool) -> Result<Vec<Leftover>> {
+    let mut out = Vec::new();
+    let walk = WalkDir::new(root).follow_links(false).into_iter();
+    let walk = walk.filter_entry(|e| {
+        let name = e.file_name().to_string_lossy();
+        e.depth() == 0
+            || !e.file_type().is_dir()
+            || include_hidden
+            || !name.starts_with('.')
+            || name == VERSIONS_DIR
+            || name == ARCHIVE_DIR
+    });
+    for entry in walk {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy();
+        let rel = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let kind = if is_temp_name(&name) {
+            LeftoverKind::TempFile
+        } else if let Some(kind) = nested_kind(rel).filter(|_| is_conflict_name(&name).is_some()) {
+            kind
+        } else {
+            continue;
+        };
+        out.push(Leftover {
+            path: entry.path().to_path_buf(),
+            kind,
+            size: entry.metadata().map_or(0, |m| m.len()),
+        });
+    }
+    out.sort_by(|a, b| a.kind.cmp(&b.kind).then(a.path.cmp(&b.path)));
+    Ok(out)
+}
+
+/// Deletes the leftovers; an archived conflict is recorded as `cleaned up` in its
+/// archive index first. Returns one result per entry.
+pub fn delete_leftovers(entries: &[Leftover]) -> Vec<Result<()>> {
+    entries
+        .iter()
+        .map(|e| {
+            let archive = e.path.parent().filter(|d| d.ends_with(ARCHIVE_DIR));
+            if let Some(dir) = archive.filter(|_| e.kind == LeftoverKind::ArchivedConflict) {
+                record_archive(dir, &e.path, "cleaned up")?;
+            }
+            remove_file_or_trash(&e.path, false)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::ARCHIVE_INDEX;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn finds_temp_files_and_nested_conflicts_and_deletes_them() {
+        let td = tempdir().unwrap();
+        let root = td.path();
+        let files = [
+            "docs/.syncthing.report.pdf.tmp",
+            "docs/~syncthing~notes.txt.tmp",
+            "docs/.stversions/a.txt.sync-conflict-20240101-010101-DEV",
+            "docs/.stversions/a~20240101-010101.txt",
+            ".stconflict-archive/b.txt.sync-conflict-20240101-010101-DEV",
+            ".git/.syncthing.x.tmp",
+            "docs/c.txt.sync-conflict-20240101-010101-DEV",
+        ];
+        for f in files {
+            let path = root.join(f);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+
+        let found = find_leftovers(root, false).unwrap();
+        let got: Vec<_> = found
+            .iter()
+            .map(|l| (l.path.strip_prefix(root).unwrap().to_path_buf(), l.kind))
+            .collect();
+        assert_eq!(
+            got,
+            [
+                (PathBuf::from(files[0]), LeftoverKind::TempFile),
+                (PathBuf::from(files[1]), LeftoverKind::TempFile),
+                (PathBuf::from(files[2]), LeftoverKind::VersionedConflict),
+                (PathBuf::from(files[4]), LeftoverKind::ArchivedConflict),
+            ]
+        );
+        assert_eq!(find_leftovers(root, true).unwrap().len(), 5);
+
+        assert!(delete_leftovers(&found).iter().all(Result::is_ok));
+        assert!(found.iter().all(|l| !l.path.exists()));
+        assert!(root.join(files[3]).exists());
+        let index = fs::read_to_string(root.join(ARCHIVE_DIR).join(ARCHIVE_INDEX)).unwrap();
+        assert!(index.lines().last().unwrap().contains("\tcleaned up\t"));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::model::ConflictGroup;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::path::{Component, Path};
+
+/// How the TUI orders groups by path (`path_order` in the config).
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PathOrder {
+    /// Directory by directory, ignoring case and accents, with runs of digits compared as
+    /// numbers: `img2` before `img10`, `Éclair` next to `eclair`.
+    #[default]
+    Natural,
+    /// Byte by byte, as the paths are stored.
+    Bytes,
+}
+
+impl PathOrder {
+    pub fn label(self) -> &'static str {
+        match self {
+            PathOrder::Natural => "natural",
+            PathOrder::Bytes => "bytes",
+        }
+    }
+
+    pub fn cmp(self, a: &Path, b: &Path) -> Ordering {
+        match self {
+            PathOrder::Natural => natural_key(a).cmp(&natural_key(b)).then_with(|| a.cmp(b)),
+            PathOrder::Bytes => a.cmp(b),
+        }
+    }
+
+    /// Sorts `groups` by base path.
+    pub fn sort(self, groups: &mut [ConflictGroup]) {
+        match self {
+            PathOrder::Natural => {
+                groups.sort_by_cached_key(|g| (natural_key(&g.base_path), g.base_path.clone()))
+            }
+            PathOrder::Bytes => groups.sort_by(|a, b| a.base_path.cmp(&b.base_path)),
+        }
+    }
+}
+
+/// A run of a name: digits compare by value (leading zeros ignored) and before text,
+/// text compares folded.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Chunk {
+    /// Significant digit count, then the digits.
+    Number(usize, String),
+    Text(String),
+}
+
+/// The natural sort key of `path`: one list of chunks per component, so a directory's
+/// contents stay together.
+fn natural_key(path: &Path) -> Vec<Vec<Chunk>> {
+    path.components()
+        .map(|c| match c {
+            Component::Normal(name) => chunks(&name.to_string_lossy()),
+            other => chunks(&other.as_os_str().to_string_lossy()),
+        })
+        .collect()
+}
+
+fn chunks(name: &str) -> Vec<Chunk> {
+    let mut out = Vec::new();
+    let mut digits = String::new();
+    let mut text = String::new();
+    for ch in name.chars() {
+        if ch.is_ascii_digit() {
+            if !text.is_empty() {
+                out.push(Chunk::Text(std::mem::take(&mut text)));
+            }
+            digits.push(ch);
+        } else {
+            if !digits.is_empty() {
+                out.push(number(std::mem::take(&mut digits)));
+            }
+            fold(ch, &mut text);
+        }
+    }
+    if !digits.is_empty() {
+        out.push(number(digits));
+    }
+    if !text.is_empty() {
+        out.push(Chunk::Text(text));
+    }
+    out
+}
+
+fn number(digits: String) -> Chunk {
+    let significant = digits.trim_start_matches('0').to_string();
+    Chunk::Number(significant.len(), significant)
+}
+
+/// Latin letters with diacritics, by the letter they sort with.
+const ACCENTED: [(&str, &str); 14] = [
+    ("a", "àáâãäåāăą"),
+    ("c", "çćĉċč"),
+    ("d", "ďđð"),
+    ("e", "èéêëēĕėęě"),
+    ("g", "ĝğġģ"),
+    ("h", "ĥħ"),
+    ("i", "ìíîïĩīĭįı"),
+    ("l", "ĺļľŀł"),
+    ("n", "ñńņňŉ"),
+    ("o", "òóôõöøōŏő"),
+    ("r", "ŕŗř"),
+    ("s", "śŝşšș"),
+    ("u", "ùúûüũūŭůűų"),
+    ("y", "ýÿŷ"),
+];
+
+/// Appends `ch` lowercased with its accents dropped; ligatures become their letters.
+fn fold(ch: char, out: &mut String) {
+    for lower in ch.to_lowercase() {
+        match lower {
+            'æ' => out.push_str("ae"),
+            'œ' => out.push_str("oe"),
+            'ß' => out.push_str("ss"),
+            'þ' => out.push_str("th"),
+            'ţ' | 'ť' | 'ŧ' | 'ț' => out.push('t'),
+            'ź' | 'ż' | 'ž' => out.push('z'),
+            'ŵ' => out.push('w'),
+            'ķ' => out.push('k'),
+            'ĵ' => out.push('j'),
+            c => match ACCENTED.iter().find(|(_, from)| from.contains(c)) {
+                Some((base, _)) => out.push_str(base),
+                None => out.push(c),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn sorted(order: PathOrder, paths: &[&str]) -> Vec<String> {
+        let mut v: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+        v.sort_by(|a, b| order.cmp(a, b));
+        v.iter().map(|p| p.display().to_string()).collect()
+    }
+
+    #[test]
+    fn natural_order_compares_numbers_by_value_and_ignores_case_and_accents() {
+        let paths = [
+            "/r/img10.jpg",
+            "/r/img2.jpg",
+            "/r/Zebra.txt",
+            "/r/éclair.txt",
+            "/r/eclair.txt",
+            "/r/b/x.txt",
+            "/r/b-notes.txt",
+            "/r/img002.jpg",
+        ];
+        assert_eq!(
+            sorted(PathOrder::Natural, &paths),
+            [
+                "/r/b/x.txt",
+                "/r/b-notes.txt",
+                "/r/eclair.txt",
+                "/r/éclair.txt",
+                "/r/img002.jpg",
+                "/r/img2.jpg",
+                "/r/img10.jpg",
+                "/r/Zebra.txt",
+            ]
+        );
+        assert_eq!(
+            sorted(PathOrder::Bytes, &paths)[..3],
+            ["/r/Zebra.txt", "/r/b/x.txt", "/r/b-notes.txt"]
+        );
+    }
+}
@@ -0,0 +1,1732 @@
+use crate::daemon::{self, DaemonArgs};
+use crate::tui::{viewer_line, Args};
+use synctui_resolver::alerts::Alerts;
+use synctui_resolver::aliases::DeviceAliases;
+use synctui_resolver::audit;
+use synctui_resolver::collate::PathOrder;
+use synctui_resolver::config::{self, parse_size, Config, ConfigIssue, LoserRule, Severity};
+use synctui_resolver::conflictstats::conflict_stats;
+use synctui_resolver::diff::DiffOptions;
+use synctui_resolver::fixture::{generate, FixtureSpec, Pattern};
+use synctui_resolver::fmt::{format_age, format_size, format_time};
+use synctui_resolver::hashcache::{self, HashCache};
+use synctui_resolver::history::{self, DeviceStats, History, RuleEvent, RuleStats};
+use synctui_resolver::hooks::HookEvent;
+use synctui_resolver::identical::{archive_duplicates, duplicate_conflicts};
+use synctui_resolver::keys::KeySpec;
+use synctui_resolver::model::{ConflictGroup, DeletedOriginal, Strategy, DEFAULT_SKEW_THRESHOLD};
+use synctui_resolver::plan::{Decision, GroupDecision, ResolutionPlan};
+use synctui_resolver::prune::{prune, prune_candidates, PruneEntry};
+use synctui_resolver::rules::PickRule;
+use synctui_resolver::scan::{conflict_meta, rel_path, scan_conflicts_with, ScanOptions};
+use synctui_resolver::scancache;
+use synctui_resolver::session;
+use synctui_resolver::snapshots::configured_shadow;
+use synctui_resolver::thumbnail::Graphics;
+use synctui_resolver::trust::attach_trust;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use clap::{Args as ClapArgs, Subcommand};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Non-interactive subcommands. Without one, the TUI starts.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Show past resolutions recorded by apply
+    History(HistoryArgs),
+    /// Inspect the config file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Try the resolution rules against a folder
+    #[command(subcommand)]
+    Rules(RulesCommand),
+    /// Show how often each pick and loser rule fired in dry-runs and applies
+    Stats(StatsArgs),
+    /// Watch a folder without a terminal and resolve conflicts that need no judgement:
+    /// identical versions, and groups a pick rule, folder preset or policy decides
+    Daemon(DaemonArgs),
+    /// Write a synthetic tree of conflicts for demos and bug reports
+    #[command(hide = true)]
+    GenerateFixture(FixtureArgs),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Report unknown keys, unreachable pick and loser rules, overlapping loser rules, and
+    /// other mistakes
+    /// without starting anything; exits non-zero on errors
+    Check {
+        /// Config file (defaults to `<config dir>/synctui-resolver/config.toml`)
+        #[arg(long, value_name = "PATH")]
+        config: Option<PathBuf>,
+    },
+    /// Print the settings from the config file; with `--effective`, every setting after
+    /// merging defaults, the config file, the environment and TUI flags, each with where
+    /// it came from (e.g. `config show --effective -- --apply --archive-remote rclone:b:c`)
+    Show {
+        /// Include defaults, environment variables and command-line flags
+        #[arg(long)]
+        effective: bool,
+        /// Config file (defaults to `<config dir>/synctui-resolver/config.toml` if present)
+        #[arg(long, value_name = "PATH")]
+        config: Option<PathBuf>,
+        /// TUI flags to resolve, read exactly as the TUI reads them
+        #[arg(last = true, value_name = "FLAGS")]
+        flags: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum RulesCommand {
+    /// Scan PATH and report, per group, what decides the kept version (an earlier pick,
+    /// the deleted-original policy, a pick rule or the strategy) and which loser rule
+    /// handles every other version; nothing is planned or changed
+    Test(RulesTestArgs),
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct RulesTestArgs {
+    /// Folder to scan
+    pub path: PathBuf,
+
+    /// Strategy for groups without a pick: current, newest, oldest, trusted or largest
+    #[arg(long, value_name = "STRATEGY")]
+    pub strategy: Option<Strategy>,
+
+    /// Policy for groups whose original was deleted: promote or discard
+    #[arg(long, value_name = "POLICY")]
+    pub deleted_original: Option<DeletedOriginal>,
+
+    /// Include hidden files and dot-directories
+    #[arg(long)]
+    pub include_hidden: bool,
+
+    /// Config file (defaults to `<config dir>/synctui-resolver/config.toml` if present)
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct FixtureArgs {
+    /// Directory to generate into (created if missing)
+    pub dir: PathBuf,
+
+    /// Number of conflict groups
+    #[arg(long, default_value_t = 20)]
+    pub groups: usize,
+
+    /// Deepest directory nesting below DIR
+    #[arg(long, default_value_t = 2)]
+    pub depth: usize,
+
+    /// Device IDs used in conflict names
+    #[arg(long, value_delimiter = ',', default_value = "PHONE12,LAPTOP1,TABLET3")]
+    pub devices: Vec<String>,
+
+    /// Most conflicts per group (each gets at least one)
+    #[arg(long, default_value_t = 3)]
+    pub max_conflicts: usize,
+
+    /// Approximate size of each file (`512`, `4K`, `1M`, ...)
+    #[arg(long, default_value = "256", value_parser = parse_size)]
+    pub size: u64,
+
+    /// Seed; the same arguments and seed always produce the same tree
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+
+    /// Conflict shapes: suffix and/or infix naming, identical content, deleted originals
+    #[arg(long, value_delimiter = ',', default_value = "suffix")]
+    pub patterns: Vec<Pattern>,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct StatsArgs {
+    /// List the latest firings of one rule (as named in the report, e.g.
+    /// `pick *.jpg: newest`) with what it did or would have done
+    #[arg(long, value_name = "RULE")]
+    pub rule: Option<String>,
+
+    /// Number of firings to list with --rule
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+
+    /// History database (overrides `history_db` in the config)
+    #[arg(long, value_name = "PATH")]
+    pub db: Option<PathBuf>,
+
+    /// Config file (defaults to `<config dir>/synctui-resolver/config.toml` if present)
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct HistoryArgs {
+    /// Per-device report: how often each device's version was kept or discarded, by month
+    #[arg(long)]
+    pub by_device: bool,
+
+    /// List snoozed groups and when each snooze ends
+    #[arg(long)]
+    pub snoozed: bool,
+
+    /// End the snooze of the group at PATH (its original file's path) early
+    #[arg(long, value_name = "PATH")]
+    pub wake: Option<PathBuf>,
+
+    /// Number of recent resolutions to list
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+
+    /// History database (overrides `history_db` in the config)
+    #[arg(long, value_name = "PATH")]
+    pub db: Option<PathBuf>,
+
+    /// Config file (defaults to `<config dir>/synctui-resolver/config.toml` if present)
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+}
+
+pub fn run(cmd: Command) -> Result<()> {
+    match cmd {
+        Command::History(args) => run_history(&args),
+        Command::Config(ConfigCommand::Check { config }) => run_config_check(config),
+        Command::Config(ConfigCommand::Show {
+            effective,
+            config,
+            flags,
+        }) => run_config_show(config, effective, &flags),
+        Command::Rules(RulesCommand::Test(args)) => run_rules_test(&args),
+        Command::Stats(args) => run_stats(&args),
+        Command::Daemon(args) => daemon::run(&args),
+        Command::GenerateFixture(args) => run_generate_fixture(args),
+    }
+}
+
+fn run_rules_test(args: &RulesTestArgs) -> Result<()> {
+    if !args.path.is_dir() {
+        return Err(anyhow!(
+            "{} is not a folder (saved scans can't be read yet)",
+            args.path.display()
+        ));
+    }
+    let config = Config::load(args.config.as_deref())?;
+    let root = args.path.canonicalize()?;
+    let mut groups = scan_conflicts_with(
+        &root,
+        ScanOptions {
+            include_hidden: args.include_hidden,
+            ..ScanOptions::default()
+        },
+    )?;
+    attach_trust(&config.device_trust, &mut groups);
+    let mut builder = ResolutionPlan::builder(&root)
+        .groups(groups)
+        .pick_rules(config.pick_rules.clone())
+        .loser_rules(config.loser_rules.clone());
+    if let Some(s) = args.strategy {
+        builder = builder.strategy(s);
+    }
+    if let Some(p) = args.deleted_original {
+        builder = builder.deleted_original(p);
+    }
+    print!(
+        "{}",
+        rules_report(
+            &root,
+            &builder.explain(),
+            &config.pick_rules,
+            &config.loser_rules
+        )
+    );
+    Ok(())
+}
+
+/// Per group: what decides it and the kept version, then each other version with its
+/// action and rule; a tally of deciding rules last.
+fn rules_report(
+    root: &Path,
+    decisions: &[GroupDecision],
+    pick_rules: &[PickRule],
+    rules: &[LoserRule],
+) -> String {
+    let mut out = String::new();
+    let mut tally: Vec<(String, usize)> = Vec::new();
+    for d in decisions {
+        let g = &d.group;
+        let name = |i: usize| {
+            g.candidates[i]
+                .path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        let why = match d.decision {
+            Decision::Picked(_) => "already picked".to_string(),
+            Decision::DeletedOriginal(p, _) => format!("deleted original: {}", p.label()),
+            Decision::Rule(r, _, _) => {
+                format!("pick_rules[{}]: {}", r + 1, pick_rules[r].label())
+            }
+            Decision::Strategy(s, _) => format!("strategy {}", s.label()),
+            Decision::SkewBlocked(s, skew) => format!(
+                "undecided: clock skew of {} keeps {} from deciding",
+                format_age(skew),
+                s.label()
+            ),
+            Decision::Undecided => "undecided: no strategy or policy applies".to_string(),
+        };
+        let line = match d.decision.chosen() {
+            Some(ci) if ci == 0 && !g.candidates[0].exists => {
+                format!("discard all conflicts ({why})")
+            }
+            Some(ci) => format!("keep {} ({why})", name(ci)),
+            None => why.clone(),
+        };
+        out.push_str(&format!(
+            "{}  {line}\n",
+            rel_path(root, &g.base_path).display()
+        ));
+        for l in &d.losers {
+            let size = g.candidates[l.index]
+                .size
+                .map(format_size)
+                .unwrap_or_else(|| "?".to_string());
+            let rule = match l.rule {
+                Some(i) => format!("loser_rules[{}] ({})", i + 1, rules[i].sizes()),
+                None => "no loser rule".to_string(),
+            };
+            let action = l.action.label();
+            out.push_str(&format!(
+                "  {action:<7} {}  {size}  {rule}\n",
+                name(l.index)
+            ));
+        }
+        let key = why.split(':').next().unwrap_or_default().to_string();
+        match tally.iter_mut().find(|(k, _)| *k == key) {
+            Some(t) => t.1 += 1,
+            None => tally.push((key, 1)),
+        }
+    }
+    let tally: Vec<String> = tally.iter().map(|(k, n)| format!("{n} {k}")).collect();
+    out.push_str(&format!(
+        "{} groups: {}\n",
+        decisions.len(),
+        if tally.is_empty() {
+            "none".to_string()
+        } else {
+            tally.join(", ")
+        }
+    ));
+    out
+}
+
+fn run_generate_fixture(args: FixtureArgs) -> Result<()> {
+    let spec = FixtureSpec {
+        groups: args.groups,
+        depth: args.depth,
+        devices: args.devices,
+        max_conflicts: args.max_conflicts,
+        size: args.size,
+        seed: args.seed,
+        patterns: args.patterns,
+    };
+    let written = generate(&args.dir, &spec)?;
+    println!(
+        "Wrote {} files in {} groups under {}",
+        written.len(),
+        spec.groups,
+        args.dir.display()
+    );
+    Ok(())
+}
+
+fn run_config_check(explicit: Option<PathBuf>) -> Result<()> {
+    let Some(path) = explicit.or_else(config::default_path) else {
+        return Err(anyhow!("no config directory on this system; pass --config"));
+    };
+    if !path.is_file() {
+        println!("No config file at {}; defaults are in use", path.display());
+        return Ok(());
+    }
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow!("read config {}: {e}", path.display()))?;
+    let issues = config::check(&text);
+    print!("{}", check_report(&path, &issues));
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(anyhow!("{errors} error(s) in {}", path.display()));
+    }
+    Ok(())
+}
+
+/// One line per issue (`error: key: message`) and a verdict.
+fn check_report(path: &Path, issues: &[ConfigIssue]) -> String {
+    let mut out = String::new();
+    for i in issues {
+        let level = match i.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let key = if i.key.is_empty() {
+            String::new()
+        } else {
+            format!("{}: ", i.key)
+        };
+        out.push_str(&format!("{level}: {key}{}\n", i.message));
+    }
+    if issues.is_empty() {
+        out.push_str(&format!("{}: OK\n", path.display()));
+    } else {
+        let errors = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .count();
+        out.push_str(&format!(
+            "{}: {errors} error(s), {} warning(s)\n",
+            path.display(),
+            issues.len() - errors
+        ));
+    }
+    out
+}
+
+/// Where an effective setting came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    Config,
+    /// An environment variable.
+    Env(&'static str),
+    /// A command-line flag.
+    Flag(&'static str),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Source::Default => f.write_str("default"),
+            Source::Config => f.write_str("config"),
+            Source::Env(var) => write!(f, "${var}"),
+            Source::Flag(flag) => f.write_str(flag),
+        }
+    }
+}
+
+/// One row of `config show`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Setting {
+    pub key: String,
+    pub value: String,
+    pub source: Source,
+}
+
+fn run_config_show(explicit: Option<PathBuf>, effective: bool, flags: &[String]) -> Result<()> {
+    let args = tui_flags(flags)?;
+    let explicit = explicit.or_else(|| args.config.clone());
+    let path = explicit.clone().or_else(config::default_path);
+    let config = Config::load(explicit.as_deref())?;
+    match path.filter(|p| p.is_file()) {
+        Some(p) => println!("# config file: {}", p.display()),
+        None => println!("# no config file; defaults are in use"),
+    }
+    let settings = effective_settings(&args, &config, |var| std::env::var(var).ok());
+    let shown: Vec<Setting> = settings
+        .into_iter()
+        .filter(|s| effective || s.source == Source::Config)
+        .collect();
+    print!("{}", settings_report(&shown));
+    Ok(())
+}
+
+/// Parses the flags after `config show --` as TUI arguments.
+fn tui_flags(flags: &[String]) -> Result<Args> {
+    use clap::Parser;
+
+    let argv = std::iter::once("synctui-resolver").chain(flags.iter().map(String::as_str));
+    let args = Args::try_parse_from(argv)?;
+    if args.command.is_some() {
+        return Err(anyhow!("flags after `--` can't include a subcommand"));
+    }
+    Ok(args)
+}
+
+/// Every setting the TUI would run with, resolved the way it resolves them: flags over the
+/// environment over the config file over defaults.
+pub fn effective_settings(
+    args: &Args,
+    config: &Config,
+    env: impl Fn(&str) -> Option<String>,
+) -> Vec<Setting> {
+    let mut out = Vec::new();
+    let mut push = |key: &str, value: String, source: Source| {
+        out.push(Setting {
+            key: key.to_string(),
+            value,
+            source,
+        })
+    };
+    let from_flag = |flag: &'static str, set: bool| {
+        if set {
+            Source::Flag(flag)
+        } else {
+            Source::Default
+        }
+    };
+    let on_off = |b: bool| if b { "on" } else { "off" }.to_string();
+
+    let mode = if args.apply { "apply" } else { "dry-run" };
+    push("mode", mode.to_string(), from_flag("--apply", args.apply));
+    push(
+        "include_hidden",
+        on_off(args.include_hidden),
+        from_flag("--include-hidden", args.include_hidden),
+    );
+    push(
+        "follow_symlinks",
+        on_off(args.follow_symlinks),
+        from_flag("--follow-symlinks", args.follow_symlinks),
+    );
+    push(
+        "allow_symlinks",
+        on_off(args.allow_symlinks),
+        from_flag("--allow-symlinks", args.allow_symlinks),
+    );
+    push(
+        "trash",
+        on_off(args.trash),
+        from_flag("--trash", args.trash),
+    );
+    push(
+        "verify",
+        on_off(args.verify),
+        from_flag("--verify", args.verify),
+    );
+    push(
+        "chown_to_owner",
+        on_off(args.chown_to_owner),
+        from_flag("--chown-to-owner", args.chown_to_owner),
+    );
+
+    match &args.archive_remote {
+        Some(remote) => push("archive", remote.clone(), Source::Flag("--archive-remote")),
+        None => push(
+            "archive",
+            ".stconflict-archive (next to each file)".to_string(),
+            Source::Default,
+        ),
+    }
+    if config.loser_rules.is_empty() {
+        push(
+            "loser_rules",
+            "none: every loser is archived".to_string(),
+            Source::Default,
+        );
+    }
+    for (i, r) in config.loser_rules.iter().enumerate() {
+        push(
+            &format!("loser_rules[{}]", i + 1),
+            format!("{} losers of {}", r.action.label(), r.sizes()),
+            Source::Config,
+        );
+    }
+    for (i, r) in config.pick_rules.iter().enumerate() {
+        push(
+            &format!("pick_rules[{}]", i + 1),
+            format!("{} for {}", r.strategy.label(), r.glob),
+            Source::Config,
+        );
+    }
+    for (folder, s) in &config.folder_presets {
+        push(
+            &format!("folder_presets.{folder}"),
+            s.label().to_string(),
+            Source::Config,
+        );
+    }
+    match &args.rename_template {
+        Some(t) => push(
+            "rename_template",
+            t.clone(),
+            Source::Flag("--rename-template"),
+        ),
+        None => push("rename_template", "none".to_string(), Source::Default),
+    }
+
+    let threshold = args.skew_threshold.unwrap_or(DEFAULT_SKEW_THRESHOLD);
+    push(
+        "skew_threshold",
+        format_age(threshold),
+        from_flag("--skew-threshold", args.skew_threshold.is_some()),
+    );
+    push(
+        "trust_mtime",
+        on_off(args.trust_mtime),
+        from_flag("--trust-mtime", args.trust_mtime),
+    );
+    let filters = [
+        (
+            "older_than",
+            "--older-than",
+            args.older_than.map(format_age),
+        ),
+        (
+            "newer_than",
+            "--newer-than",
+            args.newer_than.map(format_age),
+        ),
+        ("min_size", "--min-size", args.min_size.map(format_size)),
+        ("type", "--type", args.kind.map(|k| k.label().to_string())),
+    ];
+    for (key, flag, value) in filters {
+        let source = from_flag(flag, value.is_some());
+        push(key, value.unwrap_or_else(|| "none".to_string()), source);
+    }
+
+    let st = config.syncthing.as_ref();
+    let (url, source) = match (&args.syncthing_url, st) {
+        (Some(u), _) => (u.clone(), Source::Flag("--syncthing-url")),
+        (None, Some(s)) => (s.url.clone(), Source::Config),
+        (None, None) => ("http://127.0.0.1:8384".to_string(), Source::Default),
+    };
+    push("syncthing.url", url, source);
+    // Never print the key itself.
+    let (key, source) = match (&args.syncthing_api_key, st) {
+        (Some(_), _) => ("set", Source::Flag("--syncthing-api-key")),
+        (None, Some(_)) => ("set", Source::Config),
+        (None, None) => ("unset: folders aren't paused during apply", Source::Default),
+    };
+    push("syncthing.api_key", key.to_string(), source);
+
+    let (viewer, source) = viewer_line(config.viewer.as_deref(), &env);
+    push("viewer", viewer, source);
+    if config.device_trust.is_empty() {
+        push("device_trust", "none".to_string(), Source::Default);
+    } else {
+        push(
+            "device_trust",
+            config.device_trust.join(" < "),
+            Source::Config,
+        );
+    }
+    if config.device_aliases.is_empty() {
+        push("device_aliases", "none".to_string(), Source::Default);
+    } else {
+        let aliases: Vec<String> = config
+            .device_aliases
+            .iter()
+            .map(|(id, alias)| format!("{id} = {alias}"))
+            .collect();
+        push("device_aliases", aliases.join(", "), Source::Config);
+    }
+    let root = args
+        .path
+        .canonicalize()
+        .unwrap_or_else(|_| args.path.clone());
+    let (shadow, source) = match (
+        &args.shadow_root,
+        configured_shadow(&config.shadow_roots, &root),
+    ) {
+        (Some(d), _) => (d.display().to_string(), Source::Flag("--shadow-root")),
+        (None, Some(d)) => (d.display().to_string(), Source::Config),
+        (None, None) => ("none".to_string(), Source::Default),
+    };
+    push("shadow_root", shadow, source);
+    let (db, source) = match (&config.history_db, history_path(None, config)) {
+        (Some(p), _) => (p.display().to_string(), Source::Config),
+        (None, Ok(p)) => (p.display().to_string(), Source::Default),
+        (None, Err(_)) => ("none: history isn't recorded".to_string(), Source::Default),
+    };
+    push("history_db", db, source);
+    let (cache, source) = match (&config.hash_cache, hash_cache_path(config)) {
+        (Some(p), _) => (p.display().to_string(), Source::Config),
+        (None, Some(p)) => (p.display().to_string(), Source::Default),
+        (None, None) => ("none: every run hashes again".to_string(), Source::Default),
+    };
+    push("hash_cache", cache, source);
+    let (scans, source) = match (&config.scan_cache, scan_cache_dir(config)) {
+        (Some(p), _) => (p.display().to_string(), Source::Config),
+        (None, Some(p)) => (p.display().to_string(), Source::Default),
+        (None, None) => (
+            "none: interrupted scans start over".to_string(),
+            Source::Default,
+        ),
+    };
+    push("scan_cache", scans, source);
+    let (sessions, source) = match (&config.session_dir, session_dir(config)) {
+        (Some(p), _) => (p.display().to_string(), Source::Config),
+        (None, Some(p)) => (p.display().to_string(), Source::Default),
+        (None, None) => (
+            "none: picks are lost if the TUI dies".to_string(),
+            Source::Default,
+        ),
+    };
+    push("session_dir", sessions, source);
+    match (&args.audit_log, &config.audit_log) {
+        (Some(p), _) => push(
+            "audit_log",
+            p.display().to_string(),
+            Source::Flag("--audit-log"),
+        ),
+        (None, Some(p)) => push("audit_log", p.display().to_string(), Source::Config),
+        (None, None) => push("audit_log", "none".to_string(), Source::Default),
+    }
+    match config.min_free_space {
+        Some(min) => push("min_free_space", format_size(min), Source::Config),
+        None => push("min_free_space", "none".to_string(), Source::Default),
+    }
+    let (diff, default_diff) = (&config.diff, DiffOptions::default());
+    let source = |changed: bool| {
+        if changed {
+            Source::Config
+        } else {
+            Source::Default
+        }
+    };
+    push(
+        "diff.algorithm",
+        diff.algorithm.label().to_string(),
+        source(diff.algorithm != default_diff.algorithm),
+    );
+    push(
+        "diff.context",
+        diff.context.to_string(),
+        source(diff.context != default_diff.context),
+    );
+    push(
+        "diff.ignore_whitespace",
+        diff.ignore_whitespace.to_string(),
+        source(diff.ignore_whitespace != default_diff.ignore_whitespace),
+    );
+    push(
+        "diff.max_size",
+        format_size(diff.max_size),
+        source(diff.max_size != default_diff.max_size),
+    );
+    push(
+        "diff.memory_cap",
+        format_size(diff.memory_cap),
+        source(diff.memory_cap != default_diff.memory_cap),
+    );
+    match config.graphics {
+        Graphics::Auto => push(
+            "graphics",
+            format!("auto: {}", Graphics::Auto.resolve(&env).label()),
+            Source::Default,
+        ),
+        g => push("graphics", g.label().to_string(), Source::Config),
+    }
+    push(
+        "path_order",
+        config.path_order.label().to_string(),
+        source(config.path_order != PathOrder::default()),
+    );
+    for event in [
+        HookEvent::PreApply,
+        HookEvent::PostGroup,
+        HookEvent::PostApply,
+    ] {
+        let key = format!("hooks.{}", event.label());
+        match config.hooks.command(event) {
+            Some(line) => push(&key, line.to_string(), Source::Config),
+            None => push(&key, "none".to_string(), Source::Default),
+        }
+    }
+    let (alerts, default_alerts) = (&config.alerts, Alerts::default());
+    push(
+        "alerts.notice",
+        format_age(alerts.notice),
+        source(alerts.notice != default_alerts.notice),
+    );
+    push(
+        "alerts.warning",
+        format_age(alerts.warning),
+        source(alerts.warning != default_alerts.warning),
+    );
+    for (key, value) in [
+        ("alerts.webhook", &alerts.webhook),
+        ("alerts.command", &alerts.command),
+    ] {
+        match value {
+            Some(v) => push(key, v.clone(), Source::Config),
+            None => push(key, "none".to_string(), Source::Default),
+        }
+    }
+    // Only remapped keys; the defaults are in the help line.
+    for (scope, actions) in &config.keys {
+        for (action, spec) in actions {
+            let keys = match spec {
+                KeySpec::One(k) => k.clone(),
+                KeySpec::Many(ks) => ks.join(", "),
+            };
+            push(&format!("keys.{scope}.{action}"), keys, Source::Config);
+        }
+    }
+    out
+}
+
+/// A loser rule's size range, e.g. `>= 1.0 MiB`.
+/// Aligned `key  value  source` rows.
+fn settings_report(settings: &[Setting]) -> String {
+    let width = settings.iter().map(|s| s.key.len()).max().unwrap_or(0);
+    let value_width = settings
+        .iter()
+        .map(|s| s.value.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    for s in settings {
+        out.push_str(&format!(
+            "{:<width$}  {:<value_width$}  {}\n",
+            s.key, s.value, s.source
+        ));
+    }
+    out
+}
+
+/// History database from `--db`, then the config, then the default location.
+pub fn history_path(explicit: Option<PathBuf>, config: &Config) -> Result<PathBuf> {
+    explicit
+        .or_else(|| config.history_db.clone())
+        .or_else(history::default_path)
+        .ok_or_else(|| anyhow!("no data directory for the history database; pass --db"))
+}
+
+/// Sends this run's file operations to the audit log from `--audit-log` (`flag`), else
+/// the config's `audit_log`; without either nothing is audited.
+pub fn enable_audit(flag: Option<&Path>, config: &Config) -> Result<()> {
+    match flag.or(config.audit_log.as_deref()) {
+        Some(path) => audit::enable(path),
+        None => Ok(()),
+    }
+}
+
+/// Hash cache database from the config, then the default location.
+pub fn hash_cache_path(config: &Config) -> Option<PathBuf> {
+    config.hash_cache.clone().or_else(hashcache::default_path)
+}
+
+pub fn scan_cache_dir(config: &Config) -> Option<PathBuf> {
+    config.scan_cache.clone().or_else(scancache::default_dir)
+}
+
+pub fn session_dir(config: &Config) -> Option<PathBuf> {
+    config.session_dir.clone().or_else(session::default_dir)
+}
+
+fn run_history(args: &HistoryArgs) -> Result<()> {
+    let config = Config::load(args.config.as_deref())?;
+    let mut h = History::open(&history_path(args.db.clone(), &config)?)?;
+    if args.by_device {
+        let aliases = DeviceAliases::new(&config.device_aliases);
+        print!("{}", device_report(&h.device_stats()?, &aliases));
+        return Ok(());
+    }
+    if let Some(path) = &args.wake {
+        let path = std::path::absolute(path)?;
+        if !h.wake(&path)? {
+            return Err(anyhow!("{} is not snoozed", path.display()));
+        }
+        println!("Woke {}", path.display());
+        return Ok(());
+    }
+    if args.snoozed {
+        for (path, until) in h.snoozed(SystemTime::now())? {
+            println!("until {}  {}", when(until), path.display());
+        }
+        return Ok(());
+    }
+    for r in h.recent(args.limit)? {
+        let when = when(r.at);
+        let hash = r
+            .kept_sha256
+            .map(|h| format!("  sha256:{h}"))
+            .unwrap_or_default();
+        let by = r
+            .actor
+            .map(|a| format!("  by {}@{} (v{})", a.user, a.host, a.version))
+            .unwrap_or_default();
+        println!(
+            "{when}  {}  <- {}{hash}{by}",
+            r.base_path.display(),
+            r.kept_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Exit code of a headless scan that found conflicts (none found exits with 0).
+pub const EXIT_CONFLICTS: u8 = 1;
+/// Exit code when scanning, pruning or anything else fails.
+pub const EXIT_ERROR: u8 = 2;
+
+/// `--prune-archive`: lists archived files older than `age`, deleting them (or sending
+/// them to the trash) with `apply`. `quiet` prints only the failures.
+pub fn run_prune(
+    root: &Path,
+    include_hidden: bool,
+    age: Duration,
+    apply: bool,
+    trash: bool,
+    quiet: bool,
+) -> Result<()> {
+    let entries = prune_candidates(root, include_hidden, age, SystemTime::now())?;
+    if !quiet {
+        print!("{}", prune_report(root, &entries, apply));
+    }
+    if !apply {
+        return Ok(());
+    }
+    let mut failed = 0;
+    for (e, res) in entries.iter().zip(prune(&entries, trash)) {
+        if let Err(err) = res {
+            eprintln!("{}: {err:#}", e.path.display());
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!(
+            "{failed} of {} files could not be pruned",
+            entries.len()
+        ));
+    }
+    Ok(())
+}
+
+/// `--dedupe`: lists each group's conflict copies that duplicate another copy and, with
+/// `apply`, archives them.
+pub fn run_dedupe(
+    root: &Path,
+    options: ScanOptions,
+    cache: Option<PathBuf>,
+    apply: bool,
+    quiet: bool,
+) -> Result<()> {
+    let root = root.canonicalize()?;
+    let cache = cache.and_then(|p| HashCache::open(&p).ok());
+    let found: Vec<(ConflictGroup, Vec<Option<usize>>)> = scan_conflicts_with(&root, options)?
+        .into_iter()
+        .map(|g| {
+            let dup = duplicate_conflicts(&g, cache.as_ref());
+            (g, dup)
+        })
+        .filter(|(_, dup)| dup.iter().any(Option::is_some))
+        .collect();
+    if !quiet {
+        print!("{}", dedupe_report(&root, &found, apply));
+    }
+    if !apply {
+        return Ok(());
+    }
+    let mut failed = 0;
+    for (g, dup) in &found {
+        if let Err(err) = archive_duplicates(&root, g, dup) {
+            eprintln!("{}: {err:#}", g.base_path.display());
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!(
+            "{failed} of {} groups could not be deduplicated",
+            found.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Per group, each kept copy followed by the identical copies going to the archive.
+fn dedupe_report(
+    root: &Path,
+    found: &[(ConflictGroup, Vec<Option<usize>>)],
+    apply: bool,
+) -> String {
+    let mut out = String::new();
+    let mut total = 0;
+    for (g, dup) in found {
+        let rel = |i: usize| rel_path(root, &g.candidates[i].path).display().to_string();
+        out.push_str(&format!("{}\n", rel_path(root, &g.base_path).display()));
+        for kept in (0..dup.len()).filter(|&k| dup.contains(&Some(k))) {
+            out.push_str(&format!("  keep     {}\n", rel(kept)));
+            for copy in (0..dup.len()).filter(|&i| dup[i] == Some(kept)) {
+                out.push_str(&format!("  archive  {}\n", rel(copy)));
+                total += 1;
+            }
+        }
+    }
+    let verb = if apply {
+        "Archiving"
+    } else {
+        "Dry-run: would archive"
+    };
+    out.push_str(&format!(
+        "{verb} {total} identical conflict copies in {} groups\n",
+        found.len()
+    ));
+    out
+}
+
+/// `--stats`: scans and prints the conflict copies' counts by device, directory, extension
+/// and age.
+pub fn run_conflict_stats(
+    root: &Path,
+    options: ScanOptions,
+    aliases: &DeviceAliases,
+) -> Result<()> {
+    let groups = scan_conflicts_with(root, options)?;
+    let stats = conflict_stats(root, &groups, aliases, SystemTime::now());
+    for line in stats.lines(usize::MAX) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// `--compare-strategies`: scans and prints which version each strategy would keep.
+pub fn run_compare_strategies(
+    root: &Path,
+    options: ScanOptions,
+    strategies: &[Strategy],
+    config: &Config,
+) -> Result<()> {
+    let mut groups = scan_conflicts_with(root, options)?;
+    attach_trust(&config.device_trust, &mut groups);
+    print!("{}", strategies_report(root, &groups, strategies));
+    Ok(())
+}
+
+/// Per group, the version each strategy would keep on its own (as `--strategy` would,
+/// clock skew included), or one line when they all agree; then how often each pair of
+/// strategies disagrees. A group no strategy decides (clock skew) counts as undecided.
+fn strategies_report(root: &Path, groups: &[ConflictGroup], strategies: &[Strategy]) -> String {
+    let decisions: Vec<Vec<GroupDecision>> = strategies
+        .iter()
+        .map(|&s| {
+            ResolutionPlan::builder(root)
+                .groups(groups.to_vec())
+                .strategy(s)
+                .explain()
+        })
+        .collect();
+    let width = strategies
+        .iter()
+        .map(|s| s.label().len())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    let (mut differ, mut undecided) = (0, 0);
+    let mut pairs = vec![0; strategies.len() * strategies.len()];
+    for (gi, g) in groups.iter().enumerate() {
+        let choices: Vec<Decision> = decisions.iter().map(|d| d[gi].decision).collect();
+        let outcome = |d: Decision| match d {
+            Decision::SkewBlocked(_, skew) => {
+                format!("undecided: clock skew of {}", format_age(skew))
+            }
+            d => match d.chosen() {
+                Some(0) if !g.candidates[0].exists => "discard all conflicts".to_string(),
+                Some(ci) => format!(
+                    "keep {}",
+                    g.candidates[ci]
+                        .path
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default()
+                ),
+                None => "undecided".to_string(),
+            },
+        };
+        let path = rel_path(root, &g.base_path).display();
+        let chosen: Vec<Option<usize>> = choices.iter().map(|d| d.chosen()).collect();
+        if chosen.windows(2).all(|w| w[0] == w[1]) {
+            if chosen[0].is_some() {
+                out.push_str(&format!("{path}  all {}\n", outcome(choices[0])));
+                continue;
+            }
+            undecided += 1;
+            out.push_str(&format!("{path}  undecided\n"));
+        } else {
+            differ += 1;
+            out.push_str(&format!("{path}  strategies disagree\n"));
+        }
+        for (si, (s, &d)) in strategies.iter().zip(&choices).enumerate() {
+            out.push_str(&format!("  {:<width$}  {}\n", s.label(), outcome(d)));
+            for sj in si + 1..strategies.len() {
+                if chosen[si] != chosen[sj] {
+                    pairs[si * strategies.len() + sj] += 1;
+                }
+            }
+        }
+    }
+    out.push_str(&format!(
+        "{} groups: {} agree, {differ} disagree, {undecided} undecided\n",
+        groups.len(),
+        groups.len() - differ - undecided
+    ));
+    for si in 0..strategies.len() {
+        for sj in si + 1..strategies.len() {
+            let n = pairs[si * strategies.len() + sj];
+            if n > 0 {
+                out.push_str(&format!(
+                    "  {} and {} differ on {n}\n",
+                    strategies[si].label(),
+                    strategies[sj].label()
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// `--non-interactive` (and the fallback without a terminal): scans and prints what was
+/// found (nothing with `quiet`) without changing anything. Groups snoozed in `history`
+/// are left out. Returns the exit code: 0 without conflicts, else [`EXIT_CONFLICTS`].
+pub fn run_report(
+    root: &Path,
+    options: ScanOptions,
+    history: Option<&History>,
+    aliases: &DeviceAliases,
+    quiet: bool,
+) -> Result<u8> {
+    let mut groups = scan_conflicts_with(root, options)?;
+    let snoozed = drop_snoozed(history, &mut groups, SystemTime::now());
+    if !quiet {
+        print!("{}", scan_report(root, &groups, aliases));
+        if snoozed > 0 {
+            println!("{snoozed} snoozed groups not shown");
+        }
+    }
+    Ok(if groups.is_empty() { 0 } else { EXIT_CONFLICTS })
+}
+
+/// Removes the groups snoozed at `now`, returning how many.
+pub fn drop_snoozed(
+    history: Option<&History>,
+    groups: &mut Vec<ConflictGroup>,
+    now: SystemTime,
+) -> usize {
+    let Some(snoozed) = history.and_then(|h| h.snoozed(now).ok()) else {
+        return 0;
+    };
+    let before = groups.len();
+    groups.retain(|g| !snoozed.iter().any(|(p, _)| *p == g.base_path));
+    before - groups.len()
+}
+
+/// The history database of the config at `config`, for snoozes in headless runs; `None`
+/// when there is none to open.
+pub fn open_history(config: Option<&Path>) -> Option<History> {
+    let config = Config::load(config).ok()?;
+    History::open(&history_path(None, &config).ok()?).ok()
+}
+
+/// The device aliases of the config, if it loads; reports read without it show IDs.
+pub fn device_aliases(config: Option<&Path>) -> DeviceAliases {
+    Config::load(config)
+        .map(|c| DeviceAliases::new(&c.device_aliases))
+        .unwrap_or_default()
+}
+
+/// One line per group (path, conflict count, devices, newest conflict, clock skew) and a
+/// total.
+pub fn scan_report(root: &Path, groups: &[ConflictGroup], aliases: &DeviceAliases) -> String {
+    let mut out = String::new();
+    for g in groups {
+        let conflicts: Vec<_> = g.candidates.iter().filter(|c| !c.is_original).collect();
+        let devices: Vec<String> = conflicts
+            .iter()
+            .filter_map(|c| conflict_meta(&c.path).map(|m| aliases.name(&m.device)))
+            .collect();
+        let newest = conflicts
+            .iter()
+            .filter_map(|c| c.modified)
+            .max()
+            .map(format_time)
+            .unwrap_or_else(|| "?".to_string());
+        let orig = if g.candidates.first().is_some_and(|c| c.exists) {
+            ""
+        } else {
+            "  original deleted"
+        };
+        let skew = g
+            .clock_skew(DEFAULT_SKEW_THRESHOLD)
+            .map(|s| format!("  clock skew {}", format_age(s)))
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "{}  {} conflicts  devices:{}  newest:{newest}{skew}{orig}\n",
+            rel_path(root, &g.base_path).display(),
+            conflicts.len(),
+            devices.join(",")
+        ));
+    }
+    let versions: usize = groups.iter().map(|g| g.candidates.len() - 1).sum();
+    out.push_str(&format!(
+        "{} conflict groups ({versions} conflict files)\n",
+        groups.len()
+    ));
+    if let Some(g) = groups.first() {
+        out.push_str(&format!("Open one: {}\n", goto_command(root, &g.base_path)));
+    }
+    out
+}
+
+/// The command line that opens the TUI on the group at `path`, e.g. for a notification.
+pub fn goto_command(root: &Path, path: &Path) -> String {
+    format!(
+        "{} --goto {}",
+        open_command(root),
+        shell_word(&rel_path(root, path).display().to_string())
+    )
+}
+
+/// The command line that opens the TUI on `root`.
+pub fn open_command(root: &Path) -> String {
+    format!(
+        "{} {}",
+        env!("CARGO_PKG_NAME"),
+        shell_word(&root.display().to_string())
+    )
+}
+
+/// `s` quoted for a POSIX shell when it has anything but plain path characters.
+fn shell_word(s: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "/._-+,:@%".contains(c);
+    if !s.is_empty() && s.chars().all(plain) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+/// One line per entry (archive time, size, path) and a total.
+pub fn prune_report(root: &Path, entries: &[PruneEntry], apply: bool) -> String {
+    let mut out = String::new();
+    for e in entries {
+        out.push_str(&format!(
+            "{}  {:>10}  {}\n",
+            format_time(e.archived_at),
+            format_size(e.size),
+            e.path.strip_prefix(root).unwrap_or(&e.path).display()
+        ));
+    }
+    let total = format_size(entries.iter().map(|e| e.size).sum());
+    let verb = if apply {
+        "Deleting"
+    } else {
+        "Dry-run: would delete"
+    };
+    out.push_str(&format!(
+        "{verb} {} archived files ({total})\n",
+        entries.len()
+    ));
+    out
+}
+
+/// Devices with the most losses first (the likeliest to need attention), each followed by
+/// its per-month breakdown.
+fn run_stats(args: &StatsArgs) -> Result<()> {
+    let config = Config::load(args.config.as_deref())?;
+    let h = History::open(&history_path(args.db.clone(), &config)?)?;
+    match &args.rule {
+        Some(rule) => print!("{}", rule_events_report(&h.rule_events(rule, args.limit)?)),
+        None => print!("{}", rule_stats_report(&h.rule_stats()?)),
+    }
+    Ok(())
+}
+
+fn when(at: i64) -> String {
+    DateTime::from_timestamp(at, 0)
+        .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// One row per rule: dry-run, applied and failed firings and when it last fired.
+fn rule_stats_report(stats: &[RuleStats]) -> String {
+    if stats.is_empty() {
+        return "No rules fired yet\n".to_string();
+    }
+    let width = stats
+        .iter()
+        .map(|s| s.rule.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("RULE".len());
+    let mut out = format!(
+        "{:<width$} {:>7} {:>7} {:>6}  LAST\n",
+        "RULE", "DRY-RUN", "APPLIED", "FAILED"
+    );
+    for s in stats {
+        out.push_str(&format!(
+            "{:<width$} {:>7} {:>7} {:>6}  {}\n",
+            s.rule,
+            s.dry_runs,
+            s.applied,
+            s.failed,
+            when(s.last_at)
+        ));
+    }
+    out
+}
+
+/// One line per firing: when, how it went, the group and the action.
+fn rule_events_report(events: &[RuleEvent]) -> String {
+    if events.is_empty() {
+        return "That rule never fired\n".to_string();
+    }
+    let mut out = String::new();
+    for e in events {
+        let outcome = match (e.dry_run, e.ok) {
+            (true, _) => "would",
+            (false, true) => "did",
+            (false, false) => "failed",
+        };
+        out.push_str(&format!(
+            "{}  {outcome:<6}  {}  {}\n",
+            when(e.at),
+            e.action,
+            e.base_path.display()
+        ));
+    }
+    out
+}
+
+/// Wins and losses per device (its alias when it has one), then per month.
+fn device_report(stats: &[DeviceStats], aliases: &DeviceAliases) -> String {
+    let mut devices: Vec<(&str, u64, u64)> = Vec::new();
+    for s in stats {
+        match devices.iter_mut().find(|(d, _, _)| *d == s.device) {
+            Some(d) => {
+                d.1 += s.wins;
+                d.2 += s.losses;
+            }
+            None => devices.push((&s.device, s.wins, s.losses)),
+        }
+    }
+    devices.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)));
+
+    if devices.is_empty() {
+        return "No resolutions recorded yet\n".to_string();
+    }
+    let names: Vec<String> = devices.iter().map(|(d, _, _)| aliases.name(d)).collect();
+    let width = names
+        .iter()
+        .map(|n| n.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(10);
+    let mut out = format!(
+        "{:<width$} {:>6} {:>7} {:>5}\n",
+        "DEVICE", "WINS", "LOSSES", "WIN%"
+    );
+    for ((device, wins, losses), name) in devices.into_iter().zip(names) {
+        let pct = wins * 100 / (wins + losses).max(1);
+        out.push_str(&format!("{name:<width$} {wins:>6} {losses:>7} {pct:>4}%\n"));
+        for s in stats.iter().filter(|s| s.device == device) {
+            out.push_str(&format!("  {:<8} {:>6} {:>7}\n", s.month, s.wins, s.losses));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synctui_resolver::config::LoserAction;
+    use synctui_resolver::recency::Recency;
+    use synctui_resolver::scan::scan_conflicts;
+
+    #[test]
+    fn prune_report_lists_entries_and_total() {
+        let e = |p: &str, size| PruneEntry {
+            path: PathBuf::from("/r").join(p),
+            archive_dir: PathBuf::from("/r/.stconflict-archive"),
+            archived_at: SystemTime::UNIX_EPOCH,
+            size,
+        };
+        let report = prune_report(
+            Path::new("/r"),
+            &[e(".stconflict-archive/a.txt", 1024), e("b/x", 2048)],
+            false,
+        );
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[0].ends_with("  1.0 KiB  .stconflict-archive/a.txt"));
+        assert_eq!(lines[2], "Dry-run: would delete 2 archived files (3.0 KiB)");
+    }
+
+    #[test]
+    fn dedupe_archives_identical_conflict_copies_only_with_apply() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        std::fs::write(root.join("a.txt"), "orig").unwrap();
+        for (dev, body) in [("A", "same"), ("B", "same"), ("C", "same"), ("D", "else")] {
+            let name = format!("a.txt.sync-conflict-20240101-000000-{dev}");
+            std::fs::write(root.join(name), body).unwrap();
+        }
+        let options = ScanOptions {
+            include_hidden: true,
+            ..ScanOptions::default()
+        };
+        let groups = scan_conflicts_with(&root, options).unwrap();
+        let dup = duplicate_conflicts(&groups[0], None);
+        let report = dedupe_report(&root, &[(groups[0].clone(), dup)], false);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "a.txt");
+        assert!(lines[1].starts_with("  keep     a.txt.sync-conflict-"));
+        assert!(lines[2].starts_with("  archive  a.txt.sync-conflict-"));
+        assert_eq!(
+            lines.last().unwrap(),
+            &"Dry-run: would archive 2 identical conflict copies in 1 groups"
+        );
+
+        run_dedupe(&root, options, None, false, true).unwrap();
+        assert_eq!(
+            scan_conflicts_with(&root, options).unwrap()[0]
+                .candidates
+                .len(),
+            5
+        );
+        run_dedupe(&root, options, None, true, true).unwrap();
+        let left = &scan_conflicts_with(&root, options).unwrap()[0];
+        assert_eq!(left.candidates.len(), 3);
+        assert_eq!(duplicate_conflicts(left, None), [None, None, None]);
+    }
+
+    #[test]
+    fn config_check_parses_and_reports() {
+        use crate::tui::Args;
+        use clap::Parser;
+
+        let args =
+            Args::try_parse_from(["synctui-resolver", "config", "check", "--config", "c.toml"])
+                .unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Config(ConfigCommand::Check { config: Some(_) }))
+        ));
+
+        let issues = config::check("vewer = \"less\"\n");
+        let report = check_report(Path::new("c.toml"), &issues);
+        assert!(report.starts_with("error: vewer: unknown key"));
+        assert!(report.ends_with("c.toml: 1 error(s), 0 warning(s)\n"));
+        assert_eq!(check_report(Path::new("c.toml"), &[]), "c.toml: OK\n");
+    }
+
+    #[test]
+    fn effective_settings_name_where_each_value_came_from() {
+        use clap::Parser;
+
+        let args = Args::try_parse_from([
+            "synctui-resolver",
+            "config",
+            "show",
+            "--effective",
+            "--",
+            "--archive-remote",
+            "rclone:backup:c",
+            "--apply",
+            "--audit-log",
+            "/srv/audit.jsonl",
+        ])
+        .unwrap();
+        let Some(Command::Config(ConfigCommand::Show {
+            effective: true,
+            flags,
+            ..
+        })) = args.command
+        else {
+            panic!("{:?}", args.command);
+        };
+        let args = tui_flags(&flags).unwrap();
+        let config = Config::parse(
+            "device_trust = [\"PHONE12\", \"LAPTOP1\"]\naudit_log = \"/var/audit.jsonl\"\n\
+             [syncthing]\napi_key = \"secret\"\n\
+             [[loser_rules]]\nmin_size = \"1M\"\naction = \"delete\"\n",
+        )
+        .unwrap();
+        let env = |var: &str| (var == "EDITOR").then(|| "vim".to_string());
+        let settings = effective_settings(&args, &config, env);
+        let get = |key: &str| {
+            let s = settings.iter().find(|s| s.key == key).unwrap();
+            (s.value.as_str(), s.source)
+        };
+        assert_eq!(get("mode"), ("apply", Source::Flag("--apply")));
+        assert_eq!(
+            get("archive"),
+            ("rclone:backup:c", Source::Flag("--archive-remote"))
+        );
+        assert_eq!(
+            get("loser_rules[1]"),
+            ("delete losers of >= 1.0 MiB", Source::Config)
+        );
+        assert_eq!(
+            get("syncthing.url"),
+            ("http://127.0.0.1:8384", Source::Config)
+        );
+        assert_eq!(get("syncthing.api_key"), ("set", Source::Config));
+        assert_eq!(get("viewer"), ("vim", Source::Env("EDITOR")));
+        assert_eq!(get("device_trust"), ("PHONE12 < LAPTOP1", Source::Config));
+        assert_eq!(get("include_hidden"), ("off", Source::Default));
+        assert_eq!(
+            get("audit_log"),
+            ("/srv/audit.jsonl", Source::Flag("--audit-log"))
+        );
+        assert!(!settings.iter().any(|s| s.value.contains("secret")));
+
+        let report = settings_report(&settings[..2]);
+        assert_eq!(
+            report,
+            "mode            apply  --apply\ninclude_hidden  off    default\n"
+        );
+    }
+
+    #[test]
+    fn rules_report_names_the_deciding_rule_per_group() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path();
+        std::fs::write(root.join("a.txt"), "orig").unwrap();
+        std::fs::write(
+            root.join("a.txt.sync-conflict-20240101-000000-DEV"),
+            "conflict",
+        )
+        .unwrap();
+        std::fs::write(root.join("b.txt.sync-conflict-20240101-000000-DEV"), "c").unwrap();
+        let rules = vec![LoserRule {
+            min_size: Some(5),
+            max_size: None,
+            action: LoserAction::Delete,
+        }];
+        // Written now, so the original is newer than the conflict's 2024 name.
+        let picks = vec![PickRule {
+            glob: "a.txt".to_string(),
+            strategy: Strategy::Newest,
+            by: Recency::Name,
+        }];
+        let explained = ResolutionPlan::builder(root)
+            .groups(scan_conflicts(root, true).unwrap())
+            .strategy(Strategy::Current)
+            .deleted_original(DeletedOriginal::Discard)
+            .pick_rules(picks.clone())
+            .loser_rules(rules.clone())
+            .explain();
+        let report = rules_report(root, &explained, &picks, &rules);
+        assert_eq!(
+            report,
+            "a.txt  keep a.txt (pick_rules[1]: newest by name)\n  \
+             delete  a.txt.sync-conflict-20240101-000000-DEV  8 B  loser_rules[1] (>= 5 B)\n\
+             b.txt  discard all conflicts (deleted original: discard)\n  \
+             archive b.txt.sync-conflict-20240101-000000-DEV  1 B  no loser rule\n\
+             2 groups: 1 pick_rules[1], 1 deleted original\n"
+        );
+
+        use clap::Parser;
+        let args = Args::try_parse_from([
+            "synctui-resolver",
+            "rules",
+            "test",
+            ".",
+            "--strategy",
+            "newest",
+        ]);
+        assert!(matches!(
+            args.unwrap().command,
+            Some(Command::Rules(RulesCommand::Test(RulesTestArgs {
+                strategy: Some(Strategy::Newest),
+                ..
+            })))
+        ));
+    }
+
+    #[test]
+    fn strategies_report_shows_where_strategies_disagree() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path();
+        let write = |name: &str, body: &str, modified: SystemTime| {
+            let f = std::fs::File::create(root.join(name)).unwrap();
+            std::io::Write::write_all(&mut &f, body.as_bytes()).unwrap();
+            f.set_modified(modified).unwrap();
+        };
+        let conflict = |base: &str| format!("{base}.sync-conflict-20240101-000000-DEV");
+        let named = conflict_meta(Path::new(&conflict("a.txt")))
+            .and_then(|m| m.timestamp())
+            .unwrap();
+        let day_before = named - Duration::from_secs(86400);
+        // a: the conflict is newer and larger; b: newer but smaller.
+        write("a.txt", "orig", day_before);
+        write(&conflict("a.txt"), "conflict", named);
+        write("b.txt", "original", day_before);
+        write(&conflict("b.txt"), "c", named);
+        // c: only the conflict is left; d: its mtime is far from its name.
+        write(&conflict("c.txt"), "c", named);
+        write("d.txt", "d", day_before);
+        write(&conflict("d.txt"), "dd", SystemTime::now());
+
+        let groups = scan_conflicts(root, true).unwrap();
+        let strategies = [Strategy::Newest, Strategy::Oldest, Strategy::Largest];
+        let report = strategies_report(root, &groups, &strategies);
+        let c = conflict("a.txt");
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "a.txt  strategies disagree");
+        assert_eq!(lines[1], format!("  newest   keep {c}"));
+        assert_eq!(lines[2], "  oldest   keep a.txt");
+        assert_eq!(lines[3], format!("  largest  keep {c}"));
+        assert_eq!(lines[7], "  largest  keep b.txt");
+        assert_eq!(lines[8], format!("c.txt  all keep {}", conflict("c.txt")));
+        assert_eq!(lines[9], "d.txt  strategies disagree");
+        assert!(lines[10].starts_with("  newest   undecided: clock skew of "));
+        assert_eq!(
+            lines[13..],
+            [
+                "4 groups: 1 agree, 3 disagree, 0 undecided",
+                "  newest and oldest differ on 2",
+                "  newest and largest differ on 2",
+                "  oldest and largest differ on 2",
+            ]
+        );
+
+        use clap::Parser;
+        let args = Args::try_parse_from([
+            "synctui-resolver",
+            "--compare-strategies",
+            "newest,largest",
+            ".",
+        ]);
+        assert_eq!(
+            args.unwrap().compare_strategies,
+            [Strategy::Newest, Strategy::Largest]
+        );
+    }
+
+    #[test]
+    fn scan_report_lists_groups_and_total() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path();
+        std::fs::create_dir(root.join("d")).unwrap();
+        std::fs::write(root.join("d/f.txt"), "a").unwrap();
+        for dev in ["PHONE12", "LAPTOP1"] {
+            let name = format!("d/f.txt.sync-conflict-20240101-000000-{dev}");
+            std::fs::write(root.join(name), "b").unwrap();
+        }
+        std::fs::write(root.join("g.txt.sync-conflict-20240101-000000-X"), "c").unwrap();
+
+        let report = scan_report(
+            root,
+            &scan_conflicts(root, true).unwrap(),
+            &DeviceAliases::default(),
+        );
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[0].starts_with("d/f.txt  2 conflicts  devices:LAPTOP1,PHONE12  newest:"));
+        assert!(lines[1].ends_with("original deleted"));
+        // Written now, named 2024-01-01.
+        assert!(lines[1].contains("  clock skew "));
+        assert_eq!(lines[2], "2 conflict groups (3 conflict files)");
+        assert_eq!(
+            lines[3],
+            format!(
+                "Open one: synctui-resolver {} --goto d/f.txt",
+                root.display()
+            )
+        );
+        let spaced = Path::new("/data/My Sync");
+        assert_eq!(
+            goto_command(spaced, &spaced.join("it's.txt")),
+            r"synctui-resolver '/data/My Sync' --goto 'it'\''s.txt'"
+        );
+
+        let options = ScanOptions {
+            include_hidden: true,
+            ..ScanOptions::default()
+        };
+        assert_eq!(
+            run_report(root, options, None, &DeviceAliases::default(), true).unwrap(),
+            EXIT_CONFLICTS
+        );
+        let empty = tempfile::tempdir().unwrap();
+        assert_eq!(
+            run_report(empty.path(), options, None, &DeviceAliases::default(), true).unwrap(),
+            0
+        );
+
+        // Snoozed groups don't count.
+        let mut h = History::open(&empty.path().join("h.sqlite3")).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(3600);
+        let groups = scan_conflicts(root, true).unwrap();
+        for g in &groups {
+            h.snooze(&g.base_path, later).unwrap();
+        }
+        assert_eq!(
+            run_report(root, options, Some(&h), &DeviceAliases::default(), true).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn rule_reports_count_firings_and_say_what_a_rule_did() {
+        let report = rule_stats_report(&[
+            RuleStats {
+                rule: "pick *.jpg: newest".to_string(),
+                dry_runs: 12,
+                applied: 3,
+                failed: 1,
+                last_at: 0,
+            },
+            RuleStats {
+                rule: "loser >= 1.0 GiB: delete".to_string(),
+                dry_runs: 2,
+                applied: 0,
+                failed: 0,
+                last_at: 0,
+            },
+        ]);
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[0].starts_with("RULE                     DRY-RUN APPLIED FAILED  LAST"));
+        assert!(lines[1].starts_with("pick *.jpg: newest            12       3      1  "));
+        assert_eq!(rule_stats_report(&[]), "No rules fired yet\n");
+
+        let e = |dry_run, ok| RuleEvent {
+            at: 0,
+            rule: "pick *.jpg: newest".to_string(),
+            base_path: PathBuf::from("/r/a.jpg"),
+            action: "keep a.jpg".to_string(),
+            dry_run,
+            ok,
+        };
+        let report = rule_events_report(&[e(true, true), e(false, true), e(false, false)]);
+        let outcomes: Vec<&str> = report
+            .lines()
+            .map(|l| l.split("  ").nth(1).unwrap().trim())
+            .collect();
+        assert_eq!(outcomes, ["would", "did", "failed"]);
+        assert!(report.lines().all(|l| l.ends_with("keep a.jpg  /r/a.jpg")));
+    }
+
+    #[test]
+    fn device_report_orders_by_losses() {
+        let s = |device: &str, month: &str, wins, losses| DeviceStats {
+            device: device.to_string(),
+            month: month.to_string(),
+            wins,
+            losses,
+        };
+        let stats = [
+            s("LAPTOP1", "2024-05", 3, 0),
+            s("PHONE12", "2024-04", 1, 2),
+            s("PHONE12", "2024-05", 0, 3),
+        ];
+        let report = device_report(&stats, &DeviceAliases::default());
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[1], "PHONE12         1       5   16%");
+        assert_eq!(lines[2], "  2024-04       1       2");
+        assert!(lines[4].starts_with("LAPTOP1"));
+
+        let aliases = DeviceAliases::new(&std::collections::BTreeMap::from([(
+            "PHONE12".to_string(),
+            "Ana's phone 📱".to_string(),
+        )]));
+        let report = device_report(&stats, &aliases);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "DEVICE          WINS  LOSSES  WIN%");
+        assert_eq!(lines[1], "Ana's phone 📱      1       5   16%");
+        assert!(lines[4].starts_with("LAPTOP1 "));
+    }
+}
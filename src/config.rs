@@ -0,0 +1,852 @@
+use crate::alerts::Alerts;
+use crate::collate::PathOrder;
+use crate::diff::DiffOptions;
+use crate::fmt::format_size;
+use crate::hooks::Hooks;
+use crate::keys::{KeyOverrides, Keymap};
+use crate::model::Strategy;
+use crate::rules::PickRule;
+use crate::thumbnail::Graphics;
+use crate::trust::same_device;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Settings read from `config.toml`. Every section is optional.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub syncthing: Option<SyncthingConfig>,
+    /// Size-keyed handling of non-chosen versions; the first matching rule wins.
+    pub loser_rules: Vec<LoserRule>,
+    /// Glob-keyed strategies that pre-pick unpicked groups; the first matching rule wins.
+    pub pick_rules: Vec<PickRule>,
+    /// Strategies keyed by Syncthing folder ID or label, pre-picking unpicked groups in
+    /// that folder that no pick rule matches. Needs the Syncthing API.
+    #[serde(deserialize_with = "de_presets")]
+    pub folder_presets: BTreeMap<String, Strategy>,
+    /// Command used by `e` in Pick mode, e.g. `"less"` or `"code --wait"`.
+    pub viewer: Option<String>,
+    /// Device IDs from least to most trusted, used by the `trusted` strategy.
+    pub device_trust: Vec<String>,
+    /// Friendly names keyed by full or short device ID, shown wherever a device ID would
+    /// be. Needs no Syncthing API.
+    pub device_aliases: BTreeMap<String, String>,
+    /// SQLite file applied resolutions are logged to (defaults to the data dir).
+    pub history_db: Option<PathBuf>,
+    /// SQLite file of file hashes reused between runs (defaults to the cache dir).
+    pub hash_cache: Option<PathBuf>,
+    /// Directory interrupted scans checkpoint into (defaults to the cache dir).
+    pub scan_cache: Option<PathBuf>,
+    /// Live folders mapped to read-only copies (a mounted backup, say) whose files are
+    /// listed as references in every group below them.
+    pub shadow_roots: BTreeMap<PathBuf, PathBuf>,
+    /// Directory the TUI autosaves its picks into (defaults to the data dir).
+    pub session_dir: Option<PathBuf>,
+    /// JSONL file every file move, delete and write is appended to (off by default).
+    pub audit_log: Option<PathBuf>,
+    /// Applies are refused while the file system holding a folder has less free space
+    /// than this (e.g. `"5 GiB"`), so archiving can't be what fills the disk.
+    #[serde(deserialize_with = "de_opt_size")]
+    pub min_free_space: Option<u64>,
+    /// Algorithm, context lines and whitespace handling of the TUI's diff pane.
+    pub diff: DiffOptions,
+    /// How the TUI draws image previews: `auto`, `kitty`, `sixel` or `halfblocks`.
+    pub graphics: Graphics,
+    /// How the TUI orders groups by path: `natural` (numbers by value, case and accents
+    /// ignored) or `bytes`.
+    pub path_order: PathOrder,
+    /// TUI key remapping: `[keys.<view>]` tables of `action = "key"` (or a list of keys).
+    pub keys: KeyOverrides,
+    /// Commands run before and after real applies: `pre_apply`, `post_group`, `post_apply`.
+    pub hooks: Hooks,
+    /// The daemon's alerts about old unresolved conflicts: `notice` and `warning` ages,
+    /// `webhook`, `command`.
+    pub alerts: Alerts,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyncthingConfig {
+    #[serde(default = "default_syncthing_url")]
+    pub url: String,
+    pub api_key: String,
+}
+
+fn default_syncthing_url() -> String {
+    "http://127.0.0.1:8384".to_string()
+}
+
+/// What happens to a non-chosen version on apply.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LoserAction {
+    /// Move into the archive (local or remote).
+    #[default]
+    Archive,
+    /// Hash, record the hash in the archive index, then remove.
+    Delete,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct LoserRule {
+    #[serde(default, deserialize_with = "de_opt_size")]
+    pub min_size: Option<u64>,
+    #[serde(default, deserialize_with = "de_opt_size")]
+    pub max_size: Option<u64>,
+    pub action: LoserAction,
+}
+
+impl LoserAction {
+    pub fn label(self) -> &'static str {
+        match self {
+            LoserAction::Archive => "archive",
+            LoserAction::Delete => "delete",
+        }
+    }
+}
+
+impl LoserRule {
+    /// `any size`, `>= 1.0 GiB`, `<= 1.0 MiB` or `1.0 MiB..=1.0 GiB`.
+    pub fn sizes(&self) -> String {
+        match (self.min_size, self.max_size) {
+            (None, None) => "any size".to_string(),
+            (Some(min), None) => format!(">= {}", format_size(min)),
+            (None, Some(max)) => format!("<= {}", format_size(max)),
+            (Some(min), Some(max)) => format!("{}..={}", format_size(min), format_size(max)),
+        }
+    }
+
+    /// Bounds are inclusive; a file with unknown size only matches unbounded rules.
+    pub fn matches(&self, size: Option<u64>) -> bool {
+        match size {
+            Some(s) => {
+                self.min_size.is_none_or(|min| s >= min) && self.max_size.is_none_or(|max| s <= max)
+            }
+            None => self.min_size.is_none() && self.max_size.is_none(),
+        }
+    }
+}
+
+/// Picks the action for a loser of the given size (archive when nothing matches).
+pub fn loser_action(rules: &[LoserRule], size: Option<u64>) -> LoserAction {
+    loser_rule(rules, size)
+        .map(|i| rules[i].action)
+        .unwrap_or_default()
+}
+
+/// Index of the rule that decides a loser of the given size, if any.
+pub fn loser_rule(rules: &[LoserRule], size: Option<u64>) -> Option<usize> {
+    rules.iter().position(|r| r.matches(size))
+}
+
+/// Parses sizes like `1500`, `1M`, `1 GB` or `2GiB` (all multiples are powers of 1024).
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num: f64 = num.parse().map_err(|_| anyhow!("invalid size {s:?}"))?;
+    let mult: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        other => bail!("unknown size unit {other:?} in {s:?}"),
+    };
+    Ok((num * mult as f64) as u64)
+}
+
+/// Parses ages like `90d`, `12h`, `2w` or `30m` (a bare number is seconds).
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num: u64 = num.parse().map_err(|_| anyhow!("invalid duration {s:?}"))?;
+    let mult: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" | "min" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        other => bail!("unknown duration unit {other:?} in {s:?} (use s, m, h, d or w)"),
+    };
+    Ok(Duration::from_secs(num * mult))
+}
+
+/// An age such as `"30d"` or `"12h"`.
+pub(crate) fn de_duration<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    parse_duration(&String::deserialize(d)?).map_err(serde::de::Error::custom)
+}
+
+/// A size given as a byte count or text such as `"64 MiB"`.
+pub(crate) fn de_size<'de, D: Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+    de_opt_size(d)?.ok_or_else(|| serde::de::Error::custom("expected a size"))
+}
+
+fn de_presets<'de, D: Deserializer<'de>>(d: D) -> Result<BTreeMap<String, Strategy>, D::Error> {
+    BTreeMap::<String, String>::deserialize(d)?
+        .into_iter()
+        .map(|(folder, s)| {
+            let strategy = s
+                .parse()
+                .map_err(|e| serde::de::Error::custom(format!("folder_presets.{folder}: {e}")))?;
+            Ok((folder, strategy))
+        })
+        .collect()
+}
+
+fn de_opt_size<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Bytes(u64),
+        Text(String),
+    }
+    match Option::<Raw>::deserialize(d)? {
+        None => Ok(None),
+        Some(Raw::Bytes(b)) => Ok(Some(b)),
+        Some(Raw::Text(t)) => parse_size(&t).map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/synctui-resolver/config.toml` (or the platform equivalent;
+/// `%APPDATA%\\synctui-resolver\\config.toml` on Windows).
+pub fn default_path() -> Option<PathBuf> {
+    app_data_dir(cfg!(windows), dirs::config_dir(), |var| {
+        std::env::var(var).ok()
+    })
+    .map(|d| d.join("synctui-resolver").join("config.toml"))
+}
+
+/// `%APPDATA%` on Windows when it is set, else `platform` (the platform's config or data
+/// dir). A service or scheduled task may run as another account than the one whose
+/// settings it should use; setting `APPDATA` for it points the default paths there.
+pub fn app_data_dir(
+    windows: bool,
+    platform: Option<PathBuf>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Option<PathBuf> {
+    env("APPDATA")
+        .filter(|d| windows && !d.is_empty())
+        .map(PathBuf::from)
+        .or(platform)
+}
+
+impl Config {
+    /// Loads `explicit` if given (it must exist), otherwise the default path if present.
+    pub fn load(explicit: Option<&Path>) -> Result<Self> {
+        let path = match explicit {
+            Some(p) => p.to_path_buf(),
+            None => match default_path() {
+                Some(p) if p.is_file() => p,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let text = fs::read_to_string(&path).with_context(|| format!("read config {path:?}"))?;
+        Self::parse(&text).with_context(|| format!("parse config {path:?}"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+}
+
+/// How serious a [`ConfigIssue`] is: errors stop the config from loading or make a setting
+/// misbehave, warnings point at settings that do nothing or surprise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found by [`check`], with the key it concerns (e.g. `loser_rules[2]`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    pub key: String,
+    pub message: String,
+}
+
+/// Keys `Config` accepts, per table.
+const TOP_KEYS: [&str; 20] = [
+    "syncthing",
+    "loser_rules",
+    "pick_rules",
+    "folder_presets",
+    "viewer",
+    "device_trust",
+    "device_aliases",
+    "history_db",
+    "hash_cache",
+    "scan_cache",
+    "shadow_roots",
+    "session_dir",
+    "audit_log",
+    "min_free_space",
+    "diff",
+    "graphics",
+    "path_order",
+    "keys",
+    "hooks",
+    "alerts",
+];
+const SYNCTHING_KEYS: [&str; 2] = ["url", "api_key"];
+const DIFF_KEYS: [&str; 5] = [
+    "algorithm",
+    "context",
+    "ignore_whitespace",
+    "max_size",
+    "memory_cap",
+];
+const HOOK_KEYS: [&str; 3] = ["pre_apply", "post_group", "post_apply"];
+const ALERT_KEYS: [&str; 4] = ["notice", "warning", "webhook", "command"];
+const RULE_KEYS: [&str; 3] = ["min_size", "max_size", "action"];
+const PICK_RULE_KEYS: [&str; 3] = ["glob", "strategy", "by"];
+
+/// Checks config text beyond what loading enforces: every unknown key (loading stops at
+/// the first), loser rules that can never apply or that overlap an earlier rule with a
+/// different action, pick rules repeating an earlier glob, duplicate trust entries,
+/// folder presets without the Syncthing API, Syncthing settings, and `[keys]` names and
+/// conflicts.
+pub fn check(text: &str) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |severity, key: &str, message: String| {
+        issues.push(ConfigIssue {
+            severity,
+            key: key.to_string(),
+            message,
+        })
+    };
+    let table = match text.parse::<toml::Table>() {
+        Ok(t) => t,
+        Err(e) => {
+            issue(Severity::Error, "", format!("not valid TOML: {e}"));
+            return issues;
+        }
+    };
+
+    let mut unknown = |table: &toml::Table, known: &[&str], prefix: &str| {
+        for k in table.keys().filter(|k| !known.contains(&k.as_str())) {
+            issue(
+                Severity::Error,
+                &format!("{prefix}{k}"),
+                format!("unknown key (expected one of: {})", known.join(", ")),
+            );
+        }
+    };
+    unknown(&table, &TOP_KEYS, "");
+    if let Some(st) = table.get("syncthing").and_then(|v| v.as_table()) {
+        unknown(st, &SYNCTHING_KEYS, "syncthing.");
+    }
+    if let Some(diff) = table.get("diff").and_then(|v| v.as_table()) {
+        unknown(diff, &DIFF_KEYS, "diff.");
+    }
+    if let Some(hooks) = table.get("hooks").and_then(|v| v.as_table()) {
+        unknown(hooks, &HOOK_KEYS, "hooks.");
+    }
+    if let Some(alerts) = table.get("alerts").and_then(|v| v.as_table()) {
+        unknown(alerts, &ALERT_KEYS, "alerts.");
+    }
+    if let Some(rules) = table.get("loser_rules").and_then(|v| v.as_array()) {
+        for (i, rule) in rules.iter().enumerate() {
+            if let Some(rule) = rule.as_table() {
+                unknown(rule, &RULE_KEYS, &format!("loser_rules[{}].", i + 1));
+            }
+        }
+    }
+    if let Some(rules) = table.get("pick_rules").and_then(|v| v.as_array()) {
+        for (i, rule) in rules.iter().enumerate() {
+            if let Some(rule) = rule.as_table() {
+                unknown(rule, &PICK_RULE_KEYS, &format!("pick_rules[{}].", i + 1));
+            }
+        }
+    }
+    if !issues.is_empty() {
+        return issues;
+    }
+
+    let config = match Config::parse(text) {
+        Ok(c) => c,
+        Err(e) => {
+            issues.push(ConfigIssue {
+                severity: Severity::Error,
+                key: String::new(),
+                message: format!("{e:#}"),
+            });
+            return issues;
+        }
+    };
+    check_rules(&config.loser_rules, &mut issues);
+    check_trust(&config.device_trust, &mut issues);
+    check_aliases(&config.device_aliases, &mut issues);
+    for (i, rule) in config.pick_rules.iter().enumerate() {
+        if let Some(j) = config.pick_rules[..i].iter().position(|r| r.covers(rule)) {
+            let earlier = &config.pick_rules[j].glob;
+            let message = if *earlier == rule.glob {
+                format!(
+                    "unreachable: pick_rules[{}] has the same glob {earlier:?}",
+                    j + 1
+                )
+            } else {
+                format!(
+                    "unreachable: pick_rules[{}] ({earlier:?}) already matches every path {:?} \
+                     does",
+                    j + 1,
+                    rule.glob
+                )
+            };
+            issues.push(ConfigIssue {
+                severity: Severity::Warning,
+                key: format!("pick_rules[{}]", i + 1),
+                message,
+            });
+        }
+    }
+    if !config.folder_presets.is_empty() && config.syncthing.is_none() {
+        issues.push(ConfigIssue {
+            severity: Severity::Warning,
+            key: "folder_presets".to_string(),
+            message: "folders are looked up through the Syncthing API; without [syncthing] \
+                      (or --syncthing-api-key) these presets are ignored"
+                .to_string(),
+        });
+    }
+    for (key, message) in Keymap::issues(&config.keys) {
+        issues.push(ConfigIssue {
+            severity: Severity::Error,
+            key,
+            message,
+        });
+    }
+    if config.alerts.warning <= config.alerts.notice {
+        issues.push(ConfigIssue {
+            severity: Severity::Warning,
+            key: "alerts.warning".to_string(),
+            message: "not later than alerts.notice; conflicts go straight to a warning".to_string(),
+        });
+    }
+    for (live, shadow) in &config.shadow_roots {
+        if !shadow.is_dir() {
+            issues.push(ConfigIssue {
+                severity: Severity::Warning,
+                key: format!("shadow_roots.{:?}", live.display().to_string()),
+                message: format!(
+                    "{} is not a directory (not mounted?); no references from it",
+                    shadow.display()
+                ),
+            });
+        }
+    }
+    if let Some(st) = &config.syncthing {
+        if !(st.url.starts_with("http://") || st.url.starts_with("https://")) {
+            issues.push(ConfigIssue {
+                severity: Severity::Error,
+                key: "syncthing.url".to_string(),
+                message: format!("{:?} is not an http:// or https:// address", st.url),
+            });
+        }
+        if st.api_key.trim().is_empty() {
+            issues.push(ConfigIssue {
+                severity: Severity::Warning,
+                key: "syncthing.api_key".to_string(),
+                message: "empty; Syncthing requests will be refused".to_string(),
+            });
+        }
+    }
+    issues
+}
+
+/// Inclusive size bounds of a rule.
+fn rule_range(r: &LoserRule) -> (u64, u64) {
+    (r.min_size.unwrap_or(0), r.max_size.unwrap_or(u64::MAX))
+}
+
+fn check_rules(rules: &[LoserRule], issues: &mut Vec<ConfigIssue>) {
+    for (i, rule) in rules.iter().enumerate() {
+        let key = format!("loser_rules[{}]", i + 1);
+        let (lo, hi) = rule_range(rule);
+        if lo > hi {
+            issues.push(ConfigIssue {
+                severity: Severity::Error,
+                key,
+                message: format!(
+                    "never matches: min_size {} is above max_size {}",
+                    format_size(lo),
+                    format_size(hi)
+                ),
+            });
+            continue;
+        }
+        let earlier: Vec<(usize, &LoserRule)> = rules[..i]
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| {
+                let (l, h) = rule_range(r);
+                l <= h
+            })
+            .collect();
+        // Files of unknown size only reach fully unbounded rules.
+        let unknown_covered = rule.min_size.is_some()
+            || rule.max_size.is_some()
+            || earlier
+                .iter()
+                .any(|(_, r)| r.min_size.is_none() && r.max_size.is_none());
+        if unknown_covered && covered(lo, hi, earlier.iter().map(|(_, r)| rule_range(r))) {
+            issues.push(ConfigIssue {
+                severity: Severity::Warning,
+                key,
+                message: "unreachable: earlier rules already match every size it matches"
+                    .to_string(),
+            });
+            continue;
+        }
+        for (j, r) in earlier.iter().filter(|(_, r)| r.action != rule.action) {
+            let (l, h) = rule_range(r);
+            if l <= hi && lo <= h {
+                issues.push(ConfigIssue {
+                    severity: Severity::Warning,
+                    key: key.clone(),
+                    message: format!(
+                        "overlaps loser_rules[{}] between {} and {}; that rule wins there",
+                        j + 1,
+                        format_size(l.max(lo)),
+                        format_bound(h.min(hi))
+                    ),
+                });
+            }
+        }
+    }
+}
+
+fn format_bound(b: u64) -> String {
+    if b == u64::MAX {
+        "any size".to_string()
+    } else {
+        format_size(b)
+    }
+}
+
+/// Whether the union of `ranges` contains every size in `lo..=hi`.
+fn covered(lo: u64, hi: u64, ranges: impl Iterator<Item = (u64, u64)>) -> bool {
+    let mut ranges: Vec<(u64, u64)> = ranges.collect();
+    ranges.sort_unstable();
+    let mut next = lo;
+    for (l, h) in ranges {
+        if l > next {
+            break;
+        }
+        if h >= hi {
+            return true;
+        }
+        next = next.max(h + 1);
+    }
+    false
+}
+
+fn check_trust(trust: &[String], issues: &mut Vec<ConfigIssue>) {
+    let mut seen: Vec<String> = Vec::new();
+    for (i, entry) in trust.iter().enumerate() {
+        let key = format!("device_trust[{}]", i + 1);
+        let id = entry.trim().to_ascii_uppercase();
+        let short = id.split('-').next().unwrap_or_default().to_string();
+        if id.is_empty() {
+            issues.push(ConfigIssue {
+                severity: Severity::Warning,
+                key,
+                message: "empty entry".to_string(),
+            });
+        } else if seen.contains(&short) {
+            issues.push(ConfigIssue {
+                severity: Severity::Warning,
+                key,
+                message: format!(
+                    "{entry:?} repeats an earlier device; only the earlier rank is used"
+                ),
+            });
+        } else {
+            seen.push(short);
+        }
+    }
+}
+
+fn check_aliases(aliases: &BTreeMap<String, String>, issues: &mut Vec<ConfigIssue>) {
+    let mut seen: Vec<&str> = Vec::new();
+    for (id, alias) in aliases {
+        let key = format!("device_aliases.{id}");
+        if alias.trim().is_empty() {
+            issues.push(ConfigIssue {
+                severity: Severity::Warning,
+                key,
+                message: "empty alias; the device ID is shown".to_string(),
+            });
+        } else if let Some(earlier) = seen
+            .iter()
+            .find(|&&s| same_device(s, id) || same_device(id, s))
+        {
+            issues.push(ConfigIssue {
+                severity: Severity::Warning,
+                key,
+                message: format!(
+                    "{id:?} and {earlier:?} name the same device; {earlier:?} is used"
+                ),
+            });
+        } else {
+            seen.push(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_syncthing_section_with_default_url() {
+        let c = Config::parse("[syncthing]\napi_key = \"abc\"\n").unwrap();
+        let st = c.syncthing.unwrap();
+        assert_eq!(st.url, "http://127.0.0.1:8384");
+        assert_eq!(st.api_key, "abc");
+
+        assert!(Config::parse("").unwrap().syncthing.is_none());
+        assert!(Config::parse("bogus = 1\n").is_err());
+    }
+
+    #[test]
+    fn parse_diff_section_over_defaults() {
+        use crate::diff::DiffAlgorithm;
+        let c = Config::parse("[diff]\nalgorithm = \"patience\"\ncontext = 8\n").unwrap();
+        assert_eq!(c.diff.algorithm, DiffAlgorithm::Patience);
+        assert_eq!(c.diff.context, 8);
+        assert!(!c.diff.ignore_whitespace);
+        assert_eq!(Config::parse("").unwrap().diff, DiffOptions::default());
+        assert!(Config::parse("[diff]\nalgorithm = \"histogram\"\n").is_err());
+        let c = Config::parse("[diff]\nmax_size = \"16 MiB\"\nmemory_cap = 1000\n").unwrap();
+        assert_eq!((c.diff.max_size, c.diff.memory_cap), (16 << 20, 1000));
+        assert_eq!(
+            check("[diff]\nwhitespace = true\n")[0].key,
+            "diff.whitespace"
+        );
+    }
+
+    #[test]
+    fn appdata_overrides_the_platform_dir_on_windows_only() {
+        let env =
+            |var: &str| (var == "APPDATA").then(|| r"C:\Users\kid\AppData\Roaming".to_string());
+        let platform = Some(PathBuf::from("/home/kid/.config"));
+        assert_eq!(
+            app_data_dir(true, platform.clone(), env),
+            Some(PathBuf::from(r"C:\Users\kid\AppData\Roaming"))
+        );
+        assert_eq!(app_data_dir(false, platform.clone(), env), platform);
+        assert_eq!(app_data_dir(true, platform.clone(), |_| None), platform);
+    }
+
+    #[test]
+    fn parse_size_units() {
+        assert_eq!(parse_size("1500").unwrap(), 1500);
+        assert_eq!(parse_size("1K").unwrap(), 1024);
+        assert_eq!(parse_size("1 GB").unwrap(), 1 << 30);
+        assert_eq!(parse_size("1.5MiB").unwrap(), 3 << 19);
+        assert!(parse_size("12 parsecs").is_err());
+        assert_eq!(
+            parse_duration("90d").unwrap(),
+            Duration::from_secs(90 * 86400)
+        );
+        assert_eq!(
+            parse_duration("2w").unwrap(),
+            Duration::from_secs(14 * 86400)
+        );
+        assert!(parse_duration("3 fortnights").is_err());
+        assert!(parse_size("").is_err());
+    }
+
+    #[test]
+    fn check_reports_every_unknown_key_then_rule_and_trust_problems() {
+        let keys = |text: &str| -> Vec<(Severity, String)> {
+            check(text)
+                .into_iter()
+                .map(|i| (i.severity, i.key))
+                .collect()
+        };
+        assert_eq!(keys(""), []);
+        assert_eq!(keys("viewer = "), [(Severity::Error, String::new())]);
+        assert_eq!(
+            keys("vewer = \"less\"\n[syncthing]\napi_key = \"k\"\nport = 1\n"),
+            [
+                (Severity::Error, "vewer".to_string()),
+                (Severity::Error, "syncthing.port".to_string()),
+            ]
+        );
+
+        let issues = check(
+            r#"
+            device_trust = ["PHONE12", "phone12-AAAAAAA", "LAPTOP1"]
+
+            [[loser_rules]]
+            min_size = "1M"
+            action = "delete"
+
+            [[loser_rules]]
+            min_size = "2M"
+            max_size = "1G"
+            action = "archive"
+
+            [[loser_rules]]
+            max_size = "2M"
+            action = "archive"
+
+            [[loser_rules]]
+            min_size = "5M"
+            max_size = "1M"
+            action = "archive"
+            "#,
+        );
+        let found: Vec<(Severity, &str, &str)> = issues
+            .iter()
+            .map(|i| {
+                let word = i.message.split([' ', ':']).next().unwrap();
+                (i.severity, i.key.as_str(), word)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Severity::Warning, "loser_rules[2]", "unreachable"),
+                (Severity::Warning, "loser_rules[3]", "overlaps"),
+                (Severity::Error, "loser_rules[4]", "never"),
+                (Severity::Warning, "device_trust[2]", "\"phone12-AAAAAAA\""),
+            ]
+        );
+        assert!(issues[1].message.contains("between 1.0 MiB and 2.0 MiB"));
+
+        // A catch-all after another catch-all never sees files of unknown size either.
+        let issues =
+            check("[[loser_rules]]\naction = \"delete\"\n[[loser_rules]]\naction = \"archive\"\n");
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.starts_with("unreachable"));
+        let issues = check("[syncthing]\nurl = \"localhost:8384\"\napi_key = \"\"\n");
+        assert_eq!(issues.len(), 2);
+
+        // Aliases: an ID named twice (short and full) and an empty alias.
+        assert_eq!(
+            keys(
+                "[device_aliases]\nPHONE12 = \"📱\"\nPHONE12-AAAAAAA = \"Phone\"\nTABLET3 = \"\"\n"
+            ),
+            [
+                (
+                    Severity::Warning,
+                    "device_aliases.PHONE12-AAAAAAA".to_string()
+                ),
+                (Severity::Warning, "device_aliases.TABLET3".to_string()),
+            ]
+        );
+
+        // Key bindings: unknown actions and keys claimed twice.
+        assert_eq!(
+            keys("[keys.list]\nnewest = \"c\"\nnewset = \"x\"\n"),
+            [
+                (Severity::Error, "keys.list.newset".to_string()),
+                (Severity::Error, "keys.list.newest".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_warns_about_pick_rules_behind_a_broader_glob() {
+        let issues = check(
+            "[[pick_rules]]\nglob = \"*.jpg\"\nstrategy = \"newest\"\n\
+             [[pick_rules]]\nglob = \"photos/*.jpg\"\nstrategy = \"oldest\"\n\
+             [[pick_rules]]\nglob = \"photos/*\"\nstrategy = \"current\"\n",
+        );
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].key, "pick_rules[2]");
+        assert!(issues[0].message.contains("pick_rules[1] (\"*.jpg\")"));
+        // Narrower first is the intended order.
+        assert!(check(
+            "[[pick_rules]]\nglob = \"photos/*.jpg\"\nstrategy = \"oldest\"\n\
+             [[pick_rules]]\nglob = \"*.jpg\"\nstrategy = \"newest\"\n",
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn check_warns_about_pick_rules_repeating_a_glob() {
+        let issues = check(
+            "[[pick_rules]]\nglob = \"*.jpg\"\nstrategy = \"newest\"\n\
+             [[pick_rules]]\nglob = \"*.md\"\nstrategy = \"current\"\n\
+             [[pick_rules]]\nglob = \"*.jpg\"\nstrategy = \"oldest\"\nfolder = \"x\"\n",
+        );
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].key, "pick_rules[3].folder");
+        let issues = check(
+            "[[pick_rules]]\nglob = \"*.jpg\"\nstrategy = \"newest\"\n\
+             [[pick_rules]]\nglob = \"*.jpg\"\nstrategy = \"oldest\"\n",
+        );
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert_eq!(issues[0].key, "pick_rules[2]");
+        assert!(issues[0].message.contains("pick_rules[1]"));
+        assert!(Config::parse("[[pick_rules]]\nglob = \"*\"\nstrategy = \"latest\"\n").is_err());
+    }
+
+    #[test]
+    fn folder_presets_parse_strategies_and_want_syncthing() {
+        let text = "[folder_presets]\nPhotos = \"largest\"\n\"abcd-1234\" = \"newest\"\n";
+        let c = Config::parse(text).unwrap();
+        assert_eq!(c.folder_presets["Photos"], Strategy::Largest);
+        assert_eq!(c.folder_presets["abcd-1234"], Strategy::Newest);
+        let err = Config::parse("[folder_presets]\nNotes = \"biggest\"\n").unwrap_err();
+        assert!(format!("{err:#}").contains("folder_presets.Notes"));
+
+        let issues = check(text);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].key, "folder_presets");
+        assert!(check(&format!("{text}[syncthing]\napi_key = \"k\"\n")).is_empty());
+    }
+
+    #[test]
+    fn check_warns_about_shadow_roots_that_are_not_mounted() {
+        let td = tempfile::tempdir().unwrap();
+        let text = format!(
+            "[shadow_roots]\n\"/sync\" = {:?}\n\"/photos\" = \"/mnt/nowhere\"\n",
+            td.path().display().to_string()
+        );
+        let c = Config::parse(&text).unwrap();
+        assert_eq!(c.shadow_roots[Path::new("/sync")], td.path());
+        let issues = check(&text);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].key, "shadow_roots.\"/photos\"");
+        assert!(issues[0]
+            .message
+            .starts_with("/mnt/nowhere is not a directory"));
+    }
+
+    #[test]
+    fn loser_rules_first_match_wins() {
+        let c = Config::parse(
+            r#"
+            [[loser_rules]]
+            min_size = "1G"
+            action = "delete"
+
+            [[loser_rules]]
+            max_size = "1M"
+            action = "archive"
+            "#,
+        )
+        .unwrap();
+        let rules = &c.loser_rules;
+        assert_eq!(loser_action(rules, Some(2 << 30)), LoserAction::Delete);
+        assert_eq!(loser_action(rules, Some(10)), LoserAction::Archive);
+        // Between the tiers nothing matches; archiving is the default.
+        assert_eq!(loser_action(rules, Some(10 << 20)), LoserAction::Archive);
+        assert_eq!(loser_action(rules, None), LoserAction::Archive);
+    }
+}
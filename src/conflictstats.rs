@@ -0,0 +1,265 @@
+use crate::aliases::DeviceAliases;
+use crate::model::ConflictGroup;
+use crate::scan::{conflict_meta, rel_path};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Age buckets of the histogram, by upper bound; older conflicts land in the last one.
+pub const AGE_BUCKETS: [(&str, u64); 5] = [
+    ("< 1 day", DAY),
+    ("1-7 days", 7 * DAY),
+    ("1-4 weeks", 28 * DAY),
+    ("1-12 months", 365 * DAY),
+    ("> 1 year", u64::MAX),
+];
+
+/// Where conflict copies come from, to find the device or app that keeps making them.
+/// Each list is sorted by count, largest first, then by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConflictStats {
+    /// Conflict copies counted (originals aren't).
+    pub conflicts: usize,
+    /// By the short device ID in the file name, or its alias; `?` when the name doesn't
+    /// carry one.
+    pub by_device: Vec<(String, usize)>,
+    /// By directory below the root; `.` is the root itself.
+    pub by_dir: Vec<(PathBuf, usize)>,
+    /// By lowercase file extension; `(none)` without one.
+    pub by_extension: Vec<(String, usize)>,
+    /// One count per [`AGE_BUCKETS`] entry, then the conflicts of unknown age.
+    pub by_age: [usize; AGE_BUCKETS.len() + 1],
+}
+
+/// Counts the existing conflict copies of `groups` by device, directory, extension and
+/// age at `now`. The age comes from the time in the file name, else the modification
+/// time. Devices go by their `aliases`.
+pub fn conflict_stats(
+    root: &Path,
+    groups: &[ConflictGroup],
+    aliases: &DeviceAliases,
+    now: SystemTime,
+) -> ConflictStats {
+    let mut stats = ConflictStats::default();
+    let mut devices: BTreeMap<String, usize> = BTreeMap::new();
+    let mut dirs: BTreeMap<PathBuf, usize> = BTreeMap::new();
+    let mut extensions: BTreeMap<String, usize> = BTreeMap::new();
+    for g in groups {
+        let rel = rel_path(root, &g.base_path);
+        let dir = match rel.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        for c in g.candidates.iter().filter(|c| c.exists && !c.is_original) {
+            stats.conflicts += 1;
+            let meta = conflict_meta(&c.path);
+            let device = meta
+                .as_ref()
+                .map_or("?".to_string(), |m| aliases.name(&m.device));
+            *devices.entry(device).or_default() += 1;
+            *dirs.entry(dir.clone()).or_default() += 1;
+            *extensions
+                .entry(extension(&g.base_path, &c.path))
+                .or_default() += 1;
+            let when = meta.and_then(|m| m.timestamp()).or(c.modified);
+            let bucket = when.map_or(AGE_BUCKETS.len(), |t| {
+                let age = now.duration_since(t).unwrap_or(Duration::ZERO).as_secs();
+                AGE_BUCKETS
+                    .iter()
+                    .position(|&(_, max)| age < max)
+                    .unwrap_or(AGE_BUCKETS.len() - 1)
+            });
+            stats.by_age[bucket] += 1;
+        }
+    }
+    stats.by_device = by_count(devices);
+    stats.by_dir = by_count(dirs);
+    stats.by_extension = by_count(extensions);
+    stats
+}
+
+impl ConflictStats {
+    /// The dashboard as text: a section per breakdown, each row with its count and a bar
+    /// scaled to the section's largest count. At most `limit` rows per list; the rest are
+    /// summed up in a last row.
+    pub fn lines(&self, limit: usize) -> Vec<String> {
+        let mut out = vec![format!("{} conflict copies", self.conflicts)];
+        let devices: Vec<_> = self
+            .by_device
+            .iter()
+            .map(|(d, n)| (d.clone(), *n))
+            .collect();
+        let dirs: Vec<_> = self
+            .by_dir
+            .iter()
+            .map(|(d, n)| (d.display().to_string(), *n))
+            .collect();
+        let extensions: Vec<_> = self
+            .by_extension
+            .iter()
+            .map(|(e, n)| (e.clone(), *n))
+            .collect();
+        let ages: Vec<_> = AGE_BUCKETS
+            .iter()
+            .map(|&(label, _)| label)
+            .chain(["unknown"])
+            .zip(self.by_age)
+            .filter(|&(label, n)| n > 0 || label != "unknown")
+            .map(|(label, n)| (label.to_string(), n))
+            .collect();
+        for (title, rows, limit) in [
+            ("By device", devices, limit),
+            ("By directory", dirs, limit),
+            ("By extension", extensions, limit),
+            ("By age", ages, usize::MAX),
+        ] {
+            out.push(String::new());
+            out.push(title.to_string());
+            section(&mut out, &rows, limit);
+        }
+        out
+    }
+}
+
+fn section(out: &mut Vec<String>, rows: &[(String, usize)], limit: usize) {
+    const BAR: usize = 20;
+    let max = rows.iter().map(|&(_, n)| n).max().unwrap_or(0).max(1);
+    let shown = &rows[..rows.len().min(limit)];
+    let width = shown
+        .iter()
+        .map(|(r, _)| r.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (name, n) in shown {
+        let bar = "#".repeat((n * BAR).div_ceil(max));
+        out.push(
+            format!("  {name:<width$}  {n:>5}  {bar}")
+                .trim_end()
+                .to_string(),
+        );
+    }
+    if rows.len() > shown.len() {
+        let rest: usize = rows[shown.len()..].iter().map(|&(_, n)| n).sum();
+        out.push(format!(
+            "  ... {} more with {rest} conflict copies",
+            rows.len() - shown.len()
+        ));
+    }
+}
+
+/// The lowercase extension Syncthing kept after the device ID of `copy`'s name, else
+/// the one of `base`.
+fn extension(base: &Path, copy: &Path) -> String {
+    let name = copy.file_name().unwrap_or_default().to_string_lossy();
+    let after_id = name
+        .split_once(".sync-conflict-")
+        .and_then(|(_, rest)| rest.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_string());
+    after_id
+        .or_else(|| base.extension().map(|e| e.to_string_lossy().into_owned()))
+        .map_or_else(|| "(none)".to_string(), |e| e.to_lowercase())
+}
+
+fn by_count<K: Ord>(counts: BTreeMap<K, usize>) -> Vec<(K, usize)> {
+    let mut v: Vec<_> = counts.into_iter().collect();
+    // Stable, so equal counts stay sorted by key.
+    v.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Candidate;
+    use crate::scan::ConflictMeta;
+
+    fn cand(path: &str, is_original: bool) -> Candidate {
+        Candidate {
+            path: PathBuf::from(path),
+            exists: true,
+            is_original,
+            size: Some(1),
+            modified: None,
+            label: String::new(),
+            trust: None,
+        }
+    }
+
+    fn group(base: &str, conflicts: &[&str]) -> ConflictGroup {
+        let mut candidates = vec![cand(base, true)];
+        candidates.extend(conflicts.iter().map(|c| cand(c, false)));
+        ConflictGroup {
+            base_path: PathBuf::from(base),
+            candidates,
+            chosen: None,
+            references: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn counts_conflict_copies_by_device_directory_extension_and_age() {
+        let groups = [
+            group(
+                "/r/docs/a.TXT",
+                &[
+                    "/r/docs/a.sync-conflict-20240110-120000-PHONE12.TXT",
+                    "/r/docs/a.TXT.sync-conflict-20240101-120000-PHONE12",
+                ],
+            ),
+            group(
+                "/r/b",
+                &["/r/b.sync-conflict-20231201-120000-LAPTOP1", "/r/b.old"],
+            ),
+        ];
+        let now = ConflictMeta {
+            date: "20240111".to_string(),
+            time: "000000".to_string(),
+            device: String::new(),
+        }
+        .timestamp()
+        .unwrap();
+        let aliases = DeviceAliases::new(&BTreeMap::from([(
+            "LAPTOP1".to_string(),
+            "💻 Laptop".to_string(),
+        )]));
+        let stats = conflict_stats(Path::new("/r"), &groups, &aliases, now);
+
+        assert_eq!(stats.conflicts, 4);
+        assert_eq!(
+            stats.by_device,
+            [
+                ("PHONE12".to_string(), 2),
+                ("?".to_string(), 1),
+                ("💻 Laptop".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            stats.by_dir,
+            [(PathBuf::from("."), 2), (PathBuf::from("docs"), 2)]
+        );
+        assert_eq!(
+            stats.by_extension,
+            [("(none)".to_string(), 2), ("txt".to_string(), 2)]
+        );
+        // 12 hours, 9.5 days, 41.5 days, and no time at all.
+        assert_eq!(stats.by_age, [1, 0, 1, 1, 0, 1]);
+
+        let lines = stats.lines(1);
+        assert_eq!(lines[0], "4 conflict copies");
+        assert_eq!(lines[2], "By device");
+        assert_eq!(lines[3], "  PHONE12      2  ####################");
+        assert_eq!(lines[4], "  ... 2 more with 2 conflict copies");
+        let ages = lines.iter().position(|l| l == "By age").unwrap();
+        assert_eq!(
+            lines[ages + 1],
+            "  < 1 day          1  ####################"
+        );
+        assert_eq!(lines[ages + 2], "  1-7 days         0");
+        assert_eq!(
+            lines.last().unwrap(),
+            "  unknown          1  ####################"
+        );
+    }
+}
@@ -0,0 +1,983 @@
+use crate::commands::{
+    drop_snoozed, enable_audit, goto_command, hash_cache_path, history_path, open_command,
+};
+
+use synctui_resolver::alerts::{conflict_since, Alert, AlertLevel, Alerts};
+use synctui_resolver::config::{app_data_dir, parse_duration, Config};
+use synctui_resolver::hashcache::HashCache;
+use synctui_resolver::history::History;
+use synctui_resolver::hooks::{HookEvent, HookGroup};
+use synctui_resolver::identical::candidates_identical;
+use synctui_resolver::model::{DeletedOriginal, Strategy, DEFAULT_SKEW_THRESHOLD};
+use synctui_resolver::plan::{
+    apply_paused, check_free_space, kept_destination, rescan_folders, ApplyOptions, Decision,
+    PlannedGroup, ResolutionPlan,
+};
+use synctui_resolver::rules::fired_rules;
+use synctui_resolver::scan::{rel_path, scan_conflicts_with, ScanOptions};
+use synctui_resolver::settle::rescan_delay;
+use synctui_resolver::syncthing::{folder_preset, Folder, SyncthingClient};
+use synctui_resolver::trust::attach_trust;
+use synctui_resolver::watch::ConflictWatcher;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use clap::Args as ClapArgs;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct DaemonArgs {
+    /// Folder to watch
+    pub path: PathBuf,
+
+    /// Move files; without it every pass is a dry run that only logs what it would do
+    #[arg(long)]
+    pub apply: bool,
+
+    /// Strategy for groups no pick rule, folder preset or identical content decides
+    /// (by default they are left for the TUI)
+    #[arg(long, value_name = "STRATEGY")]
+    pub strategy: Option<Strategy>,
+
+    /// Policy for groups whose original was deleted: promote or discard
+    #[arg(long, value_name = "POLICY")]
+    pub deleted_original: Option<DeletedOriginal>,
+
+    /// Include hidden files and dot-directories
+    #[arg(long)]
+    pub include_hidden: bool,
+
+    /// Also follow Syncthing's event stream (needs `[syncthing]` in the config)
+    #[arg(long)]
+    pub watch_syncthing: bool,
+
+    /// Resolve once and exit instead of watching, e.g. from a scheduled task; exits with
+    /// status 2 when the pass or any group in it fails
+    #[arg(long)]
+    pub once: bool,
+
+    /// Quiet time after the last change before resolving, so Syncthing finishes writing
+    #[arg(long, value_name = "AGE", default_value = "10s", value_parser = parse_duration)]
+    pub settle: Duration,
+
+    /// File to append the log to (defaults to stderr, or to
+    /// `<data dir>/synctui-resolver/daemon.log` when stderr isn't a terminal)
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Append every file moved or deleted to this JSONL file (overrides `audit_log` in
+    /// the config)
+    #[arg(long, value_name = "PATH")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Config file (defaults to `<config dir>/synctui-resolver/config.toml` if present)
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+}
+
+/// `<data dir>/synctui-resolver/daemon.log` (`%APPDATA%` on Windows).
+fn default_log_path() -> Option<PathBuf> {
+    app_data_dir(cfg!(windows), dirs::data_dir(), |var| {
+        std::env::var(var).ok()
+    })
+    .map(|d| d.join("synctui-resolver").join("daemon.log"))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn label(self) -> &'static str {
+        match self {
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+/// One line per event: local time with its offset, a level matching the Windows event
+/// log's Information, Warning and Error, and the message. No colors or cursor codes, so
+/// it reads the same in a file, a service wrapper's log or a terminal.
+struct Log {
+    out: Box<dyn Write>,
+}
+
+impl Log {
+    fn open(path: Option<&Path>) -> Result<Self> {
+        let out: Box<dyn Write> = match path {
+            Some(p) => {
+                if let Some(parent) = p.parent().filter(|d| !d.as_os_str().is_empty()) {
+                    fs::create_dir_all(parent).with_context(|| format!("create {parent:?}"))?;
+                }
+                let f = File::options()
+                    .create(true)
+                    .append(true)
+                    .open(p)
+                    .with_context(|| format!("open log file {p:?}"))?;
+                Box::new(f)
+            }
+            None => Box::new(io::stderr()),
+        };
+        Ok(Self { out })
+    }
+
+    fn line(&mut self, level: Level, message: &str) {
+        let at = Local::now().format("%Y-%m-%dT%H:%M:%S%:z");
+        // Nowhere left to report a failing log to.
+        let _ = writeln!(self.out, "{at} {:<5} {message}", level.label());
+        let _ = self.out.flush();
+    }
+
+    fn info(&mut self, message: &str) {
+        self.line(Level::Info, message);
+    }
+
+    fn warn(&mut self, message: &str) {
+        self.line(Level::Warn, message);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.line(Level::Error, message);
+    }
+}
+
+/// What a pass runs with, opened once at startup.
+struct Daemon {
+    root: PathBuf,
+    args: DaemonArgs,
+    config: Config,
+    history: Option<History>,
+    hash_cache: Option<HashCache>,
+    /// Syncthing's API, when the config has `[syncthing]`.
+    client: Option<SyncthingClient>,
+    /// Syncthing's folders, for `[folder_presets]`.
+    folders: Vec<Folder>,
+    /// Folders a large apply resumed without their rescan, until the disk settles.
+    unscanned: Vec<String>,
+    /// Groups left for review that were already logged with their `--goto` command.
+    announced: BTreeSet<PathBuf>,
+    /// Groups left for review by the last pass, with when their oldest conflict appeared.
+    review_since: BTreeMap<PathBuf, SystemTime>,
+    /// The highest `[alerts]` level each group left for review was alerted at.
+    alerted: BTreeMap<PathBuf, AlertLevel>,
+    /// Whether the last pass was refused for low disk space (alerted once until it isn't).
+    low_space: bool,
+    log: Log,
+}
+
+/// What a pass did with the groups it decided.
+#[derive(Clone, Copy, Debug, Default)]
+struct PassSummary {
+    /// Resolved (or would be, in a dry run).
+    resolved: usize,
+    /// Failed to apply (or to validate, in a dry run).
+    failed: usize,
+}
+
+/// How often conflicts left for review are checked against the `[alerts]` ages when
+/// nothing changes.
+const AGE_CHECK_EVERY: Duration = Duration::from_secs(60 * 60);
+
+/// Watches a folder without a terminal and resolves the conflicts that need no
+/// judgement, logging each step; see [`Daemon::pass`] for what gets resolved.
+pub fn run(args: &DaemonArgs) -> Result<()> {
+    let log_path = args.log_file.clone().or_else(|| {
+        (!io::stderr().is_terminal())
+            .then(default_log_path)
+            .flatten()
+    });
+    let mut log = Log::open(log_path.as_deref())?;
+    let opened = Config::load(args.config.as_deref()).and_then(|config| {
+        let root = args
+            .path
+            .canonicalize()
+            .with_context(|| format!("open {:?}", args.path))?;
+        enable_audit(args.audit_log.as_deref(), &config)?;
+        Ok((config, root))
+    });
+    let (config, root) = match opened {
+        Ok(v) => v,
+        Err(e) => {
+            log.error(&format!("{e:#}"));
+            return Err(e);
+        }
+    };
+    let client = config
+        .syncthing
+        .as_ref()
+        .map(|s| SyncthingClient::new(&s.url, &s.api_key));
+    let mut daemon = Daemon {
+        root,
+        args: args.clone(),
+        config,
+        history: None,
+        hash_cache: None,
+        client,
+        folders: Vec::new(),
+        unscanned: Vec::new(),
+        announced: BTreeSet::new(),
+        review_since: BTreeMap::new(),
+        alerted: BTreeMap::new(),
+        low_space: false,
+        log,
+    };
+    let res = daemon.watch();
+    if let Err(e) = &res {
+        daemon.log.error(&format!("{e:#}"));
+    }
+    res
+}
+
+impl Daemon {
+    fn watch(&mut self) -> Result<()> {
+        self.log.info(&format!(
+            "synctui-resolver {} {} {} ({})",
+            env!("CARGO_PKG_VERSION"),
+            if self.args.once {
+                "resolving"
+            } else {
+                "watching"
+            },
+            self.root.display(),
+            if self.args.apply { "apply" } else { "dry-run" }
+        ));
+        match history_path(None, &self.config).and_then(|p| History::open(&p)) {
+            Ok(h) => self.history = Some(h),
+            Err(e) => self.log.warn(&format!("history disabled: {e:#}")),
+        }
+        self.hash_cache = hash_cache_path(&self.config).and_then(|p| HashCache::open(&p).ok());
+        if let Some(client) = self
+            .client
+            .as_ref()
+            .filter(|_| !self.config.folder_presets.is_empty())
+        {
+            match client.folders() {
+                Ok(folders) => self.folders = folders,
+                Err(e) => self.log.warn(&format!("folder presets disabled: {e:#}")),
+            }
+        }
+
+        let pass = self.pass_checked();
+        if self.args.once {
+            if let Some(summary) = pass.as_ref().ok().filter(|_| !self.unscanned.is_empty()) {
+                thread::sleep(rescan_delay(summary.resolved));
+                self.rescan_unscanned();
+            }
+            // A scheduler only sees the exit status, so failures must show there.
+            let summary = pass.context("pass failed")?;
+            if summary.failed > 0 {
+                return Err(anyhow!(
+                    "{} of {} groups could not be resolved",
+                    summary.failed,
+                    summary.failed + summary.resolved
+                ));
+            }
+            return Ok(());
+        }
+        let mut watcher = ConflictWatcher::start(&self.root)?;
+        if self.args.watch_syncthing {
+            let client = self
+                .client
+                .clone()
+                .context("--watch-syncthing needs [syncthing] in the config")?;
+            watcher
+                .watch_syncthing(client, &self.root)
+                .context("follow Syncthing events")?;
+        }
+        let mut changed_at: Option<Instant> = None;
+        // After a large apply the next pass also waits for the disk to settle.
+        let mut quiet_until = Instant::now();
+        let mut aged_at = Instant::now();
+        loop {
+            if watcher.poll_conflicts() {
+                changed_at = Some(Instant::now());
+            }
+            if changed_at.is_some_and(|t| t.elapsed() >= self.args.settle)
+                && Instant::now() >= quiet_until
+            {
+                changed_at = None;
+                let resolved = match self.pass_checked() {
+                    Ok(summary) => summary.resolved,
+                    // Waited out: the next change tries again.
+                    Err(e) => {
+                        self.log.error(&format!("pass failed: {e:#}"));
+                        0
+                    }
+                };
+                if self.args.apply {
+                    quiet_until = Instant::now() + rescan_delay(resolved);
+                }
+            }
+            if !self.unscanned.is_empty() && Instant::now() >= quiet_until {
+                self.rescan_unscanned();
+            }
+            if aged_at.elapsed() >= AGE_CHECK_EVERY {
+                aged_at = Instant::now();
+                self.check_ages(SystemTime::now());
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+
+    /// Asks Syncthing to rescan the folders a large apply resumed without their rescan.
+    fn rescan_unscanned(&mut self) {
+        let ids = std::mem::take(&mut self.unscanned);
+        let Some(client) = &self.client else {
+            return;
+        };
+        for warning in rescan_folders(client, &ids) {
+            self.log.warn(&warning);
+        }
+    }
+
+    /// Logs why a pass applied nothing for lack of disk space, and alerts about it at the
+    /// warning level the first time in a row.
+    fn refuse_low_space(&mut self, problem: &str) {
+        self.log.warn(problem);
+        if std::mem::replace(&mut self.low_space, true) {
+            return;
+        }
+        let alert = Alert {
+            level: AlertLevel::Warning,
+            path: PathBuf::from("."),
+            age_days: 0,
+            open: open_command(&self.root),
+            problem: Some(problem.to_string()),
+        };
+        if let Err(e) = self.config.alerts.send(&self.root, &[alert]) {
+            self.log.warn(&format!("{e:#}"));
+        }
+    }
+
+    /// A pass, then alerts about what it left for review (whether or not it failed).
+    fn pass_checked(&mut self) -> Result<PassSummary> {
+        let pass = self.pass();
+        self.check_ages(SystemTime::now());
+        pass
+    }
+
+    /// Alerts about each group left for review whose oldest conflict has reached a new
+    /// `[alerts]` level at `now`: a notice, then a warning, once each per daemon run. They
+    /// are logged and sent to the configured webhook and command.
+    fn check_ages(&mut self, now: SystemTime) {
+        let mut due = Vec::new();
+        for (p, since) in &self.review_since {
+            let age = now.duration_since(*since).unwrap_or_default();
+            let Some(level) = self.config.alerts.level(age) else {
+                continue;
+            };
+            if self.alerted.get(p).is_some_and(|l| *l >= level) {
+                continue;
+            }
+            self.alerted.insert(p.clone(), level);
+            due.push(Alert {
+                level,
+                path: rel_path(&self.root, p).to_path_buf(),
+                age_days: age.as_secs() / (24 * 60 * 60),
+                open: goto_command(&self.root, p),
+                problem: None,
+            });
+        }
+        for alert in &due {
+            let line = Alerts::describe(alert);
+            match alert.level {
+                AlertLevel::Notice => self.log.info(&line),
+                AlertLevel::Warning => self.log.warn(&line),
+            }
+        }
+        if let Err(e) = self.config.alerts.send(&self.root, &due) {
+            self.log.warn(&format!("{e:#}"));
+        }
+    }
+
+    /// Scans the folder and resolves groups whose versions are all identical (keeping
+    /// the original), then those a pick rule, folder preset, the deleted-original policy
+    /// or `--strategy` decides. Everything else is left for the TUI. Snoozed groups are
+    /// skipped. Returns how many groups were resolved (or would be, in a dry run) and how
+    /// many failed.
+    fn pass(&mut self) -> Result<PassSummary> {
+        let scan = ScanOptions {
+            include_hidden: self.args.include_hidden,
+            ..ScanOptions::default()
+        };
+        let mut groups = scan_conflicts_with(&self.root, scan)?;
+        drop_snoozed(self.history.as_ref(), &mut groups, SystemTime::now());
+        attach_trust(&self.config.device_trust, &mut groups);
+        for g in &mut groups {
+            if g.candidates[0].exists
+                && candidates_identical(g, self.hash_cache.as_ref()) == Some(true)
+            {
+                g.chosen = Some(0);
+            }
+        }
+        let mut builder = ResolutionPlan::builder(&self.root)
+            .groups(groups)
+            .pick_rules(self.config.pick_rules.clone());
+        if let Some(s) = self.args.strategy {
+            builder = builder.strategy(s);
+        }
+        if let Some(p) = self.args.deleted_original {
+            builder = builder.deleted_original(p);
+        }
+
+        let mut planned = Vec::new();
+        let mut reasons = Vec::new();
+        let mut left = 0;
+        let mut review = BTreeSet::new();
+        let mut since = BTreeMap::new();
+        for d in builder.explain() {
+            let mut group = d.group;
+            let (chosen, why) = match d.decision {
+                Decision::Picked(i) => (i, "identical versions".to_string()),
+                Decision::DeletedOriginal(p, i) => (i, format!("deleted original: {}", p.label())),
+                Decision::Rule(r, _, i) => (
+                    i,
+                    format!(
+                        "pick_rules[{}]: {}",
+                        r + 1,
+                        self.config.pick_rules[r].label()
+                    ),
+                ),
+                Decision::Strategy(s, i) => (i, format!("strategy {}", s.label())),
+                Decision::SkewBlocked(..) | Decision::Undecided => {
+                    let preset =
+                        folder_preset(&self.config.folder_presets, &self.folders, &group.base_path)
+                            .filter(|s| {
+                                !(s.mtime_only()
+                                    && group.clock_skew(DEFAULT_SKEW_THRESHOLD).is_some())
+                            });
+                    match preset.and_then(|s| s.choose(&group).map(|i| (s, i))) {
+                        Some((s, i)) => (i, format!("folder preset: {}", s.label())),
+                        None => {
+                            left += 1;
+                            if let Some(t) = conflict_since(&group) {
+                                since.insert(group.base_path.clone(), t);
+                            }
+                            review.insert(group.base_path);
+                            continue;
+                        }
+                    }
+                }
+            };
+            group.chosen = Some(chosen);
+            planned.push(PlannedGroup { group, chosen });
+            reasons.push(why);
+        }
+
+        let options = ApplyOptions {
+            loser_rules: self.config.loser_rules.clone(),
+            dry_run: !self.args.apply,
+            min_free_space: self.config.min_free_space,
+            ..ApplyOptions::default()
+        };
+        let mut plan = ResolutionPlan {
+            root: self.root.clone(),
+            groups: planned,
+            options,
+        };
+        // Checked once for the batch, so a full disk skips it whole rather than failing
+        // each group in turn.
+        let mut failed = 0;
+        if !plan.options.dry_run && !plan.groups.is_empty() {
+            match check_free_space(&self.root, &plan.options) {
+                Ok(()) => self.low_space = false,
+                Err(e) => {
+                    failed = std::mem::take(&mut plan.groups).len();
+                    self.refuse_low_space(&format!(
+                        "automatic applies refused, {failed} groups skipped: {e:#}"
+                    ));
+                }
+            }
+        }
+        let hooked = !plan.options.dry_run && !plan.groups.is_empty();
+        if hooked {
+            let planned: Vec<_> = plan
+                .groups
+                .iter()
+                .map(|p| HookGroup::new(&p.group, p.chosen, &plan.options, "planned"))
+                .collect();
+            self.config
+                .hooks
+                .run(HookEvent::PreApply, &self.root, &planned)?;
+        }
+        // Syncthing's folders stay paused while the files move, as in the TUI.
+        let run = apply_paused(&plan, self.client.as_ref())?;
+        for warning in &run.warnings {
+            self.log.warn(warning);
+        }
+        self.unscanned.extend(run.unscanned);
+        let mut resolved = 0;
+        let mut applied = Vec::new();
+        for ((p, why), outcome) in plan.groups.iter().zip(&reasons).zip(run.outcomes) {
+            let g = &p.group;
+            if hooked {
+                let mut group = HookGroup::new(g, p.chosen, &plan.options, "applied");
+                if let Err(e) = &outcome.result {
+                    group.status = "failed";
+                    group.error = Some(format!("{e:#}"));
+                }
+                let ran = self.config.hooks.run(
+                    HookEvent::PostGroup,
+                    &self.root,
+                    std::slice::from_ref(&group),
+                );
+                if let Err(e) = ran {
+                    self.log.warn(&format!("{e:#}"));
+                }
+                applied.push(group);
+            }
+            let name = rel_path(&self.root, &g.base_path).display().to_string();
+            let kept = g.candidates[p.chosen]
+                .path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let fired = fired_rules(
+                &self.root,
+                g,
+                &self.config.pick_rules,
+                &plan.options.loser_rules,
+            );
+            let ok = outcome.result.is_ok();
+            if let Some(h) = self.history.as_mut().filter(|_| !fired.is_empty()) {
+                let at = SystemTime::now();
+                if let Err(e) = h.record_rules(&g.base_path, &fired, plan.options.dry_run, ok, at) {
+                    self.log.warn(&format!("history: {e:#}"));
+                }
+            }
+            match outcome.result {
+                Ok(_) if plan.options.dry_run => {
+                    resolved += 1;
+                    self.log.info(&format!("{name}: would keep {kept} ({why})"));
+                }
+                Ok(hash) => {
+                    resolved += 1;
+                    self.log.info(&format!("{name}: kept {kept} ({why})"));
+                    if let Some(h) = self.history.as_mut() {
+                        let logged = kept_destination(g, p.chosen, &plan.options).and_then(|k| {
+                            h.record(
+                                &self.root,
+                                g,
+                                p.chosen,
+                                &k,
+                                hash.as_deref(),
+                                SystemTime::now(),
+                            )
+                        });
+                        if let Err(e) = logged {
+                            self.log.warn(&format!("history: {e:#}"));
+                        }
+                    }
+                }
+                Err(e) => {
+                    failed += 1;
+                    self.log.error(&format!("{name}: {e:#}"));
+                }
+            }
+        }
+        if hooked {
+            if let Err(e) = self
+                .config
+                .hooks
+                .run(HookEvent::PostApply, &self.root, &applied)
+            {
+                self.log.warn(&format!("{e:#}"));
+            }
+        }
+        for p in review.difference(&self.announced) {
+            let name = rel_path(&self.root, p).display();
+            let open = goto_command(&self.root, p);
+            self.log
+                .info(&format!("{name}: needs review, open it with {open}"));
+        }
+        self.announced = review;
+        // A resolved group that comes back later starts over at a notice.
+        self.alerted.retain(|p, _| since.contains_key(p));
+        self.review_since = since;
+        if resolved > 0 || left > 0 || failed > 0 {
+            let failures = if failed > 0 {
+                format!(", {failed} failed")
+            } else {
+                String::new()
+            };
+            let line = format!(
+                "{resolved} groups {}{failures}, {left} left for review",
+                if plan.options.dry_run {
+                    "would be resolved"
+                } else {
+                    "resolved"
+                }
+            );
+            if failed > 0 {
+                self.log.warn(&line);
+            } else {
+                self.log.info(&line);
+            }
+        }
+        Ok(PassSummary { resolved, failed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// Stands in for Syncthing's REST API with one folder, `docs`, at `root`. Each request
+    /// is recorded as `METHOD target body`, with a JSON body compacted.
+    fn stub_syncthing(root: &Path) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let folders = serde_json::json!([{ "id": "docs", "path": root }]).to_string();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    return;
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    match header.trim().split_once(':') {
+                        Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                            length = value.trim().parse().unwrap();
+                        }
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let mut parts = request.split_whitespace();
+                let (method, target) = (parts.next().unwrap(), parts.next().unwrap());
+                let body = serde_json::from_slice::<serde_json::Value>(&body)
+                    .map(|v| v.to_string())
+                    .unwrap_or_default();
+                let line = format!("{method} {target} {body}");
+                log.lock().unwrap().push(line.trim_end().to_string());
+                let reply = if method == "GET" { &folders } else { "{}" };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{reply}",
+                    reply.len()
+                );
+            }
+        });
+        (url, seen)
+    }
+
+    #[test]
+    fn applies_pause_syncthing_and_resume_and_rescan_after() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("r");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("same.txt"), "x").unwrap();
+        fs::write(root.join("same.txt.sync-conflict-20240101-000000-DEV"), "x").unwrap();
+        let (url, seen) = stub_syncthing(&root.canonicalize().unwrap());
+        let config_path = td.path().join("config.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "history_db = {:?}\nhash_cache = {:?}\n[syncthing]\nurl = {url:?}\napi_key = \"k\"\n",
+                td.path().join("h.sqlite3"),
+                td.path().join("c.sqlite3"),
+            ),
+        )
+        .unwrap();
+        let mut args = DaemonArgs {
+            path: root.clone(),
+            apply: false,
+            strategy: None,
+            deleted_original: None,
+            include_hidden: true,
+            watch_syncthing: false,
+            once: true,
+            settle: Duration::from_secs(10),
+            log_file: Some(td.path().join("daemon.log")),
+            audit_log: None,
+            config: Some(config_path),
+        };
+
+        // A dry run leaves Syncthing alone.
+        run(&args).unwrap();
+        assert!(seen.lock().unwrap().is_empty());
+
+        args.apply = true;
+        run(&args).unwrap();
+        assert!(!root
+            .join("same.txt.sync-conflict-20240101-000000-DEV")
+            .exists());
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                "GET /rest/config/folders",
+                r#"PATCH /rest/config/folders/docs {"paused":true}"#,
+                r#"PATCH /rest/config/folders/docs {"paused":false}"#,
+                "POST /rest/db/scan?folder=docs",
+            ]
+        );
+    }
+
+    #[test]
+    fn passes_resolve_identical_and_rule_matched_groups_and_log_them() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("r");
+        fs::create_dir(&root).unwrap();
+        let write = |name: &str, content: &str| fs::write(root.join(name), content).unwrap();
+        write("same.txt", "x");
+        write("same.txt.sync-conflict-20240101-000000-DEV", "x");
+        write("notes.md", "mine");
+        write("notes.md.sync-conflict-20240101-000000-DEV", "theirs");
+        write("todo.kdbx", "a");
+        write("todo.kdbx.sync-conflict-20240101-000000-DEV", "b");
+        let config_path = td.path().join("config.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "history_db = {:?}\nhash_cache = {:?}\n\
+                 [[pick_rules]]\nglob = \"*.md\"\nstrategy = \"current\"\n",
+                td.path().join("h.sqlite3"),
+                td.path().join("c.sqlite3"),
+            ),
+        )
+        .unwrap();
+        let log_path = td.path().join("logs/daemon.log");
+        let mut args = DaemonArgs {
+            path: root.clone(),
+            apply: false,
+            strategy: None,
+            deleted_original: None,
+            include_hidden: true,
+            watch_syncthing: false,
+            once: true,
+            settle: Duration::from_secs(10),
+            log_file: Some(log_path.clone()),
+            audit_log: None,
+            config: Some(config_path),
+        };
+
+        run(&args).unwrap();
+        let log = fs::read_to_string(&log_path).unwrap();
+        assert!(log.contains("(dry-run)"), "{log}");
+        assert!(log.contains("INFO  same.txt: would keep same.txt (identical versions)"));
+        assert!(log.contains("notes.md: would keep notes.md (pick_rules[1]: current)"));
+        assert!(log.contains("2 groups would be resolved, 1 left for review"));
+        let open = goto_command(&root.canonicalize().unwrap(), Path::new("todo.kdbx"));
+        assert!(log.contains(&format!("todo.kdbx: needs review, open it with {open}")));
+        assert!(open.ends_with(" --goto todo.kdbx"), "{open}");
+        assert!(root
+            .join("notes.md.sync-conflict-20240101-000000-DEV")
+            .exists());
+
+        args.apply = true;
+        run(&args).unwrap();
+        let log = fs::read_to_string(&log_path).unwrap();
+        assert!(
+            log.contains("same.txt: kept same.txt (identical versions)"),
+            "{log}"
+        );
+        assert!(!root
+            .join("same.txt.sync-conflict-20240101-000000-DEV")
+            .exists());
+        assert!(!root
+            .join("notes.md.sync-conflict-20240101-000000-DEV")
+            .exists());
+        assert!(root
+            .join("todo.kdbx.sync-conflict-20240101-000000-DEV")
+            .exists());
+        let history = History::open(&td.path().join("h.sqlite3")).unwrap();
+        assert_eq!(history.rule_stats().unwrap().len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn once_fails_when_a_group_fails_to_apply() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("r");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("real.txt"), "x").unwrap();
+        fs::write(root.join("ok.txt"), "y").unwrap();
+        fs::write(root.join("ok.txt.sync-conflict-20240101-000000-DEV"), "y").unwrap();
+        // Identical, but the original is a symlink, which apply refuses to replace.
+        std::os::unix::fs::symlink(root.join("real.txt"), root.join("same.txt")).unwrap();
+        fs::write(root.join("same.txt.sync-conflict-20240101-000000-DEV"), "x").unwrap();
+        let config_path = td.path().join("config.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "history_db = {:?}\nhash_cache = {:?}\n",
+                td.path().join("h.sqlite3"),
+                td.path().join("c.sqlite3"),
+            ),
+        )
+        .unwrap();
+        let log_path = td.path().join("daemon.log");
+        let args = DaemonArgs {
+            path: root.clone(),
+            apply: true,
+            strategy: None,
+            deleted_original: None,
+            include_hidden: true,
+            watch_syncthing: false,
+            once: true,
+            settle: Duration::from_secs(10),
+            log_file: Some(log_path.clone()),
+            audit_log: None,
+            config: Some(config_path),
+        };
+
+        let err = run(&args).unwrap_err();
+        assert_eq!(err.to_string(), "1 of 2 groups could not be resolved");
+        let log = fs::read_to_string(&log_path).unwrap();
+        assert!(log.contains("ERROR same.txt: cannot modify"), "{log}");
+        assert!(log.contains("WARN  1 groups resolved, 1 failed, 0 left for review"));
+        assert!(!root
+            .join("ok.txt.sync-conflict-20240101-000000-DEV")
+            .exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn low_disk_space_skips_the_batch_and_alerts_once() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("r");
+        fs::create_dir(&root).unwrap();
+        for name in ["a.txt", "b.txt"] {
+            fs::write(root.join(name), "x").unwrap();
+            fs::write(
+                root.join(format!("{name}.sync-conflict-20240101-000000-DEV")),
+                "x",
+            )
+            .unwrap();
+        }
+        let sent = td.path().join("sent.txt");
+        let config_path = td.path().join("config.toml");
+        // No disk has this much free, as `applies_are_refused_below_min_free_space` fakes it.
+        fs::write(
+            &config_path,
+            format!(
+                "history_db = {:?}\nhash_cache = {:?}\nmin_free_space = \"1000000 TiB\"\n\
+                 [alerts]\ncommand = \"printf '%s ' \\\"$SYNCTUI_ALERT_LEVEL\\\" >> {}\"\n",
+                td.path().join("h.sqlite3"),
+                td.path().join("c.sqlite3"),
+                sent.display(),
+            ),
+        )
+        .unwrap();
+        let log_path = td.path().join("daemon.log");
+        let args = DaemonArgs {
+            path: root.clone(),
+            apply: true,
+            strategy: None,
+            deleted_original: None,
+            include_hidden: true,
+            watch_syncthing: false,
+            once: true,
+            settle: Duration::from_secs(10),
+            log_file: Some(log_path.clone()),
+            audit_log: None,
+            config: Some(config_path.clone()),
+        };
+
+        let err = run(&args).unwrap_err();
+        assert_eq!(err.to_string(), "2 of 2 groups could not be resolved");
+        let log = fs::read_to_string(&log_path).unwrap();
+        assert!(
+            log.contains("WARN  automatic applies refused, 2 groups skipped: only "),
+            "{log}"
+        );
+        assert!(log.contains("below min_free_space"));
+        // Skipped as a batch, not tried group by group.
+        assert!(!log.contains("ERROR a.txt"), "{log}");
+        assert!(root
+            .join("a.txt.sync-conflict-20240101-000000-DEV")
+            .exists());
+        assert_eq!(fs::read_to_string(&sent).unwrap(), "warning ");
+
+        // A watching daemon alerts once, not on every pass while the disk stays full.
+        let config = Config::load(Some(&config_path)).unwrap();
+        let mut daemon = Daemon {
+            root: root.canonicalize().unwrap(),
+            args,
+            config,
+            history: None,
+            hash_cache: None,
+            client: None,
+            folders: Vec::new(),
+            unscanned: Vec::new(),
+            announced: BTreeSet::new(),
+            review_since: BTreeMap::new(),
+            alerted: BTreeMap::new(),
+            low_space: false,
+            log: Log::open(Some(&log_path)).unwrap(),
+        };
+        for _ in 0..2 {
+            assert_eq!(daemon.pass().unwrap().failed, 2);
+        }
+        assert_eq!(fs::read_to_string(&sent).unwrap(), "warning warning ");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn old_conflicts_left_for_review_are_alerted_about() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("r");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("todo.kdbx"), "a").unwrap();
+        fs::write(
+            root.join("todo.kdbx.sync-conflict-20240101-000000-DEV"),
+            "b",
+        )
+        .unwrap();
+        let sent = td.path().join("sent.txt");
+        let config_path = td.path().join("config.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "history_db = {:?}\nhash_cache = {:?}\n\
+                 [alerts]\nnotice = \"1d\"\ncommand = \"printf '%s' \\\"$SYNCTUI_ALERT_LEVEL\\\" > {}\"\n",
+                td.path().join("h.sqlite3"),
+                td.path().join("c.sqlite3"),
+                sent.display(),
+            ),
+        )
+        .unwrap();
+        let log_path = td.path().join("daemon.log");
+        let args = DaemonArgs {
+            path: root.clone(),
+            apply: false,
+            strategy: None,
+            deleted_original: None,
+            include_hidden: true,
+            watch_syncthing: false,
+            once: true,
+            settle: Duration::from_secs(10),
+            log_file: Some(log_path.clone()),
+            audit_log: None,
+            config: Some(config_path),
+        };
+
+        run(&args).unwrap();
+        let log = fs::read_to_string(&log_path).unwrap();
+        assert!(log.contains("WARN  todo.kdbx: unresolved for "), "{log}");
+        assert_eq!(fs::read_to_string(&sent).unwrap(), "warning");
+    }
+}
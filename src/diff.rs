@@ -0,0 +1,428 @@
+use crate::config::de_size;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use similar::{
+    capture_diff_slices_deadline, group_diff_ops, Algorithm, ChangeTag, DiffTag, TextDiff,
+};
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Default for `[diff] max_size`: larger files are never read or diffed automatically.
+pub const MAX_DIFF_BYTES: u64 = 4 * 1024 * 1024;
+/// Default for `[diff] memory_cap`: the most the two files of an on-demand diff may
+/// add up to.
+pub const DIFF_MEMORY_CAP: u64 = 512 * 1024 * 1024;
+/// Past this, line matching settles for a coarser (still correct) diff.
+pub const DIFF_DEADLINE: Duration = Duration::from_secs(30);
+/// An on-demand diff shows at most this many lines.
+pub const MAX_DIFF_LINES: usize = 20_000;
+
+/// Contents of `path` if it looks like text: valid UTF-8, no NUL bytes, and no larger
+/// than `max` bytes.
+pub fn read_text(path: &Path, max: u64) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > max {
+        return None;
+    }
+    let text = String::from_utf8(fs::read(path).ok()?).ok()?;
+    (!text.contains('\0')).then_some(text)
+}
+
+/// Lines added and removed going from `old` to `new`; `None` unless both are text of at
+/// most `max` bytes.
+pub fn diffstat(old: &Path, new: &Path, max: u64) -> Option<(usize, usize)> {
+    let (old, new) = (read_text(old, max)?, read_text(new, max)?);
+    let diff = TextDiff::from_lines(&old, &new);
+    let mut added = 0;
+    let mut removed = 0;
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => added += 1,
+            ChangeTag::Delete => removed += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    Some((added, removed))
+}
+
+/// Line-matching algorithm of the built-in diff. Patience anchors on lines that occur
+/// once in each file, which keeps reflowed or reordered text readable.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffAlgorithm {
+    #[default]
+    Myers,
+    Patience,
+}
+
+impl DiffAlgorithm {
+    pub fn label(self) -> &'static str {
+        match self {
+            DiffAlgorithm::Myers => "myers",
+            DiffAlgorithm::Patience => "patience",
+        }
+    }
+
+    /// The other algorithm, for the runtime toggle.
+    pub fn toggled(self) -> Self {
+        match self {
+            DiffAlgorithm::Myers => DiffAlgorithm::Patience,
+            DiffAlgorithm::Patience => DiffAlgorithm::Myers,
+        }
+    }
+}
+
+/// `[diff]`: how the diff pane compares two text files.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiffOptions {
+    pub algorithm: DiffAlgorithm,
+    /// Unchanged lines shown around each change.
+    pub context: usize,
+    /// Compare lines with all whitespace removed, like `diff -w`.
+    pub ignore_whitespace: bool,
+    /// Files above this are only diffed on request, on a worker thread.
+    #[serde(deserialize_with = "de_size")]
+    pub max_size: u64,
+    /// On-demand diffs are refused when the two files add up to more than this.
+    #[serde(deserialize_with = "de_size")]
+    pub memory_cap: u64,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: DiffAlgorithm::Myers,
+            context: 3,
+            ignore_whitespace: false,
+            max_size: MAX_DIFF_BYTES,
+            memory_cap: DIFF_MEMORY_CAP,
+        }
+    }
+}
+
+impl DiffOptions {
+    /// `patience, 5 context lines, ignoring whitespace`.
+    pub fn describe(&self) -> String {
+        let mut s = format!("{}, {} context lines", self.algorithm.label(), self.context);
+        if self.ignore_whitespace {
+            s.push_str(", ignoring whitespace");
+        }
+        s
+    }
+}
+
+/// `diff -u` style output going from `old` to `new`, labelled with the two names;
+/// `None` when they don't differ (under `options`). Context lines are taken from `new`.
+pub fn unified_diff(
+    old: &str,
+    new: &str,
+    names: (&str, &str),
+    options: &DiffOptions,
+) -> Option<String> {
+    let (old_lines, new_lines): (Vec<&str>, Vec<&str>) =
+        (old.lines().collect(), new.lines().collect());
+    fn key(line: &str, ignore_whitespace: bool) -> Cow<'_, str> {
+        if ignore_whitespace {
+            Cow::Owned(line.chars().filter(|c| !c.is_whitespace()).collect())
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+    let ws = options.ignore_whitespace;
+    let (old_keys, new_keys): (Vec<Cow<str>>, Vec<Cow<str>>) = (
+        old_lines.iter().map(|l| key(l, ws)).collect(),
+        new_lines.iter().map(|l| key(l, ws)).collect(),
+    );
+    let algorithm = match options.algorithm {
+        DiffAlgorithm::Myers => Algorithm::Myers,
+        DiffAlgorithm::Patience => Algorithm::Patience,
+    };
+    let deadline = Some(Instant::now() + DIFF_DEADLINE);
+    let ops = capture_diff_slices_deadline(algorithm, &old_keys, &new_keys, deadline);
+    let hunks = group_diff_ops(ops, options.context);
+    if hunks.is_empty() {
+        return None;
+    }
+
+    // `start,len` with 1-based starts; an empty range names the line before it.
+    let range = |r: std::ops::Range<usize>| match r.len() {
+        0 => format!("{},0", r.start),
+        1 => format!("{}", r.start + 1),
+        n => format!("{},{n}", r.start + 1),
+    };
+    let mut out = format!("--- {}\n+++ {}\n", names.0, names.1);
+    for hunk in hunks {
+        let (first, last) = (hunk[0].as_tag_tuple(), hunk[hunk.len() - 1].as_tag_tuple());
+        let old_range = first.1.start..last.1.end;
+        let new_range = first.2.start..last.2.end;
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_range),
+            range(new_range)
+        ));
+        for op in hunk {
+            let (tag, old_r, new_r) = op.as_tag_tuple();
+            match tag {
+                DiffTag::Equal => new_r.for_each(|i| out.push_str(&format!(" {}\n", new_lines[i]))),
+                DiffTag::Delete => {
+                    old_r.for_each(|i| out.push_str(&format!("-{}\n", old_lines[i])))
+                }
+                DiffTag::Insert => {
+                    new_r.for_each(|i| out.push_str(&format!("+{}\n", new_lines[i])))
+                }
+                DiffTag::Replace => {
+                    old_r.for_each(|i| out.push_str(&format!("-{}\n", old_lines[i])));
+                    new_r.for_each(|i| out.push_str(&format!("+{}\n", new_lines[i])));
+                }
+            }
+        }
+    }
+    Some(out)
+}
+
+/// A diffstat result keyed by the `(old, new)` pair it was computed for.
+pub type DiffstatResult = ((PathBuf, PathBuf), Option<(usize, usize)>);
+
+/// Runs [`diffstat`] for each `(old, new)` pair on a background thread, sending each
+/// result as it finishes. The thread stops once the receiver is dropped.
+pub fn spawn_diffstats(pairs: Vec<(PathBuf, PathBuf)>, max: u64) -> Receiver<DiffstatResult> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for (old, new) in pairs {
+            let stat = diffstat(&old, &new, max);
+            if tx.send(((old, new), stat)).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+/// What an on-demand diff worker reports.
+#[derive(Debug)]
+pub enum DiffEvent {
+    /// Bytes of the two files read so far, of `total`.
+    Read { done: u64, total: u64 },
+    /// Both files are in memory; matching lines.
+    Comparing,
+    /// The unified diff (`None` if the files don't differ), or why there is none.
+    Done(Result<Option<String>, String>),
+}
+
+/// Reads `path` in chunks, calling `progress` with the bytes read so far, and checks it
+/// is text. Stops early once `cancel` is set.
+fn read_text_chunked(
+    path: &Path,
+    cancel: &AtomicBool,
+    mut progress: impl FnMut(u64),
+) -> Result<String> {
+    let mut file = File::open(path).with_context(|| format!("open {path:?}"))?;
+    let mut bytes = Vec::with_capacity(file.metadata().map_or(0, |m| m.len()) as usize);
+    let mut chunk = vec![0; 4 * 1024 * 1024];
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            bail!("cancelled");
+        }
+        let n = file
+            .read(&mut chunk)
+            .with_context(|| format!("read {path:?}"))?;
+        if n == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..n]);
+        progress(n as u64);
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) if !text.contains('\0') => Ok(text),
+        _ => bail!("{} is not a text file", path.display()),
+    }
+}
+
+/// Diffs `old` against `new` on a worker thread, whatever their size: progress while
+/// reading, then the result, cut to [`MAX_DIFF_LINES`] lines. Setting the returned flag
+/// stops the reading; the thread also stops once the receiver is dropped.
+pub fn spawn_unified_diff(
+    old: PathBuf,
+    new: PathBuf,
+    names: (String, String),
+    options: DiffOptions,
+) -> (Receiver<DiffEvent>, Arc<AtomicBool>) {
+    let (tx, rx) = mpsc::channel();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let stop = cancel.clone();
+    thread::spawn(move || {
+        let total: u64 = [&old, &new]
+            .iter()
+            .map(|p| fs::metadata(p).map_or(0, |m| m.len()))
+            .sum();
+        let mut done = 0;
+        let mut read = |path: &Path| {
+            read_text_chunked(path, &stop, |n| {
+                done += n;
+                let _ = tx.send(DiffEvent::Read { done, total });
+            })
+        };
+        let texts = read(&old).and_then(|o| Ok((o, read(&new)?)));
+        let result = texts.map_err(|e| format!("{e:#}")).map(|(o, n)| {
+            let _ = tx.send(DiffEvent::Comparing);
+            unified_diff(&o, &n, (&names.0, &names.1), &options)
+                .map(|d| truncate_lines(d, MAX_DIFF_LINES))
+        });
+        let _ = tx.send(DiffEvent::Done(result));
+    });
+    (rx, cancel)
+}
+
+/// `diff` cut to its first `max` lines, with a note saying so.
+fn truncate_lines(diff: String, max: usize) -> String {
+    match diff.match_indices('\n').nth(max - 1) {
+        Some((end, _)) if end + 1 < diff.len() => {
+            let total = diff.lines().count();
+            format!(
+                "{}... {} more lines not shown\n",
+                &diff[..=end],
+                total - max
+            )
+        }
+        _ => diff,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn unified_diffs_follow_the_algorithm_context_and_whitespace_options() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let new = "a\nb\nc\nD\ne\nf\ng\nh\n";
+        let names = ("old", "new");
+        let default = DiffOptions::default();
+        assert_eq!(
+            unified_diff(old, new, names, &default).unwrap(),
+            "--- old\n+++ new\n@@ -1,7 +1,7 @@\n a\n b\n c\n-d\n+D\n e\n f\n g\n"
+        );
+        let tight = DiffOptions {
+            context: 1,
+            ..default
+        };
+        assert_eq!(
+            unified_diff(old, new, names, &tight).unwrap(),
+            "--- old\n+++ new\n@@ -3,3 +3,3 @@\n c\n-d\n+D\n e\n"
+        );
+        assert_eq!(unified_diff(old, old, names, &default), None);
+
+        let spaced = "a\nb\n  c\nd  e\n";
+        assert!(unified_diff("a\nb\nc\nde\n", spaced, names, &default).is_some());
+        let ws = DiffOptions {
+            ignore_whitespace: true,
+            ..default
+        };
+        assert_eq!(unified_diff("a\nb\nc\nde\n", spaced, names, &ws), None);
+
+        // Moving a block: patience keeps the unique lines as anchors.
+        let old = "x\n}\nfn a() {\n1\n}\nfn b() {\n2\n}\n";
+        let new = "x\n}\nfn b() {\n2\n}\nfn a() {\n1\n}\n";
+        let patience = DiffOptions {
+            algorithm: DiffAlgorithm::Patience,
+            context: 0,
+            ..default
+        };
+        let myers = DiffOptions {
+            context: 0,
+            ..default
+        };
+        let p = unified_diff(old, new, names, &patience).unwrap();
+        let m = unified_diff(old, new, names, &myers).unwrap();
+        assert_ne!(p, m);
+        assert_eq!(default.describe(), "myers, 3 context lines");
+        assert_eq!(ws.describe(), "myers, 3 context lines, ignoring whitespace");
+    }
+
+    #[test]
+    fn on_demand_diffs_report_progress() {
+        let td = tempdir().unwrap();
+        let (a, b, bin) = (
+            td.path().join("a"),
+            td.path().join("b"),
+            td.path().join("bin"),
+        );
+        fs::write(&a, "one\ntwo\n").unwrap();
+        fs::write(&b, "one\n2\n").unwrap();
+        fs::write(&bin, b"\0").unwrap();
+        let names = ("a".to_string(), "b".to_string());
+        let spawn = |new: &Path| {
+            spawn_unified_diff(
+                a.clone(),
+                new.to_path_buf(),
+                names.clone(),
+                DiffOptions::default(),
+            )
+        };
+
+        let events: Vec<DiffEvent> = spawn(&b).0.iter().collect();
+        assert!(matches!(events[0], DiffEvent::Read { done: 8, total: 14 }));
+        assert!(matches!(
+            events[1],
+            DiffEvent::Read {
+                done: 14,
+                total: 14
+            }
+        ));
+        let Some(DiffEvent::Done(Ok(Some(diff)))) = events.last() else {
+            panic!("{:?}", events.last());
+        };
+        assert!(diff.ends_with("-two\n+2\n"), "{diff}");
+
+        let Some(DiffEvent::Done(Err(e))) = spawn(&bin).0.iter().last() else {
+            panic!("binary file diffed");
+        };
+        assert!(e.ends_with("is not a text file"), "{e}");
+
+        let (rx, cancel) = spawn(&a);
+        cancel.store(true, Ordering::Relaxed);
+        assert!(matches!(rx.iter().last(), Some(DiffEvent::Done(_))));
+    }
+
+    #[test]
+    fn truncate_lines_notes_what_was_cut() {
+        assert_eq!(truncate_lines("a\nb\nc\n".to_string(), 3), "a\nb\nc\n");
+        assert_eq!(
+            truncate_lines("a\nb\nc\n".to_string(), 1),
+            "a\n... 2 more lines not shown\n"
+        );
+    }
+
+    #[test]
+    fn diffstat_counts_lines_and_skips_binary() {
+        let td = tempdir().unwrap();
+        let a = td.path().join("a");
+        let b = td.path().join("b");
+        let bin = td.path().join("bin");
+        fs::write(&a, "one\ntwo\nthree\n").unwrap();
+        fs::write(&b, "one\n2\nthree\nfour\n").unwrap();
+        fs::write(&bin, b"one\0two").unwrap();
+
+        assert_eq!(diffstat(&a, &b, MAX_DIFF_BYTES), Some((2, 1)));
+        assert_eq!(diffstat(&a, &a, MAX_DIFF_BYTES), Some((0, 0)));
+        assert_eq!(diffstat(&a, &bin, MAX_DIFF_BYTES), None);
+        // Too big to diff automatically.
+        assert_eq!(diffstat(&a, &b, 10), None);
+
+        let results: Vec<_> = spawn_diffstats(
+            vec![(a.clone(), b.clone()), (a.clone(), bin.clone())],
+            MAX_DIFF_BYTES,
+        )
+        .iter()
+        .collect();
+        assert_eq!(results, [((a.clone(), b), Some((2, 1))), ((a, bin), None)]);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::recency::local_time;
+use chrono::{DateTime, NaiveDateTime};
+use flate2::read::DeflateDecoder;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Largest metadata entry read out of an Office or OpenDocument file.
+const MAX_META_BYTES: u64 = 1024 * 1024;
+
+/// What an Office Open XML (`.docx`, `.xlsx`, `.pptx`) or OpenDocument (`.odt`, `.ods`,
+/// `.odp`) file records about its last save.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DocProps {
+    /// Who saved it last (`cp:lastModifiedBy`; OpenDocument's `dc:creator`).
+    pub modified_by: Option<String>,
+    /// How many times it was saved (`cp:revision`; `meta:editing-cycles`).
+    pub revision: Option<String>,
+    pub modified: Option<SystemTime>,
+}
+
+impl DocProps {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The properties of the document at `path`; `None` for other files and documents that
+/// record none of them.
+pub fn doc_props(path: &Path) -> Option<DocProps> {
+    let mut file = File::open(path).ok()?;
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic).ok()?;
+    if &magic != b"PK\x03\x04" {
+        return None;
+    }
+    let props = if let Some(core) = zip_entry(&mut file, "docProps/core.xml") {
+        let xml = String::from_utf8_lossy(&core);
+        DocProps {
+            modified_by: xml_text(&xml, "cp:lastModifiedBy"),
+            revision: xml_text(&xml, "cp:revision"),
+            modified: xml_text(&xml, "dcterms:modified").and_then(|s| iso_date(&s)),
+        }
+    } else {
+        let meta = zip_entry(&mut file, "meta.xml")?;
+        let xml = String::from_utf8_lossy(&meta);
+        DocProps {
+            modified_by: xml_text(&xml, "dc:creator"),
+            revision: xml_text(&xml, "meta:editing-cycles"),
+            modified: xml_text(&xml, "dc:date").and_then(|s| iso_date(&s)),
+        }
+    };
+    (!props.is_empty()).then_some(props)
+}
+
+/// The text of the first `<tag ...>text</tag>` in `xml`, unescaped; `None` when missing
+/// or empty.
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{tag}");
+    let at = xml
+        .match_indices(&open)
+        .map(|(i, _)| i + open.len())
+        .find(|&i| {
+            xml[i..]
+                .chars()
+                .next()
+                .is_some_and(|c| c == '>' || c.is_whitespace())
+        })?;
+    let rest = &xml[at..];
+    let text = &rest[rest.find('>')? + 1..rest.find(&format!("</{tag}>"))?];
+    let text = text
+        .trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    (!text.is_empty()).then_some(text)
+}
+
+/// RFC 3339, or without a zone (OpenDocument writes local time that way).
+pub fn iso_date(s: &str) -> Option<SystemTime> {
+    DateTime::parse_from_rfc3339(s)
+        .ok()
+        .map(SystemTime::from)
+        .or_else(|| local_time(NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f").ok()?))
+}
+
+/// The contents of the stored or deflated entry `name`, found through the zip's central
+/// directory.
+fn zip_entry(file: &mut File, name: &str) -> Option<Vec<u8>> {
+    let len = file.metadata().ok()?.len();
+    // The end of central directory record is the last 22 bytes, plus a comment of up to
+    // 64 KiB.
+    let tail_len = len.min(22 + 0xFFFF);
+    file.seek(SeekFrom::Start(len - tail_len)).ok()?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).ok()?;
+    let eocd = tail.windows(4).rposition(|w| w == b"PK\x05\x06")?;
+    let le16 = |b: &[u8], at: usize| Some(u16::from_le_bytes(b.get(at..at + 2)?.try_into().ok()?));
+    let le32 = |b: &[u8], at: usize| Some(u32::from_le_bytes(b.get(at..at + 4)?.try_into().ok()?));
+    let entries = le16(&tail, eocd + 10)?;
+    let dir_len = le32(&tail, eocd + 12)? as u64;
+    let dir_at = le32(&tail, eocd + 16)? as u64;
+
+    let mut dir = Vec::new();
+    file.seek(SeekFrom::Start(dir_at)).ok()?;
+    file.by_ref().take(dir_len).read_to_end(&mut dir).ok()?;
+    let mut at = 0;
+    for _ in 0..entries {
+        if dir.get(at..at + 4)? != b"PK\x01\x02" {
+            return None;
+        }
+        let method = le16(&dir, at + 10)?;
+        let compressed = le32(&dir, at + 20)? as u64;
+        let name_len = le16(&dir, at + 28)? as usize;
+        let extra_len = le16(&dir, at + 30)? as usize;
+        let comment_len = le16(&dir, at + 32)? as usize;
+        let local_at = le32(&dir, at + 42)? as u64;
+        if dir.get(at + 46..at + 46 + name_len)? == name.as_bytes() {
+            let mut local = [0; 30];
+            file.seek(SeekFrom::Start(local_at)).ok()?;
+            file.read_exact(&mut local).ok()?;
+            let skip = le16(&local, 26)? as i64 + le16(&local, 28)? as i64;
+            file.seek(SeekFrom::Current(skip)).ok()?;
+            let data = file.by_ref().take(compressed.min(MAX_META_BYTES));
+            let mut out = Vec::new();
+            match method {
+                0 => data.take(MAX_META_BYTES).read_to_end(&mut out).ok()?,
+                8 => DeflateDecoder::new(data)
+                    .take(MAX_META_BYTES)
+                    .read_to_end(&mut out)
+                    .ok()?,
+                _ => return None,
+            };
+            return Some(out);
+        }
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use std::fs;
+    use std::time::Duration;
+
+    /// A zip holding `name`, deflated, after an unrelated stored entry.
+    fn zip(name: &str, contents: &str) -> Vec<u8> {
+        use flate2::write::DeflateEncoder;
+        use std::io::Write;
+        let mut enc = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(contents.as_bytes()).unwrap();
+        let deflated = enc.finish().unwrap();
+        let entries = [
+            ("mimetype", 0u16, b"application/x".to_vec()),
+            (name, 8, deflated),
+        ];
+        let (mut out, mut dir) = (Vec::new(), Vec::new());
+        for (name, method, data) in &entries {
+            let at = out.len() as u32;
+            let header = |sig: &[u8], central: bool, buf: &mut Vec<u8>| {
+                buf.extend(sig);
+                if central {
+                    buf.extend([20, 0]);
+                }
+                buf.extend([20, 0, 0, 0]);
+                buf.extend(method.to_le_bytes());
+                buf.extend([0; 8]);
+                buf.extend((data.len() as u32).to_le_bytes());
+                buf.extend([0; 4]);
+                buf.extend((name.len() as u16).to_le_bytes());
+                buf.extend([0, 0]);
+                if central {
+                    buf.extend([0; 10]);
+                    buf.extend(at.to_le_bytes());
+                }
+                buf.extend(name.as_bytes());
+            };
+            header(b"PK\x03\x04", false, &mut out);
+            out.extend(data);
+            header(b"PK\x01\x02", true, &mut dir);
+        }
+        let dir_at = out.len() as u32;
+        out.extend(&dir);
+        out.extend(b"PK\x05\x06\0\0\0\0\x02\0\x02\0");
+        out.extend((dir.len() as u32).to_le_bytes());
+        out.extend(dir_at.to_le_bytes());
+        out.extend([0, 0]);
+        out
+    }
+
+    #[test]
+    fn office_and_opendocument_properties_are_read() {
+        let td = tempfile::tempdir().unwrap();
+        let write = |name: &str, data: &[u8]| {
+            let p = td.path().join(name);
+            fs::write(&p, data).unwrap();
+            p
+        };
+        let docx = write(
+            "a.docx",
+            &zip(
+                "docProps/core.xml",
+                "<cp:coreProperties><cp:lastModifiedBy>Ann &amp; Bo</cp:lastModifiedBy>\
+                 <cp:revision>14</cp:revision><cp:revisionNote/>\
+                 <dcterms:modified xsi:type=\"dcterms:W3CDTF\">2023-07-08T09:00:00Z\
+                 </dcterms:modified></cp:coreProperties>",
+            ),
+        );
+        let at = Utc.with_ymd_and_hms(2023, 7, 8, 9, 0, 0).unwrap();
+        assert_eq!(
+            doc_props(&docx),
+            Some(DocProps {
+                modified_by: Some("Ann & Bo".to_string()),
+                revision: Some("14".to_string()),
+                modified: Some(SystemTime::from(at)),
+            })
+        );
+
+        let odt = write(
+            "a.odt",
+            &zip(
+                "meta.xml",
+                "<office:meta><meta:editing-cycles>3</meta:editing-cycles>\
+                 <dc:date>2024-01-02T03:00:00.5</dc:date>",
+            ),
+        );
+        let naive = NaiveDate::from_ymd_opt(2024, 1, 2)
+            .unwrap()
+            .and_hms_opt(3, 0, 0)
+            .unwrap();
+        assert_eq!(
+            doc_props(&odt),
+            Some(DocProps {
+                modified_by: None,
+                revision: Some("3".to_string()),
+                modified: Some(local_time(naive).unwrap() + Duration::from_millis(500)),
+            })
+        );
+
+        assert_eq!(doc_props(&write("a.txt", b"plain")), None);
+        assert_eq!(
+            doc_props(&write("b.docx", &zip("word/document.xml", "<w/>"))),
+            None
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use crate::model::ConflictGroup;
+use crate::thumbnail::{head, is_binary};
+use anyhow::{anyhow, Error};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Bytes read to recognize a file's type.
+const HEAD_BYTES: usize = 512;
+
+/// Broad type of a conflicted file, for filtering and sorting groups by how they're
+/// usually resolved: photos by eye, documents in their app, code with a diff.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FileKind {
+    Image,
+    Video,
+    Audio,
+    /// PDFs, office and e-book files.
+    Document,
+    Code,
+    /// Plain text other than code: notes, markdown, CSV, logs.
+    Text,
+    Archive,
+    /// Anything else that isn't text, e.g. executables and databases.
+    Binary,
+}
+
+const ALL: [FileKind; 8] = [
+    FileKind::Image,
+    FileKind::Video,
+    FileKind::Audio,
+    FileKind::Document,
+    FileKind::Code,
+    FileKind::Text,
+    FileKind::Archive,
+    FileKind::Binary,
+];
+
+const EXTENSIONS: &[(FileKind, &[&str])] = &[
+    (
+        FileKind::Image,
+        &[
+            "jpg", "jpeg", "png", "gif", "webp", "heic", "heif", "avif", "tif", "tiff", "bmp",
+            "svg", "raw", "dng", "cr2", "cr3", "nef", "arw", "orf", "rw2", "psd", "xcf",
+        ],
+    ),
+    (
+        FileKind::Video,
+        &[
+            "mp4", "mov", "m4v", "mkv", "webm", "avi", "mts", "m2ts", "3gp",
+        ],
+    ),
+    (
+        FileKind::Audio,
+        &[
+            "mp3", "flac", "wav", "ogg", "oga", "opus", "m4a", "aac", "aiff",
+        ],
+    ),
+    (
+        FileKind::Document,
+        &[
+            "pdf", "doc", "docx", "odt", "rtf", "xls", "xlsx", "ods", "ppt", "pptx", "odp",
+            "pages", "numbers", "key", "epub", "mobi", "djvu",
+        ],
+    ),
+    (
+        FileKind::Code,
+        &[
+            "rs", "py", "js", "mjs", "ts", "tsx", "jsx", "go", "c", "h", "cc", "cpp", "hpp",
+            "java", "kt", "swift", "rb", "php", "sh", "bash", "zsh", "fish", "ps1", "lua", "pl",
+            "sql", "html", "htm", "css", "scss", "json", "toml", "yaml", "yml", "xml", "ini",
+            "cfg", "conf", "nix", "hs", "ml", "ex", "exs", "cs", "scala", "r", "dart", "vue",
+            "svelte", "el", "vim",
+        ],
+    ),
+    (
+        FileKind::Text,
+        &[
+            "txt", "md", "markdown", "org", "rst", "adoc", "tex", "csv", "tsv", "log", "srt",
+            "vtt", "ics", "vcf",
+        ],
+    ),
+    (
+        FileKind::Archive,
+        &[
+            "zip", "tar", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "iso", "dmg", "jar",
+        ],
+    ),
+];
+
+impl FileKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            FileKind::Image => "image",
+            FileKind::Video => "video",
+            FileKind::Audio => "audio",
+            FileKind::Document => "document",
+            FileKind::Code => "code",
+            FileKind::Text => "text",
+            FileKind::Archive => "archive",
+            FileKind::Binary => "binary",
+        }
+    }
+
+    /// Type of the file at `path` named like `name`: a conflict copy's own name doesn't
+    /// always end in the extension, so the group's base path is passed for it. Magic
+    /// bytes win over the extension, except that a ZIP named like an office document is
+    /// one; files that can't be read go by their extension alone.
+    pub fn detect(path: &Path, name: &Path) -> FileKind {
+        let by_ext = by_extension(name);
+        let head = head(path, HEAD_BYTES).unwrap_or_default();
+        match by_magic(&head) {
+            Some(FileKind::Archive) if by_ext == Some(FileKind::Document) => FileKind::Document,
+            Some(kind) => kind,
+            None => by_ext.unwrap_or(if is_binary(&head) {
+                FileKind::Binary
+            } else {
+                FileKind::Text
+            }),
+        }
+    }
+}
+
+impl FromStr for FileKind {
+    type Err = Error;
+
+    /// A label, or its plural (`images`, `documents`).
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let s = s.trim().to_ascii_lowercase();
+        ALL.into_iter()
+            .find(|k| s == k.label() || s.strip_suffix('s') == Some(k.label()))
+            .ok_or_else(|| {
+                let labels: Vec<_> = ALL.iter().map(|k| k.label()).collect();
+                anyhow!("expected one of {}, got {s:?}", labels.join(", "))
+            })
+    }
+}
+
+/// The group's type: its base path's extension and the content of its original, or of
+/// its first conflict when the original is gone.
+pub fn group_kind(g: &ConflictGroup) -> FileKind {
+    let file = g
+        .candidates
+        .iter()
+        .find(|c| c.exists)
+        .map_or(g.base_path.as_path(), |c| c.path.as_path());
+    FileKind::detect(file, &g.base_path)
+}
+
+fn by_extension(name: &Path) -> Option<FileKind> {
+    let ext = name.extension()?.to_str()?.to_ascii_lowercase();
+    EXTENSIONS
+        .iter()
+        .find(|(_, exts)| exts.contains(&ext.as_str()))
+        .map(|(kind, _)| *kind)
+}
+
+fn by_magic(head: &[u8]) -> Option<FileKind> {
+    let at = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+    let kind = if at(0, b"\x89PNG")
+        || at(0, b"\xff\xd8\xff")
+        || at(0, b"GIF8")
+        || at(0, b"II*\0")
+        || at(0, b"MM\0*")
+        || (at(0, b"RIFF") && at(8, b"WEBP"))
+    {
+        FileKind::Image
+    } else if at(4, b"ftyp") {
+        // ISO media: the brand says photo, sound or film.
+        match head.get(8..12) {
+            Some(b"heic" | b"heix" | b"mif1" | b"msf1" | b"avif") => FileKind::Image,
+            Some(b"M4A " | b"M4B ") => FileKind::Audio,
+            _ => FileKind::Video,
+        }
+    } else if at(0, b"\x1a\x45\xdf\xa3") || (at(0, b"RIFF") && at(8, b"AVI ")) {
+        FileKind::Video
+    } else if at(0, b"ID3")
+        || at(0, b"fLaC")
+        || at(0, b"OggS")
+        || (at(0, b"RIFF") && at(8, b"WAVE"))
+    {
+        FileKind::Audio
+    } else if at(0, b"%PDF") || at(0, b"\xd0\xcf\x11\xe0") {
+        FileKind::Document
+    } else if at(0, b"PK\x03\x04")
+        || at(0, b"\x1f\x8b")
+        || at(0, b"BZh")
+        || at(0, b"\xfd7zXZ")
+        || at(0, b"\x28\xb5\x2f\xfd")
+        || at(0, b"7z\xbc\xaf\x27\x1c")
+        || at(0, b"Rar!")
+    {
+        FileKind::Archive
+    } else if at(0, b"\x7fELF")
+        || at(0, b"MZ")
+        || at(0, b"\xcf\xfa\xed\xfe")
+        || at(0, b"SQLite format 3\0")
+    {
+        FileKind::Binary
+    } else {
+        return None;
+    };
+    Some(kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn magic_bytes_win_over_the_extension() {
+        let td = tempdir().unwrap();
+        let kind = |name: &str, content: &[u8]| {
+            let p = td.path().join(name);
+            fs::write(&p, content).unwrap();
+            FileKind::detect(&p, &p)
+        };
+        assert_eq!(
+            kind("photo.txt", b"\xff\xd8\xff\xe0\0\x10JFIF"),
+            FileKind::Image
+        );
+        assert_eq!(kind("IMG_1.HEIC", b"\0\0\0\x18ftypheic"), FileKind::Image);
+        assert_eq!(kind("clip", b"\0\0\0\x18ftypisom"), FileKind::Video);
+        assert_eq!(kind("report.docx", b"PK\x03\x04\x14\0"), FileKind::Document);
+        assert_eq!(kind("backup.zip", b"PK\x03\x04\x14\0"), FileKind::Archive);
+        assert_eq!(kind("paper", b"%PDF-1.7\n"), FileKind::Document);
+        assert_eq!(kind("main.rs", b"fn main() {}\n"), FileKind::Code);
+        assert_eq!(kind("notes", b"buy milk\n"), FileKind::Text);
+        assert_eq!(kind("blob", b"\0\x01\x02\x03"), FileKind::Binary);
+        // Unreadable files go by name.
+        let missing = td.path().join("gone.mp3");
+        assert_eq!(FileKind::detect(&missing, &missing), FileKind::Audio);
+    }
+
+    #[test]
+    fn kinds_parse_from_labels_and_plurals() {
+        assert_eq!("images".parse::<FileKind>().unwrap(), FileKind::Image);
+        assert_eq!("Document".parse::<FileKind>().unwrap(), FileKind::Document);
+        assert_eq!("code".parse::<FileKind>().unwrap(), FileKind::Code);
+        assert!("photos"
+            .parse::<FileKind>()
+            .unwrap_err()
+            .to_string()
+            .contains("expected one of image, video"));
+    }
+}
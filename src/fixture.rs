@@ -0,0 +1,326 @@
+use crate::model::{Candidate, ConflictGroup};
+use crate::scan::is_conflict_name;
+use anyhow::{anyhow, Context, Error, Result};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 2024-01-01 00:00:00 UTC; every generated timestamp counts from here.
+const EPOCH_SECS: u64 = 1_704_067_200;
+const DIRS: [&str; 6] = ["docs", "photos", "notes", "projects", "archive", "music"];
+const WORDS: [&str; 8] = [
+    "report", "todo", "budget", "draft", "index", "config", "readme", "plan",
+];
+const EXTS: [&str; 5] = ["txt", "md", "rs", "json", "toml"];
+
+/// A shape of conflict to include in a fixture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    /// `name.ext.sync-conflict-YYYYMMDD-HHMMSS-DEV`.
+    Suffix,
+    /// `name.sync-conflict-YYYYMMDD-HHMMSS-DEV.ext`, the form Syncthing writes.
+    Infix,
+    /// Some conflicts have the same content as the original.
+    Identical,
+    /// Some originals are missing (deleted while conflicts remain).
+    Deleted,
+}
+
+impl Pattern {
+    pub const ALL: [Pattern; 4] = [
+        Pattern::Suffix,
+        Pattern::Infix,
+        Pattern::Identical,
+        Pattern::Deleted,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Pattern::Suffix => "suffix",
+            Pattern::Infix => "infix",
+            Pattern::Identical => "identical",
+            Pattern::Deleted => "deleted",
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Pattern::ALL
+            .into_iter()
+            .find(|p| p.label() == s)
+            .ok_or_else(|| anyhow!("unknown pattern {s:?} (suffix, infix, identical, deleted)"))
+    }
+}
+
+/// What to generate. The same spec (including `seed`) always yields the same files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FixtureSpec {
+    pub groups: usize,
+    /// Deepest directory nesting below the root (0 puts everything in the root).
+    pub depth: usize,
+    pub devices: Vec<String>,
+    /// Each group gets between 1 and this many conflicts.
+    pub max_conflicts: usize,
+    /// Approximate size of each file in bytes.
+    pub size: u64,
+    pub seed: u64,
+    /// Naming patterns (`Suffix`/`Infix`) are picked per group; `Identical` and `Deleted`
+    /// apply to roughly a quarter of the groups each. Without a naming pattern, `Suffix`.
+    pub patterns: Vec<Pattern>,
+}
+
+impl Default for FixtureSpec {
+    fn default() -> Self {
+        Self {
+            groups: 20,
+            depth: 2,
+            devices: vec!["PHONE12".into(), "LAPTOP1".into(), "TABLET3".into()],
+            max_conflicts: 3,
+            size: 256,
+            seed: 1,
+            patterns: vec![Pattern::Suffix],
+        }
+    }
+}
+
+/// A file of a fixture, relative to its root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FixtureFile {
+    pub path: PathBuf,
+    pub content: String,
+    pub modified: SystemTime,
+}
+
+/// SplitMix64: tiny and deterministic, which is all a fixture needs.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform-ish in `0..n` (`n > 0`).
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+/// `size` bytes (give or take a line) of text identifying the group and version.
+fn body(group: usize, version: &str, size: u64) -> String {
+    let mut out = String::new();
+    let mut line = 0;
+    while (out.len() as u64) < size.max(1) {
+        out.push_str(&format!("group {group} {version} line {line}\n"));
+        line += 1;
+    }
+    out
+}
+
+/// The files `spec` describes, without touching the filesystem.
+pub fn fixture_files(spec: &FixtureSpec) -> Result<Vec<FixtureFile>> {
+    if spec.devices.is_empty() {
+        return Err(anyhow!("a fixture needs at least one device"));
+    }
+    let mut naming: Vec<Pattern> = spec
+        .patterns
+        .iter()
+        .copied()
+        .filter(|p| matches!(p, Pattern::Suffix | Pattern::Infix))
+        .collect();
+    if naming.is_empty() {
+        naming.push(Pattern::Suffix);
+    }
+    let mut rng = Rng(spec.seed);
+    let mut files = Vec::new();
+    for g in 0..spec.groups {
+        let mut dir = PathBuf::new();
+        for _ in 0..rng.below(spec.depth as u64 + 1) {
+            dir.push(rng.pick(&DIRS));
+        }
+        let stem = format!("{}-{g:03}", rng.pick(&WORDS));
+        let ext = *rng.pick(&EXTS);
+        let name = format!("{stem}.{ext}");
+        let original = body(g, "original", spec.size);
+        let t0 = UNIX_EPOCH + Duration::from_secs(EPOCH_SECS + g as u64 * 3600);
+
+        let deleted = spec.patterns.contains(&Pattern::Deleted) && rng.below(4) == 0;
+        if !deleted {
+            files.push(FixtureFile {
+                path: dir.join(&name),
+                content: original.clone(),
+                modified: t0,
+            });
+        }
+
+        let style = *rng.pick(&naming);
+        let identical = spec.patterns.contains(&Pattern::Identical) && rng.below(4) == 0;
+        let mut at = t0;
+        for k in 0..=rng.below(spec.max_conflicts.max(1) as u64) {
+            at += Duration::from_secs(60 + rng.below(86_400));
+            let device = rng.pick(&spec.devices);
+            let stamp = DateTime::<Utc>::from(at).format("%Y%m%d-%H%M%S");
+            let file = match style {
+                Pattern::Infix => format!("{stem}.sync-conflict-{stamp}-{device}.{ext}"),
+                _ => format!("{name}.sync-conflict-{stamp}-{device}"),
+            };
+            let content = if identical && k == 0 {
+                original.clone()
+            } else {
+                body(g, &format!("{device} version {k}"), spec.size)
+            };
+            files.push(FixtureFile {
+                path: dir.join(file),
+                content,
+                modified: at,
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// Writes the fixture under `root` (created if missing) with each file's mtime set, and
+/// returns the paths written.
+pub fn generate(root: &Path, spec: &FixtureSpec) -> Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    for f in fixture_files(spec)? {
+        let path = root.join(&f.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {parent:?}"))?;
+        }
+        fs::write(&path, &f.content).with_context(|| format!("write {path:?}"))?;
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(f.modified))
+            .with_context(|| format!("set mtime of {path:?}"))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// The fixture as conflict groups under `root`, built in memory: nothing is read from or
+/// written to disk. Groups and candidates are ordered the way [`scan_conflicts`] orders
+/// them.
+///
+/// [`scan_conflicts`]: crate::scan::scan_conflicts
+pub fn demo_groups(root: &Path, spec: &FixtureSpec) -> Result<Vec<ConflictGroup>> {
+    let candidate = |f: &FixtureFile, is_original: bool| Candidate {
+        path: root.join(&f.path),
+        exists: true,
+        is_original,
+        size: Some(f.content.len() as u64),
+        modified: Some(f.modified),
+        label: String::new(),
+        trust: None,
+    };
+    let files = fixture_files(spec)?;
+    let mut by_base: BTreeMap<PathBuf, Vec<Candidate>> = BTreeMap::new();
+    for f in &files {
+        let name = f.path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(base) = is_conflict_name(&name) {
+            by_base
+                .entry(root.join(f.path.with_file_name(base)))
+                .or_default()
+                .push(candidate(f, false));
+        }
+    }
+    Ok(by_base
+        .into_iter()
+        .map(|(base_path, mut conflicts)| {
+            conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+            let original = files
+                .iter()
+                .find(|f| root.join(&f.path) == base_path)
+                .map(|f| candidate(f, true))
+                .unwrap_or(Candidate {
+                    path: base_path.clone(),
+                    exists: false,
+                    is_original: true,
+                    size: None,
+                    modified: None,
+                    label: String::new(),
+                    trust: None,
+                });
+            let mut candidates = vec![Candidate {
+                label: "Original".to_string(),
+                ..original
+            }];
+            for (i, c) in conflicts.into_iter().enumerate() {
+                candidates.push(Candidate {
+                    label: format!("Conflict {}", i + 1),
+                    ..c
+                });
+            }
+            ConflictGroup {
+                base_path,
+                candidates,
+                chosen: None,
+                references: Vec::new(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::scan_conflicts;
+    use tempfile::tempdir;
+
+    #[test]
+    fn same_seed_same_tree_and_every_group_scans() {
+        let spec = FixtureSpec {
+            groups: 12,
+            patterns: vec![Pattern::Suffix, Pattern::Identical, Pattern::Deleted],
+            ..FixtureSpec::default()
+        };
+        let files = fixture_files(&spec).unwrap();
+        assert_eq!(files, fixture_files(&spec).unwrap());
+        let other = FixtureSpec {
+            seed: 2,
+            ..spec.clone()
+        };
+        assert_ne!(files, fixture_files(&other).unwrap());
+
+        let td = tempdir().unwrap();
+        let written = generate(td.path(), &spec).unwrap();
+        assert_eq!(written.len(), files.len());
+        let groups = scan_conflicts(td.path(), true).unwrap();
+        assert_eq!(groups.len(), 12);
+        assert!(groups.iter().any(|g| g.original_deleted()));
+        let c = &groups[0].candidates[1];
+        assert!(c.modified.unwrap() > UNIX_EPOCH + Duration::from_secs(EPOCH_SECS));
+        assert!(c.size.unwrap() >= 256);
+
+        // The in-memory groups match what a scan of the written tree finds.
+        let demo = demo_groups(td.path(), &spec).unwrap();
+        assert_eq!(demo.len(), groups.len());
+        for (d, g) in demo.iter().zip(&groups) {
+            assert_eq!(d.base_path, g.base_path);
+            let paths = |g: &ConflictGroup| -> Vec<(PathBuf, bool, Option<u64>)> {
+                g.candidates
+                    .iter()
+                    .map(|c| (c.path.clone(), c.exists, c.size))
+                    .collect()
+            };
+            assert_eq!(paths(d), paths(g));
+        }
+
+        assert_eq!("infix".parse::<Pattern>().unwrap(), Pattern::Infix);
+        assert!("weird".parse::<Pattern>().is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use chrono::{DateTime, Local};
+use std::time::{Duration, SystemTime};
+
+/// Local `YYYY-MM-DD HH:MM:SS`.
+pub fn format_time(t: SystemTime) -> String {
+    DateTime::<Local>::from(t)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// How long before `now` `t` was, in words: `just now`, `1 minute ago`, `3 days ago`,
+/// `2 years ago`; `in 5 minutes` when `t` is ahead of `now` (clock skew).
+pub fn format_relative(t: SystemTime, now: SystemTime) -> String {
+    let (d, ahead) = match now.duration_since(t) {
+        Ok(d) => (d, false),
+        Err(e) => (e.duration(), true),
+    };
+    let s = d.as_secs();
+    let (n, unit) = match s {
+        0..45 => return "just now".to_string(),
+        45..3600 => ((s + 30) / 60, "minute"),
+        3600..86400 => ((s + 1800) / 3600, "hour"),
+        86400..2_592_000 => ((s + 43200) / 86400, "day"),
+        2_592_000..31_536_000 => (s / 2_592_000, "month"),
+        _ => (s / 31_536_000, "year"),
+    };
+    let plural = if n == 1 { "" } else { "s" };
+    if ahead {
+        format!("in {n} {unit}{plural}")
+    } else {
+        format!("{n} {unit}{plural} ago")
+    }
+}
+
+/// Local time to the minute with how long ago it was, e.g.
+/// `2024-04-30 20:23 (3 days ago)`.
+pub fn format_time_ago(t: SystemTime, now: SystemTime) -> String {
+    let local = DateTime::<Local>::from(t).format("%Y-%m-%d %H:%M");
+    format!("{local} ({})", format_relative(t, now))
+}
+
+/// Local (`YYYYMMDD`, `HHMMSS`), the same shape Syncthing uses in conflict names.
+pub fn format_stamp(t: SystemTime) -> (String, String) {
+    let dt = DateTime::<Local>::from(t);
+    (
+        dt.format("%Y%m%d").to_string(),
+        dt.format("%H%M%S").to_string(),
+    )
+}
+
+/// Human-readable size in powers of 1024, e.g. `512 B`, `1.5 KiB`, `3.0 GiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut v = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while v >= 1024.0 && unit < UNITS.len() - 1 {
+        v /= 1024.0;
+        unit += 1;
+    }
+    format!("{v:.1} {}", UNITS[unit])
+}
+
+/// Compact age such as `45s`, `12m`, `3h` or `2d`.
+pub fn format_age(d: Duration) -> String {
+    let s = d.as_secs();
+    match s {
+        0..60 => format!("{s}s"),
+        60..3600 => format!("{}m", s / 60),
+        3600..86400 => format!("{}h", s / 3600),
+        _ => format!("{}d", s / 86400),
+    }
+}
+
+/// Compact gap such as `+45s`, `+12m`, `+3h` or `+2d`.
+pub fn format_gap(d: Duration) -> String {
+    format!("+{}", format_age(d))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn format_time_shape() {
+        let s = format_time(UNIX_EPOCH + Duration::from_secs(1_714_503_812));
+        assert_eq!(s.len(), 19);
+        assert!(s.starts_with("2024-04-30") || s.starts_with("2024-05-01"));
+    }
+
+    #[test]
+    fn format_relative_words() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_714_503_812);
+        let ago = |s: u64| format_relative(now - Duration::from_secs(s), now);
+        assert_eq!(ago(10), "just now");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(45 * 60), "45 minutes ago");
+        assert_eq!(ago(89 * 60), "1 hour ago");
+        assert_eq!(ago(2 * 3600 + 10), "2 hours ago");
+        assert_eq!(ago(3 * 86400), "3 days ago");
+        assert_eq!(ago(65 * 86400), "2 months ago");
+        assert_eq!(ago(800 * 86400), "2 years ago");
+        assert_eq!(
+            format_relative(now + Duration::from_secs(300), now),
+            "in 5 minutes"
+        );
+        let when = format_time_ago(now - Duration::from_secs(3 * 86400), now);
+        assert!(when.ends_with(" (3 days ago)"), "{when}");
+    }
+
+    #[test]
+    fn format_size_units() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[test]
+    fn format_gap_units() {
+        assert_eq!(format_gap(Duration::from_secs(5)), "+5s");
+        assert_eq!(format_gap(Duration::from_secs(600)), "+10m");
+        assert_eq!(format_gap(Duration::from_secs(7200)), "+2h");
+        assert_eq!(format_gap(Duration::from_secs(3 * 86400 + 5)), "+3d");
+        assert_eq!(format_age(Duration::from_secs(45)), "45s");
+    }
+}
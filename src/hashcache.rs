@@ -0,0 +1,163 @@
+use crate::ops::hash_file;
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS hashes (
+    path TEXT PRIMARY KEY,
+    size INTEGER NOT NULL,
+    mtime_ns INTEGER NOT NULL,
+    sha256 TEXT NOT NULL
+);
+";
+
+/// `<cache dir>/synctui-resolver/hashes.sqlite3`.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|d| d.join("synctui-resolver").join("hashes.sqlite3"))
+}
+
+/// SHA-256s of files by path, reused while the file's size and mtime are unchanged, so
+/// repeated runs don't read unchanged files again. One row per path: hashing a changed
+/// file replaces its row.
+pub struct HashCache {
+    conn: Connection,
+}
+
+impl HashCache {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {parent:?}"))?;
+        }
+        let conn = Connection::open(path).with_context(|| format!("open hash cache {path:?}"))?;
+        conn.execute_batch(SCHEMA)
+            .context("create hash cache schema")?;
+        Ok(Self { conn })
+    }
+
+    /// `path`'s SHA-256 as lowercase hex, from the cache when its size and mtime match,
+    /// else hashed and stored. Cache errors fall back to hashing.
+    pub fn hash(&self, path: &Path) -> Result<String> {
+        let meta = fs::metadata(path).with_context(|| format!("stat {path:?}"))?;
+        let size = meta.len() as i64;
+        let mtime_ns = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos() as i64);
+        let key = path.to_string_lossy();
+        let cached: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT sha256 FROM hashes WHERE path = ?1 AND size = ?2 AND mtime_ns = ?3",
+                params![key, size, mtime_ns],
+                |r| r.get(0),
+            )
+            .optional()
+            .unwrap_or(None);
+        if let Some(hash) = cached {
+            return Ok(hash);
+        }
+        let hash = hash_file(path)?;
+        let _ = self.conn.execute(
+            "INSERT OR REPLACE INTO hashes (path, size, mtime_ns, sha256) VALUES (?1, ?2, ?3, ?4)",
+            params![key, size, mtime_ns, hash],
+        );
+        Ok(hash)
+    }
+}
+
+/// Hashes `path` through `cache` when there is one.
+pub fn hash_cached(cache: Option<&HashCache>, path: &Path) -> Result<String> {
+    match cache {
+        Some(c) => c.hash(path),
+        None => hash_file(path),
+    }
+}
+
+/// Hashes `paths` on a background thread, through the hash cache at `cache` when given,
+/// sending `(index, hash)` for each one that is `Some` (`None` for an unreadable file).
+/// The thread stops before its next file once `cancel` is set or the receiver is dropped.
+pub fn spawn_hashes(
+    paths: Vec<Option<PathBuf>>,
+    cache: Option<PathBuf>,
+    cancel: Arc<AtomicBool>,
+) -> Receiver<(usize, Option<String>)> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let cache = cache.and_then(|p| HashCache::open(&p).ok());
+        for (i, path) in paths.iter().enumerate() {
+            let Some(path) = path else { continue };
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            if tx
+                .send((i, hash_cached(cache.as_ref(), path).ok()))
+                .is_err()
+            {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    #[test]
+    fn reuses_hashes_until_size_or_mtime_change() {
+        let td = tempdir().unwrap();
+        let cache = HashCache::open(&td.path().join("cache/hashes.sqlite3")).unwrap();
+        let p = td.path().join("photo.jpg");
+        fs::write(&p, "abc").unwrap();
+        let mtime = SystemTime::now() - Duration::from_secs(60);
+        let set_mtime = |t| {
+            fs::File::options()
+                .write(true)
+                .open(&p)
+                .unwrap()
+                .set_modified(t)
+                .unwrap()
+        };
+        set_mtime(mtime);
+        let abc = cache.hash(&p).unwrap();
+        assert_eq!(abc, hash_file(&p).unwrap());
+
+        // Same size and mtime: the cached hash is trusted, even for other contents.
+        fs::write(&p, "xyz").unwrap();
+        set_mtime(mtime);
+        assert_eq!(cache.hash(&p).unwrap(), abc);
+
+        // A new mtime means hashing again.
+        set_mtime(mtime + Duration::from_secs(1));
+        assert_eq!(cache.hash(&p).unwrap(), hash_file(&p).unwrap());
+        assert_ne!(cache.hash(&p).unwrap(), abc);
+    }
+
+    #[test]
+    fn hashes_in_the_background_until_cancelled() {
+        let td = tempdir().unwrap();
+        let p = td.path().join("a.txt");
+        fs::write(&p, "abc").unwrap();
+        let gone = td.path().join("gone.txt");
+        let paths = vec![Some(p.clone()), None, Some(gone)];
+        let cancel = Arc::new(AtomicBool::new(false));
+        let got: Vec<_> = spawn_hashes(paths.clone(), None, cancel.clone())
+            .iter()
+            .collect();
+        assert_eq!(got, [(0, Some(hash_file(&p).unwrap())), (2, None)]);
+
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(spawn_hashes(paths, None, cancel).iter().count(), 0);
+    }
+}
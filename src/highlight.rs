@@ -0,0 +1,323 @@
+use similar::{ChangeTag, TextDiff};
+use std::ops::Range;
+use std::path::Path;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Theme used for every highlighted pane; it reads well on dark and light terminals.
+const THEME: &str = "base16-ocean.dark";
+
+/// A run of text in one color.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub rgb: (u8, u8, u8),
+    pub bold: bool,
+    pub text: String,
+    /// Part of the words that changed between a removed line and the added line it pairs
+    /// with; only set on diff lines.
+    pub changed: bool,
+}
+
+/// What a line of `diff -u` output is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffKind {
+    /// `---`/`+++` file headers and notes such as `\ No newline at end of file`.
+    Header,
+    /// `@@ ... @@` hunk headers.
+    Hunk,
+    Added,
+    Removed,
+    Context,
+}
+
+/// A line of `diff -u` output: its kind, the `+`/`-`/` ` marker (empty for headers), and
+/// the highlighted rest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub marker: &'static str,
+    pub tokens: Vec<Token>,
+}
+
+/// Syntax highlighting by file extension. Loading the bundled syntaxes takes a moment, so
+/// build one and keep it.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        let mut themes = ThemeSet::load_defaults();
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes
+                .themes
+                .remove(THEME)
+                .expect("syntect ships base16-ocean.dark"),
+        }
+    }
+
+    /// Syntax for `path` by extension, then by full file name (`Makefile`), then by the
+    /// first line (shebangs), falling back to plain text.
+    fn syntax_for(&self, path: &Path, text: &str) -> &SyntaxReference {
+        let by = |s: Option<&std::ffi::OsStr>| {
+            s.and_then(|s| self.syntaxes.find_syntax_by_extension(&s.to_string_lossy()))
+        };
+        by(path.extension())
+            .or_else(|| by(path.file_name()))
+            .or_else(|| {
+                self.syntaxes
+                    .find_syntax_by_first_line(text.lines().next().unwrap_or(""))
+            })
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text())
+    }
+
+    /// `text` split into lines of colored tokens, using the syntax for `path`.
+    pub fn lines(&self, path: &Path, text: &str) -> Vec<Vec<Token>> {
+        let mut h = HighlightLines::new(self.syntax_for(path, text), &self.theme);
+        LinesWithEndings::from(text)
+            .map(|line| self.tokens(&mut h, line))
+            .collect()
+    }
+
+    /// Classifies each line of unified diff output and highlights the changed and context
+    /// lines with the syntax for `path` (one of the compared files). Within a run of
+    /// removed lines followed by added lines, the n-th removed and n-th added lines are
+    /// compared word by word and the differing words marked [`Token::changed`].
+    pub fn diff(&self, path: &Path, diff: &str) -> Vec<DiffLine> {
+        let mut h = HighlightLines::new(self.syntax_for(path, ""), &self.theme);
+        let mut out: Vec<DiffLine> = diff
+            .lines()
+            .map(|line| {
+                let plain = |kind| DiffLine {
+                    kind,
+                    marker: "",
+                    tokens: vec![Token {
+                        rgb: (0xc0, 0xc5, 0xce),
+                        bold: kind == DiffKind::Hunk,
+                        text: line.to_string(),
+                        changed: false,
+                    }],
+                };
+                if line.starts_with("---") || line.starts_with("+++") {
+                    return plain(DiffKind::Header);
+                }
+                if line.starts_with("@@") {
+                    return plain(DiffKind::Hunk);
+                }
+                let (kind, marker) = match line.as_bytes().first() {
+                    Some(b'+') => (DiffKind::Added, "+"),
+                    Some(b'-') => (DiffKind::Removed, "-"),
+                    Some(b' ') => (DiffKind::Context, " "),
+                    _ => return plain(DiffKind::Header),
+                };
+                DiffLine {
+                    kind,
+                    marker,
+                    tokens: self.tokens(&mut h, &format!("{}\n", &line[1..])),
+                }
+            })
+            .collect();
+        let mut i = 0;
+        while i < out.len() {
+            let run = |from: usize, kind| {
+                from + out[from..].iter().take_while(|l| l.kind == kind).count()
+            };
+            let removed_end = run(i, DiffKind::Removed);
+            let added_end = run(removed_end, DiffKind::Added);
+            if removed_end == i || added_end == removed_end {
+                i = added_end.max(i + 1);
+                continue;
+            }
+            for (r, a) in (i..removed_end).zip(removed_end..added_end) {
+                let text = |l: &DiffLine| l.tokens.iter().map(|t| t.text.as_str()).collect();
+                let (old, new): (String, String) = (text(&out[r]), text(&out[a]));
+                if let Some((old_ranges, new_ranges)) = word_changes(&old, &new) {
+                    mark_changed(&mut out[r].tokens, &old_ranges);
+                    mark_changed(&mut out[a].tokens, &new_ranges);
+                }
+            }
+            i = added_end;
+        }
+        out
+    }
+
+    fn tokens(&self, h: &mut HighlightLines, line: &str) -> Vec<Token> {
+        match h.highlight_line(line, &self.syntaxes) {
+            Ok(ranges) => ranges
+                .into_iter()
+                .map(|(style, text)| Token {
+                    rgb: (style.foreground.r, style.foreground.g, style.foreground.b),
+                    bold: style.font_style.contains(FontStyle::BOLD),
+                    text: text.trim_end_matches(['\n', '\r']).to_string(),
+                    changed: false,
+                })
+                .filter(|t| !t.text.is_empty())
+                .collect(),
+            // Unhighlightable lines are still shown.
+            Err(_) => vec![Token {
+                rgb: (0xc0, 0xc5, 0xce),
+                bold: false,
+                text: line.trim_end_matches(['\n', '\r']).to_string(),
+                changed: false,
+            }],
+        }
+    }
+}
+
+/// Byte ranges within one line.
+pub type WordRanges = Vec<Range<usize>>;
+
+/// Byte ranges of the words that differ in `old` and in `new`. Changes separated only by
+/// whitespace are merged, so a rewritten phrase reads as one change rather than a run of
+/// single words. `None` when the lines share no word, since marking all of both says no
+/// more than the `-`/`+` markers do.
+pub fn word_changes(old: &str, new: &str) -> Option<(WordRanges, WordRanges)> {
+    let diff = TextDiff::from_words(old, new);
+    let (mut old_at, mut new_at) = (0, 0);
+    let (mut old_ranges, mut new_ranges) = (Vec::new(), Vec::new());
+    let mut shared = false;
+    for change in diff.iter_all_changes() {
+        let len = change.value().len();
+        match change.tag() {
+            ChangeTag::Equal => {
+                shared |= !change.value().trim().is_empty();
+                old_at += len;
+                new_at += len;
+            }
+            ChangeTag::Delete => {
+                push_merged(&mut old_ranges, old_at..old_at + len, old);
+                old_at += len;
+            }
+            ChangeTag::Insert => {
+                push_merged(&mut new_ranges, new_at..new_at + len, new);
+                new_at += len;
+            }
+        }
+    }
+    shared.then_some((old_ranges, new_ranges))
+}
+
+/// Adds `range` of `text` to `ranges`, joining it to the last range when only whitespace
+/// lies between them. Whitespace-only changes on their own aren't marked.
+fn push_merged(ranges: &mut Vec<Range<usize>>, range: Range<usize>, text: &str) {
+    if let Some(last) = ranges.last_mut() {
+        if text[last.end..range.start].trim().is_empty() {
+            last.end = range.end;
+            return;
+        }
+    }
+    if !text[range.clone()].trim().is_empty() {
+        ranges.push(range);
+    }
+}
+
+/// Splits `tokens` at the edges of `ranges` (byte offsets into their joined text) and
+/// marks the pieces inside as changed.
+fn mark_changed(tokens: &mut Vec<Token>, ranges: &[Range<usize>]) {
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut at = 0;
+    for t in tokens.drain(..) {
+        let end = at + t.text.len();
+        let mut cuts = vec![at, end];
+        for r in ranges {
+            cuts.extend([r.start, r.end].into_iter().filter(|&c| at < c && c < end));
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+        for w in cuts.windows(2) {
+            out.push(Token {
+                text: t.text[w[0] - at..w[1] - at].to_string(),
+                changed: ranges.iter().any(|r| r.start <= w[0] && w[1] <= r.end),
+                ..t.clone()
+            });
+        }
+        at = end;
+    }
+    *tokens = out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_by_extension_and_classifies_diff_lines() {
+        let h = Highlighter::new();
+        let rust = h.lines(Path::new("a.rs"), "fn main() {}\n// note\n");
+        assert_eq!(rust.len(), 2);
+        assert!(rust[0].len() > 1);
+        // Keywords and comments get different colors; plain text gets one run per line.
+        assert_ne!(rust[0][0].rgb, rust[1][0].rgb);
+        let plain = h.lines(Path::new("a.unknownext"), "fn main() {}\n");
+        assert_eq!(plain[0].len(), 1);
+
+        let diff = "--- a.rs\n+++ b.rs\n@@ -1 +1 @@\n-fn a() {}\n+fn b() {}\n \n\\ No newline at end of file";
+        let kinds: Vec<DiffKind> = h
+            .diff(Path::new("a.rs"), diff)
+            .iter()
+            .map(|l| l.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                DiffKind::Header,
+                DiffKind::Header,
+                DiffKind::Hunk,
+                DiffKind::Removed,
+                DiffKind::Added,
+                DiffKind::Context,
+                DiffKind::Header,
+            ]
+        );
+        let added = &h.diff(Path::new("a.rs"), "+fn b() {}")[0];
+        assert_eq!(added.marker, "+");
+        let text: String = added.tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(text, "fn b() {}");
+    }
+
+    #[test]
+    fn marks_changed_words_within_paired_lines() {
+        let (old, new) = ("the quick brown fox", "the slow red fox");
+        let (o, n) = word_changes(old, new).unwrap();
+        // Adjacent changed words merge into one phrase.
+        assert_eq!(
+            o.iter().map(|r| &old[r.clone()]).collect::<Vec<_>>(),
+            ["quick brown"]
+        );
+        assert_eq!(
+            n.iter().map(|r| &new[r.clone()]).collect::<Vec<_>>(),
+            ["slow red"]
+        );
+        assert_eq!(word_changes("one two", "three four"), None);
+
+        let h = Highlighter::new();
+        let lines = h.diff(
+            Path::new("a.txt"),
+            "-keep me\n-timeout = 30\n+keep me\n+timeout = 60\n+added line",
+        );
+        let changed = |l: &DiffLine| -> Vec<String> {
+            l.tokens
+                .iter()
+                .filter(|t| t.changed)
+                .map(|t| t.text.clone())
+                .collect()
+        };
+        assert_eq!(changed(&lines[0]), Vec::<String>::new());
+        assert_eq!(changed(&lines[1]), ["30"]);
+        assert_eq!(changed(&lines[3]), ["60"]);
+        // The unpaired added line isn't marked word by word.
+        assert_eq!(changed(&lines[4]), Vec::<String>::new());
+        let text: String = lines[3].tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(text, "timeout = 60");
+    }
+}
@@ -0,0 +1,556 @@
+use crate::config::app_data_dir;
+use crate::model::{ConflictGroup, Strategy};
+use crate::ops::Actor;
+use crate::rules::FiredRule;
+use crate::scan::conflict_meta;
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Device name recorded for the original file, which carries no device in its name.
+pub const LOCAL_DEVICE: &str = "local";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS resolutions (
+    id INTEGER PRIMARY KEY,
+    at INTEGER NOT NULL,
+    root TEXT NOT NULL,
+    base_path TEXT NOT NULL,
+    kept_path TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS versions (
+    resolution_id INTEGER NOT NULL REFERENCES resolutions(id),
+    path TEXT NOT NULL,
+    device TEXT NOT NULL,
+    kept INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS versions_device ON versions(device);
+";
+
+/// Schema changes after `SCHEMA`, applied in order; `PRAGMA user_version` counts how many
+/// a database already has.
+const MIGRATIONS: &[&str] = &[
+    // 1: the strategy that matched the kept version, for "resolve like last time".
+    "ALTER TABLE resolutions ADD COLUMN strategy TEXT;
+     CREATE INDEX IF NOT EXISTS resolutions_base ON resolutions(base_path);",
+    // 2: the kept file's SHA-256 from `--verify` runs.
+    "ALTER TABLE resolutions ADD COLUMN kept_sha256 TEXT;",
+    // 3: the ledger of rules fired by dry-runs and applies, for `stats`.
+    "CREATE TABLE IF NOT EXISTS rule_events (
+         at INTEGER NOT NULL,
+         rule TEXT NOT NULL,
+         base_path TEXT NOT NULL,
+         action TEXT NOT NULL,
+         dry_run INTEGER NOT NULL,
+         ok INTEGER NOT NULL
+     );
+     CREATE INDEX IF NOT EXISTS rule_events_rule ON rule_events(rule);",
+    // 4: groups snoozed until a Unix time, hidden from the list and reports until then.
+    "CREATE TABLE IF NOT EXISTS snoozes (
+         base_path TEXT PRIMARY KEY,
+         until INTEGER NOT NULL
+     );",
+    // 5: who applied each resolution, from which host and tool version.
+    "ALTER TABLE resolutions ADD COLUMN user TEXT;
+     ALTER TABLE resolutions ADD COLUMN host TEXT;
+     ALTER TABLE resolutions ADD COLUMN tool_version TEXT;",
+];
+
+/// `<data dir>/synctui-resolver/history.sqlite3` (`%APPDATA%` on Windows).
+pub fn default_path() -> Option<PathBuf> {
+    app_data_dir(cfg!(windows), dirs::data_dir(), |var| {
+        std::env::var(var).ok()
+    })
+    .map(|d| d.join("synctui-resolver").join("history.sqlite3"))
+}
+
+/// One applied group.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resolution {
+    /// Unix seconds.
+    pub at: i64,
+    pub base_path: PathBuf,
+    pub kept_path: PathBuf,
+    /// Recorded by `--verify` runs.
+    pub kept_sha256: Option<String>,
+    /// Who applied it; `None` for entries older than this record.
+    pub actor: Option<Actor>,
+}
+
+/// How a base path was resolved the last time it was applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Remembered {
+    /// Unix seconds.
+    pub at: i64,
+    /// Device whose version was kept (`LOCAL_DEVICE` for the original).
+    pub device: String,
+    /// Strategy that would have picked the same version, if any.
+    pub strategy: Option<Strategy>,
+}
+
+impl Remembered {
+    /// Candidate to keep now: the newest version from the same device, falling back to the
+    /// remembered strategy when that device has no version this time.
+    pub fn choose(&self, g: &ConflictGroup) -> Option<usize> {
+        let by_device = g
+            .candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.exists && device_of(&c.path) == self.device)
+            .max_by_key(|(_, c)| c.modified)
+            .map(|(i, _)| i);
+        by_device.or_else(|| self.strategy.and_then(|s| s.choose(g)))
+    }
+}
+
+fn device_of(path: &Path) -> String {
+    conflict_meta(path)
+        .map(|m| m.device)
+        .unwrap_or_else(|| LOCAL_DEVICE.to_string())
+}
+
+/// The simplest strategy that keeps `chosen_idx`, if one does.
+fn matching_strategy(g: &ConflictGroup, chosen_idx: usize) -> Option<Strategy> {
+    [Strategy::Current, Strategy::Newest, Strategy::Oldest]
+        .into_iter()
+        .find(|s| s.choose(g) == Some(chosen_idx))
+}
+
+/// How often a device's versions were kept or discarded within one month.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceStats {
+    pub device: String,
+    /// Local `YYYY-MM`.
+    pub month: String,
+    pub wins: u64,
+    pub losses: u64,
+}
+
+/// One rule firing on one group.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleEvent {
+    /// Unix seconds.
+    pub at: i64,
+    pub rule: String,
+    pub base_path: PathBuf,
+    /// What the rule did, or would have done in a dry-run.
+    pub action: String,
+    pub dry_run: bool,
+    /// Whether the group went through; a failed apply didn't do `action`.
+    pub ok: bool,
+}
+
+/// How often a rule fired, by outcome.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleStats {
+    pub rule: String,
+    pub dry_runs: u64,
+    pub applied: u64,
+    pub failed: u64,
+    /// Unix seconds of the latest firing.
+    pub last_at: i64,
+}
+
+/// SQLite log of applied resolutions.
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| format!("create {parent:?}"))?;
+        }
+        let conn = Connection::open(path).with_context(|| format!("open history {path:?}"))?;
+        conn.execute_batch(SCHEMA)
+            .context("create history schema")?;
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        for (i, m) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            conn.execute_batch(m)
+                .with_context(|| format!("migrate history to version {}", i + 1))?;
+            conn.pragma_update(None, "user_version", i as i64 + 1)?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Records that `chosen_idx` was kept at `kept_path` (with its hash, if verified) and
+    /// every other existing candidate was archived or deleted, by [`Actor::current`].
+    pub fn record(
+        &mut self,
+        root: &Path,
+        g: &ConflictGroup,
+        chosen_idx: usize,
+        kept_path: &Path,
+        kept_sha256: Option<&str>,
+        at: SystemTime,
+    ) -> Result<i64> {
+        let at = at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let actor = Actor::current();
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO resolutions
+                 (at, root, base_path, kept_path, strategy, kept_sha256, user, host, tool_version)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                at,
+                root.to_string_lossy(),
+                g.base_path.to_string_lossy(),
+                kept_path.to_string_lossy(),
+                matching_strategy(g, chosen_idx).map(Strategy::label),
+                kept_sha256,
+                actor.user,
+                actor.host,
+                actor.version
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        for (i, c) in g.candidates.iter().enumerate() {
+            if !c.exists {
+                continue;
+            }
+            let device = device_of(&c.path);
+            tx.execute(
+                "INSERT INTO versions (resolution_id, path, device, kept) VALUES (?1, ?2, ?3, ?4)",
+                params![id, c.path.to_string_lossy(), device, i == chosen_idx],
+            )?;
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Logs the rules that fired on the group at `base_path` in a dry-run or an apply.
+    pub fn record_rules(
+        &mut self,
+        base_path: &Path,
+        fired: &[FiredRule],
+        dry_run: bool,
+        ok: bool,
+        at: SystemTime,
+    ) -> Result<()> {
+        let at = at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let tx = self.conn.transaction()?;
+        for f in fired {
+            tx.execute(
+                "INSERT INTO rule_events (at, rule, base_path, action, dry_run, ok)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    at,
+                    f.rule,
+                    base_path.to_string_lossy(),
+                    f.action,
+                    dry_run,
+                    ok
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Per-rule counts, most recently fired first.
+    pub fn rule_stats(&self) -> Result<Vec<RuleStats>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT rule, SUM(dry_run), SUM(ok AND NOT dry_run), SUM(NOT ok), MAX(at)
+             FROM rule_events GROUP BY rule ORDER BY MAX(at) DESC, rule",
+        )?;
+        let rows = stmt.query_map([], |r| {
+            Ok(RuleStats {
+                rule: r.get(0)?,
+                dry_runs: r.get::<_, i64>(1)? as u64,
+                applied: r.get::<_, i64>(2)? as u64,
+                failed: r.get::<_, i64>(3)? as u64,
+                last_at: r.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The latest firings of `rule`, newest first.
+    pub fn rule_events(&self, rule: &str, limit: usize) -> Result<Vec<RuleEvent>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT at, rule, base_path, action, dry_run, ok FROM rule_events
+             WHERE rule = ?1 ORDER BY at DESC, rowid DESC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![rule, limit as i64], |r| {
+            Ok(RuleEvent {
+                at: r.get(0)?,
+                rule: r.get(1)?,
+                base_path: PathBuf::from(r.get::<_, String>(2)?),
+                action: r.get(3)?,
+                dry_run: r.get(4)?,
+                ok: r.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The most recent resolutions, newest first.
+    pub fn recent(&self, limit: usize) -> Result<Vec<Resolution>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT at, base_path, kept_path, kept_sha256, user, host, tool_version
+             FROM resolutions ORDER BY at DESC, id DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map([limit as i64], |r| {
+            let actor = match (r.get(4)?, r.get(5)?, r.get(6)?) {
+                (Some(user), Some(host), Some(version)) => Some(Actor {
+                    user,
+                    host,
+                    version,
+                }),
+                _ => None,
+            };
+            Ok(Resolution {
+                at: r.get(0)?,
+                base_path: PathBuf::from(r.get::<_, String>(1)?),
+                kept_path: PathBuf::from(r.get::<_, String>(2)?),
+                kept_sha256: r.get(3)?,
+                actor,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The latest resolution of `base_path`, if it was ever applied.
+    pub fn last_for(&self, base_path: &Path) -> Result<Option<Remembered>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.at, v.device, r.strategy
+             FROM resolutions r JOIN versions v ON v.resolution_id = r.id AND v.kept = 1
+             WHERE r.base_path = ?1
+             ORDER BY r.at DESC, r.id DESC LIMIT 1",
+        )?;
+        let mut rows = stmt.query_map([base_path.to_string_lossy()], |r| {
+            Ok(Remembered {
+                at: r.get(0)?,
+                device: r.get(1)?,
+                strategy: r
+                    .get::<_, Option<String>>(2)?
+                    .and_then(|s| Strategy::from_label(&s)),
+            })
+        })?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Hides the group at `base_path` until `until`, replacing an earlier snooze.
+    pub fn snooze(&mut self, base_path: &Path, until: SystemTime) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO snoozes (base_path, until) VALUES (?1, ?2)",
+            params![base_path.to_string_lossy(), unix_secs(until)],
+        )?;
+        Ok(())
+    }
+
+    /// Ends the snooze of `base_path` early; `false` if it wasn't snoozed.
+    pub fn wake(&mut self, base_path: &Path) -> Result<bool> {
+        let n = self.conn.execute(
+            "DELETE FROM snoozes WHERE base_path = ?1",
+            [base_path.to_string_lossy()],
+        )?;
+        Ok(n > 0)
+    }
+
+    /// Groups still snoozed at `now`, with the Unix time each snooze ends, soonest first.
+    pub fn snoozed(&self, now: SystemTime) -> Result<Vec<(PathBuf, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT base_path, until FROM snoozes WHERE until > ?1 ORDER BY until, base_path",
+        )?;
+        let rows = stmt.query_map([unix_secs(now)], |r| {
+            Ok((PathBuf::from(r.get::<_, String>(0)?), r.get(1)?))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Wins and losses per device and month, ordered by device then month.
+    pub fn device_stats(&self) -> Result<Vec<DeviceStats>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT v.device,
+                    strftime('%Y-%m', r.at, 'unixepoch', 'localtime') AS month,
+                    SUM(v.kept), SUM(1 - v.kept)
+             FROM versions v JOIN resolutions r ON r.id = v.resolution_id
+             GROUP BY v.device, month
+             ORDER BY v.device, month",
+        )?;
+        let rows = stmt.query_map([], |r| {
+            Ok(DeviceStats {
+                device: r.get(0)?,
+                month: r.get(1)?,
+                wins: r.get::<_, i64>(2)? as u64,
+                losses: r.get::<_, i64>(3)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+fn unix_secs(t: SystemTime) -> i64 {
+    t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::stat_candidate;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    fn group(root: &Path, name: &str, devices: &[&str]) -> ConflictGroup {
+        let base = root.join(format!("{name}.txt"));
+        std::fs::write(&base, "x").unwrap();
+        let mut candidates = vec![stat_candidate(base.clone(), true, "Original".into())];
+        for d in devices {
+            let p = root.join(format!("{name}.sync-conflict-20240101-000000-{d}.txt"));
+            std::fs::write(&p, "y").unwrap();
+            candidates.push(stat_candidate(p, false, d.to_string()));
+        }
+        ConflictGroup {
+            base_path: base,
+            candidates,
+            chosen: None,
+            references: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn snoozes_last_until_they_expire_or_are_woken() {
+        let td = tempdir().unwrap();
+        let mut h = History::open(&td.path().join("history.sqlite3")).unwrap();
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let day = Duration::from_secs(86400);
+        let (a, b) = (Path::new("/r/a.txt"), Path::new("/r/b.txt"));
+
+        h.snooze(a, now + 3 * day).unwrap();
+        h.snooze(b, now + day).unwrap();
+        let until = |d: Duration| unix_secs(now + d);
+        assert_eq!(
+            h.snoozed(now).unwrap(),
+            [
+                (b.to_path_buf(), until(day)),
+                (a.to_path_buf(), until(3 * day))
+            ]
+        );
+        // Expired snoozes are ignored.
+        assert_eq!(h.snoozed(now + 2 * day).unwrap().len(), 1);
+        // Snoozing again moves the end.
+        h.snooze(a, now + day / 2).unwrap();
+        assert_eq!(h.snoozed(now + day).unwrap(), []);
+
+        assert!(h.wake(b).unwrap());
+        assert!(!h.wake(b).unwrap());
+        assert_eq!(h.snoozed(now).unwrap(), [(a.to_path_buf(), until(day / 2))]);
+    }
+
+    #[test]
+    fn device_stats_count_wins_and_losses() {
+        let td = tempdir().unwrap();
+        let mut h = History::open(&td.path().join("db/history.sqlite3")).unwrap();
+        let at = UNIX_EPOCH + Duration::from_secs(1_714_503_812);
+
+        let a = group(td.path(), "a", &["PHONE12"]);
+        h.record(td.path(), &a, 1, &a.base_path, None, at).unwrap();
+        let b = group(td.path(), "b", &["PHONE12", "LAPTOP1"]);
+        h.record(td.path(), &b, 2, &b.base_path, Some("ab12"), at)
+            .unwrap();
+
+        let stats = h.device_stats().unwrap();
+        let by = |d: &str| {
+            let s = stats.iter().find(|s| s.device == d).unwrap();
+            (s.wins, s.losses)
+        };
+        assert_eq!(stats.len(), 3);
+        assert_eq!(by("LAPTOP1"), (1, 0));
+        assert_eq!(by("PHONE12"), (1, 1));
+        assert_eq!(by(LOCAL_DEVICE), (0, 2));
+        assert!(stats[0].month.starts_with("2024-0"));
+
+        let recent = h.recent(10).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].base_path, b.base_path);
+        assert_eq!(recent[0].kept_sha256.as_deref(), Some("ab12"));
+        assert_eq!(recent[0].actor.as_ref(), Some(Actor::current()));
+    }
+
+    #[test]
+    fn rule_ledger_counts_dry_runs_applies_and_failures() {
+        let td = tempdir().unwrap();
+        let mut h = History::open(&td.path().join("history.sqlite3")).unwrap();
+        let at = |s| UNIX_EPOCH + Duration::from_secs(s);
+        let fired = |rule: &str, action: &str| FiredRule {
+            rule: rule.to_string(),
+            action: action.to_string(),
+        };
+        let pick = fired("pick *.jpg: newest", "keep a.jpg");
+        let loser = fired("loser >= 1.0 GiB: delete", "delete a.jpg.sync-conflict");
+        let a = Path::new("/r/a.jpg");
+        h.record_rules(a, &[pick.clone(), loser], true, true, at(10))
+            .unwrap();
+        h.record_rules(a, std::slice::from_ref(&pick), false, true, at(20))
+            .unwrap();
+        h.record_rules(a, std::slice::from_ref(&pick), false, false, at(30))
+            .unwrap();
+
+        let stats = h.rule_stats().unwrap();
+        assert_eq!(
+            stats[0],
+            RuleStats {
+                rule: pick.rule.clone(),
+                dry_runs: 1,
+                applied: 1,
+                failed: 1,
+                last_at: 30,
+            }
+        );
+        assert_eq!((stats[1].dry_runs, stats[1].applied), (1, 0));
+
+        let events = h.rule_events(&pick.rule, 2).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].at, events[0].ok), (30, false));
+        assert_eq!(events[1].action, "keep a.jpg");
+        assert!(!events[1].dry_run);
+    }
+
+    #[test]
+    fn remembers_the_last_winner_per_path() {
+        let td = tempdir().unwrap();
+        let db = td.path().join("history.sqlite3");
+        let mut h = History::open(&db).unwrap();
+        let at = UNIX_EPOCH + Duration::from_secs(1_714_503_812);
+
+        let aged = |mut g: ConflictGroup| {
+            for (i, c) in g.candidates.iter_mut().enumerate() {
+                c.modified = Some(at + Duration::from_secs(i as u64));
+            }
+            g
+        };
+        let g = aged(group(td.path(), "a", &["PHONE12", "LAPTOP1"]));
+        h.record(td.path(), &g, 2, &g.base_path, None, at).unwrap();
+        assert!(h.last_for(&td.path().join("b.txt")).unwrap().is_none());
+        // Reopening doesn't re-run migrations.
+        drop(h);
+        let h = History::open(&db).unwrap();
+        let last = h.last_for(&g.base_path).unwrap().unwrap();
+        assert_eq!(last.device, "LAPTOP1");
+        assert_eq!(last.strategy, Some(Strategy::Newest));
+
+        // Next time the same device wins, even when it isn't the newest.
+        let again = aged(group(td.path(), "a", &["LAPTOP1", "TABLET9"]));
+        assert_eq!(last.choose(&again), Some(1));
+        // Without a LAPTOP1 version the strategy decides.
+        let other = aged(group(td.path(), "a", &["TABLET9", "TABLET8"]));
+        assert_eq!(last.choose(&other), Some(2));
+
+        let orig = Remembered {
+            at: 0,
+            device: LOCAL_DEVICE.to_string(),
+            strategy: Some(Strategy::Current),
+        };
+        assert_eq!(orig.choose(&other), Some(0));
+    }
+}
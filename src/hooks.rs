@@ -0,0 +1,257 @@
+use crate::model::ConflictGroup;
+use crate::plan::{kept_destination, ApplyOptions};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Longest `SYNCTUI_PATHS` or `SYNCTUI_KEPT` list passed; Linux refuses to start a command
+/// with a single environment variable over 128 KiB.
+const MAX_ENV_LIST: usize = 32 << 10;
+
+/// Shell commands run around real (not dry-run) applies, from the `[hooks]` table. Each
+/// gets its groups as JSON on stdin and in `SYNCTUI_*` environment variables.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Before a batch moves any file; failing refuses the batch.
+    pub pre_apply: Option<String>,
+    /// After each group of a batch, applied or failed.
+    pub post_group: Option<String>,
+    /// After a batch, once Syncthing folders are resumed.
+    pub post_apply: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookEvent {
+    PreApply,
+    PostGroup,
+    PostApply,
+}
+
+impl HookEvent {
+    pub fn label(self) -> &'static str {
+        match self {
+            HookEvent::PreApply => "pre_apply",
+            HookEvent::PostGroup => "post_group",
+            HookEvent::PostApply => "post_apply",
+        }
+    }
+}
+
+/// One group as a hook sees it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HookGroup {
+    pub base_path: PathBuf,
+    /// Where the chosen version ends up.
+    pub kept: PathBuf,
+    /// `planned`, `applied`, `failed` or `cancelled`.
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl HookGroup {
+    pub fn new(
+        g: &ConflictGroup,
+        chosen: usize,
+        options: &ApplyOptions,
+        status: &'static str,
+    ) -> Self {
+        Self {
+            base_path: g.base_path.clone(),
+            kept: kept_destination(g, chosen, options).unwrap_or_else(|_| g.base_path.clone()),
+            status,
+            error: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    hook: &'static str,
+    root: &'a Path,
+    groups: &'a [HookGroup],
+}
+
+impl Hooks {
+    pub fn command(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::PreApply => self.pre_apply.as_deref(),
+            HookEvent::PostGroup => self.post_group.as_deref(),
+            HookEvent::PostApply => self.post_apply.as_deref(),
+        }
+        .filter(|c| !c.trim().is_empty())
+    }
+
+    /// Runs the `event` hook, if one is configured, through the shell in `root` and
+    /// waits for it. Its output is captured; it fails when the command can't start or
+    /// exits non-zero, with the end of its stderr.
+    ///
+    /// Besides the JSON on stdin (`{"hook", "root", "groups"}`), the command sees
+    /// `SYNCTUI_HOOK`, `SYNCTUI_ROOT`, and `SYNCTUI_PATHS` and `SYNCTUI_KEPT` with one
+    /// base path or kept path per line. A list longer than [`MAX_ENV_LIST`] is left unset,
+    /// so large batches have to be read from stdin.
+    pub fn run(&self, event: HookEvent, root: &Path, groups: &[HookGroup]) -> Result<()> {
+        let Some(line) = self.command(event) else {
+            return Ok(());
+        };
+        let lines = |f: fn(&HookGroup) -> &Path| {
+            groups
+                .iter()
+                .map(|g| f(g).display().to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let mut cmd = shell(line);
+        cmd.current_dir(root)
+            .env("SYNCTUI_HOOK", event.label())
+            .env("SYNCTUI_ROOT", root);
+        for (name, list) in [
+            ("SYNCTUI_PATHS", lines(|g| &g.base_path)),
+            ("SYNCTUI_KEPT", lines(|g| &g.kept)),
+        ] {
+            if list.len() <= MAX_ENV_LIST {
+                cmd.env(name, list);
+            } else {
+                cmd.env_remove(name);
+            }
+        }
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("start {} hook `{line}`", event.label()))?;
+        let payload = serde_json::to_vec(&Payload {
+            hook: event.label(),
+            root,
+            groups,
+        })?;
+        // Written from another thread while the output is drained here: a hook that
+        // ignores stdin and prints a lot would otherwise block on a full pipe with us.
+        let writer = child.stdin.take().map(|mut stdin| {
+            thread::spawn(move || {
+                // A hook that ignores stdin may exit before reading it all.
+                let _ = stdin.write_all(&payload);
+            })
+        });
+        let output = child.wait_with_output()?;
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let tail: Vec<_> = stderr.trim().lines().rev().take(3).collect();
+            let tail: Vec<_> = tail.into_iter().rev().collect();
+            bail!(
+                "{} hook `{line}` {}{}",
+                event.label(),
+                output.status,
+                if tail.is_empty() {
+                    String::new()
+                } else {
+                    format!(": {}", tail.join(" / "))
+                }
+            );
+        }
+        Ok(())
+    }
+}
+
+/// `line` run through `sh -c`, or `cmd /C` on Windows.
+pub(crate) fn shell(line: &str) -> Command {
+    if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.arg("/C").arg(line);
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.arg("-c").arg(line);
+        c
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn group(root: &Path, name: &str) -> HookGroup {
+        HookGroup {
+            base_path: root.join(name),
+            kept: root.join(name),
+            status: "applied",
+            error: None,
+        }
+    }
+
+    #[test]
+    fn hooks_get_groups_on_stdin_and_in_the_environment() {
+        let td = tempdir().unwrap();
+        let root = td.path();
+        let hooks = Hooks {
+            post_apply: Some(
+                "cat > payload.json; printf '%s|%s' \"$SYNCTUI_HOOK\" \"$SYNCTUI_PATHS\" > env.txt"
+                    .to_string(),
+            ),
+            ..Hooks::default()
+        };
+        let groups = [group(root, "a.txt"), group(root, "b.txt")];
+        hooks.run(HookEvent::PostApply, root, &groups).unwrap();
+
+        let env = fs::read_to_string(root.join("env.txt")).unwrap();
+        let paths = format!(
+            "{}\n{}",
+            root.join("a.txt").display(),
+            root.join("b.txt").display()
+        );
+        assert_eq!(env, format!("post_apply|{paths}"));
+        let payload: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(root.join("payload.json")).unwrap()).unwrap();
+        assert_eq!(payload["hook"], "post_apply");
+        assert_eq!(payload["groups"][1]["status"], "applied");
+        assert_eq!(payload["groups"].as_array().unwrap().len(), 2);
+
+        // Unset hooks do nothing.
+        hooks.run(HookEvent::PreApply, root, &groups).unwrap();
+    }
+
+    #[test]
+    fn large_batches_and_chatty_hooks_dont_block() {
+        let td = tempdir().unwrap();
+        let root = td.path();
+        // Ignores its stdin and prints more than a pipe holds.
+        let hooks = Hooks {
+            post_apply: Some(
+                "head -c 200000 /dev/zero; printf '%s' \"${SYNCTUI_PATHS-unset}\" > env.txt"
+                    .to_string(),
+            ),
+            ..Hooks::default()
+        };
+        let name = "x".repeat(200);
+        let groups: Vec<HookGroup> = (0..1000)
+            .map(|i| group(root, &format!("{name}{i}.txt")))
+            .collect();
+        hooks.run(HookEvent::PostApply, root, &groups).unwrap();
+        assert_eq!(fs::read_to_string(root.join("env.txt")).unwrap(), "unset");
+    }
+
+    #[test]
+    fn failing_hooks_report_their_stderr() {
+        let td = tempdir().unwrap();
+        let hooks = Hooks {
+            pre_apply: Some("echo 'not a git repo' >&2; exit 3".to_string()),
+            ..Hooks::default()
+        };
+        let err = hooks
+            .run(HookEvent::PreApply, td.path(), &[])
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("pre_apply hook"), "{err}");
+        assert!(err.ends_with(": not a git repo"), "{err}");
+    }
+}
@@ -0,0 +1,247 @@
+use crate::hashcache::{hash_cached, HashCache};
+use crate::model::{Candidate, ConflictGroup};
+use crate::ops::{
+    archive_dir_for, ensure_dir, ensure_not_archived, move_file_verified, record_archive,
+    unique_name,
+};
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+/// Whether every existing candidate of `g` has the same content, hashing through `cache`
+/// when given. `None` when fewer than two candidates exist or one of them can't be read.
+pub fn candidates_identical(g: &ConflictGroup, cache: Option<&HashCache>) -> Option<bool> {
+    let existing: Vec<&Candidate> = g.candidates.iter().filter(|c| c.exists).collect();
+    if existing.len() < 2 {
+        return None;
+    }
+    // Different sizes settle it without reading anything.
+    if existing.iter().any(|c| c.size != existing[0].size) {
+        return Some(false);
+    }
+    let first = hash_cached(cache, &existing[0].path).ok()?;
+    for c in &existing[1..] {
+        if hash_cached(cache, &c.path).ok()? != first {
+            return Some(false);
+        }
+    }
+    Some(true)
+}
+
+/// For each candidate of `g`, the earlier conflict copy it is byte-identical to; the first
+/// copy of each set stands for the rest. The original is never folded into a conflict,
+/// and missing or unreadable copies stand alone.
+pub fn duplicate_conflicts(g: &ConflictGroup, cache: Option<&HashCache>) -> Vec<Option<usize>> {
+    fold_duplicates(g, cache, &AtomicBool::new(false)).unwrap_or_default()
+}
+
+/// Whether [`duplicate_conflicts`] has to read anything: two existing conflict copies
+/// have the same size.
+pub fn may_have_duplicates(g: &ConflictGroup) -> bool {
+    let mut sizes = BTreeSet::new();
+    g.candidates
+        .iter()
+        .filter(|c| !c.is_original && c.exists)
+        .any(|c| !sizes.insert(c.size))
+}
+
+/// [`duplicate_conflicts`], giving up with `None` before its next copy once `cancel` is set.
+fn fold_duplicates(
+    g: &ConflictGroup,
+    cache: Option<&HashCache>,
+    cancel: &AtomicBool,
+) -> Option<Vec<Option<usize>>> {
+    let mut hashes: Vec<Option<Option<String>>> = vec![None; g.candidates.len()];
+    let mut hash = |i: usize| {
+        hashes[i]
+            .get_or_insert_with(|| hash_cached(cache, &g.candidates[i].path).ok())
+            .clone()
+    };
+    let mut dup = vec![None; g.candidates.len()];
+    for (i, c) in g.candidates.iter().enumerate() {
+        if c.is_original || !c.exists {
+            continue;
+        }
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        for (j, d) in g.candidates[..i].iter().enumerate() {
+            // Sizes settle most pairs without reading anything.
+            if d.is_original || !d.exists || dup[j].is_some() || d.size != c.size {
+                continue;
+            }
+            let h = hash(i);
+            if h.is_some() && h == hash(j) {
+                dup[i] = Some(j);
+                break;
+            }
+        }
+    }
+    Some(dup)
+}
+
+/// `--dedupe`: moves every conflict copy that [`duplicate_conflicts`] folds into another
+/// to the group's archive, recording it in the archive index like an apply does, and
+/// returns how many were moved. The copies they duplicate stay for a later pick.
+pub fn archive_duplicates(root: &Path, g: &ConflictGroup, dup: &[Option<usize>]) -> Result<usize> {
+    let archive_dir = archive_dir_for(&g.base_path)?;
+    let mut moved = 0;
+    for (c, _) in g.candidates.iter().zip(dup).filter(|(_, d)| d.is_some()) {
+        ensure_not_archived(root, &c.path)?;
+        ensure_dir(&archive_dir)?;
+        let file_name = c.path.file_name().ok_or_else(|| anyhow!("bad name"))?;
+        let dest = archive_dir.join(unique_name(file_name.to_string_lossy().as_ref()));
+        move_file_verified(&c.path, &dest, false)
+            .with_context(|| format!("archive {:?} -> {:?}", c.path, dest))?;
+        record_archive(&archive_dir, &c.path, &dest.display().to_string())?;
+        moved += 1;
+    }
+    Ok(moved)
+}
+
+/// Runs [`candidates_identical`] for each group on a background thread, sending
+/// `(base_path, result)` as each one finishes; with `cache`, through the hash cache at
+/// that path. The thread stops before its next group once `cancel` is set or the
+/// receiver is dropped.
+pub fn spawn_identity_checks(
+    groups: Vec<ConflictGroup>,
+    cache: Option<PathBuf>,
+    cancel: Arc<AtomicBool>,
+) -> Receiver<(PathBuf, Option<bool>)> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let cache = cache.and_then(|p| HashCache::open(&p).ok());
+        for g in groups {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            let same = candidates_identical(&g, cache.as_ref());
+            if tx.send((g.base_path, same)).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+/// [`duplicate_conflicts`] for `g` on a background thread, through the hash cache at
+/// `cache` when given; nothing is sent once `cancel` is set.
+pub fn spawn_duplicate_check(
+    g: ConflictGroup,
+    cache: Option<PathBuf>,
+    cancel: Arc<AtomicBool>,
+) -> Receiver<Vec<Option<usize>>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let cache = cache.and_then(|p| HashCache::open(&p).ok());
+        if let Some(dup) = fold_duplicates(&g, cache.as_ref(), &cancel) {
+            let _ = tx.send(dup);
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::stat_candidate;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn group(contents: &[&str]) -> (tempfile::TempDir, ConflictGroup) {
+        let td = tempdir().unwrap();
+        let candidates = contents
+            .iter()
+            .enumerate()
+            .map(|(i, body)| {
+                let p = td.path().join(format!("v{i}"));
+                fs::write(&p, body).unwrap();
+                stat_candidate(p, i == 0, format!("v{i}"))
+            })
+            .collect();
+        let g = ConflictGroup {
+            base_path: td.path().join("v0"),
+            candidates,
+            chosen: None,
+            references: Vec::new(),
+        };
+        (td, g)
+    }
+
+    #[test]
+    fn compares_sizes_then_hashes() {
+        assert_eq!(
+            candidates_identical(&group(&["same", "same"]).1, None),
+            Some(true)
+        );
+        assert_eq!(
+            candidates_identical(&group(&["same", "diff"]).1, None),
+            Some(false)
+        );
+        assert_eq!(
+            candidates_identical(&group(&["short", "longer"]).1, None),
+            Some(false)
+        );
+        assert_eq!(candidates_identical(&group(&["alone"]).1, None), None);
+    }
+
+    #[test]
+    fn identical_conflict_copies_fold_into_the_first_and_can_be_archived() {
+        let (td, g) = group(&["x", "copy", "other", "copy", "copy", "x"]);
+        let dup = duplicate_conflicts(&g, None);
+        // The original stands alone even when a conflict matches it.
+        assert_eq!(dup, [None, None, None, Some(1), Some(1), None]);
+        assert!(may_have_duplicates(&g));
+        assert!(!may_have_duplicates(&group(&["x", "copy", "other"]).1));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let rx = spawn_duplicate_check(g.clone(), None, cancel.clone());
+        assert_eq!(rx.recv().unwrap(), dup);
+        cancel.store(true, Ordering::Relaxed);
+        let rx = spawn_duplicate_check(g.clone(), None, cancel);
+        assert!(rx.recv().is_err());
+
+        let root = td.path();
+        assert_eq!(archive_duplicates(root, &g, &dup).unwrap(), 2);
+        assert!(!root.join("v3").exists() && !root.join("v4").exists());
+        assert!(root.join("v1").exists() && root.join("v5").exists());
+        let archive = archive_dir_for(&g.base_path).unwrap();
+        let archived = fs::read_dir(&archive)
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with('v')
+            })
+            .count();
+        assert_eq!(archived, 2);
+    }
+
+    #[test]
+    fn background_checks_report_every_group() {
+        let (_a, ga) = group(&["x", "x"]);
+        let (_b, gb) = group(&["x", "y"]);
+        let td = tempdir().unwrap();
+        let cache = Some(td.path().join("hashes.sqlite3"));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let groups = vec![ga.clone(), gb.clone()];
+        let mut results: Vec<_> = spawn_identity_checks(groups.clone(), cache, cancel.clone())
+            .iter()
+            .collect();
+        results.sort();
+        let mut expected = vec![(ga.base_path, Some(true)), (gb.base_path, Some(false))];
+        expected.sort();
+        assert_eq!(results, expected);
+
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(
+            spawn_identity_checks(groups, None, cancel).iter().count(),
+            0
+        );
+    }
+}
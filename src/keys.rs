@@ -0,0 +1,642 @@
+use anyhow::{anyhow, bail, Result};
+use crossterm::event::{KeyCode, KeyModifiers};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A key with its modifiers, e.g. `n`, `Ctrl-c` or `PageDown`. Shift is part of the
+/// character for letters (`N`), so it is never stored for them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub mods: KeyModifiers,
+}
+
+impl Key {
+    /// Parses `n`, `N`, `*`, `Space`, `Enter`, `Esc`, `Tab`, `Up`, `F5`, `Ctrl-c`,
+    /// `Alt-x` and the like (names are case-insensitive, single characters are not).
+    pub fn parse(s: &str) -> Result<Key> {
+        let mut mods = KeyModifiers::NONE;
+        let mut rest = s.trim();
+        while let Some((prefix, tail)) = rest.split_once(['-', '+']).filter(|(_, t)| !t.is_empty())
+        {
+            mods |= match prefix.to_ascii_lowercase().as_str() {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "a" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => break,
+            };
+            rest = tail;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                f if f.starts_with('f') && f.len() > 1 => {
+                    KeyCode::F(f[1..].parse().map_err(|_| anyhow!("unknown key {s:?}"))?)
+                }
+                _ => bail!("unknown key {s:?}"),
+            },
+        };
+        Ok(Key::new(code, mods))
+    }
+
+    /// Normalizes a key event: Shift is dropped for characters.
+    pub fn new(code: KeyCode, mods: KeyModifiers) -> Key {
+        let mods = match code {
+            KeyCode::Char(_) => mods - KeyModifiers::SHIFT,
+            _ => mods,
+        };
+        Key { code, mods }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (m, name) in [
+            (KeyModifiers::CONTROL, "Ctrl-"),
+            (KeyModifiers::ALT, "Alt-"),
+            (KeyModifiers::SHIFT, "Shift-"),
+        ] {
+            if self.mods.contains(m) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            KeyCode::Esc => f.write_str("Esc"),
+            KeyCode::PageUp => f.write_str("PageUp"),
+            KeyCode::PageDown => f.write_str("PageDown"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+/// Where a binding applies: one TUI view, or everywhere (`global`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Global,
+    List,
+    Pick,
+    Confirm,
+    Apply,
+    Prune,
+    Cleanup,
+    Columns,
+    Stats,
+    Origins,
+    Detail,
+    Timeline,
+    Preview,
+    Merge,
+    Log,
+    Help,
+    Error,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 17] = [
+        Scope::Global,
+        Scope::List,
+        Scope::Pick,
+        Scope::Confirm,
+        Scope::Apply,
+        Scope::Prune,
+        Scope::Cleanup,
+        Scope::Columns,
+        Scope::Stats,
+        Scope::Origins,
+        Scope::Detail,
+        Scope::Timeline,
+        Scope::Preview,
+        Scope::Merge,
+        Scope::Log,
+        Scope::Help,
+        Scope::Error,
+    ];
+
+    /// The `[keys.<name>]` table.
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::Global => "global",
+            Scope::List => "list",
+            Scope::Pick => "pick",
+            Scope::Confirm => "confirm",
+            Scope::Apply => "apply",
+            Scope::Prune => "prune",
+            Scope::Cleanup => "cleanup",
+            Scope::Columns => "columns",
+            Scope::Stats => "stats",
+            Scope::Origins => "origins",
+            Scope::Detail => "detail",
+            Scope::Timeline => "timeline",
+            Scope::Preview => "preview",
+            Scope::Merge => "merge",
+            Scope::Log => "log",
+            Scope::Help => "help",
+            Scope::Error => "error",
+        }
+    }
+}
+
+/// A bindable action: its scope, config name, default keys and help label (empty to
+/// leave it out of the generated help line).
+struct ActionDef {
+    scope: Scope,
+    name: &'static str,
+    defaults: &'static [&'static str],
+    help: &'static str,
+}
+
+const fn action(
+    scope: Scope,
+    name: &'static str,
+    defaults: &'static [&'static str],
+    help: &'static str,
+) -> ActionDef {
+    ActionDef {
+        scope,
+        name,
+        defaults,
+        help,
+    }
+}
+
+/// Every remappable key, in help-line order. The TUI handles the first default key of
+/// each action; `Keymap::translate` turns whatever is bound into that key.
+const ACTIONS: &[ActionDef] = &[
+    action(Scope::Global, "quit", &["Ctrl-c"], ""),
+    action(Scope::Global, "help", &["?"], "all keys"),
+    action(Scope::Global, "down", &["Down", "j"], ""),
+    action(Scope::Global, "up", &["Up", "k"], ""),
+    action(Scope::Global, "top", &["Home", "g"], ""),
+    action(Scope::Global, "bottom", &["End", "G"], ""),
+    action(Scope::Global, "page_down", &["PageDown"], ""),
+    action(Scope::Global, "page_up", &["PageUp"], ""),
+    action(Scope::Global, "half_page_down", &["Ctrl-d"], ""),
+    action(Scope::Global, "half_page_up", &["Ctrl-u"], ""),
+    action(Scope::List, "pick", &["Enter"], "pick specific"),
+    action(Scope::List, "select", &["Space"], "select"),
+    action(Scope::List, "select_all", &["*"], "all"),
+    action(Scope::List, "invert", &["I"], "invert"),
+    action(Scope::List, "visual", &["V"], "range"),
+    action(Scope::List, "end_visual", &["Esc"], ""),
+    action(Scope::List, "detail", &["i"], "detail"),
+    action(Scope::List, "timeline", &["H"], "timeline"),
+    action(Scope::List, "tree", &["T"], "tree"),
+    action(Scope::List, "fold", &["Left"], ""),
+    action(Scope::List, "unfold", &["Right"], ""),
+    action(Scope::List, "columns", &["m"], "columns"),
+    action(Scope::List, "filter", &["F"], "filter"),
+    action(Scope::List, "sort_type", &["y"], "sort by type"),
+    action(Scope::List, "stats", &["S"], "space stats"),
+    action(Scope::List, "origins", &["F2"], "conflict origins"),
+    action(Scope::List, "current", &["c", "o"], "current"),
+    action(Scope::List, "current_selected", &["C", "O"], ""),
+    action(Scope::List, "newest", &["n"], "newest"),
+    action(Scope::List, "newest_selected", &["N"], ""),
+    action(Scope::List, "oldest", &["p"], "oldest"),
+    action(Scope::List, "oldest_selected", &["P"], ""),
+    action(Scope::List, "trusted", &["r"], "trusted"),
+    action(Scope::List, "trusted_selected", &["R"], ""),
+    action(Scope::List, "largest", &["b"], "largest"),
+    action(Scope::List, "largest_selected", &["Ctrl-b"], ""),
+    action(Scope::List, "like_last_time", &["l"], "like last time"),
+    action(Scope::List, "like_last_time_selected", &["L"], ""),
+    action(
+        Scope::List,
+        "promote",
+        &["u"],
+        "promote when the original was deleted",
+    ),
+    action(Scope::List, "promote_selected", &["U"], ""),
+    action(
+        Scope::List,
+        "discard",
+        &["z"],
+        "discard when the original was deleted",
+    ),
+    action(Scope::List, "discard_selected", &["Z"], ""),
+    action(Scope::List, "undo", &["Ctrl-z"], "undo last apply"),
+    action(Scope::List, "diff", &["d"], "diff"),
+    action(Scope::List, "confirm", &["a"], "confirm"),
+    action(Scope::List, "confirm_selected", &["A"], ""),
+    action(
+        Scope::List,
+        "confirm_by_dir",
+        &["B"],
+        "confirm by directory",
+    ),
+    action(Scope::List, "new_rule", &["w"], "rule from group"),
+    action(Scope::List, "snooze", &["s"], "snooze"),
+    action(Scope::List, "prune", &["X"], "prune archives"),
+    action(Scope::List, "cleanup", &["W"], "clean up leftovers"),
+    action(Scope::List, "toggle_apply", &["t"], "toggle apply"),
+    action(Scope::List, "quit", &["q"], "quit"),
+    action(Scope::Pick, "choose", &["Enter"], "choose"),
+    action(Scope::Pick, "detail", &["i"], "detail"),
+    action(Scope::Pick, "current", &["o"], "current"),
+    action(Scope::Pick, "newest", &["n"], "newest"),
+    action(Scope::Pick, "oldest", &["p"], "oldest"),
+    action(Scope::Pick, "trusted", &["r"], "trusted"),
+    action(Scope::Pick, "largest", &["b"], "largest"),
+    action(Scope::Pick, "diff", &["d"], "diff"),
+    action(Scope::Pick, "diff_path", &["D"], "diff vs path"),
+    action(Scope::Pick, "view", &["v"], "view"),
+    action(Scope::Pick, "merge", &["m"], "merge"),
+    action(Scope::Pick, "open", &["e"], "open"),
+    action(Scope::Pick, "timeline", &["H"], "timeline"),
+    action(Scope::Pick, "toggle_apply", &["t"], "toggle apply"),
+    action(Scope::Pick, "back", &["Esc"], "back"),
+    action(Scope::Confirm, "run", &["y"], "run"),
+    action(Scope::Confirm, "skip_dir", &["s"], "skip directory"),
+    action(Scope::Confirm, "write_plan", &["w"], "write plan to file"),
+    action(Scope::Confirm, "ignore_archive", &["i"], ""),
+    action(Scope::Confirm, "toggle_apply", &["t"], "toggle apply"),
+    action(Scope::Confirm, "log", &["Tab"], "search plan"),
+    action(Scope::Confirm, "toggle_fold", &["Enter"], "fold directory"),
+    action(Scope::Confirm, "fold", &["Left"], ""),
+    action(Scope::Confirm, "unfold", &["Right"], ""),
+    action(Scope::Confirm, "cancel", &["n"], "cancel"),
+    action(Scope::Confirm, "back", &["Esc"], "back"),
+    action(Scope::Apply, "retry", &["r"], "retry highlighted"),
+    action(
+        Scope::Apply,
+        "retry_all",
+        &["R"],
+        "retry failed and cancelled",
+    ),
+    action(
+        Scope::Apply,
+        "cancel",
+        &["c"],
+        "cancel after the current group",
+    ),
+    action(Scope::Apply, "log", &["Tab"], "plan / log"),
+    action(Scope::Apply, "close", &["Esc", "Enter"], "close"),
+    action(Scope::Prune, "delete", &["y"], "delete"),
+    action(Scope::Prune, "cancel", &["n", "Esc"], "cancel"),
+    action(Scope::Cleanup, "delete", &["y"], "delete all"),
+    action(Scope::Cleanup, "cancel", &["n", "Esc"], "cancel"),
+    action(Scope::Columns, "toggle", &["Space", "Enter"], "toggle"),
+    action(Scope::Columns, "close", &["Esc", "m", "q"], "close"),
+    action(Scope::Stats, "close", &["Esc", "S", "q"], "close"),
+    action(Scope::Origins, "close", &["Esc", "F2", "q"], "close"),
+    action(Scope::Detail, "close", &["Esc", "i", "q"], "back"),
+    action(Scope::Timeline, "close", &["Esc", "H", "q"], "back"),
+    action(Scope::Preview, "algorithm", &["a"], "myers/patience"),
+    action(Scope::Preview, "more_context", &["+"], "more context"),
+    action(Scope::Preview, "less_context", &["-"], "less context"),
+    action(Scope::Preview, "whitespace", &["w"], "ignore whitespace"),
+    action(Scope::Preview, "close", &["Esc", "v", "q"], "back"),
+    action(Scope::Merge, "left", &["h", "Left"], "keep original"),
+    action(Scope::Merge, "right", &["l", "Right"], "keep conflict"),
+    action(Scope::Merge, "both", &["b"], "keep both"),
+    action(Scope::Merge, "write", &["Enter"], "write merge"),
+    action(Scope::Merge, "close", &["Esc", "q"], "back"),
+    action(Scope::Log, "search", &["/"], "search"),
+    action(Scope::Log, "next_match", &["n"], "next match"),
+    action(Scope::Log, "prev_match", &["N"], "previous match"),
+    action(Scope::Log, "close", &["Esc", "Tab", "q"], "back"),
+    action(Scope::Help, "close", &["Esc", "q"], "close"),
+    action(Scope::Error, "dismiss", &["Esc", "Enter", "q"], "dismiss"),
+];
+
+/// A `[keys.<scope>]` value: one key or several.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum KeySpec {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeySpec {
+    fn keys(&self) -> &[String] {
+        match self {
+            KeySpec::One(k) => std::slice::from_ref(k),
+            KeySpec::Many(ks) => ks,
+        }
+    }
+}
+
+/// The `[keys]` section: scope name, then action name.
+pub type KeyOverrides = BTreeMap<String, BTreeMap<String, KeySpec>>;
+
+/// The keys bound to every action: the defaults with `[keys]` applied.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    /// Parallel to `ACTIONS`.
+    bindings: Vec<Vec<Key>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: ACTIONS.iter().map(default_keys).collect(),
+        }
+    }
+}
+
+fn default_keys(a: &ActionDef) -> Vec<Key> {
+    a.defaults
+        .iter()
+        .map(|k| Key::parse(k).expect("default key"))
+        .collect()
+}
+
+impl Keymap {
+    /// Applies `overrides`, refusing unknown scopes, actions or keys and any key bound
+    /// twice where both bindings could fire.
+    pub fn new(overrides: &KeyOverrides) -> Result<Self> {
+        let (map, issues) = Self::build(overrides);
+        match issues.first() {
+            None => Ok(map),
+            Some(_) => {
+                let lines: Vec<String> = issues.iter().map(|(k, m)| format!("{k}: {m}")).collect();
+                bail!("invalid [keys]: {}", lines.join("; "))
+            }
+        }
+    }
+
+    /// Every problem with `overrides`, as `(config key, message)`.
+    pub fn issues(overrides: &KeyOverrides) -> Vec<(String, String)> {
+        Self::build(overrides).1
+    }
+
+    fn build(overrides: &KeyOverrides) -> (Self, Vec<(String, String)>) {
+        let mut map = Self::default();
+        let mut issues = Vec::new();
+        for (scope_name, actions) in overrides {
+            let Some(scope) = Scope::ALL.into_iter().find(|s| s.name() == scope_name) else {
+                issues.push((
+                    format!("keys.{scope_name}"),
+                    "unknown key scope".to_string(),
+                ));
+                continue;
+            };
+            for (name, spec) in actions {
+                let key = format!("keys.{scope_name}.{name}");
+                let Some(i) = ACTIONS
+                    .iter()
+                    .position(|a| a.scope == scope && a.name == name)
+                else {
+                    issues.push((key, "unknown action".to_string()));
+                    continue;
+                };
+                let mut keys = Vec::new();
+                for k in spec.keys() {
+                    match Key::parse(k) {
+                        Ok(k) => keys.push(k),
+                        Err(e) => issues.push((key.clone(), format!("{e:#}"))),
+                    }
+                }
+                map.bindings[i] = keys;
+            }
+        }
+        issues.extend(map.conflicts());
+        (map, issues)
+    }
+
+    /// Keys bound to two actions of one scope, or to a view's action and a global one
+    /// (which it would shadow).
+    fn conflicts(&self) -> Vec<(String, String)> {
+        let mut out = Vec::new();
+        for (i, a) in ACTIONS.iter().enumerate() {
+            for (j, b) in ACTIONS.iter().enumerate().take(i) {
+                if !(a.scope == b.scope || b.scope == Scope::Global) {
+                    continue;
+                }
+                for k in self.bindings[i]
+                    .iter()
+                    .filter(|k| self.bindings[j].contains(k))
+                {
+                    out.push((
+                        format!("keys.{}.{}", a.scope.name(), a.name),
+                        format!("{k} is also bound to {}.{}", b.scope.name(), b.name),
+                    ));
+                }
+            }
+        }
+        out
+    }
+
+    /// Maps a key pressed in `scope` to the default key of the action it is bound to
+    /// (the key the TUI handles). Default keys whose action was moved elsewhere are
+    /// swallowed (`None`); anything else passes through unchanged.
+    pub fn translate(&self, scope: Scope, code: KeyCode, mods: KeyModifiers) -> Option<Key> {
+        let pressed = Key::new(code, mods);
+        let in_scope = |s: Scope| {
+            ACTIONS
+                .iter()
+                .enumerate()
+                .filter(move |(_, a)| a.scope == s)
+        };
+        for s in [scope, Scope::Global] {
+            if let Some((_, a)) = in_scope(s).find(|(i, _)| self.bindings[*i].contains(&pressed)) {
+                return Some(default_keys(a)[0]);
+            }
+        }
+        let freed = [scope, Scope::Global]
+            .into_iter()
+            .flat_map(in_scope)
+            .any(|(_, a)| default_keys(a).contains(&pressed));
+        if freed {
+            return None;
+        }
+        Some(Key { code, mods })
+    }
+
+    /// The keys bound to `scope.name`, joined with `/` (empty when unbound).
+    pub fn keys(&self, scope: Scope, name: &str) -> String {
+        let Some(i) = ACTIONS
+            .iter()
+            .position(|a| a.scope == scope && a.name == name)
+        else {
+            return String::new();
+        };
+        let keys: Vec<String> = self.bindings[i].iter().map(Key::to_string).collect();
+        keys.join("/")
+    }
+
+    /// The first key bound to `scope.name`, for prompts (`?` when unbound).
+    pub fn key(&self, scope: Scope, name: &str) -> String {
+        let keys = self.keys(scope, name);
+        match keys.split('/').next() {
+            Some(k) if !k.is_empty() => k.to_string(),
+            _ => "?".to_string(),
+        }
+    }
+
+    /// Every bound action of `scope` as `(keys, label)`, for the help overlay. Actions
+    /// left out of the help line are labelled with their config name.
+    pub fn reference(&self, scope: Scope) -> Vec<(String, String)> {
+        ACTIONS
+            .iter()
+            .filter(|a| a.scope == scope)
+            .filter_map(|a| {
+                let keys = self.keys(scope, a.name);
+                let label = match a.help {
+                    "" => a.name.replace('_', " "),
+                    help => help.to_string(),
+                };
+                (!keys.is_empty()).then_some((keys, label))
+            })
+            .collect()
+    }
+
+    /// `Title: Up/Down | Enter choose | ...` from the bindings of `scope`, leaving out
+    /// the actions in `skip`. An action followed by `<name>_selected` is shown as
+    /// `n, N newest`, the second keys acting on the selected groups.
+    pub fn help(&self, title: &str, scope: Scope, nav: bool, skip: &[&str]) -> String {
+        let mut parts = Vec::new();
+        if nav {
+            parts.push(format!(
+                "{}/{}",
+                self.key(Scope::Global, "up"),
+                self.key(Scope::Global, "down")
+            ));
+        }
+        for a in ACTIONS {
+            if a.scope != scope || a.help.is_empty() || skip.contains(&a.name) {
+                continue;
+            }
+            let mut keys = self.keys(scope, a.name);
+            if keys.is_empty() {
+                continue;
+            }
+            let selected = format!("{}_selected", a.name);
+            if let Some(j) = ACTIONS
+                .iter()
+                .position(|b| b.scope == scope && b.name == selected)
+            {
+                if !self.bindings[j].is_empty() {
+                    keys = format!("{keys}, {}", self.keys(scope, &selected));
+                }
+            }
+            parts.push(format!("{keys} {}", a.help));
+        }
+        format!("{title}: {}", parts.join(" | "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(scope: &str, pairs: &[(&str, &[&str])]) -> KeyOverrides {
+        let actions = pairs
+            .iter()
+            .map(|(name, keys)| {
+                let keys = keys.iter().map(|k| k.to_string()).collect();
+                (name.to_string(), KeySpec::Many(keys))
+            })
+            .collect();
+        BTreeMap::from([(scope.to_string(), actions)])
+    }
+
+    #[test]
+    fn parses_and_prints_keys() {
+        let k = Key::parse("Ctrl-q").unwrap();
+        assert_eq!(k, Key::new(KeyCode::Char('q'), KeyModifiers::CONTROL));
+        assert_eq!(k.to_string(), "Ctrl-q");
+        assert_eq!(Key::parse("space").unwrap().to_string(), "Space");
+        assert_eq!(Key::parse("N").unwrap().code, KeyCode::Char('N'));
+        assert_eq!(Key::parse("-").unwrap().code, KeyCode::Char('-'));
+        assert_eq!(Key::parse("F5").unwrap().code, KeyCode::F(5));
+        assert!(Key::parse("Hyper-x").is_err());
+        // Shift comes with the character, however the terminal reports it.
+        assert_eq!(
+            Key::new(KeyCode::Char('N'), KeyModifiers::SHIFT),
+            Key::parse("N").unwrap()
+        );
+    }
+
+    #[test]
+    fn defaults_have_no_conflicts() {
+        assert_eq!(Keymap::issues(&KeyOverrides::new()), []);
+    }
+
+    #[test]
+    fn remapped_keys_translate_to_the_default_and_free_the_old_one() {
+        let map = Keymap::new(&overrides("list", &[("newest", &["x"])])).unwrap();
+        let x = map.translate(Scope::List, KeyCode::Char('x'), KeyModifiers::NONE);
+        assert_eq!(x, Some(Key::parse("n").unwrap()));
+        assert_eq!(
+            map.translate(Scope::List, KeyCode::Char('n'), KeyModifiers::NONE),
+            None
+        );
+        // Other views keep their own `n`; unbound keys pass through.
+        let pick_n = map.translate(Scope::Pick, KeyCode::Char('n'), KeyModifiers::NONE);
+        assert_eq!(pick_n, Some(Key::parse("n").unwrap()));
+        let other = map.translate(Scope::List, KeyCode::Char('y'), KeyModifiers::NONE);
+        assert_eq!(other, Some(Key::parse("y").unwrap()));
+        // Global bindings apply in every view.
+        let j = map.translate(Scope::Pick, KeyCode::Char('j'), KeyModifiers::NONE);
+        assert_eq!(j, Some(Key::parse("Down").unwrap()));
+
+        assert!(map
+            .help("List", Scope::List, true, &[])
+            .contains("| x, N newest |"));
+    }
+
+    #[test]
+    fn reference_lists_every_bound_action_with_a_label() {
+        let map = Keymap::new(&overrides("global", &[("half_page_up", &[])])).unwrap();
+        let global = map.reference(Scope::Global);
+        assert!(global.contains(&("Down/j".to_string(), "down".to_string())));
+        assert!(global.contains(&("?".to_string(), "all keys".to_string())));
+        assert!(global.iter().all(|(_, label)| label != "half page up"));
+        assert!(global.iter().any(|(_, label)| label == "half page down"));
+        for scope in Scope::ALL {
+            assert!(!map.reference(scope).is_empty(), "{}", scope.name());
+        }
+    }
+
+    #[test]
+    fn reports_unknown_names_bad_keys_and_conflicts() {
+        let mut o = overrides("list", &[("newest", &["c"]), ("nweest", &["x"])]);
+        o.insert("lsit".to_string(), BTreeMap::new());
+        o.insert(
+            "global".to_string(),
+            BTreeMap::from([("quit".to_string(), KeySpec::One("Hyper-q".to_string()))]),
+        );
+        let issues = Keymap::issues(&o);
+        let keys: Vec<&str> = issues.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "keys.global.quit",
+                "keys.list.nweest",
+                "keys.lsit",
+                "keys.list.newest"
+            ]
+        );
+        assert_eq!(issues[3].1, "c is also bound to list.current");
+
+        // Binding a view key to a global one would shadow it.
+        let shadow = overrides("pick", &[("view", &["j"])]);
+        assert_eq!(
+            Keymap::issues(&shadow)[0].1,
+            "j is also bound to global.down"
+        );
+        assert!(Keymap::new(&shadow).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+//! Detect and resolve Syncthing `*.sync-conflict-*` files.
+//!
+//! The `synctui-resolver` binary is a TUI on top of this library; the same building
+//! blocks can be used directly from automation:
+//!
+//! ```no_run
+//! use synctui_resolver::{apply, scan_conflicts, ResolutionPlan, Strategy};
+//! use std::path::Path;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let root = Path::new("/data/sync").canonicalize()?;
+//! let groups = scan_conflicts(&root, false)?;
+//! let plan = ResolutionPlan::builder(&root)
+//!     .groups(groups)
+//!     .strategy(Strategy::Newest)
+//!     .build()?;
+//! for outcome in apply(&plan) {
+//!     if let Err(e) = outcome.result {
+//!         eprintln!("{}: {e:#}", outcome.base_path.display());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod alerts;
+pub mod aliases;
+pub mod audit;
+pub mod blockdiff;
+pub mod cleanup;
+pub mod collate;
+pub mod config;
+pub mod conflictstats;
+pub mod diff;
+pub mod docmeta;
+pub mod filetype;
+pub mod fixture;
+pub mod fmt;
+pub mod hashcache;
+pub mod highlight;
+pub mod history;
+pub mod hooks;
+pub mod identical;
+pub mod keys;
+pub mod merge;
+pub mod model;
+pub mod ops;
+pub mod plan;
+pub mod prune;
+pub mod recency;
+pub mod rules;
+pub mod scan;
+pub mod scancache;
+pub mod session;
+pub mod settle;
+pub mod snapshots;
+pub mod space;
+pub mod stignore;
+pub mod syncthing;
+pub mod thumbnail;
+pub mod tree;
+pub mod trust;
+pub mod tutorial;
+pub mod undo;
+pub mod versions;
+pub mod watch;
+
+pub use model::{Candidate, ConflictGroup, DeletedOriginal, Strategy};
+pub use plan::{apply, spawn_apply, ApplyOptions, GroupOutcome, ResolutionPlan};
+pub use scan::scan_conflicts;
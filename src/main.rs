@@ -1,12 +1,74 @@
 use anyhow::Result;
 use clap::Parser;
+use std::io::IsTerminal;
+use std::process::ExitCode;
+use synctui_resolver::config::Config;
 
-mod model;
-mod ops;
-mod scan;
+mod commands;
+mod daemon;
 mod tui;
 
-fn main() -> Result<()> {
-    let args = tui::Args::parse();
-    tui::run(args)
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(commands::EXIT_ERROR)
+        }
+    }
+}
+
+/// Runs what the command line asks for, returning the exit code.
+fn run() -> Result<u8> {
+    let mut args = tui::Args::parse();
+    let quiet = args.quiet;
+    match args.command.take() {
+        Some(cmd) => commands::run(cmd).map(|()| 0),
+        None => match args.prune_archive {
+            Some(age) => {
+                let config = Config::load(args.config.as_deref())?;
+                commands::enable_audit(args.audit_log.as_deref(), &config)?;
+                let (hidden, apply, trash) = (args.include_hidden, args.apply, args.trash);
+                commands::run_prune(&args.path, hidden, age, apply, trash, quiet).map(|()| 0)
+            }
+            None if args.dedupe => {
+                let config = Config::load(args.config.as_deref())?;
+                commands::enable_audit(args.audit_log.as_deref(), &config)?;
+                let cache = commands::hash_cache_path(&config);
+                let (options, apply) = (args.scan_options(), args.apply);
+                commands::run_dedupe(&args.path, options, cache, apply, quiet).map(|()| 0)
+            }
+            None if args.stats => {
+                let aliases = commands::device_aliases(args.config.as_deref());
+                commands::run_conflict_stats(&args.path, args.scan_options(), &aliases).map(|()| 0)
+            }
+            None if !args.compare_strategies.is_empty() => {
+                let config = Config::load(args.config.as_deref())?;
+                let (options, strategies) = (args.scan_options(), &args.compare_strategies);
+                commands::run_compare_strategies(&args.path, options, strategies, &config)
+                    .map(|()| 0)
+            }
+            None if args.non_interactive => {
+                let history = commands::open_history(args.config.as_deref());
+                let aliases = commands::device_aliases(args.config.as_deref());
+                let options = args.scan_options();
+                commands::run_report(&args.path, options, history.as_ref(), &aliases, quiet)
+            }
+            None if !(std::io::stdin().is_terminal() && std::io::stdout().is_terminal()) => {
+                // Raw mode and escape codes would only garble a pipe or file.
+                if !quiet {
+                    eprintln!(
+                        "synctui-resolver: stdin/stdout is not a terminal; printing a scan \
+                         report instead of starting the TUI (pass --non-interactive to ask \
+                         for this)"
+                    );
+                }
+                let history = commands::open_history(args.config.as_deref());
+                let aliases = commands::device_aliases(args.config.as_deref());
+                let options = args.scan_options();
+                commands::run_report(&args.path, options, history.as_ref(), &aliases, quiet)
+            }
+            None => tui::run(args).map(|()| 0),
+        },
+    }
 }
@@ -0,0 +1,290 @@
+use crate::audit;
+use crate::diff::DiffAlgorithm;
+use crate::model::{Candidate, ConflictGroup};
+use crate::ops::{hash_bytes, unique_suffix_millis};
+use crate::plan::{apply_group, ApplyOptions};
+use anyhow::{anyhow, bail, Context, Result};
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Which side of a hunk the merged file keeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Take {
+    /// The original's lines.
+    Left,
+    /// The conflict's lines.
+    Right,
+    /// The original's lines, then the conflict's.
+    Both,
+}
+
+impl Take {
+    pub fn label(self) -> &'static str {
+        match self {
+            Take::Left => "left",
+            Take::Right => "right",
+            Take::Both => "both",
+        }
+    }
+}
+
+/// Lines that differ between the two files, each line with its line ending.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+    /// `None` until decided.
+    pub take: Option<Take>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    /// Lines both files share.
+    Same(Vec<String>),
+    Hunk(Hunk),
+}
+
+/// Two versions of a text file as shared lines and differing hunks, with a choice per
+/// hunk of which side the merged file keeps.
+#[derive(Clone, Debug)]
+pub struct Merge {
+    pub segments: Vec<Segment>,
+}
+
+impl Merge {
+    /// Splits `left` (the original) and `right` (a conflict) into shared lines and hunks;
+    /// a run of adjacent changes is one hunk.
+    pub fn new(left: &str, right: &str, algorithm: DiffAlgorithm) -> Self {
+        let l: Vec<&str> = left.split_inclusive('\n').collect();
+        let r: Vec<&str> = right.split_inclusive('\n').collect();
+        let algorithm = match algorithm {
+            DiffAlgorithm::Myers => Algorithm::Myers,
+            DiffAlgorithm::Patience => Algorithm::Patience,
+        };
+        let lines = |s: &[&str]| s.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        let mut segments: Vec<Segment> = Vec::new();
+        for op in capture_diff_slices(algorithm, &l, &r) {
+            let (tag, old, new) = op.as_tag_tuple();
+            match (tag, segments.last_mut()) {
+                (DiffTag::Equal, _) => segments.push(Segment::Same(lines(&l[old]))),
+                (_, Some(Segment::Hunk(h))) => {
+                    h.left.extend(lines(&l[old]));
+                    h.right.extend(lines(&r[new]));
+                }
+                _ => segments.push(Segment::Hunk(Hunk {
+                    left: lines(&l[old]),
+                    right: lines(&r[new]),
+                    take: None,
+                })),
+            }
+        }
+        Self { segments }
+    }
+
+    pub fn hunks(&self) -> impl Iterator<Item = &Hunk> {
+        self.segments.iter().filter_map(|s| match s {
+            Segment::Hunk(h) => Some(h),
+            Segment::Same(_) => None,
+        })
+    }
+
+    pub fn hunk_count(&self) -> usize {
+        self.hunks().count()
+    }
+
+    pub fn undecided(&self) -> usize {
+        self.hunks().filter(|h| h.take.is_none()).count()
+    }
+
+    /// Decides the hunk at index `hunk` (counting hunks only).
+    pub fn set(&mut self, hunk: usize, take: Take) {
+        let h = self
+            .segments
+            .iter_mut()
+            .filter_map(|s| match s {
+                Segment::Hunk(h) => Some(h),
+                Segment::Same(_) => None,
+            })
+            .nth(hunk);
+        if let Some(h) = h {
+            h.take = Some(take);
+        }
+    }
+
+    /// The merged text, once every hunk is decided. Taking both sides of a hunk whose
+    /// left side ends without a newline adds one between them.
+    pub fn result(&self) -> Option<String> {
+        let mut out = String::new();
+        for s in &self.segments {
+            match s {
+                Segment::Same(lines) => lines.iter().for_each(|l| out.push_str(l)),
+                Segment::Hunk(h) => {
+                    let left = h.left.concat();
+                    let right = h.right.concat();
+                    match h.take? {
+                        Take::Left => out.push_str(&left),
+                        Take::Right => out.push_str(&right),
+                        Take::Both => {
+                            out.push_str(&left);
+                            if !left.is_empty() && !left.ends_with('\n') && !right.is_empty() {
+                                out.push('\n');
+                            }
+                            out.push_str(&right);
+                        }
+                    }
+                }
+            }
+        }
+        Some(out)
+    }
+}
+
+/// Writes `text` as the group's file and archives the original and the conflict at
+/// `conflict` the way an apply archives losers (loser rules, remote archives and
+/// `verify` included); the group's other conflicts stay. The merge is first written
+/// next to the original, with its permissions, then moved into place. Returns the kept
+/// file's SHA-256 when verifying; a dry run only validates.
+pub fn write_merged(
+    root: &Path,
+    g: &ConflictGroup,
+    conflict: usize,
+    text: &str,
+    options: &ApplyOptions,
+) -> Result<Option<String>> {
+    let original = &g.candidates[0];
+    let theirs = g
+        .candidates
+        .get(conflict)
+        .filter(|_| conflict != 0)
+        .ok_or_else(|| anyhow!("bad conflict index {conflict}"))?;
+    if !original.exists {
+        bail!("the original {:?} no longer exists", original.path);
+    }
+    let name = g
+        .base_path
+        .file_name()
+        .ok_or_else(|| anyhow!("bad name"))?
+        .to_string_lossy();
+    let tmp = g
+        .base_path
+        .with_file_name(format!(".{name}.merged-{}", unique_suffix_millis()));
+    let merged = Candidate {
+        path: tmp.clone(),
+        exists: true,
+        is_original: false,
+        size: Some(text.len() as u64),
+        modified: Some(SystemTime::now()),
+        label: "merged".to_string(),
+        trust: None,
+    };
+    let group = ConflictGroup {
+        base_path: g.base_path.clone(),
+        candidates: vec![original.clone(), theirs.clone(), merged],
+        chosen: Some(2),
+        references: Vec::new(),
+    };
+    // The merge has no device or conflict date to rename by.
+    let options = ApplyOptions {
+        rename_template: None,
+        ..options.clone()
+    };
+    if !options.dry_run {
+        fs::write(&tmp, text).with_context(|| format!("write {tmp:?}"))?;
+        if let Ok(meta) = fs::metadata(&original.path) {
+            let _ = fs::set_permissions(&tmp, meta.permissions());
+        }
+        let hash = audit::enabled().then(|| hash_bytes(text.as_bytes()));
+        audit::record("write", &tmp, None, hash.as_deref())?;
+    }
+    let res = apply_group(root, &group, 2, &options);
+    if res.is_err() && tmp.exists() {
+        let _ = fs::remove_file(&tmp);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::scan_conflicts;
+    use tempfile::tempdir;
+
+    #[test]
+    fn hunks_merge_by_choice() {
+        let left = "title\nbuy milk\nbuy eggs\ncall mom\nend";
+        let right = "title\nbuy oat milk\nbuy eggs\ncall dad\nend";
+        let mut m = Merge::new(left, right, DiffAlgorithm::Myers);
+        assert_eq!(m.hunk_count(), 2);
+        assert_eq!(m.result(), None);
+        m.set(0, Take::Right);
+        m.set(1, Take::Both);
+        assert_eq!(m.undecided(), 0);
+        assert_eq!(
+            m.result().unwrap(),
+            "title\nbuy oat milk\nbuy eggs\ncall mom\ncall dad\nend"
+        );
+        m.set(0, Take::Left);
+        m.set(1, Take::Left);
+        assert_eq!(m.result().unwrap(), left);
+
+        // Both sides of a last line without a newline still end up on their own lines.
+        let mut m = Merge::new("a\nx", "a\ny", DiffAlgorithm::Myers);
+        m.set(0, Take::Both);
+        assert_eq!(m.result().unwrap(), "a\nx\ny");
+    }
+
+    #[test]
+    fn writing_a_merge_archives_both_inputs_and_keeps_other_conflicts() {
+        let td = tempdir().unwrap();
+        let root = td.path();
+        fs::write(root.join("notes.md"), "a\nb\n").unwrap();
+        let c1 = root.join("notes.md.sync-conflict-20240101-000000-AAA");
+        let c2 = root.join("notes.md.sync-conflict-20240102-000000-BBB");
+        fs::write(&c1, "a\nc\n").unwrap();
+        fs::write(&c2, "z\n").unwrap();
+        let g = scan_conflicts(root, true).unwrap().remove(0);
+        let conflict = g.candidates.iter().position(|c| c.path == c1).unwrap();
+
+        let dry = ApplyOptions {
+            dry_run: true,
+            ..ApplyOptions::default()
+        };
+        write_merged(root, &g, conflict, "a\nb\nc\n", &dry).unwrap();
+        assert_eq!(fs::read_to_string(root.join("notes.md")).unwrap(), "a\nb\n");
+        assert!(c1.exists());
+
+        write_merged(root, &g, conflict, "a\nb\nc\n", &ApplyOptions::default()).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("notes.md")).unwrap(),
+            "a\nb\nc\n"
+        );
+        assert!(!c1.exists() && c2.exists());
+        let archived: Vec<String> = fs::read_dir(root.join(".stconflict-archive"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            archived
+                .iter()
+                .filter(|n| n.starts_with("notes.md"))
+                .count(),
+            2,
+            "{archived:?}"
+        );
+        // No temporary file is left behind.
+        assert_eq!(
+            fs::read_dir(root)
+                .unwrap()
+                .filter(|e| e
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .contains("merged"))
+                .count(),
+            0
+        );
+    }
+}
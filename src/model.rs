@@ -1,5 +1,13 @@
+use crate::recency::Recency;
+use crate::scan::conflict_meta;
+use anyhow::{anyhow, Error};
 use std::path::PathBuf;
-use std::time::SystemTime;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// How far a conflict's mtime may stray from the timestamp in its name before the group's
+/// mtimes are treated as unreliable.
+pub const DEFAULT_SKEW_THRESHOLD: Duration = Duration::from_secs(24 * 60 * 60);
 
 #[derive(Clone, Debug)]
 pub struct Candidate {
@@ -9,6 +17,140 @@ pub struct Candidate {
     pub size: Option<u64>,
     pub modified: Option<SystemTime>,
     pub label: String,
+    /// Rank in the configured device trust list (higher is more trusted); `None` for the
+    /// original and unlisted devices.
+    pub trust: Option<usize>,
+}
+
+impl Candidate {
+    /// Gap between the mtime and the timestamp in the conflict name, in either direction.
+    /// `None` for the original and when either time is unknown.
+    pub fn clock_skew(&self) -> Option<Duration> {
+        let named = conflict_meta(&self.path)?.timestamp()?;
+        let modified = self.modified?;
+        Some(
+            named
+                .duration_since(modified)
+                .or_else(|_| modified.duration_since(named))
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// A rule for choosing which candidate of a group to keep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// The original (base) file.
+    Current,
+    Newest,
+    Oldest,
+    /// The most trusted device's version, newest first among equals.
+    Trusted,
+    /// The biggest existing version, newest first among equals.
+    Largest,
+}
+
+impl Strategy {
+    pub fn label(self) -> &'static str {
+        match self {
+            Strategy::Current => "current",
+            Strategy::Newest => "newest",
+            Strategy::Oldest => "oldest",
+            Strategy::Trusted => "trusted",
+            Strategy::Largest => "largest",
+        }
+    }
+
+    /// Decides by mtime alone, so a skewed clock makes it keep the wrong file.
+    pub fn mtime_only(self) -> bool {
+        matches!(self, Strategy::Newest | Strategy::Oldest)
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        [
+            Strategy::Current,
+            Strategy::Newest,
+            Strategy::Oldest,
+            Strategy::Trusted,
+            Strategy::Largest,
+        ]
+        .into_iter()
+        .find(|s| s.label() == label)
+    }
+
+    /// Candidate index this strategy keeps. Time-based strategies fall back to the
+    /// original when no candidate has an mtime.
+    pub fn choose(self, g: &ConflictGroup) -> Option<usize> {
+        match self {
+            Strategy::Current => Some(0),
+            Strategy::Newest => g.newest_idx().or(Some(0)),
+            Strategy::Oldest => g.oldest_idx().or(Some(0)),
+            Strategy::Trusted => g.trusted_idx().or(Some(0)),
+            Strategy::Largest => g.largest_idx().or(Some(0)),
+        }
+    }
+
+    /// Like [`Strategy::choose`], with `newest` and `oldest` comparing the versions by
+    /// `by` instead of their mtime.
+    pub fn choose_by(self, g: &ConflictGroup, by: Recency) -> Option<usize> {
+        match self {
+            _ if by == Recency::Mtime => self.choose(g),
+            Strategy::Newest => by.newest_idx(g).or(Some(0)),
+            Strategy::Oldest => by.oldest_idx(g).or(Some(0)),
+            _ => self.choose(g),
+        }
+    }
+}
+
+/// What to do with a group whose original file was deleted while conflicts remain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeletedOriginal {
+    /// Move the newest conflict to the base path, undoing the deletion.
+    Promote,
+    /// Accept the deletion and archive every conflict.
+    Discard,
+}
+
+impl DeletedOriginal {
+    pub fn label(self) -> &'static str {
+        match self {
+            DeletedOriginal::Promote => "promote",
+            DeletedOriginal::Discard => "discard",
+        }
+    }
+
+    /// Candidate index to keep; keeping the (missing) original discards the conflicts.
+    /// `None` for groups whose original still exists, or when promoting finds no conflict.
+    pub fn choose(self, g: &ConflictGroup) -> Option<usize> {
+        if !g.original_deleted() {
+            return None;
+        }
+        match self {
+            DeletedOriginal::Promote => g.newest_idx().filter(|&i| i != 0),
+            DeletedOriginal::Discard => Some(0),
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Strategy::from_label(s).ok_or_else(|| {
+            anyhow!("expected `current`, `newest`, `oldest`, `trusted` or `largest`, got {s:?}")
+        })
+    }
+}
+
+impl FromStr for DeletedOriginal {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        [DeletedOriginal::Promote, DeletedOriginal::Discard]
+            .into_iter()
+            .find(|p| p.label() == s)
+            .ok_or_else(|| anyhow!("expected `promote` or `discard`, got {s:?}"))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -16,9 +158,50 @@ pub struct ConflictGroup {
     pub base_path: PathBuf,
     pub candidates: Vec<Candidate>,
     pub chosen: Option<usize>,
+    /// Read-only versions from outside the group (e.g. snapshots), offered for diffing
+    /// only. They are never chosen, moved, or archived.
+    pub references: Vec<Candidate>,
 }
 
 impl ConflictGroup {
+    /// The base file is gone but conflict copies remain (Syncthing's "no-orig" case).
+    pub fn original_deleted(&self) -> bool {
+        self.candidates
+            .first()
+            .is_some_and(|c| c.is_original && !c.exists)
+    }
+
+    /// Keeping `idx` keeps the deleted original: every conflict is archived and nothing is
+    /// left at the base path.
+    pub fn discards(&self, idx: usize) -> bool {
+        idx == 0 && self.original_deleted()
+    }
+
+    /// The largest gap between a conflict's name timestamp and its mtime, if it exceeds
+    /// `threshold`.
+    pub fn clock_skew(&self, threshold: Duration) -> Option<Duration> {
+        self.candidates
+            .iter()
+            .filter_map(Candidate::clock_skew)
+            .max()
+            .filter(|&skew| skew > threshold)
+    }
+
+    /// Rows shown in the pick list: candidates first, then references.
+    pub fn row_count(&self) -> usize {
+        self.candidates.len() + self.references.len()
+    }
+
+    pub fn row(&self, i: usize) -> Option<&Candidate> {
+        self.candidates
+            .get(i)
+            .or_else(|| self.references.get(i.checked_sub(self.candidates.len())?))
+    }
+
+    pub fn is_reference_row(&self, i: usize) -> bool {
+        i >= self.candidates.len() && i < self.row_count()
+    }
+
     pub fn newest_idx(&self) -> Option<usize> {
         self.candidates
             .iter()
@@ -28,6 +211,27 @@ impl ConflictGroup {
             .map(|(i, _)| i)
     }
 
+    /// Highest trust rank wins; unranked candidates lose to ranked ones and ties go to the
+    /// newest mtime.
+    pub fn trusted_idx(&self) -> Option<usize> {
+        self.candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.exists)
+            .max_by_key(|(_, c)| (c.trust, c.modified))
+            .map(|(i, _)| i)
+    }
+
+    /// Biggest existing candidate; ties go to the newest mtime.
+    pub fn largest_idx(&self) -> Option<usize> {
+        self.candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.exists)
+            .max_by_key(|(_, c)| (c.size, c.modified))
+            .map(|(i, _)| i)
+    }
+
     pub fn oldest_idx(&self) -> Option<usize> {
         self.candidates
             .iter()
@@ -52,6 +256,7 @@ mod tests {
             size: None,
             modified: Some(UNIX_EPOCH + Duration::from_secs(secs)),
             label: label.to_string(),
+            trust: None,
         }
     }
 
@@ -61,8 +266,122 @@ mod tests {
             base_path: PathBuf::from("base"),
             candidates: vec![cand("a", 10), cand("b", 5), cand("c", 99)],
             chosen: None,
+            references: vec![cand("snap", 1000)],
         };
         assert_eq!(g.oldest_idx(), Some(1));
         assert_eq!(g.newest_idx(), Some(2));
+
+        // References never take part in picks, only in the row list.
+        assert_eq!(g.row_count(), 4);
+        assert_eq!(g.row(3).map(|c| c.label.as_str()), Some("snap"));
+        assert!(g.is_reference_row(3));
+        assert!(!g.is_reference_row(2));
+        assert!(g.row(4).is_none());
+    }
+
+    #[test]
+    fn deleted_original_promotes_newest_or_discards() {
+        let mut g = ConflictGroup {
+            base_path: PathBuf::from("base"),
+            candidates: vec![cand("orig", 50), cand("a", 10), cand("b", 20)],
+            chosen: None,
+            references: Vec::new(),
+        };
+        assert_eq!(DeletedOriginal::Promote.choose(&g), None);
+
+        g.candidates[0].exists = false;
+        g.candidates[0].modified = None;
+        g.candidates[0].is_original = true;
+        assert!(g.original_deleted());
+        assert_eq!(DeletedOriginal::Promote.choose(&g), Some(2));
+        assert_eq!(DeletedOriginal::Discard.choose(&g), Some(0));
+        assert!(g.discards(0));
+        assert!(!g.discards(2));
+        assert_eq!(
+            "discard".parse::<DeletedOriginal>().unwrap(),
+            DeletedOriginal::Discard
+        );
+        assert!("keep".parse::<DeletedOriginal>().is_err());
+    }
+
+    #[test]
+    fn clock_skew_compares_name_stamp_and_mtime() {
+        let named = |secs: u64| {
+            let stamp = conflict_meta(std::path::Path::new("f.sync-conflict-20240101-120000-DEV"))
+                .and_then(|m| m.timestamp())
+                .unwrap();
+            Candidate {
+                path: PathBuf::from("f.sync-conflict-20240101-120000-DEV"),
+                modified: Some(stamp - Duration::from_secs(3600) + Duration::from_secs(secs)),
+                ..cand("c", 0)
+            }
+        };
+        // An hour earlier, and two days later than the name says.
+        assert_eq!(named(0).clock_skew(), Some(Duration::from_secs(3600)));
+        let late = named(3600 + 2 * 86400);
+        assert_eq!(late.clock_skew(), Some(Duration::from_secs(2 * 86400)));
+        assert_eq!(cand("orig", 5).clock_skew(), None);
+
+        let mut g = ConflictGroup {
+            base_path: PathBuf::from("f"),
+            candidates: vec![cand("orig", 5), named(0)],
+            chosen: None,
+            references: Vec::new(),
+        };
+        assert_eq!(g.clock_skew(DEFAULT_SKEW_THRESHOLD), None);
+        g.candidates.push(late);
+        assert_eq!(
+            g.clock_skew(DEFAULT_SKEW_THRESHOLD),
+            Some(Duration::from_secs(2 * 86400))
+        );
+        assert!(Strategy::Newest.mtime_only() && !Strategy::Trusted.mtime_only());
+    }
+
+    #[test]
+    fn trusted_idx_prefers_rank_then_newest() {
+        let ranked = |label: &str, secs: u64, trust: Option<usize>| Candidate {
+            trust,
+            ..cand(label, secs)
+        };
+        let mut g = ConflictGroup {
+            base_path: PathBuf::from("base"),
+            candidates: vec![
+                ranked("orig", 500, None),
+                ranked("phone", 400, Some(0)),
+                ranked("laptop-old", 10, Some(2)),
+                ranked("laptop-new", 20, Some(2)),
+            ],
+            chosen: None,
+            references: Vec::new(),
+        };
+        assert_eq!(Strategy::Trusted.choose(&g), Some(3));
+
+        for c in &mut g.candidates {
+            c.trust = None;
+        }
+        assert_eq!(Strategy::Trusted.choose(&g), Some(0));
+    }
+
+    #[test]
+    fn largest_idx_prefers_size_then_newest() {
+        let sized = |label: &str, secs: u64, size: u64| Candidate {
+            size: Some(size),
+            ..cand(label, secs)
+        };
+        let mut g = ConflictGroup {
+            base_path: PathBuf::from("base"),
+            candidates: vec![
+                sized("orig", 500, 10),
+                sized("old", 10, 30),
+                sized("new", 20, 30),
+            ],
+            chosen: None,
+            references: Vec::new(),
+        };
+        assert_eq!(Strategy::Largest.choose(&g), Some(2));
+        // Missing files can't be kept, however big they were.
+        g.candidates[2].exists = false;
+        assert_eq!(Strategy::Largest.choose(&g), Some(1));
+        assert_eq!(Strategy::from_label("largest"), Some(Strategy::Largest));
     }
 }
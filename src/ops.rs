@@ -1,26 +1,422 @@
-use anyhow::{anyhow, Context, Result};
-use std::fs;
-use std::path::{Path, PathBuf};
+use crate::audit;
+use crate::cleanup::VERSIONS_DIR;
+use anyhow::{anyhow, bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Name of the per-archive index file recording where each archived file went.
+pub const ARCHIVE_INDEX: &str = "index.tsv";
+
+/// Directory, next to each base file, that non-chosen versions are moved into.
+pub const ARCHIVE_DIR: &str = ".stconflict-archive";
+
+/// Where non-chosen versions are sent on apply.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ArchiveTarget {
+    /// `.stconflict-archive` next to the base file.
+    #[default]
+    Local,
+    /// An rclone remote path such as `backup:conflicts`.
+    Rclone(String),
+}
+
+impl ArchiveTarget {
+    /// Parses an `--archive-remote` spec of the form `rclone:<remote>:<path>`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let remote = spec.strip_prefix("rclone:").ok_or_else(|| {
+            anyhow!("unsupported archive remote {spec:?} (expected rclone:<remote>:<path>)")
+        })?;
+        if !remote.contains(':') {
+            bail!("rclone remote {remote:?} is missing the '<remote>:' prefix");
+        }
+        let trimmed = remote.trim_end_matches('/');
+        let remote = if trimmed.ends_with(':') || trimmed.is_empty() {
+            remote
+        } else {
+            trimmed
+        };
+        Ok(Self::Rclone(remote.to_string()))
+    }
+}
+
 pub fn ensure_dir(path: &Path) -> Result<()> {
-    fs::create_dir_all(path).with_context(|| format!("create dir {path:?}"))
+    fs::create_dir_all(long_path(path)).with_context(|| format!("create dir {path:?}"))
+}
+
+/// `path` as an extended-length (`\\?\`) path on Windows, so moves and new directories
+/// work past `MAX_PATH`, e.g. deep in a `node_modules` tree. Relative, drive-relative and
+/// already-verbatim paths, and every path on other systems, are returned unchanged.
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    #[cfg(windows)]
+    if let Some(long) = windows_long_path(path) {
+        return Cow::Owned(long);
+    }
+    Cow::Borrowed(path)
+}
+
+/// `C:\a\..\b` as `\\?\C:\b` and `\\server\share\a` as `\\?\UNC\server\share\a`. Verbatim
+/// paths aren't normalized by Windows, so `.` and `..` are resolved here.
+#[cfg(windows)]
+fn windows_long_path(path: &Path) -> Option<PathBuf> {
+    use std::ffi::{OsStr, OsString};
+    use std::path::Prefix;
+    let mut components = path.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return None;
+    };
+    let mut out = OsString::from(r"\\?\");
+    match prefix.kind() {
+        Prefix::Disk(drive) => out.push(format!("{}:", drive as char)),
+        Prefix::UNC(server, share) => {
+            out.push(r"UNC\");
+            out.push(server);
+            out.push(r"\");
+            out.push(share);
+        }
+        _ => return None,
+    }
+    if components.next() != Some(Component::RootDir) {
+        return None;
+    }
+    let mut parts: Vec<&OsStr> = Vec::new();
+    for c in components {
+        match c {
+            Component::Normal(part) => parts.push(part),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            _ => {}
+        }
+    }
+    if parts.is_empty() {
+        out.push(r"\");
+    }
+    for part in parts {
+        out.push(r"\");
+        out.push(part);
+    }
+    Some(PathBuf::from(out))
 }
 
 pub fn move_file(from: &Path, to: &Path) -> Result<()> {
+    move_file_verified(from, to, false)
+}
+
+/// Like [`move_file`]; with `verify`, a copy (across file systems) must hash the same as
+/// its source before the source is removed. Recorded in the [`audit`] log.
+pub fn move_file_verified(from: &Path, to: &Path, verify: bool) -> Result<()> {
     if let Some(parent) = to.parent() {
         ensure_dir(parent)?;
     }
+    let hash = audit_hash(from)?;
+    move_unaudited(from, to, verify)?;
+    audit::record(
+        "move",
+        from,
+        Some(&to.display().to_string()),
+        hash.as_deref(),
+    )
+}
+
+fn move_unaudited(from: &Path, to: &Path, verify: bool) -> Result<()> {
+    let (from, to) = (long_path(from), long_path(to));
+    let (from, to) = (from.as_ref(), to.as_ref());
     match fs::rename(from, to) {
         Ok(_) => Ok(()),
+        Err(_) if is_symlink(from) => {
+            // Copying would turn the link into a copy of its target.
+            copy_symlink(from, to)?;
+            fs::remove_file(from).with_context(|| format!("remove {:?}", from))?;
+            Ok(())
+        }
         Err(_) => {
-            fs::copy(from, to).with_context(|| format!("copy {:?} -> {:?}", from, to))?;
+            copy_preserving(from, to)?;
+            if verify {
+                verify_copy(from, to)?;
+            }
             fs::remove_file(from).with_context(|| format!("remove {:?}", from))?;
             Ok(())
         }
     }
 }
 
+/// The SHA-256 the audit log records for `path`: none when not auditing or for a
+/// symlink.
+fn audit_hash(path: &Path) -> Result<Option<String>> {
+    if !audit::enabled() || is_symlink(path) {
+        return Ok(None);
+    }
+    hash_file(path).map(Some)
+}
+
+fn is_symlink(path: &Path) -> bool {
+    path.symlink_metadata()
+        .is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// Recreates the symlink `from` at `to`, pointing at the same target.
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> Result<()> {
+    let target = fs::read_link(from).with_context(|| format!("read link {from:?}"))?;
+    std::os::unix::fs::symlink(&target, to).with_context(|| format!("link {to:?} -> {target:?}"))
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, _to: &Path) -> Result<()> {
+    bail!("cannot move symlink {from:?} across file systems")
+}
+
+/// The outermost symlink among `path` and its ancestors below `root`, if any. Moving or
+/// replacing such a path would act on the link (or wherever it points) rather than on a
+/// file inside the folder.
+pub fn symlink_below(root: &Path, path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .take_while(|a| a.starts_with(root) && *a != root)
+        .filter(|a| is_symlink(a))
+        .last()
+        .map(Path::to_path_buf)
+}
+
+/// Fails, removing `to`, unless both files have the same SHA-256.
+fn verify_copy(from: &Path, to: &Path) -> Result<()> {
+    let want = hash_file(from)?;
+    let got = hash_file(to)?;
+    if want != got {
+        let _ = fs::remove_file(to);
+        bail!("copy of {from:?} to {to:?} is corrupt (sha256 {got}, expected {want})");
+    }
+    Ok(())
+}
+
+/// Copies `from` to `to` for a move across file systems: the contents and mode bits, the
+/// access and modification times, and on Unix the owner and (on Linux and macOS) extended
+/// attributes as far as permissions allow. Fails, removing the copy, if the copy's size
+/// differs from the source's or its metadata can't be set.
+pub fn copy_preserving(from: &Path, to: &Path) -> Result<()> {
+    let meta = fs::metadata(from).with_context(|| format!("stat {from:?}"))?;
+    fs::copy(from, to).with_context(|| format!("copy {:?} -> {:?}", from, to))?;
+    let result = finish_copy(from, to, &meta);
+    if result.is_err() {
+        let _ = fs::remove_file(to);
+    }
+    result
+}
+
+/// Checks the size of a fresh copy and gives it `meta`'s attributes, times, owner and
+/// mode. `fs::copy` already gave it the source's mode, which may be read-only, so the
+/// copy is made writable for the attributes and times and its mode is restored last.
+fn finish_copy(from: &Path, to: &Path, meta: &fs::Metadata) -> Result<()> {
+    let copied = fs::metadata(to).map(|m| m.len()).unwrap_or(0);
+    if copied != meta.len() {
+        bail!(
+            "copy of {from:?} to {to:?} is {copied} bytes, expected {}",
+            meta.len()
+        );
+    }
+    let mut writable = meta.permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        writable.set_mode(writable.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    writable.set_readonly(false);
+    fs::set_permissions(to, writable).with_context(|| format!("set permissions of {to:?}"))?;
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    copy_xattrs(from, to);
+    let mut times = fs::FileTimes::new();
+    if let Ok(t) = meta.modified() {
+        times = times.set_modified(t);
+    }
+    if let Ok(t) = meta.accessed() {
+        times = times.set_accessed(t);
+    }
+    OpenOptions::new()
+        .write(true)
+        .open(to)
+        .and_then(|f| f.set_times(times))
+        .with_context(|| format!("set times of {to:?}"))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{lchown, MetadataExt};
+        // Only root (or a member of the group) may do this; otherwise the copy keeps our
+        // ownership. chown clears setuid/setgid, so the mode is restored afterwards.
+        let _ = lchown(to, Some(meta.uid()), Some(meta.gid()))
+            .or_else(|_| lchown(to, None, Some(meta.gid())));
+    }
+    fs::set_permissions(to, meta.permissions())
+        .with_context(|| format!("set permissions of {to:?}"))
+}
+
+/// Copies every extended attribute the destination accepts; attributes that need
+/// privileges we lack (`security.*`, `trusted.*` on Linux) or that the target file system
+/// doesn't support are skipped. On macOS this carries the Finder tags and info and the
+/// resource fork (`com.apple.ResourceFork`), which are stored as attributes there.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn copy_xattrs(from: &Path, to: &Path) {
+    for name in xattr::list(from) {
+        if let Some(value) = xattr::get(from, &name) {
+            xattr::set(to, &name, &value);
+        }
+    }
+}
+
+/// The attribute holding a file's user-visible tags: Finder tags on macOS, the
+/// freedesktop `user.xdg.tags` (Dolphin and friends) on Linux.
+#[cfg(target_os = "macos")]
+const TAGS_XATTR: Option<&str> = Some("com.apple.metadata:_kMDItemUserTags");
+#[cfg(target_os = "linux")]
+const TAGS_XATTR: Option<&str> = Some("user.xdg.tags");
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+const TAGS_XATTR: Option<&str> = None;
+
+/// The raw tags attribute of `path` (see [`TAGS_XATTR`]), if it has one.
+pub fn file_tags(path: &Path) -> Option<Vec<u8>> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        let name = std::ffi::CString::new(TAGS_XATTR?).ok()?;
+        xattr::get(path, &name)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        let _ = (path, TAGS_XATTR);
+        None
+    }
+}
+
+/// Gives `path` the tags read with [`file_tags`] unless it has tags of its own, so a
+/// conflict promoted over a tagged original keeps showing its tags. Best effort.
+pub fn carry_tags(path: &Path, tags: &[u8]) {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    if file_tags(path).is_none() {
+        if let Some(name) = TAGS_XATTR.and_then(|n| std::ffi::CString::new(n).ok()) {
+            xattr::set(path, &name, tags);
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    let _ = (path, tags);
+}
+
+/// No-follow extended attribute calls, whose signatures differ between Linux and macOS.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod xattr {
+    use libc::{c_char, c_void, size_t, ssize_t};
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+
+    fn c_path(path: &Path) -> Option<CString> {
+        CString::new(path.as_os_str().as_bytes()).ok()
+    }
+
+    // SAFETY (all calls in this module): the path and name pointers are valid
+    // NUL-terminated strings and each buffer pointer is valid for the length passed with
+    // it.
+    #[cfg(target_os = "linux")]
+    unsafe fn list_raw(path: &CStr, buf: *mut c_char, size: size_t) -> ssize_t {
+        libc::llistxattr(path.as_ptr(), buf, size)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn list_raw(path: &CStr, buf: *mut c_char, size: size_t) -> ssize_t {
+        libc::listxattr(path.as_ptr(), buf, size, libc::XATTR_NOFOLLOW)
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn get_raw(path: &CStr, name: &CStr, buf: *mut c_void, size: size_t) -> ssize_t {
+        libc::lgetxattr(path.as_ptr(), name.as_ptr(), buf, size)
+    }
+
+    /// Position 0 with the full size reads a resource fork whole.
+    #[cfg(target_os = "macos")]
+    unsafe fn get_raw(path: &CStr, name: &CStr, buf: *mut c_void, size: size_t) -> ssize_t {
+        libc::getxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            buf,
+            size,
+            0,
+            libc::XATTR_NOFOLLOW,
+        )
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn set_raw(path: &CStr, name: &CStr, value: &[u8]) -> libc::c_int {
+        libc::lsetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn set_raw(path: &CStr, name: &CStr, value: &[u8]) -> libc::c_int {
+        libc::setxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+            libc::XATTR_NOFOLLOW,
+        )
+    }
+
+    pub fn list(path: &Path) -> Vec<CString> {
+        let Some(p) = c_path(path) else {
+            return Vec::new();
+        };
+        let len = unsafe { list_raw(&p, ptr::null_mut(), 0) };
+        if len <= 0 {
+            return Vec::new();
+        }
+        let mut names = vec![0u8; len as usize];
+        let len = unsafe { list_raw(&p, names.as_mut_ptr().cast(), names.len()) };
+        if len <= 0 {
+            return Vec::new();
+        }
+        names.truncate(len as usize);
+        names
+            .split(|&b| b == 0)
+            .filter(|n| !n.is_empty())
+            .filter_map(|n| CString::new(n).ok())
+            .collect()
+    }
+
+    pub fn get(path: &Path, name: &CStr) -> Option<Vec<u8>> {
+        let p = c_path(path)?;
+        let size = unsafe { get_raw(&p, name, ptr::null_mut(), 0) };
+        if size < 0 {
+            return None;
+        }
+        let mut value = vec![0u8; size as usize];
+        let size = unsafe { get_raw(&p, name, value.as_mut_ptr().cast(), value.len()) };
+        if size < 0 {
+            return None;
+        }
+        value.truncate(size as usize);
+        Some(value)
+    }
+
+    /// Sets an attribute, quietly giving up where it isn't allowed or supported.
+    pub fn set(path: &Path, name: &CStr, value: &[u8]) {
+        if let Some(p) = c_path(path) {
+            unsafe { set_raw(&p, name, value) };
+        }
+    }
+}
+
 pub fn unique_suffix_millis() -> u128 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -34,13 +430,402 @@ pub fn unique_name(base: &str) -> String {
 
 pub fn archive_dir_for(base_path: &Path) -> Result<PathBuf> {
     let parent = base_path.parent().ok_or_else(|| anyhow!("no parent"))?;
-    Ok(parent.join(".stconflict-archive"))
+    Ok(parent.join(ARCHIVE_DIR))
+}
+
+/// Values available to `--rename-template`.
+pub struct RenameVars<'a> {
+    pub base_name: &'a str,
+    pub device: &'a str,
+    pub date: &'a str,
+    pub time: &'a str,
+}
+
+/// Renders a kept-file name template. Placeholders: `{name}` (base file name), `{stem}`,
+/// `{ext}` (including the dot), `{device}`, `{date}` (YYYYMMDD) and `{time}` (HHMMSS).
+pub fn render_rename_template(template: &str, vars: &RenameVars) -> Result<String> {
+    let (stem, ext) = match vars.base_name.rfind('.') {
+        Some(i) if i > 0 => vars.base_name.split_at(i),
+        _ => (vars.base_name, ""),
+    };
+
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| anyhow!("unclosed '{{' in rename template {template:?}"))?;
+        let key = &rest[open + 1..open + close];
+        out.push_str(match key {
+            "name" => vars.base_name,
+            "stem" => stem,
+            "ext" => ext,
+            "device" => vars.device,
+            "date" => vars.date,
+            "time" => vars.time,
+            other => bail!("unknown placeholder {{{other}}} in rename template"),
+        });
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+
+    if out.is_empty() || out == "." || out == ".." || out.contains(['/', '\\']) {
+        bail!("rename template {template:?} produced invalid file name {out:?}");
+    }
+    Ok(out)
+}
+
+/// Resolves `.` and `..` components without touching the filesystem.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push(c);
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Path sandbox for externally supplied paths: confirms `path` (absolute, or relative to
+/// `root`) stays under `root` once `..` components and symlinks in its existing ancestors
+/// are resolved. Returns the resolved path.
+pub fn ensure_within(root: &Path, path: &Path) -> Result<PathBuf> {
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("resolve root {root:?}"))?;
+    let joined = normalize_lexically(&root.join(path));
+    let existing = joined
+        .ancestors()
+        .find(|a| a.exists())
+        .ok_or_else(|| anyhow!("no existing ancestor for {joined:?}"))?;
+    let mut resolved = existing
+        .canonicalize()
+        .with_context(|| format!("resolve {existing:?}"))?;
+    // Joining an empty rest would add a trailing slash, which renames refuse for files.
+    match joined.strip_prefix(existing) {
+        Ok(rest) if !rest.as_os_str().is_empty() => resolved.push(rest),
+        _ => {}
+    }
+    if !resolved.starts_with(&root) {
+        bail!("{path:?} resolves outside of {root:?}");
+    }
+    Ok(resolved)
+}
+
+/// Refuses `path` (resolved as by [`ensure_within`]) if it lies in a `.stconflict-archive`
+/// or `.stversions` under `root`: copies kept there are never originals, versions to keep
+/// or promotion targets, however a plan came to name them.
+pub fn ensure_not_archived(root: &Path, path: &Path) -> Result<()> {
+    let resolved = ensure_within(root, path)?;
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("resolve root {root:?}"))?;
+    let rel = resolved.strip_prefix(&root).unwrap_or(&resolved);
+    if let Some(dir) = rel
+        .components()
+        .filter_map(|c| c.as_os_str().to_str())
+        .find(|&c| c == ARCHIVE_DIR || c == VERSIONS_DIR)
+    {
+        bail!("{path:?} is inside a {dir} folder; archived copies can't be resolved");
+    }
+    Ok(())
+}
+
+/// Checks that `path` can be written (or, if it does not exist yet, that its nearest
+/// existing ancestor can). Catches read-only mounts and files owned by someone else.
+pub fn ensure_writable(path: &Path) -> Result<()> {
+    let existing = path
+        .ancestors()
+        .find(|a| a.symlink_metadata().is_ok())
+        .ok_or_else(|| anyhow!("no existing ancestor for {path:?}"))?;
+    if read_only_mount(existing) {
+        bail!("{existing:?} is on a read-only mount");
+    }
+    check_write_access(existing).with_context(|| format!("{existing:?} is not writable"))
+}
+
+/// Whether the file system holding `path` is mounted read-only (e.g. an NFS export
+/// mounted `ro`), which no permission change can fix.
+#[cfg(unix)]
+pub fn read_only_mount(path: &Path) -> bool {
+    // The field widths differ between platforms.
+    #[allow(clippy::useless_conversion)]
+    statvfs(path).is_ok_and(|s| u64::from(s.f_flag) & u64::from(libc::ST_RDONLY) != 0)
+}
+
+#[cfg(not(unix))]
+pub fn read_only_mount(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn check_write_access(path: &Path) -> Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `c_path` is a valid NUL-terminated string for the duration of the call.
+    if unsafe { libc::access(c_path.as_ptr(), libc::W_OK) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_write_access(path: &Path) -> Result<()> {
+    if fs::metadata(path)?.permissions().readonly() {
+        bail!("read-only");
+    }
+    Ok(())
+}
+
+/// Bytes an unprivileged user can still write on the file system holding `path`.
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Result<u64> {
+    let stat = statvfs(path)?;
+    // The field widths differ between platforms.
+    #[allow(clippy::useless_conversion)]
+    Ok(u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)))
+}
+
+#[cfg(unix)]
+fn statvfs(path: &Path) -> Result<libc::statvfs> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `c_path` is NUL-terminated and `stat` is a plain struct statvfs fills in.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error()).with_context(|| format!("statvfs {path:?}"));
+    }
+    Ok(stat)
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> Result<u64> {
+    bail!("free space can only be checked on Unix")
+}
+
+/// Who is resolving conflicts, recorded with every history entry and archive index line
+/// so admins sharing a server can tell whose apply archived what.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Actor {
+    /// Login name; under `sudo`, the user who ran it rather than root.
+    pub user: String,
+    pub host: String,
+    /// This build's version.
+    pub version: String,
+}
+
+impl Actor {
+    /// The actor of this process, looked up once.
+    pub fn current() -> &'static Actor {
+        static CURRENT: OnceLock<Actor> = OnceLock::new();
+        CURRENT.get_or_init(|| Actor::from_env(|var| std::env::var(var).ok(), hostname()))
+    }
+
+    fn from_env(env: impl Fn(&str) -> Option<String>, host: Option<String>) -> Actor {
+        let user = ["SUDO_USER", "USER", "USERNAME", "LOGNAME"]
+            .into_iter()
+            .find_map(|var| env(var).filter(|u| !u.is_empty()))
+            .unwrap_or_else(|| "unknown".to_string());
+        Actor {
+            user,
+            host: host
+                .or_else(|| env("COMPUTERNAME"))
+                .unwrap_or_else(|| "unknown".to_string()),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for its length, and gethostname NUL-terminates within it
+    // or truncates; the last byte stays 0 either way.
+    let ok = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len() - 1) } == 0;
+    let len = buf.iter().position(|&b| b == 0)?;
+    (ok && len > 0).then(|| String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    None
+}
+
+/// True when running with an effective uid of 0 (always false off Unix).
+pub fn running_as_root() -> bool {
+    #[cfg(unix)]
+    {
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        unsafe { libc::geteuid() == 0 }
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// Gives `path` the owner and group of `like` (typically the directory it lives in), so
+/// files created or moved while running as root stay usable by that directory's owner.
+/// Does nothing off Unix.
+pub fn chown_like(path: &Path, like: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{lchown, MetadataExt};
+
+        let meta = fs::metadata(like).with_context(|| format!("stat {like:?}"))?;
+        lchown(path, Some(meta.uid()), Some(meta.gid()))
+            .with_context(|| format!("chown {path:?}"))?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, like);
+    Ok(())
+}
+
+/// Resolves a user-typed path: `~/` expands to the home directory and relative paths
+/// are taken relative to `base`.
+pub fn expand_user_path(input: &str, base: &Path) -> PathBuf {
+    let input = input.trim();
+    let p = match input
+        .strip_prefix("~/")
+        .or_else(|| input.strip_prefix("~\\"))
+    {
+        Some(rest) => match dirs::home_dir() {
+            Some(home) => home.join(rest),
+            None => PathBuf::from(input),
+        },
+        None => PathBuf::from(input),
+    };
+    if p.is_absolute() {
+        p
+    } else {
+        base.join(p)
+    }
+}
+
+/// Builds the rclone destination for an archived file, mirroring `rel_dir` under the remote.
+pub fn remote_archive_dest(remote: &str, rel_dir: &Path, name: &str) -> String {
+    let mut dest = remote.to_string();
+    let parts = rel_dir
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(p) => Some(p.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .chain(std::iter::once(name.to_string()));
+    for part in parts {
+        if !dest.ends_with(':') && !dest.ends_with('/') {
+            dest.push('/');
+        }
+        dest.push_str(&part);
+    }
+    dest
+}
+
+fn rclone_moveto_command(from: &Path, dest: &str) -> Command {
+    let mut c = Command::new("rclone");
+    c.arg("moveto").arg(from).arg(dest);
+    c
+}
+
+/// Moves a local file to an rclone remote. rclone only deletes the source after a
+/// successful transfer.
+pub fn rclone_move(from: &Path, dest: &str) -> Result<()> {
+    let hash = audit_hash(from)?;
+    let output = rclone_moveto_command(from, dest)
+        .output()
+        .context("run rclone (is it installed and on PATH?)")?;
+    if !output.status.success() {
+        bail!(
+            "rclone moveto {:?} -> {dest} exited with {}: {}",
+            from,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    audit::record("move", from, Some(dest), hash.as_deref())
+}
+
+/// Appends an entry to the archive index (`<archive_dir>/index.tsv`): time, source,
+/// destination, then the [`Actor`]'s `user@host` and version.
+pub fn record_archive(archive_dir: &Path, from: &Path, to: &str) -> Result<()> {
+    let index = archive_dir.join(ARCHIVE_INDEX);
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&index)
+        .with_context(|| format!("open archive index {index:?}"))?;
+    let actor = Actor::current();
+    writeln!(
+        f,
+        "{}\t{}\t{}\t{}@{}\t{}",
+        unique_suffix_millis(),
+        from.display(),
+        to,
+        actor.user,
+        actor.host,
+        actor.version
+    )
+    .with_context(|| format!("write archive index {index:?}"))
+}
+
+/// SHA-256 of a file's contents as lowercase hex.
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut f = File::open(path).with_context(|| format!("open {path:?}"))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut f, &mut hasher).with_context(|| format!("hash {path:?}"))?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// SHA-256 of `bytes` as lowercase hex.
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Hashes `path`, records the hash in the archive index, then removes the file (or
+/// moves it to the OS trash with `trash`). Nothing is removed if hashing or recording
+/// fails.
+pub fn delete_recorded(archive_dir: &Path, path: &Path, trash: bool) -> Result<String> {
+    let hash = hash_file(path)?;
+    let how = if trash { "trashed" } else { "deleted" };
+    record_archive(archive_dir, path, &format!("{how} sha256:{hash}"))?;
+    remove_audited(path, trash, Some(hash.clone()))?;
+    Ok(hash)
+}
+
+/// Removes `path`, or with `trash` moves it to the OS trash (recycle bin) instead.
+/// Recorded in the [`audit`] log.
+pub fn remove_file_or_trash(path: &Path, trash: bool) -> Result<()> {
+    remove_audited(path, trash, audit_hash(path)?)
+}
+
+fn remove_audited(path: &Path, trash: bool, hash: Option<String>) -> Result<()> {
+    if trash {
+        trash::delete(path).with_context(|| format!("move {path:?} to the trash"))?;
+    } else {
+        fs::remove_file(path).with_context(|| format!("remove {path:?}"))?;
+    }
+    let action = if trash { "trash" } else { "delete" };
+    audit::record(action, path, None, hash.as_deref())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::Write;
     use tempfile::tempdir;
 
     #[test]
@@ -72,6 +857,56 @@ mod tests {
         assert!(s.contains("hello"));
     }
 
+    #[cfg(windows)]
+    #[test]
+    fn long_paths_get_the_extended_prefix() {
+        assert_eq!(
+            long_path(Path::new(r"C:\a\b\..\c")),
+            Path::new(r"\\?\C:\a\c")
+        );
+        assert_eq!(
+            long_path(Path::new(r"\\srv\share\d")),
+            Path::new(r"\\?\UNC\srv\share\d")
+        );
+        assert_eq!(long_path(Path::new(r"rel\x")), Path::new(r"rel\x"));
+        assert_eq!(long_path(Path::new(r"\\?\C:\x")), Path::new(r"\\?\C:\x"));
+
+        // Archiving deep in a node_modules-style tree, well past MAX_PATH.
+        let td = tempdir().unwrap();
+        let deep = (0..25).fold(td.path().to_path_buf(), |p, i| {
+            p.join(format!("node_modules_{i:02}"))
+        });
+        let from = td.path().join("from.txt");
+        fs::write(&from, "x").unwrap();
+        let to = deep.join(ARCHIVE_DIR).join("to.txt");
+        assert!(to.as_os_str().len() > 260);
+        move_file(&from, &to).unwrap();
+        assert_eq!(fs::read_to_string(long_path(&to)).unwrap(), "x");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_below_finds_links_under_the_root_only() {
+        let td = tempdir().unwrap();
+        let root = td.path().join("root");
+        ensure_dir(&root.join("real")).unwrap();
+        std::os::unix::fs::symlink(root.join("real"), root.join("link")).unwrap();
+        std::os::unix::fs::symlink(td.path(), td.path().join("above")).unwrap();
+
+        assert_eq!(symlink_below(&root, &root.join("real/f.txt")), None);
+        assert_eq!(
+            symlink_below(&root, &root.join("link/f.txt")),
+            Some(root.join("link"))
+        );
+        assert_eq!(
+            symlink_below(&root, &root.join("link")),
+            Some(root.join("link"))
+        );
+        // Links at or above the root are the user's business.
+        let above = td.path().join("above");
+        assert_eq!(symlink_below(&above, &above.join("root/real")), None);
+    }
+
     #[test]
     fn archive_dir_for_places_in_parent() {
         let td = tempdir().unwrap();
@@ -80,4 +915,313 @@ mod tests {
         let a = archive_dir_for(&base).unwrap();
         assert_eq!(a, td.path().join("x").join(".stconflict-archive"));
     }
+
+    #[test]
+    fn archive_target_parse_rclone() {
+        assert_eq!(
+            ArchiveTarget::parse("rclone:backup:conflicts/").unwrap(),
+            ArchiveTarget::Rclone("backup:conflicts".to_string())
+        );
+        assert_eq!(
+            ArchiveTarget::parse("rclone:backup:").unwrap(),
+            ArchiveTarget::Rclone("backup:".to_string())
+        );
+        assert!(ArchiveTarget::parse("s3://bucket").is_err());
+        assert!(ArchiveTarget::parse("rclone:nocolon").is_err());
+    }
+
+    #[test]
+    fn remote_archive_dest_mirrors_relative_dir() {
+        let d = remote_archive_dest("backup:conflicts", Path::new("docs/a"), "x.txt.1");
+        assert_eq!(d, "backup:conflicts/docs/a/x.txt.1");
+        let d = remote_archive_dest("backup:", Path::new(""), "x.txt.1");
+        assert_eq!(d, "backup:x.txt.1");
+
+        let c = rclone_moveto_command(Path::new("/tmp/x"), &d);
+        let args: Vec<_> = c.get_args().collect();
+        assert_eq!(args, ["moveto", "/tmp/x", "backup:x.txt.1"]);
+    }
+
+    #[test]
+    fn record_archive_appends_lines() {
+        let td = tempdir().unwrap();
+        record_archive(td.path(), Path::new("/a/x"), "backup:x.1").unwrap();
+        record_archive(td.path(), Path::new("/a/y"), "/a/.stconflict-archive/y.2").unwrap();
+        let s = fs::read_to_string(td.path().join(ARCHIVE_INDEX)).unwrap();
+        let lines: Vec<_> = s.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let actor = Actor::current();
+        assert!(lines[0].ends_with(&format!(
+            "\t/a/x\tbackup:x.1\t{}@{}\t{}",
+            actor.user, actor.host, actor.version
+        )));
+    }
+
+    #[test]
+    fn actor_is_the_user_behind_sudo() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |k: &str| {
+                vars.iter()
+                    .find(|(name, _)| *name == k)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        let a = Actor::from_env(env(&[("USER", "root"), ("SUDO_USER", "alice")]), None);
+        assert_eq!((a.user.as_str(), a.host.as_str()), ("alice", "unknown"));
+        let a = Actor::from_env(env(&[("USERNAME", "bob")]), Some("nas".to_string()));
+        assert_eq!((a.user.as_str(), a.host.as_str()), ("bob", "nas"));
+        assert_eq!(a.version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn delete_recorded_hashes_then_removes() {
+        let td = tempdir().unwrap();
+        let p = td.path().join("big.bin");
+        fs::write(&p, "abc").unwrap();
+        let archive = td.path().join(".stconflict-archive");
+        ensure_dir(&archive).unwrap();
+
+        let hash = delete_recorded(&archive, &p, false).unwrap();
+        assert_eq!(
+            hash,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(!p.exists());
+        let index = fs::read_to_string(archive.join(ARCHIVE_INDEX)).unwrap();
+        assert!(index.contains(&format!("deleted sha256:{hash}")));
+    }
+
+    #[test]
+    fn expand_user_path_relative_and_home() {
+        let base = Path::new("/root/dir");
+        assert_eq!(
+            expand_user_path(" backup/x.txt ", base),
+            base.join("backup/x.txt")
+        );
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(expand_user_path("~/x.txt", base), home.join("x.txt"));
+        }
+        let abs = std::env::temp_dir().join("x.txt");
+        assert_eq!(expand_user_path(&abs.to_string_lossy(), base), abs);
+    }
+
+    #[test]
+    fn render_rename_template_placeholders() {
+        let vars = RenameVars {
+            base_name: "IMG_001.jpg",
+            device: "PHONE",
+            date: "20240101",
+            time: "101010",
+        };
+        assert_eq!(
+            render_rename_template("{stem}-{device}-{date}{ext}", &vars).unwrap(),
+            "IMG_001-PHONE-20240101.jpg"
+        );
+        assert_eq!(
+            render_rename_template("{name}.{time}", &vars).unwrap(),
+            "IMG_001.jpg.101010"
+        );
+        assert!(render_rename_template("{nope}", &vars).is_err());
+        assert!(render_rename_template("{stem", &vars).is_err());
+        assert!(render_rename_template("../{name}", &vars).is_err());
+    }
+
+    #[test]
+    fn ensure_within_rejects_escapes() {
+        let td = tempdir().unwrap();
+        let root = td.path().join("root");
+        ensure_dir(&root.join("a")).unwrap();
+
+        let ok = ensure_within(&root, Path::new("a/../a/new.txt")).unwrap();
+        assert_eq!(ok, root.canonicalize().unwrap().join("a/new.txt"));
+        assert!(ensure_within(&root, &root.join("a/x/y/not-yet.txt")).is_ok());
+        fs::write(root.join("a/f.txt"), "").unwrap();
+        let file = ensure_within(&root, Path::new("a/f.txt")).unwrap();
+        assert_eq!(file, root.canonicalize().unwrap().join("a/f.txt"));
+        assert!(!file.to_string_lossy().ends_with('/'));
+
+        assert!(ensure_within(&root, Path::new("../outside.txt")).is_err());
+        assert!(ensure_within(&root, &root.join("a/../../outside.txt")).is_err());
+        assert!(ensure_within(&root, &td.path().join("elsewhere")).is_err());
+    }
+
+    #[test]
+    fn ensure_not_archived_refuses_archives_and_versions() {
+        let td = tempdir().unwrap();
+        let root = td.path().join("root");
+        ensure_dir(&root.join("a")).unwrap();
+
+        assert!(ensure_not_archived(&root, &root.join("a/notes.txt")).is_ok());
+        assert!(ensure_not_archived(&root, Path::new("a/.stversions-not/x.txt")).is_ok());
+        for p in [
+            ".stconflict-archive/notes.txt",
+            "a/.stversions/notes~20240101-000000.txt",
+            "a/x/../.stconflict-archive/y/notes.txt",
+        ] {
+            let err = ensure_not_archived(&root, Path::new(p)).unwrap_err();
+            assert!(err.to_string().contains("archived copies"), "{p}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ensure_within_follows_symlinked_ancestors() {
+        let td = tempdir().unwrap();
+        let root = td.path().join("root");
+        let outside = td.path().join("outside");
+        ensure_dir(&root).unwrap();
+        ensure_dir(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+
+        assert!(ensure_within(&root, Path::new("link/x.txt")).is_err());
+    }
+
+    #[test]
+    fn verify_copy_rejects_and_removes_a_corrupt_copy() {
+        let td = tempdir().unwrap();
+        let from = td.path().join("a.jpg");
+        let to = td.path().join("b.jpg");
+        fs::write(&from, "pixels").unwrap();
+        fs::write(&to, "pixelz").unwrap();
+        assert!(verify_copy(&from, &to).is_err());
+        assert!(!to.exists());
+
+        fs::write(&to, "pixels").unwrap();
+        verify_copy(&from, &to).unwrap();
+        assert!(to.exists());
+    }
+
+    #[test]
+    fn copy_preserving_keeps_mode_and_times() {
+        let td = tempdir().unwrap();
+        let from = td.path().join("a.txt");
+        let to = td.path().join("b.txt");
+        fs::write(&from, "hello").unwrap();
+        let old = UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        File::options()
+            .write(true)
+            .open(&from)
+            .unwrap()
+            .set_times(fs::FileTimes::new().set_modified(old).set_accessed(old))
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&from, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        copy_preserving(&from, &to).unwrap();
+        let meta = fs::metadata(&to).unwrap();
+        assert_eq!(fs::read_to_string(&to).unwrap(), "hello");
+        assert_eq!(meta.modified().unwrap(), old);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(meta.permissions().mode() & 0o777, 0o640);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_preserving_copies_a_read_only_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let td = tempdir().unwrap();
+        let from = td.path().join("a.txt");
+        let to = td.path().join("b.txt");
+        fs::write(&from, "locked").unwrap();
+        let old = UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        File::options()
+            .write(true)
+            .open(&from)
+            .unwrap()
+            .set_times(fs::FileTimes::new().set_modified(old))
+            .unwrap();
+        fs::set_permissions(&from, fs::Permissions::from_mode(0o444)).unwrap();
+
+        copy_preserving(&from, &to).unwrap();
+        let meta = fs::metadata(&to).unwrap();
+        assert_eq!(fs::read_to_string(&to).unwrap(), "locked");
+        assert_eq!(meta.modified().unwrap(), old);
+        assert_eq!(meta.permissions().mode() & 0o777, 0o444);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn copy_preserving_copies_user_xattrs() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let td = tempdir().unwrap();
+        let from = td.path().join("a.txt");
+        let to = td.path().join("b.txt");
+        fs::write(&from, "x").unwrap();
+        let path = CString::new(from.as_os_str().as_bytes()).unwrap();
+        let name = CString::new("user.synctui.test").unwrap();
+        let set =
+            unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), b"v1".as_ptr().cast(), 2, 0) };
+        if set != 0 {
+            // The temp file system doesn't support user xattrs.
+            return;
+        }
+
+        copy_preserving(&from, &to).unwrap();
+        let dst = CString::new(to.as_os_str().as_bytes()).unwrap();
+        let mut buf = [0u8; 8];
+        let n = unsafe {
+            libc::getxattr(
+                dst.as_ptr(),
+                name.as_ptr(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+            )
+        };
+        assert_eq!(&buf[..n as usize], b"v1");
+    }
+
+    #[test]
+    fn ensure_writable_uses_nearest_existing_ancestor() {
+        let td = tempdir().unwrap();
+        assert!(ensure_writable(&td.path().join("not/yet/there.txt")).is_ok());
+        assert!(!read_only_mount(td.path()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ensure_writable_rejects_read_only_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // root bypasses permission bits, so there is nothing to observe.
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+        let td = tempdir().unwrap();
+        let dir = td.path().join("ro");
+        ensure_dir(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+        let res = ensure_writable(&dir.join("new.txt"));
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(res.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn chown_like_copies_owner() {
+        use std::os::unix::fs::{chown, MetadataExt};
+
+        // Only root can hand files to another user.
+        if !running_as_root() {
+            return;
+        }
+        let td = tempdir().unwrap();
+        let dir = td.path().join("user");
+        ensure_dir(&dir).unwrap();
+        chown(&dir, Some(65534), Some(65534)).unwrap();
+        let f = dir.join("a.txt");
+        fs::write(&f, "x").unwrap();
+
+        chown_like(&f, &dir).unwrap();
+        let meta = fs::metadata(&f).unwrap();
+        assert_eq!((meta.uid(), meta.gid()), (65534, 65534));
+    }
 }
@@ -0,0 +1,1857 @@
+use crate::config::{loser_action, loser_rule, LoserAction, LoserRule};
+use crate::fmt::{format_age, format_size, format_stamp};
+use crate::model::{Candidate, ConflictGroup, DeletedOriginal, Strategy, DEFAULT_SKEW_THRESHOLD};
+use crate::ops::{
+    archive_dir_for, carry_tags, chown_like, delete_recorded, ensure_dir, ensure_not_archived,
+    ensure_within, ensure_writable, file_tags, free_space, hash_file, move_file_verified,
+    rclone_move, record_archive, remote_archive_dest, render_rename_template, symlink_below,
+    unique_name, ArchiveTarget, RenameVars, ARCHIVE_INDEX,
+};
+use crate::recency::Recency;
+use crate::rules::{pick_rule, PickRule};
+use crate::scan::{conflict_meta, rel_path, scan_dirs, ScanOptions};
+use crate::settle::rescan_delay;
+use crate::syncthing::{folder_for, SyncthingClient};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How the kept file and the non-chosen versions are handled on apply.
+#[derive(Clone, Debug, Default)]
+pub struct ApplyOptions {
+    pub archive: ArchiveTarget,
+    pub loser_rules: Vec<LoserRule>,
+    pub rename_template: Option<String>,
+    /// Validate each group but leave the filesystem untouched.
+    pub dry_run: bool,
+    /// Hand the archive, its index, archived files and the kept file to the owner of the
+    /// base file's directory. Meant for runs as root.
+    pub chown_to_owner: bool,
+    /// Check copies made across file systems against their source's SHA-256 before
+    /// removing the source, and hash the kept file.
+    pub verify: bool,
+    /// Move and replace paths that are, or sit inside, symlinks below the root. Off by
+    /// default: such groups are blocked.
+    pub allow_symlinks: bool,
+    /// Refuse to change anything while the file system being archived to has less free
+    /// space than this. Remote archives aren't checked: they free space here.
+    pub min_free_space: Option<u64>,
+    /// Send losers that a rule deletes to the OS trash instead of removing them.
+    pub trash: bool,
+}
+
+/// A group together with the index of the candidate to keep.
+#[derive(Clone, Debug)]
+pub struct PlannedGroup {
+    pub group: ConflictGroup,
+    pub chosen: usize,
+}
+
+/// A validated set of resolutions, ready for [`apply`].
+#[derive(Clone, Debug)]
+pub struct ResolutionPlan {
+    pub root: PathBuf,
+    pub groups: Vec<PlannedGroup>,
+    pub options: ApplyOptions,
+}
+
+/// Outcome of applying one group.
+#[derive(Debug)]
+pub struct GroupOutcome {
+    pub base_path: PathBuf,
+    /// The kept file's SHA-256 when verifying.
+    pub result: Result<Option<String>>,
+    /// The chosen version's SHA-256 from before the group was applied, when the `before`
+    /// of [`spawn_apply_then`] took one.
+    pub chosen_hash: Option<String>,
+    /// Problems after the group that don't fail it, such as a failing `post_group` hook.
+    pub warnings: Vec<String>,
+}
+
+impl ResolutionPlan {
+    pub fn builder(root: impl Into<PathBuf>) -> ResolutionPlanBuilder {
+        ResolutionPlanBuilder {
+            root: root.into(),
+            groups: Vec::new(),
+            strategy: None,
+            deleted_original: None,
+            pick_rules: Vec::new(),
+            skew_threshold: Some(DEFAULT_SKEW_THRESHOLD),
+            options: ApplyOptions::default(),
+        }
+    }
+
+    /// Human-readable operation list (paths relative to the root).
+    pub fn describe(&self) -> Result<Vec<String>> {
+        let mut lines = Vec::new();
+        for pg in &self.groups {
+            lines.extend(describe_group(
+                &self.root,
+                &pg.group,
+                pg.chosen,
+                &self.options,
+            )?);
+        }
+        Ok(lines)
+    }
+}
+
+/// What decides a group, in the order [`ResolutionPlanBuilder::build`] tries them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// Picked beforehand (`ConflictGroup::chosen`).
+    Picked(usize),
+    /// The deleted-original policy.
+    DeletedOriginal(DeletedOriginal, usize),
+    /// The pick rule at this index, with its strategy.
+    Rule(usize, Strategy, usize),
+    Strategy(Strategy, usize),
+    /// The rule's or builder's strategy decides by mtime, and the group's names and mtimes
+    /// disagree by this much; `build` refuses it.
+    SkewBlocked(Strategy, Duration),
+    /// No pick, policy or strategy applies; `build` refuses it.
+    Undecided,
+}
+
+impl Decision {
+    /// Candidate index kept, if the group is decided.
+    pub fn chosen(self) -> Option<usize> {
+        match self {
+            Decision::Picked(i)
+            | Decision::DeletedOriginal(_, i)
+            | Decision::Rule(_, _, i)
+            | Decision::Strategy(_, i) => Some(i),
+            Decision::SkewBlocked(..) | Decision::Undecided => None,
+        }
+    }
+}
+
+/// A non-chosen version and the loser rule that decides it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoserDecision {
+    /// Candidate index in the group.
+    pub index: usize,
+    /// Index into the loser rules; `None` when no rule matches (the file is archived).
+    pub rule: Option<usize>,
+    pub action: LoserAction,
+}
+
+/// One group as [`ResolutionPlanBuilder::explain`] sees it.
+#[derive(Clone, Debug)]
+pub struct GroupDecision {
+    pub group: ConflictGroup,
+    pub decision: Decision,
+    /// Existing non-chosen versions; empty while the group is undecided.
+    pub losers: Vec<LoserDecision>,
+}
+
+pub struct ResolutionPlanBuilder {
+    root: PathBuf,
+    groups: Vec<ConflictGroup>,
+    strategy: Option<Strategy>,
+    deleted_original: Option<DeletedOriginal>,
+    pick_rules: Vec<PickRule>,
+    skew_threshold: Option<Duration>,
+    options: ApplyOptions,
+}
+
+impl ResolutionPlanBuilder {
+    /// Adds a group; its `chosen` pick is used, or the builder's policy, rules or strategy
+    /// if unpicked.
+    pub fn group(mut self, group: ConflictGroup) -> Self {
+        self.groups.push(group);
+        self
+    }
+
+    pub fn groups(mut self, groups: impl IntoIterator<Item = ConflictGroup>) -> Self {
+        self.groups.extend(groups);
+        self
+    }
+
+    /// Strategy for groups that have no pick yet.
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    /// Policy for unpicked groups whose original was deleted; takes precedence over the
+    /// strategy for those groups.
+    pub fn deleted_original(mut self, policy: DeletedOriginal) -> Self {
+        self.deleted_original = Some(policy);
+        self
+    }
+
+    /// Glob rules for unpicked groups, checked after the deleted-original policy and
+    /// before the strategy.
+    pub fn pick_rules(mut self, rules: Vec<PickRule>) -> Self {
+        self.pick_rules = rules;
+        self
+    }
+
+    /// Groups whose conflict names and mtimes disagree by more than this aren't resolved
+    /// by an mtime-only strategy (`newest`, `oldest`); `build` fails on them instead.
+    /// Defaults to [`DEFAULT_SKEW_THRESHOLD`]; `None` trusts mtimes regardless.
+    pub fn skew_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.skew_threshold = threshold;
+        self
+    }
+
+    pub fn archive(mut self, target: ArchiveTarget) -> Self {
+        self.options.archive = target;
+        self
+    }
+
+    pub fn loser_rules(mut self, rules: Vec<LoserRule>) -> Self {
+        self.options.loser_rules = rules;
+        self
+    }
+
+    pub fn rename_template(mut self, template: impl Into<String>) -> Self {
+        self.options.rename_template = Some(template.into());
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.options.dry_run = dry_run;
+        self
+    }
+
+    pub fn chown_to_owner(mut self, chown: bool) -> Self {
+        self.options.chown_to_owner = chown;
+        self
+    }
+
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.options.verify = verify;
+        self
+    }
+
+    pub fn allow_symlinks(mut self, allow: bool) -> Self {
+        self.options.allow_symlinks = allow;
+        self
+    }
+
+    pub fn min_free_space(mut self, bytes: Option<u64>) -> Self {
+        self.options.min_free_space = bytes;
+        self
+    }
+
+    pub fn trash(mut self, trash: bool) -> Self {
+        self.options.trash = trash;
+        self
+    }
+
+    fn decide(&self, group: &ConflictGroup) -> Decision {
+        if let Some(i) = group.chosen {
+            return Decision::Picked(i);
+        }
+        if let Some((p, i)) = self
+            .deleted_original
+            .and_then(|p| p.choose(group).map(|i| (p, i)))
+        {
+            return Decision::DeletedOriginal(p, i);
+        }
+        let rule = pick_rule(&self.pick_rules, rel_path(&self.root, &group.base_path));
+        let (s, by) = match (rule, self.strategy) {
+            (Some(r), _) => (self.pick_rules[r].strategy, self.pick_rules[r].by),
+            (None, Some(s)) => (s, Recency::Mtime),
+            (None, None) => return Decision::Undecided,
+        };
+        let skew = self
+            .skew_threshold
+            .and_then(|t| group.clock_skew(t))
+            .filter(|_| s.mtime_only() && by == Recency::Mtime);
+        match (skew, s.choose_by(group, by), rule) {
+            (Some(skew), _, _) => Decision::SkewBlocked(s, skew),
+            (None, Some(i), Some(r)) => Decision::Rule(r, s, i),
+            (None, Some(i), None) => Decision::Strategy(s, i),
+            (None, None, _) => Decision::Undecided,
+        }
+    }
+
+    /// Which pick, policy or strategy decides each group and which loser rule decides
+    /// each non-chosen version, without validating or building anything.
+    pub fn explain(&self) -> Vec<GroupDecision> {
+        self.groups
+            .iter()
+            .map(|g| {
+                let decision = self.decide(g);
+                let losers = match decision.chosen() {
+                    Some(ci) => g
+                        .candidates
+                        .iter()
+                        .enumerate()
+                        .filter(|&(i, c)| i != ci && c.exists)
+                        .map(|(index, c)| {
+                            let rule = loser_rule(&self.options.loser_rules, c.size);
+                            LoserDecision {
+                                index,
+                                rule,
+                                action: rule
+                                    .map(|r| self.options.loser_rules[r].action)
+                                    .unwrap_or_default(),
+                            }
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                };
+                GroupDecision {
+                    group: g.clone(),
+                    decision,
+                    losers,
+                }
+            })
+            .collect()
+    }
+
+    pub fn build(self) -> Result<ResolutionPlan> {
+        let decisions: Vec<Decision> = self.groups.iter().map(|g| self.decide(g)).collect();
+        let mut groups = Vec::with_capacity(self.groups.len());
+        for (group, decision) in self.groups.into_iter().zip(decisions) {
+            let chosen = match decision {
+                Decision::SkewBlocked(s, skew) => bail!(
+                    "{:?}: conflict names and mtimes disagree by {} (clock skew); \
+                     pick it explicitly rather than by {}",
+                    group.base_path,
+                    format_age(skew),
+                    s.label()
+                ),
+                d => d
+                    .chosen()
+                    .ok_or_else(|| anyhow!("no pick for {:?}", group.base_path))?,
+            };
+            if chosen >= group.candidates.len() {
+                bail!("pick {chosen} out of range for {:?}", group.base_path);
+            }
+            // Surfaces template problems before anything is moved.
+            kept_destination(&group, chosen, &self.options)?;
+            groups.push(PlannedGroup { group, chosen });
+        }
+        Ok(ResolutionPlan {
+            root: self.root,
+            groups,
+            options: self.options,
+        })
+    }
+}
+
+/// Applies every group in the plan, continuing past failures.
+pub fn apply(plan: &ResolutionPlan) -> Vec<GroupOutcome> {
+    plan.groups
+        .iter()
+        .map(|pg| GroupOutcome {
+            base_path: pg.group.base_path.clone(),
+            result: apply_group(&plan.root, &pg.group, pg.chosen, &plan.options),
+            chosen_hash: None,
+            warnings: Vec::new(),
+        })
+        .collect()
+}
+
+/// What [`apply_paused`] did.
+pub struct PausedApply {
+    pub outcomes: Vec<GroupOutcome>,
+    /// Folders resumed without their rescan after a large apply, to rescan with
+    /// [`rescan_folders`] once the [`rescan_delay`] has passed.
+    pub unscanned: Vec<String>,
+    /// Syncthing errors from resuming or rescanning the folders, as log lines.
+    pub warnings: Vec<String>,
+}
+
+/// [`apply`] the way a real run goes with Syncthing's API: pauses the folders holding the
+/// plan's groups, applies, then resumes the folders and rescans them, unless enough
+/// groups were applied that [`rescan_delay`] puts the rescan off. A dry run, or one
+/// without a `client`, only applies. Fails, applying nothing, when a folder can't be
+/// paused.
+pub fn apply_paused(
+    plan: &ResolutionPlan,
+    client: Option<&SyncthingClient>,
+) -> Result<PausedApply> {
+    let client = client.filter(|_| !plan.options.dry_run && !plan.groups.is_empty());
+    let paused = match client {
+        Some(c) => pause_folders(c, plan.groups.iter().map(|pg| pg.group.base_path.as_path()))?,
+        None => Vec::new(),
+    };
+    let outcomes = apply(plan);
+    let applied = outcomes.iter().filter(|o| o.result.is_ok()).count();
+    let rescan = rescan_delay(applied).is_zero();
+    let warnings = client
+        .map(|c| resume_folders(c, &paused, rescan))
+        .unwrap_or_default();
+    Ok(PausedApply {
+        outcomes,
+        unscanned: if rescan { Vec::new() } else { paused },
+        warnings,
+    })
+}
+
+/// Pauses the Syncthing folders holding `paths`, so Syncthing doesn't scan or sync files
+/// while an apply moves them. Returns the ids paused here: folders that were already
+/// paused are left alone, so they aren't resumed afterwards. When one can't be paused,
+/// those paused so far are resumed before failing.
+pub fn pause_folders<'a>(
+    client: &SyncthingClient,
+    paths: impl IntoIterator<Item = &'a Path>,
+) -> Result<Vec<String>> {
+    let folders = client.folders()?;
+    let ids: BTreeSet<String> = paths
+        .into_iter()
+        .filter_map(|p| folder_for(&folders, p))
+        .filter(|f| !f.paused)
+        .map(|f| f.id.clone())
+        .collect();
+    let mut paused = Vec::new();
+    for id in ids {
+        if let Err(e) = client.set_paused(&id, true) {
+            // Don't leave anything paused behind when bailing out.
+            resume_folders(client, &paused, true);
+            return Err(e);
+        }
+        paused.push(id);
+    }
+    Ok(paused)
+}
+
+/// Resumes the folders [`pause_folders`] paused, and rescans them unless `rescan` is off
+/// (after a large apply, until the disk settles). Returns any errors as log lines.
+pub fn resume_folders(client: &SyncthingClient, ids: &[String], rescan: bool) -> Vec<String> {
+    let mut errors = Vec::new();
+    for id in ids {
+        let resumed = client.set_paused(id, false);
+        if let Err(e) = resumed.and_then(|_| if rescan { client.rescan(id) } else { Ok(()) }) {
+            errors.push(format!("syncthing folder {id}: {e:#}"));
+        }
+    }
+    errors
+}
+
+/// Asks Syncthing to rescan the given folders, returning any errors as log lines.
+pub fn rescan_folders(client: &SyncthingClient, ids: &[String]) -> Vec<String> {
+    ids.iter()
+        .filter_map(|id| {
+            client
+                .rescan(id)
+                .err()
+                .map(|e| format!("syncthing folder {id}: {e:#}"))
+        })
+        .collect()
+}
+
+/// Fails when `options.min_free_space` is set, losers are archived locally, and the file
+/// system holding `dir` has less free space than that (or can't be measured).
+pub fn check_free_space(dir: &Path, options: &ApplyOptions) -> Result<()> {
+    let Some(min) = options.min_free_space else {
+        return Ok(());
+    };
+    if matches!(options.archive, ArchiveTarget::Rclone(_)) {
+        return Ok(());
+    }
+    let free = free_space(dir)?;
+    if free < min {
+        bail!(
+            "only {} free on the file system holding {dir:?}, below min_free_space ({})",
+            format_size(free),
+            format_size(min)
+        );
+    }
+    Ok(())
+}
+
+/// Applies the plan's groups in order on a background thread, sending each outcome as it
+/// finishes so a UI can keep drawing. Setting `cancel` stops the thread before its next
+/// group (a group already being moved is finished first); the channel closes once the
+/// thread is done, whether it ran out of groups, was cancelled, or the receiver was dropped.
+pub fn spawn_apply(plan: ResolutionPlan, cancel: Arc<AtomicBool>) -> Receiver<GroupOutcome> {
+    spawn_apply_then(plan, cancel, |_| None, |_, _| None)
+}
+
+/// [`spawn_apply`], also running `before` on the thread ahead of each group and `after`
+/// once it is done, before its outcome is sent, so slow per-group work (hashing the
+/// chosen version, a `post_group` hook) doesn't hold up the receiver. What `before`
+/// returns becomes the outcome's `chosen_hash`, a message from `after` one of its warnings.
+pub fn spawn_apply_then<B, F>(
+    plan: ResolutionPlan,
+    cancel: Arc<AtomicBool>,
+    mut before: B,
+    mut after: F,
+) -> Receiver<GroupOutcome>
+where
+    B: FnMut(&PlannedGroup) -> Option<String> + Send + 'static,
+    F: FnMut(&PlannedGroup, &GroupOutcome) -> Option<String> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for pg in &plan.groups {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            let chosen_hash = before(pg);
+            let mut outcome = GroupOutcome {
+                base_path: pg.group.base_path.clone(),
+                result: apply_group(&plan.root, &pg.group, pg.chosen, &plan.options),
+                chosen_hash,
+                warnings: Vec::new(),
+            };
+            outcome.warnings.extend(after(pg, &outcome));
+            if tx.send(outcome).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+fn rename_fields(c: &Candidate) -> (String, String, String) {
+    match conflict_meta(&c.path) {
+        Some(m) => (m.device, m.date, m.time),
+        None => {
+            let (date, time) = c.modified.map(format_stamp).unwrap_or_default();
+            ("local".to_string(), date, time)
+        }
+    }
+}
+
+/// Where the chosen candidate ends up: the base path, or its `rename_template` rendering.
+pub fn kept_destination(
+    g: &ConflictGroup,
+    chosen_idx: usize,
+    options: &ApplyOptions,
+) -> Result<PathBuf> {
+    let Some(template) = options
+        .rename_template
+        .as_ref()
+        .filter(|_| !g.discards(chosen_idx))
+    else {
+        return Ok(g.base_path.clone());
+    };
+    let base_name = g
+        .base_path
+        .file_name()
+        .ok_or_else(|| anyhow!("bad name"))?
+        .to_string_lossy();
+    let (device, date, time) = rename_fields(&g.candidates[chosen_idx]);
+    let name = render_rename_template(
+        template,
+        &RenameVars {
+            base_name: &base_name,
+            device: &device,
+            date: &date,
+            time: &time,
+        },
+    )?;
+    Ok(g.base_path.with_file_name(name))
+}
+
+/// Paths apply would have to modify but can't, each with the reason. Checked up front so a
+/// read-only mount or a root-owned file blocks the whole group instead of failing halfway.
+pub fn permission_problems(
+    g: &ConflictGroup,
+    chosen_idx: usize,
+    options: &ApplyOptions,
+) -> Result<Vec<(PathBuf, String)>> {
+    let base = &g.base_path;
+    let kept_dest = kept_destination(g, chosen_idx, options)?;
+    let mut paths = BTreeSet::new();
+    for c in g.candidates.iter().filter(|c| c.exists) {
+        // Moving or unlinking needs the parent; copy fallbacks also rewrite the file.
+        paths.insert(c.path.clone());
+        if let Some(parent) = c.path.parent() {
+            paths.insert(parent.to_path_buf());
+        }
+    }
+    if let Some(parent) = kept_dest.parent() {
+        paths.insert(parent.to_path_buf());
+    }
+    if options.archive == ArchiveTarget::Local {
+        paths.insert(archive_dir_for(base)?);
+    }
+    Ok(paths
+        .into_iter()
+        .filter_map(|p| ensure_writable(&p).err().map(|e| (p, format!("{e:#}"))))
+        .collect())
+}
+
+/// Paths apply would move or replace that are symlinks or lie inside a symlinked
+/// directory below `root`, unless `options.allow_symlinks` is set.
+pub fn symlink_problems(
+    root: &Path,
+    g: &ConflictGroup,
+    chosen_idx: usize,
+    options: &ApplyOptions,
+) -> Result<Vec<(PathBuf, String)>> {
+    if options.allow_symlinks {
+        return Ok(Vec::new());
+    }
+    let mut paths = BTreeSet::new();
+    for c in g.candidates.iter().filter(|c| c.exists) {
+        paths.insert(c.path.clone());
+    }
+    paths.insert(g.base_path.clone());
+    paths.insert(kept_destination(g, chosen_idx, options)?);
+    if options.archive == ArchiveTarget::Local {
+        paths.insert(archive_dir_for(&g.base_path)?);
+    }
+    let links: BTreeSet<PathBuf> = paths
+        .iter()
+        .filter_map(|p| symlink_below(root, p))
+        .collect();
+    Ok(links
+        .into_iter()
+        .map(|l| {
+            (
+                l,
+                "is a symlink (pass --allow-symlinks to move it anyway)".to_string(),
+            )
+        })
+        .collect())
+}
+
+/// Everything that blocks a group: symlinks, then paths that aren't writable.
+fn blocked_paths(
+    root: &Path,
+    g: &ConflictGroup,
+    chosen_idx: usize,
+    options: &ApplyOptions,
+) -> Result<Vec<(PathBuf, String)>> {
+    let mut blocked = symlink_problems(root, g, chosen_idx, options)?;
+    blocked.extend(permission_problems(g, chosen_idx, options)?);
+    Ok(blocked)
+}
+
+/// Operation lines for one group, as shown in the confirm view.
+pub fn describe_group(
+    root: &Path,
+    g: &ConflictGroup,
+    chosen_idx: usize,
+    options: &ApplyOptions,
+) -> Result<Vec<String>> {
+    let base = &g.base_path;
+    let chosen = &g.candidates[chosen_idx].path;
+    let archive_dir = archive_dir_for(base)?;
+    let archive_display = match &options.archive {
+        ArchiveTarget::Local => rel_path(root, &archive_dir).display().to_string(),
+        ArchiveTarget::Rclone(remote) => {
+            let rel_dir = rel_path(root, archive_dir.parent().unwrap_or(root));
+            format!("rclone:{}", remote_archive_dest(remote, rel_dir, ""))
+        }
+    };
+
+    let keep = if g.discards(chosen_idx) {
+        "  discard -> original was deleted; every version is archived".to_string()
+    } else {
+        format!("  keep -> {}", rel_path(root, chosen).display())
+    };
+    let mut lines = vec![format!("Group: {}", rel_path(root, base).display()), keep];
+    let dest = kept_destination(g, chosen_idx, options)?;
+    if dest != *base {
+        lines.push(format!("  rename -> {}", rel_path(root, &dest).display()));
+    }
+    lines.push(format!("  archive -> {archive_display}"));
+    for c in &g.candidates {
+        if c.path == *chosen || !c.exists {
+            continue;
+        }
+        if loser_action(&options.loser_rules, c.size) == LoserAction::Delete {
+            lines.push(format!(
+                "  delete (hash recorded) -> {}",
+                rel_path(root, &c.path).display()
+            ));
+        }
+    }
+    for (p, why) in blocked_paths(root, g, chosen_idx, options)? {
+        lines.push(format!(
+            "  blocked -> {}: {why}",
+            rel_path(root, &p).display()
+        ));
+    }
+    Ok(lines)
+}
+
+/// One filesystem change apply would make, with absolute paths.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PlannedOp {
+    /// Move a non-chosen version into the archive: a local path (apply appends a unique
+    /// suffix to the name) or an `rclone:` remote path.
+    Archive { from: PathBuf, to: String },
+    /// Delete a non-chosen version after recording its hash in the archive index.
+    Delete { path: PathBuf },
+    /// Move the chosen version onto the kept destination (the base path unless renamed).
+    Replace { from: PathBuf, to: PathBuf },
+    /// A path apply would have to modify but can't; the whole group is refused.
+    Blocked { path: PathBuf, reason: String },
+}
+
+impl PlannedOp {
+    /// Tab-separated `op`, source and destination (or reason).
+    pub fn to_line(&self) -> String {
+        match self {
+            PlannedOp::Archive { from, to } => format!("archive\t{}\t{to}", from.display()),
+            PlannedOp::Delete { path } => format!("delete\t{}", path.display()),
+            PlannedOp::Replace { from, to } => {
+                format!("replace\t{}\t{}", from.display(), to.display())
+            }
+            PlannedOp::Blocked { path, reason } => {
+                format!("blocked\t{}\t{reason}", path.display())
+            }
+        }
+    }
+}
+
+/// Every change applying one group would make, in the order apply makes them.
+pub fn planned_ops(
+    root: &Path,
+    g: &ConflictGroup,
+    chosen_idx: usize,
+    options: &ApplyOptions,
+) -> Result<Vec<PlannedOp>> {
+    let chosen = &g.candidates[chosen_idx].path;
+    let archive_dir = archive_dir_for(&g.base_path)?;
+    let mut ops = Vec::new();
+    for c in g
+        .candidates
+        .iter()
+        .filter(|c| c.path != *chosen && c.exists)
+    {
+        if loser_action(&options.loser_rules, c.size) == LoserAction::Delete {
+            ops.push(PlannedOp::Delete {
+                path: c.path.clone(),
+            });
+            continue;
+        }
+        let name = c.path.file_name().ok_or_else(|| anyhow!("bad name"))?;
+        let to = match &options.archive {
+            ArchiveTarget::Local => archive_dir.join(name).display().to_string(),
+            ArchiveTarget::Rclone(remote) => {
+                let rel_dir = rel_path(root, archive_dir.parent().unwrap_or(root));
+                format!(
+                    "rclone:{}",
+                    remote_archive_dest(remote, rel_dir, &name.to_string_lossy())
+                )
+            }
+        };
+        ops.push(PlannedOp::Archive {
+            from: c.path.clone(),
+            to,
+        });
+    }
+    let dest = kept_destination(g, chosen_idx, options)?;
+    if dest != *chosen {
+        ops.push(PlannedOp::Replace {
+            from: chosen.clone(),
+            to: dest,
+        });
+    }
+    for (path, reason) in blocked_paths(root, g, chosen_idx, options)? {
+        ops.push(PlannedOp::Blocked { path, reason });
+    }
+    Ok(ops)
+}
+
+/// Assumed time per rename; generous, since renames on network shares are round trips.
+const RENAME_TIME: Duration = Duration::from_millis(10);
+/// Assumed bytes per second for copies across file systems.
+const COPY_RATE: u64 = 50 << 20;
+/// Assumed bytes per second for uploads to an rclone remote.
+const UPLOAD_RATE: u64 = 10 << 20;
+/// Assumed bytes per second for hashing (deletes, and everything with `verify`).
+const HASH_RATE: u64 = 200 << 20;
+
+/// Rough cost of applying groups: renames within a file system are cheap, while copies
+/// across file systems, uploads and hashing take time in proportion to their bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ApplyCost {
+    pub renames: usize,
+    pub copy_bytes: u64,
+    pub upload_bytes: u64,
+    pub hash_bytes: u64,
+}
+
+impl ApplyCost {
+    /// The cost of `ops` (one group's [`planned_ops`]); nothing if the group is blocked.
+    pub fn of_ops(ops: &[PlannedOp], verify: bool) -> Self {
+        let mut cost = Self::default();
+        if ops.iter().any(|op| matches!(op, PlannedOp::Blocked { .. })) {
+            return cost;
+        }
+        let size = |p: &Path| fs::metadata(p).map_or(0, |m| m.len());
+        let local = |cost: &mut Self, from: &Path, to: &Path| {
+            if same_file_system(from, to) {
+                cost.renames += 1;
+            } else {
+                cost.copy_bytes += size(from);
+                if verify {
+                    cost.hash_bytes += 2 * size(from);
+                }
+            }
+        };
+        for op in ops {
+            match op {
+                PlannedOp::Archive { from, to } if to.starts_with("rclone:") => {
+                    cost.upload_bytes += size(from);
+                }
+                PlannedOp::Archive { from, to } => local(&mut cost, from, Path::new(to)),
+                PlannedOp::Delete { path } => cost.hash_bytes += size(path),
+                PlannedOp::Replace { from, to } => {
+                    local(&mut cost, from, to);
+                    if verify {
+                        // Hashed before the move and again at its destination.
+                        cost.hash_bytes += 2 * size(from);
+                    }
+                }
+                PlannedOp::Blocked { .. } => {}
+            }
+        }
+        cost
+    }
+
+    pub fn add(&mut self, other: Self) {
+        self.renames += other.renames;
+        self.copy_bytes += other.copy_bytes;
+        self.upload_bytes += other.upload_bytes;
+        self.hash_bytes += other.hash_bytes;
+    }
+
+    /// How long this should take at the assumed rates.
+    pub fn duration(&self) -> Duration {
+        let secs = |bytes: u64, rate: u64| Duration::from_secs_f64(bytes as f64 / rate as f64);
+        RENAME_TIME * self.renames as u32
+            + secs(self.copy_bytes, COPY_RATE)
+            + secs(self.upload_bytes, UPLOAD_RATE)
+            + secs(self.hash_bytes, HASH_RATE)
+    }
+
+    /// E.g. `~25s: 3 renames, 1.2 GiB copied across file systems`.
+    pub fn describe(&self) -> String {
+        let d = self.duration();
+        let plural = if self.renames == 1 { "" } else { "s" };
+        let mut parts = vec![format!("{} rename{plural}", self.renames)];
+        if self.copy_bytes > 0 {
+            parts.push(format!(
+                "{} copied across file systems",
+                format_size(self.copy_bytes)
+            ));
+        }
+        if self.upload_bytes > 0 {
+            parts.push(format!("{} uploaded", format_size(self.upload_bytes)));
+        }
+        if self.hash_bytes > 0 {
+            parts.push(format!("{} hashed", format_size(self.hash_bytes)));
+        }
+        let time = if d < Duration::from_secs(1) {
+            "<1s".to_string()
+        } else {
+            format!("~{}", format_age(d))
+        };
+        format!("{time}: {}", parts.join(", "))
+    }
+}
+
+/// Whether `a` and `b` (or, for paths that don't exist yet, their nearest existing
+/// ancestors) are on the same file system, so a move between them is a rename.
+fn same_file_system(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let dev = |p: &Path| {
+            p.ancestors()
+                .find_map(|a| fs::metadata(a).ok())
+                .map(|m| m.dev())
+        };
+        dev(a) == dev(b)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (a, b);
+        true
+    }
+}
+
+/// Writes a plan for review: JSON (`[{"group": ..., "ops": [...]}]`) when `path` ends in
+/// `.json`, otherwise a `# group` header per group followed by its [`PlannedOp::to_line`]s.
+pub fn write_plan(path: &Path, groups: &[(PathBuf, Vec<PlannedOp>)]) -> Result<()> {
+    let json = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+    let text = if json {
+        #[derive(Serialize)]
+        struct Group<'a> {
+            group: &'a Path,
+            ops: &'a [PlannedOp],
+        }
+        let groups: Vec<Group> = groups
+            .iter()
+            .map(|(g, ops)| Group { group: g, ops })
+            .collect();
+        serde_json::to_string_pretty(&groups)? + "\n"
+    } else {
+        let mut out = String::new();
+        for (g, ops) in groups {
+            out.push_str(&format!("# {}\n", g.display()));
+            for op in ops {
+                out.push_str(&op.to_line());
+                out.push('\n');
+            }
+        }
+        out
+    };
+    fs::write(path, text).with_context(|| format!("write plan {path:?}"))
+}
+
+/// `path` as [`ensure_within`] resolves it against `root`, except that a symlink at the
+/// end stays the link: applies move links (where allowed), not the files they point at.
+fn resolve_within(root: &Path, path: &Path) -> Result<PathBuf> {
+    let resolved = ensure_within(root, path)?;
+    let is_link = root
+        .join(path)
+        .symlink_metadata()
+        .is_ok_and(|m| m.file_type().is_symlink());
+    match path.file_name() {
+        Some(name) if is_link => {
+            Ok(ensure_within(root, path.parent().unwrap_or(Path::new("")))?.join(name))
+        }
+        _ => Ok(resolved),
+    }
+}
+
+/// Keeps `chosen_idx` and archives (or deletes, per the loser rules) everything else.
+/// With `verify`, returns the kept file's SHA-256 (also recorded in the archive index);
+/// a promoted conflict must hash the same at the base path as it did before the move.
+pub fn apply_group(
+    root: &Path,
+    g: &ConflictGroup,
+    chosen_idx: usize,
+    options: &ApplyOptions,
+) -> Result<Option<String>> {
+    let chosen = g
+        .candidates
+        .get(chosen_idx)
+        .ok_or_else(|| anyhow!("bad candidate index {chosen_idx}"))?;
+    let discard = g.discards(chosen_idx);
+    if !chosen.exists && !discard {
+        bail!("chosen version {:?} no longer exists", chosen.path);
+    }
+
+    // Plans can come from outside the TUI (library callers), so never trust their paths:
+    // everything below acts on them as checked here, so a relative or symlinked path
+    // can't name one file for the check and another for the move.
+    let mut resolved = g.clone();
+    resolved.base_path = resolve_within(root, &g.base_path)?;
+    for c in &mut resolved.candidates {
+        c.path = resolve_within(root, &c.path)?;
+    }
+    let base = resolved.base_path.clone();
+    let archive_dir = resolve_within(root, &archive_dir_for(&base)?)?;
+    let chosen_path = resolved.candidates[chosen_idx].path.clone();
+    let kept_dest = resolve_within(root, &kept_destination(&resolved, chosen_idx, options)?)?;
+
+    // Determine which file ends up at the kept destination (the base path unless renamed).
+    let make_base_from = if chosen_path == kept_dest {
+        None
+    } else {
+        Some(chosen_path.clone())
+    };
+    // Nor let one resolve copies in an archive or `.stversions` back into place.
+    for p in resolved
+        .candidates
+        .iter()
+        .map(|c| &c.path)
+        .chain([&base, &kept_dest])
+    {
+        ensure_not_archived(root, p)?;
+    }
+
+    if kept_dest != base
+        && kept_dest.exists()
+        && !resolved.candidates.iter().any(|c| c.path == kept_dest)
+    {
+        bail!("rename target {kept_dest:?} already exists");
+    }
+    // Symlinks are looked for in the paths as given, before resolving follows them.
+    let mut blocked = symlink_problems(root, g, chosen_idx, options)?;
+    blocked.extend(permission_problems(&resolved, chosen_idx, options)?);
+    if let Some((p, why)) = blocked.into_iter().next() {
+        bail!("cannot modify {p:?}: {why}");
+    }
+
+    if options.dry_run {
+        // Dry-run: don't touch FS.
+        return Ok(None);
+    }
+    // Checked per group, so a run stops archiving once it has eaten into the reserve.
+    check_free_space(base.parent().unwrap_or(root), options)?;
+
+    // The local archive dir always exists: it holds the index even when losers go to a remote.
+    ensure_dir(&archive_dir)?;
+    let mut touched = vec![archive_dir.clone()];
+    // The original's tags (Finder tags on macOS) outlive it on the promoted version.
+    let base_tags = make_base_from.as_ref().and_then(|_| file_tags(&base));
+    // Hashed before anything moves, so the promoted file can be checked end to end.
+    let chosen_hash = match &make_base_from {
+        Some(src) if options.verify => Some(hash_file(src)?),
+        _ => None,
+    };
+
+    // Move all non-chosen candidates (including the old base if we are replacing it) into archive,
+    // unless a size rule says to delete them.
+    for c in &resolved.candidates {
+        if c.path == chosen_path {
+            continue;
+        }
+        if !c.exists {
+            continue;
+        }
+        if loser_action(&options.loser_rules, c.size) == LoserAction::Delete {
+            delete_recorded(&archive_dir, &c.path, options.trash)
+                .with_context(|| format!("delete {:?}", c.path))?;
+            continue;
+        }
+
+        let file_name = c.path.file_name().ok_or_else(|| anyhow!("bad name"))?;
+        let name = unique_name(file_name.to_string_lossy().as_ref());
+        let dest = match &options.archive {
+            ArchiveTarget::Local => {
+                let dest = archive_dir.join(name);
+                move_file_verified(&c.path, &dest, options.verify)
+                    .with_context(|| format!("archive {:?} -> {:?}", c.path, dest))?;
+                let shown = dest.display().to_string();
+                touched.push(dest);
+                shown
+            }
+            ArchiveTarget::Rclone(remote) => {
+                let rel_dir = rel_path(root, archive_dir.parent().unwrap_or(root));
+                let dest = remote_archive_dest(remote, rel_dir, &name);
+                rclone_move(&c.path, &dest)
+                    .with_context(|| format!("archive {:?} -> {dest}", c.path))?;
+                dest
+            }
+        };
+        record_archive(&archive_dir, &c.path, &dest)?;
+    }
+
+    // If chosen is not already there, move chosen into base (or its renamed destination).
+    if let Some(src) = make_base_from {
+        move_file_verified(&src, &kept_dest, options.verify)
+            .with_context(|| format!("set base {:?} <- {:?}", kept_dest, src))?;
+        if let Some(tags) = &base_tags {
+            carry_tags(&kept_dest, tags);
+        }
+    }
+
+    let kept_hash = if options.verify && !discard {
+        let hash = hash_file(&kept_dest)?;
+        match chosen_hash {
+            Some(want) if want != hash => {
+                record_archive(
+                    &archive_dir,
+                    &kept_dest,
+                    &format!("kept sha256:{hash} expected sha256:{want}"),
+                )?;
+                bail!(
+                    "promoted {kept_dest:?} is corrupt (sha256 {hash}, the chosen version had {want})"
+                );
+            }
+            Some(_) => record_archive(
+                &archive_dir,
+                &kept_dest,
+                &format!("kept sha256:{hash} verified"),
+            )?,
+            None => record_archive(&archive_dir, &kept_dest, &format!("kept sha256:{hash}"))?,
+        }
+        Some(hash)
+    } else {
+        None
+    };
+
+    if options.chown_to_owner {
+        let owner_dir = base.parent().unwrap_or(root);
+        touched.push(archive_dir.join(ARCHIVE_INDEX));
+        touched.push(kept_dest);
+        for p in touched.iter().filter(|p| p.symlink_metadata().is_ok()) {
+            chown_like(p, owner_dir)?;
+        }
+    }
+
+    Ok(kept_hash)
+}
+
+/// What doesn't hold after group `g` was applied keeping `chosen_idx`, checked against
+/// `rescanned`, a fresh scan of its directory: the kept version is at its destination
+/// (for a discard, nothing is at the base path) and unchanged, by `kept_hash` (the
+/// chosen version's SHA-256 from before the apply) when there is one, else by size and
+/// mtime; and no conflict copy of it is left,
+/// whether one the apply should have moved or one Syncthing has made since.
+pub fn check_applied(
+    g: &ConflictGroup,
+    chosen_idx: usize,
+    options: &ApplyOptions,
+    kept_hash: Option<&str>,
+    rescanned: &[ConflictGroup],
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    let name = |p: &Path| {
+        p.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    if g.discards(chosen_idx) {
+        if g.base_path.exists() {
+            problems.push(format!("{} exists again", name(&g.base_path)));
+        }
+    } else {
+        match kept_destination(g, chosen_idx, options) {
+            Ok(dest) if !dest.exists() => problems.push(format!("{} is missing", name(&dest))),
+            Ok(dest) => {
+                let chosen = &g.candidates[chosen_idx];
+                match kept_hash {
+                    Some(want) => match hash_file(&dest) {
+                        Ok(got) if got == want => {}
+                        Ok(got) => problems.push(format!(
+                            "{} hashes sha256:{got}, expected sha256:{want}",
+                            name(&dest)
+                        )),
+                        Err(e) => problems.push(format!("{e:#}")),
+                    },
+                    None => {
+                        let meta = fs::metadata(&dest).ok();
+                        let size = meta.as_ref().map(|m| m.len());
+                        let modified = meta.and_then(|m| m.modified().ok());
+                        if size != chosen.size || modified != chosen.modified {
+                            problems.push(format!("{} changed since it was kept", name(&dest)));
+                        }
+                    }
+                }
+            }
+            Err(e) => problems.push(format!("{e:#}")),
+        }
+    }
+    let left = rescanned
+        .iter()
+        .filter(|r| r.base_path == g.base_path)
+        .flat_map(|r| r.candidates.iter().filter(|c| !c.is_original && c.exists));
+    for c in left {
+        if g.candidates.iter().any(|old| old.path == c.path) {
+            problems.push(format!("{} is still there", name(&c.path)));
+        } else {
+            problems.push(format!("{} is a new conflict copy", name(&c.path)));
+        }
+    }
+    problems
+}
+
+/// An applied group to check against a rescan: the group as it was planned, the kept
+/// version and its SHA-256 when one was taken.
+#[derive(Clone, Debug)]
+pub struct AppliedCheck {
+    pub group: ConflictGroup,
+    pub chosen: usize,
+    pub kept_hash: Option<String>,
+}
+
+/// Rescans the directories of the applied groups and lists what [`check_applied`] finds
+/// wrong with each, as `path: problem`.
+pub fn check_applied_groups(
+    root: &Path,
+    scan: ScanOptions,
+    options: &ApplyOptions,
+    checks: &[AppliedCheck],
+) -> Vec<String> {
+    let dirs: BTreeSet<PathBuf> = checks
+        .iter()
+        .filter_map(|c| c.group.base_path.parent().map(Path::to_path_buf))
+        .collect();
+    let rescanned = match scan_dirs(root, &dirs, scan) {
+        Ok(groups) => groups,
+        Err(e) => return vec![format!("checking the applied groups: {e:#}")],
+    };
+    let mut problems = Vec::new();
+    for c in checks {
+        let hash = c.kept_hash.as_deref();
+        let rel = rel_path(root, &c.group.base_path).display();
+        for p in check_applied(&c.group, c.chosen, options, hash, &rescanned) {
+            problems.push(format!("{rel}: {p}"));
+        }
+    }
+    problems
+}
+
+/// [`check_applied_groups`] on a background thread, which sends its problems once and
+/// closes the channel.
+pub fn spawn_check_applied(
+    root: PathBuf,
+    scan: ScanOptions,
+    options: ApplyOptions,
+    checks: Vec<AppliedCheck>,
+) -> Receiver<Vec<String>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(check_applied_groups(&root, scan, &options, &checks));
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::scan_conflicts;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn fixture() -> (tempfile::TempDir, PathBuf) {
+        let td = tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        fs::write(root.join("notes.txt"), "orig").unwrap();
+        fs::write(
+            root.join("notes.txt.sync-conflict-20240101-010101-DEV"),
+            "conflict",
+        )
+        .unwrap();
+        (td, root)
+    }
+
+    #[test]
+    fn applies_are_refused_below_min_free_space() {
+        let (_td, root) = fixture();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+        let build = |min| {
+            ResolutionPlan::builder(&root)
+                .groups(groups.clone())
+                .min_free_space(min)
+                .build()
+                .unwrap()
+        };
+        let err = apply(&build(Some(u64::MAX)))[0]
+            .result
+            .as_ref()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("below min_free_space"), "{err}");
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "orig");
+
+        // Remote archives free space here, so they aren't held back.
+        let remote = ApplyOptions {
+            archive: ArchiveTarget::Rclone("backup:c".into()),
+            min_free_space: Some(u64::MAX),
+            ..ApplyOptions::default()
+        };
+        check_free_space(&root, &remote).unwrap();
+
+        for outcome in apply(&build(Some(1))) {
+            outcome.result.unwrap();
+        }
+        assert_eq!(
+            fs::read_to_string(root.join("notes.txt")).unwrap(),
+            "conflict"
+        );
+    }
+
+    #[test]
+    fn deleted_originals_follow_the_policy() {
+        for (policy, kept) in [
+            (DeletedOriginal::Promote, Some("conflict")),
+            (DeletedOriginal::Discard, None),
+        ] {
+            let (_td, root) = fixture();
+            fs::remove_file(root.join("notes.txt")).unwrap();
+            let plan = ResolutionPlan::builder(&root)
+                .groups(scan_conflicts(&root, true).unwrap())
+                .deleted_original(policy)
+                .strategy(Strategy::Oldest)
+                .rename_template("{stem}-{device}{ext}")
+                .verify(true)
+                .build()
+                .unwrap();
+            let described = plan.describe().unwrap();
+            assert_eq!(described[1].starts_with("  discard"), kept.is_none());
+            for outcome in apply(&plan) {
+                outcome.result.unwrap();
+            }
+            let left: Vec<_> = fs::read_dir(&root)
+                .unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .filter(|n| !n.starts_with('.'))
+                .collect();
+            match kept {
+                Some(body) => {
+                    assert_eq!(left, ["notes-DEV.txt"]);
+                    assert_eq!(fs::read_to_string(root.join(&left[0])).unwrap(), body);
+                }
+                None => assert!(left.is_empty()),
+            }
+        }
+    }
+
+    #[test]
+    fn explain_names_the_deciding_pick_policy_or_strategy_and_loser_rule() {
+        let (_td, root) = fixture();
+        fs::write(root.join("gone.txt.sync-conflict-20240101-010101-DEV"), "c").unwrap();
+        fs::write(root.join("todo.txt"), "orig").unwrap();
+        fs::write(
+            root.join("todo.txt.sync-conflict-20240101-010101-DEV"),
+            "conflict",
+        )
+        .unwrap();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        let names: Vec<_> = groups
+            .iter()
+            .map(|g| rel_path(&root, &g.base_path))
+            .collect();
+        assert_eq!(names, ["gone.txt", "notes.txt", "todo.txt"]);
+        groups[2].chosen = Some(1);
+        let builder = ResolutionPlan::builder(&root)
+            .groups(groups)
+            .deleted_original(DeletedOriginal::Promote)
+            .strategy(Strategy::Newest)
+            .loser_rules(vec![LoserRule {
+                min_size: None,
+                max_size: Some(4),
+                action: LoserAction::Delete,
+            }]);
+        let explained = builder.explain();
+        let decisions: Vec<Decision> = explained.iter().map(|d| d.decision).collect();
+        assert!(matches!(
+            decisions[..],
+            [
+                Decision::DeletedOriginal(DeletedOriginal::Promote, 1),
+                // Named 2024-01-01 but written now.
+                Decision::SkewBlocked(Strategy::Newest, _),
+                Decision::Picked(1),
+            ]
+        ));
+        assert!(explained[0].losers.is_empty());
+        assert!(explained[1].losers.is_empty());
+        assert_eq!(
+            explained[2].losers,
+            [LoserDecision {
+                index: 0,
+                rule: Some(0),
+                action: LoserAction::Delete,
+            }]
+        );
+        // `build` refuses exactly what `explain` reports as blocked.
+        assert!(builder
+            .build()
+            .unwrap_err()
+            .to_string()
+            .contains("clock skew"));
+    }
+
+    #[test]
+    fn pick_rules_decide_before_the_strategy_and_respect_clock_skew() {
+        let (_td, root) = fixture();
+        let rule = |glob: &str, strategy| PickRule {
+            glob: glob.to_string(),
+            strategy,
+            by: Recency::Mtime,
+        };
+        let decide = |rules: Vec<PickRule>| {
+            ResolutionPlan::builder(&root)
+                .groups(scan_conflicts(&root, true).unwrap())
+                .strategy(Strategy::Oldest)
+                .pick_rules(rules)
+                .explain()[0]
+                .decision
+        };
+        assert!(matches!(
+            decide(vec![
+                rule("*.md", Strategy::Newest),
+                rule("/*.txt", Strategy::Current),
+            ]),
+            Decision::Rule(1, Strategy::Current, _)
+        ));
+        // A rule going by mtime is held back by skew like the strategy is.
+        assert!(matches!(
+            decide(vec![rule("notes.*", Strategy::Newest)]),
+            Decision::SkewBlocked(Strategy::Newest, _)
+        ));
+        assert!(matches!(
+            decide(vec![rule("sub/*.txt", Strategy::Current)]),
+            Decision::SkewBlocked(Strategy::Oldest, _)
+        ));
+        // Going by the conflict names, skewed mtimes don't matter.
+        let by_name = PickRule {
+            by: Recency::Name,
+            ..rule("notes.*", Strategy::Newest)
+        };
+        assert!(matches!(
+            decide(vec![by_name]),
+            Decision::Rule(0, Strategy::Newest, _)
+        ));
+    }
+
+    #[test]
+    fn spawned_apply_reports_each_group_and_stops_when_cancelled() {
+        let (_td, root) = fixture();
+        fs::write(root.join("todo.txt"), "orig").unwrap();
+        fs::write(root.join("todo.txt.sync-conflict-20240101-010101-DEV"), "c").unwrap();
+        let plan = ResolutionPlan::builder(&root)
+            .groups(scan_conflicts(&root, true).unwrap())
+            .strategy(Strategy::Current)
+            .build()
+            .unwrap();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let outcomes: Vec<GroupOutcome> =
+            spawn_apply(plan.clone(), cancel.clone()).iter().collect();
+        let paths: Vec<_> = outcomes.iter().map(|o| o.base_path.clone()).collect();
+        assert_eq!(paths, [root.join("notes.txt"), root.join("todo.txt")]);
+        assert!(outcomes.iter().all(|o| o.result.is_ok()));
+
+        // Cancelled before it starts: nothing is applied and the channel just closes.
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(spawn_apply(plan, cancel).iter().count(), 0);
+    }
+
+    #[test]
+    fn mtime_strategies_refuse_groups_with_clock_skew() {
+        // Written now, but the conflict name says 2024-01-01.
+        let (_td, root) = fixture();
+        let groups = scan_conflicts(&root, true).unwrap();
+        let plan = |strategy, threshold| {
+            ResolutionPlan::builder(&root)
+                .groups(groups.clone())
+                .strategy(strategy)
+                .skew_threshold(threshold)
+                .build()
+        };
+        let err = plan(Strategy::Newest, Some(DEFAULT_SKEW_THRESHOLD)).unwrap_err();
+        assert!(err.to_string().contains("clock skew"), "{err}");
+        plan(Strategy::Current, Some(DEFAULT_SKEW_THRESHOLD)).unwrap();
+        plan(Strategy::Newest, None).unwrap();
+
+        // An explicit pick is always honored.
+        let mut picked = groups[0].clone();
+        picked.chosen = Some(1);
+        ResolutionPlan::builder(&root)
+            .group(picked)
+            .strategy(Strategy::Oldest)
+            .build()
+            .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_paths_block_the_group_unless_allowed() {
+        use crate::scan::{scan_conflicts_with, ScanOptions};
+
+        let (_td, root) = fixture();
+        let real = root.join("real");
+        fs::create_dir(&real).unwrap();
+        fs::write(real.join("a.txt"), "orig").unwrap();
+        fs::write(real.join("a.txt.sync-conflict-20240101-010101-DEV"), "c").unwrap();
+        std::os::unix::fs::symlink(&real, root.join("link")).unwrap();
+        // The base file itself is a link to another file in the folder.
+        fs::remove_file(root.join("notes.txt")).unwrap();
+        std::os::unix::fs::symlink(real.join("a.txt"), root.join("notes.txt")).unwrap();
+
+        let options = ScanOptions {
+            include_hidden: true,
+            follow_symlinks: true,
+            ..ScanOptions::default()
+        };
+        let groups = scan_conflicts_with(&root, options).unwrap();
+        let linked: Vec<&ConflictGroup> = groups
+            .iter()
+            .filter(|g| !g.base_path.starts_with(&real))
+            .collect();
+        assert_eq!(linked.len(), 2);
+        for g in linked {
+            let blocked = symlink_problems(&root, g, 1, &ApplyOptions::default()).unwrap();
+            assert_eq!(blocked.len(), 1, "{:?}", g.base_path);
+            assert!(apply_group(&root, g, 1, &ApplyOptions::default())
+                .unwrap_err()
+                .to_string()
+                .contains("symlink"));
+            let allowed = ApplyOptions {
+                allow_symlinks: true,
+                dry_run: true,
+                ..ApplyOptions::default()
+            };
+            assert!(symlink_problems(&root, g, 1, &allowed).unwrap().is_empty());
+            apply_group(&root, g, 1, &allowed).unwrap();
+        }
+    }
+
+    #[test]
+    fn builder_requires_a_pick_or_strategy() {
+        let (_td, root) = fixture();
+        let groups = scan_conflicts(&root, true).unwrap();
+        assert!(ResolutionPlan::builder(&root)
+            .groups(groups.clone())
+            .build()
+            .is_err());
+        let plan = ResolutionPlan::builder(&root)
+            .groups(groups)
+            .strategy(Strategy::Current)
+            .build()
+            .unwrap();
+        assert_eq!(plan.groups[0].chosen, 0);
+        assert!(plan.describe().unwrap()[0].starts_with("Group: notes.txt"));
+    }
+
+    #[test]
+    fn apply_promotes_conflict_and_archives_original() {
+        let (_td, root) = fixture();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+
+        let dry = ResolutionPlan::builder(&root)
+            .groups(groups.clone())
+            .dry_run(true)
+            .build()
+            .unwrap();
+        assert!(apply(&dry).iter().all(|o| o.result.is_ok()));
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "orig");
+
+        let plan = ResolutionPlan::builder(&root)
+            .groups(groups)
+            .build()
+            .unwrap();
+        let outcomes = apply(&plan);
+        assert!(outcomes.iter().all(|o| o.result.is_ok()));
+        assert_eq!(
+            fs::read_to_string(root.join("notes.txt")).unwrap(),
+            "conflict"
+        );
+        assert!(scan_conflicts(&root, true).unwrap().is_empty());
+        let archived = fs::read_dir(root.join(".stconflict-archive"))
+            .unwrap()
+            .count();
+        // The archived original plus the index.
+        assert_eq!(archived, 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn promoting_a_conflict_keeps_the_originals_tags() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let (_td, root) = fixture();
+        let tag = |path: &Path, value: &[u8]| {
+            let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+            let name = CString::new("user.xdg.tags").unwrap();
+            let set = unsafe {
+                libc::setxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    value.as_ptr().cast(),
+                    value.len(),
+                    0,
+                )
+            };
+            set == 0
+        };
+        if !tag(&root.join("notes.txt"), b"red,taxes") {
+            // The temp file system doesn't support user xattrs.
+            return;
+        }
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+        let plan = ResolutionPlan::builder(&root)
+            .groups(groups)
+            .build()
+            .unwrap();
+        assert!(apply(&plan).iter().all(|o| o.result.is_ok()));
+        assert_eq!(
+            fs::read_to_string(root.join("notes.txt")).unwrap(),
+            "conflict"
+        );
+        assert_eq!(
+            file_tags(&root.join("notes.txt")).as_deref(),
+            Some(&b"red,taxes"[..])
+        );
+    }
+
+    #[test]
+    fn apply_cost_counts_renames_uploads_and_hashing() {
+        let (_td, root) = fixture();
+        let groups = scan_conflicts(&root, true).unwrap();
+        let g = &groups[0];
+        let size = |i: usize| fs::metadata(&g.candidates[i].path).unwrap().len();
+
+        let ops = planned_ops(&root, g, 1, &ApplyOptions::default()).unwrap();
+        let cost = ApplyCost::of_ops(&ops, false);
+        assert_eq!(
+            cost,
+            ApplyCost {
+                renames: 2,
+                ..ApplyCost::default()
+            }
+        );
+        assert_eq!(cost.describe(), "<1s: 2 renames");
+
+        let remote = ApplyOptions {
+            archive: ArchiveTarget::Rclone("b:c".to_string()),
+            ..ApplyOptions::default()
+        };
+        let ops = planned_ops(&root, g, 1, &remote).unwrap();
+        let cost = ApplyCost::of_ops(&ops, true);
+        assert_eq!(cost.upload_bytes, size(0));
+        assert_eq!(cost.hash_bytes, 2 * size(1));
+
+        let big = ApplyCost {
+            copy_bytes: 100 << 20,
+            ..cost
+        };
+        assert!(big
+            .describe()
+            .starts_with("~2s: 1 rename, 100.0 MiB copied"));
+    }
+
+    #[test]
+    fn planned_ops_list_moves_with_absolute_paths() {
+        let (td, root) = fixture();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        let g = &mut groups[0];
+        g.chosen = Some(1);
+        let ops = planned_ops(&root, g, 1, &ApplyOptions::default()).unwrap();
+        assert_eq!(
+            ops,
+            [
+                PlannedOp::Archive {
+                    from: root.join("notes.txt"),
+                    to: root
+                        .join(".stconflict-archive/notes.txt")
+                        .display()
+                        .to_string(),
+                },
+                PlannedOp::Replace {
+                    from: g.candidates[1].path.clone(),
+                    to: root.join("notes.txt"),
+                },
+            ]
+        );
+
+        let out = td.path().join("plan.json");
+        write_plan(&out, &[(g.base_path.clone(), ops.clone())]).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(json[0]["ops"][1]["op"], "replace");
+
+        let out = td.path().join("plan.txt");
+        write_plan(&out, &[(g.base_path.clone(), ops)]).unwrap();
+        let text = fs::read_to_string(&out).unwrap();
+        assert!(text.lines().nth(1).unwrap().starts_with("archive\t/"));
+    }
+
+    #[test]
+    fn verify_returns_and_records_the_kept_hash() {
+        let (_td, root) = fixture();
+        let groups = scan_conflicts(&root, true).unwrap();
+        let plan = ResolutionPlan::builder(&root)
+            .groups(groups)
+            .strategy(Strategy::Current)
+            .verify(true)
+            .build()
+            .unwrap();
+        let hash = apply(&plan).remove(0).result.unwrap().unwrap();
+        assert_eq!(hash, hash_file(&root.join("notes.txt")).unwrap());
+        let index =
+            fs::read_to_string(root.join(".stconflict-archive").join(ARCHIVE_INDEX)).unwrap();
+        assert!(index.contains(&format!("kept sha256:{hash}\t")));
+
+        // A promoted conflict is checked against its hash from before the move.
+        fs::write(
+            root.join("notes.txt.sync-conflict-20240202-010101-DEV"),
+            "newer",
+        )
+        .unwrap();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+        let want = hash_file(&groups[0].candidates[1].path).unwrap();
+        let plan = ResolutionPlan::builder(&root)
+            .groups(groups)
+            .verify(true)
+            .build()
+            .unwrap();
+        let hash = apply(&plan).remove(0).result.unwrap().unwrap();
+        assert_eq!(hash, want);
+        let index =
+            fs::read_to_string(root.join(".stconflict-archive").join(ARCHIVE_INDEX)).unwrap();
+        assert!(index.contains(&format!("kept sha256:{want} verified\t")));
+    }
+
+    #[test]
+    fn check_applied_reports_a_changed_kept_file_and_recreated_conflicts() {
+        let (_td, root) = fixture();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        let g = groups.remove(0);
+        let options = ApplyOptions::default();
+        let hash = apply_group(&root, &g, 1, &options).unwrap();
+        assert_eq!(hash, None);
+        let rescan = || scan_conflicts(&root, true).unwrap();
+        assert!(check_applied(&g, 1, &options, None, &rescan()).is_empty());
+
+        // Syncthing makes a new conflict copy and the kept file changes under it.
+        let copy = "notes.txt.sync-conflict-20240102-010101-PHONE";
+        fs::write(root.join(copy), "again").unwrap();
+        fs::write(root.join("notes.txt"), "changed").unwrap();
+        assert_eq!(
+            check_applied(&g, 1, &options, None, &rescan()),
+            [
+                "notes.txt changed since it was kept".to_string(),
+                format!("{copy} is a new conflict copy"),
+            ]
+        );
+        let want = crate::ops::hash_bytes(b"conflict");
+        let problems = check_applied(&g, 1, &options, Some(&want), &[]);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].ends_with(&format!("expected sha256:{want}")));
+
+        // The kept file moved back to the conflict name, as if the apply never ran.
+        fs::remove_file(root.join(copy)).unwrap();
+        fs::rename(root.join("notes.txt"), &g.candidates[1].path).unwrap();
+        let problems = check_applied(&g, 1, &options, None, &rescan());
+        assert_eq!(
+            problems,
+            [
+                "notes.txt is missing".to_string(),
+                "notes.txt.sync-conflict-20240101-010101-DEV is still there".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_refuses_paths_outside_root() {
+        let (_td, root) = fixture();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+        groups[0].candidates[1].path = root.join("../escaped.txt");
+
+        let plan = ResolutionPlan::builder(&root)
+            .groups(groups)
+            .build()
+            .unwrap();
+        let outcomes = apply(&plan);
+        let err = outcomes[0].result.as_ref().unwrap_err();
+        assert!(format!("{err:#}").contains("outside"));
+        // Nothing was touched.
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "orig");
+    }
+
+    #[test]
+    fn apply_acts_on_relative_paths_as_resolved_under_the_root() {
+        let (_td, root) = fixture();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        // As a library caller might pass them: relative to the root, not the working dir.
+        let g = &mut groups[0];
+        g.base_path = g.base_path.strip_prefix(&root).unwrap().to_path_buf();
+        for c in &mut g.candidates {
+            c.path = c.path.strip_prefix(&root).unwrap().to_path_buf();
+        }
+        g.chosen = Some(1);
+
+        let plan = ResolutionPlan::builder(&root)
+            .groups(groups)
+            .build()
+            .unwrap();
+        for outcome in apply(&plan) {
+            outcome.result.unwrap();
+        }
+        assert_eq!(
+            fs::read_to_string(root.join("notes.txt")).unwrap(),
+            "conflict"
+        );
+        assert!(scan_conflicts(&root, true).unwrap().is_empty());
+        assert!(!Path::new("notes.txt").exists());
+    }
+
+    #[test]
+    fn apply_refuses_to_resolve_into_or_out_of_an_archive() {
+        let (_td, root) = fixture();
+        let archive = root.join(".stconflict-archive");
+        ensure_dir(&archive).unwrap();
+        let groups = scan_conflicts(&root, true).unwrap();
+        // As a hand-written or imported plan might: the base path inside the archive.
+        let mut inside = groups[0].clone();
+        inside.base_path = archive.join("notes.txt");
+        inside.candidates[0].path = inside.base_path.clone();
+        inside.chosen = Some(1);
+        // Or an archived copy offered as the version to promote.
+        let mut promoted = groups[0].clone();
+        promoted.candidates[1].path = root.join(".stversions/notes~20240101-000000.txt");
+        promoted.chosen = Some(1);
+
+        for g in [inside, promoted] {
+            let plan = ResolutionPlan::builder(&root).group(g).build().unwrap();
+            let err = apply(&plan).remove(0).result.unwrap_err();
+            assert!(format!("{err:#}").contains("archived copies"), "{err:#}");
+        }
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "orig");
+        assert_eq!(fs::read_dir(&archive).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn writable_groups_have_no_permission_problems() {
+        let (_td, root) = fixture();
+        let groups = scan_conflicts(&root, true).unwrap();
+        let problems = permission_problems(&groups[0], 0, &ApplyOptions::default()).unwrap();
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_dir_blocks_the_whole_group() {
+        use std::os::unix::fs::PermissionsExt;
+
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+        let (_td, root) = fixture();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+        fs::set_permissions(&root, fs::Permissions::from_mode(0o555)).unwrap();
+
+        let plan = ResolutionPlan::builder(&root)
+            .groups(groups)
+            .build()
+            .unwrap();
+        let described = plan.describe().unwrap();
+        let outcomes = apply(&plan);
+        fs::set_permissions(&root, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(described.iter().any(|l| l.starts_with("  blocked -> ")));
+        assert!(outcomes[0].result.is_err());
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "orig");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn chown_to_owner_returns_files_to_folder_owner() {
+        use crate::ops::running_as_root;
+        use std::os::unix::fs::{chown, MetadataExt};
+
+        if !running_as_root() {
+            return;
+        }
+        let (_td, root) = fixture();
+        chown(&root, Some(65534), Some(65534)).unwrap();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+
+        let plan = ResolutionPlan::builder(&root)
+            .groups(groups)
+            .chown_to_owner(true)
+            .build()
+            .unwrap();
+        assert!(apply(&plan)[0].result.is_ok());
+
+        let owner = |p: &Path| fs::symlink_metadata(p).unwrap().uid();
+        let archive = root.join(".stconflict-archive");
+        assert_eq!(owner(&root.join("notes.txt")), 65534);
+        assert_eq!(owner(&archive), 65534);
+        assert_eq!(owner(&archive.join(ARCHIVE_INDEX)), 65534);
+        for e in fs::read_dir(&archive).unwrap() {
+            assert_eq!(owner(&e.unwrap().path()), 65534);
+        }
+    }
+}
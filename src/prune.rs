@@ -0,0 +1,151 @@
+use crate::ops::{record_archive, remove_file_or_trash, ARCHIVE_DIR, ARCHIVE_INDEX};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+/// An archived file old enough to be pruned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PruneEntry {
+    pub path: PathBuf,
+    pub archive_dir: PathBuf,
+    /// When it was archived according to the index, else its modification time.
+    pub archived_at: SystemTime,
+    pub size: u64,
+}
+
+/// Every `.stconflict-archive` directory under `root`. Other dot-directories are only
+/// searched with `include_hidden`.
+pub fn find_archive_dirs(root: &Path, include_hidden: bool) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    let mut walk = WalkDir::new(root).follow_links(false).into_iter();
+    while let Some(entry) = walk.next() {
+        let entry = entry?;
+        if !entry.file_type().is_dir() || entry.depth() == 0 {
+            continue;
+        }
+        let name = entry.file_name();
+        if name == ARCHIVE_DIR {
+            dirs.push(entry.path().to_path_buf());
+            walk.skip_current_dir();
+        } else if !include_hidden && name.to_string_lossy().starts_with('.') {
+            walk.skip_current_dir();
+        }
+    }
+    Ok(dirs)
+}
+
+/// Archive times from `index.tsv`, keyed by the archived file's name.
+fn archived_times(archive_dir: &Path) -> HashMap<OsString, SystemTime> {
+    let Ok(text) = fs::read_to_string(archive_dir.join(ARCHIVE_INDEX)) else {
+        return HashMap::new();
+    };
+    text.lines()
+        .filter_map(|line| {
+            let mut cols = line.split('\t');
+            let millis: u64 = cols.next()?.parse().ok()?;
+            let name = Path::new(cols.nth(1)?).file_name()?.to_os_string();
+            Some((name, UNIX_EPOCH + Duration::from_millis(millis)))
+        })
+        .collect()
+}
+
+/// Archived files under `root` that were archived more than `max_age` before `now`,
+/// oldest first. Nothing is removed.
+pub fn prune_candidates(
+    root: &Path,
+    include_hidden: bool,
+    max_age: Duration,
+    now: SystemTime,
+) -> Result<Vec<PruneEntry>> {
+    let mut out = Vec::new();
+    for dir in find_archive_dirs(root, include_hidden)? {
+        let times = archived_times(&dir);
+        for entry in fs::read_dir(&dir).with_context(|| format!("read {dir:?}"))? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            if !meta.is_file() || entry.file_name() == ARCHIVE_INDEX {
+                continue;
+            }
+            let archived_at = match times.get(&entry.file_name()) {
+                Some(t) => *t,
+                None => meta.modified()?,
+            };
+            if now.duration_since(archived_at).unwrap_or_default() > max_age {
+                out.push(PruneEntry {
+                    path: entry.path(),
+                    archive_dir: dir.clone(),
+                    archived_at,
+                    size: meta.len(),
+                });
+            }
+        }
+    }
+    out.sort_by(|a, b| a.archived_at.cmp(&b.archived_at).then(a.path.cmp(&b.path)));
+    Ok(out)
+}
+
+/// Deletes the entries (or moves them to the OS trash with `trash`), recording each as
+/// `pruned` in its archive index first. Returns one result per entry.
+pub fn prune(entries: &[PruneEntry], trash: bool) -> Vec<Result<()>> {
+    let how = if trash { "pruned to trash" } else { "pruned" };
+    entries
+        .iter()
+        .map(|e| {
+            record_archive(&e.archive_dir, &e.path, how)?;
+            remove_file_or_trash(&e.path, trash)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn prunes_by_archive_time_and_keeps_the_index() {
+        let td = tempdir().unwrap();
+        let archive = td.path().join("docs").join(ARCHIVE_DIR);
+        fs::create_dir_all(&archive).unwrap();
+        fs::create_dir_all(td.path().join(".git").join(ARCHIVE_DIR)).unwrap();
+        for name in ["old.txt", "new.txt", "unindexed.txt"] {
+            fs::write(archive.join(name), "x").unwrap();
+        }
+        let day = 86_400_000u64;
+        let now = UNIX_EPOCH + Duration::from_millis(100 * day);
+        fs::write(
+            archive.join(ARCHIVE_INDEX),
+            format!(
+                "{}\t/r/docs/old.txt\t{}\n{}\t/r/docs/new.txt\t{}\n",
+                10 * day,
+                archive.join("old.txt").display(),
+                99 * day,
+                archive.join("new.txt").display(),
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            find_archive_dirs(td.path(), false).unwrap(),
+            [archive.as_path()]
+        );
+        let entries =
+            prune_candidates(td.path(), false, Duration::from_secs(30 * 86_400), now).unwrap();
+        // `unindexed.txt` falls back to its (current) mtime, which is in the future here.
+        let names: Vec<_> = entries
+            .iter()
+            .map(|e| e.path.file_name().unwrap())
+            .collect();
+        assert_eq!(names, ["old.txt"]);
+
+        assert!(prune(&entries, false).iter().all(Result::is_ok));
+        assert!(!archive.join("old.txt").exists());
+        assert!(archive.join("new.txt").exists());
+        let index = fs::read_to_string(archive.join(ARCHIVE_INDEX)).unwrap();
+        assert!(index.lines().last().unwrap().contains("\tpruned\t"));
+    }
+}
@@ -0,0 +1,375 @@
+use crate::docmeta::doc_props;
+use crate::model::{Candidate, ConflictGroup};
+use crate::scan::conflict_meta;
+use anyhow::{anyhow, Error};
+use chrono::{FixedOffset, Local, NaiveDateTime, TimeZone};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// How much of a photo is searched for its EXIF block, and of each end of a PDF for its
+/// modified date.
+const HEAD_BYTES: u64 = 256 * 1024;
+
+/// What `newest` and `oldest` compare to tell which version is more recent, per pick rule.
+/// Versions that don't carry the date asked for (the original has no conflict name, a
+/// screenshot has no EXIF block) go by their mtime.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Recency {
+    /// The filesystem modification time.
+    #[default]
+    Mtime,
+    /// The timestamp Syncthing put in the conflict name, i.e. when it saw the conflict.
+    Name,
+    /// When the photo was taken: EXIF `DateTimeOriginal` (or `DateTime`) of a JPEG or TIFF.
+    Exif,
+    /// The modified date a document records about itself: a PDF's `/ModDate`, or the
+    /// metadata of an Office Open XML (`.docx`, `.xlsx`, ...) or OpenDocument file.
+    Document,
+}
+
+const ALL: [Recency; 4] = [
+    Recency::Mtime,
+    Recency::Name,
+    Recency::Exif,
+    Recency::Document,
+];
+
+impl Recency {
+    pub fn label(self) -> &'static str {
+        match self {
+            Recency::Mtime => "mtime",
+            Recency::Name => "name",
+            Recency::Exif => "exif",
+            Recency::Document => "document",
+        }
+    }
+
+    /// When `c` last changed by this measure; `None` for missing versions.
+    pub fn time(self, c: &Candidate) -> Option<SystemTime> {
+        if !c.exists {
+            return None;
+        }
+        let own = match self {
+            Recency::Mtime => None,
+            Recency::Name => conflict_meta(&c.path).and_then(|m| m.timestamp()),
+            Recency::Exif => exif_date(&c.path),
+            Recency::Document => document_date(&c.path),
+        };
+        own.or(c.modified)
+    }
+
+    /// Index of the most recent candidate by this measure.
+    pub fn newest_idx(self, g: &ConflictGroup) -> Option<usize> {
+        self.times(g).max_by_key(|(_, t)| *t).map(|(i, _)| i)
+    }
+
+    /// Index of the least recent candidate by this measure.
+    pub fn oldest_idx(self, g: &ConflictGroup) -> Option<usize> {
+        self.times(g).min_by_key(|(_, t)| *t).map(|(i, _)| i)
+    }
+
+    fn times(self, g: &ConflictGroup) -> impl Iterator<Item = (usize, SystemTime)> + '_ {
+        g.candidates
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, c)| self.time(c).map(|t| (i, t)))
+    }
+}
+
+impl FromStr for Recency {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        ALL.into_iter()
+            .find(|r| r.label() == s)
+            .ok_or_else(|| anyhow!("expected `mtime`, `name`, `exif` or `document`, got {s:?}"))
+    }
+}
+
+/// A time without a zone, as cameras and some documents write it, read as local time.
+pub fn local_time(naive: NaiveDateTime) -> Option<SystemTime> {
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(SystemTime::from)
+}
+
+fn read_head(path: &Path) -> Option<Vec<u8>> {
+    let mut buf = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(HEAD_BYTES)
+        .read_to_end(&mut buf)
+        .ok()?;
+    Some(buf)
+}
+
+fn exif_date(path: &Path) -> Option<SystemTime> {
+    let head = read_head(path)?;
+    let tiff = if head.starts_with(&[0xFF, 0xD8]) {
+        jpeg_exif(&head)?
+    } else {
+        &head
+    };
+    let text = Tiff::new(tiff)?.date()?;
+    local_time(NaiveDateTime::parse_from_str(text, "%Y:%m:%d %H:%M:%S").ok()?)
+}
+
+/// The TIFF structure inside a JPEG's `Exif` APP1 segment.
+fn jpeg_exif(jpeg: &[u8]) -> Option<&[u8]> {
+    let mut i = 2;
+    while let [0xFF, marker, hi, lo, ..] = *jpeg.get(i..)? {
+        // The image data (start of scan) comes after every metadata segment.
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        let len = u16::from_be_bytes([hi, lo]) as usize;
+        let segment = jpeg.get(i + 4..i + 2 + len)?;
+        if marker == 0xE1 {
+            if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                return Some(tiff);
+            }
+        }
+        i += 2 + len;
+    }
+    None
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    const DATE_TIME: u16 = 0x0132;
+    const EXIF_IFD: u16 = 0x8769;
+    const DATE_TIME_ORIGINAL: u16 = 0x9003;
+
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        Some(Self {
+            data,
+            little_endian,
+        })
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let b = [*self.data.get(at)?, *self.data.get(at + 1)?];
+        Some(if self.little_endian {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<usize> {
+        let b: [u8; 4] = self.data.get(at..at + 4)?.try_into().ok()?;
+        let n = if self.little_endian {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        };
+        Some(n as usize)
+    }
+
+    /// Offset of the 12-byte entry for `tag` in the IFD at `ifd`.
+    fn entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16(ifd)? as usize;
+        (0..count)
+            .map(|k| ifd + 2 + 12 * k)
+            .find(|&e| self.u16(e) == Some(tag))
+    }
+
+    fn ascii(&self, entry: usize) -> Option<&'a str> {
+        let len = self.u32(entry + 4)?;
+        let at = if len <= 4 {
+            entry + 8
+        } else {
+            self.u32(entry + 8)?
+        };
+        let bytes = self.data.get(at..at + len)?;
+        std::str::from_utf8(bytes)
+            .ok()
+            .map(|s| s.trim_end_matches('\0').trim())
+    }
+
+    /// `DateTimeOriginal` from the EXIF IFD, else IFD0's `DateTime`.
+    fn date(&self) -> Option<&'a str> {
+        let ifd0 = self.u32(4)?;
+        self.entry(ifd0, Self::EXIF_IFD)
+            .and_then(|e| self.u32(e + 8))
+            .and_then(|exif| self.entry(exif, Self::DATE_TIME_ORIGINAL))
+            .and_then(|e| self.ascii(e))
+            .or_else(|| self.ascii(self.entry(ifd0, Self::DATE_TIME)?))
+    }
+}
+
+fn document_date(path: &Path) -> Option<SystemTime> {
+    let mut file = File::open(path).ok()?;
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic).ok()?;
+    match &magic {
+        b"%PDF" => pdf_date(&mut file),
+        b"PK\x03\x04" => doc_props(path)?.modified,
+        _ => None,
+    }
+}
+
+/// The last `/ModDate` in the first or last part of the file; incremental saves append a
+/// new info dictionary at the end.
+fn pdf_date(file: &mut File) -> Option<SystemTime> {
+    let len = file.metadata().ok()?.len();
+    let mut parts = Vec::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.by_ref()
+        .take(HEAD_BYTES)
+        .read_to_end(&mut parts)
+        .ok()?;
+    if len > HEAD_BYTES {
+        file.seek(SeekFrom::Start(
+            len.saturating_sub(HEAD_BYTES).max(HEAD_BYTES),
+        ))
+        .ok()?;
+        file.read_to_end(&mut parts).ok()?;
+    }
+    let text = String::from_utf8_lossy(&parts);
+    let after = &text[text.rfind("/ModDate")? + "/ModDate".len()..];
+    let value = after.trim_start().strip_prefix('(')?;
+    pdf_date_value(&value[..value.find(')')?])
+}
+
+/// `D:YYYYMMDDHHmmSSOHH'mm'`, where everything after the year may be left out.
+fn pdf_date_value(s: &str) -> Option<SystemTime> {
+    let s = s.strip_prefix("D:").unwrap_or(s);
+    let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+    if digits < 4 {
+        return None;
+    }
+    let defaults = "0101000000";
+    let full = format!("{}{}", &s[..digits], &defaults[(digits - 4).min(10)..]);
+    let naive = NaiveDateTime::parse_from_str(&full[..14], "%Y%m%d%H%M%S").ok()?;
+    let zone = &s[digits..];
+    let offset = match zone.chars().next() {
+        Some('Z') => Some(0),
+        Some(sign @ ('+' | '-')) => {
+            let nums: Vec<i32> = zone[1..]
+                .split('\'')
+                .filter_map(|p| p.parse().ok())
+                .collect();
+            let secs = nums.first()? * 3600 + nums.get(1).unwrap_or(&0) * 60;
+            Some(if sign == '-' { -secs } else { secs })
+        }
+        _ => None,
+    };
+    match offset {
+        Some(secs) => FixedOffset::east_opt(secs)?
+            .from_local_datetime(&naive)
+            .single()
+            .map(SystemTime::from),
+        None => local_time(naive),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, Utc};
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn local(y: i32, m: u32, d: u32, h: u32) -> SystemTime {
+        local_time(
+            NaiveDate::from_ymd_opt(y, m, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// A JPEG whose only metadata is a big-endian EXIF block with `DateTimeOriginal`.
+    fn jpeg_taken(date: &str) -> Vec<u8> {
+        let mut tiff = b"MM\0\x2a\0\0\0\x08".to_vec();
+        // IFD0 at 8: one entry pointing at the EXIF IFD at 26.
+        tiff.extend([0, 1, 0x87, 0x69, 0, 4, 0, 0, 0, 1, 0, 0, 0, 26, 0, 0, 0, 0]);
+        // EXIF IFD at 26: DateTimeOriginal, 20 ASCII bytes at 44.
+        tiff.extend([0, 1, 0x90, 0x03, 0, 2, 0, 0, 0, 20, 0, 0, 0, 44, 0, 0, 0, 0]);
+        tiff.extend(date.as_bytes());
+        tiff.push(0);
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend(((tiff.len() + 8) as u16).to_be_bytes());
+        jpeg.extend(b"Exif\0\0");
+        jpeg.extend(tiff);
+        jpeg.extend([0xFF, 0xDA, 0, 2, 0xFF, 0xD9]);
+        jpeg
+    }
+
+    #[test]
+    fn dates_are_read_from_exif_and_pdfs() {
+        let td = tempfile::tempdir().unwrap();
+        let write = |name: &str, data: &[u8]| {
+            let p = td.path().join(name);
+            fs::write(&p, data).unwrap();
+            p
+        };
+        let photo = write("a.jpg", &jpeg_taken("2021:06:05 14:00:00"));
+        assert_eq!(exif_date(&photo), Some(local(2021, 6, 5, 14)));
+        assert_eq!(exif_date(&write("b.jpg", &[0xFF, 0xD8, 0xFF, 0xD9])), None);
+
+        let pdf = write(
+            "a.pdf",
+            b"%PDF-1.7\n1 0 obj << /ModDate (D:20200101100000Z) >>\n\
+              2 0 obj << /ModDate (D:20220304120000+02'00') >>\n%%EOF",
+        );
+        let at = Utc.with_ymd_and_hms(2022, 3, 4, 10, 0, 0).unwrap();
+        assert_eq!(document_date(&pdf), Some(SystemTime::from(at)));
+        assert_eq!(pdf_date_value("D:2019"), Some(local(2019, 1, 1, 0)));
+
+        assert_eq!(document_date(&write("a.txt", b"plain")), None);
+    }
+
+    #[test]
+    fn newest_goes_by_the_chosen_date_and_falls_back_to_mtime() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path();
+        fs::write(root.join("p.jpg"), jpeg_taken("2020:01:01 00:00:00")).unwrap();
+        // Taken later, but synced (and named) earlier than the other conflict.
+        fs::write(
+            root.join("p.jpg.sync-conflict-20240101-000000-PHONE"),
+            jpeg_taken("2023:01:01 00:00:00"),
+        )
+        .unwrap();
+        fs::write(
+            root.join("p.jpg.sync-conflict-20240301-000000-LAPTOP"),
+            jpeg_taken("2022:01:01 00:00:00"),
+        )
+        .unwrap();
+        let mut g = crate::scan::scan_conflicts(root, true).unwrap().remove(0);
+        for (c, secs) in g.candidates.iter_mut().zip([300, 100, 200]) {
+            c.modified = Some(UNIX_EPOCH + Duration::from_secs(secs));
+        }
+        let label = |i: Option<usize>| {
+            conflict_meta(&g.candidates[i.unwrap()].path)
+                .map_or("Original".to_string(), |m| m.device)
+        };
+
+        assert_eq!(label(Recency::Mtime.newest_idx(&g)), "Original");
+        assert_eq!(label(Recency::Exif.newest_idx(&g)), "PHONE");
+        assert_eq!(label(Recency::Exif.oldest_idx(&g)), "Original");
+        // The original has no conflict name, so its mtime (1970) makes it the oldest.
+        assert_eq!(label(Recency::Name.newest_idx(&g)), "LAPTOP");
+        assert_eq!(label(Recency::Name.oldest_idx(&g)), "Original");
+        // No document dates anywhere: the same as mtime.
+        assert_eq!(label(Recency::Document.newest_idx(&g)), "Original");
+
+        assert_eq!("exif".parse::<Recency>().unwrap(), Recency::Exif);
+        assert!("ctime".parse::<Recency>().is_err());
+    }
+}
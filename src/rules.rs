@@ -0,0 +1,288 @@
+use crate::config::{loser_rule, Config, LoserRule};
+use crate::model::{ConflictGroup, Strategy};
+use crate::recency::Recency;
+use crate::scan::rel_path;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// `[[pick_rules]]`: keep the version `strategy` chooses for unpicked groups whose path
+/// (relative to the root) matches `glob`. The first matching rule wins.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PickRule {
+    pub glob: String,
+    #[serde(deserialize_with = "de_parse")]
+    pub strategy: Strategy,
+    /// What `newest` and `oldest` compare; mtime unless set.
+    #[serde(default, deserialize_with = "de_parse")]
+    pub by: Recency,
+}
+
+fn de_parse<'de, D, T>(d: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = anyhow::Error>,
+{
+    let s = String::deserialize(d)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+impl PickRule {
+    /// The candidate this rule keeps in `g`.
+    pub fn choose(&self, g: &ConflictGroup) -> Option<usize> {
+        self.strategy.choose_by(g, self.by)
+    }
+
+    /// `newest`, or `newest by exif` when the rule compares something else than mtimes.
+    pub fn label(&self) -> String {
+        if self.strategy.mtime_only() && self.by != Recency::Mtime {
+            format!("{} by {}", self.strategy.label(), self.by.label())
+        } else {
+            self.strategy.label().to_string()
+        }
+    }
+
+    /// A glob without `/` matches the file name in any folder; otherwise the whole
+    /// relative path (a leading `/` only anchors it at the root). `*` and `?` stay within
+    /// one path segment, `**` spans any number.
+    pub fn matches(&self, rel: &Path) -> bool {
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        if self.glob.contains('/') {
+            let glob = self.glob.strip_prefix('/').unwrap_or(&self.glob);
+            glob_match(glob.as_bytes(), rel.as_bytes())
+        } else {
+            let name = rel.rsplit('/').next().unwrap_or_default();
+            glob_match(self.glob.as_bytes(), name.as_bytes())
+        }
+    }
+
+    /// Whether every path `later` matches is already matched by this rule, judged by
+    /// matching `later`'s glob as if it were a path (a wildcard matches itself).
+    pub fn covers(&self, later: &PickRule) -> bool {
+        let glob = self.glob.strip_prefix('/').unwrap_or(&self.glob);
+        let other = later.glob.strip_prefix('/').unwrap_or(&later.glob);
+        match (self.glob.contains('/'), later.glob.contains('/')) {
+            (false, _) => {
+                let name = other.rsplit('/').next().unwrap_or_default();
+                glob_match(glob.as_bytes(), name.as_bytes())
+            }
+            // A glob without `/` matches names in any folder.
+            (true, false) => glob_match(glob.as_bytes(), format!("**/{other}").as_bytes()),
+            (true, true) => glob_match(glob.as_bytes(), other.as_bytes()),
+        }
+    }
+}
+
+/// Index of the first rule matching `rel`.
+pub fn pick_rule(rules: &[PickRule], rel: &Path) -> Option<usize> {
+    rules.iter().position(|r| r.matches(rel))
+}
+
+/// A rule that decided part of a resolved group, and what it did there.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FiredRule {
+    /// The rule by its content (`pick *.jpg: newest`, `loser >= 1.0 GiB: delete`), so it
+    /// keeps its name when rules are reordered.
+    pub rule: String,
+    /// `keep <path>`, `delete <path>` or `archive <path>`, relative to the root.
+    pub action: String,
+}
+
+/// The rules behind `g`'s resolution: the pick rule whose strategy keeps the chosen
+/// version, and the loser rule matching each other version. Versions left to the
+/// default (archive) have no rule.
+pub fn fired_rules(
+    root: &Path,
+    g: &ConflictGroup,
+    pick_rules: &[PickRule],
+    loser_rules: &[LoserRule],
+) -> Vec<FiredRule> {
+    let Some(chosen) = g.chosen else {
+        return Vec::new();
+    };
+    let rel = |i: usize| rel_path(root, &g.candidates[i].path).display().to_string();
+    let mut fired = Vec::new();
+    if let Some(r) = pick_rule(pick_rules, rel_path(root, &g.base_path)) {
+        let r = &pick_rules[r];
+        if r.choose(g) == Some(chosen) {
+            fired.push(FiredRule {
+                rule: format!("pick {}: {}", r.glob, r.label()),
+                action: format!("keep {}", rel(chosen)),
+            });
+        }
+    }
+    for (i, c) in g.candidates.iter().enumerate() {
+        if i == chosen || !c.exists {
+            continue;
+        }
+        if let Some(l) = loser_rule(loser_rules, c.size) {
+            let l = &loser_rules[l];
+            fired.push(FiredRule {
+                rule: format!("loser {}: {}", l.sizes(), l.action.label()),
+                action: format!("{} {}", l.action.label(), rel(i)),
+            });
+        }
+    }
+    fired
+}
+
+fn glob_match(pat: &[u8], text: &[u8]) -> bool {
+    match pat {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            // `**/` also matches no folders at all.
+            let rest = rest.strip_prefix(b"/").unwrap_or(rest);
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match(rest, &text[i..])),
+        [b'?', rest @ ..] => {
+            matches!(text, [c, tail @ ..] if *c != b'/' && glob_match(rest, tail))
+        }
+        [p, rest @ ..] => matches!(text, [c, tail @ ..] if c == p && glob_match(rest, tail)),
+    }
+}
+
+/// Appends `rule` to the config file at `path` (created, with its folder, if missing).
+/// The result is parsed before it is written, so a failure leaves the file unchanged.
+pub fn append_pick_rule(path: &Path, rule: &PickRule) -> Result<()> {
+    let mut text = match fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("read config {path:?}")),
+    };
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text.push_str(&format!(
+        "[[pick_rules]]\nglob = {}\nstrategy = \"{}\"\n",
+        toml::Value::String(rule.glob.clone()),
+        rule.strategy.label()
+    ));
+    if rule.by != Recency::Mtime {
+        text.push_str(&format!("by = \"{}\"\n", rule.by.label()));
+    }
+    Config::parse(&text).with_context(|| format!("config {path:?} would no longer load"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("create {dir:?}"))?;
+    }
+    fs::write(path, text).with_context(|| format!("write config {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(glob: &str) -> PickRule {
+        PickRule {
+            glob: glob.to_string(),
+            strategy: Strategy::Newest,
+            by: Recency::Mtime,
+        }
+    }
+
+    #[test]
+    fn globs_match_names_or_relative_paths() {
+        let m = |glob: &str, rel: &str| rule(glob).matches(Path::new(rel));
+        assert!(m("*.jpg", "photos/2024/a.jpg"));
+        assert!(!m("*.jpg", "photos/a.jpeg"));
+        assert!(m("photos/*.jpg", "photos/a.jpg"));
+        assert!(!m("photos/*.jpg", "photos/2024/a.jpg"));
+        assert!(m("photos/**/*.jpg", "photos/2024/a.jpg"));
+        assert!(m("photos/**/*.jpg", "photos/a.jpg"));
+        assert!(m("/*.jpg", "a.jpg"));
+        assert!(!m("/*.jpg", "photos/a.jpg"));
+        assert!(m("notes-??.md", "notes-01.md"));
+        assert!(!m("notes-??.md", "notes-1.md"));
+        assert_eq!(
+            pick_rule(&[rule("*.md"), rule("*.jpg")], Path::new("a.jpg")),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn broader_globs_cover_narrower_ones() {
+        let covers = |a: &str, b: &str| rule(a).covers(&rule(b));
+        assert!(covers("*.jpg", "*.jpg"));
+        assert!(covers("*.jpg", "photos/*.jpg"));
+        assert!(covers("*.jpg", "IMG_*.jpg"));
+        assert!(covers("*", "photos/**"));
+        assert!(covers("photos/**", "photos/2024/*.jpg"));
+        assert!(covers("**/*.jpg", "*.jpg"));
+        assert!(covers("/photos/*.jpg", "photos/a?.jpg"));
+        assert!(!covers("photos/*.jpg", "*.jpg"));
+        assert!(!covers("photos/*.jpg", "photos/**/*.jpg"));
+        assert!(!covers("IMG_*.jpg", "*.jpg"));
+        assert!(!covers("*.jpg", "*.md"));
+    }
+
+    #[test]
+    fn fired_rules_name_the_pick_and_loser_rules_by_content() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path();
+        fs::write(root.join("a.jpg"), "orig").unwrap();
+        fs::write(
+            root.join("a.jpg.sync-conflict-20240101-010101-DEV"),
+            "conflict!",
+        )
+        .unwrap();
+        let mut g = crate::scan::scan_conflicts(root, true).unwrap().remove(0);
+        let picks = [PickRule {
+            glob: "*.jpg".to_string(),
+            strategy: Strategy::Current,
+            by: Recency::Mtime,
+        }];
+        let losers = [LoserRule {
+            min_size: Some(5),
+            max_size: None,
+            action: crate::config::LoserAction::Delete,
+        }];
+        assert_eq!(fired_rules(root, &g, &picks, &losers), []);
+
+        g.chosen = Some(0);
+        assert_eq!(
+            fired_rules(root, &g, &picks, &losers),
+            [
+                FiredRule {
+                    rule: "pick *.jpg: current".to_string(),
+                    action: "keep a.jpg".to_string(),
+                },
+                FiredRule {
+                    rule: "loser >= 5 B: delete".to_string(),
+                    action: "delete a.jpg.sync-conflict-20240101-010101-DEV".to_string(),
+                },
+            ]
+        );
+        // Kept against the rule's strategy, and the small original falls to the default.
+        g.chosen = Some(1);
+        assert_eq!(fired_rules(root, &g, &picks, &losers), []);
+    }
+
+    #[test]
+    fn appended_rules_load_back_after_existing_settings() {
+        let td = tempfile::tempdir().unwrap();
+        let path = td.path().join("sub/config.toml");
+        append_pick_rule(&path, &rule("photos/*.jpg")).unwrap();
+        fs::write(
+            &path,
+            fs::read_to_string(&path).unwrap() + "[syncthing]\napi_key = \"k\"",
+        )
+        .unwrap();
+        let quoted = PickRule {
+            glob: "say \"hi\"/*".to_string(),
+            strategy: Strategy::Trusted,
+            by: Recency::Mtime,
+        };
+        append_pick_rule(&path, &quoted).unwrap();
+        let config = Config::load(Some(&path)).unwrap();
+        assert_eq!(config.pick_rules, [rule("photos/*.jpg"), quoted]);
+        assert!(config.syncthing.is_some());
+    }
+}
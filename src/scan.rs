@@ -1,11 +1,24 @@
+use crate::cleanup::nested_kind;
+use crate::filetype::{group_kind, FileKind};
+use crate::fmt::{format_age, format_size};
 use crate::model::{Candidate, ConflictGroup};
+use crate::scancache::Checkpoint;
 use anyhow::Result;
-use std::collections::BTreeMap;
+use chrono::{Local, NaiveDateTime, TimeZone};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use walkdir::WalkDir;
 
-fn is_conflict_name(file_name: &str) -> Option<&str> {
+/// How often a checkpointed walk saves how far it got.
+pub const CHECKPOINT_EVERY: Duration = Duration::from_secs(30);
+
+pub fn is_conflict_name(file_name: &str) -> Option<&str> {
     // Syncthing conflict files usually look like:
     //   <base>.sync-conflict-YYYYMMDD-HHMMSS-DEVICE
     // We treat anything containing ".sync-conflict-" as a conflict file.
@@ -14,7 +27,49 @@ fn is_conflict_name(file_name: &str) -> Option<&str> {
         .map(|idx| &file_name[..idx])
 }
 
-fn stat_candidate(path: PathBuf, is_original: bool, label: String) -> Candidate {
+/// Fields Syncthing encodes in a conflict file name
+/// (`.sync-conflict-YYYYMMDD-HHMMSS-DEVICE`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConflictMeta {
+    pub date: String,
+    pub time: String,
+    pub device: String,
+}
+
+impl ConflictMeta {
+    /// When the conflict was recorded, read as local time like Syncthing writes it.
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        let naive =
+            NaiveDateTime::parse_from_str(&format!("{}{}", self.date, self.time), "%Y%m%d%H%M%S")
+                .ok()?;
+        Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(SystemTime::from)
+    }
+}
+
+pub fn conflict_meta(path: &Path) -> Option<ConflictMeta> {
+    let name = path.file_name()?.to_string_lossy();
+    let idx = name.find(".sync-conflict-")?;
+    let rest = &name[idx + ".sync-conflict-".len()..];
+    let mut parts = rest.splitn(3, '-');
+    let date = parts.next()?;
+    let time = parts.next()?;
+    // The device ID runs until an extension (if Syncthing appended one).
+    let device = parts.next()?.split('.').next()?;
+    let digits = |s: &str, n: usize| s.len() == n && s.bytes().all(|b| b.is_ascii_digit());
+    if !digits(date, 8) || !digits(time, 6) || device.is_empty() {
+        return None;
+    }
+    Some(ConflictMeta {
+        date: date.to_string(),
+        time: time.to_string(),
+        device: device.to_string(),
+    })
+}
+
+pub fn stat_candidate(path: PathBuf, is_original: bool, label: String) -> Candidate {
     let meta = fs::metadata(&path).ok();
     Candidate {
         exists: meta.is_some(),
@@ -23,14 +78,254 @@ fn stat_candidate(path: PathBuf, is_original: bool, label: String) -> Candidate
         path,
         is_original,
         label,
+        trust: None,
+    }
+}
+
+/// What a plan relies on staying put until it is applied: existence, size, mtime and
+/// (on Unix) the inode, which changes when Syncthing swaps a new file in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileStamp {
+    pub exists: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub inode: Option<u64>,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Self {
+        let Ok(meta) = fs::metadata(path) else {
+            return Self::default();
+        };
+        #[cfg(unix)]
+        let inode = Some(std::os::unix::fs::MetadataExt::ino(&meta));
+        #[cfg(not(unix))]
+        let inode = None;
+        Self {
+            exists: true,
+            size: meta.len(),
+            modified: meta.modified().ok(),
+            inode,
+        }
+    }
+
+    /// How the file changed between `self` and `now`, if it did.
+    pub fn change(&self, now: &FileStamp) -> Option<&'static str> {
+        match (self.exists, now.exists) {
+            (false, false) => None,
+            (false, true) => Some("appeared"),
+            (true, false) => Some("deleted"),
+            _ if self.inode != now.inode => Some("replaced"),
+            _ if self != now => Some("modified"),
+            _ => None,
+        }
+    }
+}
+
+/// What [`scan_conflicts_with`] walks into.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Also scan dot-files and dot-directories.
+    pub include_hidden: bool,
+    /// Descend into symlinked directories and pick up symlinked files. Apply still refuses
+    /// symlinked paths unless `ApplyOptions::allow_symlinks` is set.
+    pub follow_symlinks: bool,
+    /// Only keep groups with a conflict copy matching this.
+    pub filter: ConflictFilter,
+}
+
+/// Age and size limits on conflict copies. A group is kept when at least one of its
+/// conflicts matches every set limit; the original never counts. `kind` is checked on
+/// the group as a whole.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConflictFilter {
+    pub older_than: Option<Duration>,
+    pub newer_than: Option<Duration>,
+    pub min_size: Option<u64>,
+    pub kind: Option<FileKind>,
+}
+
+impl ConflictFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether conflict `c` matches at `now`. Its age counts from the timestamp in its
+    /// name (when the conflict happened), falling back to the mtime; a conflict whose age
+    /// or size is unknown fails the limits that need it.
+    pub fn matches(&self, c: &Candidate, now: SystemTime) -> bool {
+        let when = conflict_meta(&c.path)
+            .and_then(|m| m.timestamp())
+            .or(c.modified);
+        let age = when.map(|t| now.duration_since(t).unwrap_or_default());
+        self.older_than
+            .is_none_or(|min| age.is_some_and(|a| a >= min))
+            && self
+                .newer_than
+                .is_none_or(|max| age.is_some_and(|a| a <= max))
+            && self
+                .min_size
+                .is_none_or(|min| c.size.is_some_and(|s| s >= min))
+    }
+
+    /// Keeps the groups with a matching conflict.
+    pub fn apply(&self, groups: Vec<ConflictGroup>, now: SystemTime) -> Vec<ConflictGroup> {
+        if self.is_empty() {
+            return groups;
+        }
+        groups
+            .into_iter()
+            .filter(|g| {
+                g.candidates
+                    .iter()
+                    .any(|c| !c.is_original && c.exists && self.matches(c, now))
+                    && self.kind.is_none_or(|k| group_kind(g) == k)
+            })
+            .collect()
+    }
+
+    /// E.g. `newer than 7d, at least 1.0 MiB, image`; empty without limits.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(d) = self.older_than {
+            parts.push(format!("older than {}", format_age(d)));
+        }
+        if let Some(d) = self.newer_than {
+            parts.push(format!("newer than {}", format_age(d)));
+        }
+        if let Some(s) = self.min_size {
+            parts.push(format!("at least {}", format_size(s)));
+        }
+        if let Some(k) = self.kind {
+            parts.push(k.label().to_string());
+        }
+        parts.join(", ")
     }
 }
 
 pub fn scan_conflicts(root: &Path, include_hidden: bool) -> Result<Vec<ConflictGroup>> {
-    let mut by_base: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    scan_conflicts_with(
+        root,
+        ScanOptions {
+            include_hidden,
+            ..ScanOptions::default()
+        },
+    )
+}
+
+pub fn scan_conflicts_with(root: &Path, options: ScanOptions) -> Result<Vec<ConflictGroup>> {
+    let mut by_base = BTreeMap::new();
+    walk_conflicts(root, root, None, options, None, None, &mut by_base)?;
+    Ok(build_groups(by_base, options.filter))
+}
+
+/// Rescans only `dirs`, not their subdirectories: the groups whose base file lies directly
+/// in one of them, e.g. the folders an apply just touched. Directories that are gone
+/// yield nothing.
+pub fn scan_dirs(
+    root: &Path,
+    dirs: &BTreeSet<PathBuf>,
+    options: ScanOptions,
+) -> Result<Vec<ConflictGroup>> {
+    let mut by_base = BTreeMap::new();
+    for dir in dirs.iter().filter(|d| d.is_dir()) {
+        walk_conflicts(root, dir, Some(1), options, None, None, &mut by_base)?;
+    }
+    Ok(build_groups(by_base, options.filter))
+}
 
-    for entry in WalkDir::new(root).follow_links(false) {
-        let entry = entry?;
+/// Runs [`scan_conflicts_with`] on a background thread and sends its result. Setting
+/// `cancel` stops the walk at the next entry; the channel then closes without a result.
+///
+/// With a `checkpoint` file, the walk saves how far it got there every
+/// [`CHECKPOINT_EVERY`] and when cancelled, and resumes from a saved [`Checkpoint`]
+/// instead of starting over. The file is removed once the walk completes.
+pub fn spawn_scan(
+    root: PathBuf,
+    options: ScanOptions,
+    cancel: Arc<AtomicBool>,
+    checkpoint: Option<PathBuf>,
+) -> Receiver<Result<Vec<ConflictGroup>>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut by_base = BTreeMap::new();
+        let res = walk_conflicts(
+            &root,
+            &root,
+            None,
+            options,
+            Some(&cancel),
+            checkpoint.as_deref(),
+            &mut by_base,
+        );
+        if !cancel.load(Ordering::Relaxed) {
+            let _ = tx.send(res.map(|()| build_groups(by_base, options.filter)));
+        }
+    });
+    rx
+}
+
+/// Adds the conflict copies below `start` (at most `max_depth` levels down) to `by_base`,
+/// keyed by their base path; stops early once `cancel` is set. With a `checkpoint` file
+/// the walk goes in name order, so it can skip what a saved checkpoint already covers.
+fn walk_conflicts(
+    root: &Path,
+    start: &Path,
+    max_depth: Option<usize>,
+    options: ScanOptions,
+    cancel: Option<&AtomicBool>,
+    checkpoint: Option<&Path>,
+    by_base: &mut BTreeMap<PathBuf, Vec<PathBuf>>,
+) -> Result<()> {
+    let include_hidden = options.include_hidden;
+    let mut walk = WalkDir::new(start).follow_links(options.follow_symlinks);
+    if let Some(depth) = max_depth {
+        walk = walk.max_depth(depth);
+    }
+    let mut resume_at = None;
+    if let Some(file) = checkpoint {
+        if let Some(saved) = Checkpoint::load(file, root) {
+            for path in &saved.conflicts {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                if let Some(base_name) = is_conflict_name(&name) {
+                    add_conflict(root, path, base_name, by_base);
+                }
+            }
+            resume_at = Some(saved.resume_at);
+        }
+        walk = walk.sort_by_file_name();
+    }
+    let mut saved_at = Instant::now();
+    let mut entries = walk.into_iter();
+    while let Some(entry) = entries.next() {
+        let entry = match entry {
+            // A link back to an ancestor; everything below it was already walked.
+            Err(e) if e.loop_ancestor().is_some() => continue,
+            entry => entry?,
+        };
+        if let Some(at) = &resume_at {
+            // Name order walks parents before their children, so everything sorting
+            // before the checkpoint was covered, except the folders leading to it.
+            if entry.path() < at.as_path() {
+                if entry.file_type().is_dir() && !at.starts_with(entry.path()) {
+                    entries.skip_current_dir();
+                }
+                continue;
+            }
+            resume_at = None;
+        }
+        let cancelled = cancel.is_some_and(|c| c.load(Ordering::Relaxed));
+        if let Some(file) = checkpoint {
+            if cancelled || saved_at.elapsed() >= CHECKPOINT_EVERY {
+                let conflicts = by_base.values().flatten().cloned().collect();
+                // Best effort: a failed save only costs the resume.
+                let _ = Checkpoint::new(root, entry.path(), conflicts).save(file);
+                saved_at = Instant::now();
+            }
+        }
+        if cancelled {
+            return Ok(());
+        }
         if !entry.file_type().is_file() {
             continue;
         }
@@ -53,16 +348,100 @@ pub fn scan_conflicts(root: &Path, include_hidden: bool) -> Result<Vec<ConflictG
         let Some(base_name) = is_conflict_name(&file_name) else {
             continue;
         };
+        // Copies in `.stversions` or an archive are cleanup leftovers, not conflicts.
+        if nested_kind(rel_path(root, entry.path())).is_some() {
+            continue;
+        }
+        // Windows can't create the base file, so apply could never resolve the group.
+        if cfg!(windows) && is_reserved_windows_name(base_name) {
+            continue;
+        }
+
+        add_conflict(root, entry.path(), base_name, by_base);
+    }
+    if let Some(file) = checkpoint {
+        let _ = fs::remove_file(file);
+    }
+    Ok(())
+}
 
-        let base_path = entry.path().parent().unwrap_or(root).join(base_name);
-        by_base
-            .entry(base_path)
-            .or_default()
-            .push(entry.path().to_path_buf());
+fn add_conflict(
+    root: &Path,
+    path: &Path,
+    base_name: &str,
+    by_base: &mut BTreeMap<PathBuf, Vec<PathBuf>>,
+) {
+    let base_path = path.parent().unwrap_or(root).join(base_name);
+    by_base
+        .entry(group_key(&base_path))
+        .or_default()
+        .push(path.to_path_buf());
+}
+
+/// What conflicts are grouped by: the base path, case-folded on Windows, where
+/// `Notes.txt` and `notes.txt` are the same file.
+fn group_key(base_path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(base_path.to_string_lossy().to_lowercase())
+    } else {
+        base_path.to_path_buf()
+    }
+}
+
+/// The base path of a case-folded group: the original's name as it is on disk, else the
+/// one the first conflict copy names.
+fn on_disk_case(conflict_paths: &[PathBuf], key: PathBuf) -> PathBuf {
+    let Some((dir, base_name)) = conflict_paths.first().and_then(|p| {
+        let name = p.file_name()?.to_string_lossy();
+        Some((p.parent()?, is_conflict_name(&name)?.to_string()))
+    }) else {
+        return key;
+    };
+    let on_disk = fs::read_dir(dir).ok().and_then(|entries| {
+        entries
+            .flatten()
+            .map(|e| e.file_name())
+            .find(|n| n.to_string_lossy().to_lowercase() == base_name.to_lowercase())
+    });
+    dir.join(on_disk.unwrap_or_else(|| base_name.into()))
+}
+
+/// Whether Windows refuses `name` as a file name: the device names (`CON`, `PRN`, `AUX`,
+/// `NUL`, `COM1`-`COM9`, `LPT1`-`LPT9`, with any extension), names ending in a dot or a
+/// space, and names with `<>:"/\|?*` or control characters.
+pub fn is_reserved_windows_name(name: &str) -> bool {
+    if name.ends_with(['.', ' ']) || name.chars().any(|c| c < ' ' || "<>:\"/\\|?*".contains(c)) {
+        return true;
+    }
+    let stem = name
+        .split('.')
+        .next()
+        .unwrap_or(name)
+        .trim_end()
+        .to_ascii_uppercase();
+    match stem.as_str() {
+        "CON" | "PRN" | "AUX" | "NUL" => true,
+        s => {
+            (s.starts_with("COM") || s.starts_with("LPT"))
+                && s.len() == 4
+                && s.as_bytes()[3].is_ascii_digit()
+                && s.as_bytes()[3] != b'0'
+        }
     }
+}
 
+/// One group per base path: the original first, then its conflicts sorted by path.
+fn build_groups(
+    by_base: BTreeMap<PathBuf, Vec<PathBuf>>,
+    filter: ConflictFilter,
+) -> Vec<ConflictGroup> {
     let mut groups = Vec::new();
-    for (base_path, conflict_paths) in by_base {
+    for (key, conflict_paths) in by_base {
+        let base_path = if cfg!(windows) {
+            on_disk_case(&conflict_paths, key)
+        } else {
+            key
+        };
         let mut candidates = Vec::new();
         candidates.push(stat_candidate(
             base_path.clone(),
@@ -86,10 +465,11 @@ pub fn scan_conflicts(root: &Path, include_hidden: bool) -> Result<Vec<ConflictG
             base_path,
             candidates,
             chosen: None,
+            references: Vec::new(),
         });
     }
 
-    Ok(groups)
+    filter.apply(groups, SystemTime::now())
 }
 
 pub fn rel_path<'a>(root: &'a Path, p: &'a Path) -> &'a Path {
@@ -110,6 +490,27 @@ mod tests {
         f.write_all(content.as_bytes()).unwrap();
     }
 
+    #[test]
+    fn conflict_meta_parses_stamp_and_device() {
+        let m = conflict_meta(Path::new(
+            "a/notes.txt.sync-conflict-20240101-010203-ABCDEFG",
+        ))
+        .unwrap();
+        assert_eq!(m.date, "20240101");
+        assert_eq!(m.time, "010203");
+        assert_eq!(m.device, "ABCDEFG");
+
+        let m = conflict_meta(Path::new("notes.sync-conflict-20240101-010203-XYZ.txt")).unwrap();
+        assert_eq!(m.device, "XYZ");
+        let local = Local.with_ymd_and_hms(2024, 1, 1, 1, 2, 3).unwrap();
+        assert_eq!(m.timestamp(), Some(SystemTime::from(local)));
+        let bad = conflict_meta(Path::new("n.sync-conflict-20241399-010203-XYZ")).unwrap();
+        assert_eq!(bad.timestamp(), None);
+
+        assert!(conflict_meta(Path::new("notes.txt")).is_none());
+        assert!(conflict_meta(Path::new("n.sync-conflict-2024-01-X")).is_none());
+    }
+
     #[test]
     fn rel_path_strips_prefix() {
         let root = Path::new("/a/b");
@@ -139,6 +540,242 @@ mod tests {
         assert_eq!(g.candidates[0].path, g.base_path);
     }
 
+    #[test]
+    fn filter_keeps_groups_with_a_matching_conflict() {
+        let td = tempdir().unwrap();
+        let root = td.path();
+        write_file(&root.join("a.txt"), "orig");
+        // Named 2024-01-01: old by any clock this test runs on.
+        write_file(
+            &root.join("a.txt.sync-conflict-20240101-010101-DEV"),
+            "small",
+        );
+        write_file(&root.join("b.txt"), "orig");
+        // No timestamp in the name: the (current) mtime decides.
+        write_file(&root.join("b.txt.sync-conflict-x"), &"x".repeat(2048));
+
+        let scan = |filter| {
+            let options = ScanOptions {
+                include_hidden: true,
+                filter,
+                ..ScanOptions::default()
+            };
+            let groups = scan_conflicts_with(root, options).unwrap();
+            groups
+                .iter()
+                .map(|g| rel_path(root, &g.base_path).display().to_string())
+                .collect::<Vec<_>>()
+        };
+        let day = Duration::from_secs(86_400);
+        assert_eq!(scan(ConflictFilter::default()), ["a.txt", "b.txt"]);
+        let recent = ConflictFilter {
+            newer_than: Some(7 * day),
+            ..ConflictFilter::default()
+        };
+        assert_eq!(scan(recent), ["b.txt"]);
+        let old = ConflictFilter {
+            older_than: Some(30 * day),
+            ..ConflictFilter::default()
+        };
+        assert_eq!(scan(old), ["a.txt"]);
+        let big = ConflictFilter {
+            min_size: Some(1024),
+            ..ConflictFilter::default()
+        };
+        assert_eq!(scan(big), ["b.txt"]);
+        assert!(scan(ConflictFilter {
+            min_size: Some(1024),
+            ..old
+        })
+        .is_empty());
+        let text = ConflictFilter {
+            kind: Some(FileKind::Text),
+            ..ConflictFilter::default()
+        };
+        assert_eq!(scan(text), ["a.txt", "b.txt"]);
+        let images = ConflictFilter {
+            kind: Some(FileKind::Image),
+            ..ConflictFilter::default()
+        };
+        assert!(scan(images).is_empty());
+        assert_eq!(
+            ConflictFilter {
+                min_size: Some(1024),
+                ..recent
+            }
+            .describe(),
+            "newer than 7d, at least 1.0 KiB"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_dirs_are_scanned_only_when_following() {
+        let td = tempdir().unwrap();
+        let root = td.path().join("root");
+        let outside = td.path().join("outside");
+        write_file(
+            &outside.join("f.txt.sync-conflict-20240101-010101-DEV"),
+            "c",
+        );
+        fs::create_dir_all(&root).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("linked")).unwrap();
+        // A loop must not abort the scan.
+        std::os::unix::fs::symlink(&root, root.join("loop")).unwrap();
+
+        assert!(scan_conflicts(&root, true).unwrap().is_empty());
+        let options = ScanOptions {
+            include_hidden: true,
+            follow_symlinks: true,
+            ..ScanOptions::default()
+        };
+        let groups = scan_conflicts_with(&root, options).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].base_path, root.join("linked/f.txt"));
+    }
+
+    #[test]
+    fn scan_dirs_rescans_only_the_given_folders() {
+        let td = tempdir().unwrap();
+        let root = td.path();
+        for dir in ["a", "a/sub", "b"] {
+            write_file(
+                &root
+                    .join(dir)
+                    .join("f.txt.sync-conflict-20240101-010101-DEV"),
+                "c",
+            );
+        }
+        let dirs = BTreeSet::from([root.join("a"), root.join("gone")]);
+        let options = ScanOptions {
+            include_hidden: true,
+            ..ScanOptions::default()
+        };
+        let groups = scan_dirs(root, &dirs, options).unwrap();
+        let bases: Vec<_> = groups.iter().map(|g| g.base_path.clone()).collect();
+        assert_eq!(bases, [root.join("a/f.txt")]);
+    }
+
+    #[test]
+    fn spawned_scans_send_their_groups_unless_cancelled() {
+        let td = tempdir().unwrap();
+        let root = td.path().to_path_buf();
+        write_file(&root.join("f.txt.sync-conflict-20240101-010101-DEV"), "c");
+        let options = ScanOptions {
+            include_hidden: true,
+            ..ScanOptions::default()
+        };
+        let rx = spawn_scan(
+            root.clone(),
+            options,
+            Arc::new(AtomicBool::new(false)),
+            None,
+        );
+        assert_eq!(rx.recv().unwrap().unwrap().len(), 1);
+
+        let rx = spawn_scan(root, options, Arc::new(AtomicBool::new(true)), None);
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn checkpointed_scans_resume_where_they_stopped() {
+        let td = tempdir().unwrap();
+        let root = td.path().join("root");
+        let file = td.path().join("scans").join("root.json");
+        write_file(&root.join("a/f.txt.sync-conflict-20240101-010101-DEV"), "c");
+        write_file(&root.join("b/g.txt.sync-conflict-20240101-010101-DEV"), "c");
+        write_file(&root.join("c/h.txt.sync-conflict-20240101-010101-DEV"), "c");
+        let options = ScanOptions {
+            include_hidden: true,
+            ..ScanOptions::default()
+        };
+
+        // Cancelling saves a checkpoint at the first entry: nothing found yet.
+        let rx = spawn_scan(
+            root.clone(),
+            options,
+            Arc::new(AtomicBool::new(true)),
+            Some(file.clone()),
+        );
+        assert!(rx.recv().is_err());
+        let saved = Checkpoint::load(&file, &root).unwrap();
+        assert_eq!((saved.resume_at, saved.conflicts), (root.clone(), vec![]));
+
+        // A checkpoint inside `b` after its conflict: `a` and `b` aren't walked again,
+        // so only what the checkpoint lists is found there.
+        let listed = root.join("a/f.txt.sync-conflict-20240101-010101-DEV");
+        Checkpoint::new(&root, &root.join("b/zz"), vec![listed])
+            .save(&file)
+            .unwrap();
+        let rx = spawn_scan(
+            root.clone(),
+            options,
+            Arc::new(AtomicBool::new(false)),
+            Some(file.clone()),
+        );
+        let groups = rx.recv().unwrap().unwrap();
+        let bases: Vec<_> = groups
+            .iter()
+            .map(|g| rel_path(&root, &g.base_path).to_path_buf())
+            .collect();
+        assert_eq!(bases, [PathBuf::from("a/f.txt"), PathBuf::from("c/h.txt")]);
+        assert!(!file.exists(), "a completed walk removes its checkpoint");
+    }
+
+    #[test]
+    fn windows_reserved_names_are_recognized() {
+        for name in [
+            "CON",
+            "con.txt",
+            "Aux.tar.gz",
+            "nul ",
+            "COM1.log",
+            "lpt9",
+            "a:b",
+            "x.",
+        ] {
+            assert!(is_reserved_windows_name(name), "{name}");
+        }
+        for name in [
+            "console.txt",
+            "COM0",
+            "COM10",
+            "LPT",
+            "notes.md",
+            "auxiliary",
+        ] {
+            assert!(!is_reserved_windows_name(name), "{name}");
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_groups_ignore_case_and_skip_reserved_base_names() {
+        use crate::ops::long_path;
+        let td = tempdir().unwrap();
+        let root = td.path();
+        write_file(&root.join("Notes.txt"), "orig");
+        write_file(
+            &root.join("Notes.txt.sync-conflict-20240101-010101-AAA"),
+            "a",
+        );
+        write_file(
+            &root.join("notes.TXT.sync-conflict-20240102-010101-BBB"),
+            "b",
+        );
+        // Only creatable through a verbatim path; Windows can't make `CON.txt` itself.
+        write_file(
+            &long_path(&root.join("CON.txt.sync-conflict-20240101-010101-AAA")),
+            "c",
+        );
+
+        let groups = scan_conflicts(root, true).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].base_path, root.join("Notes.txt"));
+        assert_eq!(groups[0].candidates.len(), 3);
+        assert!(groups[0].candidates[0].exists);
+    }
+
     #[test]
     fn scan_ignores_hidden_dirs_by_default() {
         let td = tempdir().unwrap();
@@ -157,4 +794,54 @@ mod tests {
         let groups = scan_conflicts(root, true).unwrap();
         assert_eq!(groups.len(), 1);
     }
+
+    #[test]
+    fn file_stamps_tell_replaced_from_modified_files() {
+        let td = tempdir().unwrap();
+        let path = td.path().join("f.txt");
+        let missing = FileStamp::of(&path);
+        write_file(&path, "a");
+        let first = FileStamp::of(&path);
+        assert_eq!(missing.change(&first), Some("appeared"));
+        assert_eq!(first.change(&FileStamp::of(&path)), None);
+
+        write_file(&path, "ab");
+        let modified = FileStamp::of(&path);
+        assert_eq!(first.change(&modified), Some("modified"));
+        // Syncthing writes a temp file and renames it over the original.
+        let tmp = td.path().join(".syncthing.f.txt.tmp");
+        write_file(&tmp, "abc");
+        fs::rename(&tmp, &path).unwrap();
+        let replaced = FileStamp::of(&path);
+        if cfg!(unix) {
+            assert_eq!(modified.change(&replaced), Some("replaced"));
+        }
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replaced.change(&FileStamp::of(&path)), Some("deleted"));
+    }
+
+    #[test]
+    fn conflicts_in_versions_and_archives_are_not_groups() {
+        let td = tempdir().unwrap();
+        let root = td.path();
+        for dir in [".stversions", ".stconflict-archive", "docs/.stversions/sub"] {
+            write_file(
+                &root
+                    .join(dir)
+                    .join("f.txt.sync-conflict-20240101-010101-DEV"),
+                "c",
+            );
+        }
+        write_file(
+            &root.join("docs/g.txt.sync-conflict-20240101-010101-DEV"),
+            "c",
+        );
+
+        let groups = scan_conflicts(root, true).unwrap();
+        let bases: Vec<_> = groups
+            .iter()
+            .map(|g| rel_path(root, &g.base_path))
+            .collect();
+        assert_eq!(bases, [Path::new("docs/g.txt")]);
+    }
 }
@@ -0,0 +1,134 @@
+use crate::scan::ScanOptions;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Checkpoints older than this are ignored: too much may have changed behind the walk.
+pub const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// `<cache dir>/synctui-resolver/scans`.
+pub fn default_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|d| d.join("synctui-resolver").join("scans"))
+}
+
+/// The checkpoint file in `dir` for walking `root` with `options`, named after a hash of
+/// both. The filter isn't part of it: it is applied after the walk.
+pub fn checkpoint_path(dir: &Path, root: &Path, options: ScanOptions) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(root.as_os_str().as_encoded_bytes());
+    hasher.update([
+        u8::from(options.include_hidden),
+        u8::from(options.follow_symlinks),
+    ]);
+    let hex: String = hasher.finalize()[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    dir.join(format!("{hex}.json"))
+}
+
+/// How far an interrupted walk got: every entry sorting before `resume_at` was walked,
+/// and these are the conflict copies it found.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub root: PathBuf,
+    pub resume_at: PathBuf,
+    /// Seconds since the Unix epoch.
+    pub saved_at: u64,
+    pub conflicts: Vec<PathBuf>,
+}
+
+impl Checkpoint {
+    pub fn new(root: &Path, resume_at: &Path, conflicts: Vec<PathBuf>) -> Self {
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self {
+            root: root.to_path_buf(),
+            resume_at: resume_at.to_path_buf(),
+            saved_at,
+            conflicts,
+        }
+    }
+
+    /// The checkpoint at `path` for `root`, unless it is missing, unreadable, for another
+    /// root or older than [`MAX_AGE`]. Conflict copies deleted since are dropped.
+    pub fn load(path: &Path, root: &Path) -> Option<Self> {
+        let text = fs::read_to_string(path).ok()?;
+        let mut checkpoint: Self = serde_json::from_str(&text).ok()?;
+        let saved = UNIX_EPOCH + Duration::from_secs(checkpoint.saved_at);
+        let age = SystemTime::now().duration_since(saved).unwrap_or_default();
+        if checkpoint.root != root || age > MAX_AGE {
+            return None;
+        }
+        checkpoint.conflicts.retain(|p| p.exists());
+        Some(checkpoint)
+    }
+
+    /// Writes to a temporary file first, so an interrupted save leaves the previous
+    /// checkpoint intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {parent:?}"))?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?).with_context(|| format!("write {tmp:?}"))?;
+        fs::rename(&tmp, path).with_context(|| format!("rename {tmp:?} to {path:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn checkpoints_round_trip_unless_stale_or_for_another_root() {
+        let td = tempdir().unwrap();
+        let root = td.path().join("root");
+        fs::create_dir_all(&root).unwrap();
+        let kept = root.join("a.txt.sync-conflict-20240101-010101-DEV");
+        fs::write(&kept, "c").unwrap();
+        let path = checkpoint_path(&td.path().join("scans"), &root, ScanOptions::default());
+        let mut checkpoint = Checkpoint::new(
+            &root,
+            &root.join("m"),
+            vec![
+                kept.clone(),
+                root.join("gone.sync-conflict-20240101-010101-DEV"),
+            ],
+        );
+        checkpoint.save(&path).unwrap();
+
+        let loaded = Checkpoint::load(&path, &root).unwrap();
+        assert_eq!(loaded.conflicts, vec![kept]);
+        assert_eq!(loaded.resume_at, root.join("m"));
+        assert!(Checkpoint::load(&path, td.path()).is_none());
+
+        checkpoint.saved_at -= MAX_AGE.as_secs() + 60;
+        checkpoint.save(&path).unwrap();
+        assert!(Checkpoint::load(&path, &root).is_none());
+    }
+
+    #[test]
+    fn checkpoint_paths_depend_on_root_and_walk_options() {
+        let dir = Path::new("/cache");
+        let a = checkpoint_path(dir, Path::new("/a"), ScanOptions::default());
+        let hidden = ScanOptions {
+            include_hidden: true,
+            ..ScanOptions::default()
+        };
+        assert_eq!(
+            a,
+            checkpoint_path(dir, Path::new("/a"), ScanOptions::default())
+        );
+        assert_ne!(
+            a,
+            checkpoint_path(dir, Path::new("/b"), ScanOptions::default())
+        );
+        assert_ne!(a, checkpoint_path(dir, Path::new("/a"), hidden));
+    }
+}
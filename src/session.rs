@@ -0,0 +1,149 @@
+use crate::model::ConflictGroup;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How often the TUI saves its session when no key was pressed, to catch picks made by
+/// background work.
+pub const AUTOSAVE_EVERY: Duration = Duration::from_secs(5);
+
+/// `<data dir>/synctui-resolver/sessions`.
+pub fn default_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join("synctui-resolver").join("sessions"))
+}
+
+/// The session file in `dir` for `root`, named after a hash of it.
+pub fn session_path(dir: &Path, root: &Path) -> PathBuf {
+    let hex: String = Sha256::digest(root.as_os_str().as_encoded_bytes())[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    dir.join(format!("{hex}.json"))
+}
+
+/// The decisions of a TUI session that only live in memory until applied: each picked
+/// group's chosen version and the selected groups, by path. The file is removed when
+/// the TUI quits normally, so one left behind means the session was cut short.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub root: PathBuf,
+    /// Base path and chosen version of every picked group.
+    pub picks: Vec<(PathBuf, PathBuf)>,
+    pub selected: Vec<PathBuf>,
+}
+
+impl Session {
+    /// The state of `groups`, `selected` being indices into them.
+    pub fn of(root: &Path, groups: &[ConflictGroup], selected: &BTreeSet<usize>) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            picks: groups
+                .iter()
+                .filter_map(|g| {
+                    let c = g.candidates.get(g.chosen?)?;
+                    Some((g.base_path.clone(), c.path.clone()))
+                })
+                .collect(),
+            selected: selected
+                .iter()
+                .filter_map(|&i| groups.get(i))
+                .map(|g| g.base_path.clone())
+                .collect(),
+        }
+    }
+
+    /// The session at `path` for `root`, unless it is missing, unreadable or for another
+    /// root.
+    pub fn load(path: &Path, root: &Path) -> Option<Self> {
+        let session: Self = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+        (session.root == root).then_some(session)
+    }
+
+    /// Writes to a temporary file first, so a crash mid-save leaves the previous session
+    /// intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {parent:?}"))?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?).with_context(|| format!("write {tmp:?}"))?;
+        fs::rename(&tmp, path).with_context(|| format!("rename {tmp:?} to {path:?}"))
+    }
+
+    /// Picks again every group whose chosen version still exists, returning how many, and
+    /// the indices of the selected groups still there. A pick is dropped when its group,
+    /// or its chosen version, is gone.
+    pub fn restore(&self, groups: &mut [ConflictGroup]) -> (usize, BTreeSet<usize>) {
+        let mut picked = 0;
+        for (base, chosen) in &self.picks {
+            let Some(g) = groups.iter_mut().find(|g| &g.base_path == base) else {
+                continue;
+            };
+            if let Some(ci) = g
+                .candidates
+                .iter()
+                .position(|c| &c.path == chosen && c.exists)
+            {
+                g.chosen = Some(ci);
+                picked += 1;
+            }
+        }
+        let selected = groups
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| self.selected.contains(&g.base_path))
+            .map(|(i, _)| i)
+            .collect();
+        (picked, selected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::scan_conflicts;
+    use tempfile::tempdir;
+
+    #[test]
+    fn sessions_round_trip_and_restore_picks_that_still_apply() {
+        let td = tempdir().unwrap();
+        let root = td.path().join("root");
+        fs::create_dir_all(&root).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(root.join(name), "orig").unwrap();
+            fs::write(
+                root.join(format!("{name}.sync-conflict-20240101-010101-DEV")),
+                "c",
+            )
+            .unwrap();
+        }
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+        groups[1].chosen = Some(0);
+        groups[2].chosen = Some(1);
+        let session = Session::of(&root, &groups, &BTreeSet::from([1, 2]));
+        let path = session_path(&td.path().join("sessions"), &root);
+        session.save(&path).unwrap();
+        assert!(Session::load(&path, td.path()).is_none());
+        let loaded = Session::load(&path, &root).unwrap();
+        assert_eq!(loaded, session);
+
+        // The chosen conflicts of a.txt and c.txt are gone, so only b.txt stays picked.
+        fs::remove_file(root.join("c.txt.sync-conflict-20240101-010101-DEV")).unwrap();
+        fs::remove_file(root.join("a.txt.sync-conflict-20240101-010101-DEV")).unwrap();
+        fs::write(root.join("c.txt.sync-conflict-20240202-010101-DEV"), "d").unwrap();
+        fs::write(root.join("a.txt.sync-conflict-20240202-010101-DEV"), "d").unwrap();
+        let mut fresh = scan_conflicts(&root, true).unwrap();
+        let (picked, selected) = loaded.restore(&mut fresh);
+        assert_eq!(picked, 1);
+        assert_eq!(
+            fresh.iter().map(|g| g.chosen).collect::<Vec<_>>(),
+            [None, Some(0), None]
+        );
+        assert_eq!(selected, BTreeSet::from([1, 2]));
+    }
+}
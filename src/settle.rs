@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+/// Applies of fewer groups than this are rescanned right away.
+pub const LARGE_APPLY: usize = 100;
+
+/// Longest a rescan is put off after an apply, however busy the folder stays.
+pub const MAX_RESCAN_DELAY: Duration = Duration::from_secs(60);
+
+/// How long the disk gets to settle after applying `groups` groups before the tool and
+/// Syncthing rescan it: nothing below [`LARGE_APPLY`], then a second per 100 groups,
+/// from 2 up to 30 seconds.
+pub fn rescan_delay(groups: usize) -> Duration {
+    if groups < LARGE_APPLY {
+        return Duration::ZERO;
+    }
+    Duration::from_secs((groups as u64 / 100).clamp(2, 30))
+}
+
+/// A rescan put off after a large apply. Each sign of activity in the folder pushes it
+/// back by the delay again (a debounce), but never past [`MAX_RESCAN_DELAY`] after the
+/// apply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RescanTimer {
+    delay: Duration,
+    due: Instant,
+    deadline: Instant,
+}
+
+impl RescanTimer {
+    pub fn new(delay: Duration, now: Instant) -> Self {
+        Self {
+            delay,
+            due: now + delay,
+            deadline: now + MAX_RESCAN_DELAY.max(delay),
+        }
+    }
+
+    /// Files are still changing: wait the delay again from `now`.
+    pub fn touch(&mut self, now: Instant) {
+        self.due = (now + self.delay).min(self.deadline);
+    }
+
+    /// Another apply finished meanwhile: wait for whichever is later.
+    pub fn extend(&mut self, other: RescanTimer) {
+        self.delay = self.delay.max(other.delay);
+        self.due = self.due.max(other.due);
+        self.deadline = self.deadline.max(other.deadline);
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.due
+    }
+
+    /// Time left until the rescan, zero once due.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.due.saturating_duration_since(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_grow_with_the_batch_and_small_ones_have_none() {
+        assert_eq!(rescan_delay(99), Duration::ZERO);
+        assert_eq!(rescan_delay(100), Duration::from_secs(2));
+        assert_eq!(rescan_delay(1_500), Duration::from_secs(15));
+        assert_eq!(rescan_delay(100_000), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn activity_pushes_the_rescan_back_up_to_the_limit() {
+        let t0 = Instant::now();
+        let s = Duration::from_secs;
+        let mut timer = RescanTimer::new(s(10), t0);
+        assert!(!timer.is_due(t0 + s(9)));
+        timer.touch(t0 + s(9));
+        assert!(!timer.is_due(t0 + s(18)));
+        assert!(timer.is_due(t0 + s(19)));
+        assert_eq!(timer.remaining(t0 + s(15)), s(4));
+
+        timer.touch(t0 + s(55));
+        assert!(timer.is_due(t0 + MAX_RESCAN_DELAY));
+
+        timer.extend(RescanTimer::new(s(20), t0 + s(50)));
+        assert!(!timer.is_due(t0 + s(69)));
+        assert!(timer.is_due(t0 + s(70)));
+    }
+}
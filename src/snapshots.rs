@@ -0,0 +1,178 @@
+use crate::model::Candidate;
+use crate::scan::stat_candidate;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A read-only snapshot whose `dir` mirrors the live tree mounted at `mount`.
+#[derive(Clone, Debug)]
+pub struct SnapshotSource {
+    pub name: String,
+    pub mount: PathBuf,
+    pub dir: PathBuf,
+    pub created: Option<SystemTime>,
+    /// A shadow root registered by the user rather than a discovered snapshot.
+    pub shadow: bool,
+}
+
+/// A shadow root: a mounted backup or copy of `root` at `dir`, only ever read.
+pub fn shadow_source(root: &Path, dir: &Path) -> SnapshotSource {
+    SnapshotSource {
+        name: dir.display().to_string(),
+        mount: root.to_path_buf(),
+        dir: dir.to_path_buf(),
+        created: None,
+        shadow: true,
+    }
+}
+
+/// The shadow of `root` from the config's `[shadow_roots]`, which maps live folders to
+/// their copies: the entry for the nearest of `root` and its ancestors, joined with the
+/// rest of `root`.
+pub fn configured_shadow(
+    shadow_roots: &BTreeMap<PathBuf, PathBuf>,
+    root: &Path,
+) -> Option<PathBuf> {
+    root.ancestors().find_map(|live| {
+        let shadow = shadow_roots.get(live)?;
+        Some(shadow.join(root.strip_prefix(live).ok()?))
+    })
+}
+
+/// Finds the most recent `limit` ZFS (`<dataset>/.zfs/snapshot/<name>`) or snapper-style
+/// btrfs (`<subvolume>/.snapshots/<n>/snapshot`) snapshots covering `root`.
+pub fn discover_snapshots(root: &Path, limit: usize) -> Vec<SnapshotSource> {
+    for mount in root.ancestors() {
+        let mut found = zfs_snapshots(mount);
+        if found.is_empty() {
+            found = snapper_snapshots(mount);
+        }
+        if !found.is_empty() {
+            // Newest first; fall back to name order (snapshot names are usually sortable).
+            found.sort_by(|a, b| b.created.cmp(&a.created).then(b.name.cmp(&a.name)));
+            found.truncate(limit);
+            return found;
+        }
+    }
+    Vec::new()
+}
+
+fn list_dirs(dir: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(rd) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut out: Vec<_> = rd
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .map(|e| (e.file_name().to_string_lossy().into_owned(), e.path()))
+        .collect();
+    out.sort();
+    out
+}
+
+fn created(dir: &Path) -> Option<SystemTime> {
+    fs::metadata(dir).and_then(|m| m.modified()).ok()
+}
+
+fn zfs_snapshots(mount: &Path) -> Vec<SnapshotSource> {
+    // `.zfs` is hidden from directory listings but can be entered directly.
+    list_dirs(&mount.join(".zfs").join("snapshot"))
+        .into_iter()
+        .map(|(name, dir)| SnapshotSource {
+            created: created(&dir),
+            name,
+            mount: mount.to_path_buf(),
+            dir,
+            shadow: false,
+        })
+        .collect()
+}
+
+fn snapper_snapshots(mount: &Path) -> Vec<SnapshotSource> {
+    list_dirs(&mount.join(".snapshots"))
+        .into_iter()
+        .filter_map(|(name, dir)| {
+            let dir = dir.join("snapshot");
+            dir.is_dir().then(|| SnapshotSource {
+                created: created(&dir),
+                name,
+                mount: mount.to_path_buf(),
+                dir,
+                shadow: false,
+            })
+        })
+        .collect()
+}
+
+/// Snapshot and shadow copies of `base_path` that exist, as read-only reference
+/// candidates.
+pub fn reference_candidates(snapshots: &[SnapshotSource], base_path: &Path) -> Vec<Candidate> {
+    snapshots
+        .iter()
+        .filter_map(|s| {
+            let rel = base_path.strip_prefix(&s.mount).ok()?;
+            let p = s.dir.join(rel);
+            let kind = if s.shadow { "Shadow" } else { "Snapshot" };
+            p.is_file()
+                .then(|| stat_candidate(p, false, format!("{kind} {}", s.name)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn discovers_snapper_snapshots_and_maps_base_path() {
+        let td = tempdir().unwrap();
+        let mount = td.path();
+        let root = mount.join("docs");
+        fs::create_dir_all(&root).unwrap();
+        for n in ["1", "2"] {
+            let snap = mount
+                .join(".snapshots")
+                .join(n)
+                .join("snapshot")
+                .join("docs");
+            fs::create_dir_all(&snap).unwrap();
+            if n == "2" {
+                fs::write(snap.join("notes.txt"), "old").unwrap();
+            }
+        }
+
+        let snaps = discover_snapshots(&root, 5);
+        assert_eq!(snaps.len(), 2);
+        assert!(snaps.iter().all(|s| s.mount == mount));
+
+        let refs = reference_candidates(&snaps, &root.join("notes.txt"));
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0].label, "Snapshot 2");
+        assert!(!refs[0].is_original);
+    }
+
+    #[test]
+    fn shadow_roots_mirror_the_root_or_an_ancestor() {
+        let td = tempdir().unwrap();
+        let root = td.path().join("sync/docs");
+        let backup = td.path().join("backup");
+        fs::create_dir_all(backup.join("docs")).unwrap();
+        fs::write(backup.join("docs/notes.txt"), "backed up").unwrap();
+
+        let roots = BTreeMap::from([(td.path().join("sync"), backup.clone())]);
+        let shadow = configured_shadow(&roots, &root).unwrap();
+        assert_eq!(shadow, backup.join("docs"));
+        assert_eq!(configured_shadow(&roots, td.path()), None);
+
+        let refs = reference_candidates(&[shadow_source(&root, &shadow)], &root.join("notes.txt"));
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0].path, backup.join("docs/notes.txt"));
+        assert_eq!(refs[0].label, format!("Shadow {}", shadow.display()));
+        assert!(
+            reference_candidates(&[shadow_source(&root, &shadow)], &root.join("gone.txt"))
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use crate::config::{loser_action, LoserAction};
+use crate::model::ConflictGroup;
+use crate::ops::ArchiveTarget;
+use crate::plan::ApplyOptions;
+use crate::scan::rel_path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Space taken by conflict copies, and what applying the picked groups does with it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpaceSummary {
+    /// Conflict copies found (originals aren't counted), picked or not.
+    pub conflict_files: usize,
+    pub conflict_bytes: u64,
+    /// Bytes apply removes from this machine: deleted by a loser rule or moved to a
+    /// remote archive.
+    pub freed_bytes: u64,
+    /// Bytes apply moves into a local `.stconflict-archive`, still taking space.
+    pub archived_bytes: u64,
+}
+
+impl SpaceSummary {
+    /// One group; only a picked group frees or archives anything. Unknown sizes count as 0.
+    pub fn of_group(g: &ConflictGroup, options: &ApplyOptions) -> Self {
+        let mut s = Self::default();
+        for c in g.candidates.iter().filter(|c| c.exists && !c.is_original) {
+            s.conflict_files += 1;
+            s.conflict_bytes += c.size.unwrap_or(0);
+        }
+        let Some(ci) = g.chosen else {
+            return s;
+        };
+        for (i, c) in g.candidates.iter().enumerate() {
+            if i == ci || !c.exists {
+                continue;
+            }
+            let size = c.size.unwrap_or(0);
+            let frees = loser_action(&options.loser_rules, c.size) == LoserAction::Delete
+                || matches!(options.archive, ArchiveTarget::Rclone(_));
+            if frees {
+                s.freed_bytes += size;
+            } else {
+                s.archived_bytes += size;
+            }
+        }
+        s
+    }
+
+    fn add(&mut self, other: Self) {
+        self.conflict_files += other.conflict_files;
+        self.conflict_bytes += other.conflict_bytes;
+        self.freed_bytes += other.freed_bytes;
+        self.archived_bytes += other.archived_bytes;
+    }
+}
+
+/// Totals over every group.
+pub fn space_summary(groups: &[ConflictGroup], options: &ApplyOptions) -> SpaceSummary {
+    let mut total = SpaceSummary::default();
+    for g in groups {
+        total.add(SpaceSummary::of_group(g, options));
+    }
+    total
+}
+
+/// Totals per top-level directory below `root`, sorted by path; groups directly in the
+/// root are under `.`.
+pub fn space_by_top_dir(
+    root: &Path,
+    groups: &[ConflictGroup],
+    options: &ApplyOptions,
+) -> Vec<(PathBuf, SpaceSummary)> {
+    let mut dirs: BTreeMap<PathBuf, SpaceSummary> = BTreeMap::new();
+    for g in groups {
+        let rel = rel_path(root, &g.base_path);
+        let mut parts = rel.components();
+        let top = match (parts.next(), parts.next()) {
+            (Some(dir), Some(_)) => PathBuf::from(dir.as_os_str()),
+            _ => PathBuf::from("."),
+        };
+        dirs.entry(top)
+            .or_default()
+            .add(SpaceSummary::of_group(g, options));
+    }
+    dirs.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LoserRule;
+    use crate::model::Candidate;
+
+    fn cand(path: &str, is_original: bool, size: u64) -> Candidate {
+        Candidate {
+            path: PathBuf::from(path),
+            exists: true,
+            is_original,
+            size: Some(size),
+            modified: None,
+            label: String::new(),
+            trust: None,
+        }
+    }
+
+    fn group(base: &str, sizes: &[u64], chosen: Option<usize>) -> ConflictGroup {
+        let mut candidates = vec![cand(base, true, sizes[0])];
+        for (i, &size) in sizes[1..].iter().enumerate() {
+            candidates.push(cand(&format!("{base}.sync-conflict-{i}"), false, size));
+        }
+        ConflictGroup {
+            base_path: PathBuf::from(base),
+            candidates,
+            chosen,
+            references: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn counts_conflicts_and_splits_the_plan_into_freed_and_archived() {
+        let groups = vec![
+            // Keeps the original: both conflicts are archived.
+            group("/r/docs/a.txt", &[10, 100, 1000], Some(0)),
+            // Keeps a conflict: the original is archived in its place.
+            group("/r/docs/sub/b.txt", &[20, 200], Some(1)),
+            // Unpicked: counted, but the plan doesn't touch it.
+            group("/r/c.txt", &[30, 300], None),
+        ];
+        let options = ApplyOptions::default();
+        let total = space_summary(&groups, &options);
+        assert_eq!(
+            total,
+            SpaceSummary {
+                conflict_files: 4,
+                conflict_bytes: 1600,
+                freed_bytes: 0,
+                archived_bytes: 1120,
+            }
+        );
+
+        let dirs = space_by_top_dir(Path::new("/r"), &groups, &options);
+        let names: Vec<_> = dirs.iter().map(|(d, _)| d.clone()).collect();
+        assert_eq!(names, [PathBuf::from("."), PathBuf::from("docs")]);
+        assert_eq!(dirs[0].1.conflict_bytes, 300);
+        assert_eq!(dirs[1].1.archived_bytes, 1120);
+
+        // Large losers deleted by a rule and anything sent to a remote free space.
+        let rules = ApplyOptions {
+            loser_rules: vec![LoserRule {
+                min_size: Some(500),
+                max_size: None,
+                action: LoserAction::Delete,
+            }],
+            ..ApplyOptions::default()
+        };
+        let s = space_summary(&groups, &rules);
+        assert_eq!((s.freed_bytes, s.archived_bytes), (1000, 120));
+        let remote = ApplyOptions {
+            archive: ArchiveTarget::Rclone("backup:c".into()),
+            ..ApplyOptions::default()
+        };
+        let s = space_summary(&groups, &remote);
+        assert_eq!((s.freed_bytes, s.archived_bytes), (1120, 0));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::ops::ARCHIVE_DIR;
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Syncthing's per-folder ignore file. It isn't synced, so each device keeps its own.
+pub const STIGNORE: &str = ".stignore";
+
+/// The Syncthing folder `root` lies in: the nearest of `root` and its ancestors holding
+/// the `.stfolder` marker, else `root` itself.
+pub fn folder_root(root: &Path) -> PathBuf {
+    root.ancestors()
+        .find(|a| a.join(".stfolder").exists())
+        .unwrap_or(root)
+        .to_path_buf()
+}
+
+/// Whether `folder`'s `.stignore` has a pattern for the archive directories at any
+/// depth, with or without `(?d)`-style flags, a leading `**/` or a trailing `/**`. A
+/// leading `/` only covers the top-level archive, so it doesn't count.
+pub fn ignores_archive(folder: &Path) -> bool {
+    let Ok(text) = fs::read_to_string(folder.join(STIGNORE)) else {
+        return false;
+    };
+    text.lines().any(|line| {
+        let mut p = line.trim();
+        while let Some(rest) = p
+            .strip_prefix("(?")
+            .and_then(|r| Some(&r[r.find(')')? + 1..]))
+        {
+            p = rest;
+        }
+        let p = p.trim_start_matches("**/");
+        let p = p.trim_end_matches("/**").trim_end_matches('/');
+        p == ARCHIVE_DIR
+    })
+}
+
+/// Appends `(?d).stconflict-archive` to `folder`'s `.stignore`, creating it if needed, so
+/// Syncthing leaves the archives on this device; `(?d)` still lets it delete them when
+/// their directory is deleted elsewhere. Returns the file written.
+pub fn ignore_archive(folder: &Path) -> Result<PathBuf> {
+    let path = folder.join(STIGNORE);
+    let existing = fs::read_to_string(&path).unwrap_or_default();
+    let mut text = String::new();
+    if !existing.is_empty() && !existing.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(&format!(
+        "// Conflict versions archived by synctui-resolver stay on this device\n(?d){ARCHIVE_DIR}\n"
+    ));
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut f| f.write_all(text.as_bytes()))
+        .with_context(|| format!("append to {path:?}"))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn the_archive_pattern_is_appended_once_and_recognized() {
+        let td = tempdir().unwrap();
+        let folder = td.path();
+        fs::create_dir_all(folder.join(".stfolder")).unwrap();
+        fs::create_dir_all(folder.join("photos/2024")).unwrap();
+        assert_eq!(folder_root(&folder.join("photos/2024")), folder);
+        assert!(!ignores_archive(folder));
+
+        fs::write(folder.join(STIGNORE), "*.tmp").unwrap();
+        assert!(!ignores_archive(folder));
+        assert_eq!(ignore_archive(folder).unwrap(), folder.join(STIGNORE));
+        assert!(ignores_archive(folder));
+        let text = fs::read_to_string(folder.join(STIGNORE)).unwrap();
+        assert!(text.starts_with("*.tmp\n// "), "{text}");
+        assert!(text.ends_with("\n(?d).stconflict-archive\n"), "{text}");
+
+        fs::write(folder.join(STIGNORE), "(?i)(?d)**/.stconflict-archive/**").unwrap();
+        assert!(ignores_archive(folder));
+        for line in ["/.stconflict-archive", "// .stconflict-archive"] {
+            fs::write(folder.join(STIGNORE), line).unwrap();
+            assert!(!ignores_archive(folder), "{line}");
+        }
+    }
+}
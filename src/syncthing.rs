@@ -0,0 +1,307 @@
+use crate::model::Strategy;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+/// Minimal client for the Syncthing REST API.
+#[derive(Clone)]
+pub struct SyncthingClient {
+    url: String,
+    api_key: String,
+    agent: ureq::Agent,
+}
+
+/// One entry of the `/rest/events` stream.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Event {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub data: serde_json::Value,
+}
+
+impl Event {
+    /// Folder ID and folder-relative path of a file event (`ItemFinished`,
+    /// `LocalChangeDetected`, `RemoteChangeDetected`).
+    pub fn item(&self) -> Option<(&str, &str)> {
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|n| self.data.get(*n).and_then(|v| v.as_str()))
+        };
+        Some((field(&["folderID", "folder"])?, field(&["path", "item"])?))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Folder {
+    pub id: String,
+    /// Name shown in Syncthing's GUI; may be empty.
+    #[serde(default)]
+    pub label: String,
+    pub path: PathBuf,
+    #[serde(default)]
+    pub paused: bool,
+}
+
+/// A file's entry in Syncthing's database (`/rest/db/file`).
+#[derive(Clone, Debug, Deserialize)]
+pub struct DbFile {
+    /// The version the cluster agrees on.
+    pub global: FileInfo,
+    /// This device's copy, which lags behind the global one until it is pulled.
+    pub local: Option<FileInfo>,
+    /// Devices that have the global version.
+    #[serde(default)]
+    pub availability: Vec<Availability>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileInfo {
+    /// Short ID of the device that announced this version.
+    #[serde(default)]
+    pub modified_by: String,
+    /// Version vector, one `<short device ID>:<counter>` entry per device that changed it.
+    #[serde(default)]
+    pub version: Vec<String>,
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Availability {
+    pub id: String,
+}
+
+impl SyncthingClient {
+    pub fn new(url: &str, api_key: &str) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(10)))
+            .build()
+            .into();
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            agent,
+        }
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}{path}", self.url)
+    }
+
+    pub fn folders(&self) -> Result<Vec<Folder>> {
+        let mut folders: Vec<Folder> = self
+            .agent
+            .get(self.endpoint("/rest/config/folders"))
+            .header("X-API-Key", &self.api_key)
+            .call()
+            .context("GET /rest/config/folders")?
+            .body_mut()
+            .read_json()
+            .context("decode folder list")?;
+        for f in &mut folders {
+            f.path = normalize_folder_path(&f.path);
+        }
+        Ok(folders)
+    }
+
+    pub fn set_paused(&self, folder_id: &str, paused: bool) -> Result<()> {
+        self.agent
+            .patch(self.endpoint(&format!("/rest/config/folders/{folder_id}")))
+            .header("X-API-Key", &self.api_key)
+            .send_json(serde_json::json!({ "paused": paused }))
+            .with_context(|| format!("set paused={paused} on folder {folder_id}"))?;
+        Ok(())
+    }
+
+    /// Events of the given types after `since`, long-polling up to `timeout` when there are
+    /// none yet. `limit` keeps only the latest ones.
+    pub fn events(
+        &self,
+        since: u64,
+        types: &[&str],
+        timeout: Duration,
+        limit: Option<usize>,
+    ) -> Result<Vec<Event>> {
+        let mut req = self
+            .agent
+            .get(self.endpoint("/rest/events"))
+            .query("since", since.to_string())
+            .query("events", types.join(","))
+            .query("timeout", timeout.as_secs().to_string());
+        if let Some(limit) = limit {
+            req = req.query("limit", limit.to_string());
+        }
+        req.header("X-API-Key", &self.api_key)
+            .config()
+            .timeout_global(Some(timeout + Duration::from_secs(10)))
+            .build()
+            .call()
+            .context("GET /rest/events")?
+            .body_mut()
+            .read_json()
+            .context("decode events")
+    }
+
+    /// The database entry for `name`, a path relative to the folder.
+    pub fn file(&self, folder_id: &str, name: &str) -> Result<DbFile> {
+        self.agent
+            .get(self.endpoint("/rest/db/file"))
+            .query("folder", folder_id)
+            .query("file", name)
+            .header("X-API-Key", &self.api_key)
+            .call()
+            .with_context(|| format!("GET /rest/db/file for {name:?} in folder {folder_id}"))?
+            .body_mut()
+            .read_json()
+            .context("decode file info")
+    }
+
+    /// The database entry for `path`, looked up in the folder that contains it.
+    pub fn file_at(&self, path: &Path) -> Result<DbFile> {
+        let folders = self.folders()?;
+        let folder = folder_for(&folders, path)
+            .ok_or_else(|| anyhow!("{path:?} is not in a Syncthing folder"))?;
+        let name = path.strip_prefix(&folder.path).unwrap_or(path);
+        self.file(&folder.id, &name.to_string_lossy())
+    }
+
+    /// [`file_at`](Self::file_at) on a background thread, so a slow or unreachable API
+    /// doesn't hold up the caller; the result arrives on the channel.
+    pub fn spawn_file_at(&self, path: PathBuf) -> Receiver<Result<DbFile>> {
+        let (tx, rx) = mpsc::channel();
+        let client = self.clone();
+        thread::spawn(move || {
+            let _ = tx.send(client.file_at(&path));
+        });
+        rx
+    }
+
+    pub fn rescan(&self, folder_id: &str) -> Result<()> {
+        self.agent
+            .post(self.endpoint("/rest/db/scan"))
+            .query("folder", folder_id)
+            .header("X-API-Key", &self.api_key)
+            .send_empty()
+            .with_context(|| format!("rescan folder {folder_id}"))?;
+        Ok(())
+    }
+}
+
+/// Expands a leading `~` and canonicalizes when possible so folder paths compare
+/// against the (canonical) scan root.
+fn normalize_folder_path(p: &Path) -> PathBuf {
+    let expanded = match p.strip_prefix("~") {
+        Ok(rest) => dirs::home_dir()
+            .map(|h| h.join(rest))
+            .unwrap_or_else(|| p.to_path_buf()),
+        Err(_) => p.to_path_buf(),
+    };
+    expanded.canonicalize().unwrap_or(expanded)
+}
+
+/// Returns the folder that contains `path`, preferring the most specific one.
+pub fn folder_for<'a>(folders: &'a [Folder], path: &Path) -> Option<&'a Folder> {
+    folders
+        .iter()
+        .filter(|f| path.starts_with(&f.path))
+        .max_by_key(|f| f.path.components().count())
+}
+
+/// Strategy `[folder_presets]` sets for the Syncthing folder holding `path`, keyed by the
+/// folder's ID or, failing that, its label.
+pub fn folder_preset(
+    presets: &BTreeMap<String, Strategy>,
+    folders: &[Folder],
+    path: &Path,
+) -> Option<Strategy> {
+    let f = folder_for(folders, path)?;
+    presets
+        .get(&f.id)
+        .or_else(|| presets.get(&f.label).filter(|_| !f.label.is_empty()))
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(id: &str, path: &str) -> Folder {
+        Folder {
+            id: id.to_string(),
+            label: String::new(),
+            path: PathBuf::from(path),
+            paused: false,
+        }
+    }
+
+    #[test]
+    fn event_item_reads_either_field_spelling() {
+        let events: Vec<Event> = serde_json::from_str(
+            r#"[
+                {"id": 7, "type": "ItemFinished",
+                 "data": {"item": "a.txt", "folder": "abcd-1234", "error": null}},
+                {"id": 8, "type": "LocalChangeDetected",
+                 "data": {"folder": "abcd-1234", "folderID": "abcd-1234", "path": "b.txt"}},
+                {"id": 9, "type": "Starting", "data": {"home": "/x"}}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(events[0].item(), Some(("abcd-1234", "a.txt")));
+        assert_eq!(events[1].item(), Some(("abcd-1234", "b.txt")));
+        assert_eq!(events[2].item(), None);
+    }
+
+    #[test]
+    fn db_file_reads_the_announcing_device_and_version_vector() {
+        let file: DbFile = serde_json::from_str(
+            r#"{
+                "availability": [{"id": "SMOMXRA-LONG", "fromTemporary": false}],
+                "global": {"deleted": false, "modified": "2024-01-01T12:00:00Z",
+                           "modifiedBy": "SMOMXRA", "name": "a.txt", "size": 3,
+                           "version": ["K7Q2ZRS:1", "SMOMXRA:4"]},
+                "local": {"modifiedBy": "K7Q2ZRS", "version": ["K7Q2ZRS:1"]},
+                "mtime": {"err": null, "value": {"real": "", "virtual": ""}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(file.global.modified_by, "SMOMXRA");
+        assert_eq!(file.global.version, ["K7Q2ZRS:1", "SMOMXRA:4"]);
+        assert_eq!(file.local.unwrap().version, ["K7Q2ZRS:1"]);
+        assert_eq!(file.availability[0].id, "SMOMXRA-LONG");
+    }
+
+    #[test]
+    fn folder_for_picks_most_specific_prefix() {
+        let folders = vec![folder("outer", "/data"), folder("inner", "/data/photos")];
+        let f = folder_for(&folders, Path::new("/data/photos/a.jpg")).unwrap();
+        assert_eq!(f.id, "inner");
+        let f = folder_for(&folders, Path::new("/data/notes.txt")).unwrap();
+        assert_eq!(f.id, "outer");
+        assert!(folder_for(&folders, Path::new("/elsewhere/x")).is_none());
+        // Component-wise prefix, not string prefix.
+        assert!(folder_for(&folders, Path::new("/database/x")).is_none());
+    }
+
+    #[test]
+    fn folder_presets_match_by_id_then_label() {
+        let mut photos = folder("ph0to-s", "/data/photos");
+        photos.label = "Photos".to_string();
+        let folders = vec![photos, folder("n0tes", "/data/notes")];
+        let presets = BTreeMap::from([
+            ("Photos".to_string(), Strategy::Largest),
+            ("n0tes".to_string(), Strategy::Newest),
+        ]);
+        let preset = |p: &str| folder_preset(&presets, &folders, Path::new(p));
+        assert_eq!(preset("/data/photos/a.jpg"), Some(Strategy::Largest));
+        assert_eq!(preset("/data/notes/todo.md"), Some(Strategy::Newest));
+        assert_eq!(preset("/data/other.txt"), None);
+    }
+}
@@ -0,0 +1,360 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use icy_sixel::SixelImage;
+use image::imageops::FilterType;
+use image::{ImageReader, RgbImage};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Extensions previewed as images. HEIC and HEIF are only decoded in builds with the
+/// `heic` feature; elsewhere they get a hexdump like other binaries.
+const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "heic", "heif"];
+
+/// Longest side of a decoded image kept in memory; panes are resized from this.
+pub const MAX_THUMBNAIL_SIDE: u32 = 512;
+
+/// Bytes a hexdump line shows.
+const HEX_WIDTH: usize = 16;
+
+/// How images are drawn in the TUI (`graphics` in the config).
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Graphics {
+    /// Kitty's protocol in kitty, WezTerm and Ghostty, sixel in terminals known to
+    /// support it, half blocks elsewhere (and always inside tmux, which eats both).
+    #[default]
+    Auto,
+    Kitty,
+    Sixel,
+    /// `▀` characters with the top pixel as foreground and the bottom as background.
+    Halfblocks,
+}
+
+impl Graphics {
+    pub fn label(self) -> &'static str {
+        match self {
+            Graphics::Auto => "auto",
+            Graphics::Kitty => "kitty",
+            Graphics::Sixel => "sixel",
+            Graphics::Halfblocks => "halfblocks",
+        }
+    }
+
+    /// `Auto` decided from the terminal's environment variables; other values as is.
+    pub fn resolve(self, env: impl Fn(&str) -> Option<String>) -> Graphics {
+        if self != Graphics::Auto {
+            return self;
+        }
+        if env("TMUX").is_some() {
+            return Graphics::Halfblocks;
+        }
+        let term = env("TERM").unwrap_or_default();
+        let program = env("TERM_PROGRAM").unwrap_or_default();
+        if env("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || term.contains("ghostty")
+            || matches!(program.as_str(), "WezTerm" | "ghostty")
+        {
+            Graphics::Kitty
+        } else if term.contains("foot") || term.contains("mlterm") || term.contains("sixel") {
+            Graphics::Sixel
+        } else {
+            Graphics::Halfblocks
+        }
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Whether `path` is a photo by its extension (including HEIC, decoded only with the
+/// `heic` feature).
+pub fn is_image(path: &Path) -> bool {
+    IMAGE_EXTENSIONS.contains(&extension(path).as_str())
+}
+
+/// Decodes the JPEG or PNG at `path` (or HEIC with the `heic` feature), told apart by
+/// content rather than name, shrunk to at most [`MAX_THUMBNAIL_SIDE`] on each side.
+pub fn load(path: &Path) -> Result<RgbImage> {
+    let reader = ImageReader::open(path)
+        .with_context(|| format!("open {path:?}"))?
+        .with_guessed_format()
+        .with_context(|| format!("read {path:?}"))?;
+    let img = if reader.format().is_some() {
+        reader
+            .decode()
+            .with_context(|| format!("decode {path:?}"))?
+    } else {
+        load_heic(path)?
+    };
+    let img = if img.width().max(img.height()) > MAX_THUMBNAIL_SIDE {
+        img.thumbnail(MAX_THUMBNAIL_SIDE, MAX_THUMBNAIL_SIDE)
+    } else {
+        img
+    };
+    Ok(img.to_rgb8())
+}
+
+/// Decodes the HEIC or HEIF photo at `path` with libheif, rotations and crops applied.
+#[cfg(feature = "heic")]
+fn load_heic(path: &Path) -> Result<image::DynamicImage> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let name = path
+        .to_str()
+        .with_context(|| format!("{path:?} isn't valid UTF-8"))?;
+    let context = HeifContext::read_from_file(name)
+        .with_context(|| format!("not a JPEG, PNG or HEIC: {path:?}"))?;
+    let handle = context
+        .primary_image_handle()
+        .with_context(|| format!("read {path:?}"))?;
+    let decoded = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)
+        .with_context(|| format!("decode {path:?}"))?;
+    let plane = decoded
+        .planes()
+        .interleaved
+        .with_context(|| format!("decode {path:?}: no RGB plane"))?;
+    // Rows may be padded past the pixels, so copy them one by one.
+    let row = plane.width as usize * 3;
+    let mut pixels = Vec::with_capacity(row * plane.height as usize);
+    for line in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&line[..row]);
+    }
+    let img = RgbImage::from_raw(plane.width, plane.height, pixels)
+        .with_context(|| format!("decode {path:?}: short RGB plane"))?;
+    Ok(img.into())
+}
+
+#[cfg(not(feature = "heic"))]
+fn load_heic(_path: &Path) -> Result<image::DynamicImage> {
+    anyhow::bail!("not a JPEG or PNG (HEIC needs a build with the `heic` feature)");
+}
+
+/// Cells an image of `width`x`height` pixels fills when fitted into `cols`x`rows`
+/// without changing its aspect ratio, taking a cell to be twice as tall as wide.
+pub fn fit(width: u32, height: u32, cols: u16, rows: u16) -> (u16, u16) {
+    let (w, h) = fit_half_cells(width, height, cols, rows);
+    (w as u16, h.div_ceil(2) as u16)
+}
+
+/// [`fit`] in half-cell units, where a pixel is square.
+fn fit_half_cells(width: u32, height: u32, cols: u16, rows: u16) -> (u32, u32) {
+    if width == 0 || height == 0 || cols == 0 || rows == 0 {
+        return (0, 0);
+    }
+    let (max_w, max_h) = (u64::from(cols), u64::from(rows) * 2);
+    let (w, h) = (u64::from(width), u64::from(height));
+    let (fw, fh) = if w * max_h > h * max_w {
+        (max_w, (h * max_w / w).max(1))
+    } else {
+        ((w * max_h / h).max(1), max_h)
+    };
+    (fw as u32, fh as u32)
+}
+
+/// Colors of one `▀` cell: the top pixel, and the bottom one unless the image ends.
+pub type HalfBlock = ([u8; 3], Option<[u8; 3]>);
+
+/// `img` fitted into `cols`x`rows` cells as rows of (top, bottom) pixel colors, one per
+/// `▀` cell. The bottom is `None` in the last row of an odd pixel height.
+pub fn half_blocks(img: &RgbImage, cols: u16, rows: u16) -> Vec<Vec<HalfBlock>> {
+    let (w, h) = fit_half_cells(img.width(), img.height(), cols, rows);
+    if w == 0 {
+        return Vec::new();
+    }
+    let small = image::imageops::resize(img, w, h, FilterType::Triangle);
+    (0..h.div_ceil(2))
+        .map(|r| {
+            (0..w)
+                .map(|x| {
+                    let top = small.get_pixel(x, r * 2).0;
+                    let bottom = (r * 2 + 1 < h).then(|| small.get_pixel(x, r * 2 + 1).0);
+                    (top, bottom)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Kitty graphics escape drawing `img` scaled into `cols`x`rows` cells at the cursor,
+/// sent in the protocol's 4096-byte chunks. The cursor doesn't move.
+pub fn kitty_escape(img: &RgbImage, cols: u16, rows: u16) -> String {
+    let data = STANDARD.encode(img.as_raw());
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        if i == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=T,f=24,s={},v={},c={cols},r={rows},C=1,q=2,m={more};{chunk}\x1b\\",
+                img.width(),
+                img.height()
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={more};{chunk}\x1b\\"));
+        }
+    }
+    out
+}
+
+/// Deletes every image kitty shows, e.g. when the pane it was drawn in closes.
+pub const KITTY_CLEAR: &str = "\x1b_Ga=d,d=A,q=2\x1b\\";
+
+/// Sixel escape drawing `img` resized to `width`x`height` pixels at the cursor.
+pub fn sixel_escape(img: &RgbImage, width: u32, height: u32) -> Result<String> {
+    let small = image::imageops::resize(img, width.max(1), height.max(1), FilterType::Triangle);
+    let rgba: Vec<u8> = small
+        .pixels()
+        .flat_map(|p| [p.0[0], p.0[1], p.0[2], 255])
+        .collect();
+    SixelImage::try_from_rgba(rgba, small.width() as usize, small.height() as usize)
+        .and_then(|s| s.encode())
+        .map_err(|e| anyhow::anyhow!("sixel encoding failed: {e}"))
+}
+
+/// The first `len` bytes of `path`.
+pub fn head(path: &Path, len: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(len);
+    File::open(path)
+        .with_context(|| format!("open {path:?}"))?
+        .take(len as u64)
+        .read_to_end(&mut buf)
+        .with_context(|| format!("read {path:?}"))?;
+    Ok(buf)
+}
+
+/// Whether a file starting with `head` is binary: it has a NUL byte or isn't UTF-8
+/// (a character cut off at the end of `head` doesn't count).
+pub fn is_binary(head: &[u8]) -> bool {
+    head.contains(&0) || std::str::from_utf8(head).is_err_and(|e| e.error_len().is_some())
+}
+
+/// `hexdump -C` style lines of `bytes`: offset, hex bytes in two groups of eight, and the
+/// printable ASCII.
+pub fn hexdump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(HEX_WIDTH)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut hex = String::new();
+            for j in 0..HEX_WIDTH {
+                if j == HEX_WIDTH / 2 {
+                    hex.push(' ');
+                }
+                match chunk.get(j) {
+                    Some(b) => hex.push_str(&format!("{b:02x} ")),
+                    None => hex.push_str("   "),
+                }
+            }
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}  {hex} |{ascii}|", i * HEX_WIDTH)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    #[test]
+    fn auto_picks_a_protocol_from_the_environment() {
+        let resolve = |vars: &[(&str, &str)]| {
+            let vars: HashMap<String, String> = vars
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            Graphics::Auto.resolve(|k| vars.get(k).cloned())
+        };
+        assert_eq!(resolve(&[("TERM", "xterm-kitty")]), Graphics::Kitty);
+        assert_eq!(resolve(&[("TERM_PROGRAM", "WezTerm")]), Graphics::Kitty);
+        assert_eq!(resolve(&[("TERM", "foot")]), Graphics::Sixel);
+        assert_eq!(resolve(&[("TERM", "xterm-256color")]), Graphics::Halfblocks);
+        assert_eq!(
+            resolve(&[("TERM", "xterm-kitty"), ("TMUX", "/tmp/tmux-0/default")]),
+            Graphics::Halfblocks
+        );
+        assert_eq!(Graphics::Sixel.resolve(|_| None), Graphics::Sixel);
+    }
+
+    #[test]
+    fn images_keep_their_aspect_ratio_in_cells() {
+        // A cell is two square half-cells tall.
+        assert_eq!(fit(100, 100, 40, 10), (20, 10));
+        assert_eq!(fit(400, 100, 40, 10), (40, 5));
+        assert_eq!(fit(100, 400, 40, 10), (5, 10));
+        assert_eq!(fit(0, 100, 40, 10), (0, 0));
+    }
+
+    #[test]
+    fn png_decodes_into_half_blocks() {
+        let td = tempdir().unwrap();
+        let path = td.path().join("photo.PNG");
+        // Red top half, blue bottom half.
+        let img = RgbImage::from_fn(4, 4, |_, y| {
+            if y < 2 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        });
+        img.save_with_format(&path, image::ImageFormat::Png)
+            .unwrap();
+
+        assert!(is_image(&path));
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.dimensions(), (4, 4));
+        let cells = half_blocks(&loaded, 4, 2);
+        assert_eq!(cells.len(), 2);
+        assert_eq!(cells[0][0], ([255, 0, 0], Some([255, 0, 0])));
+        assert_eq!(cells[1][3], ([0, 0, 255], Some([0, 0, 255])));
+
+        assert!(kitty_escape(&loaded, 4, 2).starts_with("\x1b_Ga=T,f=24,s=4,v=4,c=4,r=2,"));
+        assert!(sixel_escape(&loaded, 8, 12).unwrap().starts_with("\x1bP"));
+
+        let heic = td.path().join("photo.heic");
+        std::fs::write(&heic, b"\0\0\0\x18ftypheic").unwrap();
+        assert!(is_image(&heic));
+        // A bare header: without the `heic` feature the error says how to get one, with
+        // it libheif rejects the truncated file.
+        let err = load(&heic).unwrap_err().to_string();
+        assert!(err.contains("HEIC"), "{err}");
+    }
+
+    #[test]
+    fn binaries_get_a_hexdump() {
+        assert!(is_binary(b"PK\x03\x04\0\0"));
+        assert!(!is_binary("plain text é".as_bytes()));
+        // A multi-byte character cut off by the read is still text.
+        assert!(!is_binary(&"é".as_bytes()[..1]));
+
+        let lines = hexdump(b"%PDF-1.7\n\x00\x01\x02abcdefghijklmnop");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "00000000  25 50 44 46 2d 31 2e 37  0a 00 01 02 61 62 63 64  |%PDF-1.7....abcd|"
+        );
+        assert!(lines[1].starts_with("00000010  65 66 67"));
+        assert!(lines[1].ends_with("|efghijklmnop|"));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::collate::PathOrder;
+use crate::model::ConflictGroup;
+use crate::scan::rel_path;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// A row of the directory tree view.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TreeRow {
+    /// A directory relative to the root, with the number of groups anywhere below it.
+    Dir {
+        path: PathBuf,
+        depth: usize,
+        count: usize,
+        collapsed: bool,
+    },
+    /// `index` into the group list.
+    Group { index: usize, depth: usize },
+}
+
+/// Directory of `g` relative to `root` (empty for groups directly in the root).
+fn rel_dir(root: &Path, g: &ConflictGroup) -> PathBuf {
+    rel_path(root, &g.base_path)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+/// Non-empty ancestors of a relative directory, shortest first (`a`, `a/b`, `a/b/c`).
+fn prefixes(dir: &Path) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    let mut acc = PathBuf::new();
+    for c in dir.components() {
+        acc.push(c);
+        out.push(acc.clone());
+    }
+    out
+}
+
+/// Visible rows: each directory followed by its groups and subdirectories, with the
+/// contents of `collapsed` directories hidden. Directories and groups follow `order`.
+pub fn tree_rows(
+    root: &Path,
+    groups: &[ConflictGroup],
+    collapsed: &BTreeSet<PathBuf>,
+    order: PathOrder,
+) -> Vec<TreeRow> {
+    let mut rows_by_dir: Vec<(PathBuf, usize)> = groups
+        .iter()
+        .enumerate()
+        .map(|(i, g)| (rel_dir(root, g), i))
+        .collect();
+    rows_by_dir.sort_by(|(da, ia), (db, ib)| {
+        order
+            .cmp(da, db)
+            .then_with(|| order.cmp(&groups[*ia].base_path, &groups[*ib].base_path))
+    });
+
+    let mut counts: BTreeMap<PathBuf, usize> = BTreeMap::new();
+    for (dir, _) in &rows_by_dir {
+        for p in prefixes(dir) {
+            *counts.entry(p).or_default() += 1;
+        }
+    }
+
+    let mut rows = Vec::new();
+    let mut emitted = BTreeSet::new();
+    for (dir, index) in rows_by_dir {
+        let chain = prefixes(&dir);
+        for (depth, p) in chain.iter().enumerate() {
+            if chain[..depth].iter().any(|a| collapsed.contains(a)) {
+                break;
+            }
+            if emitted.insert(p.clone()) {
+                rows.push(TreeRow::Dir {
+                    path: p.clone(),
+                    depth,
+                    count: counts[p],
+                    collapsed: collapsed.contains(p),
+                });
+            }
+        }
+        if !chain.iter().any(|a| collapsed.contains(a)) {
+            rows.push(TreeRow::Group {
+                index,
+                depth: chain.len(),
+            });
+        }
+    }
+    rows
+}
+
+/// Indices of every group anywhere below `dir` (relative to `root`).
+pub fn groups_under(root: &Path, groups: &[ConflictGroup], dir: &Path) -> Vec<usize> {
+    groups
+        .iter()
+        .enumerate()
+        .filter(|(_, g)| rel_dir(root, g).starts_with(dir))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups(paths: &[&str]) -> Vec<ConflictGroup> {
+        paths
+            .iter()
+            .map(|p| ConflictGroup {
+                base_path: Path::new("/r").join(p),
+                candidates: Vec::new(),
+                chosen: None,
+                references: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn dir(path: &str, depth: usize, count: usize, collapsed: bool) -> TreeRow {
+        TreeRow::Dir {
+            path: PathBuf::from(path),
+            depth,
+            count,
+            collapsed,
+        }
+    }
+
+    #[test]
+    fn nests_groups_under_directories_with_counts() {
+        let gs = groups(&["docs/b.txt", "top.txt", "docs/a.txt", "docs/old/c.txt"]);
+        let rows = tree_rows(Path::new("/r"), &gs, &BTreeSet::new(), PathOrder::Natural);
+        assert_eq!(
+            rows,
+            [
+                TreeRow::Group { index: 1, depth: 0 },
+                dir("docs", 0, 3, false),
+                TreeRow::Group { index: 2, depth: 1 },
+                TreeRow::Group { index: 0, depth: 1 },
+                dir("docs/old", 1, 1, false),
+                TreeRow::Group { index: 3, depth: 2 },
+            ]
+        );
+        assert_eq!(
+            groups_under(Path::new("/r"), &gs, Path::new("docs")),
+            [0, 2, 3]
+        );
+    }
+
+    #[test]
+    fn directories_follow_the_path_order() {
+        let gs = groups(&["Shots10/a.jpg", "shots2/b.jpg"]);
+        let dirs = |order| {
+            tree_rows(Path::new("/r"), &gs, &BTreeSet::new(), order)
+                .into_iter()
+                .filter_map(|r| match r {
+                    TreeRow::Dir { path, .. } => Some(path.display().to_string()),
+                    TreeRow::Group { .. } => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(dirs(PathOrder::Natural), ["shots2", "Shots10"]);
+        assert_eq!(dirs(PathOrder::Bytes), ["Shots10", "shots2"]);
+    }
+
+    #[test]
+    fn collapsed_directories_hide_their_contents() {
+        let gs = groups(&["docs/a.txt", "docs/old/c.txt", "pics/x.jpg"]);
+        let collapsed = BTreeSet::from([PathBuf::from("docs")]);
+        let rows = tree_rows(Path::new("/r"), &gs, &collapsed, PathOrder::Natural);
+        assert_eq!(
+            rows,
+            [
+                dir("docs", 0, 2, true),
+                dir("pics", 0, 1, false),
+                TreeRow::Group { index: 2, depth: 1 },
+            ]
+        );
+    }
+}
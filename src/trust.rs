@@ -0,0 +1,46 @@
+use crate::model::ConflictGroup;
+use crate::scan::conflict_meta;
+
+/// Rank of `device` in `trust` (least trusted first), so higher means more trusted.
+/// Conflict names carry Syncthing's short device ID (the first block of the full ID), so
+/// entries may be either full or short IDs; matching is case-insensitive.
+pub fn trust_rank(trust: &[String], device: &str) -> Option<usize> {
+    trust.iter().position(|entry| same_device(entry, device))
+}
+
+/// Whether `entry` (a full or short ID from the config) names `device`, the short ID in a
+/// conflict name.
+pub fn same_device(entry: &str, device: &str) -> bool {
+    let (entry, device) = (
+        entry.trim().to_ascii_uppercase(),
+        device.to_ascii_uppercase(),
+    );
+    entry == device || entry.split('-').next() == Some(device.as_str())
+}
+
+/// Sets each candidate's trust rank from the device in its conflict name. Originals carry
+/// no device, so they stay unranked.
+pub fn attach_trust(trust: &[String], groups: &mut [ConflictGroup]) {
+    for c in groups.iter_mut().flat_map(|g| g.candidates.iter_mut()) {
+        c.trust = conflict_meta(&c.path).and_then(|m| trust_rank(trust, &m.device));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_full_and_short_ids() {
+        let trust = vec![
+            "PHONE12".to_string(),
+            "LAPTOP1-AAAAAAA-BBBBBBB-CCCCCCC-DDDDDDD-EEEEEEE-FFFFFFF-GGGGGGG".to_string(),
+            "desktop".to_string(),
+        ];
+        assert_eq!(trust_rank(&trust, "PHONE12"), Some(0));
+        assert_eq!(trust_rank(&trust, "LAPTOP1"), Some(1));
+        assert_eq!(trust_rank(&trust, "DESKTOP"), Some(2));
+        assert_eq!(trust_rank(&trust, "TABLET1"), None);
+        assert_eq!(trust_rank(&trust, "AAAAAAA"), None);
+    }
+}
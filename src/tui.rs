@@ -1,26 +1,98 @@
-use crate::model::ConflictGroup;
-use crate::ops::{archive_dir_for, ensure_dir, move_file, unique_name};
-use crate::scan::{rel_path, scan_conflicts};
+use crate::commands::{self, history_path, Source};
+
+use synctui_resolver::aliases::DeviceAliases;
+use synctui_resolver::blockdiff::{spawn_block_diffs, BlockDiff};
+use synctui_resolver::cleanup::{delete_leftovers, find_leftovers, Leftover, LeftoverKind};
+use synctui_resolver::collate::PathOrder;
+use synctui_resolver::config::{self, parse_duration, parse_size, Config};
+use synctui_resolver::conflictstats::conflict_stats;
+use synctui_resolver::diff::{
+    diffstat, read_text, spawn_diffstats, spawn_unified_diff, unified_diff, DiffEvent, DiffOptions,
+    DiffstatResult,
+};
+use synctui_resolver::docmeta::{doc_props, DocProps};
+use synctui_resolver::filetype::{group_kind, FileKind};
+use synctui_resolver::fixture::{demo_groups, generate, FixtureSpec, Pattern};
+use synctui_resolver::fmt::{
+    format_age, format_gap, format_relative, format_size, format_time, format_time_ago,
+};
+use synctui_resolver::hashcache::{hash_cached, spawn_hashes, HashCache};
+use synctui_resolver::highlight::{DiffKind, Highlighter, Token};
+use synctui_resolver::history::History;
+use synctui_resolver::hooks::{HookEvent, HookGroup, Hooks};
+use synctui_resolver::identical::{
+    may_have_duplicates, spawn_duplicate_check, spawn_identity_checks,
+};
+use synctui_resolver::keys::{Keymap, Scope};
+use synctui_resolver::merge::{write_merged, Merge, Segment, Take};
+use synctui_resolver::model::{
+    Candidate, ConflictGroup, DeletedOriginal, Strategy, DEFAULT_SKEW_THRESHOLD,
+};
+use synctui_resolver::ops::{
+    archive_dir_for, expand_user_path, render_rename_template, running_as_root,
+    unique_suffix_millis, ArchiveTarget, RenameVars, ARCHIVE_DIR,
+};
+use synctui_resolver::plan::{
+    check_free_space, describe_group, kept_destination, pause_folders, planned_ops, rescan_folders,
+    resume_folders, spawn_apply_then, spawn_check_applied, write_plan, AppliedCheck, ApplyCost,
+    ApplyOptions, GroupOutcome, PlannedGroup, ResolutionPlan,
+};
+use synctui_resolver::prune::{prune, prune_candidates, PruneEntry};
+use synctui_resolver::recency::Recency;
+use synctui_resolver::rules::{append_pick_rule, fired_rules, pick_rule, PickRule};
+use synctui_resolver::scan::{
+    conflict_meta, rel_path, scan_conflicts_with, scan_dirs, spawn_scan, stat_candidate,
+    ConflictFilter, FileStamp, ScanOptions,
+};
+use synctui_resolver::scancache::{checkpoint_path, Checkpoint};
+use synctui_resolver::session::{session_path, Session, AUTOSAVE_EVERY};
+use synctui_resolver::settle::{rescan_delay, RescanTimer};
+use synctui_resolver::snapshots::{
+    configured_shadow, discover_snapshots, shadow_source, SnapshotSource,
+};
+use synctui_resolver::space::{space_by_top_dir, space_summary, SpaceSummary};
+use synctui_resolver::stignore::{folder_root, ignore_archive, ignores_archive};
+use synctui_resolver::syncthing::{folder_preset, DbFile, Folder, SyncthingClient};
+use synctui_resolver::thumbnail::{self, fit, half_blocks, hexdump, Graphics};
+use synctui_resolver::tree::{groups_under, tree_rows, TreeRow};
+use synctui_resolver::trust::attach_trust;
+use synctui_resolver::tutorial::{self, Step};
+use synctui_resolver::undo::{undo_group, undo_record, UndoGroup};
+use synctui_resolver::versions::attach_references;
+use synctui_resolver::watch::ConflictWatcher;
+
 use anyhow::{anyhow, Context, Result};
 use clap::Parser;
+use crossterm::cursor::MoveTo;
 use crossterm::event::{
     self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
+    MouseButton, MouseEvent, MouseEventKind,
 };
 use crossterm::execute;
+use crossterm::queue;
+use crossterm::style::Print;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
+use image::RgbImage;
 use ratatui::backend::CrosstermBackend;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::widgets::{
+    Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Scrollbar,
+    ScrollbarOrientation, ScrollbarState, Wrap,
+};
 use ratatui::Terminal;
-use std::collections::BTreeSet;
-use std::io::{self, Stdout};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Stdout, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::time::{Duration, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 #[derive(Clone, Copy, Debug)]
 struct Theme {
@@ -35,6 +107,7 @@ struct Theme {
 
     badge_apply: Style,
     badge_dry_run: Style,
+    badge_root: Style,
 
     selected_mark: Style,
     unpicked: Style,
@@ -43,6 +116,13 @@ struct Theme {
     message_warn: Style,
     message_error: Style,
 
+    /// Backgrounds for the words that changed within a removed or added diff line.
+    diff_removed_word: Style,
+    diff_added_word: Style,
+
+    /// Plan lines matching the log search.
+    search_match: Style,
+
     confirm_title_apply: Style,
     confirm_title_dry_run: Style,
 }
@@ -74,6 +154,10 @@ impl Theme {
                 .fg(Color::Black)
                 .bg(Color::Yellow)
                 .add_modifier(Modifier::BOLD),
+            badge_root: Style::default()
+                .fg(Color::White)
+                .bg(Color::Red)
+                .add_modifier(Modifier::BOLD),
 
             selected_mark: Style::default().fg(Color::Green),
             unpicked: Style::default().fg(Color::Yellow),
@@ -82,6 +166,11 @@ impl Theme {
             message_warn: Style::default().fg(Color::Yellow),
             message_error: Style::default().fg(Color::Red),
 
+            diff_removed_word: Style::default().bg(Color::Rgb(0x5c, 0x2b, 0x2e)),
+            diff_added_word: Style::default().bg(Color::Rgb(0x2b, 0x4a, 0x2e)),
+
+            search_match: Style::default().fg(Color::Black).bg(Color::Yellow),
+
             confirm_title_apply: Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
             confirm_title_dry_run: Style::default()
                 .fg(Color::Yellow)
@@ -100,9 +189,13 @@ fn titled_block(title: &str, theme: Theme) -> Block<'_> {
 #[derive(Parser, Debug, Clone)]
 #[command(
     name = "synctui-resolver",
-    about = "Resolve Syncthing sync-conflict files via TUI"
+    about = "Resolve Syncthing sync-conflict files via TUI",
+    args_conflicts_with_subcommands = true
 )]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<commands::Command>,
+
     /// Root directory to scan
     #[arg(default_value = ".")]
     pub path: PathBuf,
@@ -114,13 +207,406 @@ pub struct Args {
     /// Include hidden files and dot-directories
     #[arg(long)]
     pub include_hidden: bool,
+
+    /// Scan inside symlinked directories (and symlinked conflict files)
+    #[arg(long)]
+    pub follow_symlinks: bool,
+
+    /// Let apply move or replace symlinks and files inside symlinked directories; without
+    /// it such groups are blocked
+    #[arg(long)]
+    pub allow_symlinks: bool,
+
+    /// Send files that a loser rule deletes, and pruned archives, to the OS trash instead
+    /// of removing them
+    #[arg(long)]
+    pub trash: bool,
+
+    /// Send non-chosen versions to a remote instead of `.stconflict-archive`
+    /// (e.g. `rclone:backup:conflicts/`)
+    #[arg(long, value_name = "REMOTE")]
+    pub archive_remote: Option<String>,
+
+    /// Config file (defaults to `<config dir>/synctui-resolver/config.toml` if present)
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Syncthing GUI/API address (overrides `[syncthing].url`)
+    #[arg(long, value_name = "URL")]
+    pub syncthing_url: Option<String>,
+
+    /// Syncthing API key; enables pausing folders during apply (overrides `[syncthing].api_key`)
+    #[arg(long, value_name = "KEY")]
+    pub syncthing_api_key: Option<String>,
+
+    /// Rename the kept file on apply, e.g. `{stem}-{device}{ext}`
+    /// (placeholders: name, stem, ext, device, date, time)
+    #[arg(long, value_name = "TEMPLATE")]
+    pub rename_template: Option<String>,
+
+    /// Watch the root and add new conflicts to the list while the TUI is open
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Watch through Syncthing's event stream (needs the API key); works on network mounts
+    /// and large folders. Combine with --watch to also use filesystem notifications
+    #[arg(long)]
+    pub watch_syncthing: bool,
+
+    /// Explore the UI with generated conflicts held in memory; the filesystem is never read
+    /// or changed, and the output is the same on every run (for screenshots and recordings)
+    #[arg(long, conflicts_with_all = ["apply", "watch", "watch_syncthing", "only_differing"])]
+    pub demo: bool,
+
+    /// Walk through selecting, picking, planning, a dry run and an apply on generated
+    /// conflicts in a scratch folder, removed on quit
+    #[arg(long, conflicts_with_all = ["apply", "demo", "watch", "watch_syncthing"])]
+    pub tutorial: bool,
+
+    /// Hide groups whose versions all have identical content (hashes every group up front)
+    #[arg(long)]
+    pub only_differing: bool,
+
+    /// Only show groups with a conflict at least AGE old (e.g. `30d`), by the time in its
+    /// name (or its mtime)
+    #[arg(long, value_name = "AGE", value_parser = parse_duration)]
+    pub older_than: Option<Duration>,
+
+    /// Only show groups with a conflict at most AGE old (e.g. `7d`)
+    #[arg(long, value_name = "AGE", value_parser = parse_duration)]
+    pub newer_than: Option<Duration>,
+
+    /// Only show groups with a conflict of at least SIZE (e.g. `1M`)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub min_size: Option<u64>,
+
+    /// Only show groups of one file type, by extension and content: image, video, audio,
+    /// document, code, text, archive or binary
+    #[arg(long = "type", value_name = "KIND")]
+    pub kind: Option<FileKind>,
+
+    /// Instead of starting the TUI, list archived files older than AGE (e.g. `90d`, `12h`,
+    /// `2w`); with `--apply` they are deleted
+    #[arg(long, value_name = "AGE", value_parser = parse_duration)]
+    pub prune_archive: Option<Duration>,
+
+    /// Instead of starting the TUI, list conflict copies byte-identical to another copy of
+    /// the same file; with `--apply` they are archived, keeping one of each
+    #[arg(long)]
+    pub dedupe: bool,
+
+    /// Instead of starting the TUI, print where the conflicts come from: counts by device,
+    /// directory, file extension and age (the `F2` view)
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Instead of starting the TUI, print which version each of these strategies (e.g.
+    /// `newest,oldest,largest`) would keep in every group, and where they disagree
+    #[arg(long, value_name = "LIST", value_delimiter = ',')]
+    pub compare_strategies: Vec<Strategy>,
+
+    /// Print a scan report instead of starting the TUI (what happens anyway when stdin or
+    /// stdout isn't a terminal)
+    #[arg(long)]
+    pub non_interactive: bool,
+
+    /// Print nothing but errors in headless runs (`--non-interactive`, `--prune-archive`,
+    /// `--dedupe`);
+    /// the exit code tells the outcome: 0 no conflicts, 1 conflicts found, 2 errors
+    #[arg(long, short)]
+    pub quiet: bool,
+
+    /// Pre-pick groups whose original was deleted: `promote` moves the newest conflict to
+    /// the base path, `discard` accepts the deletion and archives every conflict
+    #[arg(long, value_name = "POLICY")]
+    pub deleted_original: Option<DeletedOriginal>,
+
+    /// Pre-pick every group the way its path was resolved last time (from the history)
+    #[arg(long)]
+    pub like_last_time: bool,
+
+    /// Warn about groups whose conflict names and mtimes disagree by more than AGE
+    /// (default 1d); newest/oldest quick picks skip them
+    #[arg(long, value_name = "AGE", value_parser = parse_duration)]
+    pub skew_threshold: Option<Duration>,
+
+    /// Let newest/oldest pick groups with clock skew anyway (the warning stays)
+    #[arg(long)]
+    pub trust_mtime: bool,
+
+    /// Checksum copies made across file systems before removing their source, and record
+    /// the kept file's SHA-256 in the archive index and history
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Write every confirmed plan (absolute paths) to this file; `.json` for JSON,
+    /// anything else for tab-separated text
+    #[arg(long, value_name = "PATH")]
+    pub plan_out: Option<PathBuf>,
+
+    /// Give archives and kept files back to the owner of their directory
+    /// (for runs as root, which would otherwise leave root-owned files behind)
+    #[arg(long)]
+    pub chown_to_owner: bool,
+
+    /// Append every file moved, deleted or written to this JSONL file, with the time,
+    /// user, host, session and SHA-256 (overrides `audit_log` in the config)
+    #[arg(long, value_name = "PATH")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Open the conflict group at PATH (relative to the root; its base file or any of its
+    /// conflict copies) in the Pick view once the scan is done
+    #[arg(long, value_name = "PATH")]
+    pub goto: Option<PathBuf>,
+
+    /// A read-only copy of the root (a mounted backup or snapshot); its copy of each base
+    /// file is listed as a reference to diff against. Overrides `[shadow_roots]`
+    #[arg(long, value_name = "DIR")]
+    pub shadow_root: Option<PathBuf>,
+}
+
+impl Args {
+    /// What the command line asks the scanner for.
+    pub fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            include_hidden: self.include_hidden,
+            follow_symlinks: self.follow_symlinks,
+            filter: ConflictFilter {
+                older_than: self.older_than,
+                newer_than: self.newer_than,
+                min_size: self.min_size,
+                kind: self.kind,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum GroupStatus {
+    Pending,
+    Done,
+    Failed(String),
+    /// Still pending when the run was cancelled; nothing was changed.
+    Cancelled,
+}
+
+/// A visual range selection: every visible group between `anchor` and the cursor is
+/// selected on top of the selection that existed when it started.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Visual {
+    anchor: usize,
+    before: BTreeSet<usize>,
+    /// Started by Shift+Up/Down, shift-click or dragging; ends on the next plain move.
+    shift: bool,
+}
+
+/// A full scan walking the root on a background thread: at startup, or after the filter
+/// changed.
+struct ScanRun {
+    rx: Receiver<Result<Vec<ConflictGroup>>>,
+    /// Tells the scan thread to stop walking.
+    cancel: Arc<AtomicBool>,
+    /// Started by a filter change, which reports the filter and the group count.
+    after_filter: bool,
+}
+
+/// Syncthing's record of the base file in the detail panel, fetched in the background.
+enum Provenance {
+    Loading(Receiver<Result<DbFile>>),
+    Loaded(Result<DbFile, String>),
+}
+
+/// Hashing on a background thread whose results a view is waiting on. Dropping it sets
+/// `cancel`, which stops the thread before its next file.
+struct Hashing<T> {
+    rx: Receiver<T>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl<T> Hashing<T> {
+    fn spawn(start: impl FnOnce(Arc<AtomicBool>) -> Receiver<T>) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        Hashing {
+            rx: start(cancel.clone()),
+            cancel,
+        }
+    }
+
+    /// The results that have arrived (with `wait`, all of them), and whether the
+    /// thread is done.
+    fn poll(&self, wait: bool) -> (Vec<T>, bool) {
+        if wait {
+            return (self.rx.iter().collect(), true);
+        }
+        let mut got = Vec::new();
+        loop {
+            match self.rx.try_recv() {
+                Ok(t) => got.push(t),
+                Err(TryRecvError::Empty) => return (got, false),
+                Err(TryRecvError::Disconnected) => return (got, true),
+            }
+        }
+    }
+}
+
+impl<T> Drop for Hashing<T> {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Progress of an apply (or dry-run) started from the confirmation.
+struct ApplyRun {
+    dry_run: bool,
+    /// Every group in the run, in plan order, with its latest result.
+    entries: Vec<(usize, GroupStatus)>,
+    /// Outcomes from the apply thread, drained by `run_loop`; `None` when it isn't running.
+    rx: Option<Receiver<GroupOutcome>>,
+    /// Tells the apply thread to stop before its next group.
+    cancel: Arc<AtomicBool>,
+    /// Syncthing folders paused by this run, resumed when the apply thread finishes.
+    paused: Vec<String>,
+    /// Problems that don't fail a group (Syncthing resume, history logging).
+    warnings: Vec<String>,
+    /// How to undo each group applied so far, retries included.
+    undo: Vec<UndoGroup>,
+    /// Kept versions' SHA-256 by group, from `--verify` or else taken before the move,
+    /// for the checks after the run.
+    kept_hashes: BTreeMap<usize, String>,
+}
+
+impl ApplyRun {
+    fn started(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|(_, st)| *st != GroupStatus::Pending)
+    }
+
+    fn running(&self) -> bool {
+        self.rx.is_some()
+    }
+
+    fn failed(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entries
+            .iter()
+            .filter(|(_, st)| matches!(st, GroupStatus::Failed(_)))
+            .map(|&(gi, _)| gi)
+    }
+
+    /// Failed and cancelled groups: what `R` runs again.
+    fn unfinished(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entries
+            .iter()
+            .filter(|(_, st)| matches!(st, GroupStatus::Failed(_) | GroupStatus::Cancelled))
+            .map(|&(gi, _)| gi)
+    }
+}
+
+/// The rescans put off after a large apply so the tool and Syncthing don't both read
+/// the disk while it is still busy with the moves.
+struct DeferredRescan {
+    timer: RescanTimer,
+    /// Folders to rescan in the list, as for `rescan_dirs`.
+    dirs: BTreeSet<PathBuf>,
+    /// Syncthing folders resumed without their rescan.
+    folders: Vec<String>,
+    /// Applied groups to check once the disk has settled.
+    checks: Vec<AppliedCheck>,
+}
+
+/// Confirmation one directory at a time (`B`): each directory gets its own
+/// Confirm/Apply round before the next one is planned.
+struct DirBatch {
+    /// Directories still to review (relative to the root) with their groups' base paths.
+    /// Paths rather than indices, since applying a directory rescans the list.
+    pending: VecDeque<(PathBuf, Vec<PathBuf>)>,
+    current: PathBuf,
+    total: usize,
+}
+
+/// Optional List-mode columns, toggled from the columns menu (`m`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Column {
+    Hash,
+    Device,
+    Age,
+    Size,
+    Diffstat,
+    Type,
+}
+
+impl Column {
+    const ALL: [Column; 6] = [
+        Column::Hash,
+        Column::Device,
+        Column::Age,
+        Column::Size,
+        Column::Diffstat,
+        Column::Type,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Column::Hash => "hash badge (= identical, ≠ differs)",
+            Column::Device => "devices",
+            Column::Age => "age of the newest conflict",
+            Column::Size => "largest version size",
+            Column::Diffstat => "diffstat (original vs picked or newest)",
+            Column::Type => "file type (image, document, code, ...)",
+        }
+    }
+
+    /// Short name for the list's header row.
+    fn heading(self) -> &'static str {
+        match self {
+            Column::Hash => "=",
+            Column::Device => "Devices",
+            Column::Age => "Age",
+            Column::Size => "Size",
+            Column::Diffstat => "Diff",
+            Column::Type => "Type",
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Mode {
     List,
     Pick,
+    RefPath,
+    /// Typing the age for pruning archives (`X`).
+    PruneAge,
+    /// Typing how long to snooze the selected groups (`s`).
+    SnoozeFor,
+    /// Previewing the archived files a prune would delete.
+    Prune,
+    /// Syncthing temp files and nested conflicts to delete (`W`).
+    Cleanup,
+    /// Typing the file to export the confirmed plan to (`w`).
+    PlanOut,
+    Timeline,
+    Detail,
+    /// Highlighted file contents (`v`) or `diff -u` output (`d`).
+    Preview,
+    /// Choosing hunk by hunk between the original and a conflict (`m`).
+    Merge,
+    /// The Plan / Log panel focused at full height (`Tab`).
+    Log,
+    /// Typing text to find in the Plan / Log panel (`/`).
+    LogSearch,
+    /// Every view's keys, full screen (`?`).
+    Help,
+    Columns,
+    /// Editing the age and size filter (`F`).
+    Filter,
+    /// Space taken by conflicts and what the plan frees or archives (`S`).
+    Stats,
+    /// Conflict copies counted by device, directory, extension and age (`F2`).
+    Origins,
+    /// Writing a pick rule from the highlighted group (`w`).
+    RuleEdit,
     Confirm,
+    Apply,
     Error,
     Done,
 }
@@ -128,54 +614,531 @@ enum Mode {
 struct App {
     root: PathBuf,
     apply: bool,
+    /// `--demo`: groups are generated in memory and apply can't be turned on.
+    demo: bool,
+    /// `--tutorial`: the step shown over the UI; the root is a generated scratch folder.
+    tutorial: Option<Step>,
     include_hidden: bool,
+    follow_symlinks: bool,
+    options: ApplyOptions,
+    syncthing: Option<SyncthingClient>,
+    snapshots: Vec<SnapshotSource>,
+    watcher: Option<ConflictWatcher>,
+    /// A watch notification arrived; merge a fresh scan once back in List mode.
+    watch_pending: bool,
+    /// Base paths of groups that appeared while watching (cleared when opened).
+    new_groups: BTreeSet<PathBuf>,
+    /// `--goto` target, opened by the first scan that finishes.
+    goto: Option<PathBuf>,
+    /// Base paths of groups the last confirmation found blocked (not writable, on a
+    /// read-only mount, symlinked), badged in the list until planned again or rescanned.
+    blocked: BTreeSet<PathBuf>,
     mode: Mode,
     groups: Vec<ConflictGroup>,
     list_state: ListState,
     pick_state: ListState,
+    /// Cursor over `pick_rows`, the Pick list as drawn; `pick_state` follows it with the
+    /// candidate index.
+    pick_view: ListState,
+    timeline_state: ListState,
+    /// Mode to return to when the timeline is closed.
+    timeline_from: Mode,
+    /// Mode to return to when the detail panel is closed.
+    detail_from: Mode,
+    /// SHA-256 per row of the group shown in the detail panel (`None` if unreadable,
+    /// too large to hash, or not hashed yet).
+    detail_hashes: Vec<Option<String>>,
+    /// Hashing of those rows, drained into `detail_hashes`.
+    detail_hashing: Option<Hashing<(usize, Option<String>)>>,
+    /// Syncthing's database entry for the base file, fetched on open when connected.
+    detail_provenance: Option<Provenance>,
+    /// Block comparisons against the original, by row, of versions too large to diff.
+    detail_blocks: BTreeMap<usize, BlockDiff>,
+    /// Progress and results of those comparisons, drained into `detail_blocks`.
+    detail_blocks_rx: Option<Receiver<(usize, BlockDiff)>>,
+    detail_scroll: u16,
+    /// Mode to return to when the preview is closed.
+    preview_from: Mode,
+    preview_title: String,
+    /// Files of the built-in diff on screen, so changed diff options can redo it; `None`
+    /// for file previews and `diff` command output.
+    diff_paths: Option<(PathBuf, PathBuf)>,
+    /// Built-in diff settings (`[diff]`), changed at runtime from the diff pane.
+    diff_options: DiffOptions,
+    /// A diff of files over `[diff] max_size`, computing on a worker thread.
+    diff_job: Option<DiffJob>,
+    /// The merge being edited: group index, conflict index and the hunks.
+    merge: Option<(usize, usize, Merge)>,
+    /// Highlighted hunk of the merge.
+    merge_state: ListState,
+    preview: Vec<Line<'static>>,
+    preview_scroll: usize,
+    /// Image shown in the preview pane instead of `preview` lines (`v` on a photo).
+    preview_image: Option<PathBuf>,
+    /// Thumbnail or hexdump of the last non-text version looked at, by path and mtime.
+    file_preview: Option<(PathBuf, Option<SystemTime>, FilePreview)>,
+    /// Image protocol of the terminal (`graphics`, with `auto` resolved).
+    graphics: Graphics,
+    /// Where the last frame left room for a kitty/sixel image, and where one is drawn.
+    graphics_target: Option<(Rect, PathBuf)>,
+    graphics_shown: Option<(Rect, PathBuf)>,
+    /// Loaded on first use; building the syntax set takes a moment.
+    highlighter: Option<Highlighter>,
     selected_groups: BTreeSet<usize>,
+    /// Active `V` range selection.
+    visual: Option<Visual>,
+    /// Archived files the pending prune would delete.
+    prune_preview: Vec<PruneEntry>,
+    /// Leftovers the Cleanup view would delete.
+    leftovers: Vec<Leftover>,
+    /// Where the group list was last drawn, for mouse hit-testing.
+    list_area: Rect,
+    /// Where the Pick list was last drawn, for mouse hit-testing.
+    pick_area: Rect,
+    /// Confirm buttons as last drawn, with the default key each one presses.
+    confirm_buttons: Vec<(Rect, KeyCode)>,
+    /// Last left click on a list row, to tell a double-click.
+    last_click: Option<(Instant, usize)>,
+    /// Pre-pick groups like their last resolution on every scan (`--like-last-time`).
+    like_last_time: bool,
+    /// `--deleted-original` policy applied to unpicked groups on every scan.
+    deleted_original: Option<DeletedOriginal>,
+    /// Name/mtime disagreement above which a group is flagged for clock skew.
+    skew_threshold: Duration,
+    /// `--trust-mtime`: newest/oldest also pick groups with clock skew.
+    trust_mtime: bool,
+    /// Key bindings from `[keys]`.
+    keymap: Keymap,
+    /// Export every confirmed plan here (`--plan-out`).
+    plan_out: Option<PathBuf>,
     message: String,
+    /// Text typed into the reference-path prompt.
+    input: String,
     planned_ops: Vec<String>,
     planned_targets: Vec<usize>,
+    /// Planned groups that apply will refuse because something isn't writable.
+    planned_blocked: usize,
+    /// Estimated cost of applying the planned groups.
+    planned_cost: ApplyCost,
+    /// Syncthing folder whose `.stignore` the Confirm view offers to add the archive to:
+    /// the plan creates a local archive there and nothing ignores it yet.
+    stignore_offer: Option<PathBuf>,
+    /// Each planned group's files as they were when planned, parallel to `planned_targets`.
+    planned_stamps: Vec<Vec<FileStamp>>,
+    /// Files that changed between planning and `y`, which planned again instead.
+    plan_changes: Vec<String>,
+    apply_run: Option<ApplyRun>,
+    /// A full scan in progress; Esc in the List view cancels it.
+    scan_run: Option<ScanRun>,
+    apply_state: ListState,
+    /// Rescans waiting for the disk to settle after a large apply.
+    deferred_rescan: Option<DeferredRescan>,
+    /// Applied batches, oldest first; `Ctrl-z` undoes the last one.
+    undo_stack: Vec<Vec<UndoGroup>>,
 
     compare_error: Option<String>,
     last_error: Option<String>,
+    /// Set after a child process used the terminal so the next draw repaints everything.
+    needs_clear: bool,
+    /// Viewer command from the config (falls back to `$VISUAL`/`$EDITOR`).
+    viewer: Option<String>,
+    /// Commands run around real applies (`[hooks]`); never run in demo mode.
+    hooks: Hooks,
+    /// Device IDs from least to most trusted (`device_trust` in the config).
+    device_trust: Vec<String>,
+    /// Names shown for device IDs (`[device_aliases]` in the config).
+    device_aliases: DeviceAliases,
+    /// Rows visible in the main list area, for paging keys.
+    page_rows: usize,
+    /// First plan line shown in the footer and the focused log; follows the Confirm
+    /// cursor.
+    log_scroll: usize,
+    /// Cursor over `plan_rows` in the Confirm view.
+    plan_state: ListState,
+    /// Folded directories in the Confirm view's plan, relative to the root (`.` for the
+    /// root itself).
+    plan_folded: BTreeSet<PathBuf>,
+    /// Plan lines that fit where the plan was last drawn, for paging keys.
+    log_rows: usize,
+    /// Mode to return to when the focused log is closed.
+    log_from: Mode,
+    /// Text last searched for in the log (`/`).
+    log_search: String,
+    /// Mode to return to when the help overlay is closed.
+    help_from: Mode,
+    /// First line shown in the help overlay.
+    help_scroll: usize,
+    /// Where applied groups are logged; `None` if the database couldn't be opened.
+    history: Option<History>,
+    /// List mode shows groups nested under their directories (`T`).
+    tree_view: bool,
+    /// Cursor over `tree_rows`; `list_state` follows it (none on directory rows).
+    tree_state: ListState,
+    /// Folded directories in the tree view, relative to the root.
+    collapsed: BTreeSet<PathBuf>,
+    dir_batch: Option<DirBatch>,
+    /// Whether each group's versions are identical, by base path. Missing means not
+    /// checked yet; `None` means it couldn't be determined.
+    identical: HashMap<PathBuf, Option<bool>>,
+    /// Folding of the highlighted group's identical copies, for its base path.
+    duplicates_check: Option<(PathBuf, Hashing<Vec<Option<usize>>>)>,
+    /// Identity checks of the listed groups; Esc in the List view stops them.
+    identity_checks: Option<Hashing<(PathBuf, Option<bool>)>>,
+    /// Checks of applied groups still running, drained by `run_loop`.
+    check_rxs: Vec<Receiver<Vec<String>>>,
+    /// Per base path, the conflict copy each candidate is byte-identical to (see
+    /// `duplicate_conflicts`); worked out when the group's Pick view is drawn.
+    duplicates: HashMap<PathBuf, Vec<Option<usize>>>,
+    /// Office and OpenDocument properties shown in the Pick view, by path and mtime.
+    doc_props: HashMap<(PathBuf, Option<SystemTime>), Option<DocProps>>,
+    /// Hash cache database shared by identity checks and the detail view; `None` hashes
+    /// every time (demo mode, or no cache dir).
+    hash_cache: Option<PathBuf>,
+    /// Directory full scans checkpoint into so an interrupted one resumes; `None` in demo
+    /// mode or without a cache dir.
+    scan_cache: Option<PathBuf>,
+    /// File picks and selection are autosaved to; `None` in demo mode, without a data
+    /// dir, or after a save failed.
+    session_path: Option<PathBuf>,
+    /// What the session file holds; `None` until the first scan restored it.
+    session_saved: Option<Session>,
+    /// When the session was last compared with the file.
+    session_at: Instant,
+    /// Drop groups with identical versions on every scan (`--only-differing`).
+    only_differing: bool,
+    /// Age and size limits applied on every scan (`--older-than` etc., or `F`).
+    filter: ConflictFilter,
+    /// Text of the filter popup's fields: older than, newer than, min size, type.
+    filter_inputs: [String; 4],
+    /// File type per group, filled while the type column is shown.
+    kinds: HashMap<PathBuf, FileKind>,
+    /// List groups by file type instead of by path, on every scan (`y`).
+    sort_by_type: bool,
+    /// How groups are ordered by path (`path_order` in the config).
+    path_order: PathOrder,
+    /// Field of the filter popup being edited.
+    filter_field: usize,
+    /// `[[pick_rules]]` from the config, plus those written from the TUI.
+    pick_rules: Vec<PickRule>,
+    /// `[folder_presets]` from the config: strategies by Syncthing folder ID or label.
+    folder_presets: BTreeMap<String, Strategy>,
+    /// Syncthing's folders, fetched at startup when there are folder presets.
+    folders: Vec<Folder>,
+    /// Config file new pick rules are appended to.
+    config_path: Option<PathBuf>,
+    /// Strategy of the rule being written; its glob is in `input`.
+    rule_strategy: Strategy,
+    /// Rule popup field being edited: 0 glob, 1 strategy.
+    rule_field: usize,
+    /// Optional columns shown in List mode.
+    columns: BTreeSet<Column>,
+    columns_state: ListState,
+    /// Line diffstats keyed by (original, compared version); `None` for non-text files.
+    diffstats: HashMap<(PathBuf, PathBuf), Option<(usize, usize)>>,
+    /// Pick-view diffstats computed in the background, drained into `diffstats`.
+    diffstat_rx: Option<Receiver<DiffstatResult>>,
 }
 
 pub fn run(args: Args) -> Result<()> {
-    let root = args
-        .path
-        .canonicalize()
-        .with_context(|| format!("open {:?}", args.path))?;
-    let groups = scan_conflicts(&root, args.include_hidden)?;
+    let root = if args.demo {
+        // Never created; apply stays a dry-run.
+        std::env::temp_dir().join("synctui-resolver-demo")
+    } else if args.tutorial {
+        let dir = std::env::temp_dir().join(format!(
+            "synctui-resolver-tutorial-{}",
+            unique_suffix_millis()
+        ));
+        generate(&dir, &tutorial::spec())?;
+        dir.canonicalize()?
+    } else {
+        args.path
+            .canonicalize()
+            .with_context(|| format!("open {:?}", args.path))?
+    };
+    let archive = match &args.archive_remote {
+        Some(spec) => ArchiveTarget::parse(spec)?,
+        None => ArchiveTarget::Local,
+    };
+    if let Some(t) = &args.rename_template {
+        // Fail fast on typos instead of on the first apply.
+        let vars = RenameVars {
+            base_name: "file.txt",
+            device: "DEVICE",
+            date: "20000101",
+            time: "000000",
+        };
+        render_rename_template(t, &vars)?;
+    }
+    let config = Config::load(args.config.as_deref())?;
+    let keymap = Keymap::new(&config.keys)?;
+    // Neither the demo nor the tutorial leaves traces in the user's data.
+    let sandboxed = args.demo || args.tutorial;
+    if !sandboxed {
+        commands::enable_audit(args.audit_log.as_deref(), &config)?;
+    }
+    let history = if sandboxed {
+        Err(anyhow!("not recorded in demo or tutorial mode"))
+    } else {
+        history_path(None, &config).and_then(|p| History::open(&p))
+    };
+    let hash_cache = commands::hash_cache_path(&config).filter(|_| !sandboxed);
+    let scan_cache = commands::scan_cache_dir(&config).filter(|_| !sandboxed);
+    let session_path = commands::session_dir(&config)
+        .filter(|_| !sandboxed)
+        .map(|d| session_path(&d, &root));
+    let goto = args.goto.as_ref().map(|p| root.join(p));
+    let syncthing = syncthing_client(&args, &config).filter(|_| !sandboxed);
+    let mut snapshots = if sandboxed {
+        Vec::new()
+    } else {
+        discover_snapshots(&root, 5)
+    };
+    // An unmounted shadow from the config is skipped (`config check` warns about it).
+    let shadow = match &args.shadow_root {
+        Some(dir) => Some(
+            dir.canonicalize()
+                .with_context(|| format!("--shadow-root {}", dir.display()))?,
+        ),
+        None => configured_shadow(&config.shadow_roots, &root).and_then(|d| d.canonicalize().ok()),
+    };
+    if let Some(dir) = shadow.filter(|_| !sandboxed) {
+        snapshots.push(shadow_source(&root, &dir));
+    }
+    let watcher = if args.watch || args.watch_syncthing {
+        let mut w = ConflictWatcher::new();
+        if args.watch {
+            w.watch_fs(&root)?;
+        }
+        if args.watch_syncthing {
+            let client = syncthing
+                .clone()
+                .ok_or_else(|| anyhow!("--watch-syncthing needs a Syncthing API key"))?;
+            w.watch_syncthing(client, &root)
+                .context("follow Syncthing events")?;
+        }
+        Some(w)
+    } else {
+        None
+    };
 
     let mut app = App {
         root,
         apply: args.apply,
+        demo: args.demo,
+        tutorial: args.tutorial.then_some(Step::Select),
         include_hidden: args.include_hidden,
+        follow_symlinks: args.follow_symlinks,
+        options: ApplyOptions {
+            archive,
+            loser_rules: config.loser_rules,
+            rename_template: args.rename_template.clone(),
+            dry_run: !args.apply,
+            chown_to_owner: args.chown_to_owner,
+            verify: args.verify,
+            allow_symlinks: args.allow_symlinks,
+            min_free_space: config.min_free_space,
+            trash: args.trash,
+        },
+        syncthing,
+        snapshots,
+        watcher,
+        watch_pending: false,
+        new_groups: BTreeSet::new(),
+        goto,
+        blocked: BTreeSet::new(),
         mode: Mode::List,
-        groups,
+        groups: Vec::new(),
         list_state: ListState::default(),
         pick_state: ListState::default(),
+        pick_view: ListState::default(),
+        timeline_state: ListState::default(),
+        timeline_from: Mode::List,
+        detail_from: Mode::List,
+        detail_hashes: Vec::new(),
+        detail_hashing: None,
+        detail_provenance: None,
+        detail_blocks: BTreeMap::new(),
+        detail_blocks_rx: None,
+        detail_scroll: 0,
+        preview_from: Mode::Pick,
+        preview_title: String::new(),
+        diff_paths: None,
+        diff_job: None,
+        diff_options: config.diff,
+        merge: None,
+        merge_state: ListState::default(),
+        preview: Vec::new(),
+        preview_scroll: 0,
+        preview_image: None,
+        file_preview: None,
+        graphics: config.graphics.resolve(|var| std::env::var(var).ok()),
+        graphics_target: None,
+        graphics_shown: None,
+        highlighter: None,
         selected_groups: BTreeSet::new(),
+        visual: None,
+        prune_preview: Vec::new(),
+        leftovers: Vec::new(),
+        list_area: Rect::default(),
+        pick_area: Rect::default(),
+        confirm_buttons: Vec::new(),
+        last_click: None,
+        like_last_time: args.like_last_time,
+        deleted_original: args.deleted_original,
+        skew_threshold: args.skew_threshold.unwrap_or(DEFAULT_SKEW_THRESHOLD),
+        trust_mtime: args.trust_mtime,
+        keymap,
+        plan_out: args.plan_out.clone(),
         message: String::new(),
+        input: String::new(),
         planned_ops: Vec::new(),
         planned_targets: Vec::new(),
+        planned_blocked: 0,
+        planned_cost: ApplyCost::default(),
+        stignore_offer: None,
+        planned_stamps: Vec::new(),
+        plan_changes: Vec::new(),
+        apply_run: None,
+        scan_run: None,
+        deferred_rescan: None,
+        undo_stack: Vec::new(),
+        apply_state: ListState::default(),
 
         compare_error: None,
         last_error: None,
+        needs_clear: false,
+        viewer: config.viewer,
+        hooks: if sandboxed {
+            Hooks::default()
+        } else {
+            config.hooks
+        },
+        device_trust: config.device_trust,
+        device_aliases: DeviceAliases::new(&config.device_aliases),
+        page_rows: 10,
+        log_scroll: 0,
+        plan_state: ListState::default(),
+        plan_folded: BTreeSet::new(),
+        log_rows: 10,
+        log_from: Mode::Confirm,
+        help_from: Mode::List,
+        help_scroll: 0,
+        log_search: String::new(),
+        history: None,
+        tree_view: false,
+        tree_state: ListState::default(),
+        collapsed: BTreeSet::new(),
+        dir_batch: None,
+        identical: HashMap::new(),
+        hash_cache,
+        scan_cache,
+        session_path,
+        session_saved: None,
+        session_at: Instant::now(),
+        identity_checks: None,
+        duplicates_check: None,
+        check_rxs: Vec::new(),
+        duplicates: HashMap::new(),
+        doc_props: HashMap::new(),
+        only_differing: args.only_differing,
+        filter: args.scan_options().filter,
+        filter_inputs: Default::default(),
+        kinds: HashMap::new(),
+        sort_by_type: false,
+        path_order: config.path_order,
+        filter_field: 0,
+        pick_rules: config.pick_rules,
+        folder_presets: config.folder_presets,
+        folders: Vec::new(),
+        config_path: args.config.clone().or_else(config::default_path),
+        rule_strategy: Strategy::Newest,
+        rule_field: 0,
+        columns: BTreeSet::from([Column::Hash]),
+        columns_state: ListState::default(),
+        diffstats: HashMap::new(),
+        diffstat_rx: None,
     };
 
-    if !app.groups.is_empty() {
-        app.list_state.select(Some(0));
+    match history {
+        Ok(h) => app.history = Some(h),
+        Err(e) => app.message = format!("History disabled: {e:#}"),
+    }
+    if let Some(client) = app
+        .syncthing
+        .as_ref()
+        .filter(|_| !app.folder_presets.is_empty())
+    {
+        match client.folders() {
+            Ok(folders) => app.folders = folders,
+            Err(e) => app.message = format!("Folder presets disabled: {e:#}"),
+        }
+    }
+    // A big tree takes a while; the list fills in when the walk is done, and Esc stops it.
+    start_scan(&mut app, false)?;
+    if args.demo {
+        app.message = "Demo: generated conflicts; nothing on disk is read or changed".to_string();
+    } else if args.tutorial {
+        app.message = format!(
+            "Tutorial: generated conflicts in {}; follow the box in the corner",
+            app.root.display()
+        );
+    } else if running_as_root() {
+        app.message = if args.chown_to_owner {
+            "Running as root: applied files will be chowned to each folder's owner".to_string()
+        } else {
+            "Running as root: applied files may end up root-owned; consider --chown-to-owner"
+                .to_string()
+        };
     }
 
     let mut terminal = setup_terminal()?;
     let res = run_loop(&mut terminal, &mut app);
     restore_terminal(&mut terminal)?;
+    // Never exit halfway through moving a group: stop the worker and wait for it.
+    if app.apply_run.as_ref().is_some_and(ApplyRun::running) {
+        cancel_apply(&mut app);
+        poll_apply(&mut app, true);
+    }
+    // Syncthing still gets the rescans that were put off.
+    if let (Some(d), Some(client)) = (app.deferred_rescan.take(), &app.syncthing) {
+        rescan_folders(client, &d.folders);
+    }
+    // A normal quit leaves nothing to restore.
+    if res.is_ok() {
+        if let Some(path) = &app.session_path {
+            let _ = fs::remove_file(path);
+        }
+    }
+    if app.tutorial.is_some() {
+        let _ = fs::remove_dir_all(&app.root);
+    }
     res
 }
 
+/// What `--demo` generates.
+fn demo_spec() -> FixtureSpec {
+    FixtureSpec {
+        groups: 40,
+        max_conflicts: 4,
+        patterns: Pattern::ALL.to_vec(),
+        ..FixtureSpec::default()
+    }
+}
+
+fn syncthing_client(args: &Args, config: &Config) -> Option<SyncthingClient> {
+    let st = config.syncthing.as_ref();
+    let api_key = args
+        .syncthing_api_key
+        .clone()
+        .or_else(|| st.map(|s| s.api_key.clone()))?;
+    let url = args
+        .syncthing_url
+        .clone()
+        .or_else(|| st.map(|s| s.url.clone()))
+        .unwrap_or_else(|| "http://127.0.0.1:8384".to_string());
+    Some(SyncthingClient::new(&url, &api_key))
+}
+
 fn setup_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -198,29 +1161,179 @@ fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result
 
 fn run_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, app: &mut App) -> Result<()> {
     loop {
+        advance_tutorial(app);
+        if app.needs_clear {
+            app.needs_clear = false;
+            terminal.clear()?;
+        }
         if let Err(e) = terminal.draw(|f| ui(f, app)) {
             app.last_error = Some(format!("{e:#}"));
             app.mode = Mode::Error;
         }
+        if let Err(e) = show_graphics(terminal, app) {
+            app.message = format!("Image preview failed, using half blocks: {e:#}");
+            app.graphics = Graphics::Halfblocks;
+        }
 
         if app.mode == Mode::Done {
             return Ok(());
         }
 
-        if event::poll(Duration::from_millis(200))? {
-            if let Event::Key(k) = event::read()? {
-                if k.kind != KeyEventKind::Press {
-                    continue;
+        poll_hashing(app, false);
+        poll_provenance(app, false);
+        if let Some(rx) = &app.diffstat_rx {
+            app.diffstats.extend(rx.try_iter());
+        }
+        if let Some(rx) = &app.detail_blocks_rx {
+            app.detail_blocks.extend(rx.try_iter());
+        }
+        poll_diff_job(app);
+        poll_applied_checks(app, false);
+
+        if app.watcher.as_ref().is_some_and(|w| w.poll_conflicts()) {
+            app.watch_pending = true;
+            // The folder is still busy: put a pending rescan off a little longer.
+            if let Some(d) = &mut app.deferred_rescan {
+                d.timer.touch(Instant::now());
+            }
+        }
+        if let Err(e) = poll_deferred_rescan(app) {
+            app.message = format!("Rescan failed: {e:#}");
+        }
+        // Only reshuffle the list when no picks or plans refer to group indices.
+        if app.watch_pending && app.mode == Mode::List && app.deferred_rescan.is_none() {
+            app.watch_pending = false;
+            if let Err(e) = live_rescan(app) {
+                app.message = format!("Watch rescan failed: {e:#}");
+            }
+        }
+
+        let applying = app.apply_run.as_ref().is_some_and(ApplyRun::running);
+        if applying {
+            poll_apply(app, false);
+        }
+        poll_scan(app, false);
+
+        // Redraw often enough to show each group finishing while an apply is running,
+        // and a big diff's progress.
+        let timeout = if applying || app.diff_job.is_some() || app.scan_run.is_some() {
+            50
+        } else {
+            200
+        };
+        if app.session_at.elapsed() >= AUTOSAVE_EVERY {
+            autosave(app);
+        }
+        if event::poll(Duration::from_millis(timeout))? {
+            match event::read()? {
+                Event::Key(k) if k.kind == KeyEventKind::Press => {
+                    if handle_key(app, k.code, k.modifiers)? {
+                        return Ok(());
+                    }
+                    autosave(app);
                 }
-                if handle_key(app, k.code, k.modifiers)? {
-                    return Ok(());
+                Event::Mouse(m) => {
+                    handle_mouse(app, m)?;
+                    autosave(app);
                 }
+                _ => {}
             }
         }
     }
 }
 
+/// The `[keys]` scope of a mode; text prompts keep their keys.
+fn key_scope(mode: Mode) -> Option<Scope> {
+    Some(match mode {
+        Mode::List => Scope::List,
+        Mode::Pick => Scope::Pick,
+        Mode::Confirm => Scope::Confirm,
+        Mode::Apply => Scope::Apply,
+        Mode::Prune => Scope::Prune,
+        Mode::Cleanup => Scope::Cleanup,
+        Mode::Columns => Scope::Columns,
+        Mode::Stats => Scope::Stats,
+        Mode::Origins => Scope::Origins,
+        Mode::Detail => Scope::Detail,
+        Mode::Timeline => Scope::Timeline,
+        Mode::Preview => Scope::Preview,
+        Mode::Merge => Scope::Merge,
+        Mode::Log => Scope::Log,
+        Mode::Help => Scope::Help,
+        Mode::Error => Scope::Error,
+        Mode::RefPath
+        | Mode::PruneAge
+        | Mode::SnoozeFor
+        | Mode::PlanOut
+        | Mode::Filter
+        | Mode::RuleEdit
+        | Mode::LogSearch
+        | Mode::Done => return None,
+    })
+}
+
 fn handle_key(app: &mut App, code: KeyCode, mods: KeyModifiers) -> Result<bool> {
+    // Remapped keys become the default key of their action; the match below only knows those.
+    let (code, mods) = match key_scope(app.mode) {
+        Some(scope) => match app.keymap.translate(scope, code, mods) {
+            Some(k) => (k.code, k.mods),
+            None => return Ok(false),
+        },
+        None => (code, mods),
+    };
+    dispatch_key(app, code, mods)
+}
+
+/// Handles a key as bound by default; mouse clicks press these directly.
+fn dispatch_key(app: &mut App, code: KeyCode, mods: KeyModifiers) -> Result<bool> {
+    if let Some(nav) = Nav::from_key(code, mods) {
+        if app.mode == Mode::List {
+            let shift =
+                mods.contains(KeyModifiers::SHIFT) && matches!(code, KeyCode::Up | KeyCode::Down);
+            shift_range(app, shift);
+        }
+        let page = app.page_rows;
+        if let Some((state, len)) = nav_list(app) {
+            nav.apply(state, len, page);
+            if app.mode == Mode::List && app.tree_view {
+                sync_from_tree(app);
+            }
+            if app.mode == Mode::Pick {
+                sync_from_pick_view(app);
+            }
+            if app.mode == Mode::List {
+                extend_visual(app);
+            }
+            return Ok(false);
+        }
+        if app.mode == Mode::Preview {
+            app.preview_scroll = nav.scroll(app.preview_scroll, app.preview.len(), page);
+            return Ok(false);
+        }
+        if app.mode == Mode::Confirm {
+            let len = plan_rows(&app.planned_ops, &app.plan_folded).len();
+            nav.apply(&mut app.plan_state, len, app.log_rows);
+            sync_log_to_plan(app);
+            return Ok(false);
+        }
+        if app.mode == Mode::Help {
+            let len = help_lines(app, Theme::default()).len();
+            app.help_scroll = nav.scroll(app.help_scroll, len, page);
+            return Ok(false);
+        }
+        if app.mode == Mode::Log {
+            let len = app.planned_ops.len();
+            app.log_scroll = nav.scroll(app.log_scroll, len, app.log_rows);
+            return Ok(false);
+        }
+    }
+    if app.mode == Mode::List && app.tree_view && handle_tree_key(app, code) {
+        return Ok(false);
+    }
+    if app.mode == Mode::Confirm && handle_plan_fold_key(app, code) {
+        return Ok(false);
+    }
+
     match (app.mode, code, mods) {
         (Mode::Error, KeyCode::Esc, _)
         | (Mode::Error, KeyCode::Enter, _)
@@ -231,6 +1344,83 @@ fn handle_key(app: &mut App, code: KeyCode, mods: KeyModifiers) -> Result<bool>
 
         (_, KeyCode::Char('c'), KeyModifiers::CONTROL) => return Ok(true),
 
+        (Mode::Help, KeyCode::Char('?'), _)
+        | (Mode::Help, KeyCode::Esc, _)
+        | (Mode::Help, KeyCode::Char('q'), _) => app.mode = app.help_from,
+        (mode, KeyCode::Char('?'), _) if key_scope(mode).is_some() => {
+            app.help_from = mode;
+            app.help_scroll = 0;
+            app.mode = Mode::Help;
+        }
+
+        (Mode::RefPath, KeyCode::Esc, _) => app.mode = Mode::Pick,
+        (Mode::RefPath, KeyCode::Enter, _) => launch_reference_compare(app)?,
+        (Mode::RefPath, KeyCode::Backspace, _) => {
+            app.input.pop();
+        }
+        (Mode::RefPath, KeyCode::Char(ch), _) => app.input.push(ch),
+
+        (Mode::List, KeyCode::Char('X'), _) => {
+            app.input.clear();
+            app.mode = Mode::PruneAge;
+        }
+        (Mode::PruneAge, KeyCode::Esc, _) => app.mode = Mode::List,
+        (Mode::PruneAge, KeyCode::Enter, _) => preview_prune(app)?,
+        (Mode::PruneAge, KeyCode::Backspace, _) => {
+            app.input.pop();
+        }
+        (Mode::PruneAge, KeyCode::Char(ch), _) => app.input.push(ch),
+        (Mode::List, KeyCode::Char('s'), _) => {
+            app.input.clear();
+            app.mode = Mode::SnoozeFor;
+        }
+        (Mode::SnoozeFor, KeyCode::Esc, _) => app.mode = Mode::List,
+        (Mode::SnoozeFor, KeyCode::Enter, _) => snooze_groups(app),
+        (Mode::SnoozeFor, KeyCode::Backspace, _) => {
+            app.input.pop();
+        }
+        (Mode::SnoozeFor, KeyCode::Char(ch), _) => app.input.push(ch),
+        (Mode::Prune, KeyCode::Char('y'), _) => run_prune(app),
+        (Mode::List, KeyCode::Char('W'), _) => preview_cleanup(app)?,
+        (Mode::Cleanup, KeyCode::Char('y'), _) => run_cleanup(app),
+        (Mode::Cleanup, KeyCode::Char('n'), _) | (Mode::Cleanup, KeyCode::Esc, _) => {
+            app.leftovers.clear();
+            app.mode = Mode::List;
+            app.message = "Cleanup cancelled".to_string();
+        }
+
+        (Mode::Confirm, KeyCode::Char('i'), _) if app.apply => add_archive_ignore(app),
+        (Mode::Confirm, KeyCode::Char('w'), _) => {
+            app.input = app
+                .plan_out
+                .as_ref()
+                .map_or("synctui-plan.txt".to_string(), |p| p.display().to_string());
+            app.mode = Mode::PlanOut;
+        }
+        (Mode::PlanOut, KeyCode::Esc, _) => app.mode = Mode::Confirm,
+        (Mode::PlanOut, KeyCode::Enter, _) => {
+            app.mode = Mode::Confirm;
+            let cwd = std::env::current_dir().unwrap_or_default();
+            let path = expand_user_path(&app.input, &cwd);
+            export_plan(app, &path);
+        }
+        (Mode::PlanOut, KeyCode::Backspace, _) => {
+            app.input.pop();
+        }
+        (Mode::PlanOut, KeyCode::Char(ch), _) => app.input.push(ch),
+        (Mode::Prune, KeyCode::Char('n'), _) | (Mode::Prune, KeyCode::Esc, _) => {
+            app.prune_preview.clear();
+            app.mode = Mode::List;
+            app.message = "Prune cancelled".to_string();
+        }
+
+        (Mode::List, KeyCode::Char('t'), _)
+        | (Mode::Pick, KeyCode::Char('t'), _)
+        | (Mode::Confirm, KeyCode::Char('t'), _)
+            if app.demo =>
+        {
+            app.message = "Demo mode: apply is disabled".to_string();
+        }
         (Mode::List, KeyCode::Char('t'), _)
         | (Mode::Pick, KeyCode::Char('t'), _)
         | (Mode::Confirm, KeyCode::Char('t'), _) => {
@@ -249,52 +1439,145 @@ fn handle_key(app: &mut App, code: KeyCode, mods: KeyModifiers) -> Result<bool>
         }
         (Mode::Confirm, KeyCode::Esc, _) => {
             app.mode = Mode::List;
+            app.dir_batch = None;
             app.planned_ops.clear();
             app.planned_targets.clear();
             app.message.clear();
         }
-        (Mode::List, KeyCode::Down, _) => list_down(&mut app.list_state, app.groups.len()),
-        (Mode::List, KeyCode::Up, _) => list_up(&mut app.list_state, app.groups.len()),
-        (Mode::Pick, KeyCode::Down, _) => {
-            let len = current_group_len(app);
-            list_down(&mut app.pick_state, len)
+        (Mode::List, KeyCode::Char(' '), _) => toggle_selected(app),
+        (Mode::List, KeyCode::Char('*'), _) => select_all_visible(app),
+        (Mode::List, KeyCode::Char('I'), _) => invert_selection(app),
+        (Mode::List, KeyCode::Char('V'), _) => toggle_visual(app),
+        (Mode::List, KeyCode::Esc, _) if app.scan_run.is_some() => cancel_scan(app),
+        (Mode::List, KeyCode::Esc, _) if app.visual.is_some() => toggle_visual(app),
+        (Mode::List, KeyCode::Esc, _) if app.identity_checks.is_some() => {
+            app.identity_checks = None;
+            app.message = "Stopped checking which groups have identical versions".to_string();
         }
-        (Mode::Pick, KeyCode::Up, _) => {
-            let len = current_group_len(app);
-            list_up(&mut app.pick_state, len)
+        (Mode::List, KeyCode::Char('T'), _) => toggle_tree_view(app),
+        (Mode::List, KeyCode::Char('y'), _) => toggle_sort_by_type(app),
+        (Mode::List, KeyCode::Char('m'), _) => {
+            app.columns_state.select(Some(0));
+            app.mode = Mode::Columns;
         }
-        (Mode::List, KeyCode::Char(' '), _) => toggle_selected(app),
+        (Mode::Columns, KeyCode::Char(' '), _) | (Mode::Columns, KeyCode::Enter, _) => {
+            if let Some(c) = app.columns_state.selected().map(|i| Column::ALL[i]) {
+                if !app.columns.remove(&c) {
+                    app.columns.insert(c);
+                }
+            }
+        }
+        (Mode::Columns, KeyCode::Esc, _)
+        | (Mode::Columns, KeyCode::Char('m'), _)
+        | (Mode::Columns, KeyCode::Char('q'), _) => app.mode = Mode::List,
+        (Mode::List, KeyCode::Char('S'), _) => app.mode = Mode::Stats,
+        (Mode::List, KeyCode::F(2), _) => app.mode = Mode::Origins,
+        (Mode::List, KeyCode::Char('F'), _) => open_filter(app),
+        (Mode::Filter, KeyCode::Esc, _) => app.mode = Mode::List,
+        (Mode::Filter, KeyCode::Enter, _) => apply_filter(app)?,
+        (Mode::Filter, KeyCode::Down, _) | (Mode::Filter, KeyCode::Tab, _) => {
+            app.filter_field = (app.filter_field + 1) % app.filter_inputs.len();
+        }
+        (Mode::Filter, KeyCode::Up, _) | (Mode::Filter, KeyCode::BackTab, _) => {
+            app.filter_field =
+                (app.filter_field + app.filter_inputs.len() - 1) % app.filter_inputs.len();
+        }
+        (Mode::Filter, KeyCode::Backspace, _) => {
+            app.filter_inputs[app.filter_field].pop();
+        }
+        (Mode::Filter, KeyCode::Char(ch), _) => app.filter_inputs[app.filter_field].push(ch),
+        (Mode::List, KeyCode::Char('w'), _) => open_rule_editor(app),
+        (Mode::RuleEdit, KeyCode::Esc, _) => app.mode = Mode::List,
+        (Mode::RuleEdit, KeyCode::Enter, _) => save_rule(app),
+        (Mode::RuleEdit, KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down, _) => {
+            app.rule_field = 1 - app.rule_field;
+        }
+        (Mode::RuleEdit, KeyCode::Left, _) if app.rule_field == 1 => cycle_rule_strategy(app, -1),
+        (Mode::RuleEdit, KeyCode::Right | KeyCode::Char(' '), _) if app.rule_field == 1 => {
+            cycle_rule_strategy(app, 1)
+        }
+        (Mode::RuleEdit, KeyCode::Backspace, _) if app.rule_field == 0 => {
+            app.input.pop();
+        }
+        (Mode::RuleEdit, KeyCode::Char(ch), _) if app.rule_field == 0 => app.input.push(ch),
+        (Mode::Stats, KeyCode::Esc, _)
+        | (Mode::Stats, KeyCode::Char('S'), _)
+        | (Mode::Stats, KeyCode::Char('q'), _) => app.mode = Mode::List,
+        (Mode::Origins, KeyCode::Esc, _)
+        | (Mode::Origins, KeyCode::F(2), _)
+        | (Mode::Origins, KeyCode::Char('q'), _) => app.mode = Mode::List,
 
         // Quick-pick from list view
         (Mode::List, KeyCode::Char('c'), _) | (Mode::List, KeyCode::Char('o'), _) => {
-            pick_kind_for_targets(app, PickKind::Current, false)?;
+            pick_kind_for_targets(app, Strategy::Current, false)?;
         }
         (Mode::List, KeyCode::Char('n'), _) => {
-            pick_kind_for_targets(app, PickKind::Newest, false)?;
+            pick_kind_for_targets(app, Strategy::Newest, false)?;
         }
         (Mode::List, KeyCode::Char('p'), _) => {
-            pick_kind_for_targets(app, PickKind::Oldest, false)?;
+            pick_kind_for_targets(app, Strategy::Oldest, false)?;
         }
         (Mode::List, KeyCode::Char('C'), _) | (Mode::List, KeyCode::Char('O'), _) => {
-            pick_kind_for_targets(app, PickKind::Current, true)?;
+            pick_kind_for_targets(app, Strategy::Current, true)?;
+        }
+        (Mode::List, KeyCode::Char('r'), _) => {
+            pick_kind_for_targets(app, Strategy::Trusted, false)?;
+        }
+        (Mode::List, KeyCode::Char('R'), _) => {
+            pick_kind_for_targets(app, Strategy::Trusted, true)?;
+        }
+        (Mode::List, KeyCode::Char('b'), KeyModifiers::CONTROL) => {
+            pick_kind_for_targets(app, Strategy::Largest, true)?;
+        }
+        (Mode::List, KeyCode::Char('b'), _) => {
+            pick_kind_for_targets(app, Strategy::Largest, false)?;
         }
         (Mode::List, KeyCode::Char('N'), _) => {
-            pick_kind_for_targets(app, PickKind::Newest, true)?;
+            pick_kind_for_targets(app, Strategy::Newest, true)?;
         }
         (Mode::List, KeyCode::Char('P'), _) => {
-            pick_kind_for_targets(app, PickKind::Oldest, true)?;
+            pick_kind_for_targets(app, Strategy::Oldest, true)?;
+        }
+
+        (Mode::List, KeyCode::Char('u'), _) => {
+            pick_deleted_original(app, DeletedOriginal::Promote, false)
+        }
+        (Mode::List, KeyCode::Char('U'), _) => {
+            pick_deleted_original(app, DeletedOriginal::Promote, true)
+        }
+        (Mode::List, KeyCode::Char('z'), KeyModifiers::CONTROL) => undo_last_batch(app)?,
+        (Mode::List, KeyCode::Char('z'), _) => {
+            pick_deleted_original(app, DeletedOriginal::Discard, false)
+        }
+        (Mode::List, KeyCode::Char('Z'), _) => {
+            pick_deleted_original(app, DeletedOriginal::Discard, true)
         }
+        (Mode::List, KeyCode::Char('l'), _) => pick_like_last_time(app, false),
+        (Mode::List, KeyCode::Char('L'), _) => pick_like_last_time(app, true),
 
         (Mode::List, KeyCode::Enter, _) => enter_pick(app)?,
         (Mode::Pick, KeyCode::Enter, _) => pick_current(app)?,
         (Mode::Pick, KeyCode::Char('o'), _) => pick_original(app)?,
         (Mode::Pick, KeyCode::Char('n'), _) => pick_newest(app)?,
         (Mode::Pick, KeyCode::Char('p'), _) => pick_oldest(app)?,
+        (Mode::Pick, KeyCode::Char('r'), _) => pick_trusted(app)?,
+        (Mode::Pick, KeyCode::Char('b'), _) => pick_largest(app)?,
         (Mode::List, KeyCode::Char('A'), _) => plan_and_confirm(app, true)?,
         (Mode::List, KeyCode::Char('a'), _) => plan_and_confirm(app, false)?,
         (Mode::Confirm, KeyCode::Char('y'), _) => apply_plan(app)?,
+        (Mode::Confirm, KeyCode::Char('s'), _) if app.dir_batch.is_some() => next_dir(app)?,
+        (Mode::List, KeyCode::Char('B'), _) => start_dir_batch(app)?,
+        (Mode::Apply, KeyCode::Char('r'), _) => retry_failed(app, true),
+        (Mode::Apply, KeyCode::Char('R'), _) => retry_failed(app, false),
+        (Mode::Apply, KeyCode::Esc, _) | (Mode::Apply, KeyCode::Char('c'), _)
+            if app.apply_run.as_ref().is_some_and(ApplyRun::running) =>
+        {
+            cancel_apply(app);
+        }
+        (Mode::Apply, KeyCode::Enter, _) | (Mode::Apply, KeyCode::Esc, _) => close_apply(app)?,
         (Mode::Confirm, KeyCode::Char('n'), _) => {
             app.mode = Mode::List;
+            app.dir_batch = None;
             app.planned_ops.clear();
             app.planned_targets.clear();
             app.message = "Cancelled".to_string();
@@ -303,20 +1586,82 @@ fn handle_key(app: &mut App, code: KeyCode, mods: KeyModifiers) -> Result<bool>
         (Mode::List, KeyCode::Char('d'), _) | (Mode::Pick, KeyCode::Char('d'), _) => {
             launch_compare(app)?;
         }
+        (Mode::Pick, KeyCode::Char('D'), _) => start_reference_input(app),
+        (Mode::Pick, KeyCode::Char('e'), _) => open_in_viewer(app)?,
+
+        (Mode::List, KeyCode::Char('H'), _) | (Mode::Pick, KeyCode::Char('H'), _) => {
+            enter_timeline(app)
+        }
+        (Mode::List, KeyCode::Char('i'), _) | (Mode::Pick, KeyCode::Char('i'), _) => {
+            enter_detail(app)
+        }
+        (Mode::Pick, KeyCode::Char('v'), _) => open_preview(app),
+        (Mode::Pick, KeyCode::Char('m'), _) => open_merge(app),
+        (Mode::Merge, KeyCode::Char('h'), _) => take_hunk(app, Take::Left),
+        (Mode::Merge, KeyCode::Char('l'), _) => take_hunk(app, Take::Right),
+        (Mode::Merge, KeyCode::Char('b'), _) => take_hunk(app, Take::Both),
+        (Mode::Merge, KeyCode::Enter, _) => write_merge(app)?,
+        (Mode::Merge, KeyCode::Esc, _) | (Mode::Merge, KeyCode::Char('q'), _) => {
+            app.merge = None;
+            app.mode = Mode::Pick;
+        }
+        (Mode::Confirm, KeyCode::Tab, _) | (Mode::Apply, KeyCode::Tab, _) => {
+            app.log_from = app.mode;
+            app.mode = Mode::Log;
+        }
+        (Mode::Log, KeyCode::Esc, _)
+        | (Mode::Log, KeyCode::Tab, _)
+        | (Mode::Log, KeyCode::Char('q'), _) => app.mode = app.log_from,
+        (Mode::Log, KeyCode::Char('/'), _) => {
+            app.input = app.log_search.clone();
+            app.mode = Mode::LogSearch;
+        }
+        (Mode::Log, KeyCode::Char('n'), _) => find_in_log(app, true, false),
+        (Mode::Log, KeyCode::Char('N'), _) => find_in_log(app, false, false),
+        (Mode::LogSearch, KeyCode::Esc, _) => app.mode = Mode::Log,
+        (Mode::LogSearch, KeyCode::Enter, _) => {
+            app.log_search = app.input.trim().to_string();
+            app.mode = Mode::Log;
+            find_in_log(app, true, true);
+        }
+        (Mode::LogSearch, KeyCode::Backspace, _) => {
+            app.input.pop();
+        }
+        (Mode::LogSearch, KeyCode::Char(ch), _) => app.input.push(ch),
+
+        (Mode::Preview, KeyCode::Char('a'), _) => {
+            change_diff_options(app, |o| o.algorithm = o.algorithm.toggled())
+        }
+        (Mode::Preview, KeyCode::Char('+'), _) => change_diff_options(app, |o| o.context += 1),
+        (Mode::Preview, KeyCode::Char('-'), _) => {
+            change_diff_options(app, |o| o.context = o.context.saturating_sub(1))
+        }
+        (Mode::Preview, KeyCode::Char('w'), _) => {
+            change_diff_options(app, |o| o.ignore_whitespace = !o.ignore_whitespace)
+        }
+        (Mode::Preview, KeyCode::Esc, _)
+        | (Mode::Preview, KeyCode::Char('v'), _)
+        | (Mode::Preview, KeyCode::Char('q'), _) => app.mode = app.preview_from,
+        (Mode::Detail, KeyCode::Esc, _)
+        | (Mode::Detail, KeyCode::Char('i'), _)
+        | (Mode::Detail, KeyCode::Char('q'), _) => {
+            // Stops the hashing and block comparisons.
+            app.detail_hashing = None;
+            app.detail_blocks_rx = None;
+            app.mode = app.detail_from;
+        }
+        (Mode::Detail, KeyCode::Down, _) => app.detail_scroll = app.detail_scroll.saturating_add(1),
+        (Mode::Detail, KeyCode::Up, _) => app.detail_scroll = app.detail_scroll.saturating_sub(1),
+        (Mode::Timeline, KeyCode::Esc, _)
+        | (Mode::Timeline, KeyCode::Char('H'), _)
+        | (Mode::Timeline, KeyCode::Char('q'), _) => app.mode = app.timeline_from,
 
         _ => {}
     }
     Ok(false)
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum PickKind {
-    Current,
-    Newest,
-    Oldest,
-}
-
-fn pick_kind_for_targets(app: &mut App, kind: PickKind, selected_only: bool) -> Result<()> {
+fn pick_kind_for_targets(app: &mut App, kind: Strategy, selected_only: bool) -> Result<()> {
     let mut targets: Vec<usize> = if selected_only {
         app.selected_groups.iter().copied().collect()
     } else {
@@ -328,824 +1673,8287 @@ fn pick_kind_for_targets(app: &mut App, kind: PickKind, selected_only: bool) ->
         return Ok(());
     }
 
+    let mut skewed = 0;
     for gi in targets {
-        let idx = match kind {
-            PickKind::Current => Some(0),
-            PickKind::Newest => app.groups[gi].newest_idx().or(Some(0)),
-            PickKind::Oldest => app.groups[gi].oldest_idx().or(Some(0)),
-        };
-        app.groups[gi].chosen = idx;
+        if kind.mtime_only() && skew_blocks_mtime(app, gi) {
+            skewed += 1;
+            continue;
+        }
+        app.groups[gi].chosen = kind.choose(&app.groups[gi]);
 
         // Selecting a choice from the main list implies selecting the group.
         // This matches the Space behavior so the user can batch-confirm quickly.
         app.selected_groups.insert(gi);
     }
 
-    app.message = match (kind, selected_only) {
-        (PickKind::Current, false) => "Picked current".to_string(),
-        (PickKind::Newest, false) => "Picked newest".to_string(),
-        (PickKind::Oldest, false) => "Picked oldest".to_string(),
-        (PickKind::Current, true) => "Picked current for selected".to_string(),
-        (PickKind::Newest, true) => "Picked newest for selected".to_string(),
-        (PickKind::Oldest, true) => "Picked oldest for selected".to_string(),
+    app.message = if selected_only {
+        format!("Picked {} for selected", kind.label())
+    } else {
+        format!("Picked {}", kind.label())
     };
+    if skewed > 0 {
+        app.message = format!(
+            "{}; skipped {skewed} with clock skew (pick them with Enter, or start with --trust-mtime)",
+            app.message
+        );
+    }
     Ok(())
 }
 
-fn list_down(state: &mut ListState, len: usize) {
-    if len == 0 {
-        state.select(None);
-        return;
-    }
-    let next = match state.selected() {
-        None => 0,
-        Some(i) => (i + 1).min(len - 1),
+/// Group `gi`'s conflict names and mtimes disagree, so newest/oldest shouldn't decide it.
+fn skew_blocks_mtime(app: &App, gi: usize) -> bool {
+    !app.trust_mtime && app.groups[gi].clock_skew(app.skew_threshold).is_some()
+}
+
+/// Opens the filter popup with the current limits filled in.
+fn open_filter(app: &mut App) {
+    let f = app.filter;
+    app.filter_inputs = [
+        f.older_than.map(format_age).unwrap_or_default(),
+        f.newer_than.map(format_age).unwrap_or_default(),
+        f.min_size.map(|s| s.to_string()).unwrap_or_default(),
+        f.kind.map(|k| k.label().to_string()).unwrap_or_default(),
+    ];
+    app.filter_field = 0;
+    app.mode = Mode::Filter;
+}
+
+/// Parses the filter popup and rescans with it; on a typo the popup stays open.
+fn apply_filter(app: &mut App) -> Result<()> {
+    fn field(s: &str) -> Option<&str> {
+        Some(s.trim()).filter(|s| !s.is_empty())
+    }
+    let parsed = (|| -> Result<ConflictFilter> {
+        Ok(ConflictFilter {
+            older_than: field(&app.filter_inputs[0])
+                .map(parse_duration)
+                .transpose()?,
+            newer_than: field(&app.filter_inputs[1])
+                .map(parse_duration)
+                .transpose()?,
+            min_size: field(&app.filter_inputs[2]).map(parse_size).transpose()?,
+            kind: field(&app.filter_inputs[3]).map(str::parse).transpose()?,
+        })
+    })();
+    let filter = match parsed {
+        Ok(f) => f,
+        Err(e) => {
+            app.message = format!("Filter: {e:#}");
+            return Ok(());
+        }
     };
-    state.select(Some(next));
+    app.filter = filter;
+    app.mode = Mode::List;
+    start_scan(app, true)?;
+    Ok(())
 }
 
-fn list_up(state: &mut ListState, len: usize) {
-    if len == 0 {
-        state.select(None);
+/// Refuses an mtime-only pick on a group with clock skew, saying why; true if refused.
+fn refuse_skewed_pick(app: &mut App, gi: usize, kind: Strategy) -> bool {
+    let Some(skew) = app.groups[gi]
+        .clock_skew(app.skew_threshold)
+        .filter(|_| !app.trust_mtime)
+    else {
+        return false;
+    };
+    app.message = format!(
+        "Clock skew of {}: {} is unreliable here; choose a version with Enter",
+        format_age(skew),
+        kind.label()
+    );
+    true
+}
+
+/// Picks the highlighted (or selected) groups the way each path was resolved last time.
+fn pick_like_last_time(app: &mut App, selected_only: bool) {
+    let Some(history) = &app.history else {
+        app.message = "History is disabled; nothing to remember".to_string();
+        return;
+    };
+    let targets: Vec<usize> = if selected_only {
+        app.selected_groups.iter().copied().collect()
+    } else {
+        app.list_state.selected().into_iter().collect()
+    };
+    if targets.is_empty() {
+        app.message = "No groups selected".to_string();
         return;
     }
-    let next = match state.selected() {
-        None => 0,
-        Some(i) => i.saturating_sub(1),
+
+    let (mut picked, mut unknown) = (0, 0);
+    let mut last_desc = String::new();
+    for gi in targets {
+        let g = &mut app.groups[gi];
+        match history.last_for(&g.base_path) {
+            Ok(Some(r)) => match r.choose(g) {
+                Some(ci) => {
+                    g.chosen = Some(ci);
+                    app.selected_groups.insert(gi);
+                    picked += 1;
+                    let device = app.device_aliases.name(&r.device);
+                    last_desc = match r.strategy {
+                        Some(s) => format!("{device} ({})", s.label()),
+                        None => device,
+                    };
+                }
+                None => unknown += 1,
+            },
+            Ok(None) => unknown += 1,
+            Err(e) => {
+                app.message = format!("History lookup failed: {e:#}");
+                return;
+            }
+        }
+    }
+    app.message = match (picked, unknown) {
+        (0, _) => "Not resolved before".to_string(),
+        (1, 0) => format!("Picked like last time: {last_desc}"),
+        (p, 0) => format!("Picked {p} groups like last time"),
+        (p, u) => format!("Picked {p} groups like last time; {u} not resolved before"),
     };
-    state.select(Some(next));
 }
 
-fn toggle_selected(app: &mut App) {
-    let Some(i) = app.list_state.selected() else {
+/// With `--like-last-time`, pre-picks unpicked groups that have a remembered resolution.
+fn auto_pick_like_last_time(app: &mut App) {
+    let Some(history) = app.history.as_ref().filter(|_| app.like_last_time) else {
         return;
     };
-    if app.selected_groups.contains(&i) {
-        app.selected_groups.remove(&i);
-    } else {
-        app.selected_groups.insert(i);
+    for g in app.groups.iter_mut().filter(|g| g.chosen.is_none()) {
+        if let Ok(Some(r)) = history.last_for(&g.base_path) {
+            g.chosen = r.choose(g);
+        }
     }
 }
 
-fn enter_pick(app: &mut App) -> Result<()> {
-    let gi = app
-        .list_state
-        .selected()
-        .ok_or_else(|| anyhow!("no selection"))?;
-    let g = app.groups.get(gi).ok_or_else(|| anyhow!("bad index"))?;
-    app.mode = Mode::Pick;
-    app.pick_state = ListState::default();
-    // Default to newest if it exists, else original.
-    let default_idx = g.newest_idx().unwrap_or(0);
-    app.pick_state.select(Some(default_idx));
-    Ok(())
+/// Pre-picks unpicked groups matching a `[[pick_rules]]` glob, or else through their
+/// Syncthing folder's `[folder_presets]` entry, leaving groups with clock skew alone when
+/// the strategy goes by mtime.
+fn auto_pick_rules(app: &mut App) {
+    for gi in 0..app.groups.len() {
+        let g = &app.groups[gi];
+        if g.chosen.is_some() {
+            continue;
+        }
+        let rule = pick_rule(&app.pick_rules, rel_path(&app.root, &g.base_path));
+        let Some((s, by)) = rule
+            .map(|r| (app.pick_rules[r].strategy, app.pick_rules[r].by))
+            .or_else(|| {
+                folder_preset(&app.folder_presets, &app.folders, &g.base_path)
+                    .map(|s| (s, Recency::Mtime))
+            })
+        else {
+            continue;
+        };
+        if s.mtime_only() && by == Recency::Mtime && skew_blocks_mtime(app, gi) {
+            continue;
+        }
+        app.groups[gi].chosen = s.choose_by(&app.groups[gi], by);
+    }
 }
 
-fn pick_current(app: &mut App) -> Result<()> {
-    let gi = app
-        .list_state
-        .selected()
-        .ok_or_else(|| anyhow!("no selection"))?;
-    let ci = app
-        .pick_state
-        .selected()
-        .ok_or_else(|| anyhow!("no candidate"))?;
-    app.groups[gi].chosen = Some(ci);
-    app.mode = Mode::List;
-    app.message = "Picked".to_string();
-    Ok(())
+/// Strategies the rule popup cycles through.
+const RULE_STRATEGIES: [Strategy; 5] = [
+    Strategy::Newest,
+    Strategy::Oldest,
+    Strategy::Current,
+    Strategy::Trusted,
+    Strategy::Largest,
+];
+
+/// Opens the rule popup for the highlighted group: its extension in its folder, with the
+/// strategy that explains its current pick (newest when unpicked).
+fn open_rule_editor(app: &mut App) {
+    if app.demo {
+        app.message = "Demo mode: rules aren't saved".to_string();
+        return;
+    }
+    let Some(g) = app.list_state.selected().and_then(|gi| app.groups.get(gi)) else {
+        app.message = "No group highlighted".to_string();
+        return;
+    };
+    let rel = rel_path(&app.root, &g.base_path);
+    let name = rel
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let pattern = match rel.extension() {
+        Some(ext) => format!("*.{}", ext.to_string_lossy()),
+        None => name,
+    };
+    let dir = rel
+        .parent()
+        .map(|d| d.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_default();
+    // A leading `/` keeps a rule for the root from matching every folder.
+    app.input = format!("{dir}/{pattern}");
+    app.rule_strategy = RULE_STRATEGIES
+        .into_iter()
+        .find(|s| g.chosen.is_some() && s.choose(g) == g.chosen)
+        .unwrap_or(Strategy::Newest);
+    app.rule_field = 0;
+    app.mode = Mode::RuleEdit;
 }
 
-fn pick_original(app: &mut App) -> Result<()> {
-    let gi = app
-        .list_state
-        .selected()
-        .ok_or_else(|| anyhow!("no selection"))?;
-    // Original is always candidates[0]
-    app.groups[gi].chosen = Some(0);
-    app.mode = Mode::List;
-    app.message = "Picked original".to_string();
-    Ok(())
+fn cycle_rule_strategy(app: &mut App, step: isize) {
+    let n = RULE_STRATEGIES.len() as isize;
+    let i = RULE_STRATEGIES
+        .iter()
+        .position(|&s| s == app.rule_strategy)
+        .unwrap_or(0) as isize;
+    app.rule_strategy = RULE_STRATEGIES[(i + step).rem_euclid(n) as usize];
 }
 
-fn pick_newest(app: &mut App) -> Result<()> {
-    let gi = app
-        .list_state
-        .selected()
-        .ok_or_else(|| anyhow!("no selection"))?;
-    let idx = app.groups[gi]
-        .newest_idx()
-        .ok_or_else(|| anyhow!("no mtime"))?;
-    app.groups[gi].chosen = Some(idx);
-    app.mode = Mode::List;
-    app.message = "Picked newest".to_string();
-    Ok(())
+/// The popup's rule, if its glob isn't empty.
+fn rule_from_popup(app: &App) -> Option<PickRule> {
+    let glob = app.input.trim();
+    (!glob.is_empty()).then(|| PickRule {
+        glob: glob.to_string(),
+        strategy: app.rule_strategy,
+        by: Recency::Mtime,
+    })
 }
 
-fn pick_oldest(app: &mut App) -> Result<()> {
-    let gi = app
-        .list_state
-        .selected()
-        .ok_or_else(|| anyhow!("no selection"))?;
-    let idx = app.groups[gi]
-        .oldest_idx()
-        .ok_or_else(|| anyhow!("no mtime"))?;
-    app.groups[gi].chosen = Some(idx);
+/// Appends the popup's rule to the config file and pre-picks the groups it matches; on a
+/// problem the popup stays open.
+fn save_rule(app: &mut App) {
+    let Some(rule) = rule_from_popup(app) else {
+        app.message = "Rule: type a glob first".to_string();
+        return;
+    };
+    let Some(path) = app.config_path.clone() else {
+        app.message = "Rule: no config directory on this system; start with --config".to_string();
+        return;
+    };
+    if let Err(e) = append_pick_rule(&path, &rule) {
+        app.message = format!("Rule not saved: {e:#}");
+        return;
+    }
+    let before = app.groups.iter().filter(|g| g.chosen.is_some()).count();
+    app.message = format!(
+        "Saved to {}: {} for {}",
+        path.display(),
+        rule.strategy.label(),
+        rule.glob
+    );
+    app.pick_rules.push(rule);
+    auto_pick_rules(app);
+    let picked = app.groups.iter().filter(|g| g.chosen.is_some()).count() - before;
+    app.message.push_str(&format!(" ({picked} groups picked)"));
     app.mode = Mode::List;
-    app.message = "Picked oldest".to_string();
-    Ok(())
 }
 
-fn plan_and_confirm(app: &mut App, all_selected: bool) -> Result<()> {
-    let mut targets: Vec<usize> = if all_selected {
+/// With `--deleted-original`, pre-picks unpicked groups whose original was deleted.
+fn auto_pick_deleted_original(app: &mut App) {
+    let Some(policy) = app.deleted_original else {
+        return;
+    };
+    for g in app.groups.iter_mut().filter(|g| g.chosen.is_none()) {
+        g.chosen = policy.choose(g);
+    }
+}
+
+/// Applies `policy` to the highlighted (or selected) groups whose original was deleted.
+fn pick_deleted_original(app: &mut App, policy: DeletedOriginal, selected_only: bool) {
+    let targets: Vec<usize> = if selected_only {
         app.selected_groups.iter().copied().collect()
     } else {
         app.list_state.selected().into_iter().collect()
     };
-    targets.sort_unstable();
-
     if targets.is_empty() {
         app.message = "No groups selected".to_string();
-        return Ok(());
+        return;
     }
-
-    app.planned_ops.clear();
-    app.planned_targets.clear();
-    for &gi in &targets {
-        let g = &app.groups[gi];
-        let Some(ci) = g.chosen else {
-            app.message = "Pick a version first (Enter)".to_string();
-            app.planned_ops.clear();
-            app.planned_targets.clear();
-            return Ok(());
-        };
-        plan_group_ops(app, gi, ci)?;
+    let mut picked = 0;
+    for gi in targets {
+        if let Some(ci) = policy.choose(&app.groups[gi]) {
+            app.groups[gi].chosen = Some(ci);
+            app.selected_groups.insert(gi);
+            picked += 1;
+        }
     }
-    app.planned_targets = targets;
-    app.mode = Mode::Confirm;
-    Ok(())
+    app.message = match (picked, policy) {
+        (0, _) => "No group with a deleted original to resolve".to_string(),
+        (n, DeletedOriginal::Promote) => format!("Promoting the newest conflict in {n} group(s)"),
+        (n, DeletedOriginal::Discard) => {
+            format!("Accepting the deletion in {n} group(s); their conflicts get archived")
+        }
+    };
 }
 
-fn plan_group_ops(app: &mut App, gi: usize, chosen_idx: usize) -> Result<()> {
-    let g = &app.groups[gi];
-    let base = &g.base_path;
-    let chosen = &g.candidates[chosen_idx].path;
-    let archive_dir = archive_dir_for(base)?;
-
-    app.planned_ops
-        .push(format!("Group: {}", rel_path(&app.root, base).display()));
-    app.planned_ops.push(format!(
-        "  keep -> {}",
-        rel_path(&app.root, chosen).display()
-    ));
-    app.planned_ops.push(format!(
-        "  archive -> {}",
-        rel_path(&app.root, &archive_dir).display()
-    ));
-
-    Ok(())
+/// Cursor movement shared by every scrollable list (arrows, vim keys, paging).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Nav {
+    Down,
+    Up,
+    Top,
+    Bottom,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    PageUp,
 }
 
-fn apply_plan(app: &mut App) -> Result<()> {
-    let targets = app.planned_targets.clone();
-    if targets.is_empty() {
-        app.message = "Nothing planned".to_string();
-        app.mode = Mode::List;
-        app.planned_ops.clear();
-        return Ok(());
+impl Nav {
+    fn from_key(code: KeyCode, mods: KeyModifiers) -> Option<Nav> {
+        let ctrl = mods.contains(KeyModifiers::CONTROL);
+        Some(match code {
+            KeyCode::Char('d') if ctrl => Nav::HalfPageDown,
+            KeyCode::Char('u') if ctrl => Nav::HalfPageUp,
+            _ if ctrl => return None,
+            KeyCode::Down | KeyCode::Char('j') => Nav::Down,
+            KeyCode::Up | KeyCode::Char('k') => Nav::Up,
+            KeyCode::Home | KeyCode::Char('g') => Nav::Top,
+            KeyCode::End | KeyCode::Char('G') => Nav::Bottom,
+            KeyCode::PageDown => Nav::PageDown,
+            KeyCode::PageUp => Nav::PageUp,
+            _ => return None,
+        })
     }
 
-    let mut errors = Vec::new();
-    for gi in targets {
-        let chosen_idx = match app.groups[gi].chosen {
-            Some(v) => v,
-            None => continue,
+    /// Moves `state` within a list of `len` rows, `page` of which are visible.
+    fn apply(self, state: &mut ListState, len: usize, page: usize) {
+        let page = page.max(1);
+        let delta = match self {
+            Nav::Down => 1,
+            Nav::Up => -1,
+            Nav::Top => isize::MIN,
+            Nav::Bottom => isize::MAX,
+            Nav::HalfPageDown => (page / 2).max(1) as isize,
+            Nav::HalfPageUp => -((page / 2).max(1) as isize),
+            Nav::PageDown => page as isize,
+            Nav::PageUp => -(page as isize),
         };
-        if let Err(e) = apply_group(app, gi, chosen_idx) {
-            errors.push(format!(
-                "{}: {e:#}",
-                rel_path(&app.root, &app.groups[gi].base_path).display()
-            ));
-        }
+        move_selection(state, len, delta);
     }
 
-    if errors.is_empty() {
-        if app.apply {
-            app.planned_ops.clear();
-            app.planned_targets.clear();
-            rescan(app)?;
-            app.mode = Mode::List;
-            app.message = "Applied".to_string();
-            return Ok(());
-        }
-
-        // Dry-run: keep the confirmation open so the user can toggle apply and run again.
-        app.mode = Mode::Confirm;
-        app.message =
-            "Dry-run complete. Toggle apply with 't', then press 'y' to apply.".to_string();
-        return Ok(());
+    /// Moves a scroll offset the same way, keeping the last `page` rows in view at most.
+    fn scroll(self, offset: usize, len: usize, page: usize) -> usize {
+        let mut state = ListState::default().with_selected(Some(offset));
+        self.apply(&mut state, len.saturating_sub(page.max(1)) + 1, page);
+        state.selected().unwrap_or(0)
     }
+}
 
-    app.planned_ops.clear();
-    app.planned_targets.clear();
+/// The list the current mode navigates, with its length.
+fn nav_list(app: &mut App) -> Option<(&mut ListState, usize)> {
+    match app.mode {
+        Mode::List if app.tree_view => {
+            let len = tree_rows(&app.root, &app.groups, &app.collapsed, app.path_order).len();
+            Some((&mut app.tree_state, len))
+        }
+        Mode::List => Some((&mut app.list_state, app.groups.len())),
+        Mode::Pick => {
+            let len = pick_rows(app).len();
+            sync_to_pick_view(app);
+            Some((&mut app.pick_view, len))
+        }
+        Mode::Timeline => {
+            // Rows whose file is gone aren't on the timeline.
+            let len = app
+                .list_state
+                .selected()
+                .and_then(|gi| app.groups.get(gi))
+                .map_or(0, |g| timeline_entries(g).len());
+            Some((&mut app.timeline_state, len))
+        }
+        Mode::Apply => {
+            let len = app.apply_run.as_ref().map_or(0, |r| r.entries.len());
+            Some((&mut app.apply_state, len))
+        }
+        Mode::Columns => Some((&mut app.columns_state, Column::ALL.len())),
+        Mode::Merge => {
+            let len = app.merge.as_ref().map_or(0, |(_, _, m)| m.hunk_count());
+            Some((&mut app.merge_state, len))
+        }
+        _ => None,
+    }
+}
 
-    app.message = format!(
-        "Some groups failed ({}). See details in the log panel.",
-        errors.len()
-    );
-    app.planned_ops = errors;
-    app.mode = Mode::Confirm;
-    Ok(())
+fn move_selection(state: &mut ListState, len: usize, delta: isize) {
+    if len == 0 {
+        state.select(None);
+        return;
+    }
+    let next = match state.selected() {
+        None => 0,
+        Some(i) => i.saturating_add_signed(delta).min(len - 1),
+    };
+    state.select(Some(next));
 }
 
-fn rescan(app: &mut App) -> Result<()> {
-    let groups = scan_conflicts(&app.root, app.include_hidden)?;
-    app.groups = groups;
-    app.selected_groups.clear();
-    app.list_state = ListState::default();
-    app.pick_state = ListState::default();
-    app.compare_error = None;
-    app.last_error = None;
-    if !app.groups.is_empty() {
+fn toggle_tree_view(app: &mut App) {
+    app.tree_view = !app.tree_view;
+    if app.tree_view {
+        sync_to_tree(app);
+    } else if app.list_state.selected().is_none() && !app.groups.is_empty() {
         app.list_state.select(Some(0));
     }
-    Ok(())
 }
 
-fn apply_group(app: &mut App, gi: usize, chosen_idx: usize) -> Result<()> {
-    let g = &app.groups[gi];
-    let base = g.base_path.clone();
-    let archive_dir = archive_dir_for(&base)?;
+/// Points the group cursor at the group under the tree cursor (none on directory rows).
+fn sync_from_tree(app: &mut App) {
+    let rows = tree_rows(&app.root, &app.groups, &app.collapsed, app.path_order);
+    let gi = match app.tree_state.selected().and_then(|i| rows.get(i)) {
+        Some(TreeRow::Group { index, .. }) => Some(*index),
+        _ => None,
+    };
+    app.list_state.select(gi);
+}
 
-    let chosen_path = g.candidates[chosen_idx].path.clone();
+/// The highlighted group's duplicate copies, once worked out.
+fn current_duplicates(app: &App) -> Option<&[Option<usize>]> {
+    let g = app.groups.get(app.list_state.selected()?)?;
+    app.duplicates.get(&g.base_path).map(Vec::as_slice)
+}
 
-    // Determine which file ends up at base path.
-    let make_base_from = if chosen_path == base {
-        None
-    } else {
-        Some(chosen_path.clone())
+/// Hashes the highlighted group's conflicts of equal size in the background to find
+/// identical copies; the Pick view shows every copy until they are in.
+fn ensure_duplicates(app: &mut App) {
+    let Some(g) = app.list_state.selected().and_then(|i| app.groups.get(i)) else {
+        return;
     };
-
-    if !app.apply {
-        // Dry-run: don't touch FS.
-        return Ok(());
+    let checking = app
+        .duplicates_check
+        .as_ref()
+        .is_some_and(|(p, _)| *p == g.base_path);
+    if checking || app.duplicates.contains_key(&g.base_path) {
+        return;
     }
+    if !may_have_duplicates(g) {
+        app.duplicates
+            .insert(g.base_path.clone(), vec![None; g.candidates.len()]);
+        return;
+    }
+    let (group, cache) = (g.clone(), app.hash_cache.clone());
+    let job = Hashing::spawn(|cancel| spawn_duplicate_check(group, cache, cancel));
+    app.duplicates_check = Some((g.base_path.clone(), job));
+}
 
-    ensure_dir(&archive_dir)?;
-
-    // Move all non-chosen candidates (including the old base if we are replacing it) into archive.
-    for c in &g.candidates {
-        if c.path == chosen_path {
-            continue;
-        }
-        if !c.exists {
-            continue;
-        }
+/// Extensions of the documents whose saved-by, revision and save time the Pick view shows.
+const DOC_PROPS_EXTENSIONS: [&str; 6] = ["docx", "xlsx", "pptx", "odt", "ods", "odp"];
 
-        let file_name = c.path.file_name().ok_or_else(|| anyhow!("bad name"))?;
-        let dest = archive_dir.join(unique_name(file_name.to_string_lossy().as_ref()));
-        move_file(&c.path, &dest).with_context(|| format!("archive {:?} -> {:?}", c.path, dest))?;
+/// Reads the document properties of group `gi`'s versions not read yet. Conflict names
+/// end in the device ID, so the base name's extension tells the type.
+fn ensure_doc_props(app: &mut App, gi: usize) {
+    let Some(g) = app.groups.get(gi) else {
+        return;
+    };
+    let ext = g
+        .base_path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    if !ext.is_some_and(|e| DOC_PROPS_EXTENSIONS.contains(&e.as_str())) {
+        return;
     }
-
-    // If chosen is not base, move chosen into base.
-    if let Some(src) = make_base_from {
-        move_file(&src, &base).with_context(|| format!("set base {:?} <- {:?}", base, src))?;
+    for c in g
+        .candidates
+        .iter()
+        .chain(&g.references)
+        .filter(|c| c.exists)
+    {
+        app.doc_props
+            .entry((c.path.clone(), c.modified))
+            .or_insert_with(|| doc_props(&c.path));
     }
+}
 
-    Ok(())
+/// `by Ann · rev 14 · saved 2h ago`, leaving out what the document doesn't record.
+fn doc_props_line(props: &DocProps, now: SystemTime) -> String {
+    [
+        props.modified_by.as_ref().map(|who| format!("by {who}")),
+        props.revision.as_ref().map(|rev| format!("rev {rev}")),
+        props.modified.map(|t| {
+            let age = now.duration_since(t).unwrap_or_default();
+            format!("saved {} ago", format_age(age))
+        }),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(" · ")
 }
 
-fn current_group_len(app: &App) -> usize {
-    let Some(i) = app.list_state.selected() else {
-        return 0;
-    };
-    app.groups.get(i).map(|g| g.candidates.len()).unwrap_or(0)
+/// Rows of the Pick list, as candidate (or reference) indices: copies identical to an
+/// earlier conflict are folded into it.
+fn pick_rows(app: &App) -> Vec<usize> {
+    let dup = current_duplicates(app).unwrap_or_default();
+    (0..current_group_len(app))
+        .filter(|&i| dup.get(i).copied().flatten().is_none())
+        .collect()
 }
 
-fn current_group_paths(app: &App) -> Result<(PathBuf, PathBuf)> {
-    let gi = app
-        .list_state
-        .selected()
-        .ok_or_else(|| anyhow!("no selection"))?;
-    let g = app.groups.get(gi).ok_or_else(|| anyhow!("bad index"))?;
+/// The row standing for candidate `i`: its own, or the one it was folded into.
+fn pick_row_of(app: &App, i: usize) -> usize {
+    current_duplicates(app)
+        .and_then(|d| d.get(i).copied().flatten())
+        .unwrap_or(i)
+}
 
-    let chosen_idx = g.chosen.unwrap_or(0);
-    let left = g.base_path.clone();
-    let right = g
+fn sync_from_pick_view(app: &mut App) {
+    let rows = pick_rows(app);
+    let ci = app.pick_view.selected().and_then(|r| rows.get(r).copied());
+    app.pick_state.select(ci);
+}
+
+/// Moves the drawn Pick cursor to the row of the highlighted candidate.
+fn sync_to_pick_view(app: &mut App) {
+    let rows = pick_rows(app);
+    let row = app.pick_state.selected().and_then(|i| {
+        let shown = pick_row_of(app, i);
+        rows.iter().position(|&r| r == shown)
+    });
+    app.pick_view.select(row);
+}
+
+/// Moves the tree cursor to the group cursor's row, e.g. after a rescan reordered groups.
+fn sync_to_tree(app: &mut App) {
+    let rows = tree_rows(&app.root, &app.groups, &app.collapsed, app.path_order);
+    let row = app.list_state.selected().and_then(|gi| {
+        rows.iter()
+            .position(|r| matches!(r, TreeRow::Group { index, .. } if *index == gi))
+    });
+    match row {
+        Some(r) => app.tree_state.select(Some(r)),
+        None if rows.is_empty() => app.tree_state.select(None),
+        None => {
+            let r = app.tree_state.selected().unwrap_or(0).min(rows.len() - 1);
+            app.tree_state.select(Some(r));
+            sync_from_tree(app);
+        }
+    }
+}
+
+/// Folding and folder-wide selection in the tree view. Returns whether the key was used.
+fn handle_tree_key(app: &mut App, code: KeyCode) -> bool {
+    let rows = tree_rows(&app.root, &app.groups, &app.collapsed, app.path_order);
+    let Some(row) = app.tree_state.selected().and_then(|i| rows.get(i)).cloned() else {
+        return false;
+    };
+    match (row, code) {
+        (TreeRow::Dir { path, .. }, KeyCode::Enter) => {
+            if !app.collapsed.remove(&path) {
+                app.collapsed.insert(path);
+            }
+        }
+        (TreeRow::Dir { path, .. }, KeyCode::Right) => {
+            app.collapsed.remove(&path);
+        }
+        (TreeRow::Dir { path, .. }, KeyCode::Left) => {
+            app.collapsed.insert(path);
+        }
+        (TreeRow::Group { index, .. }, KeyCode::Left) => {
+            // Jump to the containing directory's row.
+            let Some(dir) = rel_path(&app.root, &app.groups[index].base_path)
+                .parent()
+                .map(Path::to_path_buf)
+            else {
+                return true;
+            };
+            let pos = rows
+                .iter()
+                .position(|r| matches!(r, TreeRow::Dir { path, .. } if *path == dir));
+            if let Some(pos) = pos {
+                app.tree_state.select(Some(pos));
+                app.list_state.select(None);
+            }
+        }
+        (TreeRow::Dir { path, .. }, KeyCode::Char(' ')) => {
+            let under = groups_under(&app.root, &app.groups, &path);
+            if under.iter().all(|gi| app.selected_groups.contains(gi)) {
+                for gi in &under {
+                    app.selected_groups.remove(gi);
+                }
+                app.message = format!("Unselected {} groups in {}", under.len(), path.display());
+            } else {
+                app.selected_groups.extend(under.iter().copied());
+                app.message = format!("Selected {} groups in {}", under.len(), path.display());
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Enter folds or unfolds the plan directory under the Confirm cursor, Right unfolds it
+/// and Left folds it, moving the cursor to its heading.
+fn handle_plan_fold_key(app: &mut App, code: KeyCode) -> bool {
+    if !matches!(code, KeyCode::Enter | KeyCode::Left | KeyCode::Right) {
+        return false;
+    }
+    let rows = plan_rows(&app.planned_ops, &app.plan_folded);
+    let Some(cursor) = app.plan_state.selected().filter(|&i| i < rows.len()) else {
+        return false;
+    };
+    // The directory heading at or above the cursor.
+    let Some((pos, dir)) = rows[..=cursor]
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(i, r)| match r {
+            PlanRow::Dir { dir, .. } => Some((i, dir.clone())),
+            PlanRow::Line(_) => None,
+        })
+    else {
+        return false;
+    };
+    let fold = match code {
+        KeyCode::Left => true,
+        KeyCode::Right => false,
+        _ => !app.plan_folded.contains(&dir),
+    };
+    if fold {
+        app.plan_folded.insert(dir);
+        app.plan_state.select(Some(pos));
+    } else {
+        app.plan_folded.remove(&dir);
+    }
+    sync_log_to_plan(app);
+    true
+}
+
+/// Points the footer's Plan / Log panel at the plan line under the Confirm cursor.
+fn sync_log_to_plan(app: &mut App) {
+    let rows = plan_rows(&app.planned_ops, &app.plan_folded);
+    match app.plan_state.selected().and_then(|i| rows.get(i)) {
+        Some(PlanRow::Dir { first_line, .. }) => app.log_scroll = *first_line,
+        Some(PlanRow::Line(i)) => app.log_scroll = *i,
+        None => {}
+    }
+}
+
+/// Groups currently shown in List mode, in display order (the tree view hides the
+/// contents of collapsed directories).
+fn visible_groups(app: &App) -> Vec<usize> {
+    if !app.tree_view {
+        return (0..app.groups.len()).collect();
+    }
+    tree_rows(&app.root, &app.groups, &app.collapsed, app.path_order)
+        .into_iter()
+        .filter_map(|r| match r {
+            TreeRow::Group { index, .. } => Some(index),
+            TreeRow::Dir { .. } => None,
+        })
+        .collect()
+}
+
+fn select_all_visible(app: &mut App) {
+    let visible = visible_groups(app);
+    app.selected_groups.extend(visible.iter().copied());
+    app.message = format!("Selected {} groups", visible.len());
+}
+
+fn invert_selection(app: &mut App) {
+    for gi in visible_groups(app) {
+        if !app.selected_groups.remove(&gi) {
+            app.selected_groups.insert(gi);
+        }
+    }
+    app.message = format!("{} groups selected", app.selected_groups.len());
+}
+
+/// Starts a visual range at the cursor, or ends the running one keeping its selection.
+fn toggle_visual(app: &mut App) {
+    if app.visual.take().is_some() {
+        app.message = format!("{} groups selected", app.selected_groups.len());
+        return;
+    }
+    if start_visual(app, false) {
+        app.message = "-- VISUAL -- move to extend, V/Esc to finish".to_string();
+    } else {
+        app.message = "Move to a group to start a range".to_string();
+    }
+}
+
+/// Anchors a range at the cursor's group. Returns false when the cursor isn't on a group.
+fn start_visual(app: &mut App, shift: bool) -> bool {
+    let Some(anchor) = app.list_state.selected() else {
+        return false;
+    };
+    app.visual = Some(Visual {
+        anchor,
+        before: app.selected_groups.clone(),
+        shift,
+    });
+    extend_visual(app);
+    true
+}
+
+/// Starts a shift range if none is running, or ends one on a plain move.
+fn shift_range(app: &mut App, shift: bool) {
+    match &app.visual {
+        None if shift => {
+            start_visual(app, true);
+        }
+        Some(v) if v.shift && !shift => app.visual = None,
+        _ => {}
+    }
+}
+
+/// Second click on the same row within this opens it.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Row of a bordered list drawn in `area` and scrolled to `offset` under a screen
+/// position, if any.
+fn row_at(area: Rect, offset: usize, len: usize, column: u16, row: u16) -> Option<usize> {
+    // Inside the block borders.
+    if column <= area.x
+        || column + 1 >= area.x + area.width
+        || row <= area.y
+        || row + 1 >= area.y + area.height
+    {
+        return None;
+    }
+    let i = offset + usize::from(row - area.y - 1);
+    (i < len).then_some(i)
+}
+
+/// List row (flat or tree) under a screen position, if any.
+fn list_row_at(app: &App, column: u16, row: u16) -> Option<usize> {
+    let (state, len) = if app.tree_view {
+        let len = tree_rows(&app.root, &app.groups, &app.collapsed, app.path_order).len();
+        (&app.tree_state, len)
+    } else {
+        (&app.list_state, app.groups.len())
+    };
+    row_at(app.list_area, state.offset(), len, column, row)
+}
+
+/// In the list a click moves the cursor and a double-click opens the group (or folds a
+/// tree directory); shift-click and dragging select the range from where the cursor was.
+/// In Pick a click chooses the version, in Confirm it presses the button. The wheel
+/// moves through whichever list or pane is shown.
+fn handle_mouse(app: &mut App, m: MouseEvent) -> Result<()> {
+    match (app.mode, m.kind) {
+        (_, MouseEventKind::ScrollDown) => {
+            dispatch_key(app, KeyCode::Down, KeyModifiers::NONE)?;
+        }
+        (_, MouseEventKind::ScrollUp) => {
+            dispatch_key(app, KeyCode::Up, KeyModifiers::NONE)?;
+        }
+        (
+            Mode::List,
+            MouseEventKind::Down(MouseButton::Left) | MouseEventKind::Drag(MouseButton::Left),
+        ) => {
+            let down = matches!(m.kind, MouseEventKind::Down(_));
+            let shift = !down || m.modifiers.contains(KeyModifiers::SHIFT);
+            let Some(row) = list_row_at(app, m.column, m.row) else {
+                return Ok(());
+            };
+            let double = down
+                && !shift
+                && app
+                    .last_click
+                    .is_some_and(|(at, r)| r == row && at.elapsed() < DOUBLE_CLICK);
+            app.last_click = (down && !double).then(|| (Instant::now(), row));
+            shift_range(app, shift);
+            if app.tree_view {
+                app.tree_state.select(Some(row));
+                sync_from_tree(app);
+            } else {
+                app.list_state.select(Some(row));
+            }
+            extend_visual(app);
+            if double {
+                dispatch_key(app, KeyCode::Enter, KeyModifiers::NONE)?;
+            }
+        }
+        (Mode::Pick, MouseEventKind::Down(MouseButton::Left)) => {
+            let rows = pick_rows(app);
+            let offset = app.pick_view.offset();
+            if let Some(row) = row_at(app.pick_area, offset, rows.len(), m.column, m.row) {
+                app.pick_state.select(Some(rows[row]));
+                dispatch_key(app, KeyCode::Enter, KeyModifiers::NONE)?;
+            }
+        }
+        (Mode::Confirm, MouseEventKind::Down(MouseButton::Left)) => {
+            let clicked = app.confirm_buttons.iter().find(|(r, _)| {
+                (r.x..r.x + r.width).contains(&m.column) && (r.y..r.y + r.height).contains(&m.row)
+            });
+            if let Some(&(_, code)) = clicked {
+                dispatch_key(app, code, KeyModifiers::NONE)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Selects the visible groups between the visual anchor and the cursor.
+fn extend_visual(app: &mut App) {
+    let Some(visual) = &app.visual else {
+        return;
+    };
+    let Some(cursor) = app.list_state.selected() else {
+        return;
+    };
+    let visible = visible_groups(app);
+    let pos = |gi| visible.iter().position(|&v| v == gi);
+    let (Some(a), Some(b)) = (pos(visual.anchor), pos(cursor)) else {
+        return;
+    };
+    let mut selected = visual.before.clone();
+    selected.extend(&visible[a.min(b)..=a.max(b)]);
+    app.selected_groups = selected;
+}
+
+fn toggle_selected(app: &mut App) {
+    let Some(i) = app.list_state.selected() else {
+        return;
+    };
+    if app.selected_groups.contains(&i) {
+        app.selected_groups.remove(&i);
+    } else {
+        app.selected_groups.insert(i);
+    }
+}
+
+fn enter_pick(app: &mut App) -> Result<()> {
+    let gi = app
+        .list_state
+        .selected()
+        .ok_or_else(|| anyhow!("no selection"))?;
+    let g = app.groups.get(gi).ok_or_else(|| anyhow!("bad index"))?;
+    app.new_groups.remove(&g.base_path);
+    app.mode = Mode::Pick;
+    app.pick_state = ListState::default();
+    // Default to newest if it exists, else original.
+    let default_idx = g.newest_idx().unwrap_or(0);
+    app.pick_state.select(Some(default_idx));
+    if let Some(skew) = g.clock_skew(app.skew_threshold) {
+        app.message = format!(
+            "Clock skew: conflict names and mtimes disagree by up to {}; newest/oldest may be wrong",
+            format_age(skew)
+        );
+    }
+    start_pick_diffstats(app, gi);
+    Ok(())
+}
+
+/// Diffstats of every other version of group `gi` against its original, computed in the
+/// background for the Pick view.
+fn start_pick_diffstats(app: &mut App, gi: usize) {
+    let g = &app.groups[gi];
+    let Some(orig) = g.candidates.first().filter(|c| c.exists) else {
+        return;
+    };
+    let pending: Vec<(PathBuf, PathBuf)> = g
         .candidates
-        .get(chosen_idx)
-        .map(|c| c.path.clone())
-        .unwrap_or(left.clone());
+        .iter()
+        .chain(&g.references)
+        .skip(1)
+        .filter(|c| c.exists)
+        .map(|c| (orig.path.clone(), c.path.clone()))
+        .filter(|k| !app.diffstats.contains_key(k))
+        .collect();
+    if !pending.is_empty() {
+        app.diffstat_rx = Some(spawn_diffstats(pending, app.diff_options.max_size));
+    }
+}
+
+fn pick_current(app: &mut App) -> Result<()> {
+    let gi = app
+        .list_state
+        .selected()
+        .ok_or_else(|| anyhow!("no selection"))?;
+    let ci = app
+        .pick_state
+        .selected()
+        .ok_or_else(|| anyhow!("no candidate"))?;
+    if app.groups[gi].is_reference_row(ci) {
+        app.message = "Reference versions are read-only; diff them with d".to_string();
+        return Ok(());
+    }
+    app.groups[gi].chosen = Some(ci);
+    app.mode = Mode::List;
+    app.message = "Picked".to_string();
+    Ok(())
+}
+
+fn pick_original(app: &mut App) -> Result<()> {
+    let gi = app
+        .list_state
+        .selected()
+        .ok_or_else(|| anyhow!("no selection"))?;
+    // Original is always candidates[0]
+    app.groups[gi].chosen = Some(0);
+    app.mode = Mode::List;
+    app.message = "Picked original".to_string();
+    Ok(())
+}
+
+fn pick_newest(app: &mut App) -> Result<()> {
+    let gi = app
+        .list_state
+        .selected()
+        .ok_or_else(|| anyhow!("no selection"))?;
+    if refuse_skewed_pick(app, gi, Strategy::Newest) {
+        return Ok(());
+    }
+    let idx = app.groups[gi]
+        .newest_idx()
+        .ok_or_else(|| anyhow!("no mtime"))?;
+    app.groups[gi].chosen = Some(idx);
+    app.mode = Mode::List;
+    app.message = "Picked newest".to_string();
+    Ok(())
+}
+
+fn pick_trusted(app: &mut App) -> Result<()> {
+    let gi = app
+        .list_state
+        .selected()
+        .ok_or_else(|| anyhow!("no selection"))?;
+    app.groups[gi].chosen = Strategy::Trusted.choose(&app.groups[gi]);
+    app.mode = Mode::List;
+    app.message = "Picked most trusted device".to_string();
+    Ok(())
+}
+
+fn pick_largest(app: &mut App) -> Result<()> {
+    let gi = app
+        .list_state
+        .selected()
+        .ok_or_else(|| anyhow!("no selection"))?;
+    app.groups[gi].chosen = Strategy::Largest.choose(&app.groups[gi]);
+    app.mode = Mode::List;
+    app.message = "Picked largest".to_string();
+    Ok(())
+}
+
+fn pick_oldest(app: &mut App) -> Result<()> {
+    let gi = app
+        .list_state
+        .selected()
+        .ok_or_else(|| anyhow!("no selection"))?;
+    if refuse_skewed_pick(app, gi, Strategy::Oldest) {
+        return Ok(());
+    }
+    let idx = app.groups[gi]
+        .oldest_idx()
+        .ok_or_else(|| anyhow!("no mtime"))?;
+    app.groups[gi].chosen = Some(idx);
+    app.mode = Mode::List;
+    app.message = "Picked oldest".to_string();
+    Ok(())
+}
+
+fn plan_and_confirm(app: &mut App, all_selected: bool) -> Result<()> {
+    let mut targets: Vec<usize> = if all_selected {
+        app.selected_groups.iter().copied().collect()
+    } else {
+        app.list_state.selected().into_iter().collect()
+    };
+    targets.sort_unstable();
+
+    if targets.is_empty() {
+        app.message = "No groups selected".to_string();
+        return Ok(());
+    }
+    plan_targets(app, targets)
+}
+
+/// Describes `targets` and opens the confirmation, or asks for picks if one is missing.
+fn plan_targets(app: &mut App, targets: Vec<usize>) -> Result<()> {
+    app.planned_ops.clear();
+    app.log_scroll = 0;
+    app.planned_targets.clear();
+    app.planned_blocked = 0;
+    app.planned_cost = ApplyCost::default();
+    app.plan_changes.clear();
+    for &gi in &targets {
+        let g = &app.groups[gi];
+        let Some(ci) = g.chosen else {
+            app.message = "Pick a version first (Enter)".to_string();
+            app.planned_ops.clear();
+            app.planned_targets.clear();
+            return Ok(());
+        };
+        plan_group_ops(app, gi, ci)?;
+    }
+    app.planned_stamps = targets
+        .iter()
+        .map(|&gi| {
+            let g = &app.groups[gi];
+            g.candidates
+                .iter()
+                .map(|c| FileStamp::of(&c.path))
+                .collect()
+        })
+        .collect();
+    app.planned_targets = targets;
+    // A plan over several directories opens folded to one row per directory.
+    let dirs: BTreeSet<PathBuf> = plan_rows(&app.planned_ops, &BTreeSet::new())
+        .into_iter()
+        .filter_map(|r| match r {
+            PlanRow::Dir { dir, .. } => Some(dir),
+            PlanRow::Line(_) => None,
+        })
+        .collect();
+    app.plan_folded = if dirs.len() > 1 {
+        dirs
+    } else {
+        BTreeSet::new()
+    };
+    app.plan_state.select(Some(0));
+    app.stignore_offer = stignore_offer(app);
+    app.mode = Mode::Confirm;
+    if let Some(path) = app.plan_out.clone() {
+        export_plan(app, &path);
+    }
+    Ok(())
+}
+
+/// The Syncthing folder to offer ignoring `.stconflict-archive` in: when the plan creates
+/// the first local archive next to some group and the folder's `.stignore` doesn't
+/// cover archives yet. Otherwise the archived versions sync to every device.
+fn stignore_offer(app: &App) -> Option<PathBuf> {
+    if app.demo || app.options.archive != ArchiveTarget::Local {
+        return None;
+    }
+    let creates_archive = app
+        .planned_targets
+        .iter()
+        .filter_map(|&gi| app.groups.get(gi))
+        .any(|g| archive_dir_for(&g.base_path).is_ok_and(|d| !d.exists()));
+    let folder = folder_root(&app.root);
+    (creates_archive && !ignores_archive(&folder)).then_some(folder)
+}
+
+/// `i` in the Confirm view: appends the archive pattern to the offered `.stignore`.
+fn add_archive_ignore(app: &mut App) {
+    let Some(folder) = app.stignore_offer.take() else {
+        return;
+    };
+    app.message = match ignore_archive(&folder) {
+        Ok(path) => format!("Added (?d){ARCHIVE_DIR} to {}", path.display()),
+        Err(e) => format!("Couldn't update .stignore: {e:#}"),
+    };
+}
+
+/// Writes the planned groups' operations to `path`, reporting the outcome in the message.
+fn export_plan(app: &mut App, path: &Path) {
+    let groups: Result<Vec<_>> = app
+        .planned_targets
+        .iter()
+        .filter_map(|&gi| {
+            let g = &app.groups[gi];
+            let ci = g.chosen?;
+            Some(planned_ops(&app.root, g, ci, &app.options).map(|ops| (g.base_path.clone(), ops)))
+        })
+        .collect();
+    app.message = match groups.and_then(|groups| write_plan(path, &groups).map(|()| groups.len())) {
+        Ok(n) => format!("Wrote plan for {n} groups to {}", path.display()),
+        Err(e) => format!("Plan export failed: {e:#}"),
+    };
+}
+
+/// Starts per-directory confirmation over the selected groups (or every picked group
+/// when nothing is selected).
+fn start_dir_batch(app: &mut App) -> Result<()> {
+    let use_selection = !app.selected_groups.is_empty();
+    let mut dirs: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for (i, g) in app.groups.iter().enumerate() {
+        if g.chosen.is_none() || (use_selection && !app.selected_groups.contains(&i)) {
+            continue;
+        }
+        let dir = rel_path(&app.root, &g.base_path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        dirs.entry(dir).or_default().push(g.base_path.clone());
+    }
+    if dirs.is_empty() {
+        app.message = "No picked groups to confirm".to_string();
+        return Ok(());
+    }
+    app.dir_batch = Some(DirBatch {
+        total: dirs.len(),
+        pending: dirs.into_iter().collect(),
+        current: PathBuf::new(),
+    });
+    next_dir(app)
+}
+
+/// Plans the next directory of the batch that still has picked groups, or ends the batch.
+fn next_dir(app: &mut App) -> Result<()> {
+    while let Some((dir, paths)) = app.dir_batch.as_mut().and_then(|b| b.pending.pop_front()) {
+        let targets: Vec<usize> = app
+            .groups
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| g.chosen.is_some() && paths.contains(&g.base_path))
+            .map(|(i, _)| i)
+            .collect();
+        if targets.is_empty() {
+            continue;
+        }
+        if let Some(b) = app.dir_batch.as_mut() {
+            b.current = dir;
+        }
+        return plan_targets(app, targets);
+    }
+    app.dir_batch = None;
+    app.planned_ops.clear();
+    app.planned_targets.clear();
+    app.mode = Mode::List;
+    app.message = "All directories reviewed".to_string();
+    Ok(())
+}
+
+/// Whether a plan line contains the log search text, ignoring case.
+fn log_line_matches(line: &str, needle: &str) -> bool {
+    !needle.is_empty() && line.to_lowercase().contains(&needle.to_lowercase())
+}
+
+/// Scrolls the log to the next (or previous) line containing the search text, wrapping
+/// around; with `from_top`, the line at the top counts as the next one.
+fn find_in_log(app: &mut App, forward: bool, from_top: bool) {
+    let n = app.planned_ops.len();
+    if app.log_search.is_empty() || n == 0 {
+        return;
+    }
+    let skip = usize::from(!from_top);
+    let found = (0..n)
+        .map(|k| {
+            if forward {
+                (app.log_scroll + skip + k) % n
+            } else {
+                (app.log_scroll + 2 * n - skip - k) % n
+            }
+        })
+        .find(|&i| log_line_matches(&app.planned_ops[i], &app.log_search));
+    let matches: Vec<usize> = (0..n)
+        .filter(|&i| log_line_matches(&app.planned_ops[i], &app.log_search))
+        .collect();
+    app.message = match found {
+        Some(i) => {
+            app.log_scroll = i;
+            let nth = matches.iter().position(|&m| m == i).unwrap_or(0) + 1;
+            format!("Match {nth} of {} for {:?}", matches.len(), app.log_search)
+        }
+        None => format!("No match for {:?}", app.log_search),
+    };
+}
+
+fn plan_group_ops(app: &mut App, gi: usize, chosen_idx: usize) -> Result<()> {
+    let g = &app.groups[gi];
+    let mut lines = describe_group(&app.root, g, chosen_idx, &app.options)?;
+    if lines.iter().any(|l| l.starts_with("  blocked")) {
+        app.planned_blocked += 1;
+        app.blocked.insert(g.base_path.clone());
+    } else {
+        app.blocked.remove(&g.base_path);
+        let ops = planned_ops(&app.root, g, chosen_idx, &app.options)?;
+        let cost = ApplyCost::of_ops(&ops, app.options.verify);
+        lines.push(format!("  estimate -> {}", cost.describe()));
+        app.planned_cost.add(cost);
+    }
+    app.planned_ops.extend(lines);
+    Ok(())
+}
+
+/// Re-stats the planned groups' files. When Syncthing changed any since planning,
+/// refreshes those groups and plans again, listing what changed, instead of applying.
+fn replan_if_changed(app: &mut App) -> Result<bool> {
+    let mut changes = Vec::new();
+    let mut changed_groups = Vec::new();
+    for (&gi, before) in app.planned_targets.iter().zip(&app.planned_stamps) {
+        let g = &app.groups[gi];
+        let count = changes.len();
+        for (c, old) in g.candidates.iter().zip(before) {
+            if let Some(what) = old.change(&FileStamp::of(&c.path)) {
+                changes.push(format!(
+                    "{}: {what}",
+                    rel_path(&app.root, &c.path).display()
+                ));
+            }
+        }
+        if changes.len() > count {
+            changed_groups.push(gi);
+        }
+    }
+    if changes.is_empty() {
+        return Ok(false);
+    }
+    for gi in changed_groups {
+        let g = &mut app.groups[gi];
+        for c in &mut g.candidates {
+            let fresh = stat_candidate(c.path.clone(), c.is_original, String::new());
+            (c.exists, c.size, c.modified) = (fresh.exists, fresh.size, fresh.modified);
+        }
+        let base = g.base_path.clone();
+        app.identical.remove(&base);
+        app.duplicates.remove(&base);
+        app.duplicates_check.take_if(|(p, _)| *p == base);
+        app.diffstats.retain(|(orig, _), _| *orig != base);
+    }
+    let targets = app.planned_targets.clone();
+    plan_targets(app, targets)?;
+    app.message = format!(
+        "{} files changed since planning; review the updated plan",
+        changes.len()
+    );
+    app.plan_changes = changes;
+    Ok(true)
+}
+
+fn apply_plan(app: &mut App) -> Result<()> {
+    if replan_if_changed(app)? {
+        return Ok(());
+    }
+    let targets: Vec<usize> = app
+        .planned_targets
+        .iter()
+        .copied()
+        .filter(|&gi| app.groups[gi].chosen.is_some())
+        .collect();
+    if targets.is_empty() {
+        app.message = "Nothing planned".to_string();
+        app.mode = Mode::List;
+        app.planned_ops.clear();
+        return Ok(());
+    }
+
+    app.apply_run = Some(ApplyRun {
+        dry_run: !app.apply,
+        entries: targets
+            .iter()
+            .map(|&gi| (gi, GroupStatus::Pending))
+            .collect(),
+        rx: None,
+        cancel: Arc::new(AtomicBool::new(false)),
+        paused: Vec::new(),
+        undo: Vec::new(),
+        kept_hashes: BTreeMap::new(),
+        warnings: Vec::new(),
+    });
+    app.apply_state = ListState::default();
+    app.apply_state.select(Some(0));
+    start_apply(app, targets);
+    Ok(())
+}
+
+/// Starts applying `targets` on a background thread (pausing their Syncthing folders first)
+/// and switches to Apply mode; `run_loop` keeps drawing and collects each outcome.
+fn start_apply(app: &mut App, targets: Vec<usize>) {
+    let dry_run = app.apply_run.as_ref().is_some_and(|r| r.dry_run);
+    if !dry_run {
+        if let Err(e) = check_free_space(&app.root, &app.options) {
+            refuse_apply(app, "Low disk space", e);
+            return;
+        }
+        let planned: Vec<HookGroup> = targets
+            .iter()
+            .map(|&gi| &app.groups[gi])
+            .filter_map(|g| Some(HookGroup::new(g, g.chosen?, &app.options, "planned")))
+            .collect();
+        if let Err(e) = app.hooks.run(HookEvent::PreApply, &app.root, &planned) {
+            refuse_apply(app, "pre_apply hook failed", e);
+            return;
+        }
+    }
+    // Keep Syncthing from scanning the folders while files are being moved around.
+    let paused = match app.syncthing.as_ref().filter(|_| !dry_run) {
+        Some(client) => {
+            let paths = targets.iter().map(|&gi| app.groups[gi].base_path.as_path());
+            match pause_folders(client, paths) {
+                Ok(ids) => ids,
+                Err(e) => {
+                    refuse_apply(app, "Syncthing pause failed", e);
+                    return;
+                }
+            }
+        }
+        None => Vec::new(),
+    };
+
+    let Some(run) = app.apply_run.as_mut() else {
+        return;
+    };
+    let mut planned = Vec::new();
+    for &gi in &targets {
+        let g = &app.groups[gi];
+        let status = match g.chosen {
+            Some(chosen) => {
+                planned.push(PlannedGroup {
+                    group: g.clone(),
+                    chosen,
+                });
+                GroupStatus::Pending
+            }
+            None => GroupStatus::Failed("no version picked".to_string()),
+        };
+        if let Some(entry) = run.entries.iter_mut().find(|(g, _)| *g == gi) {
+            entry.1 = status;
+        }
+    }
+    let plan = ResolutionPlan {
+        root: app.root.clone(),
+        groups: planned,
+        options: ApplyOptions {
+            dry_run,
+            ..app.options.clone()
+        },
+    };
+    let plan_options = plan.options.clone();
+    run.cancel = Arc::new(AtomicBool::new(false));
+    let (hooks, root) = (app.hooks.clone(), app.root.clone());
+    // Without --verify, the chosen version is still hashed for the checks after the run.
+    let hash_cache = app.hash_cache.clone();
+    let hash_chosen = !dry_run && !plan_options.verify;
+    let mut cache = None;
+    let hash_before = move |pg: &PlannedGroup| {
+        if !hash_chosen || pg.group.discards(pg.chosen) {
+            return None;
+        }
+        if cache.is_none() {
+            cache = hash_cache.as_ref().and_then(|p| HashCache::open(p).ok());
+        }
+        hash_cached(cache.as_ref(), &pg.group.candidates.get(pg.chosen)?.path).ok()
+    };
+    // The post_group hook runs on the worker, so a slow one doesn't stall the screen.
+    let post_group = move |pg: &PlannedGroup, outcome: &GroupOutcome| {
+        if dry_run {
+            return None;
+        }
+        let mut hooked = HookGroup::new(&pg.group, pg.chosen, &plan_options, "applied");
+        if let Err(e) = &outcome.result {
+            hooked.status = "failed";
+            hooked.error = Some(format!("{e:#}"));
+        }
+        let ran = hooks.run(HookEvent::PostGroup, &root, &[hooked]);
+        ran.err().map(|e| format!("{e:#}"))
+    };
+    run.rx = Some(spawn_apply_then(
+        plan,
+        run.cancel.clone(),
+        hash_before,
+        post_group,
+    ));
+    run.paused = paused;
+    run.warnings.clear();
+    app.mode = Mode::Apply;
+    app.message = if dry_run {
+        "Dry-run in progress...".to_string()
+    } else {
+        "Applying...".to_string()
+    };
+}
+
+/// Reports why an apply didn't start: in the message bar when retrying (keeping the
+/// finished run on screen), otherwise in the error panel.
+fn refuse_apply(app: &mut App, why: &str, e: anyhow::Error) {
+    if app.apply_run.as_ref().is_some_and(ApplyRun::started) {
+        app.message = format!("{why}; nothing retried: {e:#}");
+    } else {
+        app.apply_run = None;
+        app.last_error = Some(format!("{e:#}"));
+        app.message = format!("{why}; nothing applied");
+        app.mode = Mode::Error;
+    }
+}
+
+/// Collects outcomes from the apply thread (waiting for it to finish when `wait` is set).
+/// Once it has finished, resumes Syncthing and summarizes.
+fn poll_apply(app: &mut App, wait: bool) {
+    loop {
+        let Some(rx) = app.apply_run.as_ref().and_then(|r| r.rx.as_ref()) else {
+            return;
+        };
+        let next = if wait {
+            rx.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            rx.try_recv()
+        };
+        match next {
+            Ok(outcome) => record_outcome(app, outcome),
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {
+                finish_apply(app);
+                return;
+            }
+        }
+    }
+}
+
+/// Stores one group's outcome on the run and logs it to the history, along with the
+/// rules that fired on it (dry-runs and failures too).
+fn record_outcome(app: &mut App, outcome: GroupOutcome) {
+    let Some(run) = app.apply_run.as_mut() else {
+        return;
+    };
+    let Some(entry) = run
+        .entries
+        .iter_mut()
+        .find(|(gi, _)| app.groups[*gi].base_path == outcome.base_path)
+    else {
+        return;
+    };
+    let gi = entry.0;
+    let g = &app.groups[gi];
+    let fired = fired_rules(&app.root, g, &app.pick_rules, &app.options.loser_rules);
+    if let Some(h) = app.history.as_mut().filter(|_| !fired.is_empty()) {
+        let ok = outcome.result.is_ok();
+        if let Err(e) = h.record_rules(&g.base_path, &fired, run.dry_run, ok, SystemTime::now()) {
+            run.warnings.push(format!("rule ledger: {e:#}"));
+        }
+    }
+    run.warnings.extend(outcome.warnings);
+    let chosen_hash = outcome.chosen_hash;
+    entry.1 = match outcome.result {
+        Ok(kept_hash) => {
+            if let Some(hash) = kept_hash.clone().or(chosen_hash) {
+                run.kept_hashes.insert(gi, hash);
+            }
+            if let (false, Some(ci)) = (run.dry_run, app.groups[gi].chosen) {
+                match undo_record(&app.groups[gi], ci, &app.options) {
+                    Ok(undo) => run.undo.push(undo),
+                    Err(e) => run.warnings.push(format!("undo: {e:#}")),
+                }
+                let logged = record_history(
+                    app.history.as_mut(),
+                    &app.root,
+                    &app.groups[gi],
+                    ci,
+                    kept_hash.as_deref(),
+                    &app.options,
+                );
+                if let Err(e) = logged {
+                    run.warnings.push(format!("history: {e:#}"));
+                }
+            }
+            GroupStatus::Done
+        }
+        Err(e) => GroupStatus::Failed(format!("{e:#}")),
+    };
+}
+
+/// The apply thread is done: groups it never reached were cancelled.
+fn finish_apply(app: &mut App) {
+    let Some(run) = app.apply_run.as_mut() else {
+        return;
+    };
+    run.rx = None;
+    for entry in &mut run.entries {
+        if entry.1 == GroupStatus::Pending {
+            entry.1 = GroupStatus::Cancelled;
+        }
+    }
+    let paused = std::mem::take(&mut run.paused);
+    let applied = run
+        .entries
+        .iter()
+        .filter(|(_, st)| *st == GroupStatus::Done)
+        .count();
+    let delay = if run.dry_run {
+        Duration::ZERO
+    } else {
+        rescan_delay(applied)
+    };
+    let checks = applied_checks(app);
+    let mut warnings = app
+        .syncthing
+        .as_ref()
+        .map(|c| resume_folders(c, &paused, delay.is_zero()))
+        .unwrap_or_default();
+    if delay.is_zero() {
+        start_applied_checks(app, checks);
+    } else {
+        defer_rescan(app, delay, BTreeSet::new(), paused, checks);
+    }
+    let Some(run) = app.apply_run.as_mut() else {
+        return;
+    };
+    if !run.dry_run {
+        let groups: Vec<HookGroup> = run
+            .entries
+            .iter()
+            .filter_map(|(gi, st)| {
+                let g = &app.groups[*gi];
+                let mut hooked = HookGroup::new(g, g.chosen?, &app.options, "applied");
+                match st {
+                    GroupStatus::Failed(e) => {
+                        hooked.status = "failed";
+                        hooked.error = Some(e.clone());
+                    }
+                    GroupStatus::Cancelled => hooked.status = "cancelled",
+                    GroupStatus::Done | GroupStatus::Pending => {}
+                }
+                Some(hooked)
+            })
+            .collect();
+        if let Err(e) = app.hooks.run(HookEvent::PostApply, &app.root, &groups) {
+            warnings.push(format!("{e:#}"));
+        }
+    }
+    run.warnings.extend(warnings);
+    app.message = apply_summary(run);
+}
+
+/// The message bar once a run is finished.
+fn apply_summary(run: &ApplyRun) -> String {
+    let failed = run.failed().count();
+    let cancelled = run
+        .entries
+        .iter()
+        .filter(|(_, st)| *st == GroupStatus::Cancelled)
+        .count();
+    let done = run.entries.len() - failed - cancelled;
+    let verb = if run.dry_run { "Checked" } else { "Applied" };
+    if failed + cancelled == 0 && run.warnings.is_empty() {
+        format!("{verb} {done} groups. Enter to close.")
+    } else if cancelled > 0 {
+        format!(
+            "Cancelled: {verb} {done} groups, {failed} failed, {cancelled} not started. R run the rest | Enter close"
+        )
+    } else {
+        format!(
+            "{verb} {done} groups, {failed} failed. r retry highlighted | R retry all failed | Enter close"
+        )
+    }
+}
+
+/// The groups a real run applied, to check once the disk has settled.
+fn applied_checks(app: &App) -> Vec<AppliedCheck> {
+    let Some(run) = app.apply_run.as_ref().filter(|r| !r.dry_run) else {
+        return Vec::new();
+    };
+    run.entries
+        .iter()
+        .filter(|(_, st)| *st == GroupStatus::Done)
+        .filter_map(|&(gi, _)| {
+            let g = &app.groups[gi];
+            Some(AppliedCheck {
+                group: g.clone(),
+                chosen: g.chosen?,
+                kept_hash: run.kept_hashes.get(&gi).cloned(),
+            })
+        })
+        .collect()
+}
+
+/// Checks `checks` on a background thread; its problems are drained by `run_loop`.
+fn start_applied_checks(app: &mut App, checks: Vec<AppliedCheck>) {
+    if checks.is_empty() {
+        return;
+    }
+    let rx = spawn_check_applied(
+        app.root.clone(),
+        scan_options(app),
+        app.options.clone(),
+        checks,
+    );
+    app.check_rxs.push(rx);
+}
+
+/// Collects the problems of finished checks: onto the Done summary while the run is
+/// still on screen, else into the message bar.
+fn poll_applied_checks(app: &mut App, wait: bool) {
+    let mut problems = Vec::new();
+    app.check_rxs.retain(|rx| {
+        let got = if wait {
+            rx.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            rx.try_recv()
+        };
+        match got {
+            Ok(p) => {
+                problems.extend(p);
+                false
+            }
+            Err(TryRecvError::Empty) => true,
+            Err(TryRecvError::Disconnected) => false,
+        }
+    });
+    let Some(first) = problems.first() else {
+        return;
+    };
+    match app.apply_run.as_mut().filter(|r| !r.running()) {
+        Some(run) => {
+            run.warnings.extend(problems);
+            app.message = apply_summary(run);
+        }
+        None => {
+            app.message = match problems.len() {
+                1 => format!("After the apply: {first}"),
+                n => format!("After the apply: {first} (and {} more)", n - 1),
+            }
+        }
+    }
+}
+
+/// Asks the apply thread to stop; the group it is moving finishes first.
+fn cancel_apply(app: &mut App) {
+    if let Some(run) = &app.apply_run {
+        run.cancel.store(true, Ordering::Relaxed);
+        app.message = "Cancelling: finishing the current group...".to_string();
+    }
+}
+
+/// Logs an applied group. The files have already moved, so a logging failure is only a
+/// warning, never a failed group.
+fn record_history(
+    history: Option<&mut History>,
+    root: &Path,
+    g: &ConflictGroup,
+    chosen_idx: usize,
+    kept_hash: Option<&str>,
+    options: &ApplyOptions,
+) -> Result<()> {
+    let Some(h) = history else {
+        return Ok(());
+    };
+    let kept = kept_destination(g, chosen_idx, options)?;
+    h.record(root, g, chosen_idx, &kept, kept_hash, SystemTime::now())?;
+    Ok(())
+}
+
+fn retry_failed(app: &mut App, only_highlighted: bool) {
+    let Some(run) = app.apply_run.as_ref() else {
+        return;
+    };
+    if run.running() {
+        return;
+    }
+    let targets: Vec<usize> = if only_highlighted {
+        app.apply_state
+            .selected()
+            .and_then(|i| run.entries.get(i))
+            .filter(|(_, st)| matches!(st, GroupStatus::Failed(_) | GroupStatus::Cancelled))
+            .map(|&(gi, _)| gi)
+            .into_iter()
+            .collect()
+    } else {
+        run.unfinished().collect()
+    };
+    if targets.is_empty() {
+        app.message = "Nothing to retry".to_string();
+        return;
+    }
+    start_apply(app, targets);
+}
+
+/// Leaves Apply mode once the run is finished: dry-runs go back to the confirmation so
+/// apply can be toggled on, real runs rescan so resolved groups disappear.
+fn close_apply(app: &mut App) -> Result<()> {
+    let Some(run) = app.apply_run.as_ref() else {
+        app.mode = Mode::List;
+        return Ok(());
+    };
+    if run.running() {
+        return Ok(());
+    }
+    let failed = run.unfinished().count();
+    if run.dry_run && failed == 0 {
+        app.apply_run = None;
+        app.mode = Mode::Confirm;
+        app.message = format!(
+            "Dry-run complete. Toggle apply with '{}', then press '{}' to apply.",
+            app.keymap.key(Scope::Confirm, "toggle_apply"),
+            app.keymap.key(Scope::Confirm, "run")
+        );
+        return Ok(());
+    }
+    let dry_run = run.dry_run;
+    let applied: BTreeSet<PathBuf> = run
+        .entries
+        .iter()
+        .filter(|(_, st)| *st == GroupStatus::Done)
+        .filter_map(|&(gi, _)| app.groups.get(gi))
+        .map(|g| g.base_path.clone())
+        .collect();
+    if let Some(run) = app.apply_run.take().filter(|r| !r.undo.is_empty()) {
+        app.undo_stack.push(run.undo);
+    }
+    let touched = group_dirs(
+        app.planned_targets
+            .iter()
+            .filter_map(|&gi| app.groups.get(gi))
+            .map(|g| g.base_path.as_path()),
+    );
+    app.planned_ops.clear();
+    app.planned_targets.clear();
+    if app.dir_batch.is_some() {
+        // Keep the picks of the directories still to come.
+        if !dry_run {
+            rescan_after_apply(app, touched, &applied)?;
+        }
+        next_dir(app)?;
+        if failed > 0 {
+            app.message = format!("{failed} groups failed in the previous directory");
+        }
+        return Ok(());
+    }
+    if !dry_run {
+        rescan_after_apply(app, touched, &applied)?;
+    }
+    app.mode = Mode::List;
+    app.message = if failed == 0 {
+        "Applied".to_string()
+    } else {
+        format!("{failed} groups failed")
+    };
+    if let Some(d) = &app.deferred_rescan {
+        app.message.push_str(&format!(
+            "; rescanning in {}s, once the disk settles",
+            d.timer.remaining(Instant::now()).as_millis().div_ceil(1000)
+        ));
+    }
+    Ok(())
+}
+
+/// Undoes the most recent applied batch, last group first, then rescans so its groups
+/// are back in the list with the versions they had kept still picked.
+fn undo_last_batch(app: &mut App) -> Result<()> {
+    let Some(batch) = app.undo_stack.pop() else {
+        app.message = "Nothing to undo".to_string();
+        return Ok(());
+    };
+    let mut failed = Vec::new();
+    let mut gone = 0;
+    for undo in batch.iter().rev() {
+        match undo_group(undo) {
+            Ok(()) => gone += undo.gone.len(),
+            Err(e) => failed.push(format!(
+                "{}: {e:#}",
+                rel_path(&app.root, &undo.base_path).display()
+            )),
+        }
+    }
+    rescan_dirs(app, group_dirs(batch.iter().map(|u| u.base_path.as_path())))?;
+    for undo in &batch {
+        let kept = undo.kept.as_ref().map_or(&undo.base_path, |(_, was)| was);
+        if let Some(g) = app
+            .groups
+            .iter_mut()
+            .find(|g| g.base_path == undo.base_path)
+        {
+            g.chosen = g.candidates.iter().position(|c| &c.path == kept);
+        }
+    }
+    let undone = batch.len() - failed.len();
+    let left = app.undo_stack.len();
+    app.message = format!("Undid {undone} groups ({left} more batches to undo)");
+    if gone > 0 {
+        app.message.push_str(&format!(
+            "; {gone} deleted or remote versions can't be restored"
+        ));
+    }
+    if !failed.is_empty() {
+        app.last_error = Some(failed.join("\n"));
+        app.message = format!("Undo failed for {} groups; {}", failed.len(), app.message);
+        app.mode = Mode::Error;
+    }
+    Ok(())
+}
+
+/// Puts off rescanning `dirs` and the Syncthing `folders` by `delay`, or until the
+/// later of it and an already pending rescan.
+fn defer_rescan(
+    app: &mut App,
+    delay: Duration,
+    dirs: BTreeSet<PathBuf>,
+    folders: Vec<String>,
+    checks: Vec<AppliedCheck>,
+) {
+    let timer = RescanTimer::new(delay, Instant::now());
+    match &mut app.deferred_rescan {
+        Some(d) => {
+            d.timer.extend(timer);
+            d.dirs.extend(dirs);
+            d.checks.extend(checks);
+            d.folders.extend(folders);
+            d.folders.sort();
+            d.folders.dedup();
+        }
+        None => {
+            app.deferred_rescan = Some(DeferredRescan {
+                timer,
+                dirs,
+                folders,
+                checks,
+            })
+        }
+    }
+}
+
+/// Runs the put-off rescans once they are due and the list isn't in use, so group
+/// indices don't shift under a pick or plan.
+fn poll_deferred_rescan(app: &mut App) -> Result<()> {
+    let due = app
+        .deferred_rescan
+        .as_ref()
+        .is_some_and(|d| d.timer.is_due(Instant::now()));
+    // The checks don't touch the list, so they start whatever the mode.
+    if let Some(d) = app.deferred_rescan.as_mut().filter(|_| due) {
+        let checks = std::mem::take(&mut d.checks);
+        start_applied_checks(app, checks);
+    }
+    if !due || app.mode != Mode::List {
+        return Ok(());
+    }
+    let Some(d) = app.deferred_rescan.take() else {
+        return Ok(());
+    };
+    let warnings = app
+        .syncthing
+        .as_ref()
+        .map(|c| rescan_folders(c, &d.folders))
+        .unwrap_or_default();
+    rescan_dirs(app, d.dirs)?;
+    app.message = match warnings.first() {
+        None => "Rescanned now that the apply has settled".to_string(),
+        Some(w) => format!("Rescanned now that the apply has settled; {w}"),
+    };
+    Ok(())
+}
+
+/// After a real apply: rescans the touched `dirs`, or while rescans are put off, only
+/// drops the `applied` groups from the list and adds `dirs` to the pending rescan.
+fn rescan_after_apply(
+    app: &mut App,
+    dirs: BTreeSet<PathBuf>,
+    applied: &BTreeSet<PathBuf>,
+) -> Result<()> {
+    let Some(d) = &mut app.deferred_rescan else {
+        return rescan_dirs(app, dirs);
+    };
+    d.dirs.extend(dirs);
+    remove_groups(app, applied);
+    Ok(())
+}
+
+/// Takes the groups with base paths in `gone` out of the list, keeping the selection
+/// and the cursor on its group (or where a removed group was).
+fn remove_groups(app: &mut App, gone: &BTreeSet<PathBuf>) {
+    let cursor = app.list_state.selected();
+    let cursor_path = cursor
+        .and_then(|i| app.groups.get(i))
+        .map(|g| g.base_path.clone());
+    let selected: BTreeSet<PathBuf> = app
+        .selected_groups
+        .iter()
+        .filter_map(|&i| app.groups.get(i))
+        .map(|g| g.base_path.clone())
+        .collect();
+    app.groups.retain(|g| !gone.contains(&g.base_path));
+    app.selected_groups = app
+        .groups
+        .iter()
+        .enumerate()
+        .filter(|(_, g)| selected.contains(&g.base_path))
+        .map(|(i, _)| i)
+        .collect();
+    app.visual = None;
+    app.pick_state = ListState::default();
+    let at = cursor_path
+        .and_then(|p| app.groups.iter().position(|g| g.base_path == p))
+        .or(cursor)
+        .filter(|_| !app.groups.is_empty())
+        .map(|i| i.min(app.groups.len() - 1));
+    app.list_state = ListState::default();
+    app.list_state.select(at);
+    if app.tree_view {
+        sync_to_tree(app);
+    }
+}
+
+/// Starts a full scan of the root on a background thread; `poll_scan` puts its groups in
+/// the list once it is done. Demo groups are generated at once.
+fn start_scan(app: &mut App, after_filter: bool) -> Result<()> {
+    if let Some(run) = app.scan_run.take() {
+        run.cancel.store(true, Ordering::Relaxed);
+    }
+    if app.demo {
+        let groups = app
+            .filter
+            .apply(demo_groups(&app.root, &demo_spec())?, SystemTime::now());
+        finish_scan(app, groups, after_filter);
+        return Ok(());
+    }
+    let options = scan_options(app);
+    let checkpoint = app
+        .scan_cache
+        .as_deref()
+        .map(|d| checkpoint_path(d, &app.root, options));
+    if let Some(saved) = checkpoint
+        .as_deref()
+        .and_then(|f| Checkpoint::load(f, &app.root))
+    {
+        app.message = format!(
+            "Resuming the interrupted scan at {}",
+            rel_path(&app.root, &saved.resume_at).display()
+        );
+    }
+    let cancel = Arc::new(AtomicBool::new(false));
+    app.scan_run = Some(ScanRun {
+        rx: spawn_scan(app.root.clone(), options, cancel.clone(), checkpoint),
+        cancel,
+        after_filter,
+    });
+    Ok(())
+}
+
+/// Takes the groups of a finished scan, if any; with `wait`, waits for it.
+fn poll_scan(app: &mut App, wait: bool) {
+    let Some(run) = &app.scan_run else {
+        return;
+    };
+    let res = if wait {
+        run.rx.recv().ok()
+    } else {
+        match run.rx.try_recv() {
+            Ok(res) => Some(res),
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => None,
+        }
+    };
+    let Some(run) = app.scan_run.take() else {
+        return;
+    };
+    match res {
+        Some(Ok(groups)) => finish_scan(app, groups, run.after_filter),
+        Some(Err(e)) => app.message = format!("Scan failed: {e:#}"),
+        None => {}
+    }
+}
+
+/// Stops the scan in progress, leaving the list as it was.
+fn cancel_scan(app: &mut App) {
+    if let Some(run) = app.scan_run.take() {
+        run.cancel.store(true, Ordering::Relaxed);
+        app.message = format!(
+            "Scan cancelled; showing {} groups from before it",
+            app.groups.len()
+        );
+    }
+}
+
+/// Replaces the list with a full scan's groups, dropping picks and selections the way a
+/// fresh start would, and pre-picks again.
+fn finish_scan(app: &mut App, groups: Vec<ConflictGroup>, after_filter: bool) {
+    let (groups, snoozed) = prepare_groups(app, groups);
+    // Files changed under us; check everything again.
+    app.identical.clear();
+    app.duplicates.clear();
+    app.duplicates_check = None;
+    app.diffstats.clear();
+    app.blocked.clear();
+    app.groups = filter_identical(app, groups);
+    start_identity_checks(app);
+    app.new_groups
+        .retain(|p| app.groups.iter().any(|g| &g.base_path == p));
+    app.selected_groups.clear();
+    app.visual = None;
+    app.list_state = ListState::default();
+    app.pick_state = ListState::default();
+    app.compare_error = None;
+    app.last_error = None;
+    if !app.groups.is_empty() {
+        app.list_state.select(Some(0));
+    }
+    auto_pick_like_last_time(app);
+    auto_pick_deleted_original(app);
+    auto_pick_rules(app);
+    if after_filter {
+        app.message = if app.filter.is_empty() {
+            format!("Filter cleared: {} groups", app.groups.len())
+        } else {
+            format!(
+                "Filter {}: {} groups",
+                app.filter.describe(),
+                app.groups.len()
+            )
+        };
+    } else if snoozed > 0 {
+        app.message = format!("{snoozed} snoozed groups hidden (history --snoozed lists them)");
+    }
+    restore_session(app);
+    goto_group(app);
+}
+
+/// On the first scan: picks and selects again what a session that didn't end normally
+/// left in its file.
+fn restore_session(app: &mut App) {
+    if app.session_saved.is_some() {
+        return;
+    }
+    let Some(path) = &app.session_path else {
+        return;
+    };
+    if let Some(session) = Session::load(path, &app.root) {
+        let (picked, selected) = session.restore(&mut app.groups);
+        if picked > 0 || !selected.is_empty() {
+            app.message = format!(
+                "Restored {picked} picks and {} selected groups from a session that didn't \
+                 end normally",
+                selected.len()
+            );
+        }
+        app.selected_groups = selected;
+    }
+    app.session_saved = Some(Session::of(&app.root, &app.groups, &app.selected_groups));
+}
+
+/// Moves the tutorial on once the user has done what its step asks: selected two groups,
+/// picked one, opened the plan, finished a dry run, then a real apply.
+fn advance_tutorial(app: &mut App) {
+    let Some(step) = app.tutorial else {
+        return;
+    };
+    let finished = app
+        .apply_run
+        .as_ref()
+        .filter(|r| app.mode == Mode::Apply && r.started() && !r.running());
+    let done = match step {
+        Step::Select => app.selected_groups.len() >= 2,
+        Step::Pick => app.groups.iter().any(|g| g.chosen.is_some()),
+        Step::Plan => app.mode == Mode::Confirm,
+        Step::DryRun => finished.is_some_and(|r| r.dry_run),
+        Step::Apply => finished.is_some_and(|r| !r.dry_run),
+        Step::Done => false,
+    };
+    if done {
+        app.tutorial = Some(step.next());
+    }
+}
+
+/// Writes the picks and selection to the session file when they changed since the last
+/// save. Skipped while a scan replaces the groups.
+fn autosave(app: &mut App) {
+    app.session_at = Instant::now();
+    let (Some(path), Some(saved)) = (&app.session_path, &app.session_saved) else {
+        return;
+    };
+    if app.scan_run.is_some() {
+        return;
+    }
+    let session = Session::of(&app.root, &app.groups, &app.selected_groups);
+    if session == *saved {
+        return;
+    }
+    match session.save(path) {
+        Ok(()) => app.session_saved = Some(session),
+        Err(e) => {
+            app.message = format!("Autosave disabled: {e:#}");
+            app.session_path = None;
+        }
+    }
+}
+
+/// `--goto`: selects the group whose base file or conflict copy is the target, and opens
+/// it in the Pick view with that version highlighted.
+fn goto_group(app: &mut App) {
+    let Some(target) = app.goto.take() else {
+        return;
+    };
+    let found = app.groups.iter().enumerate().find_map(|(gi, g)| {
+        let ci = g.candidates.iter().position(|c| c.path == target);
+        (g.base_path == target || ci.is_some()).then_some((gi, ci))
+    });
+    let Some((gi, ci)) = found else {
+        app.message = format!(
+            "--goto: no conflict group at {}",
+            rel_path(&app.root, &target).display()
+        );
+        return;
+    };
+    app.list_state.select(Some(gi));
+    if app.tree_view {
+        sync_to_tree(app);
+    }
+    if enter_pick(app).is_ok() && ci.is_some() {
+        app.pick_state.select(ci);
+    }
+}
+
+/// After an apply, merge or undo: rescans only `dirs`, the folders of the groups it
+/// touched, instead of walking the whole tree again. Every other group stays as it was,
+/// its pick and selection included.
+fn rescan_dirs(app: &mut App, dirs: BTreeSet<PathBuf>) -> Result<()> {
+    if app.demo {
+        return start_scan(app, false);
+    }
+    let cursor = app.list_state.selected();
+    let cursor_path = cursor
+        .and_then(|i| app.groups.get(i))
+        .map(|g| g.base_path.clone());
+    let selected: BTreeSet<PathBuf> = app
+        .selected_groups
+        .iter()
+        .filter_map(|&i| app.groups.get(i))
+        .map(|g| g.base_path.clone())
+        .collect();
+    let fresh = scan_dirs(&app.root, &dirs, scan_options(app))?;
+    let (fresh, _) = prepare_groups(app, fresh);
+    let in_dirs = |p: &Path| p.parent().is_some_and(|d| dirs.contains(d));
+    app.identical.retain(|p, _| !in_dirs(p));
+    app.duplicates.retain(|p, _| !in_dirs(p));
+    app.duplicates_check.take_if(|(p, _)| in_dirs(p));
+    app.kinds.retain(|p, _| !in_dirs(p));
+    app.diffstats.retain(|(p, _), _| !in_dirs(p));
+    app.blocked.retain(|p| !in_dirs(p));
+    let fresh = filter_identical(app, fresh);
+    let mut groups: Vec<ConflictGroup> = std::mem::take(&mut app.groups)
+        .into_iter()
+        .filter(|g| !in_dirs(&g.base_path))
+        .chain(fresh)
+        .collect();
+    app.path_order.sort(&mut groups);
+    if app.sort_by_type {
+        groups.sort_by_cached_key(group_kind);
+    }
+    app.groups = groups;
+    start_identity_checks(app);
+    app.new_groups
+        .retain(|p| app.groups.iter().any(|g| &g.base_path == p));
+    app.selected_groups = app
+        .groups
+        .iter()
+        .enumerate()
+        .filter(|(_, g)| selected.contains(&g.base_path))
+        .map(|(i, _)| i)
+        .collect();
+    app.visual = None;
+    app.pick_state = ListState::default();
+    app.compare_error = None;
+    app.last_error = None;
+    // The cursor stays on its group, or where a resolved group was.
+    let at = cursor_path
+        .and_then(|p| app.groups.iter().position(|g| g.base_path == p))
+        .or(cursor)
+        .filter(|_| !app.groups.is_empty())
+        .map(|i| i.min(app.groups.len() - 1));
+    app.list_state = ListState::default();
+    app.list_state.select(at);
+    auto_pick_like_last_time(app);
+    auto_pick_deleted_original(app);
+    auto_pick_rules(app);
+    Ok(())
+}
+
+/// The folders holding these base files, which an apply or undo of their groups touches.
+fn group_dirs<'a>(base_paths: impl IntoIterator<Item = &'a Path>) -> BTreeSet<PathBuf> {
+    base_paths
+        .into_iter()
+        .filter_map(|p| p.parent().map(Path::to_path_buf))
+        .collect()
+}
+
+/// With `--only-differing`, checks every group now and drops those whose versions are
+/// identical. Otherwise returns `groups` unchanged.
+fn filter_identical(app: &App, groups: Vec<ConflictGroup>) -> Vec<ConflictGroup> {
+    if !app.only_differing {
+        return groups;
+    }
+    groups
+        .into_iter()
+        .filter(|g| app.identical.get(&g.base_path) != Some(&Some(true)))
+        .collect()
+}
+
+/// With `--only-differing`, takes the groups the identity checks found identical out of
+/// the list; only in the List view, so group indices don't shift under a pick or plan.
+fn hide_identical(app: &mut App) {
+    if !app.only_differing || app.mode != Mode::List {
+        return;
+    }
+    let same: BTreeSet<PathBuf> = app
+        .groups
+        .iter()
+        .filter(|g| app.identical.get(&g.base_path) == Some(&Some(true)))
+        .map(|g| g.base_path.clone())
+        .collect();
+    if same.is_empty() {
+        return;
+    }
+    remove_groups(app, &same);
+    app.message = format!("Hid {} groups with identical versions", same.len());
+}
+
+/// Checks groups not yet known to be identical or not in the background; results arrive
+/// through `identity_checks` and are drained by `run_loop`.
+fn start_identity_checks(app: &mut App) {
+    let pending: Vec<ConflictGroup> = app
+        .groups
+        .iter()
+        .filter(|g| !app.identical.contains_key(&g.base_path))
+        .cloned()
+        .collect();
+    let cache = app.hash_cache.clone();
+    app.identity_checks = (!pending.is_empty())
+        .then(|| Hashing::spawn(|cancel| spawn_identity_checks(pending, cache, cancel)));
+}
+
+/// Collects what the hashing threads have found so far; `wait` blocks until they are done.
+fn poll_hashing(app: &mut App, wait: bool) {
+    if let Some(job) = &app.identity_checks {
+        let (got, done) = job.poll(wait);
+        app.identical.extend(got);
+        if done {
+            app.identity_checks = None;
+        }
+    }
+    hide_identical(app);
+    if let Some((base, job)) = &app.duplicates_check {
+        let (got, done) = job.poll(wait);
+        if let Some(dup) = got.into_iter().next() {
+            app.duplicates.insert(base.clone(), dup);
+        }
+        if done {
+            app.duplicates_check = None;
+        }
+    }
+    if let Some(job) = &app.detail_hashing {
+        let (got, done) = job.poll(wait);
+        for (i, hash) in got {
+            if let Some(slot) = app.detail_hashes.get_mut(i) {
+                *slot = hash;
+            }
+        }
+        if done {
+            app.detail_hashing = None;
+        }
+    }
+}
+
+fn scan_options(app: &App) -> ScanOptions {
+    ScanOptions {
+        include_hidden: app.include_hidden,
+        follow_symlinks: app.follow_symlinks,
+        filter: app.filter,
+    }
+}
+
+/// A fresh scan of the root (regenerated groups in demo mode), prepared for the list.
+fn scan_groups(app: &App) -> Result<Vec<ConflictGroup>> {
+    let groups = if app.demo {
+        app.filter
+            .apply(demo_groups(&app.root, &demo_spec())?, SystemTime::now())
+    } else {
+        scan_conflicts_with(&app.root, scan_options(app))?
+    };
+    Ok(prepare_groups(app, groups).0)
+}
+
+/// Scanned groups with references and trust attached, snoozed groups dropped and the
+/// list's order applied; also returns how many were snoozed.
+fn prepare_groups(app: &App, mut groups: Vec<ConflictGroup>) -> (Vec<ConflictGroup>, usize) {
+    attach_references(&app.snapshots, &mut groups);
+    attach_trust(&app.device_trust, &mut groups);
+    let snoozed = commands::drop_snoozed(app.history.as_ref(), &mut groups, SystemTime::now());
+    app.path_order.sort(&mut groups);
+    if app.sort_by_type {
+        groups.sort_by_cached_key(group_kind);
+    }
+    (groups, snoozed)
+}
+
+/// Switches the list between path order and grouping by file type, keeping picks,
+/// selections and the cursor.
+fn toggle_sort_by_type(app: &mut App) {
+    app.sort_by_type = !app.sort_by_type;
+    let mut groups = app.groups.clone();
+    if app.sort_by_type {
+        groups.sort_by_cached_key(group_kind);
+        app.message = "Sorted by file type".to_string();
+    } else {
+        app.path_order.sort(&mut groups);
+        app.message = "Sorted by path".to_string();
+    }
+    merge_groups(app, groups);
+}
+
+/// Rescans and merges the result into the list, keeping picks and selections.
+fn live_rescan(app: &mut App) -> Result<()> {
+    let groups = scan_groups(app)?;
+    let groups = filter_identical(app, groups);
+    let added = merge_groups(app, groups);
+    start_identity_checks(app);
+    auto_pick_like_last_time(app);
+    auto_pick_deleted_original(app);
+    auto_pick_rules(app);
+    if added > 0 {
+        app.message = format!("{added} new conflict group(s)");
+    }
+    Ok(())
+}
+
+/// Replaces the group list with `fresh`, carrying over picks, selections, and the cursor
+/// for groups that still exist. New or changed groups are marked in `new_groups`.
+/// Returns how many groups were marked.
+fn merge_groups(app: &mut App, mut fresh: Vec<ConflictGroup>) -> usize {
+    let cursor = app
+        .list_state
+        .selected()
+        .and_then(|i| app.groups.get(i))
+        .map(|g| g.base_path.clone());
+    let selected: BTreeSet<PathBuf> = app
+        .selected_groups
+        .iter()
+        .filter_map(|&i| app.groups.get(i))
+        .map(|g| g.base_path.clone())
+        .collect();
+
+    let mut added = 0;
+    for g in &mut fresh {
+        let old = app.groups.iter().find(|o| o.base_path == g.base_path);
+        match old {
+            Some(old) => {
+                g.chosen = old
+                    .chosen
+                    .and_then(|ci| old.candidates.get(ci))
+                    .and_then(|c| g.candidates.iter().position(|n| n.path == c.path));
+                let stat = |c: &Candidate| (c.path.clone(), c.size, c.modified);
+                if !old
+                    .candidates
+                    .iter()
+                    .map(stat)
+                    .eq(g.candidates.iter().map(stat))
+                {
+                    app.identical.remove(&g.base_path);
+                    app.duplicates.remove(&g.base_path);
+                    app.duplicates_check.take_if(|(p, _)| *p == g.base_path);
+                    app.kinds.remove(&g.base_path);
+                }
+                if g.candidates.len() > old.candidates.len() {
+                    added += usize::from(app.new_groups.insert(g.base_path.clone()));
+                }
+            }
+            None => {
+                app.identical.remove(&g.base_path);
+                app.duplicates.remove(&g.base_path);
+                app.duplicates_check.take_if(|(p, _)| *p == g.base_path);
+                app.kinds.remove(&g.base_path);
+                added += usize::from(app.new_groups.insert(g.base_path.clone()));
+            }
+        }
+    }
+
+    app.groups = fresh;
+    app.visual = None;
+    app.new_groups
+        .retain(|p| app.groups.iter().any(|g| &g.base_path == p));
+    app.blocked
+        .retain(|p| app.groups.iter().any(|g| &g.base_path == p));
+    app.selected_groups = app
+        .groups
+        .iter()
+        .enumerate()
+        .filter(|(_, g)| selected.contains(&g.base_path))
+        .map(|(i, _)| i)
+        .collect();
+    let cursor = cursor
+        .and_then(|p| app.groups.iter().position(|g| g.base_path == p))
+        .or(if app.groups.is_empty() { None } else { Some(0) });
+    app.list_state.select(cursor);
+    added
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TimelineKind {
+    Original,
+    Conflict,
+    Version,
+    Snapshot,
+}
+
+impl TimelineKind {
+    fn marker(self) -> &'static str {
+        match self {
+            TimelineKind::Original => "● original",
+            TimelineKind::Conflict => "◆ conflict",
+            TimelineKind::Version => "○ version ",
+            TimelineKind::Snapshot => "◇ snapshot",
+        }
+    }
+}
+
+/// All versions of a group's file (row indices into `ConflictGroup::row`), oldest first.
+/// Versions without a known mtime sort last.
+fn timeline_entries(g: &ConflictGroup) -> Vec<(usize, TimelineKind)> {
+    let mut rows: Vec<(usize, TimelineKind)> = (0..g.row_count())
+        .filter_map(|i| {
+            let c = g.row(i)?;
+            if !c.exists {
+                return None;
+            }
+            let kind = if c.is_original {
+                TimelineKind::Original
+            } else if !g.is_reference_row(i) {
+                TimelineKind::Conflict
+            } else if c.label.starts_with("Snapshot") || c.label.starts_with("Shadow") {
+                TimelineKind::Snapshot
+            } else {
+                TimelineKind::Version
+            };
+            Some((i, kind))
+        })
+        .collect();
+    rows.sort_by_key(|&(i, _)| {
+        let m = g.row(i).and_then(|c| c.modified);
+        (m.is_none(), m)
+    });
+    rows
+}
+
+fn enter_timeline(app: &mut App) {
+    if app.list_state.selected().is_none() {
+        app.message = "No group selected".to_string();
+        return;
+    }
+    app.timeline_from = app.mode;
+    app.timeline_state = ListState::default();
+    app.timeline_state.select(Some(0));
+    app.mode = Mode::Timeline;
+}
+
+fn token_spans(tokens: Vec<Token>) -> Vec<Span<'static>> {
+    tokens
+        .into_iter()
+        .map(|t| {
+            let (r, g, b) = t.rgb;
+            let mut style = Style::default().fg(Color::Rgb(r, g, b));
+            if t.bold {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            Span::styled(t.text, style)
+        })
+        .collect()
+}
+
+/// Opens a merge of the original with the highlighted conflict; both must be text.
+fn open_merge(app: &mut App) {
+    let Some(gi) = app.list_state.selected() else {
+        return;
+    };
+    let Some(g) = app.groups.get(gi) else {
+        return;
+    };
+    let Some(ci) = app
+        .pick_state
+        .selected()
+        .filter(|&i| i > 0 && i < g.candidates.len())
+    else {
+        app.message = "Merge: highlight a conflict to merge with the original".to_string();
+        return;
+    };
+    if !g.candidates[0].exists {
+        app.message = "Merge: the original was deleted".to_string();
+        return;
+    }
+    let max = app.diff_options.max_size;
+    let (Some(left), Some(right)) = (
+        read_text(&g.candidates[0].path, max),
+        read_text(&g.candidates[ci].path, max),
+    ) else {
+        app.message = format!(
+            "Merge: only text files up to {} can be merged",
+            format_size(max)
+        );
+        return;
+    };
+    let merge = Merge::new(&left, &right, app.diff_options.algorithm);
+    if merge.hunk_count() == 0 {
+        app.message = "Merge: the two versions have the same lines".to_string();
+        return;
+    }
+    app.merge = Some((gi, ci, merge));
+    app.merge_state = ListState::default();
+    app.merge_state.select(Some(0));
+    app.mode = Mode::Merge;
+}
+
+/// Decides the highlighted hunk and moves on to the next undecided one.
+fn take_hunk(app: &mut App, take: Take) {
+    let (Some((_, _, merge)), Some(i)) = (app.merge.as_mut(), app.merge_state.selected()) else {
+        return;
+    };
+    merge.set(i, take);
+    let n = merge.hunk_count();
+    let undecided: Vec<bool> = merge.hunks().map(|h| h.take.is_none()).collect();
+    if let Some(next) = (1..n).map(|d| (i + d) % n).find(|&j| undecided[j]) {
+        app.merge_state.select(Some(next));
+    }
+}
+
+/// Writes the merge as the group's file, archiving the original and the conflict; a
+/// dry run only checks that it could.
+fn write_merge(app: &mut App) -> Result<()> {
+    let Some((gi, ci, merge)) = &app.merge else {
+        return Ok(());
+    };
+    let Some(text) = merge.result() else {
+        app.message = format!("Merge: {} hunks undecided", merge.undecided());
+        return Ok(());
+    };
+    let g = &app.groups[*gi];
+    let name = rel_path(&app.root, &g.base_path).display().to_string();
+    let options = ApplyOptions {
+        dry_run: !app.apply,
+        ..app.options.clone()
+    };
+    if let Err(e) = write_merged(&app.root, g, *ci, &text, &options) {
+        app.message = format!("Merge failed: {e:#}");
+        return Ok(());
+    }
+    app.merge = None;
+    if !app.apply {
+        app.message = format!(
+            "Dry-run: would write the merge to {name} and archive both versions (t toggles apply)"
+        );
+        app.mode = Mode::Pick;
+        return Ok(());
+    }
+    app.mode = Mode::List;
+    let touched = group_dirs([g.base_path.as_path()]);
+    rescan_dirs(app, touched)?;
+    app.message = format!("Merged {name}; both versions archived");
+    Ok(())
+}
+
+/// Shows the highlighted Pick row's contents, colored by its file extension.
+fn open_preview(app: &mut App) {
+    let Some((g, c)) = app
+        .list_state
+        .selected()
+        .and_then(|gi| app.groups.get(gi))
+        .and_then(|g| Some((g, app.pick_state.selected().and_then(|i| g.row(i))?)))
+    else {
+        return;
+    };
+    let (path, base, modified) = (c.path.clone(), g.base_path.clone(), c.modified);
+    load_file_preview(app, &path, &base, modified);
+    let title = rel_path(&app.root, &path).display().to_string();
+    match app.file_preview.as_ref().map(|(_, _, p)| p) {
+        Some(FilePreview::Image(img)) => {
+            app.preview_title = format!("{title} ({}x{} thumbnail)", img.width(), img.height());
+            app.preview = Vec::new();
+            app.preview_image = Some(path);
+        }
+        Some(FilePreview::Hex { bytes, note }) => {
+            app.preview_title = format!("{title} (first {})", format_size(bytes.len() as u64));
+            app.preview = note
+                .iter()
+                .map(|n| Line::from(Span::styled(n.clone(), Theme::default().message_warn)))
+                .chain(hexdump(bytes).into_iter().map(Line::from))
+                .collect();
+            app.preview_image = None;
+        }
+        _ => {
+            if !open_text_preview(app, &path) {
+                return;
+            }
+            app.preview_title = title;
+            app.preview_image = None;
+        }
+    }
+    app.diff_paths = None;
+    app.preview_scroll = 0;
+    app.preview_from = app.mode;
+    app.mode = Mode::Preview;
+}
+
+/// Fills the preview with `path`'s text highlighted by its extension; `false` (with a
+/// message) when it isn't text or is too large.
+fn open_text_preview(app: &mut App, path: &Path) -> bool {
+    let Some(text) = read_text(path, app.diff_options.max_size) else {
+        app.message = format!(
+            "Preview: not a text file (or larger than {})",
+            format_size(app.diff_options.max_size)
+        );
+        return false;
+    };
+    let highlighter = app.highlighter.get_or_insert_with(Highlighter::new);
+    app.preview = highlighter
+        .lines(path, &text)
+        .into_iter()
+        .map(|tokens| Line::from(token_spans(tokens)))
+        .collect();
+    true
+}
+
+/// What the Pick pane and `v` show for a version that isn't plain text.
+enum FilePreview {
+    /// Decoded and shrunk to at most [`thumbnail::MAX_THUMBNAIL_SIDE`].
+    Image(RgbImage),
+    /// The file's first [`PREVIEW_BYTES`], with why it wasn't shown as an image (HEIC
+    /// without the `heic` feature).
+    Hex {
+        bytes: Vec<u8>,
+        note: Option<String>,
+    },
+    /// Text, or unreadable: nothing beyond the usual preview.
+    Text,
+}
+
+/// Bytes of a binary the hexdump covers.
+const PREVIEW_BYTES: usize = 4096;
+/// Hexdump lines under the Pick list.
+const PICK_HEX_LINES: u16 = 4;
+/// Smallest image pane worth drawing under the Pick list, borders included.
+const MIN_THUMBNAIL_ROWS: u16 = 6;
+
+/// Decodes `path` into `app.file_preview` unless it already holds that file as of
+/// `modified`. Only the last file is kept; photos are decoded again when revisited.
+/// `base` is the group's file, whose extension says whether it's a photo: a conflict
+/// copy's name doesn't always end in it.
+fn load_file_preview(app: &mut App, path: &Path, base: &Path, modified: Option<SystemTime>) {
+    if app
+        .file_preview
+        .as_ref()
+        .is_some_and(|(p, m, _)| p == path && *m == modified)
+    {
+        return;
+    }
+    let preview = match thumbnail::head(path, PREVIEW_BYTES) {
+        Err(_) => FilePreview::Text,
+        Ok(bytes) if thumbnail::is_image(base) => match thumbnail::load(path) {
+            Ok(img) => FilePreview::Image(img),
+            Err(e) => FilePreview::Hex {
+                bytes,
+                note: Some(format!("Not shown as an image: {e}")),
+            },
+        },
+        Ok(bytes) if thumbnail::is_binary(&bytes) => FilePreview::Hex { bytes, note: None },
+        Ok(_) => FilePreview::Text,
+    };
+    app.file_preview = Some((path.to_path_buf(), modified, preview));
+}
+
+/// `img` fitted into `area` as `▀` cells colored with the top and bottom pixels.
+fn half_block_lines(img: &RgbImage, area: Rect) -> Vec<Line<'static>> {
+    let rgb = |[r, g, b]: [u8; 3]| Color::Rgb(r, g, b);
+    half_blocks(img, area.width, area.height)
+        .into_iter()
+        .map(|row| {
+            Line::from(
+                row.into_iter()
+                    .map(|(top, bottom)| {
+                        let style = Style::default().fg(rgb(top));
+                        Span::styled("▀", bottom.map_or(style, |b| style.bg(rgb(b))))
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect()
+}
+
+/// Draws `app.file_preview` in `area`: an image as half blocks (or left empty for
+/// [`show_graphics`] to draw over), a binary as a hexdump.
+fn draw_file_preview(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let Some((path, _, preview)) = &app.file_preview else {
+        return;
+    };
+    match preview {
+        FilePreview::Image(img) if app.graphics == Graphics::Halfblocks => {
+            f.render_widget(Paragraph::new(half_block_lines(img, area)), area);
+        }
+        FilePreview::Image(_) => app.graphics_target = Some((area, path.clone())),
+        FilePreview::Hex { bytes, note } => {
+            let lines: Vec<Line> = note
+                .iter()
+                .map(|n| Line::from(Span::styled(n.clone(), theme.message_warn)))
+                .chain(hexdump(bytes).into_iter().map(Line::from))
+                .collect();
+            f.render_widget(Paragraph::new(lines), area);
+        }
+        FilePreview::Text => {}
+    }
+}
+
+/// Size of a terminal cell in pixels, for scaling sixel images; 10x20 when the terminal
+/// doesn't say.
+fn cell_pixels() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(w) if w.width > 0 && w.height > 0 && w.columns > 0 && w.rows > 0 => (
+            u32::from(w.width / w.columns).max(1),
+            u32::from(w.height / w.rows).max(1),
+        ),
+        _ => (10, 20),
+    }
+}
+
+/// Draws the image the last frame left room for with kitty or sixel graphics, after
+/// removing the one drawn before. Images are only redrawn when their place changes, and
+/// only while the Pick view or preview is up, so popups aren't covered.
+fn show_graphics(terminal: &mut Terminal<CrosstermBackend<Stdout>>, app: &mut App) -> Result<()> {
+    let target = app
+        .graphics_target
+        .clone()
+        .filter(|_| matches!(app.mode, Mode::Pick | Mode::Preview));
+    if target == app.graphics_shown {
+        return Ok(());
+    }
+    if app.graphics_shown.take().is_some() {
+        if app.graphics == Graphics::Kitty {
+            queue!(terminal.backend_mut(), Print(thumbnail::KITTY_CLEAR))?;
+        } else {
+            // Sixel pixels stay until their cells are written again.
+            terminal.clear()?;
+            terminal.draw(|f| ui(f, app))?;
+        }
+    }
+    let Some((area, path)) = target else {
+        terminal.backend_mut().flush()?;
+        return Ok(());
+    };
+    let Some((_, _, FilePreview::Image(img))) =
+        app.file_preview.as_ref().filter(|(p, ..)| *p == path)
+    else {
+        return Ok(());
+    };
+    let (cols, rows) = fit(img.width(), img.height(), area.width, area.height);
+    let escape = match app.graphics {
+        Graphics::Kitty => thumbnail::kitty_escape(img, cols, rows),
+        Graphics::Sixel => {
+            let (w, h) = cell_pixels();
+            thumbnail::sixel_escape(img, u32::from(cols) * w, u32::from(rows) * h)?
+        }
+        _ => return Ok(()),
+    };
+    let backend = terminal.backend_mut();
+    queue!(backend, MoveTo(area.x, area.y), Print(escape))?;
+    backend.flush()?;
+    app.graphics_shown = Some((area, path));
+    Ok(())
+}
+
+/// Shows `diff -u` output with `+`/`-` markers in green/red and the code highlighted for
+/// `right`'s file type; the words that changed within a line get a red/green background.
+fn show_diff(app: &mut App, left: &Path, right: &Path, diff: &str) {
+    let theme = Theme::default();
+    let highlighter = app.highlighter.get_or_insert_with(Highlighter::new);
+    app.preview = highlighter
+        .diff(right, diff)
+        .into_iter()
+        .map(|l| {
+            let marker = match l.kind {
+                DiffKind::Added => theme.selected_mark,
+                DiffKind::Removed => theme.message_error,
+                _ => theme.header_meta,
+            };
+            let mut spans = vec![Span::styled(l.marker, marker)];
+            match l.kind {
+                DiffKind::Header => spans.extend(
+                    l.tokens
+                        .into_iter()
+                        .map(|t| Span::styled(t.text, theme.header_meta)),
+                ),
+                DiffKind::Hunk => spans.extend(
+                    l.tokens
+                        .into_iter()
+                        .map(|t| Span::styled(t.text, theme.message_info)),
+                ),
+                kind => {
+                    let word = match kind {
+                        DiffKind::Added => theme.diff_added_word,
+                        _ => theme.diff_removed_word,
+                    };
+                    let changed: Vec<bool> = l.tokens.iter().map(|t| t.changed).collect();
+                    spans.extend(
+                        token_spans(l.tokens)
+                            .into_iter()
+                            .zip(changed)
+                            .map(|(s, c)| if c { s.patch_style(word) } else { s }),
+                    );
+                }
+            }
+            Line::from(spans)
+        })
+        .collect();
+    app.preview_title = format!(
+        "diff {} {}",
+        rel_path(&app.root, left).display(),
+        rel_path(&app.root, right).display()
+    );
+    app.preview_image = None;
+    app.preview_scroll = 0;
+    app.preview_from = app.mode;
+    app.mode = Mode::Preview;
+}
+
+fn enter_detail(app: &mut App) {
+    let Some(g) = app.list_state.selected().and_then(|gi| app.groups.get(gi)) else {
+        app.message = "No group selected".to_string();
+        return;
+    };
+    // Hashed once on open, in the background. Versions too large to diff aren't hashed:
+    // their block comparison says whether they match.
+    let max = app.diff_options.max_size;
+    let paths: Vec<Option<PathBuf>> = (0..g.row_count())
+        .map(|i| {
+            g.row(i)
+                .filter(|c| c.exists && c.size.is_none_or(|s| s <= max))
+                .map(|c| c.path.clone())
+        })
+        .collect();
+    app.detail_hashes = vec![None; paths.len()];
+    let cache = app.hash_cache.clone();
+    app.detail_hashing = paths
+        .iter()
+        .any(Option::is_some)
+        .then(|| Hashing::spawn(|cancel| spawn_hashes(paths, cache, cancel)));
+    app.detail_provenance = app
+        .syncthing
+        .as_ref()
+        .map(|c| Provenance::Loading(c.spawn_file_at(g.base_path.clone())));
+    // Versions too large to diff are compared with the original block by block instead,
+    // in the background: VM images and databases can take a while.
+    let pairs: Vec<(usize, PathBuf, PathBuf)> = match g.row(0).filter(|c| c.exists) {
+        Some(orig) => (1..g.row_count())
+            .filter_map(|i| {
+                let c = g.row(i).filter(|c| c.exists)?;
+                let large = c.size.max(orig.size).is_some_and(|s| s > max);
+                large.then(|| (i, orig.path.clone(), c.path.clone()))
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    app.detail_blocks.clear();
+    app.detail_blocks_rx = (!pairs.is_empty()).then(|| spawn_block_diffs(pairs));
+    app.detail_from = app.mode;
+    app.detail_scroll = 0;
+    app.mode = Mode::Detail;
+}
+
+/// Takes the detail panel's Syncthing record once it has arrived; `wait` blocks for it.
+fn poll_provenance(app: &mut App, wait: bool) {
+    let Some(Provenance::Loading(rx)) = &app.detail_provenance else {
+        return;
+    };
+    let got = if wait {
+        rx.recv().map_err(|_| TryRecvError::Disconnected)
+    } else {
+        rx.try_recv()
+    };
+    let loaded = match got {
+        Ok(r) => r.map_err(|e| format!("{e:#}")),
+        Err(TryRecvError::Empty) => return,
+        Err(TryRecvError::Disconnected) => Err("lookup stopped".to_string()),
+    };
+    app.detail_provenance = Some(Provenance::Loaded(loaded));
+}
+
+/// Lines of the detail panel: what Syncthing knows about the base file (when connected),
+/// one block per version with where it differs from the original when `blocks` has it,
+/// then the content-equality matrix; `hashing` while `hashes` are still coming in.
+fn detail_lines(
+    g: &ConflictGroup,
+    hashes: &[Option<String>],
+    hashing: bool,
+    blocks: &BTreeMap<usize, BlockDiff>,
+    provenance: Option<&Provenance>,
+    aliases: &DeviceAliases,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    match provenance {
+        Some(Provenance::Loading(_)) => {
+            lines.push("Syncthing: loading...".to_string());
+            lines.push(String::new());
+        }
+        Some(Provenance::Loaded(Ok(file))) => {
+            let global = &file.global;
+            lines.push("Syncthing (latest version of the base file)".to_string());
+            let by = if global.modified_by.is_empty() {
+                "?".to_string()
+            } else {
+                aliases.name_and_id(&global.modified_by)
+            };
+            let deleted = if global.deleted { " (deleted)" } else { "" };
+            lines.push(format!("    announced by: {by}{deleted}"));
+            lines.push(format!("    version:      {}", global.version.join(", ")));
+            lines.push(match &file.local {
+                Some(local) if local.version == global.version => {
+                    "    local copy:   up to date".to_string()
+                }
+                Some(local) => format!("    local copy:   behind ({})", local.version.join(", ")),
+                None => "    local copy:   none".to_string(),
+            });
+            lines.push(format!(
+                "    available on: {} devices",
+                file.availability.len()
+            ));
+            lines.push(String::new());
+        }
+        Some(Provenance::Loaded(Err(e))) => {
+            lines.push(format!("Syncthing: {e}"));
+            lines.push(String::new());
+        }
+        None => {}
+    }
+    for i in 0..g.row_count() {
+        let Some(c) = g.row(i) else { continue };
+        let mut title = format!("[{i}] {}", c.label);
+        if g.is_reference_row(i) {
+            title.push_str(" (ref)");
+        }
+        if !c.exists {
+            title.push_str(" (missing)");
+        }
+        if g.chosen == Some(i) {
+            title.push_str(" (picked)");
+        }
+        lines.push(title);
+        lines.push(format!("    path:     {}", c.path.display()));
+        lines.push(format!(
+            "    size:     {}",
+            c.size
+                .map(|s| format!("{} ({s} bytes)", format_size(s)))
+                .unwrap_or_else(|| "?".to_string())
+        ));
+        lines.push(format!(
+            "    modified: {}",
+            c.modified
+                .map(|t| format!(
+                    "{} ({})",
+                    format_time(t),
+                    format_relative(t, SystemTime::now())
+                ))
+                .unwrap_or_else(|| "?".to_string())
+        ));
+        if let Some(meta) = conflict_meta(&c.path) {
+            lines.push(format!(
+                "    device:   {}",
+                aliases.name_and_id(&meta.device)
+            ));
+        }
+        if let Some(rank) = c.trust {
+            lines.push(format!("    trust:    {}", rank + 1));
+        }
+        let hash = hashes.get(i).cloned().flatten();
+        let blockwise = blocks.contains_key(&i) || (i == 0 && !blocks.is_empty());
+        lines.push(format!(
+            "    sha256:   {}",
+            match hash.as_deref() {
+                Some(h) => &h[..16],
+                None if blockwise && c.exists => "- (compared block by block)",
+                None if hashing && c.exists => "hashing...",
+                None => "?",
+            }
+        ));
+        if let Some(diff) = blocks.get(&i) {
+            for (n, l) in diff.describe(5).into_iter().enumerate() {
+                let label = if n == 0 { "vs [0]:" } else { "" };
+                lines.push(format!("    {label:<10}{l}"));
+            }
+        }
+        lines.push(String::new());
+    }
+
+    // Versions too large to hash are only known to match [0] or not, by their blocks.
+    let same_as_first = |i: usize| -> Option<bool> {
+        if i == 0 {
+            return g.row(0).filter(|c| c.exists).map(|_| true);
+        }
+        match (
+            hashes.get(i).cloned().flatten(),
+            hashes.first().cloned().flatten(),
+        ) {
+            (Some(a), Some(b)) => Some(a == b),
+            _ => blocks
+                .get(&i)
+                .filter(|d| d.done())
+                .map(|d| d.differing == 0),
+        }
+    };
+    let n = g.row_count();
+    lines.push("Content equality (= same, x differs, ? unreadable or comparing):".to_string());
+    lines.push(format!(
+        "     {}",
+        (0..n).map(|j| format!("{j:>3}")).collect::<String>()
+    ));
+    for i in 0..n {
+        let cells: String = (0..n)
+            .map(|j| {
+                match (
+                    hashes.get(i).cloned().flatten(),
+                    hashes.get(j).cloned().flatten(),
+                ) {
+                    (Some(a), Some(b)) if a == b => "  =",
+                    (Some(_), Some(_)) => "  x",
+                    _ if i == j && same_as_first(i).is_some() => "  =",
+                    _ => match (same_as_first(i), same_as_first(j)) {
+                        (Some(true), Some(true)) => "  =",
+                        (Some(true), Some(false)) | (Some(false), Some(true)) => "  x",
+                        _ => "  ?",
+                    },
+                }
+            })
+            .collect();
+        lines.push(format!("  {i:>3}{cells}"));
+    }
+    lines.push(String::new());
+
+    if hashing {
+        lines.push("Hashing versions...".to_string());
+        return lines;
+    }
+    if !blocks.is_empty() {
+        let existing = (0..n).filter(|&i| g.row(i).is_some_and(|c| c.exists));
+        let vs_first: Vec<Option<bool>> = existing.map(same_as_first).collect();
+        lines.push(if vs_first.contains(&Some(false)) {
+            "Versions differ".to_string()
+        } else if vs_first.iter().all(|s| *s == Some(true)) {
+            "All versions are identical; any pick keeps the same content".to_string()
+        } else {
+            "Comparing the large versions block by block...".to_string()
+        });
+        return lines;
+    }
+    let known: BTreeSet<&String> = hashes.iter().flatten().collect();
+    let unknown = hashes.iter().filter(|h| h.is_none()).count();
+    lines.push(match (known.len(), unknown) {
+        (0, _) => "No readable versions".to_string(),
+        (1, 0) => "All versions are identical; any pick keeps the same content".to_string(),
+        (1, u) => format!("Readable versions are identical ({u} unreadable)"),
+        (k, 0) => format!("Versions differ: {k} distinct contents"),
+        (k, u) => format!("Versions differ: {k} distinct contents ({u} unreadable)"),
+    });
+    lines
+}
+
+fn current_group_len(app: &App) -> usize {
+    let Some(i) = app.list_state.selected() else {
+        return 0;
+    };
+    app.groups.get(i).map(|g| g.row_count()).unwrap_or(0)
+}
+
+fn current_group_paths(app: &App) -> Result<(PathBuf, PathBuf)> {
+    let gi = app
+        .list_state
+        .selected()
+        .ok_or_else(|| anyhow!("no selection"))?;
+    let g = app.groups.get(gi).ok_or_else(|| anyhow!("bad index"))?;
+
+    let chosen_idx = g.chosen.unwrap_or(0);
+    let left = g.base_path.clone();
+    let right = g
+        .candidates
+        .get(chosen_idx)
+        .map(|c| c.path.clone())
+        .unwrap_or(left.clone());
+
+    // A highlighted reference is diffed against the current pick instead.
+    if app.mode == Mode::Pick {
+        if let Some(r) = app
+            .pick_state
+            .selected()
+            .filter(|&i| g.is_reference_row(i))
+            .and_then(|i| g.row(i))
+        {
+            return Ok((right, r.path.clone()));
+        }
+    }
+
+    Ok((left, right))
+}
+
+fn compare_command(left: &Path, right: &Path) -> Command {
+    if cfg!(target_os = "windows") {
+        let mut c = Command::new("cmd");
+        c.arg("/C").arg("fc").arg(left).arg(right);
+        return c;
+    }
+
+    let mut c = Command::new("diff");
+    c.arg("-u").arg(left).arg(right);
+    c
+}
+
+fn launch_compare(app: &mut App) -> Result<()> {
+    app.compare_error = None;
+    if let Some(job) = app.diff_job.take() {
+        job.cancel.store(true, Ordering::Relaxed);
+        app.message = "Diff cancelled".to_string();
+        return Ok(());
+    }
+
+    let (left, right) = match current_group_paths(app) {
+        Ok(v) => v,
+        Err(e) => {
+            let msg = format!("{e:#}");
+            app.compare_error = Some(msg.clone());
+            app.last_error = Some(msg);
+            app.message = "Compare failed".to_string();
+            app.mode = Mode::Error;
+            return Ok(());
+        }
+    };
+
+    if left == right {
+        app.message = "Compare: selected is original".to_string();
+        return Ok(());
+    }
+
+    if app.mode == Mode::Confirm {
+        app.message = "Compare not available in confirm view".to_string();
+        return Ok(());
+    }
+
+    run_compare(app, &left, &right)
+}
+
+/// Hides the selected groups (or the highlighted one) for the typed time, remembering
+/// the snooze in the history so later runs hide them too.
+fn snooze_groups(app: &mut App) {
+    let d = match parse_duration(&app.input) {
+        Ok(d) => d,
+        Err(e) => {
+            app.message = format!("{e:#}");
+            return;
+        }
+    };
+    app.mode = Mode::List;
+    let cursor = app.list_state.selected();
+    let indices: Vec<usize> = if app.selected_groups.is_empty() {
+        cursor.into_iter().collect()
+    } else {
+        app.selected_groups.iter().copied().collect()
+    };
+    let targets: Vec<PathBuf> = indices
+        .into_iter()
+        .filter_map(|gi| app.groups.get(gi).map(|g| g.base_path.clone()))
+        .collect();
+    if targets.is_empty() {
+        app.message = "No group to snooze".to_string();
+        return;
+    }
+    let Some(history) = app.history.as_mut() else {
+        app.message = "Snoozing needs the history database, which is disabled".to_string();
+        return;
+    };
+    let until = SystemTime::now() + d;
+    for p in &targets {
+        if let Err(e) = history.snooze(p, until) {
+            app.message = format!("Snooze failed: {e:#}");
+            return;
+        }
+    }
+    let fresh: Vec<ConflictGroup> = app
+        .groups
+        .iter()
+        .filter(|g| !targets.contains(&g.base_path))
+        .cloned()
+        .collect();
+    merge_groups(app, fresh);
+    if let Some(i) = cursor.filter(|_| !app.groups.is_empty()) {
+        app.list_state.select(Some(i.min(app.groups.len() - 1)));
+    }
+    app.message = format!(
+        "Snoozed {} groups until {}",
+        targets.len(),
+        format_time(until)
+    );
+}
+
+/// Lists the archived files older than the typed age for confirmation.
+fn preview_prune(app: &mut App) -> Result<()> {
+    let age = match parse_duration(&app.input) {
+        Ok(age) => age,
+        Err(e) => {
+            app.message = format!("{e:#}");
+            return Ok(());
+        }
+    };
+    app.prune_preview = prune_candidates(&app.root, app.include_hidden, age, SystemTime::now())?;
+    app.mode = Mode::Prune;
+    Ok(())
+}
+
+/// Deletes the previewed archive entries (only reported in dry-run).
+fn run_prune(app: &mut App) {
+    let entries = std::mem::take(&mut app.prune_preview);
+    app.mode = Mode::List;
+    if !app.apply {
+        app.message = format!("Dry-run: would prune {} archived files", entries.len());
+        return;
+    }
+    let errors: Vec<String> = entries
+        .iter()
+        .zip(prune(&entries, app.options.trash))
+        .filter_map(|(e, r)| r.err().map(|err| format!("{}: {err:#}", e.path.display())))
+        .collect();
+    if errors.is_empty() {
+        let to = if app.options.trash {
+            " to the trash"
+        } else {
+            ""
+        };
+        app.message = format!("Pruned {} archived files{to}", entries.len());
+    } else {
+        app.last_error = Some(errors.join("\n"));
+        app.mode = Mode::Error;
+    }
+}
+
+/// Finds Syncthing temp files and conflicts inside `.stversions` and archives.
+fn preview_cleanup(app: &mut App) -> Result<()> {
+    if app.demo {
+        app.message = "Demo mode: nothing to clean up".to_string();
+        return Ok(());
+    }
+    app.leftovers = find_leftovers(&app.root, app.include_hidden)?;
+    if app.leftovers.is_empty() {
+        app.message = "Nothing to clean up".to_string();
+    } else {
+        app.mode = Mode::Cleanup;
+    }
+    Ok(())
+}
+
+/// Deletes every previewed leftover (only reported in dry-run).
+fn run_cleanup(app: &mut App) {
+    let entries = std::mem::take(&mut app.leftovers);
+    app.mode = Mode::List;
+    if !app.apply {
+        app.message = format!("Dry-run: would delete {} leftover files", entries.len());
+        return;
+    }
+    let errors: Vec<String> = entries
+        .iter()
+        .zip(delete_leftovers(&entries))
+        .filter_map(|(e, r)| r.err().map(|err| format!("{}: {err:#}", e.path.display())))
+        .collect();
+    if errors.is_empty() {
+        app.message = format!("Deleted {} leftover files", entries.len());
+    } else {
+        app.last_error = Some(errors.join("\n"));
+        app.mode = Mode::Error;
+    }
+}
+
+fn start_reference_input(app: &mut App) {
+    if app.pick_state.selected().is_none() {
+        app.message = "No candidate highlighted".to_string();
+        return;
+    }
+    app.input.clear();
+    app.mode = Mode::RefPath;
+}
+
+/// Diffs the highlighted candidate against the path typed into the reference prompt.
+fn launch_reference_compare(app: &mut App) -> Result<()> {
+    app.compare_error = None;
+    app.mode = Mode::Pick;
+
+    let reference = expand_user_path(&app.input, &app.root);
+    if !reference.is_file() {
+        app.message = format!("Reference not found: {}", reference.display());
+        return Ok(());
+    }
+    let candidate = app
+        .list_state
+        .selected()
+        .and_then(|gi| app.groups.get(gi))
+        .zip(app.pick_state.selected())
+        .and_then(|(g, ci)| g.row(ci))
+        .map(|c| c.path.clone())
+        .ok_or_else(|| anyhow!("no candidate"))?;
+
+    run_compare(app, &candidate, &reference)
+}
+
+/// Diffs two text files into the diff pane with `app.diff_options`; `false` when they
+/// don't differ under those options.
+fn show_text_diff(app: &mut App, left: &Path, right: &Path) -> bool {
+    let max = app.diff_options.max_size;
+    let (Some(old), Some(new)) = (read_text(left, max), read_text(right, max)) else {
+        return false;
+    };
+    let names = (
+        rel_path(&app.root, left).display().to_string(),
+        rel_path(&app.root, right).display().to_string(),
+    );
+    let Some(diff) = unified_diff(&old, &new, (&names.0, &names.1), &app.diff_options) else {
+        return false;
+    };
+    show_diff(app, left, right, &diff);
+    app.preview_title
+        .push_str(&format!(" [{}]", app.diff_options.describe()));
+    app.diff_paths = Some((left.to_path_buf(), right.to_path_buf()));
+    true
+}
+
+/// Changes the diff options from the diff pane and diffs the same files again, keeping
+/// the scroll position.
+fn change_diff_options(app: &mut App, change: impl FnOnce(&mut DiffOptions)) {
+    let Some((left, right)) = app.diff_paths.clone() else {
+        app.message = "Diff options apply to the built-in diff (d)".to_string();
+        return;
+    };
+    change(&mut app.diff_options);
+    if is_large(&app.diff_options, &left) || is_large(&app.diff_options, &right) {
+        let from = app.preview_from;
+        app.mode = from;
+        start_diff_job(app, &left, &right);
+        return;
+    }
+    let (from, scroll) = (app.preview_from, app.preview_scroll);
+    if !show_text_diff(app, &left, &right) {
+        app.preview = vec![Line::from(Span::styled(
+            "No differences",
+            Theme::default().header_meta,
+        ))];
+        app.preview_title = format!("diff [{}]", app.diff_options.describe());
+    }
+    app.mode = Mode::Preview;
+    app.preview_from = from;
+    app.preview_scroll = scroll.min(app.preview.len().saturating_sub(1));
+    app.message = format!("Diff: {}", app.diff_options.describe());
+}
+
+/// An on-demand diff of files too big to diff on the spot.
+struct DiffJob {
+    rx: Receiver<DiffEvent>,
+    cancel: Arc<AtomicBool>,
+    left: PathBuf,
+    right: PathBuf,
+    /// The view it was started from; the diff opens only if that view is still up.
+    from: Mode,
+    /// What the worker is doing, for the status line.
+    status: String,
+    ticks: usize,
+}
+
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Whether `path` is over `[diff] max_size`, so it is only diffed on request.
+fn is_large(options: &DiffOptions, path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.len() > options.max_size)
+}
+
+/// Starts diffing two big files on a worker thread, unless together they exceed
+/// `[diff] memory_cap`.
+fn start_diff_job(app: &mut App, left: &Path, right: &Path) {
+    let total: u64 = [left, right]
+        .iter()
+        .map(|p| fs::metadata(p).map_or(0, |m| m.len()))
+        .sum();
+    if total > app.diff_options.memory_cap {
+        let msg = format!(
+            "Not diffing: the files add up to {}, over [diff] memory_cap ({})",
+            format_size(total),
+            format_size(app.diff_options.memory_cap)
+        );
+        app.compare_error = Some(msg.clone());
+        app.last_error = Some(msg);
+        app.message = "Compare".to_string();
+        app.mode = Mode::Error;
+        return;
+    }
+    let names = (
+        rel_path(&app.root, left).display().to_string(),
+        rel_path(&app.root, right).display().to_string(),
+    );
+    let (rx, cancel) = spawn_unified_diff(
+        left.to_path_buf(),
+        right.to_path_buf(),
+        names,
+        app.diff_options,
+    );
+    app.diff_job = Some(DiffJob {
+        rx,
+        cancel,
+        left: left.to_path_buf(),
+        right: right.to_path_buf(),
+        from: app.mode,
+        status: format!("reading 0 B of {}", format_size(total)),
+        ticks: 0,
+    });
+    poll_diff_job(app);
+}
+
+/// Takes the diff worker's progress into the status line, and opens the diff pane once
+/// it is done.
+fn poll_diff_job(app: &mut App) {
+    let Some(job) = app.diff_job.as_mut() else {
+        return;
+    };
+    let mut done = None;
+    for event in job.rx.try_iter() {
+        match event {
+            DiffEvent::Read { done, total } => {
+                job.status = format!(
+                    "reading {} of {} ({}%)",
+                    format_size(done),
+                    format_size(total),
+                    done * 100 / total.max(1)
+                );
+            }
+            DiffEvent::Comparing => job.status = "comparing lines".to_string(),
+            DiffEvent::Done(result) => done = Some(result),
+        }
+    }
+    let Some(result) = done else {
+        job.ticks += 1;
+        app.message = format!(
+            "{} Diffing {}: {} | d cancels",
+            SPINNER[job.ticks % SPINNER.len()],
+            rel_path(&app.root, &job.right).display(),
+            job.status
+        );
+        return;
+    };
+    let Some(job) = app.diff_job.take() else {
+        return;
+    };
+    if app.mode != job.from {
+        app.message = "Diff finished after leaving the view; d runs it again".to_string();
+        return;
+    }
+    match result {
+        Ok(Some(diff)) => {
+            show_diff(app, &job.left, &job.right, &diff);
+            app.preview_title
+                .push_str(&format!(" [{}]", app.diff_options.describe()));
+            app.diff_paths = Some((job.left, job.right));
+            app.message = "Compare done".to_string();
+        }
+        Ok(None) => {
+            app.compare_error = Some("No differences".to_string());
+            app.last_error = app.compare_error.clone();
+            app.message = "Compare".to_string();
+            app.mode = Mode::Error;
+        }
+        Err(e) => {
+            app.compare_error = Some(e.clone());
+            app.last_error = Some(e);
+            app.message = "Compare failed".to_string();
+            app.mode = Mode::Error;
+        }
+    }
+}
+
+fn run_compare(app: &mut App, left: &Path, right: &Path) -> Result<()> {
+    // Text files are diffed here, with the `[diff]` options; anything else (and every
+    // file on Windows, where `fc` stands in) goes to the `diff` command. Files over
+    // `max_size` are diffed on a worker thread so the TUI stays responsive.
+    if is_large(&app.diff_options, left) || is_large(&app.diff_options, right) {
+        start_diff_job(app, left, right);
+        return Ok(());
+    }
+    let max = app.diff_options.max_size;
+    if read_text(left, max).is_some() && read_text(right, max).is_some() {
+        if show_text_diff(app, left, right) {
+            app.message = "Compare done".to_string();
+        } else {
+            let mut msg = "No differences".to_string();
+            if app.diff_options.ignore_whitespace {
+                msg.push_str(" (ignoring whitespace)");
+            }
+            app.compare_error = Some(msg.clone());
+            app.last_error = Some(msg);
+            app.message = "Compare".to_string();
+            app.mode = Mode::Error;
+        }
+        return Ok(());
+    }
+    let res = restore_terminal_for_child(app, || -> Result<String> {
+        let output = compare_command(left, right).output().context("run diff")?;
+
+        let mut combined = String::new();
+        combined.push_str(&String::from_utf8_lossy(&output.stdout));
+        combined.push_str(&String::from_utf8_lossy(&output.stderr));
+        let combined = combined.trim().to_string();
+
+        // diff exit codes:
+        //   0 = no differences
+        //   1 = differences found
+        //  >1 = error
+        match output.status.code() {
+            Some(0) => Err(anyhow!("No differences")),
+            // `fc` output isn't a unified diff; it goes to the error panel as is.
+            Some(1) if !combined.is_empty() && !cfg!(target_os = "windows") => Ok(combined),
+            Some(1) => Err(anyhow!(if combined.is_empty() {
+                "files differ".to_string()
+            } else {
+                combined
+            })),
+            _ => Err(anyhow!(
+                "diff exited with {}{}{}",
+                output.status,
+                if combined.is_empty() { "" } else { "\n\n" },
+                combined
+            )),
+        }
+    })?;
+
+    match res {
+        Ok(diff) => {
+            show_diff(app, left, right, &diff);
+            app.diff_paths = None;
+            app.message = "Compare done".to_string();
+        }
+        Err(e) => {
+            let msg = format!("{e:#}");
+            app.compare_error = Some(msg.clone());
+            app.last_error = Some(msg);
+            app.message = "Compare".to_string();
+            app.mode = Mode::Error;
+        }
+    }
+    Ok(())
+}
+
+/// Hands the terminal to a child process for the duration of `f`, then takes it back.
+fn restore_terminal_for_child<F, T>(app: &mut App, f: F) -> Result<T>
+where
+    F: FnOnce() -> T,
+{
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+
+    let res = f();
+
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+    // The child may have drawn over the alternate screen; force a full repaint.
+    app.needs_clear = true;
+
+    Ok(res)
+}
+
+/// The viewer command line and where it came from: the config, `$VISUAL`, `$EDITOR`, or
+/// the platform default.
+pub(crate) fn viewer_line(
+    viewer: Option<&str>,
+    env: impl Fn(&str) -> Option<String>,
+) -> (String, Source) {
+    let configured = viewer
+        .map(|v| (v.to_string(), Source::Config))
+        .or_else(|| env("VISUAL").map(|v| (v, Source::Env("VISUAL"))))
+        .or_else(|| env("EDITOR").map(|v| (v, Source::Env("EDITOR"))))
+        .filter(|(v, _)| !v.trim().is_empty());
+    configured.unwrap_or_else(|| {
+        let line = if cfg!(target_os = "windows") {
+            "notepad"
+        } else {
+            "less"
+        };
+        (line.to_string(), Source::Default)
+    })
+}
+
+fn viewer_command(viewer: Option<&str>, path: &Path) -> Command {
+    let (line, _) = viewer_line(viewer, |var| std::env::var(var).ok());
+    let mut parts = line.split_whitespace();
+    let mut c = Command::new(parts.next().unwrap_or("less"));
+    c.args(parts).arg(path);
+    c
+}
+
+/// Opens the highlighted candidate in the configured viewer, `$VISUAL`, or `$EDITOR`.
+fn open_in_viewer(app: &mut App) -> Result<()> {
+    if app.demo {
+        // An editor could create the file on save.
+        app.message = "Demo mode: nothing to open".to_string();
+        return Ok(());
+    }
+    let Some(path) = app
+        .list_state
+        .selected()
+        .and_then(|gi| app.groups.get(gi))
+        .zip(app.pick_state.selected())
+        .and_then(|(g, ci)| g.row(ci))
+        .filter(|c| c.exists)
+        .map(|c| c.path.clone())
+    else {
+        app.message = "No existing candidate highlighted".to_string();
+        return Ok(());
+    };
+
+    let mut cmd = viewer_command(app.viewer.as_deref(), &path);
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let status = restore_terminal_for_child(app, || cmd.status())?;
+    match status {
+        Ok(st) if st.success() => app.message = format!("Closed {program}"),
+        Ok(st) => app.message = format!("{program} exited with {st}"),
+        Err(e) => {
+            app.last_error = Some(format!("run {program}: {e}"));
+            app.message = "Viewer failed".to_string();
+            app.mode = Mode::Error;
+        }
+    }
+    Ok(())
+}
+
+/// Below this size the header and footer shrink to one line each.
+const COMPACT_WIDTH: u16 = 80;
+const COMPACT_HEIGHT: u16 = 24;
+/// Below this size nothing usable fits; only a notice is drawn.
+const MIN_WIDTH: u16 = 30;
+const MIN_HEIGHT: u16 = 8;
+
+/// The header's key help for the current mode, from the actual bindings.
+fn help_line(app: &App) -> String {
+    let keys = &app.keymap;
+    let k = |scope, name| keys.key(scope, name);
+    let (up, down) = (k(Scope::Global, "up"), k(Scope::Global, "down"));
+    match app.mode {
+        Mode::List if app.tree_view => format!(
+            "Tree: {up}/{down} | {} fold folder / pick | {}/{} fold | {} select (whole folder on a folder) | {} flat list | {}/{}/{}/{} quick pick | {}/{} confirm | {} quit",
+            k(Scope::List, "pick"),
+            k(Scope::List, "fold"),
+            k(Scope::List, "unfold"),
+            k(Scope::List, "select"),
+            k(Scope::List, "tree"),
+            k(Scope::List, "current"),
+            k(Scope::List, "newest"),
+            k(Scope::List, "oldest"),
+            k(Scope::List, "trusted"),
+            k(Scope::List, "confirm"),
+            k(Scope::List, "confirm_selected"),
+            k(Scope::List, "quit"),
+        ),
+        Mode::List => format!(
+            "{} (second keys act on the selected groups)",
+            keys.help("List", Scope::List, true, &[])
+        ),
+        Mode::Pick => keys.help("Pick", Scope::Pick, true, &[]),
+        Mode::RefPath => "Reference: type a path | Enter diff highlighted against it | Esc back".to_string(),
+        Mode::PruneAge => "Prune archives: type an age (90d, 12h, 2w) | Enter preview | Esc back".to_string(),
+        Mode::SnoozeFor => "Snooze: type how long (3d, 12h, 2w) | Enter snooze | Esc back".to_string(),
+        Mode::Prune => keys.help("Prune archives", Scope::Prune, false, &[]),
+        Mode::Cleanup => keys.help("Cleanup", Scope::Cleanup, false, &[]),
+        Mode::Timeline => keys.help("Timeline", Scope::Timeline, true, &[]),
+        Mode::Detail => keys.help("Detail", Scope::Detail, true, &[]),
+        Mode::Preview if app.diff_paths.is_some() => format!(
+            "Diff: {up}/{down}/{}/{} scroll | {}",
+            k(Scope::Global, "page_up"),
+            k(Scope::Global, "page_down"),
+            keys.help("", Scope::Preview, false, &[]).trim_start_matches(": "),
+        ),
+        Mode::Preview => format!(
+            "Preview: {up}/{down}/{}/{} scroll | {}/{} top/bottom | {} back",
+            k(Scope::Global, "page_up"),
+            k(Scope::Global, "page_down"),
+            k(Scope::Global, "top"),
+            k(Scope::Global, "bottom"),
+            keys.keys(Scope::Preview, "close"),
+        ),
+        Mode::Merge => keys.help("Merge", Scope::Merge, true, &[]),
+        Mode::Log => keys.help("Plan / Log", Scope::Log, true, &[]),
+        Mode::Help => format!(
+            "Keys: {up}/{down}/{}/{} scroll | {} back",
+            k(Scope::Global, "page_up"),
+            k(Scope::Global, "page_down"),
+            keys.keys(Scope::Help, "close"),
+        ),
+        Mode::LogSearch => "Search log: type text (any case) | Enter find | Esc back".to_string(),
+        Mode::Columns => keys.help("Columns", Scope::Columns, true, &[]),
+        Mode::Stats => keys.help("Space", Scope::Stats, false, &[]),
+        Mode::Origins => keys.help("Conflict origins", Scope::Origins, false, &[]),
+        Mode::Filter => "Filter: type a limit | Up/Down/Tab field | Enter apply (all empty clears) | Esc cancel".to_string(),
+        Mode::RuleEdit => "Rule: type a glob (*.jpg, photos/**/*.raw; a leading / anchors at the root) | Tab field | Left/Right strategy | Enter save to config | Esc cancel".to_string(),
+        Mode::Confirm if app.dir_batch.is_some() => {
+            keys.help("Confirm directory", Scope::Confirm, false, &[])
+        }
+        Mode::Confirm => keys.help("Confirm", Scope::Confirm, false, &["skip_dir"]),
+        Mode::PlanOut => "Export plan: type a path (.json for JSON) | Enter write | Esc back".to_string(),
+        Mode::Apply if app.apply_run.as_ref().is_some_and(ApplyRun::running) => {
+            keys.help("Apply", Scope::Apply, true, &["retry", "retry_all", "close"])
+        }
+        Mode::Apply => keys.help("Apply", Scope::Apply, true, &["cancel"]),
+        Mode::Error => keys.help("Error", Scope::Error, false, &[]),
+        Mode::Done => "Done".to_string(),
+    }
+}
+
+fn ui(f: &mut ratatui::Frame, app: &mut App) {
+    let theme = Theme::default();
+    app.graphics_target = None;
+
+    let area = f.area();
+    if area.width < MIN_WIDTH || area.height < MIN_HEIGHT {
+        let notice = Paragraph::new(format!(
+            "Terminal too small ({}x{}); need {MIN_WIDTH}x{MIN_HEIGHT}. q quits.",
+            area.width, area.height
+        ))
+        .style(theme.message_warn)
+        .wrap(Wrap { trim: true });
+        f.render_widget(notice, area);
+        return;
+    }
+    let compact = area.width < COMPACT_WIDTH || area.height < COMPACT_HEIGHT;
+    let (header_rows, footer_rows) = if compact { (1, 1) } else { (4, 5) };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(header_rows),
+            Constraint::Min(0),
+            Constraint::Length(footer_rows),
+        ])
+        .split(area);
+
+    let root_badge = if running_as_root() {
+        Span::styled(" ROOT ", theme.badge_root)
+    } else {
+        Span::raw("")
+    };
+    let mode_badge = Span::styled(
+        if app.apply { " APPLY " } else { " DRY-RUN " },
+        if app.apply {
+            theme.badge_apply
+        } else {
+            theme.badge_dry_run
+        },
+    );
+
+    let help = help_line(app);
+
+    let root_str = app.root.display().to_string();
+    let root_max = (area.width as usize).saturating_sub(26).max(20);
+    let root_short = shorten_middle(&root_str, root_max);
+
+    let selected = app.selected_groups.len();
+    let chosen = app.groups.iter().filter(|g| g.chosen.is_some()).count();
+    let mut counts = format!(
+        "groups:{}  picked:{}  selected:{}",
+        app.groups.len(),
+        chosen,
+        selected
+    );
+    if app.visual.is_some() {
+        counts.push_str("  VISUAL");
+    }
+    if app.demo {
+        counts.push_str("  DEMO");
+    }
+    if !app.filter.is_empty() {
+        counts.push_str(&format!("  filter: {}", app.filter.describe()));
+    }
+    let space = space_summary(&app.groups, &app.options);
+    counts.push_str(&format!(
+        "  conflicts: {}  plan frees {}, archives {}",
+        format_size(space.conflict_bytes),
+        format_size(space.freed_bytes),
+        format_size(space.archived_bytes)
+    ));
+    if app.watcher.is_some() {
+        counts.push_str(&format!("  watching (new:{})", app.new_groups.len()));
+    }
+
+    let header = if compact {
+        // Help moves to the footer line while no message is shown.
+        Paragraph::new(Line::from(vec![
+            mode_badge,
+            root_badge,
+            Span::styled(
+                format!(" {chosen}/{} picked  {selected} sel", app.groups.len()),
+                theme.header_meta,
+            ),
+        ]))
+    } else {
+        Paragraph::new(vec![
+            Line::from(vec![
+                Span::styled("synctui-resolver", theme.header_title),
+                Span::raw("  "),
+                mode_badge,
+                Span::raw(" "),
+                root_badge,
+                Span::raw("  "),
+                Span::styled(format!("root: {root_short}"), theme.header_meta),
+                Span::styled(
+                    format!("  {} all keys", app.keymap.key(Scope::Global, "help")),
+                    theme.header_help,
+                ),
+            ]),
+            Line::from(Span::styled(counts, theme.header_meta)),
+            Line::from(Span::styled(help.as_str(), theme.header_help)),
+        ])
+        .wrap(Wrap { trim: true })
+    };
+    f.render_widget(header, chunks[0]);
+
+    match app.mode {
+        Mode::List
+        | Mode::Confirm
+        | Mode::Done
+        | Mode::Error
+        | Mode::Columns
+        | Mode::Stats
+        | Mode::Origins
+        | Mode::Filter
+        | Mode::RuleEdit
+        | Mode::PruneAge
+        | Mode::SnoozeFor
+        | Mode::Prune
+        | Mode::Cleanup
+        | Mode::PlanOut => draw_list(f, app, chunks[1], theme),
+        Mode::Pick | Mode::RefPath => draw_pick(f, app, chunks[1], theme),
+        Mode::Apply => draw_apply(f, app, chunks[1], theme),
+        Mode::Timeline => draw_timeline(f, app, chunks[1], theme),
+        Mode::Detail => match app.detail_from {
+            Mode::Pick => draw_pick(f, app, chunks[1], theme),
+            _ => draw_list(f, app, chunks[1], theme),
+        },
+        Mode::Preview => draw_preview(f, app, chunks[1], theme),
+        Mode::Merge => draw_merge(f, app, chunks[1], theme),
+        Mode::Log | Mode::LogSearch => draw_log(f, app, chunks[1], theme),
+        // Drawn over the whole screen below.
+        Mode::Help => {}
+    }
+
+    // Minus the block borders.
+    app.page_rows = usize::from(chunks[1].height.saturating_sub(2));
+    if compact {
+        let line = if app.message.is_empty() {
+            Span::styled(help.as_str(), theme.header_help)
+        } else {
+            Span::styled(app.message.as_str(), message_style(&app.message, theme))
+        };
+        f.render_widget(Paragraph::new(Line::from(line)), chunks[2]);
+    } else {
+        draw_footer(f, app, chunks[2], theme);
+    }
+
+    if matches!(app.mode, Mode::Confirm | Mode::PlanOut) {
+        draw_confirm_modal(f, app, chunks[1], theme);
+    }
+
+    if app.mode == Mode::Help {
+        draw_help(f, app, area, theme);
+        return;
+    }
+
+    if app.mode == Mode::LogSearch {
+        draw_input_modal(
+            f,
+            app,
+            chunks[1],
+            theme,
+            "Search log",
+            "Text to find in the plan, any case:",
+        );
+    }
+
+    if app.mode == Mode::RefPath {
+        draw_input_modal(
+            f,
+            app,
+            chunks[1],
+            theme,
+            "Reference file",
+            "Diff highlighted candidate against (absolute, ~/..., or relative to root):",
+        );
+    }
+
+    if app.mode == Mode::PlanOut {
+        draw_input_modal(
+            f,
+            app,
+            chunks[1],
+            theme,
+            "Export plan",
+            "Write the full operation list to (relative to the current directory):",
+        );
+    }
+
+    if app.mode == Mode::SnoozeFor {
+        draw_input_modal(
+            f,
+            app,
+            chunks[1],
+            theme,
+            "Snooze",
+            "Hide the selected groups (or the highlighted one) for (e.g. 3d, 12h, 2w):",
+        );
+    }
+
+    if app.mode == Mode::PruneAge {
+        draw_input_modal(
+            f,
+            app,
+            chunks[1],
+            theme,
+            "Prune archives",
+            "Delete archived files older than (e.g. 90d, 12h, 2w):",
+        );
+    }
+
+    if app.mode == Mode::Cleanup {
+        draw_cleanup_modal(f, app, chunks[1], theme);
+    }
+
+    if app.mode == Mode::Prune {
+        draw_prune_modal(f, app, chunks[1], theme);
+    }
+
+    if app.mode == Mode::Detail {
+        draw_detail_modal(f, app, chunks[1], theme);
+    }
+
+    if app.mode == Mode::Columns {
+        draw_columns_modal(f, app, chunks[1], theme);
+    }
+
+    if app.mode == Mode::Stats {
+        draw_stats_modal(f, app, chunks[1], theme);
+    }
+
+    if app.mode == Mode::Origins {
+        draw_origins_modal(f, app, chunks[1], theme);
+    }
+
+    if app.mode == Mode::Filter {
+        draw_filter_modal(f, app, chunks[1], theme);
+    }
+
+    if app.mode == Mode::RuleEdit {
+        draw_rule_modal(f, app, chunks[1], theme);
+    }
+
+    if app.mode == Mode::Error {
+        draw_error_modal(f, app, chunks[1], theme);
+    }
+
+    draw_tutorial(f, app, area, theme);
+}
+
+/// The tutorial's current step in the bottom right corner, over the footer.
+fn draw_tutorial(f: &mut ratatui::Frame, app: &App, area: Rect, theme: Theme) {
+    let Some(step) = app.tutorial else {
+        return;
+    };
+    let text = step.prompt(&app.keymap);
+    let width = area.width.min(56);
+    let rows = wrapped_rows(&text, usize::from(width.saturating_sub(2))) as u16 + 2;
+    let height = rows.min(area.height);
+    let rect = Rect::new(
+        area.x + area.width - width,
+        area.y + area.height - height,
+        width,
+        height,
+    );
+    f.render_widget(Clear, rect);
+    let heading = step.heading();
+    let p = Paragraph::new(Span::styled(text, theme.message_info))
+        .block(titled_block(&heading, theme))
+        .wrap(Wrap { trim: true });
+    f.render_widget(p, rect);
+}
+
+/// Rows `text` takes when word-wrapped to `width` columns.
+fn wrapped_rows(text: &str, width: usize) -> usize {
+    let mut rows = 1;
+    let mut used = 0;
+    for word in text.split_whitespace() {
+        let len = word.chars().count();
+        if used > 0 && used + 1 + len > width {
+            rows += 1;
+            used = len;
+        } else {
+            used += len + usize::from(used > 0);
+        }
+    }
+    rows
+}
+
+fn shorten_middle(s: &str, max_chars: usize) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    if chars.len() <= max_chars {
+        return s.to_string();
+    }
+    if max_chars <= 5 {
+        return chars.into_iter().take(max_chars).collect();
+    }
+
+    let keep = (max_chars - 3) / 2;
+    let head: String = chars.iter().take(keep).collect();
+    let tail: String = chars
+        .iter()
+        .rev()
+        .take(keep)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    format!("{head}...{tail}")
+}
+
+/// A `percent_x` by `percent_y` rectangle centered in `r`, grown to at least 40x8 (or all
+/// of `r`) so popups stay usable in small terminals.
+fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let min_w = 40u16.min(r.width);
+    let min_h = 8u16.min(r.height);
+    if r.width * percent_x / 100 < min_w || r.height * percent_y / 100 < min_h {
+        let w = (r.width * percent_x / 100).max(min_w);
+        let h = (r.height * percent_y / 100).max(min_h);
+        return Rect::new(r.x + (r.width - w) / 2, r.y + (r.height - h) / 2, w, h);
+    }
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(popup_layout[1])[1]
+}
+
+fn draw_error_modal(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let rect = centered_rect(80, 50, area);
+    f.render_widget(Clear, rect);
+
+    let mut lines = Vec::new();
+    lines.push(Line::from(Span::styled(
+        "ERROR",
+        theme.message_error.add_modifier(Modifier::BOLD),
+    )));
+    lines.push(Line::from(""));
+
+    if let Some(e) = &app.last_error {
+        for l in e.lines().take((rect.height as usize).saturating_sub(6)) {
+            lines.push(Line::from(l));
+        }
+    } else {
+        lines.push(Line::from("Unknown error"));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::styled("Esc", theme.header_title),
+        Span::styled(": dismiss   ", theme.header_meta),
+        Span::styled("Enter", theme.header_title),
+        Span::styled(": dismiss   ", theme.header_meta),
+        Span::styled("q", theme.header_title),
+        Span::styled(": dismiss", theme.header_meta),
+    ]));
+
+    let p = Paragraph::new(lines)
+        .block(titled_block("", theme))
+        .wrap(Wrap { trim: false });
+    f.render_widget(p, rect);
+}
+
+fn draw_columns_modal(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let rect = centered_rect(60, 50, area);
+    f.render_widget(Clear, rect);
+    let items: Vec<ListItem> = Column::ALL
+        .iter()
+        .map(|c| {
+            let (mark, style) = if app.columns.contains(c) {
+                ("[x] ", theme.selected_mark)
+            } else {
+                ("[ ] ", theme.header_meta)
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(mark, style),
+                Span::raw(c.label()),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(titled_block("Columns", theme))
+        .highlight_style(theme.list_highlight);
+    f.render_stateful_widget(list, rect, &mut app.columns_state);
+}
+
+fn draw_filter_modal(f: &mut ratatui::Frame, app: &App, area: Rect, theme: Theme) {
+    let rect = centered_rect(70, 40, area);
+    f.render_widget(Clear, rect);
+    let labels = [
+        "Older than (e.g. 30d)",
+        "Newer than (e.g. 7d) ",
+        "Min size (e.g. 1M)   ",
+        "Type (e.g. image)    ",
+    ];
+    let mut lines = vec![
+        Line::from(Span::styled(
+            "Show groups with a conflict matching every limit (empty = no limit):",
+            theme.header_meta,
+        )),
+        Line::from(""),
+    ];
+    for (i, label) in labels.iter().enumerate() {
+        let active = i == app.filter_field;
+        let mut spans = vec![
+            Span::styled(if active { "> " } else { "  " }, theme.header_title),
+            Span::styled(*label, theme.header_meta),
+            Span::raw("  "),
+            Span::raw(app.filter_inputs[i].clone()),
+        ];
+        if active {
+            spans.push(Span::styled("_", theme.header_help));
+        }
+        lines.push(Line::from(spans));
+    }
+    let p = Paragraph::new(lines)
+        .block(titled_block("Filter", theme))
+        .wrap(Wrap { trim: false });
+    f.render_widget(p, rect);
+}
+
+fn draw_rule_modal(f: &mut ratatui::Frame, app: &App, area: Rect, theme: Theme) {
+    let rect = centered_rect(70, 40, area);
+    f.render_widget(Clear, rect);
+    let saved_to = app
+        .config_path
+        .as_ref()
+        .map_or("(no config file)".to_string(), |p| p.display().to_string());
+    let matching = rule_from_popup(app).map_or(0, |r| {
+        app.groups
+            .iter()
+            .filter(|g| r.matches(rel_path(&app.root, &g.base_path)))
+            .count()
+    });
+    let field = |i: usize, label: &'static str, value: String| {
+        let active = i == app.rule_field;
+        let mut spans = vec![
+            Span::styled(if active { "> " } else { "  " }, theme.header_title),
+            Span::styled(label, theme.header_meta),
+            Span::raw("  "),
+            Span::raw(value),
+        ];
+        if active && i == 0 {
+            spans.push(Span::styled("_", theme.header_help));
+        }
+        Line::from(spans)
+    };
+    let lines = vec![
+        Line::from(Span::styled(
+            "Always pick this way for unpicked groups matching the glob:",
+            theme.header_meta,
+        )),
+        Line::from(""),
+        field(0, "Glob    ", app.input.clone()),
+        field(1, "Strategy", format!("< {} >", app.rule_strategy.label())),
+        Line::from(""),
+        Line::from(Span::styled(
+            format!("Matches {matching} of {} groups here", app.groups.len()),
+            theme.header_meta,
+        )),
+        Line::from(Span::styled(
+            format!("Saved as [[pick_rules]] in {saved_to}"),
+            theme.header_help,
+        )),
+    ];
+    let p = Paragraph::new(lines)
+        .block(titled_block("Pick rule", theme))
+        .wrap(Wrap { trim: false });
+    f.render_widget(p, rect);
+}
+
+fn draw_stats_modal(f: &mut ratatui::Frame, app: &App, area: Rect, theme: Theme) {
+    let rect = centered_rect(80, 70, area);
+    f.render_widget(Clear, rect);
+    let total = space_summary(&app.groups, &app.options);
+    let dirs = space_by_top_dir(&app.root, &app.groups, &app.options);
+    let width = dirs
+        .iter()
+        .map(|(d, _)| d.display().to_string().chars().count())
+        .chain([7])
+        .max()
+        .unwrap_or(7);
+    let row = |name: &str, s: &SpaceSummary| {
+        format!(
+            "{name:<width$}  {:>5}  {:>10}  {:>10}  {:>10}",
+            s.conflict_files,
+            format_size(s.conflict_bytes),
+            format_size(s.freed_bytes),
+            format_size(s.archived_bytes)
+        )
+    };
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!(
+                "{:<width$}  {:>5}  {:>10}  {:>10}  {:>10}",
+                "", "Files", "Conflicts", "Frees", "Archives"
+            ),
+            theme.block_title,
+        )),
+        Line::from(Span::styled(row("Overall", &total), theme.message_info)),
+    ];
+    if dirs.len() > 1 {
+        lines.extend(
+            dirs.iter()
+                .map(|(d, s)| Line::from(row(&d.display().to_string(), s))),
+        );
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "Frees: deleted by a loser rule or sent to a remote archive. Archives: moved into \
+         .stconflict-archive, still on this disk. Only picked groups count.",
+        theme.header_help,
+    )));
+    let p = Paragraph::new(lines)
+        .block(titled_block("Space", theme))
+        .wrap(Wrap { trim: false });
+    f.render_widget(p, rect);
+}
+
+fn draw_origins_modal(f: &mut ratatui::Frame, app: &App, area: Rect, theme: Theme) {
+    let rect = centered_rect(80, 90, area);
+    f.render_widget(Clear, rect);
+    let stats = conflict_stats(
+        &app.root,
+        &app.groups,
+        &app.device_aliases,
+        SystemTime::now(),
+    );
+    let lines: Vec<Line> = stats
+        .lines(5)
+        .into_iter()
+        .map(|l| {
+            if l.starts_with("By ") {
+                Line::from(Span::styled(l, theme.block_title))
+            } else {
+                Line::from(l)
+            }
+        })
+        .collect();
+    let p = Paragraph::new(lines).block(titled_block("Conflict origins", theme));
+    f.render_widget(p, rect);
+}
+
+fn draw_detail_modal(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let Some(g) = app.list_state.selected().and_then(|gi| app.groups.get(gi)) else {
+        return;
+    };
+    let rect = centered_rect(90, 90, area);
+    f.render_widget(Clear, rect);
+
+    let lines: Vec<Line> = detail_lines(
+        g,
+        &app.detail_hashes,
+        app.detail_hashing.is_some(),
+        &app.detail_blocks,
+        app.detail_provenance.as_ref(),
+        &app.device_aliases,
+    )
+    .into_iter()
+    .map(|l| {
+        if l.starts_with('[') || l.starts_with("Content") || l.starts_with("Syncthing") {
+            Line::from(Span::styled(l, theme.message_info))
+        } else {
+            Line::from(l)
+        }
+    })
+    .collect();
+    let title = format!("Detail: {}", rel_path(&app.root, &g.base_path).display());
+    let p = Paragraph::new(lines)
+        .block(titled_block(&title, theme))
+        .wrap(Wrap { trim: false })
+        .scroll((app.detail_scroll, 0));
+    f.render_widget(p, rect);
+}
+
+fn draw_input_modal(
+    f: &mut ratatui::Frame,
+    app: &mut App,
+    area: Rect,
+    theme: Theme,
+    title: &str,
+    prompt: &str,
+) {
+    let rect = centered_rect(80, 20, area);
+    f.render_widget(Clear, rect);
+
+    let lines = vec![
+        Line::from(Span::styled(prompt.to_string(), theme.header_meta)),
+        Line::from(vec![
+            Span::styled("> ", theme.header_title),
+            Span::raw(app.input.as_str()),
+            Span::styled("_", theme.header_help),
+        ]),
+    ];
+
+    let p = Paragraph::new(lines)
+        .block(titled_block(title, theme))
+        .wrap(Wrap { trim: false });
+    f.render_widget(p, rect);
+}
+
+fn draw_cleanup_modal(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let rect = centered_rect(80, 70, area);
+    f.render_widget(Clear, rect);
+
+    let total: u64 = app.leftovers.iter().map(|e| e.size).sum();
+    let mut lines = vec![
+        if app.apply {
+            Line::from(Span::styled(
+                "This will delete every file below.",
+                theme.message_error.add_modifier(Modifier::BOLD),
+            ))
+        } else {
+            Line::from(Span::styled(
+                "Dry-run: no filesystem changes.",
+                theme.message_warn,
+            ))
+        },
+        Line::from(format!(
+            "{} leftover files, {}",
+            app.leftovers.len(),
+            format_size(total)
+        )),
+    ];
+    let mut kind = None;
+    for e in &app.leftovers {
+        if kind != Some(e.kind) {
+            kind = Some(e.kind);
+            let count = app.leftovers.iter().filter(|o| o.kind == e.kind).count();
+            let title = match e.kind {
+                LeftoverKind::TempFile => "Syncthing temp files",
+                LeftoverKind::VersionedConflict => "Conflicts inside .stversions",
+                LeftoverKind::ArchivedConflict => "Conflicts inside .stconflict-archive",
+            };
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(
+                format!("{title} ({count})"),
+                theme.header_title,
+            )));
+        }
+        lines.push(Line::from(format!(
+            "  {:>10}  {}",
+            format_size(e.size),
+            rel_path(&app.root, &e.path).display()
+        )));
+    }
+
+    let p = Paragraph::new(lines)
+        .block(titled_block("Cleanup", theme))
+        .wrap(Wrap { trim: false });
+    f.render_widget(p, rect);
+}
+
+fn draw_prune_modal(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let rect = centered_rect(80, 70, area);
+    f.render_widget(Clear, rect);
+
+    let total: u64 = app.prune_preview.iter().map(|e| e.size).sum();
+    let mut lines = vec![
+        if app.apply {
+            Line::from(Span::styled(
+                "This will delete archived files.",
+                theme.message_error.add_modifier(Modifier::BOLD),
+            ))
+        } else {
+            Line::from(Span::styled(
+                "Dry-run: no filesystem changes.",
+                theme.message_warn,
+            ))
+        },
+        Line::from(format!(
+            "{} archived files, {} (older than {})",
+            app.prune_preview.len(),
+            format_size(total),
+            app.input.trim()
+        )),
+        Line::from(""),
+    ];
+    for e in &app.prune_preview {
+        lines.push(Line::from(vec![
+            Span::styled(format_time(e.archived_at), theme.header_meta),
+            Span::raw(format!(
+                "  {:>10}  {}",
+                format_size(e.size),
+                rel_path(&app.root, &e.path).display()
+            )),
+        ]));
+    }
+
+    let p = Paragraph::new(lines)
+        .block(titled_block("Prune archives", theme))
+        .wrap(Wrap { trim: false });
+    f.render_widget(p, rect);
+}
+
+fn draw_confirm_modal(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let rect = centered_rect(80, 70, area);
+    f.render_widget(Clear, rect);
+
+    let title = if app.apply {
+        Span::styled("CONFIRM APPLY", theme.confirm_title_apply)
+    } else {
+        Span::styled("CONFIRM DRY-RUN", theme.confirm_title_dry_run)
+    };
+
+    let mut lines = Vec::new();
+    if app.apply {
+        lines.push(Line::from(Span::styled(
+            "This will move files on disk.",
+            theme.message_error.add_modifier(Modifier::BOLD),
+        )));
+    } else {
+        lines.push(Line::from(Span::styled(
+            "Dry-run: no filesystem changes.",
+            theme.message_warn,
+        )));
+    }
+    lines.push(Line::from(""));
+
+    let planned = if app.planned_targets.is_empty() {
+        "No groups planned".to_string()
+    } else {
+        format!("Planned groups: {}", app.planned_targets.len())
+    };
+    lines.push(Line::from(planned));
+    if app.planned_targets.len() > app.planned_blocked {
+        lines.push(Line::from(Span::styled(
+            format!("Estimated: {}", app.planned_cost.describe()),
+            theme.message_info,
+        )));
+    }
+    if let Some(b) = &app.dir_batch {
+        let dir = if b.current.as_os_str().is_empty() {
+            ".".to_string()
+        } else {
+            b.current.display().to_string()
+        };
+        lines.push(Line::from(Span::styled(
+            format!("Directory {}/{}: {dir}", b.total - b.pending.len(), b.total),
+            theme.message_info,
+        )));
+    }
+    if !app.plan_changes.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "Changed on disk since planning; the plan below is updated:",
+            theme.message_warn.add_modifier(Modifier::BOLD),
+        )));
+        for c in &app.plan_changes {
+            lines.push(Line::from(Span::styled(
+                format!("  {c}"),
+                theme.message_warn,
+            )));
+        }
+    }
+    if app.planned_blocked > 0 {
+        lines.push(Line::from(Span::styled(
+            format!(
+                "Blocked groups: {} (not writable; apply refuses them before touching any file)",
+                app.planned_blocked
+            ),
+            theme.message_error,
+        )));
+    }
+    if let Some(folder) = app.stignore_offer.as_ref().filter(|_| app.apply) {
+        lines.push(Line::from(Span::styled(
+            format!(
+                "{} doesn't ignore {ARCHIVE_DIR}: archived versions will sync to every device. {} adds it",
+                folder.join(".stignore").display(),
+                app.keymap.key(Scope::Confirm, "ignore_archive")
+            ),
+            theme.message_warn,
+        )));
+    }
+    lines.push(Line::from(""));
+
+    let block = titled_block("", theme).title(title);
+    let inner = block.inner(rect);
+    f.render_widget(block, rect);
+    let [body, buttons] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(inner);
+
+    let rows = plan_rows(&app.planned_ops, &app.plan_folded);
+    if !rows.is_empty() {
+        let dirs = rows
+            .iter()
+            .filter(|r| matches!(r, PlanRow::Dir { .. }))
+            .count();
+        lines.push(Line::from(Span::styled(
+            format!(
+                "Plan by directory: {dirs} directories ({}/{} move, {} fold, {} search)",
+                app.keymap.key(Scope::Global, "up"),
+                app.keymap.key(Scope::Global, "down"),
+                app.keymap.key(Scope::Confirm, "toggle_fold"),
+                app.keymap.key(Scope::Confirm, "log"),
+            ),
+            theme.header_help,
+        )));
+    }
+    // The summary wraps; the plan gets the rows below it.
+    let width = usize::from(body.width).max(1);
+    let height: usize = lines.iter().map(|l| l.width().div_ceil(width).max(1)).sum();
+    let height = u16::try_from(height).unwrap_or(u16::MAX).min(body.height);
+    let summary = Paragraph::new(lines).wrap(Wrap { trim: false });
+    let [top, plan_area] =
+        Layout::vertical([Constraint::Length(height), Constraint::Min(0)]).areas(body);
+    f.render_widget(summary, top);
+
+    app.log_rows = usize::from(plan_area.height).max(1);
+    let items: Vec<ListItem> = rows
+        .iter()
+        .map(|r| match r {
+            PlanRow::Dir {
+                dir,
+                groups,
+                blocked,
+                folded,
+                ..
+            } => {
+                let mut spans = vec![
+                    Span::styled(if *folded { "▸ " } else { "▾ " }, theme.header_meta),
+                    Span::styled(format!("{}/", dir.display()), theme.block_title),
+                    Span::styled(format!("  {groups} groups"), theme.header_meta),
+                ];
+                if *blocked > 0 {
+                    spans.push(Span::styled(
+                        format!(", {blocked} blocked"),
+                        theme.message_error,
+                    ));
+                }
+                ListItem::new(Line::from(spans))
+            }
+            PlanRow::Line(i) => {
+                let mut line = plan_line(&app.planned_ops[*i], &app.log_search, theme);
+                line.spans.insert(0, Span::raw("  "));
+                ListItem::new(line)
+            }
+        })
+        .collect();
+    let list = List::new(items).highlight_style(theme.list_highlight);
+    f.render_stateful_widget(list, plan_area, &mut app.plan_state);
+    draw_confirm_buttons(f, app, buttons, theme);
+}
+
+/// The Confirm actions as clickable buttons, each labelled with its bound key.
+fn draw_confirm_buttons(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let mut buttons = vec![
+        ("run", "run", KeyCode::Char('y')),
+        ("toggle_apply", "toggle apply", KeyCode::Char('t')),
+        ("write_plan", "write plan", KeyCode::Char('w')),
+    ];
+    if app.dir_batch.is_some() {
+        buttons.push(("skip_dir", "skip directory", KeyCode::Char('s')));
+    }
+    if app.apply && app.stignore_offer.is_some() {
+        buttons.push(("ignore_archive", "ignore archive", KeyCode::Char('i')));
+    }
+    buttons.push(("cancel", "cancel", KeyCode::Char('n')));
+
+    app.confirm_buttons.clear();
+    let mut spans = Vec::new();
+    let mut x = area.x;
+    for (name, label, code) in buttons {
+        let text = format!(" {} {label} ", app.keymap.key(Scope::Confirm, name));
+        let width = (text.chars().count() as u16).min((area.x + area.width).saturating_sub(x));
+        app.confirm_buttons
+            .push((Rect::new(x, area.y, width, area.height.min(1)), code));
+        x = x.saturating_add(width + 2);
+        spans.push(Span::styled(text, theme.list_highlight));
+        spans.push(Span::raw("  "));
+    }
+    f.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+fn draw_list(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    ensure_diffstats(app);
+    ensure_kinds(app);
+    app.list_area = area;
+    if app.tree_view {
+        draw_tree(f, app, area, theme);
+        return;
+    }
+    let title = match app.list_state.selected() {
+        Some(i) if !app.groups.is_empty() => format!(
+            "Conflicts ({} of {}{})",
+            i + 1,
+            app.groups.len(),
+            if app.sort_by_type { ", by type" } else { "" }
+        ),
+        _ => "Conflicts".to_string(),
+    };
+    let title = if app.scan_run.is_some() {
+        format!("{title} · scanning, Esc cancels")
+    } else if app.identity_checks.is_some() && app.only_differing {
+        format!("{title} · hashing, Esc stops")
+    } else {
+        title
+    };
+    let block = titled_block("", theme).title(Span::styled(title, theme.block_title));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    if inner.height == 0 {
+        return;
+    }
+    // Rows start below the header; `list_row_at` expects them one row below `list_area`'s top.
+    app.list_area = Rect {
+        y: area.y + 1,
+        height: area.height.saturating_sub(1),
+        ..area
+    };
+
+    let names: Vec<String> = app
+        .groups
+        .iter()
+        .map(|g| rel_path(&app.root, &g.base_path).display().to_string())
+        .collect();
+    let width = names
+        .iter()
+        .map(|n| n.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Path".len())
+        .min(usize::from(inner.width / 2));
+    let mut header = format!(
+        "Sel {}{:<width$}  Versions",
+        if app.columns.contains(&Column::Hash) {
+            "= "
+        } else {
+            ""
+        },
+        "Path"
+    );
+    for c in Column::ALL {
+        if c != Column::Hash && app.columns.contains(&c) {
+            header.push_str("  ");
+            header.push_str(c.heading());
+        }
+    }
+    let header_area = Rect { height: 1, ..inner };
+    f.render_widget(
+        Paragraph::new(Span::styled(header, theme.header_help)),
+        header_area,
+    );
+
+    let rows = Rect {
+        y: inner.y + 1,
+        height: inner.height - 1,
+        ..inner
+    };
+    let items: Vec<ListItem> = names
+        .into_iter()
+        .enumerate()
+        .map(|(i, rel)| ListItem::new(group_line(app, i, format!("{rel:<width$}"), theme)))
+        .collect();
+    let list = List::new(items).highlight_style(theme.list_highlight);
+    f.render_stateful_widget(list, rows, &mut app.list_state);
+
+    if app.groups.len() > usize::from(rows.height) {
+        let mut state =
+            ScrollbarState::new(app.groups.len()).position(app.list_state.selected().unwrap_or(0));
+        // Drawn over the right border, next to the rows.
+        let track = Rect {
+            x: area.x,
+            width: area.width,
+            ..rows
+        };
+        f.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None),
+            track,
+            &mut state,
+        );
+    }
+}
+
+/// One group's list entry, with `name` as the displayed path.
+fn group_line(app: &App, i: usize, name: String, theme: Theme) -> Line<'static> {
+    let g = &app.groups[i];
+    let (sel, sel_style) = if app.selected_groups.contains(&i) {
+        ("[*]", theme.selected_mark)
+    } else {
+        ("[ ]", theme.header_meta)
+    };
+
+    let picked = match g.chosen {
+        None => Span::styled("(unpicked)", theme.unpicked),
+        Some(ci) if g.discards(ci) => Span::styled("(discard all)", theme.selected_mark),
+        Some(ci) => Span::styled(
+            format!("(keep: {})", g.candidates[ci].label),
+            theme.selected_mark,
+        ),
+    };
+
+    let rel = Span::styled(name, Style::default());
+    let cnt = g.candidates.len().saturating_sub(1);
+    let orig = if g.candidates.first().map(|c| c.exists).unwrap_or(false) {
+        Span::styled("orig", theme.message_info)
+    } else {
+        Span::styled(
+            "ORIG DELETED",
+            theme.message_error.add_modifier(Modifier::BOLD),
+        )
+    };
+
+    let new = if app.new_groups.contains(&g.base_path) {
+        Span::styled("NEW ", theme.badge_apply)
+    } else {
+        Span::raw("")
+    };
+    let skew = match g.clock_skew(app.skew_threshold) {
+        Some(skew) => Span::styled(
+            format!("SKEW {} ", format_age(skew)),
+            theme.message_warn.add_modifier(Modifier::BOLD),
+        ),
+        None => Span::raw(""),
+    };
+    let blocked = if app.blocked.contains(&g.base_path) {
+        Span::styled("BLOCKED ", theme.message_error.add_modifier(Modifier::BOLD))
+    } else {
+        Span::raw("")
+    };
+
+    let same = match app.identical.get(&g.base_path) {
+        _ if !app.columns.contains(&Column::Hash) => Span::raw(""),
+        Some(Some(true)) => Span::styled("= ", theme.header_meta),
+        Some(Some(false)) => Span::styled("≠ ", theme.unpicked),
+        _ => Span::raw("  "),
+    };
+
+    let mut extra = Vec::new();
+    if app.columns.contains(&Column::Device) {
+        let devices: Vec<String> = g
+            .candidates
+            .iter()
+            .filter_map(|c| conflict_meta(&c.path).map(|m| app.device_aliases.name(&m.device)))
+            .collect();
+        extra.push(Span::styled(
+            format!("  dev:{}", devices.join(",")),
+            theme.header_meta,
+        ));
+    }
+    if app.columns.contains(&Column::Age) {
+        let age = g
+            .candidates
+            .iter()
+            .filter(|c| !c.is_original)
+            .filter_map(|c| c.modified)
+            .max()
+            .and_then(|m| SystemTime::now().duration_since(m).ok())
+            .map(format_age)
+            .unwrap_or_else(|| "?".to_string());
+        extra.push(Span::styled(format!("  age:{age}"), theme.header_meta));
+    }
+    if app.columns.contains(&Column::Size) {
+        let size = g
+            .candidates
+            .iter()
+            .filter_map(|c| c.size)
+            .max()
+            .map(format_size)
+            .unwrap_or_else(|| "?".to_string());
+        extra.push(Span::styled(format!("  {size}"), theme.header_meta));
+    }
+    if app.columns.contains(&Column::Diffstat) {
+        let stat = diff_pair(g).and_then(|k| app.diffstats.get(&k).copied().flatten());
+        extra.push(match stat {
+            Some((add, del)) => Span::styled(format!("  +{add}/-{del}"), theme.message_info),
+            None => Span::styled("  +?/-?", theme.header_meta),
+        });
+    }
+    if app.columns.contains(&Column::Type) {
+        let kind = app.kinds.get(&g.base_path).map_or("?", |k| k.label());
+        extra.push(Span::styled(format!("  {kind}"), theme.header_meta));
+    }
+    if app.mode == Mode::List && app.list_state.selected() == Some(i) {
+        extra.push(Span::styled(
+            format!("  {}", quick_pick_hints(app, i, SystemTime::now())),
+            theme.header_meta,
+        ));
+    }
+
+    let mut spans = vec![
+        Span::styled(sel, sel_style),
+        Span::raw(" "),
+        same,
+        new,
+        skew,
+        blocked,
+        rel,
+        Span::styled("  [", theme.header_meta),
+        Span::styled(format!("{cnt}"), theme.header_meta),
+        Span::styled(" conflicts, ", theme.header_meta),
+        orig,
+        Span::styled("] ", theme.header_meta),
+        picked,
+    ];
+    spans.extend(extra);
+    Line::from(spans)
+}
+
+/// Where the quick-pick keys would land for group `gi`, as bound, e.g.
+/// `n=newest PHONE12 2h ago · p=oldest original 3w ago · c=current`. Trusted is only
+/// offered once some version has a trust rank, largest once the versions differ in size.
+fn quick_pick_hints(app: &App, gi: usize, now: SystemTime) -> String {
+    let g = &app.groups[gi];
+    let skewed = skew_blocks_mtime(app, gi);
+    let target = |s: Strategy| {
+        if s.mtime_only() && skewed {
+            return " skipped: clock skew".to_string();
+        }
+        let Some(c) = s.choose(g).and_then(|ci| g.candidates.get(ci)) else {
+            return String::new();
+        };
+        if !c.exists {
+            return " discards all".to_string();
+        }
+        let who = match conflict_meta(&c.path) {
+            _ if c.is_original => "original".to_string(),
+            Some(m) => app.device_aliases.name(&m.device),
+            None => c.label.clone(),
+        };
+        let ago = c
+            .modified
+            .and_then(|m| now.duration_since(m).ok())
+            .map(|d| format!(" {} ago", format_age(d)))
+            .unwrap_or_default();
+        format!(" {who}{ago}")
+    };
+    let mut picks = vec![("newest", Strategy::Newest), ("oldest", Strategy::Oldest)];
+    if g.candidates.iter().any(|c| c.trust.is_some()) {
+        picks.push(("trusted", Strategy::Trusted));
+    }
+    let sizes: BTreeSet<_> = g
+        .candidates
+        .iter()
+        .filter(|c| c.exists)
+        .map(|c| c.size)
+        .collect();
+    if sizes.len() > 1 {
+        picks.push(("largest", Strategy::Largest));
+    }
+    let mut hints: Vec<String> = picks
+        .into_iter()
+        .map(|(action, s)| {
+            let key = app.keymap.key(Scope::List, action);
+            format!("{key}={}{}", s.label(), target(s))
+        })
+        .collect();
+    let current = if g.discards(0) { " discards all" } else { "" };
+    hints.push(format!(
+        "{}=current{current}",
+        app.keymap.key(Scope::List, "current")
+    ));
+    hints.join(" · ")
+}
+
+/// Files compared by the diffstat column: the original against the picked version, or
+/// against the newest conflict while nothing (or the original) is picked.
+fn diff_pair(g: &ConflictGroup) -> Option<(PathBuf, PathBuf)> {
+    let orig = g.candidates.first().filter(|c| c.exists)?;
+    let other = match g.chosen {
+        Some(ci) if ci != 0 => g.candidates.get(ci)?,
+        _ => g
+            .candidates
+            .iter()
+            .skip(1)
+            .filter(|c| c.exists)
+            .max_by_key(|c| c.modified)?,
+    };
+    Some((orig.path.clone(), other.path.clone()))
+}
+
+/// Fills the file type cache for every group while the column is shown.
+fn ensure_kinds(app: &mut App) {
+    if !app.columns.contains(&Column::Type) {
+        return;
+    }
+    for g in &app.groups {
+        app.kinds
+            .entry(g.base_path.clone())
+            .or_insert_with(|| group_kind(g));
+    }
+}
+
+/// Fills the diffstat cache for every group while the column is shown.
+fn ensure_diffstats(app: &mut App) {
+    if !app.columns.contains(&Column::Diffstat) {
+        return;
+    }
+    let max = app.diff_options.max_size;
+    for key in app.groups.iter().filter_map(diff_pair) {
+        app.diffstats
+            .entry(key)
+            .or_insert_with_key(|(a, b)| diffstat(a, b, max));
+    }
+}
+
+fn draw_tree(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    sync_to_tree(app);
+    let items: Vec<ListItem> = tree_rows(&app.root, &app.groups, &app.collapsed, app.path_order)
+        .into_iter()
+        .map(|row| match row {
+            TreeRow::Dir {
+                path,
+                depth,
+                count,
+                collapsed,
+            } => {
+                let under = groups_under(&app.root, &app.groups, &path);
+                let selected = under
+                    .iter()
+                    .filter(|gi| app.selected_groups.contains(gi))
+                    .count();
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let mut spans = vec![
+                    Span::raw("  ".repeat(depth)),
+                    Span::styled(if collapsed { "▸ " } else { "▾ " }, theme.header_meta),
+                    Span::styled(format!("{name}/"), theme.message_info),
+                    Span::styled(format!("  ({count})"), theme.header_meta),
+                ];
+                if selected > 0 {
+                    spans.push(Span::styled(
+                        format!("  [{selected} selected]"),
+                        theme.selected_mark,
+                    ));
+                }
+                ListItem::new(Line::from(spans))
+            }
+            TreeRow::Group { index, depth } => {
+                let name = app.groups[index]
+                    .base_path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let mut line = group_line(app, index, name, theme);
+                line.spans.insert(0, Span::raw("  ".repeat(depth)));
+                ListItem::new(line)
+            }
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(titled_block("Conflicts by directory", theme))
+        .highlight_style(theme.list_highlight);
+    f.render_stateful_widget(list, area, &mut app.tree_state);
+}
+
+fn draw_pick(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let gi = match app.list_state.selected() {
+        Some(i) => i,
+        None => {
+            let p = Paragraph::new("No group selected").block(titled_block("Pick", theme));
+            f.render_widget(p, area);
+            return;
+        }
+    };
+    ensure_duplicates(app);
+    ensure_doc_props(app, gi);
+    sync_to_pick_view(app);
+    let highlighted = app
+        .pick_state
+        .selected()
+        .and_then(|i| app.groups[gi].row(i))
+        .filter(|c| c.exists)
+        .map(|c| (c.path.clone(), c.modified));
+    if let Some((path, modified)) = &highlighted {
+        let base = app.groups[gi].base_path.clone();
+        load_file_preview(app, path, &base, *modified);
+    }
+    let g = &app.groups[gi];
+    let now = SystemTime::now();
+    let dup = current_duplicates(app).unwrap_or_default();
+    let picked_row = g.chosen.map(|ci| pick_row_of(app, ci));
+
+    let items: Vec<ListItem> = pick_rows(app)
+        .into_iter()
+        .filter_map(|i| g.row(i).map(|c| (i, c)))
+        .map(|(i, c)| {
+            let rel = rel_path(&app.root, &c.path).display().to_string();
+            let size = c.size.map(format_size).unwrap_or_else(|| "?".to_string());
+            let m = c
+                .modified
+                .map(|t| format_time_ago(t, now))
+                .unwrap_or_else(|| "?".to_string());
+
+            let mut spans = Vec::new();
+            if !c.exists {
+                let missing = if c.is_original {
+                    "(deleted: keeping it archives every conflict) "
+                } else {
+                    "(missing) "
+                };
+                spans.push(Span::styled(missing, theme.message_error));
+            }
+            if g.is_reference_row(i) {
+                spans.push(Span::styled("(ref) ", theme.header_meta));
+            }
+            spans.push(Span::styled(c.label.clone(), theme.message_info));
+            let copies = dup.iter().filter(|&&d| d == Some(i)).count();
+            if copies > 0 {
+                spans.push(Span::styled(
+                    format!(" ×{} identical", copies + 1),
+                    theme.message_info,
+                ));
+            }
+            spans.push(Span::raw("  "));
+            spans.push(Span::styled(rel, Style::default()));
+            spans.push(Span::raw("  "));
+            spans.push(Span::styled(size, theme.header_meta));
+            spans.push(Span::raw("  "));
+            spans.push(Span::styled("modified ", theme.header_meta));
+            spans.push(Span::styled(m, theme.header_meta));
+            if let Some(Some(props)) = app.doc_props.get(&(c.path.clone(), c.modified)) {
+                spans.push(Span::raw("  "));
+                spans.push(Span::styled(doc_props_line(props, now), theme.message_info));
+            }
+            let orig = g.candidates.first().filter(|o| o.exists && i > 0);
+            let large = orig
+                .and_then(|o| o.size.max(c.size))
+                .filter(|&s| s > app.diff_options.max_size);
+            if let Some(large) = large {
+                spans.push(Span::raw("  "));
+                spans.push(Span::styled(
+                    format!(
+                        "press {} to compute diff ({})",
+                        app.keymap.keys(Scope::Pick, "diff"),
+                        format_size(large)
+                    ),
+                    theme.message_warn,
+                ));
+            } else if let Some(orig) = orig {
+                match app.diffstats.get(&(orig.path.clone(), c.path.clone())) {
+                    Some(Some((add, del))) => {
+                        spans.push(Span::raw("  "));
+                        spans.push(Span::styled(format!("+{add}/-{del}"), theme.message_info));
+                    }
+                    // Not text.
+                    Some(None) => {}
+                    None if c.exists => {
+                        spans.push(Span::raw("  "));
+                        spans.push(Span::styled("+…", theme.header_meta));
+                    }
+                    None => {}
+                }
+            }
+            if let Some(skew) = c.clock_skew().filter(|&s| s > app.skew_threshold) {
+                spans.push(Span::raw("  "));
+                spans.push(Span::styled(
+                    format!("skew:{} vs name", format_age(skew)),
+                    theme.message_warn,
+                ));
+            }
+            if let Some(rank) = c.trust {
+                spans.push(Span::raw("  "));
+                spans.push(Span::styled(
+                    format!("trust:{}/{}", rank + 1, app.device_trust.len()),
+                    theme.message_info,
+                ));
+            }
+            if picked_row == Some(i) {
+                spans.push(Span::raw("  "));
+                spans.push(Span::styled("(picked)", theme.selected_mark));
+            }
+
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let title = rel_path(&app.root, &g.base_path).display().to_string();
+
+    let list_rows = items.len() as u16 + 2;
+    let list = List::new(items)
+        .block(titled_block(&title, theme))
+        .highlight_style(theme.list_highlight);
+
+    // A thumbnail of a highlighted photo takes the rest of the pane, the first bytes of
+    // another binary a few lines.
+    let preview_rows = match highlighted.and(app.file_preview.as_ref()) {
+        Some((_, _, FilePreview::Image(_))) => {
+            Some(area.height.saturating_sub(list_rows)).filter(|&rows| rows >= MIN_THUMBNAIL_ROWS)
+        }
+        Some((_, _, FilePreview::Hex { bytes, note })) => {
+            let lines = (bytes.len().div_ceil(16) as u16).min(PICK_HEX_LINES);
+            Some(lines + u16::from(note.is_some()) + 2)
+        }
+        _ => None,
+    }
+    .filter(|&rows| list_rows + rows <= area.height);
+    let (list_area, preview_area) = match preview_rows {
+        Some(rows) => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(rows)])
+                .split(area);
+            (chunks[0], Some(chunks[1]))
+        }
+        None => (area, None),
+    };
+
+    app.pick_area = list_area;
+    f.render_stateful_widget(list, list_area, &mut app.pick_view);
+    if let Some(preview_area) = preview_area {
+        let block = titled_block("Preview", theme);
+        let inner = block.inner(preview_area);
+        f.render_widget(block, preview_area);
+        draw_file_preview(f, app, inner, theme);
+    }
+}
+
+fn draw_apply(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let Some(run) = &app.apply_run else {
+        return;
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .split(area);
+
+    let total = run.entries.len().max(1);
+    let finished = run
+        .entries
+        .iter()
+        .filter(|(_, st)| *st != GroupStatus::Pending)
+        .count();
+    let failed = run.failed().count();
+    let title = if run.dry_run { "Dry-run" } else { "Apply" };
+    let gauge = Gauge::default()
+        .block(titled_block(title, theme))
+        .gauge_style(if failed > 0 {
+            theme.message_error
+        } else {
+            theme.selected_mark
+        })
+        .ratio(finished as f64 / total as f64)
+        .label(format!(
+            "{finished}/{} groups, {failed} failed",
+            run.entries.len()
+        ));
+    f.render_widget(gauge, chunks[0]);
+
+    let mut items: Vec<ListItem> = run
+        .entries
+        .iter()
+        .map(|(gi, st)| {
+            let rel = rel_path(&app.root, &app.groups[*gi].base_path)
+                .display()
+                .to_string();
+            let (mark, style) = match st {
+                GroupStatus::Pending => ("…", theme.header_meta),
+                GroupStatus::Done => ("✓", theme.selected_mark),
+                GroupStatus::Failed(_) => ("✗", theme.message_error),
+                GroupStatus::Cancelled => ("-", theme.message_warn),
+            };
+            let mut spans = vec![Span::styled(format!("{mark} "), style), Span::raw(rel)];
+            if let GroupStatus::Failed(e) = st {
+                spans.push(Span::styled(format!("  {e}"), theme.message_error));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    items.extend(
+        run.warnings
+            .iter()
+            .map(|e| ListItem::new(Span::styled(format!("! {e}"), theme.message_warn))),
+    );
+
+    let list = List::new(items)
+        .block(titled_block("Groups", theme))
+        .highlight_style(theme.list_highlight);
+    f.render_stateful_widget(list, chunks[1], &mut app.apply_state);
+}
+
+fn draw_preview(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    if app.preview_image.is_some() {
+        let block = titled_block("", theme)
+            .title(Span::styled(app.preview_title.clone(), theme.block_title));
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        draw_file_preview(f, app, inner, theme);
+        return;
+    }
+    let title = format!(
+        "{} ({}/{})",
+        app.preview_title,
+        (app.preview_scroll + 1).min(app.preview.len()),
+        app.preview.len()
+    );
+    let lines: Vec<Line> = app
+        .preview
+        .iter()
+        .skip(app.preview_scroll)
+        .take(usize::from(area.height))
+        .cloned()
+        .collect();
+    let p = Paragraph::new(lines)
+        .block(titled_block("", theme).title(Span::styled(title, theme.block_title)));
+    f.render_widget(p, area);
+}
+
+/// Shared lines shown before and after each hunk of a merge.
+const MERGE_CONTEXT: usize = 3;
+
+/// The merge's hunks between their shared lines, `<` for the original's lines and `>`
+/// for the conflict's; the side a decided hunk drops is dimmed.
+fn draw_merge(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let Some((gi, ci, merge)) = &app.merge else {
+        return;
+    };
+    let g = &app.groups[*gi];
+    let n = merge.hunk_count();
+    let selected = app.merge_state.selected().unwrap_or(0);
+    let text = |l: &str| l.trim_end_matches(['\n', '\r']).to_string();
+    let mut lines: Vec<Line> = Vec::new();
+    let (mut top, mut hunk) = (0, 0);
+    let last = merge.segments.len().saturating_sub(1);
+    for (si, segment) in merge.segments.iter().enumerate() {
+        match segment {
+            Segment::Same(same) => {
+                let head = if si == 0 { 0 } else { MERGE_CONTEXT };
+                let tail = if si == last { 0 } else { MERGE_CONTEXT };
+                let plain = |l: &String| Line::from(format!("  {}", text(l)));
+                if same.len() > head + tail + 1 {
+                    lines.extend(same[..head].iter().map(plain));
+                    lines.push(Line::styled(
+                        format!("  ... {} unchanged lines", same.len() - head - tail),
+                        theme.header_help,
+                    ));
+                    lines.extend(same[same.len() - tail..].iter().map(plain));
+                } else {
+                    lines.extend(same.iter().map(plain));
+                }
+            }
+            Segment::Hunk(h) => {
+                let header = format!(
+                    "hunk {}/{n}: {}",
+                    hunk + 1,
+                    h.take.map_or("undecided", Take::label)
+                );
+                if hunk == selected {
+                    top = lines.len();
+                    lines.push(Line::styled(format!("> {header}"), theme.list_highlight));
+                } else {
+                    lines.push(Line::styled(format!("  {header}"), theme.message_info));
+                }
+                let (left, right) = match h.take {
+                    Some(Take::Left) => (theme.message_error, theme.header_help),
+                    Some(Take::Right) => (theme.header_help, theme.selected_mark),
+                    _ => (theme.message_error, theme.selected_mark),
+                };
+                for l in &h.left {
+                    lines.push(Line::styled(format!("< {}", text(l)), left));
+                }
+                for l in &h.right {
+                    lines.push(Line::styled(format!("> {}", text(l)), right));
+                }
+                hunk += 1;
+            }
+        }
+    }
+    let title = format!(
+        "Merge {}: < original, > {} ({} of {n} hunks undecided)",
+        rel_path(&app.root, &g.base_path).display(),
+        g.candidates[*ci].label,
+        merge.undecided()
+    );
+    let height = usize::from(area.height.saturating_sub(2));
+    let scroll = top
+        .saturating_sub(2)
+        .min(lines.len().saturating_sub(height));
+    let lines: Vec<Line> = lines.into_iter().skip(scroll).take(height).collect();
+    let p = Paragraph::new(lines)
+        .block(titled_block("", theme).title(Span::styled(title, theme.block_title)));
+    f.render_widget(p, area);
+}
+
+fn draw_timeline(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let Some(g) = app.list_state.selected().and_then(|gi| app.groups.get(gi)) else {
+        let p = Paragraph::new("No group selected").block(titled_block("Timeline", theme));
+        f.render_widget(p, area);
+        return;
+    };
+
+    let mut prev = None;
+    let items: Vec<ListItem> = timeline_entries(g)
+        .into_iter()
+        .filter_map(|(i, kind)| {
+            let c = g.row(i)?;
+            let when = c
+                .modified
+                .map(format_time)
+                .unwrap_or_else(|| "unknown time       ".to_string());
+            let gap = match (prev, c.modified) {
+                (Some(p), Some(m)) => m.duration_since(p).map(format_gap).unwrap_or_default(),
+                _ => String::new(),
+            };
+            prev = c.modified.or(prev);
+
+            let mut spans = vec![
+                Span::styled(when, theme.header_meta),
+                Span::styled(format!(" {gap:>5} "), theme.header_help),
+                Span::styled(kind.marker(), theme.message_info),
+                Span::raw("  "),
+                Span::raw(rel_path(&app.root, &c.path).display().to_string()),
+            ];
+            if let Some(sz) = c.size {
+                spans.push(Span::styled(
+                    format!("  {}", format_size(sz)),
+                    theme.header_meta,
+                ));
+            }
+            if g.chosen == Some(i) {
+                spans.push(Span::styled("  (picked)", theme.selected_mark));
+            }
+            Some(ListItem::new(Line::from(spans)))
+        })
+        .collect();
+
+    let title = format!("Timeline: {}", rel_path(&app.root, &g.base_path).display());
+    let list = List::new(items)
+        .block(titled_block(&title, theme))
+        .highlight_style(theme.list_highlight);
+    f.render_stateful_widget(list, area, &mut app.timeline_state);
+}
+
+/// Colors a status message by what it reports.
+fn message_style(message: &str, theme: Theme) -> Style {
+    let message = message.to_ascii_lowercase();
+    if message.contains("fail") || message.contains("error") {
+        theme.message_error
+    } else if message.contains("cancel")
+        || message.contains("dry-run")
+        || message.contains("pick")
+        || message.contains("no ")
+    {
+        theme.message_warn
+    } else {
+        theme.message_info
+    }
+}
+
+fn draw_footer(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(area);
+
+    let msg_style = message_style(&app.message, theme);
+
+    let mut msg_text = app.message.clone();
+    if let Some(e) = &app.compare_error {
+        if !msg_text.is_empty() {
+            msg_text.push('\n');
+        }
+        msg_text.push_str(e);
+    }
+
+    let msg = Paragraph::new(Span::styled(msg_text, msg_style))
+        .block(titled_block("Message", theme))
+        .wrap(Wrap { trim: true });
+    f.render_widget(msg, chunks[0]);
+
+    let title = if app.planned_ops.is_empty() {
+        "Plan / Log".to_string()
+    } else {
+        format!(
+            "Plan / Log ({}/{})",
+            app.log_scroll + 1,
+            app.planned_ops.len()
+        )
+    };
+    let lines: Vec<Line> = app
+        .planned_ops
+        .iter()
+        .skip(app.log_scroll)
+        .map(|l| plan_line(l, &app.log_search, theme))
+        .collect();
+    let plan = Paragraph::new(lines)
+        .block(titled_block(&title, theme))
+        .wrap(Wrap { trim: false });
+    f.render_widget(plan, chunks[1]);
+}
+
+/// A row of the Confirm view's plan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PlanRow {
+    /// A directory relative to the root (`.` for the root), with its planned groups, how
+    /// many of them are blocked, and its first plan line.
+    Dir {
+        dir: PathBuf,
+        groups: usize,
+        blocked: usize,
+        folded: bool,
+        first_line: usize,
+    },
+    /// Index into the plan lines.
+    Line(usize),
+}
+
+/// The plan's groups under one heading per directory, directories sorted and groups in
+/// plan order; folded directories show only their heading. Lines before the first
+/// group come first, without a heading.
+fn plan_rows(ops: &[String], folded: &BTreeSet<PathBuf>) -> Vec<PlanRow> {
+    let starts: Vec<(usize, PathBuf)> = ops
+        .iter()
+        .enumerate()
+        .filter_map(|(i, l)| {
+            let rel = Path::new(l.strip_prefix("Group: ")?);
+            let dir = rel.parent().filter(|d| !d.as_os_str().is_empty());
+            Some((i, dir.unwrap_or(Path::new(".")).to_path_buf()))
+        })
+        .collect();
+    let mut dirs: BTreeMap<PathBuf, Vec<std::ops::Range<usize>>> = BTreeMap::new();
+    for (k, (start, dir)) in starts.iter().enumerate() {
+        let end = starts.get(k + 1).map_or(ops.len(), |(next, _)| *next);
+        dirs.entry(dir.clone()).or_default().push(*start..end);
+    }
+
+    let lead = starts.first().map_or(ops.len(), |(start, _)| *start);
+    let mut rows: Vec<PlanRow> = (0..lead).map(PlanRow::Line).collect();
+    for (dir, groups) in dirs {
+        let blocked = groups
+            .iter()
+            .filter(|r| ops[(*r).clone()].iter().any(|l| l.starts_with("  blocked")))
+            .count();
+        let is_folded = folded.contains(&dir);
+        rows.push(PlanRow::Dir {
+            first_line: groups[0].start,
+            groups: groups.len(),
+            blocked,
+            folded: is_folded,
+            dir,
+        });
+        if !is_folded {
+            rows.extend(groups.into_iter().flatten().map(PlanRow::Line));
+        }
+    }
+    rows
+}
+
+/// A plan line: blocked groups in red, lines matching the log search highlighted.
+fn plan_line<'a>(line: &'a str, search: &str, theme: Theme) -> Line<'a> {
+    if log_line_matches(line, search) {
+        Line::from(Span::styled(line, theme.search_match))
+    } else if line.starts_with("  blocked") {
+        Line::from(Span::styled(line, theme.message_error))
+    } else {
+        Line::from(line)
+    }
+}
+
+/// One section per key view, the one `?` was pressed in first, then global keys, the
+/// rest in `Scope::ALL` order; all from the current bindings.
+fn help_lines(app: &App, theme: Theme) -> Vec<Line<'static>> {
+    let here = key_scope(app.help_from).filter(|&s| s != Scope::Help);
+    let mut scopes: Vec<Scope> = here.into_iter().collect();
+    scopes.extend(
+        Scope::ALL
+            .into_iter()
+            .filter(|&s| Some(s) != here && s != Scope::Help),
+    );
+    let sections: Vec<(Scope, Vec<(String, String)>)> = scopes
+        .into_iter()
+        .map(|s| (s, app.keymap.reference(s)))
+        .collect();
+    let width = sections
+        .iter()
+        .flat_map(|(_, keys)| keys.iter().map(|(k, _)| k.chars().count()))
+        .max()
+        .unwrap_or(0)
+        .min(24);
+
+    let mut lines = Vec::new();
+    for (scope, keys) in sections {
+        let mut title = match scope {
+            Scope::Global => "global (every view)".to_string(),
+            s => s.name().to_string(),
+        };
+        if Some(scope) == here {
+            title.push_str(" (this view)");
+        }
+        lines.push(Line::from(Span::styled(title, theme.block_title)));
+        for (k, label) in keys {
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {k:<width$}  "), theme.header_help),
+                Span::raw(label),
+            ]));
+        }
+        lines.push(Line::from(""));
+    }
+    lines.push(Line::from(Span::styled(
+        "Text prompts (paths, ages, filter, rule, search) keep their keys: type, Enter to confirm, Esc to cancel. Remap the keys above in [keys.<view>].",
+        theme.header_meta,
+    )));
+    lines
+}
+
+/// The help overlay over the whole screen, scrolled to `help_scroll`.
+fn draw_help(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let lines = help_lines(app, theme);
+    let title = format!(
+        "Keys ({}/{}) {}/{} scroll, {} close",
+        (app.help_scroll + 1).min(lines.len()),
+        lines.len(),
+        app.keymap.key(Scope::Global, "up"),
+        app.keymap.key(Scope::Global, "down"),
+        app.keymap.keys(Scope::Global, "help"),
+    );
+    f.render_widget(Clear, area);
+    let p = Paragraph::new(lines)
+        .block(titled_block("", theme).title(Span::styled(title, theme.block_title)))
+        .wrap(Wrap { trim: false })
+        .scroll((u16::try_from(app.help_scroll).unwrap_or(u16::MAX), 0));
+    f.render_widget(p, area);
+}
+
+/// The Plan / Log panel at full height (`Tab`), scrolled to `log_scroll`.
+fn draw_log(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
+    let total = app.planned_ops.len();
+    let mut title = format!("Plan / Log ({}/{total})", (app.log_scroll + 1).min(total));
+    if !app.log_search.is_empty() {
+        let hits = app
+            .planned_ops
+            .iter()
+            .filter(|l| log_line_matches(l, &app.log_search))
+            .count();
+        title.push_str(&format!("  /{}: {hits} matching", app.log_search));
+    }
+    app.log_rows = usize::from(area.height.saturating_sub(2)).max(1);
+    let lines: Vec<Line> = app
+        .planned_ops
+        .iter()
+        .skip(app.log_scroll)
+        .take(app.log_rows)
+        .map(|l| plan_line(l, &app.log_search, theme))
+        .collect();
+    let p = Paragraph::new(lines)
+        .block(titled_block("", theme).title(Span::styled(title, theme.block_title)));
+    f.render_widget(p, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+    use synctui_resolver::ops::{ARCHIVE_DIR, ARCHIVE_INDEX};
+    use synctui_resolver::scan::scan_conflicts;
+    use synctui_resolver::settle::LARGE_APPLY;
+
+    fn test_app(groups: Vec<ConflictGroup>) -> App {
+        App {
+            root: PathBuf::from("/"),
+            apply: false,
+            demo: false,
+            tutorial: None,
+            include_hidden: false,
+            follow_symlinks: false,
+            options: ApplyOptions::default(),
+            syncthing: None,
+            snapshots: vec![],
+            watcher: None,
+            watch_pending: false,
+            new_groups: BTreeSet::new(),
+            goto: None,
+            blocked: BTreeSet::new(),
+            mode: Mode::List,
+            groups,
+            list_state: ListState::default(),
+            pick_state: ListState::default(),
+            pick_view: ListState::default(),
+            timeline_state: ListState::default(),
+            timeline_from: Mode::List,
+            detail_from: Mode::List,
+            detail_hashes: Vec::new(),
+            detail_hashing: None,
+            detail_provenance: None,
+            detail_blocks: BTreeMap::new(),
+            detail_blocks_rx: None,
+            detail_scroll: 0,
+            preview_from: Mode::Pick,
+            preview_title: String::new(),
+            diff_paths: None,
+            diff_job: None,
+            diff_options: DiffOptions::default(),
+            merge: None,
+            merge_state: ListState::default(),
+            preview: Vec::new(),
+            preview_scroll: 0,
+            preview_image: None,
+            file_preview: None,
+            graphics: Graphics::Halfblocks,
+            graphics_target: None,
+            graphics_shown: None,
+            highlighter: None,
+            selected_groups: BTreeSet::new(),
+            visual: None,
+            prune_preview: Vec::new(),
+            leftovers: Vec::new(),
+            list_area: Rect::default(),
+            pick_area: Rect::default(),
+            confirm_buttons: Vec::new(),
+            last_click: None,
+            like_last_time: false,
+            deleted_original: None,
+            skew_threshold: DEFAULT_SKEW_THRESHOLD,
+            trust_mtime: false,
+            keymap: Keymap::default(),
+            plan_out: None,
+            message: String::new(),
+            input: String::new(),
+            planned_ops: vec![],
+            planned_targets: vec![],
+            planned_blocked: 0,
+            planned_cost: ApplyCost::default(),
+            stignore_offer: None,
+            planned_stamps: Vec::new(),
+            plan_changes: Vec::new(),
+            apply_run: None,
+            scan_run: None,
+            deferred_rescan: None,
+            undo_stack: Vec::new(),
+            apply_state: ListState::default(),
+
+            compare_error: None,
+            last_error: None,
+            needs_clear: false,
+            viewer: None,
+            hooks: Hooks::default(),
+            device_trust: Vec::new(),
+            device_aliases: DeviceAliases::default(),
+            page_rows: 10,
+            log_scroll: 0,
+            plan_state: ListState::default(),
+            plan_folded: BTreeSet::new(),
+            log_rows: 10,
+            log_from: Mode::Confirm,
+            help_from: Mode::List,
+            help_scroll: 0,
+            log_search: String::new(),
+            history: None,
+            tree_view: false,
+            tree_state: ListState::default(),
+            collapsed: BTreeSet::new(),
+            dir_batch: None,
+            identical: HashMap::new(),
+            hash_cache: None,
+            scan_cache: None,
+            session_path: None,
+            session_saved: None,
+            session_at: Instant::now(),
+            identity_checks: None,
+            duplicates_check: None,
+            check_rxs: Vec::new(),
+            duplicates: HashMap::new(),
+            doc_props: HashMap::new(),
+            only_differing: false,
+            filter: ConflictFilter::default(),
+            filter_inputs: Default::default(),
+            kinds: HashMap::new(),
+            sort_by_type: false,
+            path_order: PathOrder::Natural,
+            filter_field: 0,
+            pick_rules: Vec::new(),
+            folder_presets: BTreeMap::new(),
+            folders: Vec::new(),
+            config_path: None,
+            rule_strategy: Strategy::Newest,
+            rule_field: 0,
+            columns: BTreeSet::from([Column::Hash]),
+            columns_state: ListState::default(),
+            diffstats: HashMap::new(),
+            diffstat_rx: None,
+        }
+    }
+
+    #[test]
+    fn columns_menu_toggles_columns_and_fills_diffstats() {
+        let td = tempfile::tempdir().unwrap();
+        let base = td.path().join("f.txt");
+        let other = td.path().join("f.txt.sync-conflict-20240101-000000-DEV");
+        std::fs::write(&base, "a\nb\n").unwrap();
+        std::fs::write(&other, "a\nc\nd\n").unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+
+        handle_key(&mut app, KeyCode::Char('m'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::Columns);
+        // Hash is on by default; turn it off and diffstat on.
+        handle_key(&mut app, KeyCode::Char(' '), KeyModifiers::NONE).unwrap();
+        handle_key(&mut app, KeyCode::Char('G'), KeyModifiers::NONE).unwrap();
+        handle_key(&mut app, KeyCode::Up, KeyModifiers::NONE).unwrap();
+        handle_key(&mut app, KeyCode::Enter, KeyModifiers::NONE).unwrap();
+        handle_key(&mut app, KeyCode::Esc, KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::List);
+        assert_eq!(app.columns, BTreeSet::from([Column::Diffstat]));
+
+        ensure_diffstats(&mut app);
+        assert_eq!(app.diffstats[&(base, other)], Some((2, 1)));
+    }
+
+    #[test]
+    fn groups_sort_by_file_type_and_show_it_in_a_column() {
+        let td = tempfile::tempdir().unwrap();
+        let files: [(&str, &[u8]); 3] = [
+            ("a.txt", b"notes\n"),
+            ("b.jpg", b"\xff\xd8\xff\xe0\0\x10JFIF"),
+            ("c.rs", b"fn main() {}\n"),
+        ];
+        for (name, content) in files {
+            std::fs::write(td.path().join(name), content).unwrap();
+            std::fs::write(
+                td.path()
+                    .join(format!("{name}.sync-conflict-20240101-000000-DEV")),
+                content,
+            )
+            .unwrap();
+        }
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.root = td.path().to_path_buf();
+        app.include_hidden = true;
+        app.list_state.select(Some(0));
+        app.groups[1].chosen = Some(1);
+        let names = |app: &App| {
+            app.groups
+                .iter()
+                .map(|g| {
+                    g.base_path
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&app), ["a.txt", "b.jpg", "c.rs"]);
+
+        handle_key(&mut app, KeyCode::Char('y'), KeyModifiers::NONE).unwrap();
+        assert_eq!(names(&app), ["b.jpg", "c.rs", "a.txt"]);
+        // The pick and the cursor follow their groups.
+        assert_eq!(app.groups[0].chosen, Some(1));
+        assert_eq!(app.list_state.selected(), Some(2));
+        // Rescans keep the order.
+        start_scan(&mut app, false).unwrap();
+        poll_scan(&mut app, true);
+        assert_eq!(names(&app), ["b.jpg", "c.rs", "a.txt"]);
+
+        app.columns.insert(Column::Type);
+        let screen = render(&mut app, 140, 30);
+        assert!(screen.contains("of 3, by type)"), "{screen}");
+        assert!(screen.contains("  image"), "{screen}");
+        assert!(screen.contains("  code"), "{screen}");
+
+        handle_key(&mut app, KeyCode::Char('y'), KeyModifiers::NONE).unwrap();
+        assert_eq!(names(&app), ["a.txt", "b.jpg", "c.rs"]);
+    }
+
+    #[test]
+    fn current_group_len_empty_when_none_selected() {
+        let mut app = test_app(vec![]);
+        app.list_state.select(None);
+        assert_eq!(current_group_len(&app), 0);
+    }
+
+    #[test]
+    fn viewer_command_prefers_configured_viewer_with_args() {
+        let c = viewer_command(Some("code --wait"), Path::new("x.txt"));
+        assert_eq!(c.get_program(), "code");
+        let args: Vec<_> = c.get_args().collect();
+        assert_eq!(args, ["--wait", "x.txt"]);
+    }
+
+    #[test]
+    fn timeline_sorts_all_versions_by_mtime() {
+        let at = |label: &str, secs: Option<u64>, original: bool| Candidate {
+            path: PathBuf::from(label),
+            exists: true,
+            is_original: original,
+            size: None,
+            modified: secs.map(|s| UNIX_EPOCH + Duration::from_secs(s)),
+            label: label.to_string(),
+            trust: None,
+        };
+        let g = ConflictGroup {
+            base_path: PathBuf::from("f"),
+            candidates: vec![
+                at("Original", Some(50), true),
+                at("Conflict 1", Some(70), false),
+            ],
+            chosen: None,
+            references: vec![
+                at("Snapshot daily", Some(10), false),
+                at("Version 20240101-000000", None, false),
+            ],
+        };
+        let kinds: Vec<_> = timeline_entries(&g).into_iter().collect();
+        assert_eq!(
+            kinds,
+            [
+                (2, TimelineKind::Snapshot),
+                (0, TimelineKind::Original),
+                (1, TimelineKind::Conflict),
+                (3, TimelineKind::Version),
+            ]
+        );
+
+        // A deleted original isn't listed, and the cursor stops at the last listed row.
+        let mut gone = g.clone();
+        gone.candidates[0].exists = false;
+        let mut app = test_app(vec![gone]);
+        app.list_state.select(Some(0));
+        enter_timeline(&mut app);
+        for _ in 0..5 {
+            handle_key(&mut app, KeyCode::Down, KeyModifiers::NONE).unwrap();
+        }
+        assert_eq!(app.timeline_state.selected(), Some(2));
+    }
+
+    #[test]
+    fn pick_rows_show_readable_sizes_and_times() {
+        let td = tempfile::tempdir().unwrap();
+        std::fs::write(td.path().join("f.txt"), "x".repeat(1536)).unwrap();
+        std::fs::write(
+            td.path().join("f.txt.sync-conflict-20240101-000000-DEV"),
+            "c",
+        )
+        .unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.list_state.select(Some(0));
+        handle_key(&mut app, KeyCode::Enter, KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::Pick);
+        let screen = render(&mut app, 160, 30);
+        assert!(screen.contains("1.5 KiB  modified "), "{screen}");
+        assert!(screen.contains("1 B  modified "));
+        assert!(screen.contains(" (just now)"));
+    }
+
+    #[test]
+    fn detail_reports_equality_and_device() {
+        let cand = |p: &str, original: bool| Candidate {
+            path: PathBuf::from(p),
+            exists: true,
+            is_original: original,
+            size: Some(2048),
+            modified: None,
+            label: p.to_string(),
+            trust: None,
+        };
+        let g = ConflictGroup {
+            base_path: PathBuf::from("a.txt"),
+            candidates: vec![
+                cand("a.txt", true),
+                cand("a.sync-conflict-20240101-120000-ABCDEF.txt", false),
+                cand("a.sync-conflict-20240102-120000-GHIJKL.txt", false),
+            ],
+            chosen: None,
+            references: Vec::new(),
+        };
+        let h = |c: char| Some(c.to_string().repeat(64));
+        let lines = detail_lines(
+            &g,
+            &[h('a'), h('a'), h('b')],
+            false,
+            &BTreeMap::new(),
+            None,
+            &DeviceAliases::default(),
+        );
+        assert!(lines.iter().any(|l| l.contains("2.0 KiB (2048 bytes)")));
+        assert!(lines.iter().any(|l| l.ends_with("device:   ABCDEF")));
+        assert!(lines.contains(&"    0  =  =  x".to_string()));
+        assert_eq!(
+            lines.last().unwrap(),
+            "Versions differ: 2 distinct contents"
+        );
+
+        let same = detail_lines(
+            &g,
+            &[h('a'), h('a'), None],
+            false,
+            &BTreeMap::new(),
+            None,
+            &DeviceAliases::default(),
+        );
+        assert_eq!(
+            same.last().unwrap(),
+            "Readable versions are identical (1 unreadable)"
+        );
+
+        let file: DbFile = serde_json::from_str(
+            r#"{"global": {"modifiedBy": "GHIJKL", "version": ["ABCDEF:2", "GHIJKL:5"]},
+                "local": {"modifiedBy": "ABCDEF", "version": ["ABCDEF:2"]},
+                "availability": [{"id": "GHIJKL-FULL"}]}"#,
+        )
+        .unwrap();
+        let with = detail_lines(
+            &g,
+            &[h('a'), h('a'), h('b')],
+            false,
+            &BTreeMap::new(),
+            Some(&Provenance::Loaded(Ok(file))),
+            &DeviceAliases::new(&BTreeMap::from([(
+                "GHIJKL".to_string(),
+                "Ann's phone".to_string(),
+            )])),
+        );
+        assert_eq!(
+            with[..5],
+            [
+                "Syncthing (latest version of the base file)",
+                "    announced by: Ann's phone (GHIJKL)",
+                "    version:      ABCDEF:2, GHIJKL:5",
+                "    local copy:   behind (ABCDEF:2)",
+                "    available on: 1 devices",
+            ]
+        );
+        let failed = detail_lines(
+            &g,
+            &[],
+            false,
+            &BTreeMap::new(),
+            Some(&Provenance::Loaded(Err("connection refused".to_string()))),
+            &DeviceAliases::default(),
+        );
+        assert_eq!(failed[0], "Syncthing: connection refused");
+    }
+
+    #[test]
+    fn detail_hashes_versions_in_the_background() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        std::fs::write(root.join("a.txt"), "same").unwrap();
+        std::fs::write(root.join("a.txt.sync-conflict-20240101-120000-ABC"), "same").unwrap();
+        let mut app = test_app(scan_conflicts(&root, true).unwrap());
+        app.list_state.select(Some(0));
+        enter_detail(&mut app);
+        assert_eq!(app.mode, Mode::Detail);
+        let screen = render(&mut app, 120, 40);
+        assert!(screen.contains("Hashing versions..."), "{screen}");
+        assert!(screen.contains("sha256:   hashing..."), "{screen}");
+
+        poll_hashing(&mut app, true);
+        assert!(app.detail_hashing.is_none());
+        let screen = render(&mut app, 120, 40);
+        assert!(screen.contains("All versions are identical"), "{screen}");
+
+        // Closing the panel stops the hashing.
+        enter_detail(&mut app);
+        let cancel = app.detail_hashing.as_ref().unwrap().cancel.clone();
+        handle_key(&mut app, KeyCode::Esc, KeyModifiers::NONE).unwrap();
+        assert!(cancel.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn detail_loads_syncthings_record_in_the_background() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        std::fs::write(root.join("a.txt"), "a").unwrap();
+        std::fs::write(root.join("a.txt.sync-conflict-20240101-120000-ABC"), "b").unwrap();
+        let mut app = test_app(scan_conflicts(&root, true).unwrap());
+        app.list_state.select(Some(0));
+        app.syncthing = Some(SyncthingClient::new("http://127.0.0.1:1", "key"));
+        enter_detail(&mut app);
+        let screen = render(&mut app, 120, 40);
+        assert!(screen.contains("Syncthing: loading..."), "{screen}");
+        poll_provenance(&mut app, true);
+        assert!(matches!(
+            app.detail_provenance,
+            Some(Provenance::Loaded(Err(_)))
+        ));
+        assert!(render(&mut app, 120, 40).contains("Syncthing: "));
+    }
+
+    #[test]
+    fn detail_compares_versions_too_large_to_diff_block_by_block() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        let image = vec![0u8; 64 << 10];
+        let mut changed = image.clone();
+        changed[5000] = 1;
+        std::fs::write(root.join("disk.img"), &image).unwrap();
+        std::fs::write(
+            root.join("disk.img.sync-conflict-20240101-120000-ABC"),
+            &changed,
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("disk.img.sync-conflict-20240102-120000-ABC"),
+            &image,
+        )
+        .unwrap();
+        let mut app = test_app(scan_conflicts(&root, true).unwrap());
+        app.root = root;
+        app.diff_options.max_size = 1024;
+        app.list_state.select(Some(0));
+        enter_detail(&mut app);
+        // None of them is hashed: the block comparisons tell which match.
+        assert!(app.detail_hashes.iter().all(Option::is_none));
+        let rx = app.detail_blocks_rx.take().unwrap();
+        app.detail_blocks.extend(rx.iter());
+        assert_eq!(app.detail_blocks.keys().collect::<Vec<_>>(), [&1, &2]);
+        let screen = render(&mut app, 120, 60);
+        assert!(
+            screen.contains("vs [0]:   6.2% differs (4.0 KiB) in 1 regions, by 4.0 KiB blocks"),
+            "{screen}"
+        );
+        assert!(screen.contains("0x1000..0x2000 (4.0 KiB)"), "{screen}");
+        assert!(screen.contains("vs [0]:   identical"), "{screen}");
+        assert!(screen.contains("    0  =  x  ="), "{screen}");
+        assert!(screen.contains("    1  x  =  x"), "{screen}");
+        assert!(screen.contains("Versions differ"), "{screen}");
+    }
+
+    #[test]
+    fn folder_presets_pick_what_no_pick_rule_matches() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("r");
+        for (dir, name, orig, conflict) in [
+            ("photos", "a.jpg", "small", "much bigger"),
+            ("photos", "b.png", "tiny", "a lot bigger"),
+            ("notes", "todo.md", "longer original", "short"),
+        ] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join(name), orig).unwrap();
+            let conflict_name = format!("{name}.sync-conflict-20240101-120000-ABC");
+            std::fs::write(root.join(dir).join(conflict_name), conflict).unwrap();
+        }
+        let mut app = test_app(scan_conflicts(&root, true).unwrap());
+        app.root = root.clone();
+        app.folders = vec![Folder {
+            id: "ph0to-s".to_string(),
+            label: "Photos".to_string(),
+            path: root.join("photos"),
+            paused: false,
+        }];
+        app.folder_presets = BTreeMap::from([("Photos".to_string(), Strategy::Largest)]);
+        app.pick_rules = vec![PickRule {
+            glob: "*.png".to_string(),
+            strategy: Strategy::Current,
+            by: Recency::Mtime,
+        }];
+        auto_pick_rules(&mut app);
+        let chosen = |name: &str| {
+            let g = app.groups.iter().find(|g| g.base_path.ends_with(name));
+            g.unwrap().chosen
+        };
+        assert_eq!(chosen("a.jpg"), Some(1));
+        // The glob rule wins over the folder's preset; other folders stay unpicked.
+        assert_eq!(chosen("b.png"), Some(0));
+        assert_eq!(chosen("todo.md"), None);
+    }
+
+    #[test]
+    fn dry_runs_log_the_rules_that_fired_to_the_ledger() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("r");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "orig").unwrap();
+        std::fs::write(root.join("a.txt.sync-conflict-20240101-120000-ABC"), "x").unwrap();
+        let mut app = test_app(scan_conflicts(&root, true).unwrap());
+        app.root = root;
+        app.history = Some(History::open(&td.path().join("h.sqlite3")).unwrap());
+        app.pick_rules = vec![PickRule {
+            glob: "*.txt".to_string(),
+            strategy: Strategy::Current,
+            by: Recency::Mtime,
+        }];
+        auto_pick_rules(&mut app);
+        app.planned_targets = vec![0];
+        apply_plan(&mut app).unwrap();
+        poll_apply(&mut app, true);
+
+        let h = app.history.as_ref().unwrap();
+        let stats = h.rule_stats().unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].rule, "pick *.txt: current");
+        assert_eq!((stats[0].dry_runs, stats[0].applied), (1, 0));
+        assert_eq!(
+            h.rule_events(&stats[0].rule, 5).unwrap()[0].action,
+            "keep a.txt"
+        );
+    }
+
+    #[test]
+    fn apply_runs_on_a_worker_and_retries_failures() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(root.join(name), "orig").unwrap();
+        }
+        std::fs::write(root.join("a.txt.sync-conflict-20240101-120000-ABC"), "x").unwrap();
+        std::fs::write(root.join("b.txt.sync-conflict-20240101-120000-ABC"), "y").unwrap();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+        groups[1].chosen = Some(9);
+
+        let mut app = test_app(groups);
+        app.root = root;
+        app.planned_targets = vec![0, 1];
+        apply_plan(&mut app).unwrap();
+        assert_eq!(app.mode, Mode::Apply);
+        assert!(app.apply_run.as_ref().unwrap().running());
+        // Closing is refused until the worker is done.
+        close_apply(&mut app).unwrap();
+        assert_eq!(app.mode, Mode::Apply);
+
+        poll_apply(&mut app, true);
+        let run = app.apply_run.as_ref().unwrap();
+        assert!(!run.running());
+        assert_eq!(run.entries[0].1, GroupStatus::Done);
+        assert_eq!(run.failed().collect::<Vec<_>>(), [1]);
+
+        app.groups[1].chosen = Some(1);
+        app.apply_state.select(Some(1));
+        retry_failed(&mut app, true);
+        assert_eq!(
+            app.apply_run.as_ref().unwrap().entries[1].1,
+            GroupStatus::Pending
+        );
+        poll_apply(&mut app, true);
+        assert_eq!(app.apply_run.as_ref().unwrap().failed().count(), 0);
+
+        // A clean dry-run returns to the confirmation.
+        close_apply(&mut app).unwrap();
+        assert_eq!(app.mode, Mode::Confirm);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_real_apply_checks_what_it_left_behind() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        let setup = |name: &str| {
+            std::fs::write(root.join(name), "orig").unwrap();
+            let conflict = format!("{name}.sync-conflict-20240101-120000-ABC");
+            std::fs::write(root.join(conflict), "x").unwrap();
+        };
+        setup("a.txt");
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+        let mut app = test_app(groups);
+        app.root = root.clone();
+        app.include_hidden = true;
+        app.apply = true;
+        app.planned_targets = vec![0];
+        apply_plan(&mut app).unwrap();
+        poll_apply(&mut app, true);
+        poll_applied_checks(&mut app, true);
+        assert!(app.apply_run.as_ref().unwrap().warnings.is_empty());
+        assert_eq!(app.message, "Applied 1 groups. Enter to close.");
+
+        // Syncthing makes a new conflict copy right after, and the kept file changes
+        // without its size or mtime changing: only the hash taken before the move tells.
+        setup("b.txt");
+        let mut app = test_app(scan_conflicts(&root, true).unwrap());
+        app.groups[0].chosen = Some(1);
+        app.root = root.clone();
+        app.include_hidden = true;
+        app.apply = true;
+        app.planned_targets = vec![0];
+        let copy = "b.txt.sync-conflict-20240101-130000-DEF";
+        app.hooks.post_group = Some(format!(
+            "printf y > {copy}; touch -r b.txt ref; printf z > b.txt; touch -r ref b.txt; rm ref"
+        ));
+        apply_plan(&mut app).unwrap();
+        poll_apply(&mut app, true);
+        poll_applied_checks(&mut app, true);
+        let run = app.apply_run.as_ref().unwrap();
+        assert_eq!(run.warnings.len(), 2, "{:?}", run.warnings);
+        assert!(run.warnings[0].starts_with("b.txt: b.txt hashes sha256:"));
+        assert_eq!(
+            run.warnings[1],
+            format!("b.txt: {copy} is a new conflict copy")
+        );
+        assert!(app.message.contains("0 failed"), "{}", app.message);
+    }
+
+    #[test]
+    fn the_tutorial_follows_select_pick_plan_dry_run_and_apply() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        generate(&root, &tutorial::spec()).unwrap();
+        let mut app = test_app(Vec::new());
+        app.root = root.clone();
+        app.include_hidden = true;
+        app.tutorial = Some(Step::Select);
+        start_scan(&mut app, false).unwrap();
+        poll_scan(&mut app, true);
+        let conflicts = || {
+            walkdir::WalkDir::new(&root)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| {
+                    !e.path().to_string_lossy().contains(ARCHIVE_DIR)
+                        && e.file_name().to_string_lossy().contains(".sync-conflict-")
+                })
+                .count()
+        };
+        let before = conflicts();
+        assert!(render(&mut app, 120, 40).contains("Tutorial 1/5: Select"));
+
+        let press = |app: &mut App, code| {
+            handle_key(app, code, KeyModifiers::NONE).unwrap();
+            advance_tutorial(app);
+        };
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(app.tutorial, Some(Step::Select));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(app.tutorial, Some(Step::Pick));
+        press(&mut app, KeyCode::Char('C'));
+        assert_eq!(app.tutorial, Some(Step::Plan));
+        press(&mut app, KeyCode::Char('a'));
+        assert_eq!(app.tutorial, Some(Step::DryRun));
+        press(&mut app, KeyCode::Char('y'));
+        poll_apply(&mut app, true);
+        advance_tutorial(&mut app);
+        assert_eq!(app.tutorial, Some(Step::Apply));
+        assert_eq!(conflicts(), before);
+
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Char('t'));
+        press(&mut app, KeyCode::Char('y'));
+        poll_apply(&mut app, true);
+        advance_tutorial(&mut app);
+        assert_eq!(app.tutorial, Some(Step::Done));
+        assert!(conflicts() < before);
+    }
+
+    #[test]
+    fn large_applies_put_the_rescan_off_until_the_disk_settles() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        for i in 0..LARGE_APPLY + 1 {
+            std::fs::write(root.join(format!("{i}.txt")), "orig").unwrap();
+            std::fs::write(
+                root.join(format!("{i}.txt.sync-conflict-20240101-120000-ABC")),
+                "x",
+            )
+            .unwrap();
+        }
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        for g in &mut groups[1..] {
+            g.chosen = Some(1);
+        }
+        let mut app = test_app(groups);
+        app.root = root.clone();
+        app.include_hidden = true;
+        app.apply = true;
+        app.planned_targets = (1..=LARGE_APPLY).collect();
+        apply_plan(&mut app).unwrap();
+        poll_apply(&mut app, true);
+        close_apply(&mut app).unwrap();
+
+        // The applied groups leave the list without a rescan.
+        assert_eq!(app.mode, Mode::List);
+        assert_eq!(app.groups.len(), 1);
+        assert!(
+            app.message.starts_with("Applied; rescanning in "),
+            "{}",
+            app.message
+        );
+        std::fs::write(root.join("new.txt"), "orig").unwrap();
+        std::fs::write(root.join("new.txt.sync-conflict-20240101-120000-ABC"), "x").unwrap();
+        poll_deferred_rescan(&mut app).unwrap();
+        assert_eq!(app.groups.len(), 1);
+        // So are the checks of the applied groups.
+        assert!(app.check_rxs.is_empty());
+        let copy = "1.txt.sync-conflict-20240101-130000-DEF";
+        std::fs::write(root.join(copy), "y").unwrap();
+
+        app.deferred_rescan.as_mut().unwrap().timer =
+            RescanTimer::new(Duration::ZERO, Instant::now());
+        poll_deferred_rescan(&mut app).unwrap();
+        assert!(app.deferred_rescan.is_none());
+        assert_eq!(app.groups.len(), 3);
+        assert_eq!(app.message, "Rescanned now that the apply has settled");
+        poll_applied_checks(&mut app, true);
+        assert_eq!(
+            app.message,
+            format!("After the apply: 1.txt: {copy} is a new conflict copy")
+        );
+    }
+
+    #[test]
+    fn cancelling_an_apply_leaves_unstarted_groups_for_a_retry() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(root.join(name), "orig").unwrap();
+            std::fs::write(
+                root.join(format!("{name}.sync-conflict-20240101-120000-ABC")),
+                "x",
+            )
+            .unwrap();
+        }
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        for g in &mut groups {
+            g.chosen = Some(1);
+        }
+        let mut app = test_app(groups);
+        app.root = root;
+        app.planned_targets = vec![0, 1];
+        apply_plan(&mut app).unwrap();
+        poll_apply(&mut app, true);
+
+        // Stand in for a worker that reports the first group, then sees the cancel flag.
+        let (tx, rx) = std::sync::mpsc::channel();
+        let run = app.apply_run.as_mut().unwrap();
+        for entry in &mut run.entries {
+            entry.1 = GroupStatus::Pending;
+        }
+        run.rx = Some(rx);
+        handle_key(&mut app, KeyCode::Char('c'), KeyModifiers::NONE).unwrap();
+        assert!(app.message.starts_with("Cancelling"));
+        assert!(app
+            .apply_run
+            .as_ref()
+            .unwrap()
+            .cancel
+            .load(Ordering::Relaxed));
+        tx.send(GroupOutcome {
+            base_path: app.groups[0].base_path.clone(),
+            result: Ok(None),
+            chosen_hash: None,
+            warnings: Vec::new(),
+        })
+        .unwrap();
+        drop(tx);
+        poll_apply(&mut app, false);
+        let run = app.apply_run.as_ref().unwrap();
+        assert!(!run.running());
+        assert_eq!(run.entries[0].1, GroupStatus::Done);
+        assert_eq!(run.entries[1].1, GroupStatus::Cancelled);
+        assert!(app.message.starts_with("Cancelled:"), "{}", app.message);
+
+        // R runs the group that never started.
+        retry_failed(&mut app, false);
+        assert_eq!(
+            app.apply_run.as_ref().unwrap().entries[0].1,
+            GroupStatus::Done
+        );
+        poll_apply(&mut app, true);
+        let run = app.apply_run.as_ref().unwrap();
+        assert!(run.entries.iter().all(|(_, st)| *st == GroupStatus::Done));
+    }
+
+    #[test]
+    fn merge_groups_keeps_picks_and_marks_new() {
+        let cand = |p: &str| Candidate {
+            path: PathBuf::from(p),
+            exists: true,
+            is_original: false,
+            size: None,
+            modified: None,
+            label: p.to_string(),
+            trust: None,
+        };
+        let group = |base: &str, cands: &[&str]| ConflictGroup {
+            base_path: PathBuf::from(base),
+            candidates: cands.iter().map(|c| cand(c)).collect(),
+            chosen: None,
+            references: vec![],
+        };
+
+        let mut app = test_app(vec![group("b", &["b", "b.c1"]), group("c", &["c", "c.c1"])]);
+        app.groups[0].chosen = Some(1);
+        app.selected_groups.insert(1);
+        app.list_state.select(Some(1));
+
+        let fresh = vec![
+            group("a", &["a", "a.c1"]),
+            group("b", &["b", "b.c1"]),
+            group("c", &["c", "c.c1", "c.c2"]),
+        ];
+        let added = merge_groups(&mut app, fresh);
+
+        assert_eq!(added, 2);
+        assert!(app.new_groups.contains(Path::new("a")));
+        assert!(app.new_groups.contains(Path::new("c")));
+        assert_eq!(app.groups[1].chosen, Some(1));
+        assert_eq!(app.selected_groups, BTreeSet::from([2]));
+        assert_eq!(app.list_state.selected(), Some(2));
+    }
+
+    #[test]
+    fn list_nav_bounds() {
+        let mut state = ListState::default();
+        move_selection(&mut state, 0, 1);
+        assert_eq!(state.selected(), None);
 
-    Ok((left, right))
-}
+        move_selection(&mut state, 3, 1);
+        assert_eq!(state.selected(), Some(0));
+        move_selection(&mut state, 3, 1);
+        assert_eq!(state.selected(), Some(1));
+        move_selection(&mut state, 3, 1);
+        assert_eq!(state.selected(), Some(2));
+        move_selection(&mut state, 3, 1);
+        assert_eq!(state.selected(), Some(2));
 
-fn compare_command(left: &Path, right: &Path) -> Command {
-    if cfg!(target_os = "windows") {
-        let mut c = Command::new("cmd");
-        c.arg("/C").arg("fc").arg(left).arg(right);
-        return c;
+        move_selection(&mut state, 3, -1);
+        assert_eq!(state.selected(), Some(1));
+        move_selection(&mut state, 3, -1);
+        assert_eq!(state.selected(), Some(0));
+        move_selection(&mut state, 3, -1);
+        assert_eq!(state.selected(), Some(0));
     }
 
-    let mut c = Command::new("diff");
-    c.arg("-u").arg(left).arg(right);
-    c
-}
-
-fn launch_compare(app: &mut App) -> Result<()> {
-    app.compare_error = None;
+    #[test]
+    fn history_subcommand_and_root_path_both_parse() {
+        let a = Args::try_parse_from(["synctui-resolver", "history", "--by-device"]).unwrap();
+        assert!(matches!(
+            a.command,
+            Some(commands::Command::History(ref h)) if h.by_device
+        ));
+        let a = Args::try_parse_from(["synctui-resolver", "--apply", "some/dir"]).unwrap();
+        assert!(a.command.is_none());
+        assert_eq!(a.path, PathBuf::from("some/dir"));
+    }
 
-    let (left, right) = match current_group_paths(app) {
-        Ok(v) => v,
-        Err(e) => {
-            let msg = format!("{e:#}");
-            app.compare_error = Some(msg.clone());
-            app.last_error = Some(msg);
-            app.message = "Compare failed".to_string();
-            app.mode = Mode::Error;
-            return Ok(());
+    #[test]
+    fn dir_batch_confirms_each_directory_and_keeps_later_picks() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        for dir in ["a", "b"] {
+            std::fs::create_dir(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join("f.txt"), "orig").unwrap();
+            std::fs::write(
+                root.join(dir)
+                    .join("f.txt.sync-conflict-20240101-120000-ABC"),
+                "new",
+            )
+            .unwrap();
         }
-    };
-
-    if left == right {
-        app.message = "Compare: selected is original".to_string();
-        return Ok(());
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        for g in &mut groups {
+            g.chosen = Some(1);
+        }
+        let mut app = test_app(groups);
+        app.root = root.clone();
+        // The tempdir itself is a dot-directory.
+        app.include_hidden = true;
+        app.apply = true;
+        let none = KeyModifiers::NONE;
+
+        handle_key(&mut app, KeyCode::Char('B'), none).unwrap();
+        assert_eq!(app.mode, Mode::Confirm);
+        assert_eq!(app.dir_batch.as_ref().unwrap().current, Path::new("a"));
+        assert_eq!(app.planned_targets.len(), 1);
+
+        handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+        poll_apply(&mut app, true);
+        handle_key(&mut app, KeyCode::Enter, none).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("a/f.txt")).unwrap(),
+            "new"
+        );
+
+        // The rescan after directory `a` kept the pick for `b`.
+        assert_eq!(app.mode, Mode::Confirm);
+        assert_eq!(app.dir_batch.as_ref().unwrap().current, Path::new("b"));
+        let gi = app.planned_targets[0];
+        assert_eq!(app.groups[gi].chosen, Some(1));
+
+        handle_key(&mut app, KeyCode::Char('s'), none).unwrap();
+        assert_eq!(app.mode, Mode::List);
+        assert!(app.dir_batch.is_none());
+        assert_eq!(
+            std::fs::read_to_string(root.join("b/f.txt")).unwrap(),
+            "orig"
+        );
     }
 
-    if app.mode == Mode::Confirm {
-        app.message = "Compare not available in confirm view".to_string();
-        return Ok(());
+    #[test]
+    fn applies_are_refused_below_min_free_space() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().to_path_buf();
+        std::fs::write(root.join("f.txt"), "orig").unwrap();
+        std::fs::write(root.join("f.txt.sync-conflict-20240101-120000-ABC"), "new").unwrap();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+        let mut app = test_app(groups);
+        app.root = root.clone();
+        app.options.min_free_space = Some(u64::MAX);
+        app.list_state.select(Some(0));
+        let none = KeyModifiers::NONE;
+
+        // Dry-runs don't write, so they still run.
+        handle_key(&mut app, KeyCode::Char('a'), none).unwrap();
+        handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+        assert_eq!(app.mode, Mode::Apply);
+        poll_apply(&mut app, true);
+        handle_key(&mut app, KeyCode::Enter, none).unwrap();
+
+        app.apply = true;
+        handle_key(&mut app, KeyCode::Char('a'), none).unwrap();
+        handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+        assert_eq!(app.mode, Mode::Error);
+        assert_eq!(app.message, "Low disk space; nothing applied");
+        assert!(app
+            .last_error
+            .as_ref()
+            .unwrap()
+            .contains("below min_free_space"));
+        assert!(app.apply_run.is_none());
+        assert_eq!(std::fs::read_to_string(root.join("f.txt")).unwrap(), "orig");
     }
 
-    restore_terminal_for_child(app, || {
-        let output = compare_command(&left, &right)
-            .output()
-            .context("run diff")?;
+    #[test]
+    fn confirm_offers_to_ignore_the_first_archive_in_stignore() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join(".stfolder")).unwrap();
+        std::fs::create_dir(root.join("docs")).unwrap();
+        std::fs::write(root.join("docs/f.txt"), "orig").unwrap();
+        std::fs::write(
+            root.join("docs/f.txt.sync-conflict-20240101-120000-ABC"),
+            "new",
+        )
+        .unwrap();
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+        let mut app = test_app(groups);
+        app.root = root.join("docs");
+        app.list_state.select(Some(0));
+        let none = KeyModifiers::NONE;
+
+        // Dry-runs create no archive, so there is nothing to offer.
+        handle_key(&mut app, KeyCode::Char('a'), none).unwrap();
+        assert!(!render(&mut app, 160, 40).contains("doesn't ignore"));
+
+        app.apply = true;
+        let screen = render(&mut app, 160, 40);
+        assert!(
+            screen.contains("doesn't ignore .stconflict-archive"),
+            "{screen}"
+        );
+        assert!(screen.contains(" i ignore archive "), "{screen}");
+        handle_key(&mut app, KeyCode::Char('i'), none).unwrap();
+        let stignore = std::fs::read_to_string(root.join(".stignore")).unwrap();
+        assert!(
+            stignore.ends_with("(?d).stconflict-archive\n"),
+            "{stignore}"
+        );
+        assert!(app.stignore_offer.is_none());
+        assert_eq!(app.mode, Mode::Confirm);
+
+        // Planning again doesn't offer it twice.
+        handle_key(&mut app, KeyCode::Char('n'), none).unwrap();
+        handle_key(&mut app, KeyCode::Char('a'), none).unwrap();
+        assert!(app.stignore_offer.is_none());
+    }
 
-        let mut combined = String::new();
-        combined.push_str(&String::from_utf8_lossy(&output.stdout));
-        combined.push_str(&String::from_utf8_lossy(&output.stderr));
-        let combined = combined.trim().to_string();
+    #[cfg(unix)]
+    #[test]
+    fn hooks_run_around_real_applies() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("r");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("f.txt"), "orig").unwrap();
+        std::fs::write(root.join("f.txt.sync-conflict-20240101-120000-ABC"), "new").unwrap();
+        let log = td.path().join("hooks.log");
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        groups[0].chosen = Some(1);
+        let mut app = test_app(groups);
+        app.root = root.clone();
+        app.apply = true;
+        app.hooks = Hooks {
+            pre_apply: Some("echo dirty tree >&2; exit 1".to_string()),
+            post_group: Some(format!("echo \"$SYNCTUI_HOOK $SYNCTUI_PATHS\" >> {log:?}")),
+            post_apply: Some(format!("grep -c applied >> {log:?}")),
+        };
+        app.list_state.select(Some(0));
+        let none = KeyModifiers::NONE;
 
-        // diff exit codes:
-        //   0 = no differences
-        //   1 = differences found
-        //  >1 = error
-        match output.status.code() {
-            Some(0) => Err(anyhow!("No differences")),
-            Some(1) => Err(anyhow!(if combined.is_empty() {
-                "files differ".to_string()
-            } else {
-                combined
-            })),
-            _ => Err(anyhow!(
-                "diff exited with {}{}{}",
-                output.status,
-                if combined.is_empty() { "" } else { "\n\n" },
-                combined
-            )),
-        }
-    })
-}
+        // A failing pre_apply hook refuses the batch.
+        handle_key(&mut app, KeyCode::Char('a'), none).unwrap();
+        handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+        assert_eq!(app.mode, Mode::Error);
+        assert_eq!(app.message, "pre_apply hook failed; nothing applied");
+        assert!(app.last_error.as_ref().unwrap().ends_with(": dirty tree"));
+        assert_eq!(std::fs::read_to_string(root.join("f.txt")).unwrap(), "orig");
 
-fn restore_terminal_for_child<F>(app: &mut App, f: F) -> Result<()>
-where
-    F: FnOnce() -> Result<()>,
-{
-    disable_raw_mode()?;
-    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+        app.hooks.pre_apply = None;
+        app.mode = Mode::List;
+        handle_key(&mut app, KeyCode::Char('a'), none).unwrap();
+        handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+        poll_apply(&mut app, true);
+        assert_eq!(std::fs::read_to_string(root.join("f.txt")).unwrap(), "new");
+        let logged = std::fs::read_to_string(&log).unwrap();
+        let group = root.join("f.txt");
+        assert_eq!(logged, format!("post_group {}\n1\n", group.display()));
+    }
 
-    let res = f();
+    #[test]
+    fn undo_rolls_back_applied_batches_in_reverse_order() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(root.join(name), "orig").unwrap();
+            let conflict = format!("{name}.sync-conflict-20240101-120000-ABC");
+            std::fs::write(root.join(conflict), "new").unwrap();
+        }
+        let mut app = test_app(scan_conflicts(&root, true).unwrap());
+        app.root = root.clone();
+        app.include_hidden = true;
+        app.apply = true;
+        let none = KeyModifiers::NONE;
+        let ctrl_z = |app: &mut App| handle_key(app, KeyCode::Char('z'), KeyModifiers::CONTROL);
+
+        // Two batches: keep the conflict of a.txt, then of b.txt.
+        for _ in 0..2 {
+            app.list_state.select(Some(0));
+            app.groups[0].chosen = Some(1);
+            handle_key(&mut app, KeyCode::Char('a'), none).unwrap();
+            handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+            poll_apply(&mut app, true);
+            handle_key(&mut app, KeyCode::Enter, none).unwrap();
+        }
+        assert!(app.groups.is_empty());
+        assert_eq!(app.undo_stack.len(), 2);
+        let read = |name: &str| std::fs::read_to_string(root.join(name)).unwrap();
+        assert_eq!((read("a.txt"), read("b.txt")), ("new".into(), "new".into()));
+
+        ctrl_z(&mut app).unwrap();
+        assert_eq!(app.message, "Undid 1 groups (1 more batches to undo)");
+        assert_eq!(
+            (read("a.txt"), read("b.txt")),
+            ("new".into(), "orig".into())
+        );
+        assert_eq!(app.groups.len(), 1);
+        assert_eq!(app.groups[0].base_path, root.join("b.txt"));
+        // The undone group comes back with the version it had kept picked.
+        let kept = &app.groups[0].candidates[app.groups[0].chosen.unwrap()];
+        assert!(kept.path.to_string_lossy().contains("sync-conflict"));
+
+        ctrl_z(&mut app).unwrap();
+        assert_eq!(read("a.txt"), "orig");
+        assert_eq!(app.groups.len(), 2);
+        ctrl_z(&mut app).unwrap();
+        assert_eq!(app.message, "Nothing to undo");
+    }
 
-    enable_raw_mode()?;
-    execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+    #[test]
+    fn confirm_groups_the_plan_by_directory_in_foldable_sections() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        for f in ["docs/a.txt", "docs/b.txt", "photos/2024/c.jpg", "d.txt"] {
+            let path = root.join(f);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "orig").unwrap();
+            let conflict = format!("{}.sync-conflict-20240101-120000-ABC", path.display());
+            std::fs::write(conflict, "new").unwrap();
+        }
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        for g in &mut groups {
+            g.chosen = Some(0);
+        }
+        let mut app = test_app(groups);
+        app.root = root.clone();
+        let none = KeyModifiers::NONE;
+        let press = |app: &mut App, code| handle_key(app, code, none).unwrap();
+
+        press(&mut app, KeyCode::Char('*'));
+        press(&mut app, KeyCode::Char('A'));
+        assert_eq!(app.mode, Mode::Confirm);
+        // Several directories open folded, one row each, sorted.
+        let rows = plan_rows(&app.planned_ops, &app.plan_folded);
+        let dirs: Vec<_> = rows
+            .iter()
+            .map(|r| match r {
+                PlanRow::Dir { dir, groups, .. } => (dir.display().to_string(), *groups),
+                PlanRow::Line(_) => panic!("folded plan shows a line"),
+            })
+            .collect();
+        assert_eq!(
+            dirs,
+            [
+                (".".to_string(), 1),
+                ("docs".to_string(), 2),
+                ("photos/2024".to_string(), 1)
+            ]
+        );
+        let screen = render(&mut app, 100, 40);
+        assert!(screen.contains("▸ docs/  2 groups"), "{screen}");
+        assert!(screen.contains("Estimated: <1s: 4 renames"), "{screen}");
+        assert!(!screen.contains("Group: docs/a.txt"));
+
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        let screen = render(&mut app, 100, 40);
+        assert!(screen.contains("▾ docs/  2 groups"), "{screen}");
+        assert!(screen.contains("Group: docs/a.txt"));
+        assert!(screen.contains("Group: docs/b.txt"));
+        assert!(!screen.contains("Group: photos/2024/c.jpg"));
+
+        // The footer follows the cursor into the section; Left folds it from any row.
+        press(&mut app, KeyCode::Down);
+        assert!(app.planned_ops[app.log_scroll].starts_with("Group: docs/a.txt"));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Left);
+        assert_eq!(app.plan_state.selected(), Some(1));
+        assert!(app.plan_folded.contains(Path::new("docs")));
+        press(&mut app, KeyCode::Right);
+        assert!(!app.plan_folded.contains(Path::new("docs")));
+        assert_eq!(app.mode, Mode::Confirm);
+    }
 
-    match res {
-        Ok(()) => {
-            app.message = "Compare done".to_string();
-            Ok(())
+    #[test]
+    fn long_plans_scroll_and_can_be_searched() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        for i in 0..30 {
+            std::fs::write(root.join(format!("f{i:02}.txt")), "orig").unwrap();
+            let conflict = format!("f{i:02}.txt.sync-conflict-20240101-120000-ABC");
+            std::fs::write(root.join(conflict), "new").unwrap();
         }
-        Err(e) => {
-            let msg = format!("{e:#}");
-            app.compare_error = Some(msg.clone());
-            app.last_error = Some(msg);
-            app.message = "Compare".to_string();
-            app.mode = Mode::Error;
-            Ok(())
+        let mut groups = scan_conflicts(&root, true).unwrap();
+        for g in &mut groups {
+            g.chosen = Some(0);
+        }
+        let mut app = test_app(groups);
+        app.root = root.clone();
+        let none = KeyModifiers::NONE;
+        let press = |app: &mut App, code| handle_key(app, code, none).unwrap();
+
+        press(&mut app, KeyCode::Char('*'));
+        press(&mut app, KeyCode::Char('A'));
+        assert_eq!(app.mode, Mode::Confirm);
+        let total = app.planned_ops.len();
+        let screen = render(&mut app, 100, 30);
+        assert!(
+            screen.contains("1 directories (Up/Down move, Enter fold, Tab search)"),
+            "{screen}"
+        );
+        // The first row is the root's heading, then the plan lines.
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.log_scroll, 1);
+        assert!(render(&mut app, 100, 30).contains(&format!("Plan / Log (2/{total})")));
+
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(app.mode, Mode::Log);
+        press(&mut app, KeyCode::Char('/'));
+        assert_eq!(app.mode, Mode::LogSearch);
+        for ch in "F17.TXT".chars() {
+            press(&mut app, KeyCode::Char(ch));
         }
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::Log);
+        let first = app.log_scroll;
+        assert!(app.planned_ops[first].contains("f17.txt"));
+        assert!(app.message.starts_with("Match 1 of "), "{}", app.message);
+        assert!(render(&mut app, 100, 30).contains("/F17.TXT: "));
+        press(&mut app, KeyCode::Char('n'));
+        assert!(app.log_scroll > first);
+        assert!(app.planned_ops[app.log_scroll].contains("f17.txt"));
+        press(&mut app, KeyCode::Char('N'));
+        assert_eq!(app.log_scroll, first);
+
+        press(&mut app, KeyCode::Char('/'));
+        app.input = "no such file".to_string();
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.message, "No match for \"no such file\"");
+        assert_eq!(app.log_scroll, first);
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.mode, Mode::Confirm);
     }
-}
 
-fn ui(f: &mut ratatui::Frame, app: &mut App) {
-    let theme = Theme::default();
+    #[test]
+    fn only_differing_hides_identical_groups() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path();
+        for (name, orig, conflict) in [("same.txt", "x", "x"), ("diff.txt", "x", "y")] {
+            std::fs::write(root.join(name), orig).unwrap();
+            std::fs::write(
+                root.join(format!("{name}.sync-conflict-20240101-120000-ABC")),
+                conflict,
+            )
+            .unwrap();
+        }
+        let groups = scan_conflicts(root, true).unwrap();
+        let mut app = test_app(groups.clone());
+        app.list_state.select(Some(1));
 
-    let area = f.area();
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(4),
-            Constraint::Min(0),
-            Constraint::Length(5),
-        ])
-        .split(area);
+        // Hashing runs in the background; the list is complete until it is done.
+        start_identity_checks(&mut app);
+        poll_hashing(&mut app, true);
+        assert_eq!(app.groups.len(), 2);
+        app.only_differing = true;
+        app.identical.clear();
+        start_identity_checks(&mut app);
+        assert!(render(&mut app, 100, 20).contains("hashing, Esc stops"));
+        poll_hashing(&mut app, true);
+        assert!(app.identity_checks.is_none());
+        assert_eq!(app.groups.len(), 1);
+        assert_eq!(app.groups[0].base_path, root.join("diff.txt"));
+        assert_eq!(app.list_state.selected(), Some(0));
+        assert_eq!(app.message, "Hid 1 groups with identical versions");
+        assert_eq!(app.identical[&root.join("same.txt")], Some(true));
+        // Known results filter a rescan right away.
+        assert_eq!(filter_identical(&app, groups).len(), 1);
+
+        // Esc stops the checks.
+        app.identical.clear();
+        start_identity_checks(&mut app);
+        let cancel = app.identity_checks.as_ref().unwrap().cancel.clone();
+        handle_key(&mut app, KeyCode::Esc, KeyModifiers::NONE).unwrap();
+        assert!(app.identity_checks.is_none());
+        assert!(cancel.load(Ordering::Relaxed));
+    }
 
-    let mode_badge = Span::styled(
-        if app.apply { " APPLY " } else { " DRY-RUN " },
-        if app.apply {
-            theme.badge_apply
-        } else {
-            theme.badge_dry_run
-        },
-    );
+    #[test]
+    fn tree_view_selects_and_folds_whole_directories() {
+        let groups = ["docs/a.txt", "docs/b.txt", "top.txt"]
+            .iter()
+            .map(|p| ConflictGroup {
+                base_path: Path::new("/").join(p),
+                candidates: vec![],
+                chosen: None,
+                references: vec![],
+            })
+            .collect();
+        let mut app = test_app(groups);
+        app.list_state.select(Some(2));
+        let none = KeyModifiers::NONE;
+
+        handle_key(&mut app, KeyCode::Char('T'), none).unwrap();
+        // Rows: top.txt, docs/, a.txt, b.txt; the cursor follows the selected group.
+        assert_eq!(app.tree_state.selected(), Some(0));
+
+        handle_key(&mut app, KeyCode::Down, none).unwrap();
+        assert_eq!(app.list_state.selected(), None);
+        handle_key(&mut app, KeyCode::Char(' '), none).unwrap();
+        assert_eq!(app.selected_groups, BTreeSet::from([0, 1]));
+
+        handle_key(&mut app, KeyCode::Enter, none).unwrap();
+        assert!(app.collapsed.contains(Path::new("docs")));
+        handle_key(&mut app, KeyCode::Char('G'), none).unwrap();
+        assert_eq!(app.tree_state.selected(), Some(1));
+    }
 
-    let help = match app.mode {
-        Mode::List => "List: Up/Down | Enter pick specific | Space select | c current, n newest, p oldest (uppercase = selected) | d diff | a/A confirm | t toggle apply | q quit",
-        Mode::Pick => "Pick: Up/Down | Enter choose | o current | n newest | p oldest | d diff | t toggle apply | Esc back",
-        Mode::Confirm => "Confirm: y run | t toggle apply | n cancel | Esc back",
-        Mode::Error => "Error: Esc/Enter/q to dismiss",
-        Mode::Done => "Done",
-    };
+    #[test]
+    fn cleanup_lists_leftovers_by_kind_and_deletes_them_when_applying() {
+        let td = tempfile::tempdir().unwrap();
+        let temp = td.path().join(".syncthing.f.txt.tmp");
+        let nested = td
+            .path()
+            .join(".stversions/f.txt.sync-conflict-20240101-000000-DEV");
+        std::fs::create_dir_all(nested.parent().unwrap()).unwrap();
+        std::fs::write(&temp, "x").unwrap();
+        std::fs::write(&nested, "x").unwrap();
+        let mut app = test_app(vec![]);
+        app.root = td.path().to_path_buf();
+        let none = KeyModifiers::NONE;
+
+        handle_key(&mut app, KeyCode::Char('W'), none).unwrap();
+        assert_eq!(app.mode, Mode::Cleanup);
+        let screen = render(&mut app, 100, 30);
+        assert!(screen.contains("Syncthing temp files (1)"));
+        assert!(screen.contains("Conflicts inside .stversions (1)"));
+        handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+        assert!(app.message.starts_with("Dry-run: would delete 2"));
+        assert!(temp.exists() && nested.exists());
+
+        app.apply = true;
+        handle_key(&mut app, KeyCode::Char('W'), none).unwrap();
+        handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+        assert_eq!(app.mode, Mode::List);
+        assert!(!temp.exists() && !nested.exists());
+        handle_key(&mut app, KeyCode::Char('W'), none).unwrap();
+        assert_eq!(app.message, "Nothing to clean up");
+    }
 
-    let root_str = app.root.display().to_string();
-    let root_max = (area.width as usize).saturating_sub(26).max(20);
-    let root_short = shorten_middle(&root_str, root_max);
+    #[test]
+    fn prune_previews_then_deletes_only_when_applying() {
+        let td = tempfile::tempdir().unwrap();
+        let archive = td.path().join(ARCHIVE_DIR);
+        std::fs::create_dir_all(&archive).unwrap();
+        std::fs::write(archive.join("f.txt.1"), "x").unwrap();
+        std::fs::write(
+            archive.join(ARCHIVE_INDEX),
+            format!("0\tf.txt\t{}\n", archive.join("f.txt.1").display()),
+        )
+        .unwrap();
+        let mut app = test_app(vec![]);
+        app.root = td.path().to_path_buf();
+        let none = KeyModifiers::NONE;
+
+        handle_key(&mut app, KeyCode::Char('X'), none).unwrap();
+        for ch in "30d".chars() {
+            handle_key(&mut app, KeyCode::Char(ch), none).unwrap();
+        }
+        handle_key(&mut app, KeyCode::Enter, none).unwrap();
+        assert_eq!(app.mode, Mode::Prune);
+        assert_eq!(app.prune_preview.len(), 1);
+        handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+        assert!(archive.join("f.txt.1").exists());
+
+        app.apply = true;
+        handle_key(&mut app, KeyCode::Char('X'), none).unwrap();
+        app.input = "30d".to_string();
+        handle_key(&mut app, KeyCode::Enter, none).unwrap();
+        handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+        assert_eq!(app.mode, Mode::List);
+        assert!(!archive.join("f.txt.1").exists());
+    }
 
-    let selected = app.selected_groups.len();
-    let chosen = app.groups.iter().filter(|g| g.chosen.is_some()).count();
-    let counts = format!(
-        "groups:{}  picked:{}  selected:{}",
-        app.groups.len(),
-        chosen,
-        selected
-    );
+    #[test]
+    fn a_file_replaced_while_confirming_is_planned_again_before_applying() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("r");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("f.txt"), "a").unwrap();
+        std::fs::write(root.join("f.txt.sync-conflict-20240101-000000-DEV"), "b").unwrap();
+        let mut app = test_app(scan_conflicts(&root, true).unwrap());
+        app.root = root.clone();
+        app.groups[0].chosen = Some(1);
+        app.list_state.select(Some(0));
+        let none = KeyModifiers::NONE;
+
+        handle_key(&mut app, KeyCode::Char('a'), none).unwrap();
+        assert_eq!(app.mode, Mode::Confirm);
+        // Syncthing swaps in a new original through a temp file.
+        let tmp = root.join(".syncthing.f.txt.tmp");
+        std::fs::write(&tmp, "newer and longer").unwrap();
+        std::fs::rename(&tmp, root.join("f.txt")).unwrap();
+
+        handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+        assert_eq!(app.mode, Mode::Confirm);
+        assert_eq!(app.plan_changes.len(), 1);
+        assert!(app.plan_changes[0].starts_with("f.txt: "));
+        assert_eq!(app.groups[0].candidates[0].size, Some(16));
+        assert!(render(&mut app, 100, 30).contains("Changed on disk since planning"));
+
+        // Nothing changed since the new plan: `y` applies it.
+        handle_key(&mut app, KeyCode::Char('y'), none).unwrap();
+        assert_eq!(app.mode, Mode::Apply);
+        poll_apply(&mut app, true);
+    }
 
-    let header = Paragraph::new(vec![
-        Line::from(vec![
-            Span::styled("synctui-resolver", theme.header_title),
-            Span::raw("  "),
-            mode_badge,
-            Span::raw("  "),
-            Span::styled(format!("root: {root_short}"), theme.header_meta),
-        ]),
-        Line::from(Span::styled(counts, theme.header_meta)),
-        Line::from(Span::styled(help, theme.header_help)),
-    ])
-    .wrap(Wrap { trim: true });
-    f.render_widget(header, chunks[0]);
+    #[test]
+    fn confirm_exports_the_plan_to_a_file() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("r");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("f.txt"), "a").unwrap();
+        std::fs::write(root.join("f.txt.sync-conflict-20240101-000000-DEV"), "b").unwrap();
+        let mut app = test_app(scan_conflicts(&root, true).unwrap());
+        app.root = root.clone();
+        app.groups[0].chosen = Some(1);
+        app.list_state.select(Some(0));
+        let out = td.path().join("plan.json");
+        app.plan_out = Some(out.clone());
+
+        handle_key(&mut app, KeyCode::Char('a'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::Confirm);
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(json[0]["ops"].as_array().unwrap().len(), 2);
+
+        let txt = td.path().join("plan.txt");
+        handle_key(&mut app, KeyCode::Char('w'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::PlanOut);
+        app.input = txt.display().to_string();
+        handle_key(&mut app, KeyCode::Enter, KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::Confirm);
+        assert!(std::fs::read_to_string(&txt).unwrap().contains("replace\t"));
+    }
 
-    match app.mode {
-        Mode::List | Mode::Confirm | Mode::Done | Mode::Error => {
-            draw_list(f, app, chunks[1], theme)
+    #[test]
+    fn mouse_opens_groups_chooses_versions_and_presses_confirm_buttons() {
+        let td = tempfile::tempdir().unwrap();
+        for name in ["f.txt", "g.txt"] {
+            std::fs::write(td.path().join(name), "a").unwrap();
+            std::fs::write(
+                td.path()
+                    .join(format!("{name}.sync-conflict-20240101-000000-DEV")),
+                "b",
+            )
+            .unwrap();
         }
-        Mode::Pick => draw_pick(f, app, chunks[1], theme),
-    }
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.root = td.path().to_path_buf();
+        app.list_state.select(Some(0));
+        let mouse = |app: &mut App, kind, column, row| {
+            let m = MouseEvent {
+                kind,
+                column,
+                row,
+                modifiers: KeyModifiers::NONE,
+            };
+            handle_mouse(app, m).unwrap();
+        };
+        let click = MouseEventKind::Down(MouseButton::Left);
+
+        render(&mut app, 100, 24);
+        mouse(&mut app, MouseEventKind::ScrollDown, 0, 0);
+        assert_eq!(app.list_state.selected(), Some(1));
+        mouse(&mut app, MouseEventKind::ScrollUp, 0, 0);
+        assert_eq!(app.list_state.selected(), Some(0));
+
+        // A second click on the same row opens it.
+        let row = app.list_area.y + 2;
+        mouse(&mut app, click, 3, row);
+        assert_eq!((app.mode, app.list_state.selected()), (Mode::List, Some(1)));
+        mouse(&mut app, click, 3, row);
+        assert_eq!(app.mode, Mode::Pick);
+
+        render(&mut app, 100, 24);
+        let row = app.pick_area.y + 2;
+        mouse(&mut app, click, 3, row);
+        assert_eq!(app.groups[1].chosen, Some(1));
 
-    draw_footer(f, app, chunks[2], theme);
+        app.mode = Mode::List;
+        handle_key(&mut app, KeyCode::Char('a'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::Confirm);
+        let screen = render(&mut app, 100, 24);
+        assert!(screen.contains(" y run ") && screen.contains(" n cancel "));
+        let (toggle, _) = app.confirm_buttons[1];
+        mouse(&mut app, click, toggle.x + 1, toggle.y);
+        assert!(app.apply);
+        let (cancel, _) = *app.confirm_buttons.last().unwrap();
+        mouse(&mut app, click, cancel.x + cancel.width - 1, cancel.y);
+        assert_eq!(app.mode, Mode::List);
+        assert_eq!(app.message, "Cancelled");
+    }
 
-    if app.mode == Mode::Confirm {
-        draw_confirm_modal(f, app, chunks[1], theme);
+    fn render(app: &mut App, width: u16, height: u16) -> String {
+        let mut term = Terminal::new(ratatui::backend::TestBackend::new(width, height)).unwrap();
+        term.draw(|f| ui(f, app)).unwrap();
+        let buf = term.backend().buffer();
+        buf.content().iter().map(|c| c.symbol()).collect()
     }
 
-    if app.mode == Mode::Error {
-        draw_error_modal(f, app, chunks[1], theme);
+    #[test]
+    fn small_terminals_get_a_compact_layout_or_a_notice() {
+        let td = tempfile::tempdir().unwrap();
+        std::fs::write(td.path().join("f.txt"), "a").unwrap();
+        std::fs::write(
+            td.path().join("f.txt.sync-conflict-20240101-000000-DEV"),
+            "b",
+        )
+        .unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.list_state.select(Some(0));
+
+        let screen = render(&mut app, 60, 15);
+        assert!(screen.contains("0/1 picked"));
+        assert!(screen.contains("f.txt"));
+        assert!(!screen.contains("synctui-resolver"));
+
+        handle_key(&mut app, KeyCode::Enter, KeyModifiers::NONE).unwrap();
+        assert!(render(&mut app, 30, 8).contains("Original"));
+
+        assert!(render(&mut app, 20, 6).contains("too small"));
+        assert!(render(&mut app, 120, 40).contains("synctui-resolver"));
     }
-}
 
-fn shorten_middle(s: &str, max_chars: usize) -> String {
-    let chars: Vec<char> = s.chars().collect();
-    if chars.len() <= max_chars {
-        return s.to_string();
+    #[test]
+    fn long_lists_show_position_header_and_scrollbar() {
+        let groups = (0..40)
+            .map(|i| ConflictGroup {
+                base_path: PathBuf::from(format!("/f{i:02}.txt")),
+                candidates: Vec::new(),
+                chosen: None,
+                references: Vec::new(),
+            })
+            .collect();
+        let mut app = test_app(groups);
+        app.list_state.select(Some(29));
+
+        let screen = render(&mut app, 100, 30);
+        assert!(screen.contains("Conflicts (30 of 40)"));
+        assert!(screen.contains("Sel = Path"));
+        assert!(screen.contains('█'));
+
+        // Clicks land on the row drawn under the header.
+        let offset = app.list_state.offset();
+        let first_row = app.list_area.y + 1;
+        assert_eq!(list_row_at(&app, 5, first_row), Some(offset));
+        let top: String = screen
+            .chars()
+            .skip(usize::from(first_row) * 100)
+            .take(100)
+            .collect();
+        assert!(top.contains(&format!("f{offset:02}.txt")));
     }
-    if max_chars <= 5 {
-        return chars.into_iter().take(max_chars).collect();
+
+    #[test]
+    fn the_highlighted_row_hints_where_each_quick_pick_lands() {
+        let now = SystemTime::now();
+        let cand = |name: &str, original: bool, age: u64| Candidate {
+            path: PathBuf::from("/r").join(name),
+            exists: true,
+            is_original: original,
+            size: Some(1),
+            modified: Some(now - Duration::from_secs(age)),
+            label: name.to_string(),
+            trust: None,
+        };
+        let group = ConflictGroup {
+            base_path: PathBuf::from("/r/f.txt"),
+            candidates: vec![
+                cand("f.txt", true, 3 * 7 * 86400),
+                cand(
+                    "f.txt.sync-conflict-20240101-000000-PHONE12",
+                    false,
+                    2 * 3600,
+                ),
+            ],
+            chosen: None,
+            references: Vec::new(),
+        };
+        let mut app = test_app(vec![group.clone(), group]);
+        app.trust_mtime = true;
+        app.list_state.select(Some(0));
+        assert_eq!(
+            quick_pick_hints(&app, 0, now),
+            "n=newest PHONE12 2h ago · p=oldest original 21d ago · c=current"
+        );
+        let screen = render(&mut app, 200, 30);
+        assert_eq!(screen.matches("n=newest PHONE12").count(), 1, "{screen}");
+
+        // Remapped keys show as bound; mtime picks a skewed group would skip say so.
+        let config = Config::parse("[keys.list]\nnewest = \"x\"\n").unwrap();
+        app.keymap = Keymap::new(&config.keys).unwrap();
+        app.trust_mtime = false;
+        app.skew_threshold = Duration::from_secs(60);
+        assert!(quick_pick_hints(&app, 0, now)
+            .starts_with("x=newest skipped: clock skew · p=oldest skipped: clock skew"));
     }
 
-    let keep = (max_chars - 3) / 2;
-    let head: String = chars.iter().take(keep).collect();
-    let tail: String = chars
-        .iter()
-        .rev()
-        .take(keep)
-        .collect::<Vec<_>>()
-        .into_iter()
-        .rev()
-        .collect();
-    format!("{head}...{tail}")
-}
+    #[test]
+    fn largest_quick_picks_keep_the_biggest_version() {
+        let now = SystemTime::now();
+        let cand = |name: &str, original: bool, size: u64, age: u64| Candidate {
+            path: PathBuf::from("/r").join(name),
+            exists: true,
+            is_original: original,
+            size: Some(size),
+            modified: Some(now - Duration::from_secs(age)),
+            label: name.to_string(),
+            trust: None,
+        };
+        let group = |name: &str| ConflictGroup {
+            base_path: PathBuf::from("/r").join(name),
+            candidates: vec![
+                cand(name, true, 4_000_000, 86400),
+                cand(
+                    &format!("{name}.sync-conflict-20240101-000000-PHONE"),
+                    false,
+                    900_000,
+                    60,
+                ),
+            ],
+            chosen: None,
+            references: Vec::new(),
+        };
+        let mut app = test_app(vec![group("a.jpg"), group("b.jpg"), group("c.jpg")]);
+        app.list_state.select(Some(0));
+        assert!(quick_pick_hints(&app, 0, now).contains("b=largest original 1d ago"));
 
-fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
-    let popup_layout = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Percentage((100 - percent_y) / 2),
-            Constraint::Percentage(percent_y),
-            Constraint::Percentage((100 - percent_y) / 2),
-        ])
-        .split(r);
+        handle_key(&mut app, KeyCode::Char('b'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.groups[0].chosen, Some(0));
+        assert_eq!(app.message, "Picked largest");
 
-    Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage((100 - percent_x) / 2),
-            Constraint::Percentage(percent_x),
-            Constraint::Percentage((100 - percent_x) / 2),
-        ])
-        .split(popup_layout[1])[1]
-}
+        app.selected_groups = BTreeSet::from([1]);
+        handle_key(&mut app, KeyCode::Char('b'), KeyModifiers::CONTROL).unwrap();
+        assert_eq!(app.groups[1].chosen, Some(0));
+        assert_eq!(app.message, "Picked largest for selected");
 
-fn draw_error_modal(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
-    let rect = centered_rect(80, 50, area);
-    f.render_widget(Clear, rect);
+        app.list_state.select(Some(2));
+        handle_key(&mut app, KeyCode::Enter, KeyModifiers::NONE).unwrap();
+        handle_key(&mut app, KeyCode::Char('b'), KeyModifiers::NONE).unwrap();
+        assert_eq!((app.mode, app.groups[2].chosen), (Mode::List, Some(0)));
+    }
 
-    let mut lines = Vec::new();
-    lines.push(Line::from(Span::styled(
-        "ERROR",
-        theme.message_error.add_modifier(Modifier::BOLD),
-    )));
-    lines.push(Line::from(""));
+    #[cfg(unix)]
+    #[test]
+    fn groups_the_preflight_blocks_are_badged_in_the_list() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        std::fs::write(root.join("elsewhere.txt"), "orig").unwrap();
+        std::os::unix::fs::symlink(root.join("elsewhere.txt"), root.join("f.txt")).unwrap();
+        std::fs::write(root.join("f.txt.sync-conflict-20240101-000000-DEV"), "c").unwrap();
+        let mut app = test_app(scan_conflicts(&root, true).unwrap());
+        app.root = root.clone();
+        app.list_state.select(Some(0));
+        app.groups[0].chosen = Some(1);
+        assert!(!render(&mut app, 140, 30).contains("BLOCKED"));
+
+        handle_key(&mut app, KeyCode::Char('a'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::Confirm);
+        assert_eq!(app.planned_blocked, 1);
+        handle_key(&mut app, KeyCode::Esc, KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::List);
+        let screen = render(&mut app, 140, 30);
+        assert!(screen.contains("BLOCKED f.txt"), "{screen}");
+
+        // Fixed and planned again, the badge goes.
+        app.options.allow_symlinks = true;
+        handle_key(&mut app, KeyCode::Char('a'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.planned_blocked, 0);
+        handle_key(&mut app, KeyCode::Esc, KeyModifiers::NONE).unwrap();
+        assert!(!render(&mut app, 140, 30).contains("BLOCKED"));
+    }
 
-    if let Some(e) = &app.last_error {
-        for l in e.lines().take((rect.height as usize).saturating_sub(6)) {
-            lines.push(Line::from(l));
+    #[test]
+    fn goto_opens_the_group_of_a_path_once_scanned() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        for dir in ["a", "b"] {
+            std::fs::create_dir(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join("f.txt"), "orig").unwrap();
+            for dev in ["DEV", "LAPTOP"] {
+                let name = format!("f.txt.sync-conflict-20240101-000000-{dev}");
+                std::fs::write(root.join(dir).join(name), dev).unwrap();
+            }
         }
-    } else {
-        lines.push(Line::from("Unknown error"));
-    }
+        let mut app = test_app(Vec::new());
+        app.root = root.clone();
+        app.include_hidden = true;
+        let copy = root.join("b/f.txt.sync-conflict-20240101-000000-LAPTOP");
+        app.goto = Some(copy.clone());
+        start_scan(&mut app, false).unwrap();
+        poll_scan(&mut app, true);
+        assert_eq!(app.mode, Mode::Pick);
+        assert_eq!(app.list_state.selected(), Some(1));
+        let ci = app.pick_state.selected().unwrap();
+        assert_eq!(app.groups[1].candidates[ci].path, copy);
+        assert!(app.goto.is_none(), "only the first scan jumps");
 
-    lines.push(Line::from(""));
-    lines.push(Line::from(vec![
-        Span::styled("Esc", theme.header_title),
-        Span::styled(": dismiss   ", theme.header_meta),
-        Span::styled("Enter", theme.header_title),
-        Span::styled(": dismiss   ", theme.header_meta),
-        Span::styled("q", theme.header_title),
-        Span::styled(": dismiss", theme.header_meta),
-    ]));
+        app.mode = Mode::List;
+        app.goto = Some(root.join("c/missing.txt"));
+        start_scan(&mut app, false).unwrap();
+        poll_scan(&mut app, true);
+        assert_eq!(app.mode, Mode::List);
+        assert_eq!(app.message, "--goto: no conflict group at c/missing.txt");
+    }
 
-    let p = Paragraph::new(lines)
-        .block(titled_block("", theme))
-        .wrap(Wrap { trim: false });
-    f.render_widget(p, rect);
-}
+    #[test]
+    fn autosaved_picks_come_back_after_a_session_that_didnt_end_normally() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(root.join(name), "orig").unwrap();
+            std::fs::write(
+                root.join(format!("{name}.sync-conflict-20240101-000000-DEV")),
+                "c",
+            )
+            .unwrap();
+        }
+        let session = td.path().join("sessions/root.json");
+        let open = || {
+            let mut app = test_app(Vec::new());
+            app.root = root.clone();
+            app.include_hidden = true;
+            app.session_path = Some(session.clone());
+            start_scan(&mut app, false).unwrap();
+            poll_scan(&mut app, true);
+            app
+        };
 
-fn draw_confirm_modal(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
-    let rect = centered_rect(80, 70, area);
-    f.render_widget(Clear, rect);
+        let mut app = open();
+        handle_key(&mut app, KeyCode::Char('c'), KeyModifiers::NONE).unwrap();
+        handle_key(&mut app, KeyCode::Down, KeyModifiers::NONE).unwrap();
+        handle_key(&mut app, KeyCode::Char(' '), KeyModifiers::NONE).unwrap();
+        autosave(&mut app);
+        assert!(session.exists());
+
+        let app = open();
+        assert_eq!(app.groups[0].chosen, Some(0));
+        assert_eq!(app.groups[1].chosen, None);
+        // Picking from the list selects the group too.
+        assert_eq!(app.selected_groups, BTreeSet::from([0, 1]));
+        assert_eq!(
+            app.message,
+            "Restored 1 picks and 2 selected groups from a session that didn't end normally"
+        );
+    }
 
-    let title = if app.apply {
-        Span::styled("CONFIRM APPLY", theme.confirm_title_apply)
-    } else {
-        Span::styled("CONFIRM DRY-RUN", theme.confirm_title_dry_run)
-    };
+    #[test]
+    fn rescans_after_an_apply_only_revisit_the_touched_folders() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        for dir in ["a", "b"] {
+            std::fs::create_dir(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join("f.txt"), "orig").unwrap();
+            std::fs::write(
+                root.join(dir)
+                    .join("f.txt.sync-conflict-20240101-000000-DEV"),
+                "c",
+            )
+            .unwrap();
+        }
+        let mut app = test_app(scan_conflicts(&root, true).unwrap());
+        app.root = root.clone();
+        app.include_hidden = true;
+        app.groups[1].chosen = Some(1);
+        app.selected_groups.insert(1);
+        app.list_state.select(Some(1));
+        // A new conflict outside the rescanned folder isn't picked up yet.
+        std::fs::write(root.join("b").join("g.txt"), "orig").unwrap();
+        std::fs::write(
+            root.join("b")
+                .join("g.txt.sync-conflict-20240101-000000-DEV"),
+            "c",
+        )
+        .unwrap();
+        std::fs::remove_file(root.join("a/f.txt.sync-conflict-20240101-000000-DEV")).unwrap();
+
+        rescan_dirs(&mut app, BTreeSet::from([root.join("a")])).unwrap();
+        assert_eq!(app.groups.len(), 1);
+        assert_eq!(app.groups[0].base_path, root.join("b/f.txt"));
+        // The untouched group keeps its pick, selection and the cursor.
+        assert_eq!(app.groups[0].chosen, Some(1));
+        assert!(app.selected_groups.contains(&0));
+        assert_eq!(app.list_state.selected(), Some(0));
+
+        // A full scan runs in the background, and Esc cancels it.
+        start_scan(&mut app, false).unwrap();
+        assert!(render(&mut app, 140, 30).contains("Esc cancels"));
+        handle_key(&mut app, KeyCode::Esc, KeyModifiers::NONE).unwrap();
+        assert!(app.scan_run.is_none());
+        assert_eq!(app.groups.len(), 1);
+        assert!(app.message.starts_with("Scan cancelled"), "{}", app.message);
+
+        start_scan(&mut app, false).unwrap();
+        poll_scan(&mut app, true);
+        assert!(app.scan_run.is_none());
+        assert_eq!(app.groups.len(), 2);
+    }
 
-    let mut lines = Vec::new();
-    if app.apply {
-        lines.push(Line::from(Span::styled(
-            "This will move files on disk.",
-            theme.message_error.add_modifier(Modifier::BOLD),
-        )));
-    } else {
-        lines.push(Line::from(Span::styled(
-            "Dry-run: no filesystem changes.",
-            theme.message_warn,
-        )));
+    #[test]
+    fn identical_conflict_copies_fold_into_one_pick_row() {
+        let td = tempfile::tempdir().unwrap();
+        std::fs::write(td.path().join("f.txt"), "orig").unwrap();
+        for (dev, body) in [("A", "same"), ("B", "else"), ("C", "same"), ("D", "same")] {
+            let name = format!("f.txt.sync-conflict-20240101-000000-{dev}");
+            std::fs::write(td.path().join(name), body).unwrap();
+        }
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.list_state.select(Some(0));
+        assert_eq!(app.groups[0].candidates.len(), 5);
+        handle_key(&mut app, KeyCode::Enter, KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::Pick);
+        // Every copy shows until the hashing in the background has folded them.
+        let screen = render(&mut app, 140, 30);
+        assert!(app.duplicates_check.is_some());
+        assert_eq!(screen.matches("sync-conflict").count(), 4, "{screen}");
+        poll_hashing(&mut app, true);
+        assert!(app.duplicates_check.is_none());
+        let screen = render(&mut app, 140, 30);
+        assert_eq!(screen.matches("×3 identical").count(), 1, "{screen}");
+        assert_eq!(screen.matches("sync-conflict").count(), 2, "{screen}");
+
+        // Moving skips the folded copies, and keeping the row keeps one of them while
+        // the plan archives the rest.
+        let rows = pick_rows(&app);
+        assert_eq!(rows.len(), 3);
+        handle_key(&mut app, KeyCode::End, KeyModifiers::NONE).unwrap();
+        assert_eq!(app.pick_state.selected(), Some(rows[2]));
+        let dup = app.duplicates[&app.groups[0].base_path].clone();
+        let folded = *rows.iter().find(|&&r| dup.contains(&Some(r))).unwrap();
+        handle_key(&mut app, KeyCode::Home, KeyModifiers::NONE).unwrap();
+        for _ in 0..rows.iter().position(|&r| r == folded).unwrap() {
+            handle_key(&mut app, KeyCode::Down, KeyModifiers::NONE).unwrap();
+        }
+        handle_key(&mut app, KeyCode::Enter, KeyModifiers::NONE).unwrap();
+        assert_eq!(app.groups[0].chosen, Some(folded));
+        assert_eq!(dup.iter().filter(|&&d| d == Some(folded)).count(), 2);
     }
-    lines.push(Line::from(""));
 
-    let planned = if app.planned_targets.is_empty() {
-        "No groups planned".to_string()
-    } else {
-        format!("Planned groups: {}", app.planned_targets.len())
-    };
-    lines.push(Line::from(planned));
-    lines.push(Line::from(""));
+    #[test]
+    fn pick_rows_show_who_saved_each_document_version() {
+        let td = tempfile::tempdir().unwrap();
+        std::fs::write(td.path().join("report.docx"), "orig").unwrap();
+        let conflict = td
+            .path()
+            .join("report.docx.sync-conflict-20240101-000000-DEV");
+        std::fs::write(&conflict, "conflict").unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.list_state.select(Some(0));
+        let now = SystemTime::now();
+        let c = &app.groups[0].candidates[1];
+        app.doc_props.insert(
+            (c.path.clone(), c.modified),
+            Some(DocProps {
+                modified_by: Some("Ann".to_string()),
+                revision: Some("14".to_string()),
+                modified: Some(now - Duration::from_secs(2 * 3600)),
+            }),
+        );
+        handle_key(&mut app, KeyCode::Enter, KeyModifiers::NONE).unwrap();
+        let screen = render(&mut app, 160, 30);
+        assert!(
+            screen.contains("by Ann · rev 14 · saved 2h ago"),
+            "{screen}"
+        );
+        // Read (and found to be no document) for the original.
+        let o = &app.groups[0].candidates[0];
+        assert_eq!(
+            app.doc_props.get(&(o.path.clone(), o.modified)),
+            Some(&None)
+        );
+
+        let partial = DocProps {
+            revision: Some("3".to_string()),
+            ..DocProps::default()
+        };
+        assert_eq!(doc_props_line(&partial, now), "rev 3");
+    }
 
-    lines.push(Line::from(vec![
-        Span::styled("y", theme.header_title),
-        Span::styled(": run   ", theme.header_meta),
-        Span::styled("t", theme.header_title),
-        Span::styled(": toggle apply   ", theme.header_meta),
-        Span::styled("n", theme.header_title),
-        Span::styled(": cancel", theme.header_meta),
-    ]));
-    lines.push(Line::from(""));
+    #[test]
+    fn clock_skew_is_flagged_and_keeps_newest_from_picking() {
+        // Written now, but the conflict name says 2024-01-01.
+        let td = tempfile::tempdir().unwrap();
+        std::fs::write(td.path().join("f.txt"), "orig").unwrap();
+        std::fs::write(
+            td.path().join("f.txt.sync-conflict-20240101-000000-DEV"),
+            "c",
+        )
+        .unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.list_state.select(Some(0));
+        assert!(render(&mut app, 140, 30).contains("SKEW "));
+
+        handle_key(&mut app, KeyCode::Char('n'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.groups[0].chosen, None);
+        assert!(app.message.contains("skipped 1 with clock skew"));
+
+        handle_key(&mut app, KeyCode::Enter, KeyModifiers::NONE).unwrap();
+        assert!(app.message.starts_with("Clock skew"));
+        assert!(render(&mut app, 140, 30).contains("skew:"));
+        handle_key(&mut app, KeyCode::Char('p'), KeyModifiers::NONE).unwrap();
+        assert_eq!((app.mode, app.groups[0].chosen), (Mode::Pick, None));
+        handle_key(&mut app, KeyCode::Esc, KeyModifiers::NONE).unwrap();
+
+        app.trust_mtime = true;
+        handle_key(&mut app, KeyCode::Char('n'), KeyModifiers::NONE).unwrap();
+        assert!(app.groups[0].chosen.is_some());
+    }
 
-    if !app.planned_ops.is_empty() {
-        for l in app
-            .planned_ops
+    #[test]
+    fn remapped_keys_drive_the_list_and_its_help_line() {
+        let td = tempfile::tempdir().unwrap();
+        std::fs::write(td.path().join("f.txt"), "orig").unwrap();
+        std::fs::write(
+            td.path().join("f.txt.sync-conflict-20240101-000000-DEV"),
+            "c",
+        )
+        .unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.trust_mtime = true;
+        app.list_state.select(Some(0));
+        let config =
+            Config::parse("[keys.list]\noldest = \"x\"\n[keys.global]\nquit = \"Ctrl-q\"\n")
+                .unwrap();
+        app.keymap = Keymap::new(&config.keys).unwrap();
+        assert!(render(&mut app, 300, 30).contains("| x, P oldest |"));
+
+        // The old key does nothing; the new one picks.
+        handle_key(&mut app, KeyCode::Char('p'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.groups[0].chosen, None);
+        handle_key(&mut app, KeyCode::Char('x'), KeyModifiers::NONE).unwrap();
+        assert!(app.message.starts_with("Picked oldest"), "{}", app.message);
+
+        let ctrl = KeyModifiers::CONTROL;
+        assert!(!handle_key(&mut app, KeyCode::Char('c'), ctrl).unwrap());
+        assert!(handle_key(&mut app, KeyCode::Char('q'), ctrl).unwrap());
+    }
+
+    #[test]
+    fn question_mark_opens_every_views_keys_with_the_current_one_first() {
+        let mut app = test_app(Vec::new());
+        let config = Config::parse("[keys.confirm]\nrun = \"Y\"\n").unwrap();
+        app.keymap = Keymap::new(&config.keys).unwrap();
+        let key = |app: &mut App, code| handle_key(app, code, KeyModifiers::NONE).unwrap();
+
+        assert!(render(&mut app, 120, 30).contains("? all keys"));
+        key(&mut app, KeyCode::Char('?'));
+        assert_eq!(app.mode, Mode::Help);
+        let lines: Vec<String> = help_lines(&app, Theme::default())
             .iter()
-            .take((rect.height as usize).saturating_sub(9))
-        {
-            lines.push(Line::from(l.as_str()));
+            .map(Line::to_string)
+            .collect();
+        assert_eq!(lines[0], "list (this view)");
+        let section = |name: &str| lines.iter().position(|l| l == name).unwrap();
+        assert!(section("global (every view)") < section("confirm"));
+        let confirm = &lines[section("confirm")..];
+        assert!(confirm.iter().any(|l| l.trim_start().starts_with("Y ")));
+        // Actions without a help-line label are listed by name.
+        assert!(lines.iter().any(|l| l.ends_with("  half page down")));
+        assert!(render(&mut app, 120, 30).contains("Keys (1/"));
+
+        key(&mut app, KeyCode::Down);
+        assert_eq!(app.help_scroll, 1);
+        key(&mut app, KeyCode::Esc);
+        assert_eq!(app.mode, Mode::List);
+
+        // Text prompts take `?` as text.
+        app.mode = Mode::LogSearch;
+        key(&mut app, KeyCode::Char('?'));
+        assert_eq!((app.mode, app.input.as_str()), (Mode::LogSearch, "?"));
+    }
+
+    #[test]
+    fn rule_popup_writes_the_config_and_picks_matching_groups() {
+        let td = tempfile::tempdir().unwrap();
+        let docs = td.path().join("docs");
+        std::fs::create_dir(&docs).unwrap();
+        for dir in [td.path(), docs.as_path()] {
+            std::fs::write(dir.join("f.txt"), "orig").unwrap();
+            std::fs::write(dir.join("f.txt.sync-conflict-20240101-000000-DEV"), "c").unwrap();
         }
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.root = td.path().to_path_buf();
+        let config_path = td.path().join("cfg/config.toml");
+        app.config_path = Some(config_path.clone());
+        app.list_state.select(Some(0));
+        let key = |app: &mut App, code| handle_key(app, code, KeyModifiers::NONE).unwrap();
+
+        key(&mut app, KeyCode::Char('w'));
+        assert_eq!(app.mode, Mode::RuleEdit);
+        assert_eq!(app.input, "docs/*.txt");
+        assert!(render(&mut app, 120, 30).contains("Matches 1 of 2 groups here"));
+        // Up to the strategy, then back past newest to largest, trusted and current.
+        key(&mut app, KeyCode::Tab);
+        key(&mut app, KeyCode::Left);
+        assert_eq!(app.rule_strategy, Strategy::Largest);
+        key(&mut app, KeyCode::Left);
+        assert_eq!(app.rule_strategy, Strategy::Trusted);
+        key(&mut app, KeyCode::Left);
+        key(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::List);
+        assert!(app.message.contains("(1 groups picked)"), "{}", app.message);
+        assert_eq!(app.groups[0].chosen, Some(0));
+        assert_eq!(app.groups[1].chosen, None);
+        let config = Config::load(Some(&config_path)).unwrap();
+        assert_eq!(config.pick_rules, app.pick_rules);
+        assert_eq!(app.pick_rules[0].strategy, Strategy::Current);
+
+        // A root file gets an anchored glob; an empty one isn't saved.
+        app.list_state.select(Some(1));
+        key(&mut app, KeyCode::Char('w'));
+        assert_eq!(app.input, "/*.txt");
+        app.input.clear();
+        key(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::RuleEdit);
+        key(&mut app, KeyCode::Esc);
+        assert_eq!(
+            Config::load(Some(&config_path)).unwrap().pick_rules.len(),
+            1
+        );
     }
 
-    let p = Paragraph::new(lines)
-        .block(titled_block("", theme).title(title))
-        .wrap(Wrap { trim: false });
-    f.render_widget(p, rect);
-}
+    #[test]
+    fn filter_popup_rescans_with_limits_and_keeps_typos_open() {
+        let td = tempfile::tempdir().unwrap();
+        for (name, size) in [("small.txt", 10), ("big.txt", 4096)] {
+            std::fs::write(td.path().join(name), "orig").unwrap();
+            std::fs::write(
+                td.path()
+                    .join(format!("{name}.sync-conflict-20240101-000000-DEV")),
+                vec![b'x'; size],
+            )
+            .unwrap();
+        }
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.root = td.path().to_path_buf();
+        app.include_hidden = true;
+        let key = |app: &mut App, code| handle_key(app, code, KeyModifiers::NONE).unwrap();
+
+        key(&mut app, KeyCode::Char('F'));
+        assert_eq!(app.mode, Mode::Filter);
+        key(&mut app, KeyCode::Tab);
+        key(&mut app, KeyCode::Tab);
+        for ch in "2 parsecs".chars() {
+            key(&mut app, KeyCode::Char(ch));
+        }
+        key(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::Filter);
+        assert!(app.message.starts_with("Filter: "));
+        assert!(render(&mut app, 120, 30).contains("Min size"));
+
+        app.filter_inputs[2] = "1K".to_string();
+        key(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::List);
+        poll_scan(&mut app, true);
+        assert_eq!(app.groups.len(), 1);
+        assert!(app.groups[0].base_path.ends_with("big.txt"));
+        assert!(render(&mut app, 140, 30).contains("filter: at least 1.0 KiB"));
+
+        // Reopening shows the limit; clearing every field shows everything again.
+        key(&mut app, KeyCode::Char('F'));
+        assert_eq!(app.filter_inputs[2], "1024");
+        key(&mut app, KeyCode::Up);
+        for ch in "images".chars() {
+            key(&mut app, KeyCode::Char(ch));
+        }
+        key(&mut app, KeyCode::Enter);
+        poll_scan(&mut app, true);
+        assert_eq!(app.filter.kind, Some(FileKind::Image));
+        assert!(app.groups.is_empty());
+        key(&mut app, KeyCode::Char('F'));
+        key(&mut app, KeyCode::Up);
+        for _ in 0.."image".len() {
+            key(&mut app, KeyCode::Backspace);
+        }
+        key(&mut app, KeyCode::Up);
+        for _ in 0..4 {
+            key(&mut app, KeyCode::Backspace);
+        }
+        key(&mut app, KeyCode::Enter);
+        poll_scan(&mut app, true);
+        assert!(app.filter.is_empty());
+        assert_eq!(app.groups.len(), 2);
+    }
 
-fn draw_list(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
-    let items: Vec<ListItem> = app
-        .groups
-        .iter()
-        .enumerate()
-        .map(|(i, g)| {
-            let (sel, sel_style) = if app.selected_groups.contains(&i) {
-                ("[*]", theme.selected_mark)
-            } else {
-                ("[ ]", theme.header_meta)
-            };
+    #[test]
+    fn header_and_stats_popup_show_conflict_space() {
+        let td = tempfile::tempdir().unwrap();
+        for dir in ["docs", "music"] {
+            std::fs::create_dir(td.path().join(dir)).unwrap();
+            std::fs::write(td.path().join(dir).join("f.txt"), "orig").unwrap();
+            std::fs::write(
+                td.path()
+                    .join(dir)
+                    .join("f.txt.sync-conflict-20240101-000000-DEV"),
+                vec![b'x'; 2048],
+            )
+            .unwrap();
+        }
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.root = td.path().to_path_buf();
+        app.groups[0].chosen = Some(0);
+
+        let screen = render(&mut app, 140, 30);
+        assert!(screen.contains("conflicts: 4.0 KiB  plan frees 0 B, archives 2.0 KiB"));
+
+        handle_key(&mut app, KeyCode::Char('S'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::Stats);
+        let screen = render(&mut app, 140, 30);
+        assert!(screen.contains("Overall"));
+        assert!(screen.contains("docs"));
+        assert!(screen.contains("music"));
+        handle_key(&mut app, KeyCode::Esc, KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::List);
+    }
 
-            let picked = match g.chosen {
-                None => Span::styled("(unpicked)", theme.unpicked),
-                Some(ci) => Span::styled(
-                    format!("(keep: {})", g.candidates[ci].label),
-                    theme.selected_mark,
-                ),
-            };
+    #[test]
+    fn f2_toggles_the_conflict_origins_view() {
+        let td = tempfile::tempdir().unwrap();
+        std::fs::create_dir(td.path().join("docs")).unwrap();
+        for name in [
+            "docs/a.txt",
+            "docs/a.sync-conflict-20240101-000000-PHONE12.txt",
+            "docs/a.sync-conflict-20240102-000000-PHONE12.txt",
+            "b.md",
+            "b.sync-conflict-20240101-000000-LAPTOP1.md",
+        ] {
+            std::fs::write(td.path().join(name), "x").unwrap();
+        }
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.root = td.path().to_path_buf();
+
+        handle_key(&mut app, KeyCode::F(2), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::Origins);
+        let screen = render(&mut app, 120, 50);
+        assert!(screen.contains("3 conflict copies"), "{screen}");
+        assert!(screen.contains("PHONE12      2"), "{screen}");
+        assert!(screen.contains("docs      2"), "{screen}");
+        assert!(screen.contains("txt      2"), "{screen}");
+        assert!(screen.contains("> 1 year"), "{screen}");
+        handle_key(&mut app, KeyCode::F(2), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::List);
+    }
 
-            let rel = Span::styled(
-                rel_path(&app.root, &g.base_path).display().to_string(),
-                Style::default(),
-            );
-            let cnt = g.candidates.len().saturating_sub(1);
-            let orig = if g.candidates.first().map(|c| c.exists).unwrap_or(false) {
-                Span::styled("orig", theme.message_info)
-            } else {
-                Span::styled("no-orig", theme.message_error)
-            };
+    #[test]
+    fn pick_view_shows_diffstats_once_computed() {
+        let td = tempfile::tempdir().unwrap();
+        std::fs::write(td.path().join("f.txt"), "a\nb\n").unwrap();
+        std::fs::write(
+            td.path().join("f.txt.sync-conflict-20240101-000000-DEV"),
+            "a\nc\nd\n",
+        )
+        .unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.list_state.select(Some(0));
 
-            ListItem::new(Line::from(vec![
-                Span::styled(sel, sel_style),
-                Span::raw(" "),
-                rel,
-                Span::styled("  [", theme.header_meta),
-                Span::styled(format!("{cnt}"), theme.header_meta),
-                Span::styled(" conflicts, ", theme.header_meta),
-                orig,
-                Span::styled("] ", theme.header_meta),
-                picked,
-            ]))
-        })
-        .collect();
+        handle_key(&mut app, KeyCode::Enter, KeyModifiers::NONE).unwrap();
+        assert!(render(&mut app, 120, 30).contains("+…"));
+        let rx = app.diffstat_rx.take().unwrap();
+        app.diffstats.extend(rx.iter());
+        assert!(render(&mut app, 120, 30).contains("+2/-1"));
+    }
 
-    let list = List::new(items)
-        .block(titled_block("Conflicts", theme))
-        .highlight_style(theme.list_highlight);
-    f.render_stateful_widget(list, area, &mut app.list_state);
-}
+    #[test]
+    fn snoozed_groups_stay_hidden_across_rescans() {
+        let td = tempfile::tempdir().unwrap();
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(td.path().join(name), "x").unwrap();
+            let conflict = format!("{name}.sync-conflict-20240101-000000-DEV");
+            std::fs::write(td.path().join(conflict), "y").unwrap();
+        }
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.root = td.path().to_path_buf();
+        app.include_hidden = true;
+        app.history = Some(History::open(&td.path().join("h.sqlite3")).unwrap());
+        app.list_state.select(Some(0));
+        let key = |app: &mut App, code| handle_key(app, code, KeyModifiers::NONE).unwrap();
 
-fn draw_pick(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
-    let gi = match app.list_state.selected() {
-        Some(i) => i,
-        None => {
-            let p = Paragraph::new("No group selected").block(titled_block("Pick", theme));
-            f.render_widget(p, area);
-            return;
+        key(&mut app, KeyCode::Char('s'));
+        assert_eq!(app.mode, Mode::SnoozeFor);
+        for ch in "3d".chars() {
+            key(&mut app, KeyCode::Char(ch));
         }
-    };
-    let g = &app.groups[gi];
+        key(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::List);
+        assert!(
+            app.message.starts_with("Snoozed 1 groups until "),
+            "{}",
+            app.message
+        );
+        let names = |app: &App| -> Vec<PathBuf> {
+            app.groups
+                .iter()
+                .map(|g| rel_path(&app.root, &g.base_path).to_path_buf())
+                .collect()
+        };
+        assert_eq!(names(&app), [PathBuf::from("b.txt")]);
+        assert_eq!(app.list_state.selected(), Some(0));
+
+        start_scan(&mut app, false).unwrap();
+        poll_scan(&mut app, true);
+        assert_eq!(names(&app), [PathBuf::from("b.txt")]);
+        let snoozed = app
+            .history
+            .as_ref()
+            .unwrap()
+            .snoozed(SystemTime::now())
+            .unwrap();
+        assert_eq!(snoozed[0].0, td.path().join("a.txt"));
+    }
 
-    let items: Vec<ListItem> = g
-        .candidates
-        .iter()
-        .enumerate()
-        .map(|(i, c)| {
-            let rel = rel_path(&app.root, &c.path).display().to_string();
-            let size = c
-                .size
-                .map(|s| format!("{s}b"))
-                .unwrap_or_else(|| "?".to_string());
-            let m = c
-                .modified
-                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
-                .map(|d| d.as_secs().to_string())
-                .unwrap_or_else(|| "?".to_string());
+    #[test]
+    fn merging_takes_hunks_from_either_side_and_writes_the_result() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path();
+        let notes = root.join("notes.md");
+        std::fs::write(&notes, "title\nbuy milk\nsame\ncall mom\n").unwrap();
+        let conflict = root.join("notes.md.sync-conflict-20240101-000000-DEV");
+        std::fs::write(&conflict, "title\nbuy oat milk\nsame\ncall dad\n").unwrap();
+        let mut app = test_app(scan_conflicts(root, true).unwrap());
+        app.root = root.to_path_buf();
+        app.list_state.select(Some(0));
+        let key = |app: &mut App, code| handle_key(app, code, KeyModifiers::NONE).unwrap();
+
+        key(&mut app, KeyCode::Enter);
+        key(&mut app, KeyCode::Char('m'));
+        assert_eq!(app.mode, Mode::Merge);
+        let screen = render(&mut app, 100, 30);
+        assert!(screen.contains("(2 of 2 hunks undecided)"), "{screen}");
+        assert!(screen.contains("< buy milk") && screen.contains("> buy oat milk"));
+        key(&mut app, KeyCode::Right);
+        assert_eq!(app.merge_state.selected(), Some(1));
+        key(&mut app, KeyCode::Enter);
+        assert!(app.message.contains("1 hunks undecided"), "{}", app.message);
+        key(&mut app, KeyCode::Char('b'));
+        assert!(render(&mut app, 100, 30).contains("hunk 2/2: both"));
+
+        // A dry run leaves both files alone.
+        key(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::Pick);
+        assert!(app.message.starts_with("Dry-run"), "{}", app.message);
+        assert!(conflict.exists());
+
+        app.apply = true;
+        key(&mut app, KeyCode::Char('m'));
+        key(&mut app, KeyCode::Char('l'));
+        key(&mut app, KeyCode::Char('b'));
+        key(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::List);
+        assert_eq!(
+            std::fs::read_to_string(&notes).unwrap(),
+            "title\nbuy oat milk\nsame\ncall mom\ncall dad\n"
+        );
+        assert!(!conflict.exists());
+        assert!(app.groups.is_empty());
+        assert_eq!(app.message, "Merged notes.md; both versions archived");
+    }
 
-            let mut spans = Vec::new();
-            if !c.exists {
-                spans.push(Span::styled("(missing) ", theme.message_error));
-            }
-            spans.push(Span::styled(c.label.clone(), theme.message_info));
-            spans.push(Span::raw("  "));
-            spans.push(Span::styled(rel, Style::default()));
-            spans.push(Span::raw("  "));
-            spans.push(Span::styled("size:", theme.header_meta));
-            spans.push(Span::styled(size, theme.header_meta));
-            spans.push(Span::raw("  "));
-            spans.push(Span::styled("mtime:", theme.header_meta));
-            spans.push(Span::styled(m, theme.header_meta));
-            if g.chosen == Some(i) {
-                spans.push(Span::raw("  "));
-                spans.push(Span::styled("(picked)", theme.selected_mark));
-            }
+    #[test]
+    fn photos_get_a_thumbnail_and_binaries_a_hexdump() {
+        let td = tempfile::tempdir().unwrap();
+        let photo = image::RgbImage::from_pixel(8, 8, image::Rgb([200, 30, 30]));
+        photo.save(td.path().join("cat.png")).unwrap();
+        photo
+            .save_with_format(
+                td.path().join("cat.png.sync-conflict-20240101-000000-DEV"),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        std::fs::write(td.path().join("a.zip"), b"PK\x03\x04\x14\0\0\0").unwrap();
+        std::fs::write(
+            td.path().join("a.zip.sync-conflict-20240101-000000-DEV"),
+            b"PK\x03\x04\x0a\0\0\0",
+        )
+        .unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.root = td.path().to_path_buf();
+        let photo_group = app
+            .groups
+            .iter()
+            .position(|g| g.base_path.ends_with("cat.png"))
+            .unwrap();
+        let key = |app: &mut App, code| handle_key(app, code, KeyModifiers::NONE).unwrap();
+
+        app.list_state.select(Some(photo_group));
+        key(&mut app, KeyCode::Enter);
+        let screen = render(&mut app, 100, 30);
+        assert!(screen.contains("Preview"), "{screen}");
+        assert!(screen.contains("▀▀▀▀▀▀▀▀"), "{screen}");
+
+        key(&mut app, KeyCode::Char('v'));
+        assert_eq!(app.mode, Mode::Preview);
+        assert!(app.preview_title.ends_with("-DEV (8x8 thumbnail)"));
+        assert!(render(&mut app, 100, 30).contains("▀▀▀▀▀▀▀▀"));
+        key(&mut app, KeyCode::Esc);
+        key(&mut app, KeyCode::Esc);
+
+        app.list_state.select(Some(1 - photo_group));
+        key(&mut app, KeyCode::Enter);
+        let screen = render(&mut app, 100, 30);
+        assert!(
+            screen.contains("00000000  50 4b 03 04 0a 00 00 00"),
+            "{screen}"
+        );
+        key(&mut app, KeyCode::Char('v'));
+        assert_eq!(app.mode, Mode::Preview);
+        assert!(app.preview_title.ends_with("-DEV (first 8 B)"));
+        assert!(app.preview[0].to_string().ends_with("|PK......|"));
+    }
 
-            ListItem::new(Line::from(spans))
-        })
-        .collect();
+    #[test]
+    fn large_files_are_diffed_on_request_on_a_worker() {
+        let td = tempfile::tempdir().unwrap();
+        std::fs::write(td.path().join("big.txt"), "a\nb\nc\n").unwrap();
+        std::fs::write(
+            td.path().join("big.txt.sync-conflict-20240101-000000-DEV"),
+            "a\nB\nc\n",
+        )
+        .unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.root = td.path().to_path_buf();
+        app.diff_options.max_size = 4;
+        app.list_state.select(Some(0));
+        let key = |app: &mut App, code| handle_key(app, code, KeyModifiers::NONE).unwrap();
+        key(&mut app, KeyCode::Enter);
+        app.groups[0].chosen = Some(1);
+        app.pick_state.select(Some(1));
+        let screen = render(&mut app, 160, 30);
+        assert!(screen.contains("press d to compute diff (6 B)"), "{screen}");
+
+        key(&mut app, KeyCode::Char('d'));
+        assert!(app.diff_job.is_some());
+        assert!(
+            app.message.contains("Diffing big.txt.sync-conflict"),
+            "{}",
+            app.message
+        );
+        while app.diff_job.is_some() {
+            std::thread::sleep(Duration::from_millis(5));
+            poll_diff_job(&mut app);
+        }
+        assert_eq!(app.mode, Mode::Preview);
+        assert!(app.preview.iter().any(|l| l.to_string() == "+B"));
+
+        // d again stops a running diff.
+        key(&mut app, KeyCode::Esc);
+        key(&mut app, KeyCode::Char('d'));
+        key(&mut app, KeyCode::Char('d'));
+        assert!(app.diff_job.is_none());
+        assert_eq!(app.message, "Diff cancelled");
+
+        app.diff_options.memory_cap = 10;
+        key(&mut app, KeyCode::Char('d'));
+        assert_eq!(app.mode, Mode::Error);
+        assert!(app
+            .last_error
+            .as_deref()
+            .unwrap()
+            .contains("memory_cap (10 B)"));
+    }
 
-    let title = rel_path(&app.root, &g.base_path).display().to_string();
+    #[test]
+    fn diff_pane_options_change_at_runtime() {
+        let td = tempfile::tempdir().unwrap();
+        let body: String = (0..20).map(|i| format!("line {i}\n")).collect();
+        std::fs::write(td.path().join("t.txt"), &body).unwrap();
+        let changed = body
+            .replace("line 3\n", "line  3\n")
+            .replace("line 15", "LINE 15");
+        std::fs::write(
+            td.path().join("t.txt.sync-conflict-20240101-000000-DEV"),
+            changed,
+        )
+        .unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.root = td.path().to_path_buf();
+        app.list_state.select(Some(0));
+        let key = |app: &mut App, code| handle_key(app, code, KeyModifiers::NONE).unwrap();
+        key(&mut app, KeyCode::Enter);
+        // `d` diffs the original against the picked version.
+        app.groups[0].chosen = Some(1);
+        app.pick_state.select(Some(1));
+
+        key(&mut app, KeyCode::Char('d'));
+        assert_eq!(app.mode, Mode::Preview);
+        assert!(app.preview_title.ends_with(" [myers, 3 context lines]"));
+        let hunks = |app: &App| {
+            app.preview
+                .iter()
+                .filter(|l| l.to_string().starts_with("@@"))
+                .count()
+        };
+        let len = app.preview.len();
+        assert_eq!(hunks(&app), 2);
+        assert!(render(&mut app, 120, 30).contains("a myers/patience"));
+
+        key(&mut app, KeyCode::Char('w'));
+        assert_eq!(hunks(&app), 1);
+        assert_eq!(
+            app.message,
+            "Diff: myers, 3 context lines, ignoring whitespace"
+        );
+        key(&mut app, KeyCode::Char('-'));
+        key(&mut app, KeyCode::Char('-'));
+        assert!(app.preview.len() < len - 4);
+        key(&mut app, KeyCode::Char('a'));
+        assert!(app
+            .preview_title
+            .contains("[patience, 1 context lines, ignoring whitespace]"));
+        key(&mut app, KeyCode::Esc);
+        assert_eq!(app.mode, Mode::Pick);
+
+        // A file preview has no diff to redo.
+        key(&mut app, KeyCode::Char('v'));
+        key(&mut app, KeyCode::Char('a'));
+        assert_eq!(app.message, "Diff options apply to the built-in diff (d)");
+        assert_eq!(
+            app.diff_options.algorithm,
+            synctui_resolver::diff::DiffAlgorithm::Patience
+        );
+    }
 
-    let list = List::new(items)
-        .block(titled_block(&title, theme))
-        .highlight_style(theme.list_highlight);
+    #[test]
+    fn preview_and_diff_panes_are_highlighted_and_scroll() {
+        let td = tempfile::tempdir().unwrap();
+        let body: String = (0..50).map(|i| format!("fn f{i}() {{}}\n")).collect();
+        std::fs::write(td.path().join("m.rs"), &body).unwrap();
+        std::fs::write(
+            td.path().join("m.rs.sync-conflict-20240101-000000-DEV"),
+            "fn g() {}\n",
+        )
+        .unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.root = td.path().to_path_buf();
+        app.list_state.select(Some(0));
+        handle_key(&mut app, KeyCode::Enter, KeyModifiers::NONE).unwrap();
+        app.pick_state.select(Some(0));
+
+        handle_key(&mut app, KeyCode::Char('v'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::Preview);
+        assert_eq!(app.preview.len(), 50);
+        assert!(app.preview[0]
+            .spans
+            .iter()
+            .any(|s| matches!(s.style.fg, Some(Color::Rgb(..)))));
+        app.page_rows = 10;
+        handle_key(&mut app, KeyCode::Char('G'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.preview_scroll, 40);
+        handle_key(&mut app, KeyCode::PageUp, KeyModifiers::NONE).unwrap();
+        assert_eq!(app.preview_scroll, 30);
+        assert!(render(&mut app, 80, 30).contains("m.rs (31/50)"));
+        handle_key(&mut app, KeyCode::Esc, KeyModifiers::NONE).unwrap();
+        assert_eq!(app.mode, Mode::Pick);
+
+        let (left, right) = (td.path().join("a.rs"), td.path().join("b.rs"));
+        show_diff(
+            &mut app,
+            &left,
+            &right,
+            "--- a.rs\n+++ b.rs\n@@ -1 +1 @@\n-fn a() {}\n+fn b() {}",
+        );
+        assert_eq!(app.mode, Mode::Preview);
+        assert_eq!(app.preview_title, "diff a.rs b.rs");
+        let added = &app.preview[4];
+        assert_eq!(added.spans[0].content, "+");
+        assert_eq!(added.spans[0].style, Theme::default().selected_mark);
+        assert!(added.spans.len() > 2);
+
+        // Only the renamed function gets the changed-word background.
+        show_diff(
+            &mut app,
+            &left,
+            &right,
+            "@@ -1 +1 @@\n-let colour = \"red\";\n+let color = \"red\";",
+        );
+        let marked = |line: &Line| -> String {
+            line.spans
+                .iter()
+                .filter(|s| s.style.bg.is_some())
+                .map(|s| s.content.as_ref())
+                .collect()
+        };
+        assert_eq!(marked(&app.preview[1]), "colour");
+        assert_eq!(marked(&app.preview[2]), "color");
+        assert_eq!(
+            app.preview[2]
+                .spans
+                .iter()
+                .find(|s| s.content == "color")
+                .unwrap()
+                .style
+                .bg,
+            Theme::default().diff_added_word.bg
+        );
+    }
 
-    f.render_stateful_widget(list, area, &mut app.pick_state);
-}
+    #[test]
+    fn deleted_originals_can_be_promoted_or_discarded() {
+        let td = tempfile::tempdir().unwrap();
+        for name in [
+            "f.txt.sync-conflict-20240101-000000-OLD",
+            "f.txt.sync-conflict-20240102-000000-NEW",
+            "g.txt",
+            "g.txt.sync-conflict-20240101-000000-DEV",
+        ] {
+            std::fs::write(td.path().join(name), name).unwrap();
+        }
+        let newer = std::time::SystemTime::now() + Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(td.path().join("f.txt.sync-conflict-20240102-000000-NEW"))
+            .unwrap()
+            .set_modified(newer)
+            .unwrap();
+        let mut app = test_app(scan_conflicts(td.path(), true).unwrap());
+        app.list_state.select(Some(0));
+        assert!(render(&mut app, 140, 30).contains("ORIG DELETED"));
 
-fn draw_footer(f: &mut ratatui::Frame, app: &mut App, area: Rect, theme: Theme) {
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
-        .split(area);
+        handle_key(&mut app, KeyCode::Char('u'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.groups[0].chosen, Some(2));
+        handle_key(&mut app, KeyCode::Char('z'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.groups[0].chosen, Some(0));
+        assert!(render(&mut app, 140, 30).contains("(discard all)"));
 
-    let msg_style = if app.message.to_ascii_lowercase().contains("fail")
-        || app.message.to_ascii_lowercase().contains("error")
-    {
-        theme.message_error
-    } else if app.message.to_ascii_lowercase().contains("cancel")
-        || app.message.to_ascii_lowercase().contains("dry-run")
-        || app.message.to_ascii_lowercase().contains("pick")
-        || app.message.to_ascii_lowercase().contains("no ")
-    {
-        theme.message_warn
-    } else {
-        theme.message_info
-    };
+        // The policy leaves groups with an original alone.
+        app.list_state.select(Some(1));
+        handle_key(&mut app, KeyCode::Char('u'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.groups[1].chosen, None);
+        assert!(app.message.starts_with("No group"));
+
+        app.groups[0].chosen = None;
+        app.deleted_original = Some(DeletedOriginal::Promote);
+        auto_pick_deleted_original(&mut app);
+        assert_eq!(app.groups[0].chosen, Some(2));
+        assert_eq!(app.groups[1].chosen, None);
+    }
 
-    let mut msg_text = app.message.clone();
-    if let Some(e) = &app.compare_error {
-        if !msg_text.is_empty() {
-            msg_text.push('\n');
-        }
-        msg_text.push_str(e);
+    #[test]
+    fn demo_mode_never_turns_apply_on() {
+        assert!(Args::try_parse_from(["synctui-resolver", "--demo", "--apply"]).is_err());
+        let args = Args::try_parse_from(["synctui-resolver", "--demo"]).unwrap();
+        assert!(args.demo);
+
+        let mut app = test_app(Vec::new());
+        app.demo = true;
+        handle_key(&mut app, KeyCode::Char('t'), KeyModifiers::NONE).unwrap();
+        assert!(!app.apply);
+        assert!(app.message.contains("disabled"));
+        assert!(render(&mut app, 120, 30).contains("DEMO"));
     }
 
-    let msg = Paragraph::new(Span::styled(msg_text, msg_style))
-        .block(titled_block("Message", theme))
-        .wrap(Wrap { trim: true });
-    f.render_widget(msg, chunks[0]);
+    #[test]
+    fn like_last_time_picks_the_remembered_device() {
+        let td = tempfile::tempdir().unwrap();
+        let root = td.path().join("r");
+        std::fs::create_dir(&root).unwrap();
+        for name in [
+            "f.txt",
+            "f.txt.sync-conflict-20240101-000000-PHONE12",
+            "f.txt.sync-conflict-20240102-000000-LAPTOP1",
+            "g.txt",
+            "g.txt.sync-conflict-20240101-000000-PHONE12",
+        ] {
+            std::fs::write(root.join(name), name).unwrap();
+        }
+        let groups = scan_conflicts(&root, true).unwrap();
+        let mut h = History::open(&td.path().join("h.sqlite3")).unwrap();
+        h.record(
+            &root,
+            &groups[0],
+            2,
+            &groups[0].base_path,
+            None,
+            SystemTime::now(),
+        )
+        .unwrap();
+
+        let mut app = test_app(groups);
+        app.history = Some(h);
+        app.list_state.select(Some(0));
+        handle_key(&mut app, KeyCode::Char('l'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.groups[0].chosen, Some(2));
+        assert!(app.message.contains("LAPTOP1"), "{}", app.message);
 
-    let plan_text = if app.planned_ops.is_empty() {
-        "".to_string()
-    } else {
-        app.planned_ops.join("\n")
-    };
-    let plan = Paragraph::new(plan_text)
-        .block(titled_block("Plan / Log", theme))
-        .wrap(Wrap { trim: false });
-    f.render_widget(plan, chunks[1]);
-}
+        app.list_state.select(Some(1));
+        handle_key(&mut app, KeyCode::Char('l'), KeyModifiers::NONE).unwrap();
+        assert_eq!(app.groups[1].chosen, None);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        app.groups[0].chosen = None;
+        app.like_last_time = true;
+        auto_pick_like_last_time(&mut app);
+        assert_eq!(app.groups[0].chosen, Some(2));
+    }
 
     #[test]
-    fn current_group_len_empty_when_none_selected() {
-        let mut app = App {
-            root: PathBuf::from("/"),
-            apply: false,
-            include_hidden: false,
-            mode: Mode::List,
-            groups: vec![],
-            list_state: ListState::default(),
-            pick_state: ListState::default(),
-            selected_groups: BTreeSet::new(),
-            message: String::new(),
-            planned_ops: vec![],
-            planned_targets: vec![],
+    fn shift_keys_click_and_drag_select_ranges() {
+        let groups = (0..8)
+            .map(|i| ConflictGroup {
+                base_path: PathBuf::from(format!("f{i}")),
+                candidates: vec![],
+                chosen: None,
+                references: vec![],
+            })
+            .collect();
+        let mut app = test_app(groups);
+        app.list_area = Rect::new(0, 5, 40, 12);
+        app.list_state.select(Some(1));
 
-            compare_error: None,
-            last_error: None,
+        handle_key(&mut app, KeyCode::Down, KeyModifiers::SHIFT).unwrap();
+        handle_key(&mut app, KeyCode::Down, KeyModifiers::SHIFT).unwrap();
+        assert_eq!(app.selected_groups, BTreeSet::from([1, 2, 3]));
+        // A plain move ends the range but keeps it selected.
+        handle_key(&mut app, KeyCode::Down, KeyModifiers::NONE).unwrap();
+        assert!(app.visual.is_none());
+        assert_eq!(app.selected_groups, BTreeSet::from([1, 2, 3]));
+
+        let mouse = |kind, row, modifiers| MouseEvent {
+            kind,
+            column: 3,
+            row,
+            modifiers,
         };
-        app.list_state.select(None);
-        assert_eq!(current_group_len(&app), 0);
+        // Rows start below the top border (y = 6 is group 0).
+        handle_mouse(
+            &mut app,
+            mouse(
+                MouseEventKind::Down(MouseButton::Left),
+                11,
+                KeyModifiers::NONE,
+            ),
+        )
+        .unwrap();
+        assert_eq!(app.list_state.selected(), Some(5));
+        handle_mouse(
+            &mut app,
+            mouse(
+                MouseEventKind::Down(MouseButton::Left),
+                13,
+                KeyModifiers::SHIFT,
+            ),
+        )
+        .unwrap();
+        assert_eq!(app.selected_groups, BTreeSet::from([1, 2, 3, 5, 6, 7]));
+
+        app.selected_groups.clear();
+        handle_mouse(
+            &mut app,
+            mouse(
+                MouseEventKind::Down(MouseButton::Left),
+                6,
+                KeyModifiers::NONE,
+            ),
+        )
+        .unwrap();
+        handle_mouse(
+            &mut app,
+            mouse(
+                MouseEventKind::Drag(MouseButton::Left),
+                7,
+                KeyModifiers::NONE,
+            ),
+        )
+        .unwrap();
+        assert_eq!(app.selected_groups, BTreeSet::from([0, 1]));
+        // Below the last group: ignored.
+        handle_mouse(
+            &mut app,
+            mouse(
+                MouseEventKind::Down(MouseButton::Left),
+                15,
+                KeyModifiers::NONE,
+            ),
+        )
+        .unwrap();
+        assert_eq!(app.list_state.selected(), Some(1));
     }
 
     #[test]
-    fn list_nav_bounds() {
-        let mut state = ListState::default();
-        list_down(&mut state, 0);
-        assert_eq!(state.selected(), None);
-
-        list_down(&mut state, 3);
-        assert_eq!(state.selected(), Some(0));
-        list_down(&mut state, 3);
-        assert_eq!(state.selected(), Some(1));
-        list_down(&mut state, 3);
-        assert_eq!(state.selected(), Some(2));
-        list_down(&mut state, 3);
-        assert_eq!(state.selected(), Some(2));
+    fn select_all_invert_and_visual_range() {
+        let groups = (0..6)
+            .map(|i| ConflictGroup {
+                base_path: PathBuf::from(format!("f{i}")),
+                candidates: vec![],
+                chosen: None,
+                references: vec![],
+            })
+            .collect();
+        let mut app = test_app(groups);
+        let none = KeyModifiers::NONE;
+        app.list_state.select(Some(4));
+        app.selected_groups.insert(0);
+
+        handle_key(&mut app, KeyCode::Char('V'), none).unwrap();
+        handle_key(&mut app, KeyCode::Up, none).unwrap();
+        handle_key(&mut app, KeyCode::Up, none).unwrap();
+        assert_eq!(app.selected_groups, BTreeSet::from([0, 2, 3, 4]));
+        // Shrinking the range unselects what it no longer covers.
+        handle_key(&mut app, KeyCode::Down, none).unwrap();
+        assert_eq!(app.selected_groups, BTreeSet::from([0, 3, 4]));
+        handle_key(&mut app, KeyCode::Esc, none).unwrap();
+        assert!(app.visual.is_none());
+        handle_key(&mut app, KeyCode::Down, none).unwrap();
+        assert_eq!(app.selected_groups, BTreeSet::from([0, 3, 4]));
+
+        handle_key(&mut app, KeyCode::Char('I'), none).unwrap();
+        assert_eq!(app.selected_groups, BTreeSet::from([1, 2, 5]));
+        handle_key(&mut app, KeyCode::Char('*'), none).unwrap();
+        assert_eq!(app.selected_groups.len(), 6);
+    }
 
-        list_up(&mut state, 3);
-        assert_eq!(state.selected(), Some(1));
-        list_up(&mut state, 3);
-        assert_eq!(state.selected(), Some(0));
-        list_up(&mut state, 3);
-        assert_eq!(state.selected(), Some(0));
+    #[test]
+    fn vim_and_paging_keys_move_the_active_list() {
+        let none = KeyModifiers::NONE;
+        assert_eq!(Nav::from_key(KeyCode::Char('j'), none), Some(Nav::Down));
+        assert_eq!(
+            Nav::from_key(KeyCode::Char('d'), KeyModifiers::CONTROL),
+            Some(Nav::HalfPageDown)
+        );
+        // Plain `d` stays the diff key.
+        assert_eq!(Nav::from_key(KeyCode::Char('d'), none), None);
+
+        let groups = (0..50)
+            .map(|i| ConflictGroup {
+                base_path: PathBuf::from(format!("f{i}")),
+                candidates: vec![],
+                chosen: None,
+                references: vec![],
+            })
+            .collect();
+        let mut app = test_app(groups);
+        app.page_rows = 20;
+        app.list_state.select(Some(0));
+        let press = |app: &mut App, code, mods| {
+            handle_key(app, code, mods).unwrap();
+            app.list_state.selected()
+        };
+        assert_eq!(press(&mut app, KeyCode::Char('G'), none), Some(49));
+        assert_eq!(press(&mut app, KeyCode::PageUp, none), Some(29));
+        assert_eq!(
+            press(&mut app, KeyCode::Char('u'), KeyModifiers::CONTROL),
+            Some(19)
+        );
+        assert_eq!(press(&mut app, KeyCode::Char('k'), none), Some(18));
+        assert_eq!(press(&mut app, KeyCode::Char('g'), none), Some(0));
+        assert_eq!(press(&mut app, KeyCode::PageDown, none), Some(20));
     }
 
     #[test]
@@ -1154,32 +9962,19 @@ mod tests {
             base_path: PathBuf::from("a"),
             candidates: vec![],
             chosen: None,
+            references: vec![],
         };
         let g1 = ConflictGroup {
             base_path: PathBuf::from("b"),
             candidates: vec![],
             chosen: None,
+            references: vec![],
         };
 
-        let mut app = App {
-            root: PathBuf::from("/"),
-            apply: false,
-            include_hidden: false,
-            mode: Mode::List,
-            groups: vec![g0, g1],
-            list_state: ListState::default(),
-            pick_state: ListState::default(),
-            selected_groups: BTreeSet::new(),
-            message: String::new(),
-            planned_ops: vec![],
-            planned_targets: vec![],
-
-            compare_error: None,
-            last_error: None,
-        };
+        let mut app = test_app(vec![g0, g1]);
         app.list_state.select(Some(1));
 
-        pick_kind_for_targets(&mut app, PickKind::Newest, false).unwrap();
+        pick_kind_for_targets(&mut app, Strategy::Newest, false).unwrap();
         assert!(app.selected_groups.contains(&1));
     }
 }
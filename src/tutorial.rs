@@ -0,0 +1,136 @@
+use crate::fixture::{FixtureSpec, Pattern};
+use crate::keys::{Keymap, Scope};
+
+/// What `--tutorial` writes to its scratch folder: a handful of small groups, so every
+/// step fits on one screen.
+pub fn spec() -> FixtureSpec {
+    FixtureSpec {
+        groups: 6,
+        depth: 1,
+        max_conflicts: 2,
+        patterns: vec![Pattern::Suffix, Pattern::Infix],
+        ..FixtureSpec::default()
+    }
+}
+
+/// A step of the tutorial, in order. Each ends once the user has done what its prompt
+/// asks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Step {
+    Select,
+    Pick,
+    Plan,
+    DryRun,
+    Apply,
+    Done,
+}
+
+impl Step {
+    pub const ALL: [Step; 6] = [
+        Step::Select,
+        Step::Pick,
+        Step::Plan,
+        Step::DryRun,
+        Step::Apply,
+        Step::Done,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Step::Select => "Select",
+            Step::Pick => "Pick",
+            Step::Plan => "Plan",
+            Step::DryRun => "Dry-run",
+            Step::Apply => "Apply",
+            Step::Done => "Done",
+        }
+    }
+
+    /// The step after this one; `Done` stays.
+    pub fn next(self) -> Step {
+        let i = Step::ALL.iter().position(|&s| s == self).unwrap_or(0);
+        Step::ALL[(i + 1).min(Step::ALL.len() - 1)]
+    }
+
+    /// `Tutorial 2/5: Pick`; the last step isn't counted.
+    pub fn heading(self) -> String {
+        let n = Step::ALL.len() - 1;
+        match Step::ALL.iter().position(|&s| s == self) {
+            Some(i) if i < n => format!("Tutorial {}/{n}: {}", i + 1, self.title()),
+            _ => format!("Tutorial: {}", self.title()),
+        }
+    }
+
+    /// What to do next, naming the keys `keys` binds.
+    pub fn prompt(self, keys: &Keymap) -> String {
+        let list = |name| keys.key(Scope::List, name);
+        match self {
+            Step::Select => format!(
+                "Each row is a file that was changed on two devices at once, with the copies \
+                 Syncthing made. Move with Up/Down and press {} to select a group. Select two.",
+                list("select")
+            ),
+            Step::Pick => format!(
+                "Decide which version to keep. {} keeps the current file for the selected \
+                 groups. Or press {} to see a group's versions and {} to keep the highlighted \
+                 one.",
+                list("current_selected"),
+                list("pick"),
+                keys.key(Scope::Pick, "choose")
+            ),
+            Step::Plan => format!(
+                "Press {} to plan the picked groups. The plan lists every file that would \
+                 move; nothing has changed yet.",
+                list("confirm")
+            ),
+            Step::DryRun => format!(
+                "The DRY-RUN badge at the top means nothing is moved. Press {} to run the plan \
+                 anyway and see what it would do.",
+                keys.key(Scope::Confirm, "run")
+            ),
+            Step::Apply => format!(
+                "Now for real: close this with {}, press {} for APPLY, then {} again. The \
+                 versions you didn't keep go to .stconflict-archive next to each file.",
+                keys.key(Scope::Apply, "close"),
+                keys.key(Scope::Confirm, "toggle_apply"),
+                keys.key(Scope::Confirm, "run")
+            ),
+            Step::Done => format!(
+                "Done: the kept versions are in place and the rest is archived. {} undoes the \
+                 last apply, and {} quits and removes the tutorial folder.",
+                list("undo"),
+                list("quit")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::KeySpec;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn steps_run_in_order_and_prompt_with_the_bound_keys() {
+        let mut step = Step::Select;
+        let mut seen = vec![step];
+        while step != Step::Done {
+            step = step.next();
+            seen.push(step);
+        }
+        assert_eq!(seen, Step::ALL);
+        assert_eq!(Step::Done.next(), Step::Done);
+        assert_eq!(Step::Pick.heading(), "Tutorial 2/5: Pick");
+        assert_eq!(Step::Done.heading(), "Tutorial: Done");
+
+        let remapped = BTreeMap::from([(
+            "list".to_string(),
+            BTreeMap::from([("select".to_string(), KeySpec::One("x".to_string()))]),
+        )]);
+        let keys = Keymap::new(&remapped).unwrap();
+        assert!(Step::Select.prompt(&keys).contains("press x to select"));
+        let default = Keymap::new(&BTreeMap::new()).unwrap();
+        assert!(Step::DryRun.prompt(&default).contains("Press y to run"));
+    }
+}
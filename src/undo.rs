@@ -0,0 +1,153 @@
+use crate::model::ConflictGroup;
+use crate::ops::{archive_dir_for, move_file, record_archive, ARCHIVE_INDEX};
+use crate::plan::{kept_destination, ApplyOptions};
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How to put one applied group back the way it was.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UndoGroup {
+    pub base_path: PathBuf,
+    /// The kept version's `(current, original)` paths, when apply moved it.
+    pub kept: Option<(PathBuf, PathBuf)>,
+    /// Versions moved into the local archive, as `(archived, original)` paths.
+    pub archived: Vec<(PathBuf, PathBuf)>,
+    /// Versions deleted by a loser rule or sent to a remote archive; undo can't bring
+    /// them back.
+    pub gone: Vec<PathBuf>,
+}
+
+/// What applying `g` (keeping `chosen`) just did, read back from the group's archive
+/// index. Call it right after the group was applied: each version's latest index entry
+/// is taken to be the one that apply wrote.
+pub fn undo_record(g: &ConflictGroup, chosen: usize, options: &ApplyOptions) -> Result<UndoGroup> {
+    let archive_dir = archive_dir_for(&g.base_path)?;
+    let index = fs::read_to_string(archive_dir.join(ARCHIVE_INDEX)).unwrap_or_default();
+    let latest = |path: &Path| {
+        let from = path.display().to_string();
+        index
+            .lines()
+            .rev()
+            .filter_map(|line| {
+                let mut cols = line.split('\t').skip(1);
+                Some((cols.next()?, cols.next()?))
+            })
+            // The kept file's hash is logged under the base path too.
+            .find(|(f, to)| *f == from && !to.starts_with("kept "))
+            .map(|(_, to)| PathBuf::from(to))
+    };
+
+    let kept_dest = kept_destination(g, chosen, options)?;
+    let chosen_path = &g.candidates[chosen].path;
+    let mut undo = UndoGroup {
+        base_path: g.base_path.clone(),
+        kept: (*chosen_path != kept_dest).then(|| (kept_dest, chosen_path.clone())),
+        archived: Vec::new(),
+        gone: Vec::new(),
+    };
+    for (i, c) in g.candidates.iter().enumerate() {
+        if i == chosen || !c.exists {
+            continue;
+        }
+        match latest(&c.path).filter(|to| to.starts_with(&archive_dir)) {
+            Some(to) => undo.archived.push((to, c.path.clone())),
+            None => undo.gone.push(c.path.clone()),
+        }
+    }
+    Ok(undo)
+}
+
+/// Moves the kept version back to where it was, then the archived versions back to their
+/// original paths, recording each as `restored` in the archive index. Everything is
+/// checked first, so a group whose files have changed since is left alone.
+pub fn undo_group(undo: &UndoGroup) -> Result<()> {
+    let freed = undo.kept.as_ref().map(|(now, _)| now);
+    if let Some((now, was)) = &undo.kept {
+        if !now.exists() {
+            bail!("kept version {now:?} is gone");
+        }
+        if was.exists() {
+            bail!("{was:?} exists again");
+        }
+    }
+    for (archived, original) in &undo.archived {
+        if !archived.exists() {
+            bail!("archived version {archived:?} is gone");
+        }
+        if original.exists() && Some(original) != freed {
+            bail!("{original:?} exists again");
+        }
+    }
+
+    if let Some((now, was)) = &undo.kept {
+        move_file(now, was)?;
+    }
+    for (archived, original) in &undo.archived {
+        move_file(archived, original)?;
+        if let Some(dir) = archived.parent() {
+            record_archive(dir, archived, &format!("restored {}", original.display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LoserAction, LoserRule};
+    use crate::plan::apply_group;
+    use crate::scan::scan_conflicts;
+    use tempfile::tempdir;
+
+    #[test]
+    fn undoes_archived_and_renamed_versions_and_reports_deleted_ones() {
+        let td = tempdir().unwrap();
+        let root = td.path().canonicalize().unwrap();
+        fs::write(root.join("a.txt"), "orig").unwrap();
+        fs::write(
+            root.join("a.txt.sync-conflict-20240101-010101-DEV"),
+            "conflict",
+        )
+        .unwrap();
+        fs::write(
+            root.join("a.txt.sync-conflict-20240102-010101-DEV"),
+            "big conflict",
+        )
+        .unwrap();
+        let mut g = scan_conflicts(&root, true).unwrap().remove(0);
+        g.chosen = Some(1);
+        let before: Vec<_> = g.candidates.iter().map(|c| c.path.clone()).collect();
+        let options = ApplyOptions {
+            rename_template: Some("{stem}-kept{ext}".to_string()),
+            loser_rules: vec![LoserRule {
+                min_size: Some(10),
+                max_size: None,
+                action: LoserAction::Delete,
+            }],
+            verify: true,
+            ..ApplyOptions::default()
+        };
+
+        apply_group(&root, &g, 1, &options).unwrap();
+        let undo = undo_record(&g, 1, &options).unwrap();
+        assert_eq!(
+            undo.kept,
+            Some((root.join("a-kept.txt"), before[1].clone()))
+        );
+        assert_eq!(undo.archived.len(), 1);
+        assert_eq!(undo.archived[0].1, before[0]);
+        assert_eq!(undo.gone, [before[2].clone()]);
+
+        undo_group(&undo).unwrap();
+        assert_eq!(fs::read_to_string(&before[0]).unwrap(), "orig");
+        assert_eq!(fs::read_to_string(&before[1]).unwrap(), "conflict");
+        assert!(!root.join("a-kept.txt").exists());
+        let index = fs::read_to_string(root.join(crate::ops::ARCHIVE_DIR).join(ARCHIVE_INDEX));
+        assert!(index.unwrap().contains("\trestored "));
+
+        // Nothing left to move back.
+        let err = undo_group(&undo).unwrap_err().to_string();
+        assert!(err.contains("is gone"), "{err}");
+    }
+}
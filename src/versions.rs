@@ -0,0 +1,122 @@
+use crate::model::{Candidate, ConflictGroup};
+use crate::scan::stat_candidate;
+use crate::snapshots::{reference_candidates, SnapshotSource};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Finds the nearest `.stversions` directory above `base_path` and returns the directory
+/// inside it that mirrors the base file's directory.
+fn stversions_dir_for(base_path: &Path) -> Option<PathBuf> {
+    let parent = base_path.parent()?;
+    parent.ancestors().find_map(|folder| {
+        let versions = folder.join(".stversions");
+        if !versions.is_dir() {
+            return None;
+        }
+        let rel = parent.strip_prefix(folder).ok()?;
+        Some(versions.join(rel))
+    })
+}
+
+/// Splits `name.ext` into (`name`, `.ext`) the way Syncthing's file versioning does.
+fn split_ext(file_name: &str) -> (&str, &str) {
+    match file_name.rfind('.') {
+        Some(i) if i > 0 => file_name.split_at(i),
+        _ => (file_name, ""),
+    }
+}
+
+/// Returns the `YYYYMMDD-HHMMSS` stamp if `name` is a version of `base_name`
+/// (`<stem>~YYYYMMDD-HHMMSS<ext>`).
+fn version_stamp<'a>(base_name: &str, name: &'a str) -> Option<&'a str> {
+    let (stem, ext) = split_ext(base_name);
+    let stamp = name
+        .strip_prefix(stem)?
+        .strip_prefix('~')?
+        .strip_suffix(ext)?;
+    let ok = stamp.len() == 15
+        && stamp.as_bytes()[8] == b'-'
+        && stamp
+            .bytes()
+            .enumerate()
+            .all(|(i, b)| i == 8 || b.is_ascii_digit());
+    ok.then_some(stamp)
+}
+
+/// Syncthing file-versioning copies (`.stversions`) of `base_path`, oldest first.
+pub fn stversions_candidates(base_path: &Path) -> Vec<Candidate> {
+    let Some(base_name) = base_path.file_name().map(|n| n.to_string_lossy()) else {
+        return Vec::new();
+    };
+    let Some(dir) = stversions_dir_for(base_path) else {
+        return Vec::new();
+    };
+    let Ok(rd) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    let mut found: Vec<(String, PathBuf)> = rd
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            let stamp = version_stamp(&base_name, &name)?.to_string();
+            Some((stamp, e.path()))
+        })
+        .collect();
+    found.sort();
+    found
+        .into_iter()
+        .map(|(stamp, p)| stat_candidate(p, false, format!("Version {stamp}")))
+        .collect()
+}
+
+/// Fills each group's read-only references with snapshot and `.stversions` copies.
+pub fn attach_references(snapshots: &[SnapshotSource], groups: &mut [ConflictGroup]) {
+    for g in groups {
+        let mut refs = reference_candidates(snapshots, &g.base_path);
+        refs.extend(stversions_candidates(&g.base_path));
+        g.references = refs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn version_stamp_matches_syncthing_naming() {
+        assert_eq!(
+            version_stamp("notes.txt", "notes~20240101-010203.txt"),
+            Some("20240101-010203")
+        );
+        assert_eq!(
+            version_stamp("Makefile", "Makefile~20240101-010203"),
+            Some("20240101-010203")
+        );
+        assert_eq!(version_stamp("notes.txt", "notes~2024.txt"), None);
+        assert_eq!(
+            version_stamp("notes.txt", "other~20240101-010203.txt"),
+            None
+        );
+    }
+
+    #[test]
+    fn stversions_found_in_owning_folder() {
+        let td = tempdir().unwrap();
+        let folder = td.path();
+        let versions = folder.join(".stversions").join("docs");
+        fs::create_dir_all(&versions).unwrap();
+        fs::create_dir_all(folder.join("docs")).unwrap();
+        fs::write(versions.join("notes~20240102-000000.txt"), "b").unwrap();
+        fs::write(versions.join("notes~20240101-000000.txt"), "a").unwrap();
+        fs::write(versions.join("other~20240101-000000.txt"), "x").unwrap();
+
+        let found = stversions_candidates(&folder.join("docs").join("notes.txt"));
+        let labels: Vec<_> = found.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["Version 20240101-000000", "Version 20240102-000000"]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use crate::scan::is_conflict_name;
+use crate::syncthing::{Event, Folder, SyncthingClient};
+use anyhow::{Context, Result};
+use notify::{recommended_watcher, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// Syncthing events that carry a changed file.
+const FILE_EVENTS: &[&str] = &[
+    "ItemFinished",
+    "LocalChangeDetected",
+    "RemoteChangeDetected",
+];
+
+/// Reports changes to `.sync-conflict-` files under a root, from filesystem notifications,
+/// Syncthing's event stream, or both.
+pub struct ConflictWatcher {
+    // Dropping the watcher stops the notifications.
+    fs: Option<RecommendedWatcher>,
+    tx: Sender<PathBuf>,
+    rx: Receiver<PathBuf>,
+}
+
+impl ConflictWatcher {
+    /// A watcher with no sources yet.
+    pub fn new() -> Self {
+        let (tx, rx) = channel();
+        Self { fs: None, tx, rx }
+    }
+
+    /// Recursively watches `root` with filesystem notifications.
+    pub fn start(root: &Path) -> Result<Self> {
+        let mut w = Self::new();
+        w.watch_fs(root)?;
+        Ok(w)
+    }
+
+    pub fn watch_fs(&mut self, root: &Path) -> Result<()> {
+        let tx = self.tx.clone();
+        let mut watcher = recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(ev) = res {
+                for p in ev.paths {
+                    let _ = tx.send(p);
+                }
+            }
+        })
+        .context("start filesystem watcher")?;
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .with_context(|| format!("watch {root:?}"))?;
+        self.fs = Some(watcher);
+        Ok(())
+    }
+
+    /// Follows Syncthing's event stream for file changes under `root`. Works where
+    /// filesystem notifications don't (network mounts) or are too costly (huge folders).
+    /// The folder list and the current event ID are fetched now, so errors surface here;
+    /// later connection errors are retried in the background.
+    pub fn watch_syncthing(&mut self, client: SyncthingClient, root: &Path) -> Result<()> {
+        let folders = client.folders()?;
+        let mut since = client
+            .events(0, FILE_EVENTS, Duration::ZERO, Some(1))?
+            .last()
+            .map_or(0, |e| e.id);
+        let root = root.to_path_buf();
+        let tx = self.tx.clone();
+        thread::spawn(move || loop {
+            match client.events(since, FILE_EVENTS, Duration::from_secs(60), None) {
+                Ok(events) => {
+                    for ev in &events {
+                        since = since.max(ev.id);
+                        let Some(path) = event_path(ev, &folders) else {
+                            continue;
+                        };
+                        if path.starts_with(&root) && tx.send(path).is_err() {
+                            // The watcher was dropped.
+                            return;
+                        }
+                    }
+                }
+                // Syncthing restarting or unreachable; try again shortly.
+                Err(_) => thread::sleep(Duration::from_secs(5)),
+            }
+        });
+        Ok(())
+    }
+
+    /// Drains pending notifications; true if any of them touched a conflict file.
+    pub fn poll_conflicts(&self) -> bool {
+        let mut hit = false;
+        while let Ok(p) = self.rx.try_recv() {
+            hit |= touches_conflict(&p);
+        }
+        hit
+    }
+}
+
+impl Default for ConflictWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Absolute path of the file an event is about.
+fn event_path(ev: &Event, folders: &[Folder]) -> Option<PathBuf> {
+    let (folder, item) = ev.item()?;
+    Some(folders.iter().find(|f| f.id == folder)?.path.join(item))
+}
+
+fn touches_conflict(p: &Path) -> bool {
+    p.file_name()
+        .map(|n| is_conflict_name(&n.to_string_lossy()).is_some())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_conflict_names_trigger() {
+        assert!(touches_conflict(Path::new(
+            "/r/a.txt.sync-conflict-20240101-010101-DEV"
+        )));
+        assert!(!touches_conflict(Path::new("/r/a.txt")));
+        assert!(!touches_conflict(Path::new("/")));
+    }
+
+    #[test]
+    fn syncthing_events_map_to_folder_paths() {
+        let folders = vec![Folder {
+            id: "abcd-1234".to_string(),
+            label: String::new(),
+            path: PathBuf::from("/data/notes"),
+            paused: false,
+        }];
+        let ev: Event = serde_json::from_str(
+            r#"{"id": 3, "type": "LocalChangeDetected",
+                "data": {"folderID": "abcd-1234",
+                         "path": "a.txt.sync-conflict-20240101-010101-DEV"}}"#,
+        )
+        .unwrap();
+        let p = event_path(&ev, &folders).unwrap();
+        assert_eq!(
+            p,
+            Path::new("/data/notes/a.txt.sync-conflict-20240101-010101-DEV")
+        );
+        assert!(touches_conflict(&p));
+
+        let ev = Event {
+            data: serde_json::json!({"folder": "other", "item": "x"}),
+            ..ev
+        };
+        assert!(event_path(&ev, &folders).is_none());
+    }
+}